@@ -0,0 +1,65 @@
+use std::hint::black_box;
+
+use bucket_common_types::bucket_guid::BucketGuid;
+use bucket_common_types::expiry::ExpiryTimestamp;
+use bucket_common_types::share_link::{BucketSharePermissionFlags, ShareLinkToken};
+use bucket_common_types::{BucketRegion, RegionCluster};
+use criterion::{criterion_group, criterion_main, Criterion};
+use strum::IntoEnumIterator;
+
+// One edge cache entry, the shape the store keeps per share link.
+#[derive(serde::Serialize, serde::Deserialize, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+#[archive(check_bytes)]
+struct Entry {
+    token: ShareLinkToken,
+    bucket: BucketGuid,
+    cluster: RegionCluster,
+    permission: BucketSharePermissionFlags,
+    expires: ExpiryTimestamp,
+}
+
+fn entries() -> Vec<Entry> {
+    BucketRegion::iter()
+        .cycle()
+        .take(1000)
+        .enumerate()
+        .map(|(index, region)| Entry {
+            token: ShareLinkToken([index as u8; 32]),
+            bucket: BucketGuid::generate(),
+            cluster: RegionCluster { region, cluster_id: index as u32 },
+            permission: BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            expires: ExpiryTimestamp::from_unix_timestamp(1_717_200_000 + index as i64).unwrap(),
+        })
+        .collect()
+}
+
+// Reading the expiry of one entry, what the cache does on every request.
+fn archive_access(c: &mut Criterion) {
+    let entries = entries();
+    let json = serde_json::to_vec(&entries).unwrap();
+    let archive = rkyv::to_bytes::<_, 4096>(&entries).unwrap();
+    let mut group = c.benchmark_group("archive_access");
+    group.bench_function("serde_json", |b| {
+        b.iter(|| {
+            let entries = serde_json::from_slice::<Vec<Entry>>(black_box(&json)).unwrap();
+            black_box(entries[500].expires.unix_timestamp())
+        })
+    });
+    group.bench_function("rkyv_checked", |b| {
+        b.iter(|| {
+            let entries = rkyv::check_archived_root::<Vec<Entry>>(black_box(&archive)).unwrap();
+            black_box(entries[500].expires.unix_timestamp())
+        })
+    });
+    // Stores validated once when mapped can skip the check, archive was written by to_bytes above.
+    group.bench_function("rkyv_unchecked", |b| {
+        b.iter(|| {
+            let entries = unsafe { rkyv::archived_root::<Vec<Entry>>(black_box(&archive)) };
+            black_box(entries[500].expires.unix_timestamp())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, archive_access);
+criterion_main!(benches);
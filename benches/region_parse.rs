@@ -0,0 +1,72 @@
+use std::hint::black_box;
+
+use bucket_common_types::BucketRegion;
+use criterion::{criterion_group, criterion_main, Criterion};
+use strum::IntoEnumIterator;
+
+// The string comparison chain strum derived for BucketRegion before parse_fast.
+fn legacy_parse(s: &str) -> Option<BucketRegion> {
+    let region = match s {
+        "eu-center" => BucketRegion::EuropeCentral(0),
+        "eu-north" => BucketRegion::EuropeNorth(0),
+        "eu-south" => BucketRegion::EuropeSouth(0),
+        "eu-west" => BucketRegion::EuropeWest(0),
+        "eu-east" => BucketRegion::EuropeEast(0),
+        "us-central" => BucketRegion::AmericaCentral(0),
+        "us-north" => BucketRegion::AmericaNorth(0),
+        "us-south" => BucketRegion::AmericaSouth(0),
+        "us-west" => BucketRegion::AmericaWest(0),
+        "us-east" => BucketRegion::AmericaEast(0),
+        "af-central" => BucketRegion::AfricaCentral(0),
+        "af-north" => BucketRegion::AfricaNorth(0),
+        "af-south" => BucketRegion::AfricaSouth(0),
+        "af-west" => BucketRegion::AfricaWest(0),
+        "af-east" => BucketRegion::AfricaEast(0),
+        "ap-center" => BucketRegion::AsiaPacificCentral(0),
+        "ap-north" => BucketRegion::AsiaPacificNorth(0),
+        "ap-south" => BucketRegion::AsiaPacificSouth(0),
+        "ap-west" => BucketRegion::AsiaPacificWest(0),
+        "ap-east" => BucketRegion::AsiaPacificEast(0),
+        "me-central" => BucketRegion::MiddleEastCentral(0),
+        "me-north" => BucketRegion::MiddleEastNorth(0),
+        "me-south" => BucketRegion::MiddleEastSouth(0),
+        "me-west" => BucketRegion::MiddleEastWest(0),
+        "me-east" => BucketRegion::MiddleEastEast(0),
+        "sa-central" => BucketRegion::SouthAmericaCentral(0),
+        "sa-north" => BucketRegion::SouthAmericaNorth(0),
+        "sa-south" => BucketRegion::SouthAmericaSouth(0),
+        "sa-west" => BucketRegion::SouthAmericaWest(0),
+        "sa-east" => BucketRegion::SouthAmericaEast(0),
+        _ => return None,
+    };
+    Some(region)
+}
+
+fn inputs() -> Vec<String> {
+    let mut inputs = BucketRegion::iter().map(|region| region.to_string()).collect::<Vec<_>>();
+    inputs.extend(["", "eu", "mars-central", "sa-eastt", "EU-NORTH", "eu-north-1"].map(String::from));
+    inputs
+}
+
+fn region_parse(c: &mut Criterion) {
+    let inputs = inputs();
+    let mut group = c.benchmark_group("region_parse");
+    group.bench_function("legacy", |b| {
+        b.iter(|| {
+            for input in &inputs {
+                black_box(legacy_parse(black_box(input)));
+            }
+        })
+    });
+    group.bench_function("parse_fast", |b| {
+        b.iter(|| {
+            for input in &inputs {
+                black_box(BucketRegion::parse_fast(black_box(input)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, region_parse);
+criterion_main!(benches);
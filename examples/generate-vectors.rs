@@ -0,0 +1,8 @@
+// Regenerates the secret share link test vectors:
+// cargo run --example generate-vectors > testdata/secret_share_link_vectors.json
+use bucket_common_types::test_vectors::secret_share_link_vectors;
+
+fn main() {
+    let json = serde_json::to_string_pretty(&secret_share_link_vectors()).expect("vectors serialize");
+    println!("{}", json);
+}
@@ -0,0 +1,40 @@
+// Prints the validation report of a secret share link as JSON, exits with 1 if the link is not valid:
+// cargo run --example validate_link -- <link> <keyring.json> [revocation list file]
+use std::process::ExitCode;
+
+use bucket_common_types::clock::SystemClock;
+use bucket_common_types::keyring::Keyring;
+use bucket_common_types::link_validator::LinkValidator;
+use bucket_common_types::revocation::RevocationList;
+use bucket_common_types::secret_share_link::VerificationPolicy;
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (link, keyring, revocations) = match args.as_slice() {
+        [link, keyring] => (link, keyring, None),
+        [link, keyring, revocations] => (link, keyring, Some(revocations)),
+        _ => {
+            eprintln!("usage: validate_link <link> <keyring.json> [revocation list file]");
+            return ExitCode::from(2);
+        }
+    };
+    let keyring: Keyring = serde_json::from_slice(&std::fs::read(keyring).expect("keyring file is readable"))
+        .expect("keyring file is a JSON keyring");
+    let revocations = revocations.map(|path| {
+        let file = std::fs::File::open(path).expect("revocation list file is readable");
+        RevocationList::read_from(std::io::BufReader::new(file)).expect("revocation list file is a revocation list")
+    });
+
+    let validator = LinkValidator {
+        keyring,
+        revocations,
+        policy: VerificationPolicy::default(),
+        clock: Box::new(SystemClock),
+    };
+    let report = validator.validate(link);
+    println!("{}", serde_json::to_string_pretty(&report).expect("reports serialize"));
+    match report.valid {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
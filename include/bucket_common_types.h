@@ -0,0 +1,77 @@
+#ifndef BUCKET_COMMON_TYPES_H
+#define BUCKET_COMMON_TYPES_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum BdctStatus {
+  BDCT_STATUS_OK = 0,
+  BDCT_STATUS_NULL_ARGUMENT = 1,
+  BDCT_STATUS_INVALID_UTF8 = 2,
+  BDCT_STATUS_INVALID_HANDLE = 3,
+  BDCT_STATUS_PANIC = 4,
+  BDCT_STATUS_INVALID_ARGUMENT = 5,
+  BDCT_STATUS_INVALID_URL = 16,
+  BDCT_STATUS_INVALID_SCHEME = 17,
+  BDCT_STATUS_INVALID_HOST = 18,
+  BDCT_STATUS_INVALID_PATH = 19,
+  BDCT_STATUS_INVALID_FRAGMENT = 20,
+  BDCT_STATUS_INVALID_BUCKET_KEY = 21,
+  BDCT_STATUS_INVALID_PERMISSION = 22,
+  BDCT_STATUS_INVALID_TIME = 23,
+  BDCT_STATUS_INVALID_PREFIX = 24,
+  BDCT_STATUS_INVALID_CHANNEL = 25,
+  BDCT_STATUS_INVALID_BYTE_CAP = 26,
+  BDCT_STATUS_INVALID_SIGNATURE_ENCODING = 27,
+  BDCT_STATUS_CHECKSUM_MISMATCH = 28,
+  BDCT_STATUS_INVALID_SIGNATURE = 32,
+  BDCT_STATUS_NOT_YET_VALID = 33,
+  BDCT_STATUS_EXPIRED = 34,
+  BDCT_STATUS_MISSING_EXPIRY = 35,
+  BDCT_STATUS_UNKNOWN_ISSUE_TIME = 36,
+  BDCT_STATUS_LINK_TOO_OLD = 37,
+} BdctStatus;
+
+typedef struct BdctLink BdctLink;
+
+/**
+ * Parses a secret share link in either URL form. On success `*out` is a handle to free with `bdct_link_free`,
+ * on failure it is NULL.
+ *
+ * # Safety
+ * `url` must be NULL or a NUL-terminated string, `out` must be NULL or valid for a write.
+ */
+enum BdctStatus bdct_parse_secret_link(const char *url,
+                                       struct BdctLink **out);
+
+/**
+ * Checks the signature against the 32 byte Ed25519 public key and that `now_unix` is inside the link's
+ * not before and expiry window.
+ *
+ * # Safety
+ * `public_key` must be NULL or valid for 32 byte reads.
+ */
+enum BdctStatus bdct_verify_link(const struct BdctLink *link,
+                                 const uint8_t *public_key,
+                                 int64_t now_unix);
+
+/**
+ * Writes the 32 byte token the server knows the link by to `out`.
+ *
+ * # Safety
+ * `out` must be NULL or valid for 32 byte writes.
+ */
+enum BdctStatus bdct_link_token(const struct BdctLink *link, uint8_t *out);
+
+/**
+ * Frees a handle from `bdct_parse_secret_link`. NULL is ignored like in `free`, a handle that was already freed
+ * gives `BDCT_STATUS_INVALID_HANDLE`.
+ */
+enum BdctStatus bdct_link_free(struct BdctLink *link);
+
+#endif  /* BUCKET_COMMON_TYPES_H */
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bucket_settings::BucketSettingsBuilder;
+use crate::compression::{CompressionSpec, CompressionSpecError};
+use crate::{BucketCompression, BucketRegion, BucketStorageClass, BucketVisibility, DataResidency, Verification};
+
+/*
+* Account wide defaults for new buckets. Every field is optional when deserializing, missing fields default to:
+* default_region: none, the region has to be picked per bucket.
+* default_compression: "none".
+* default_visibility: Private.
+* default_storage_class: General.
+* residency: Global.
+* verification_required_for_share: UNVERIFIED, sharing needs no verification.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserAccountSettings {
+    pub default_region: Option<BucketRegion>,
+    pub default_compression: CompressionSpec,
+    pub default_visibility: BucketVisibility,
+    pub default_storage_class: BucketStorageClass,
+    pub residency: DataResidency,
+    pub verification_required_for_share: Verification,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum UserAccountSettingsError {
+    #[error("default region {region} is outside the {residency} residency")]
+    RegionOutsideResidency { region: BucketRegion, residency: DataResidency },
+    #[error(transparent)]
+    Compression(#[from] CompressionSpecError),
+}
+
+impl Default for UserAccountSettings {
+    fn default() -> Self {
+        Self {
+            default_region: None,
+            default_compression: CompressionSpec {
+                algorithm: BucketCompression::None,
+                level: None,
+            },
+            default_visibility: BucketVisibility::Private,
+            default_storage_class: BucketStorageClass::General,
+            residency: DataResidency::Global,
+            verification_required_for_share: Verification::UNVERIFIED,
+        }
+    }
+}
+
+impl UserAccountSettings {
+    pub fn validate(&self) -> Result<(), UserAccountSettingsError> {
+        if let Some(region) = &self.default_region {
+            if !self.residency.allows(region) {
+                return Err(UserAccountSettingsError::RegionOutsideResidency {
+                    region: region.clone(),
+                    residency: self.residency,
+                });
+            }
+        }
+        self.default_compression.validate()?;
+        Ok(())
+    }
+
+    /*
+    * Fills the fields the bucket did not set explicitly, an explicit bucket setting always wins.
+    * Bucket settings only store the compression algorithm, the level of default_compression is not carried over.
+    */
+    pub fn apply_to(&self, mut partial: BucketSettingsBuilder) -> BucketSettingsBuilder {
+        if partial.region.is_none() {
+            partial.region = self.default_region.clone();
+        }
+        if partial.compression.is_none() {
+            partial.compression = Some(self.default_compression.algorithm.clone());
+        }
+        if partial.visibility.is_none() {
+            partial.visibility = Some(self.default_visibility);
+        }
+        if partial.storage_class.is_none() {
+            partial.storage_class = Some(self.default_storage_class.clone());
+        }
+        partial
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bucket_settings::BucketSettings;
+    use crate::maybe_unknown::MaybeUnknown;
+
+    fn eu_only() -> UserAccountSettings {
+        UserAccountSettings {
+            default_region: Some(BucketRegion::EuropeNorth(0)),
+            default_compression: "zstd:3".parse().unwrap(),
+            residency: DataResidency::Europe,
+            ..UserAccountSettings::default()
+        }
+    }
+
+    #[test]
+    fn sparse_json_round_trip() {
+        let settings: UserAccountSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings, UserAccountSettings::default());
+
+        let settings: UserAccountSettings =
+            serde_json::from_str(r#"{"default_compression":"zstd:3","residency":"Europe"}"#).unwrap();
+        assert_eq!(settings.default_compression, "zstd:3".parse().unwrap());
+        assert_eq!(settings.residency, DataResidency::Europe);
+        assert_eq!(settings.default_visibility, BucketVisibility::Private);
+        assert_eq!(settings.default_region, None);
+
+        let json = serde_json::to_string(&eu_only()).unwrap();
+        assert_eq!(serde_json::from_str::<UserAccountSettings>(&json).unwrap(), eu_only());
+        assert!(serde_json::from_str::<UserAccountSettings>(r#"{"default_compression":"gzip:42"}"#).is_err());
+    }
+
+    #[test]
+    fn explicit_bucket_settings_beat_account_defaults() {
+        let settings = eu_only()
+            .apply_to(
+                BucketSettings::builder()
+                    .region(BucketRegion::EuropeWest(1))
+                    .visibility(BucketVisibility::Public),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(settings.region, MaybeUnknown::Known(BucketRegion::EuropeWest(1)));
+        assert_eq!(settings.visibility, BucketVisibility::Public);
+        assert_eq!(settings.compression, BucketCompression::Zstd);
+        assert_eq!(settings.storage_class, BucketStorageClass::General);
+
+        let settings = eu_only().apply_to(BucketSettings::builder()).build().unwrap();
+        assert_eq!(settings.region, MaybeUnknown::Known(BucketRegion::EuropeNorth(0)));
+        assert_eq!(settings.visibility, BucketVisibility::Private);
+
+        // Without a default region the bucket still has to pick one.
+        assert!(UserAccountSettings::default().apply_to(BucketSettings::builder()).build().is_err());
+    }
+
+    #[test]
+    fn default_region_outside_residency_is_rejected() {
+        assert_eq!(eu_only().validate(), Ok(()));
+        let settings = UserAccountSettings {
+            default_region: Some(BucketRegion::AmericaEast(0)),
+            ..eu_only()
+        };
+        assert_eq!(
+            settings.validate(),
+            Err(UserAccountSettingsError::RegionOutsideResidency {
+                region: BucketRegion::AmericaEast(0),
+                residency: DataResidency::Europe
+            })
+        );
+        assert_eq!(UserAccountSettings::default().validate(), Ok(()));
+    }
+}
@@ -0,0 +1,254 @@
+#![cfg(feature = "share_link")]
+
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::bucket_guid::BucketGuid;
+use crate::expiry::ExpiryTimestamp;
+use crate::share_link::BucketSharePermissionFlags;
+use crate::{UserId, Verification};
+
+// Tolerated clock difference between the issuing and the validating service.
+pub const ACTOR_CLAIMS_CLOCK_SKEW: Duration = Duration::seconds(30);
+
+/*
+* Claims one internal service passes to another: who acts, for whom, on which bucket and with what permissions.
+* The token carrying them (JWT, PASETO, ...) is up to the services, only the claim names are fixed here.
+* Field names are short since the claims travel in every request, they never change.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ActorClaims {
+    #[serde(rename = "act")]
+    pub actor: UserId,
+    #[serde(rename = "obo")]
+    pub on_behalf_of: Option<UserId>,
+    // None grants the permissions on every bucket the actor can reach.
+    #[serde(rename = "bkt")]
+    pub bucket_scope: Option<BucketGuid>,
+    #[serde(rename = "prm")]
+    pub permissions: BucketSharePermissionFlags,
+    #[serde(rename = "vrf")]
+    pub verification: Verification,
+    #[serde(rename = "iat")]
+    pub issued_at: ExpiryTimestamp,
+    #[serde(rename = "exp")]
+    pub expires_at: ExpiryTimestamp,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum ActorClaimsError {
+    #[error("claims expire at {expires_at}, not after they were issued at {issued_at}")]
+    InvalidTimeWindow {
+        issued_at: ExpiryTimestamp,
+        expires_at: ExpiryTimestamp,
+    },
+    #[error("claims are issued in the future at {issued_at}")]
+    IssuedInFuture { issued_at: ExpiryTimestamp },
+    #[error("claims expired at {expires_at}")]
+    Expired { expires_at: ExpiryTimestamp },
+    #[error("actor acts on behalf of itself")]
+    SelfDelegation,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum EscalationError {
+    #[error("claims are scoped to bucket {scope}, not {requested}")]
+    BucketOutOfScope { scope: BucketGuid, requested: BucketGuid },
+    #[error("permissions {added:?} are not granted by the claims")]
+    Permissions { added: BucketSharePermissionFlags },
+}
+
+impl ActorClaims {
+    pub fn validate(&self, now: OffsetDateTime) -> Result<(), ActorClaimsError> {
+        if self.expires_at <= self.issued_at {
+            return Err(ActorClaimsError::InvalidTimeWindow {
+                issued_at: self.issued_at,
+                expires_at: self.expires_at,
+            });
+        }
+        if self.issued_at.as_offset_date_time() > now + ACTOR_CLAIMS_CLOCK_SKEW {
+            return Err(ActorClaimsError::IssuedInFuture {
+                issued_at: self.issued_at,
+            });
+        }
+        if now >= self.expires_at.as_offset_date_time() {
+            return Err(ActorClaimsError::Expired {
+                expires_at: self.expires_at,
+            });
+        }
+        if self.on_behalf_of == Some(self.actor) {
+            return Err(ActorClaimsError::SelfDelegation);
+        }
+        Ok(())
+    }
+
+    /*
+    * Claims for delegating to another service, limited to one bucket and a subset of the permissions.
+    * Actor, on_behalf_of, verification and the time window are kept, a delegate can never outlive the original claims.
+    */
+    pub fn narrowed_to(
+        &self,
+        bucket: BucketGuid,
+        permissions: BucketSharePermissionFlags,
+    ) -> Result<Self, EscalationError> {
+        if let Some(scope) = self.bucket_scope {
+            if scope != bucket {
+                return Err(EscalationError::BucketOutOfScope {
+                    scope,
+                    requested: bucket,
+                });
+            }
+        }
+        let added = permissions.difference(self.permissions);
+        if !added.is_empty() {
+            return Err(EscalationError::Permissions { added });
+        }
+        Ok(Self {
+            bucket_scope: Some(bucket),
+            permissions,
+            ..self.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn claims() -> ActorClaims {
+        ActorClaims {
+            actor: "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            on_behalf_of: Some("33333333-0000-4000-8000-000000000003".parse().unwrap()),
+            bucket_scope: None,
+            permissions: BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            verification: Verification::EMAIL | Verification::TOTP,
+            issued_at: ExpiryTimestamp::try_from(datetime!(2024-06-01 12:00 UTC)).unwrap(),
+            expires_at: ExpiryTimestamp::try_from(datetime!(2024-06-01 12:05 UTC)).unwrap(),
+        }
+    }
+
+    fn bucket() -> BucketGuid {
+        BucketGuid::new(
+            "33333333-0000-4000-8000-000000000003".parse().unwrap(),
+            "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn golden_json() {
+        let json = concat!(
+            r#"{"act":"11111111-0000-4000-8000-000000000001","obo":"33333333-0000-4000-8000-000000000003","#,
+            r#""bkt":null,"prm":"VIEW | READ","vrf":"EMAIL | TOTP","#,
+            r#""iat":"2024-06-01T12:00:00Z","exp":"2024-06-01T12:05:00Z"}"#
+        );
+        assert_eq!(serde_json::to_string(&claims()).unwrap(), json);
+        assert_eq!(serde_json::from_str::<ActorClaims>(json).unwrap(), claims());
+
+        let narrowed = claims()
+            .narrowed_to(bucket(), BucketSharePermissionFlags::READ)
+            .unwrap();
+        let json = serde_json::to_string(&narrowed).unwrap();
+        assert!(
+            json.contains(r#""bkt":{"user_id":"33333333-0000-4000-8000-000000000003","#),
+            "{}",
+            json
+        );
+        assert_eq!(serde_json::from_str::<ActorClaims>(&json).unwrap(), narrowed);
+    }
+
+    #[test]
+    fn validation() {
+        let issued = datetime!(2024-06-01 12:00 UTC);
+        assert_eq!(claims().validate(issued), Ok(()));
+        assert_eq!(claims().validate(issued + Duration::seconds(299)), Ok(()));
+        assert_eq!(claims().validate(issued - ACTOR_CLAIMS_CLOCK_SKEW), Ok(()));
+        assert_eq!(
+            claims().validate(issued + Duration::seconds(300)),
+            Err(ActorClaimsError::Expired {
+                expires_at: claims().expires_at
+            })
+        );
+        assert_eq!(
+            claims().validate(issued - Duration::seconds(31)),
+            Err(ActorClaimsError::IssuedInFuture {
+                issued_at: claims().issued_at
+            })
+        );
+
+        let inverted = ActorClaims {
+            expires_at: claims().issued_at,
+            ..claims()
+        };
+        assert_eq!(
+            inverted.validate(issued),
+            Err(ActorClaimsError::InvalidTimeWindow {
+                issued_at: claims().issued_at,
+                expires_at: claims().issued_at
+            })
+        );
+
+        let own = ActorClaims {
+            on_behalf_of: Some(claims().actor),
+            ..claims()
+        };
+        assert_eq!(own.validate(issued), Err(ActorClaimsError::SelfDelegation));
+        let direct = ActorClaims {
+            on_behalf_of: None,
+            ..claims()
+        };
+        assert_eq!(direct.validate(issued), Ok(()));
+    }
+
+    #[test]
+    fn narrowing_never_escalates() {
+        let narrowed = claims()
+            .narrowed_to(bucket(), BucketSharePermissionFlags::READ)
+            .unwrap();
+        assert_eq!(narrowed.bucket_scope, Some(bucket()));
+        assert_eq!(narrowed.permissions, BucketSharePermissionFlags::READ);
+        assert_eq!(
+            (narrowed.actor, narrowed.expires_at),
+            (claims().actor, claims().expires_at)
+        );
+
+        // Narrowing again to the same bucket, with the same or fewer permissions.
+        assert_eq!(
+            narrowed.narrowed_to(bucket(), BucketSharePermissionFlags::READ),
+            Ok(narrowed.clone())
+        );
+        assert!(narrowed
+            .narrowed_to(bucket(), BucketSharePermissionFlags::empty())
+            .is_ok());
+
+        assert_eq!(
+            claims().narrowed_to(
+                bucket(),
+                BucketSharePermissionFlags::READ
+                    | BucketSharePermissionFlags::WRITE
+                    | BucketSharePermissionFlags::SHARE_BUCKET
+            ),
+            Err(EscalationError::Permissions {
+                added: BucketSharePermissionFlags::WRITE | BucketSharePermissionFlags::SHARE_BUCKET
+            })
+        );
+        let other = BucketGuid::new(
+            bucket().user_id,
+            "22222222-0000-4000-8000-000000000009".parse().unwrap(),
+        );
+        assert_eq!(
+            narrowed.narrowed_to(other, BucketSharePermissionFlags::READ),
+            Err(EscalationError::BucketOutOfScope {
+                scope: bucket(),
+                requested: other
+            })
+        );
+        assert_eq!(
+            narrowed.narrowed_to(bucket(), BucketSharePermissionFlags::VIEW),
+            Err(EscalationError::Permissions {
+                added: BucketSharePermissionFlags::VIEW
+            })
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::str::FromStr;
+
+use crate::{BucketStorageClass, DownloadFormat, Verification};
+
+/*
+* Legacy API strings still accepted in place of the current name, as (alias, canonical) pairs.
+* Aliases are only ever parsed, values always serialize under the canonical name.
+* With the compat-aliases feature the serde Deserialize impls of these types consult the tables too.
+*/
+pub const STORAGE_CLASS_ALIASES: &[(&str, &str)] = &[("reduced", "ReducedRedundancy")];
+pub const DOWNLOAD_FORMAT_ALIASES: &[(&str, &str)] = &[("zip64", "Zip")];
+// Per flag name, "EMAIL | 2fa" is accepted as well.
+pub const VERIFICATION_ALIASES: &[(&str, &str)] = &[("2fa", "TOTP")];
+
+// Replaces s with its canonical name when it is an alias and parses it. Aliases are matched exactly.
+pub fn parse_with_aliases<T: FromStr>(s: &str, table: &[(&str, &str)]) -> Result<T, T::Err> {
+    canonical_name(s, table).parse()
+}
+
+fn canonical_name<'a>(s: &'a str, table: &[(&'a str, &'a str)]) -> &'a str {
+    table
+        .iter()
+        .find(|(alias, _)| *alias == s)
+        .map_or(s, |(_, canonical)| canonical)
+}
+
+impl BucketStorageClass {
+    pub fn parse_compat(s: &str) -> Result<Self, strum::ParseError> {
+        parse_with_aliases(s, STORAGE_CLASS_ALIASES)
+    }
+}
+
+impl DownloadFormat {
+    pub fn parse_compat(s: &str) -> Result<Self, strum::ParseError> {
+        parse_with_aliases(s, DOWNLOAD_FORMAT_ALIASES)
+    }
+}
+
+impl Verification {
+    // Flags are separated by '|' like the bitflags text format, each flag name may be an alias.
+    pub fn parse_compat(s: &str) -> Result<Self, bitflags::parser::ParseError> {
+        let canonical = s
+            .split('|')
+            .map(|name| canonical_name(name.trim(), VERIFICATION_ALIASES))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        bitflags::parser::from_str(&canonical)
+    }
+}
+
+/*
+* Only human-readable formats carry the names, binary formats keep the derived encoding.
+* The remote definitions have to list the same variants as the types they mirror.
+*/
+#[cfg(feature = "compat-aliases")]
+mod compat_serde {
+    use serde::{Deserialize, Deserializer};
+
+    use crate::{BucketStorageClass, DownloadFormat, Verification};
+
+    #[derive(Deserialize)]
+    #[serde(remote = "BucketStorageClass")]
+    enum BucketStorageClassDef {
+        General,
+        ReducedRedundancy,
+        Archive,
+        DeepArchive,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(remote = "DownloadFormat")]
+    enum DownloadFormatDef {
+        Zip,
+        Tar,
+        Raw,
+    }
+
+    impl<'de> Deserialize<'de> for BucketStorageClass {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            if !deserializer.is_human_readable() {
+                return BucketStorageClassDef::deserialize(deserializer);
+            }
+            let s = String::deserialize(deserializer)?;
+            Self::parse_compat(&s).map_err(|_| serde::de::Error::custom(format!("unknown storage class \"{}\"", s)))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for DownloadFormat {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            if !deserializer.is_human_readable() {
+                return DownloadFormatDef::deserialize(deserializer);
+            }
+            let s = String::deserialize(deserializer)?;
+            Self::parse_compat(&s).map_err(|_| serde::de::Error::custom(format!("unknown download format \"{}\"", s)))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Verification {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            if !deserializer.is_human_readable() {
+                return bitflags::serde::deserialize(deserializer);
+            }
+            let s = String::deserialize(deserializer)?;
+            Self::parse_compat(&s).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_alias_parses_to_its_canonical_value() {
+        for (alias, canonical) in STORAGE_CLASS_ALIASES {
+            let class = BucketStorageClass::parse_compat(alias).unwrap();
+            assert_eq!(class, canonical.parse().unwrap());
+            assert_eq!(class.to_string(), *canonical);
+            assert_eq!(serde_json::to_string(&class).unwrap(), format!("\"{}\"", canonical));
+            assert!(alias.parse::<BucketStorageClass>().is_err(), "{}", alias);
+        }
+        for (alias, canonical) in DOWNLOAD_FORMAT_ALIASES {
+            let format = DownloadFormat::parse_compat(alias).unwrap();
+            assert_eq!(format, canonical.parse().unwrap());
+            assert_eq!(format.to_string(), *canonical);
+            assert_eq!(serde_json::to_string(&format).unwrap(), format!("\"{}\"", canonical));
+            assert!(alias.parse::<DownloadFormat>().is_err(), "{}", alias);
+        }
+        for (alias, canonical) in VERIFICATION_ALIASES {
+            let flag = Verification::parse_compat(alias).unwrap();
+            assert_eq!(flag, Verification::from_name(canonical).unwrap());
+            assert_eq!(serde_json::to_string(&flag).unwrap(), format!("\"{}\"", canonical));
+            assert!(bitflags::parser::from_str::<Verification>(alias).is_err(), "{}", alias);
+        }
+        assert_eq!(Verification::parse_compat("EMAIL | 2fa").unwrap(), Verification::EMAIL | Verification::TOTP);
+        assert_eq!(Verification::parse_compat("").unwrap(), Verification::UNVERIFIED);
+    }
+
+    #[test]
+    fn canonical_names_and_unknown_strings() {
+        assert_eq!(BucketStorageClass::parse_compat("DeepArchive"), Ok(BucketStorageClass::DeepArchive));
+        assert_eq!(DownloadFormat::parse_compat("Tar"), Ok(DownloadFormat::Tar));
+        assert_eq!(Verification::parse_compat("PHONE").unwrap(), Verification::PHONE);
+        for unknown in ["Reduced", "reduced ", "zip", "cold", ""] {
+            assert!(BucketStorageClass::parse_compat(unknown).is_err(), "{:?}", unknown);
+            assert!(DownloadFormat::parse_compat(unknown).is_err(), "{:?}", unknown);
+        }
+        assert!(Verification::parse_compat("2FA").is_err());
+        assert!(Verification::parse_compat("EMAIL | sms").is_err());
+    }
+
+    #[cfg(feature = "compat-aliases")]
+    #[test]
+    fn deserialize_accepts_aliases_and_serializes_canonically() {
+        let class: BucketStorageClass = serde_json::from_str("\"reduced\"").unwrap();
+        assert_eq!(class, BucketStorageClass::ReducedRedundancy);
+        assert_eq!(serde_json::to_string(&class).unwrap(), "\"ReducedRedundancy\"");
+        let format: DownloadFormat = serde_json::from_str("\"zip64\"").unwrap();
+        assert_eq!(serde_json::to_string(&format).unwrap(), "\"Zip\"");
+        let verification: Verification = serde_json::from_str("\"EMAIL | 2fa\"").unwrap();
+        assert_eq!(serde_json::to_string(&verification).unwrap(), "\"EMAIL | TOTP\"");
+        assert!(serde_json::from_str::<BucketStorageClass>("\"cold\"").is_err());
+        assert!(serde_json::from_str::<Verification>("\"sms\"").is_err());
+
+        // Binary formats are unchanged.
+        let bytes = bincode::serialize(&BucketStorageClass::DeepArchive).unwrap();
+        assert_eq!(bincode::deserialize::<BucketStorageClass>(&bytes).unwrap(), BucketStorageClass::DeepArchive);
+        let bytes = bincode::serialize(&DownloadFormat::Raw).unwrap();
+        assert_eq!(bincode::deserialize::<DownloadFormat>(&bytes).unwrap(), DownloadFormat::Raw);
+        let bytes = bincode::serialize(&(Verification::EMAIL | Verification::TOTP)).unwrap();
+        assert_eq!(
+            bincode::deserialize::<Verification>(&bytes).unwrap(),
+            Verification::EMAIL | Verification::TOTP
+        );
+    }
+}
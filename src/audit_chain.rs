@@ -0,0 +1,222 @@
+#![cfg(feature = "share_link")]
+
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+use crate::bucket_object::ContentHash;
+use crate::canonical_json::to_canonical_vec;
+use crate::link_audit::LinkAuditRecord;
+
+// prev_hash of the first record, and the head of an empty chain.
+pub const GENESIS_HASH: ContentHash = ContentHash([0; 32]);
+
+// A record in an AuditChain, record_hash is SHA3-256 over the canonical JSON of record followed by prev_hash.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ChainedRecord {
+    pub record: LinkAuditRecord,
+    pub prev_hash: ContentHash,
+    pub record_hash: ContentHash,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[error("audit chain breaks at record {index}")]
+pub struct ChainBreak {
+    pub index: usize,
+}
+
+/*
+* Tamper-evident log of link issuance for a bucket. Every record hashes the one before it, so changing, dropping or
+* reordering a record breaks the chain from there on and verify reports the first record that does not fit.
+* Serialized as the array of chained records. Deserializing does not verify, call verify on chains from storage.
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AuditChain {
+    records: Vec<ChainedRecord>,
+}
+
+fn record_hash(record: &LinkAuditRecord, prev_hash: &ContentHash) -> ContentHash {
+    // Audit records are strings, uuids and optional strings, none of which canonical JSON rejects.
+    let mut hasher = Sha3_256::new();
+    hasher.update(to_canonical_vec(record).expect("audit records are canonical JSON"));
+    hasher.update(prev_hash.0);
+    ContentHash(hasher.finalize().into())
+}
+
+impl ChainedRecord {
+    pub fn new(record: LinkAuditRecord, prev_hash: ContentHash) -> Self {
+        let record_hash = record_hash(&record, &prev_hash);
+        Self {
+            record,
+            prev_hash,
+            record_hash,
+        }
+    }
+}
+
+impl AuditChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> &[ChainedRecord] {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // record_hash of the last record, GENESIS_HASH for an empty chain.
+    pub fn head_hash(&self) -> ContentHash {
+        self.records.last().map_or(GENESIS_HASH, |last| last.record_hash)
+    }
+
+    pub fn append(&mut self, record: LinkAuditRecord) -> ChainedRecord {
+        let chained = ChainedRecord::new(record, self.head_hash());
+        self.records.push(chained.clone());
+        chained
+    }
+
+    pub fn verify(&self) -> Result<(), ChainBreak> {
+        Self::verify_extension(GENESIS_HASH, &self.records).map(|_| ())
+    }
+
+    /*
+    * For servers that only keep the head: checks that new_records continue a chain whose head is old_head and
+    * returns the new head. The index of a ChainBreak counts from the first of new_records.
+    */
+    pub fn verify_extension(old_head: ContentHash, new_records: &[ChainedRecord]) -> Result<ContentHash, ChainBreak> {
+        new_records.iter().enumerate().try_fold(old_head, |head, (index, chained)| {
+            match chained.prev_hash == head && chained.record_hash == record_hash(&chained.record, &head) {
+                true => Ok(chained.record_hash),
+                false => Err(ChainBreak { index }),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::share_link::{BucketSharePermissionFlags, ShareLinkToken};
+
+    fn record(index: u32) -> LinkAuditRecord {
+        let mut token = [0; 32];
+        token[..4].copy_from_slice(&index.to_be_bytes());
+        LinkAuditRecord {
+            token: ShareLinkToken(token),
+            user_id: "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            bucket_id: "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+            permission: BucketSharePermissionFlags::READ,
+            expires: None,
+            recipient: None,
+            channel: None,
+            issued_at: datetime!(2024-06-01 12:00 UTC) + time::Duration::seconds(index as i64),
+        }
+    }
+
+    fn chain(len: u32) -> AuditChain {
+        let mut chain = AuditChain::new();
+        for index in 0..len {
+            chain.append(record(index));
+        }
+        chain
+    }
+
+    #[test]
+    fn append_links_records() {
+        let mut chain = AuditChain::new();
+        let first = chain.append(record(0));
+        assert_eq!(first.prev_hash, GENESIS_HASH);
+        let second = chain.append(record(1));
+        assert_eq!(second.prev_hash, first.record_hash);
+        assert_eq!(chain.head_hash(), second.record_hash);
+        assert_eq!(chain.records(), [first, second.clone()]);
+        assert_eq!(chain.verify(), Ok(()));
+        // The same record after another head hashes differently.
+        assert_ne!(ChainedRecord::new(record(1), GENESIS_HASH).record_hash, second.record_hash);
+    }
+
+    #[test]
+    fn tampering_breaks_at_the_record() {
+        let mut tampered = chain(5);
+        tampered.records[2].record.permission = BucketSharePermissionFlags::all();
+        assert_eq!(tampered.verify(), Err(ChainBreak { index: 2 }));
+
+        // Rehashing the tampered record moves the break to the record after it.
+        let mut rehashed = chain(5);
+        let mut record = record(2);
+        record.recipient = Some(uuid::Uuid::nil());
+        rehashed.records[2] = ChainedRecord::new(record, rehashed.records[1].record_hash);
+        assert_eq!(rehashed.verify(), Err(ChainBreak { index: 3 }));
+
+        let mut flipped = chain(5);
+        flipped.records[4].record_hash.0[31] ^= 1;
+        assert_eq!(flipped.verify(), Err(ChainBreak { index: 4 }));
+    }
+
+    #[test]
+    fn reordering_breaks_the_chain() {
+        let mut reordered = chain(5);
+        reordered.records.swap(1, 3);
+        assert_eq!(reordered.verify(), Err(ChainBreak { index: 1 }));
+
+        let mut dropped = chain(5);
+        dropped.records.remove(2);
+        assert_eq!(dropped.verify(), Err(ChainBreak { index: 2 }));
+    }
+
+    #[test]
+    fn long_chain_verifies() {
+        let chain = chain(10_000);
+        assert_eq!(chain.len(), 10_000);
+        assert_eq!(chain.verify(), Ok(()));
+        assert_eq!(AuditChain::verify_extension(GENESIS_HASH, chain.records()), Ok(chain.head_hash()));
+    }
+
+    #[test]
+    fn extensions() {
+        let full = chain(6);
+        let (old, new) = full.records().split_at(4);
+        let old_head = old.last().unwrap().record_hash;
+        assert_eq!(AuditChain::verify_extension(old_head, new), Ok(full.head_hash()));
+        // Extensions of another head, or with a gap, break at their first record.
+        assert_eq!(AuditChain::verify_extension(GENESIS_HASH, new), Err(ChainBreak { index: 0 }));
+        assert_eq!(AuditChain::verify_extension(old_head, &new[1..]), Err(ChainBreak { index: 0 }));
+    }
+
+    #[test]
+    fn empty_chain() {
+        let empty = AuditChain::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.head_hash(), GENESIS_HASH);
+        assert_eq!(empty.verify(), Ok(()));
+        assert_eq!(AuditChain::verify_extension(GENESIS_HASH, &[]), Ok(GENESIS_HASH));
+        let head = chain(3).head_hash();
+        assert_eq!(AuditChain::verify_extension(head, &[]), Ok(head));
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "[]");
+        assert_eq!(serde_json::from_str::<AuditChain>("[]").unwrap(), empty);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let chain = chain(3);
+        let json = serde_json::to_string(&chain).unwrap();
+        assert!(json.starts_with(r#"[{"record":{"token":"#), "{}", json);
+        assert!(json.contains(&format!(r#""prev_hash":"{}""#, GENESIS_HASH)), "{}", json);
+        let parsed = serde_json::from_str::<AuditChain>(&json).unwrap();
+        assert_eq!(parsed, chain);
+        assert_eq!(parsed.verify(), Ok(()));
+
+        // Deserializing does not verify.
+        let tampered = json.replacen("READ", "WRITE", 1);
+        assert_eq!(serde_json::from_str::<AuditChain>(&tampered).unwrap().verify(), Err(ChainBreak { index: 0 }));
+    }
+}
@@ -0,0 +1,364 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::util::truncate_input;
+
+// Brand palette of the console, a bucket picks one of these or any RGB color.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Hash,
+    strum::EnumString,
+    strum::Display,
+    strum::EnumIter,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum PaletteColor {
+    Red,
+    Orange,
+    Amber,
+    Yellow,
+    Lime,
+    Green,
+    Emerald,
+    Teal,
+    Cyan,
+    Sky,
+    Blue,
+    Indigo,
+    Violet,
+    Purple,
+    Pink,
+    Slate,
+}
+
+/*
+* Color of a bucket in the console, either a palette name like "teal" or "#RRGGBB".
+* Parsing is strict since the value ends up in markup: exactly '#' and six hex digits or a palette name in lowercase.
+* Displayed and serialized the same way, hex in lowercase.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum BucketColor {
+    Palette(PaletteColor),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum BucketColorParsingError {
+    #[error("invalid bucket color \"{0}\", expected #RRGGBB or a palette color")]
+    InvalidFormat(String),
+}
+
+impl SpannedParseError for BucketColorParsingError {
+    fn failure(&self) -> ParseFailure {
+        let BucketColorParsingError::InvalidFormat(input) = self;
+        ParseFailure::whole(self, input)
+    }
+}
+
+impl fmt::Display for BucketColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BucketColor::Palette(color) => write!(f, "{}", color),
+            BucketColor::Rgb(red, green, blue) => write!(f, "#{:02x}{:02x}{:02x}", red, green, blue),
+        }
+    }
+}
+
+impl FromStr for BucketColor {
+    type Err = BucketColorParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BucketColorParsingError::InvalidFormat(truncate_input(s));
+        let Some(hex) = s.strip_prefix('#') else {
+            return s.parse().map(BucketColor::Palette).map_err(|_| invalid());
+        };
+        // from_str_radix alone would also take a sign.
+        if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid());
+        Ok(BucketColor::Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl Serialize for BucketColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BucketColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// Ranges of emoji that can start an emoji, regional indicators are handled separately.
+const EMOJI_RANGES: [(char, char); 9] = [
+    ('\u{2600}', '\u{26FF}'),
+    ('\u{2700}', '\u{27BF}'),
+    ('\u{1F300}', '\u{1F3FA}'),
+    ('\u{1F400}', '\u{1F5FF}'),
+    ('\u{1F600}', '\u{1F64F}'),
+    ('\u{1F680}', '\u{1F6FF}'),
+    ('\u{1F900}', '\u{1F9FF}'),
+    ('\u{1FA70}', '\u{1FAFF}'),
+    ('\u{1F004}', '\u{1F004}'),
+];
+const REGIONAL_INDICATORS: (char, char) = ('\u{1F1E6}', '\u{1F1FF}');
+const SKIN_TONES: (char, char) = ('\u{1F3FB}', '\u{1F3FF}');
+const TAGS: (char, char) = ('\u{E0020}', '\u{E007E}');
+const CANCEL_TAG: char = '\u{E007F}';
+const VARIATION_SELECTOR_16: char = '\u{FE0F}';
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+fn in_range(c: char, (first, last): (char, char)) -> bool {
+    (first..=last).contains(&c)
+}
+
+fn is_emoji_base(c: char) -> bool {
+    EMOJI_RANGES.iter().any(|range| in_range(c, *range))
+}
+
+/*
+* Whether s is a single emoji: a flag of two regional indicators, or emoji joined by zero width joiners where each
+* may carry a variation selector, a skin tone and a tag sequence (subdivision flags).
+* That is one extended grapheme cluster (UAX #29 rules GB9, GB11 and GB12) built only from emoji, so text, combining
+* marks on letters and several emoji in a row are rejected. Keycaps like "1️⃣" start with a digit and are rejected too.
+*/
+fn is_single_emoji(s: &str) -> bool {
+    if s.starts_with(|c| in_range(c, REGIONAL_INDICATORS)) {
+        return s.chars().count() == 2 && s.chars().all(|c| in_range(c, REGIONAL_INDICATORS));
+    }
+    let mut chars = s.chars().peekable();
+    loop {
+        if !chars.next().is_some_and(is_emoji_base) {
+            return false;
+        }
+        chars.next_if_eq(&VARIATION_SELECTOR_16);
+        chars.next_if(|c| in_range(*c, SKIN_TONES));
+        if chars.next_if(|c| in_range(*c, TAGS)).is_some() {
+            while chars.next_if(|c| in_range(*c, TAGS)).is_some() {}
+            if chars.next_if_eq(&CANCEL_TAG).is_none() {
+                return false;
+            }
+        }
+        match chars.next() {
+            None => return true,
+            Some(ZERO_WIDTH_JOINER) => continue,
+            Some(_) => return false,
+        }
+    }
+}
+
+// A single emoji, see is_single_emoji for what counts as one.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct EmojiChar(String);
+
+impl EmojiChar {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for EmojiChar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for EmojiChar {
+    type Err = BucketIconParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match is_single_emoji(s) {
+            true => Ok(Self(s.to_string())),
+            false => Err(BucketIconParsingError::InvalidFormat(truncate_input(s))),
+        }
+    }
+}
+
+/*
+* Icon of a bucket in the console, one of the built in icons by name or a single emoji.
+* Names are ASCII and emoji never are, so the two can not be confused.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash, strum::IntoStaticStr, strum::EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum BucketIcon {
+    Folder,
+    Photos,
+    Music,
+    Video,
+    Documents,
+    Archive,
+    Code,
+    Backup,
+    Shared,
+    Work,
+    Personal,
+    Star,
+    Heart,
+    Cloud,
+    Lock,
+    #[strum(disabled)]
+    Custom(EmojiChar),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum BucketIconParsingError {
+    #[error("invalid bucket icon \"{0}\", expected an icon name or a single emoji")]
+    InvalidFormat(String),
+}
+
+impl SpannedParseError for BucketIconParsingError {
+    fn failure(&self) -> ParseFailure {
+        let BucketIconParsingError::InvalidFormat(input) = self;
+        ParseFailure::whole(self, input)
+    }
+}
+
+impl fmt::Display for BucketIcon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BucketIcon::Custom(emoji) => write!(f, "{}", emoji),
+            named => f.write_str(named.into()),
+        }
+    }
+}
+
+impl FromStr for BucketIcon {
+    type Err = BucketIconParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.is_ascii() {
+            return s.parse().map(BucketIcon::Custom);
+        }
+        <BucketIcon as strum::IntoEnumIterator>::iter()
+            .find(|icon| <&'static str>::from(icon) == s)
+            .ok_or_else(|| BucketIconParsingError::InvalidFormat(truncate_input(s)))
+    }
+}
+
+impl Serialize for BucketIcon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BucketIcon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!("#1a2B3c".parse(), Ok(BucketColor::Rgb(0x1a, 0x2b, 0x3c)));
+        assert_eq!(BucketColor::Rgb(0x1a, 0x2b, 0x3c).to_string(), "#1a2b3c");
+        assert_eq!("teal".parse(), Ok(BucketColor::Palette(PaletteColor::Teal)));
+        for input in [
+            "", "#", "#GGG", "#GGGGGG", "#fff", "#ffff", "#fffffff", "#+fffff", "# fffff", "ffffff", "Teal", "teal ",
+            "javascript:alert(1)", "url(javascript:alert(1))", "red;background:url(x)", "#ff0000\"><script>",
+        ] {
+            assert_eq!(
+                input.parse::<BucketColor>(),
+                Err(BucketColorParsingError::InvalidFormat(input.to_string())),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn color_serde_round_trip() {
+        let colors = PaletteColor::iter()
+            .map(BucketColor::Palette)
+            .chain([BucketColor::Rgb(0, 0, 0), BucketColor::Rgb(0xff, 0x80, 0x01)]);
+        for color in colors {
+            let json = serde_json::to_string(&color).unwrap();
+            assert_eq!(json, format!("\"{}\"", color));
+            assert_eq!(serde_json::from_str::<BucketColor>(&json).unwrap(), color);
+        }
+        assert_eq!(PaletteColor::iter().count(), 16);
+        assert!(serde_json::from_str::<BucketColor>("\"#GGG\"").is_err());
+    }
+
+    #[test]
+    fn emoji() {
+        for input in [
+            "📁",
+            "☀",
+            "☀️",
+            "👍🏽",
+            "🇸🇪",
+            // Woman technologist, family and rainbow flag are ZWJ sequences.
+            "👩‍💻",
+            "👨‍👩‍👧‍👦",
+            "🏳️‍🌈",
+            // Flag of Scotland, a tag sequence.
+            "🏴\u{E0067}\u{E0062}\u{E0073}\u{E0063}\u{E0074}\u{E007F}",
+        ] {
+            assert_eq!(input.parse::<EmojiChar>().map(|emoji| emoji.to_string()), Ok(input.to_string()));
+            assert_eq!(input.parse(), Ok(BucketIcon::Custom(EmojiChar(input.to_string()))));
+        }
+        for input in [
+            "",
+            "a",
+            "é",
+            "e\u{301}",
+            "日本",
+            "📁📁",
+            "📁 ",
+            "🇸",
+            "🇸🇪🇸",
+            "👩‍",
+            "‍💻",
+            "\u{FE0F}",
+            "🏽",
+            "1️⃣",
+            "🏴\u{E0067}\u{E0062}",
+            "📁<script>",
+        ] {
+            assert!(input.parse::<EmojiChar>().is_err(), "{}", input);
+            assert!(input.parse::<BucketIcon>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn icons() {
+        let icons = BucketIcon::iter().chain([BucketIcon::Custom("🚀".parse().unwrap())]).collect::<Vec<_>>();
+        assert_eq!(icons.len(), 16);
+        for icon in icons {
+            let json = serde_json::to_string(&icon).unwrap();
+            assert_eq!(json, format!("\"{}\"", icon));
+            assert_eq!(serde_json::from_str::<BucketIcon>(&json).unwrap(), icon);
+        }
+        assert_eq!(BucketIcon::Documents.to_string(), "documents");
+        for input in ["Folder", "custom", "folder ", "javascript:alert(1)"] {
+            assert_eq!(
+                input.parse::<BucketIcon>(),
+                Err(BucketIconParsingError::InvalidFormat(input.to_string())),
+                "{}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::errors::{char_index_span, ParseFailure, SpannedParseError};
+use crate::util::truncate_input;
+
+pub const MAX_DESCRIPTION_CHARS: usize = 2048;
+pub const MAX_DISPLAY_NAME_CHARS: usize = 140;
+pub const MAX_BUCKET_NAME_CHARS: usize = 512;
+
+// Free text description of a bucket. Trimmed and NFC-normalized, newlines are the only control characters allowed.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BucketDescription(String);
+
+// Name a bucket is created with, also used for file names of its downloads. Same rules as BucketDisplayName with a higher limit.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BucketName(String);
+
+// Title shown for a bucket instead of its name. Trimmed and NFC-normalized, no control characters including newlines.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BucketDisplayName(String);
+
+/*
+* Lengths are counted in chars after normalization.
+* The index of a forbidden character is its char index in the normalized text, which the error carries truncated.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum BucketTextError {
+    #[error("text is empty")]
+    Empty,
+    #[error("text is {got} characters long, max is {max}")]
+    TooLong { max: usize, got: usize },
+    #[error("forbidden character {char:?} at index {index}")]
+    ForbiddenCharacter { index: usize, char: char, text: String },
+}
+
+impl SpannedParseError for BucketTextError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            BucketTextError::ForbiddenCharacter { index, char, text } => {
+                ParseFailure::spanned(self, text, char_index_span(text, *index, *char))
+            }
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+/*
+* Canonical form of user supplied bucket text: NFC (canonical composition) followed by trimming whitespace.
+* NFC is deterministic across platforms and unicode-normalization versions for assigned characters, and both steps are idempotent.
+*/
+pub fn normalize(text: &str) -> String {
+    text.nfc().collect::<String>().trim().to_string()
+}
+
+fn validate(text: &str, max: usize, allow_newline: bool) -> Result<String, BucketTextError> {
+    let text = normalize(text);
+    if text.is_empty() {
+        return Err(BucketTextError::Empty);
+    }
+    let mut count = 0;
+    for (index, char) in text.chars().enumerate() {
+        if char.is_control() && !(allow_newline && char == '\n') {
+            return Err(BucketTextError::ForbiddenCharacter {
+                index,
+                char,
+                text: truncate_input(&text),
+            });
+        }
+        count += 1;
+    }
+    if count > max {
+        return Err(BucketTextError::TooLong { max, got: count });
+    }
+    Ok(text)
+}
+
+impl BucketDescription {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for BucketDescription {
+    type Error = BucketTextError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(Self(validate(&value, MAX_DESCRIPTION_CHARS, true)?))
+    }
+}
+
+impl AsRef<str> for BucketDescription {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for BucketDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for BucketDescription {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for BucketDescription {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::try_from(s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl BucketDisplayName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for BucketDisplayName {
+    type Error = BucketTextError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(Self(validate(&value, MAX_DISPLAY_NAME_CHARS, false)?))
+    }
+}
+
+impl AsRef<str> for BucketDisplayName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for BucketDisplayName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for BucketDisplayName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for BucketDisplayName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::try_from(s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl BucketName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for BucketName {
+    type Error = BucketTextError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(Self(validate(&value, MAX_BUCKET_NAME_CHARS, false)?))
+    }
+}
+
+impl AsRef<str> for BucketName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for BucketName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for BucketName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for BucketName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::try_from(s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description_validation() {
+        let description = BucketDescription::try_from("  Holiday photos\nfrom 2024  ".to_string()).unwrap();
+        assert_eq!(description.as_str(), "Holiday photos\nfrom 2024");
+
+        let longest = "\u{e5}".repeat(MAX_DESCRIPTION_CHARS);
+        assert!(BucketDescription::try_from(longest.clone()).is_ok());
+        assert_eq!(
+            BucketDescription::try_from(longest + "a"),
+            Err(BucketTextError::TooLong {
+                max: MAX_DESCRIPTION_CHARS,
+                got: MAX_DESCRIPTION_CHARS + 1
+            })
+        );
+        assert_eq!(
+            BucketDescription::try_from("tab\there".to_string()),
+            Err(BucketTextError::ForbiddenCharacter {
+                index: 3,
+                char: '\t',
+                text: "tab\there".to_string()
+            })
+        );
+        assert_eq!(
+            BucketDescription::try_from("a\r\nb".to_string()),
+            Err(BucketTextError::ForbiddenCharacter {
+                index: 1,
+                char: '\r',
+                text: "a\r\nb".to_string()
+            })
+        );
+        assert_eq!(BucketDescription::try_from(" \n ".to_string()), Err(BucketTextError::Empty));
+    }
+
+    #[test]
+    fn display_name_validation() {
+        assert_eq!(BucketDisplayName::try_from(" Photos ".to_string()).unwrap().to_string(), "Photos");
+        assert!(BucketDisplayName::try_from("x".repeat(MAX_DISPLAY_NAME_CHARS)).is_ok());
+        assert_eq!(
+            BucketDisplayName::try_from("x".repeat(MAX_DISPLAY_NAME_CHARS + 1)),
+            Err(BucketTextError::TooLong {
+                max: MAX_DISPLAY_NAME_CHARS,
+                got: MAX_DISPLAY_NAME_CHARS + 1
+            })
+        );
+        assert_eq!(
+            BucketDisplayName::try_from("two\nlines".to_string()),
+            Err(BucketTextError::ForbiddenCharacter {
+                index: 3,
+                char: '\n',
+                text: "two\nlines".to_string()
+            })
+        );
+        assert_eq!(
+            BucketDisplayName::try_from("bell\u{7}".to_string()),
+            Err(BucketTextError::ForbiddenCharacter {
+                index: 4,
+                char: '\u{7}',
+                text: "bell\u{7}".to_string()
+            })
+        );
+        assert_eq!(BucketDisplayName::try_from("".to_string()), Err(BucketTextError::Empty));
+    }
+
+    #[test]
+    fn text_is_nfc_normalized() {
+        // "a" followed by a combining ring above composes to a single char.
+        let name = BucketDisplayName::try_from("a\u{30a}ngstr\u{f6}m".to_string()).unwrap();
+        assert_eq!(name.as_str(), "\u{e5}ngstr\u{f6}m");
+        assert_eq!(name, BucketDisplayName::try_from("\u{e5}ngstr\u{f6}m".to_string()).unwrap());
+        // Length is counted after composition.
+        let composed = "a\u{30a}".repeat(MAX_DISPLAY_NAME_CHARS);
+        assert!(BucketDisplayName::try_from(composed).is_ok());
+    }
+
+    #[test]
+    fn serde_validates() {
+        let name = BucketDisplayName::try_from("Photos".to_string()).unwrap();
+        assert_eq!(serde_json::to_string(&name).unwrap(), "\"Photos\"");
+        assert_eq!(serde_json::from_str::<BucketDisplayName>("\" Photos \"").unwrap(), name);
+        assert!(serde_json::from_str::<BucketDisplayName>("\"a\\nb\"").is_err());
+        assert!(serde_json::from_str::<BucketDescription>("\"\"").is_err());
+        let description = serde_json::from_str::<BucketDescription>("\"a\\nb\"").unwrap();
+        assert_eq!(description.as_ref(), "a\nb");
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn normalize_is_idempotent(s in "\\PC*|[ \\t\\na\u{300}-\u{36f}\u{e5}\u{2000}-\u{200a}\u{1100}-\u{1112}\u{1161}-\u{1175}]*") {
+            let normalized = normalize(&s);
+            proptest::prop_assert_eq!(normalize(&normalized), normalized);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::bucket_guid::BucketGuid;
+use crate::storage_quota::StorageQuota;
+use crate::BucketStatus;
+
+/*
+* Something that happened to a bucket, delivered to webhooks in an EventEnvelope. Tagged with "event".
+* BucketEventKind is generated from the variants, its snake_case names are what webhook filters list.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, strum::EnumDiscriminants)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[strum_discriminants(
+    name(BucketEventKind),
+    derive(Hash, Ord, PartialOrd, strum::Display, strum::EnumString, strum::EnumIter, Serialize, Deserialize),
+    strum(serialize_all = "snake_case"),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum BucketEvent {
+    BucketCreated,
+    BucketDeleted,
+    StatusChanged { status: BucketStatus },
+    ObjectUploaded { key: String, size: u64 },
+    ObjectDeleted { key: String },
+    QuotaExceeded { quota: StorageQuota, attempted: StorageQuota },
+}
+
+impl BucketEvent {
+    pub fn kind(&self) -> BucketEventKind {
+        self.into()
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub id: uuid::Uuid,
+    pub bucket: BucketGuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub occurred_at: OffsetDateTime,
+    #[serde(flatten)]
+    pub event: BucketEvent,
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn kinds_are_snake_case() {
+        let kinds = BucketEventKind::iter().map(|kind| kind.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                "bucket_created",
+                "bucket_deleted",
+                "status_changed",
+                "object_uploaded",
+                "object_deleted",
+                "quota_exceeded",
+            ]
+        );
+        for kind in BucketEventKind::iter() {
+            assert_eq!(kind.to_string().parse(), Ok(kind));
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.to_string());
+        }
+        assert!("ObjectUploaded".parse::<BucketEventKind>().is_err());
+    }
+
+    #[test]
+    fn envelope_json() {
+        let envelope = EventEnvelope {
+            id: uuid::Uuid::from_u128(1),
+            bucket: BucketGuid::new(uuid::Uuid::from_u128(2), uuid::Uuid::from_u128(3)),
+            occurred_at: datetime!(2024-06-01 0:00 UTC),
+            event: BucketEvent::ObjectUploaded { key: "photos/1.jpg".to_string(), size: 1024 },
+        };
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["event"], "object_uploaded");
+        assert_eq!(json["event"], envelope.event.kind().to_string());
+        assert_eq!(json["size"], 1024);
+        assert_eq!(json["occurred_at"], "2024-06-01T00:00:00Z");
+        assert_eq!(serde_json::from_value::<EventEnvelope>(json).unwrap(), envelope);
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::util::truncate_input;
+use crate::{BucketId, UserId};
+
+// Globally unique bucket identifier, bucket ids are only unique per user.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize), archive(check_bytes))]
+pub struct BucketGuid {
+    pub user_id: UserId,
+    pub bucket_id: BucketId,
+}
+
+impl BucketGuid {
+    pub fn new(user_id: UserId, bucket_id: BucketId) -> Self {
+        Self { user_id, bucket_id }
+    }
+
+    pub fn generate() -> Self {
+        Self::new(uuid::Uuid::new_v4(), uuid::Uuid::new_v4())
+    }
+}
+
+impl fmt::Display for BucketGuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.user_id, self.bucket_id)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum BucketGuidParsingError {
+    #[error("invalid bucket guid \"{0}\", expected <user_id>/<bucket_id>")]
+    InvalidFormat(String),
+}
+
+impl FromStr for BucketGuid {
+    type Err = BucketGuidParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BucketGuidParsingError::InvalidFormat(truncate_input(s));
+        let (user_id, bucket_id) = s.split_once('/').ok_or_else(invalid)?;
+        Ok(Self {
+            user_id: user_id.parse().map_err(|_| invalid())?,
+            bucket_id: bucket_id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+// Points at the id that failed to parse, or the whole input when there is no '/'.
+impl SpannedParseError for BucketGuidParsingError {
+    fn failure(&self) -> ParseFailure {
+        let BucketGuidParsingError::InvalidFormat(input) = self;
+        let span = match input.split_once('/') {
+            Some((user_id, _)) if user_id.parse::<UserId>().is_err() => 0..user_id.len(),
+            Some((user_id, _)) => user_id.len() + 1..input.len(),
+            None => 0..input.len(),
+        };
+        ParseFailure::spanned(self, input, span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_guid_round_trip() {
+        let guid = BucketGuid::generate();
+        assert_eq!(guid.to_string().parse::<BucketGuid>(), Ok(guid));
+        assert!("".parse::<BucketGuid>().is_err());
+        assert!(format!("{}", guid.user_id).parse::<BucketGuid>().is_err());
+        assert!(format!("{}/x", guid.user_id).parse::<BucketGuid>().is_err());
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn bucket_guid_archive_round_trip() {
+        use rkyv::Deserialize;
+
+        let guid = BucketGuid::generate();
+        let bytes = rkyv::to_bytes::<_, 64>(&guid).unwrap();
+        let archived = rkyv::check_archived_root::<BucketGuid>(&bytes).unwrap();
+        assert_eq!((archived.user_id, archived.bucket_id), (guid.user_id, guid.bucket_id));
+        assert_eq!(archived.deserialize(&mut rkyv::Infallible), Ok(guid));
+        assert!(rkyv::check_archived_root::<BucketGuid>(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Sha3_256};
+
+use crate::errors::{char_span, hex_span, ParseFailure, SpannedParseError};
+use crate::util::truncate_input;
+
+pub const MAX_OBJECT_KEY_LEN: usize = 1024;
+
+// Key of an object inside a bucket, e.g. "/photos/2024/beach.jpg". At most 1024 bytes and no control characters.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct BucketObjectKey(String);
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum BucketObjectKeyError {
+    #[error("object key can not be empty")]
+    Empty,
+    #[error("object key is {0} bytes, max is {MAX_OBJECT_KEY_LEN}")]
+    TooLong(usize),
+    #[error("forbidden character {character:?} at index {index} in object key \"{key}\"")]
+    ForbiddenCharacter { index: usize, character: char, key: String },
+}
+
+impl SpannedParseError for BucketObjectKeyError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            BucketObjectKeyError::ForbiddenCharacter { index, character, key } => {
+                ParseFailure::spanned(self, key, char_span(key, *index, *character))
+            }
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl BucketObjectKey {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for BucketObjectKey {
+    type Error = BucketObjectKeyError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Err(BucketObjectKeyError::Empty);
+        }
+        if value.len() > MAX_OBJECT_KEY_LEN {
+            return Err(BucketObjectKeyError::TooLong(value.len()));
+        }
+        if let Some((index, character)) = value.char_indices().find(|(_, c)| c.is_control()) {
+            return Err(BucketObjectKeyError::ForbiddenCharacter {
+                index,
+                character,
+                key: truncate_input(&value),
+            });
+        }
+        Ok(Self(value))
+    }
+}
+
+impl FromStr for BucketObjectKey {
+    type Err = BucketObjectKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s.to_string())
+    }
+}
+
+impl fmt::Display for BucketObjectKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for BucketObjectKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Serialize for BucketObjectKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for BucketObjectKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::try_from(String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+// SHA3-256 of an object or part of an object. Displayed and serialized as lowercase hex.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct ContentHash(pub [u8; 32]);
+
+impl ContentHash {
+    pub fn of(bytes: &[u8]) -> Self {
+        Self(Sha3_256::digest(bytes).into())
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum ContentHashParsingError {
+    #[error("invalid content hash \"{0}\", expected 64 hex characters")]
+    InvalidFormat(String),
+}
+
+impl FromStr for ContentHash {
+    type Err = ContentHashParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ContentHashParsingError::InvalidFormat(truncate_input(s));
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0u8; 32];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl SpannedParseError for ContentHashParsingError {
+    fn failure(&self) -> ParseFailure {
+        let ContentHashParsingError::InvalidFormat(input) = self;
+        ParseFailure::spanned(self, input, hex_span(input, 64))
+    }
+}
+
+impl Serialize for ContentHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_key_validation() {
+        assert!("/photos/2024/beach.jpg".parse::<BucketObjectKey>().is_ok());
+        assert_eq!("".parse::<BucketObjectKey>(), Err(BucketObjectKeyError::Empty));
+        assert_eq!(
+            "a".repeat(MAX_OBJECT_KEY_LEN + 1).parse::<BucketObjectKey>(),
+            Err(BucketObjectKeyError::TooLong(MAX_OBJECT_KEY_LEN + 1))
+        );
+        assert!("a".repeat(MAX_OBJECT_KEY_LEN).parse::<BucketObjectKey>().is_ok());
+        assert!(matches!(
+            "/a\nb".parse::<BucketObjectKey>(),
+            Err(BucketObjectKeyError::ForbiddenCharacter { index: 2, character: '\n', .. })
+        ));
+        assert!(serde_json::from_str::<BucketObjectKey>("\"\"").is_err());
+    }
+
+    #[test]
+    fn content_hash_round_trip() {
+        let hash = ContentHash::of(b"bucketdrive");
+        assert_eq!(hash.to_string().parse::<ContentHash>(), Ok(hash));
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(serde_json::from_str::<ContentHash>(&json).unwrap(), hash);
+        assert!("00".parse::<ContentHash>().is_err());
+    }
+}
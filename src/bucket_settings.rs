@@ -0,0 +1,561 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bucket_appearance::{BucketColor, BucketIcon};
+use crate::bucket_description::{BucketDescription, BucketDisplayName};
+use crate::indexing_policy::IndexingPolicy;
+use crate::maybe_unknown::MaybeUnknown;
+use crate::patch::Patch;
+use crate::write_conflict::WriteConflictStrategy;
+use crate::{
+    BucketCompression, BucketEncryption, BucketFeaturesFlags, BucketRegion, BucketStorageClass, BucketVisibility,
+};
+
+// All the user configurable settings of a bucket.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BucketSettings {
+    pub region: MaybeUnknown<BucketRegion>,
+    pub storage_class: BucketStorageClass,
+    pub compression: BucketCompression,
+    pub encryption: BucketEncryption,
+    pub visibility: BucketVisibility,
+    pub features: BucketFeaturesFlags,
+    // Absent in settings stored before they were added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<BucketDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<BucketDisplayName>,
+    // None shows the console's default color and icon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<BucketColor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<BucketIcon>,
+    // Applies to writes that do not bring their own strategy. Only serialized when not the default.
+    #[serde(default, skip_serializing_if = "WriteConflictStrategy::is_default")]
+    pub write_conflict: WriteConflictStrategy,
+    // Search engine indexing of the public listing, unrelated to BucketFeaturesFlags::IS_SEARCH_INDEXED.
+    #[serde(default, skip_serializing_if = "IndexingPolicy::is_default")]
+    pub indexing: IndexingPolicy,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum BucketSettingsError {
+    #[error("unknown {field} \"{value}\"")]
+    UnknownValue { field: &'static str, value: String },
+    #[error("{0} is required")]
+    MissingValue(&'static str),
+    #[error("{0} can only be set per write")]
+    PerWriteOnly(&'static str),
+    #[error("{0} only applies to public buckets")]
+    PublicOnly(&'static str),
+}
+
+/*
+* Settings for a new bucket, unset fields can be filled from account defaults before building.
+* Region is required, the rest fall back to: General storage, no compression, AES256 encryption, Private, no features,
+* last writer wins, indexing allowed.
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct BucketSettingsBuilder {
+    pub(crate) region: Option<BucketRegion>,
+    pub(crate) storage_class: Option<BucketStorageClass>,
+    pub(crate) compression: Option<BucketCompression>,
+    pub(crate) encryption: Option<BucketEncryption>,
+    pub(crate) visibility: Option<BucketVisibility>,
+    pub(crate) features: Option<BucketFeaturesFlags>,
+    pub(crate) description: Option<BucketDescription>,
+    pub(crate) display_name: Option<BucketDisplayName>,
+    pub(crate) color: Option<BucketColor>,
+    pub(crate) icon: Option<BucketIcon>,
+    pub(crate) write_conflict: Option<WriteConflictStrategy>,
+    pub(crate) indexing: Option<IndexingPolicy>,
+}
+
+impl BucketSettingsBuilder {
+    pub fn region(mut self, region: BucketRegion) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn storage_class(mut self, storage_class: BucketStorageClass) -> Self {
+        self.storage_class = Some(storage_class);
+        self
+    }
+
+    pub fn compression(mut self, compression: BucketCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub fn encryption(mut self, encryption: BucketEncryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    pub fn visibility(mut self, visibility: BucketVisibility) -> Self {
+        self.visibility = Some(visibility);
+        self
+    }
+
+    pub fn features(mut self, features: BucketFeaturesFlags) -> Self {
+        self.features = Some(features);
+        self
+    }
+
+    pub fn description(mut self, description: BucketDescription) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    pub fn display_name(mut self, display_name: BucketDisplayName) -> Self {
+        self.display_name = Some(display_name);
+        self
+    }
+
+    pub fn color(mut self, color: BucketColor) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn icon(mut self, icon: BucketIcon) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    pub fn write_conflict(mut self, write_conflict: WriteConflictStrategy) -> Self {
+        self.write_conflict = Some(write_conflict);
+        self
+    }
+
+    pub fn indexing(mut self, indexing: IndexingPolicy) -> Self {
+        self.indexing = Some(indexing);
+        self
+    }
+
+    pub fn build(self) -> Result<BucketSettings, BucketSettingsError> {
+        Ok(BucketSettings {
+            region: self.region.ok_or(BucketSettingsError::MissingValue("region"))?.into(),
+            storage_class: self.storage_class.unwrap_or(BucketStorageClass::General),
+            compression: self.compression.unwrap_or(BucketCompression::None),
+            encryption: self.encryption.unwrap_or(BucketEncryption::AES256),
+            visibility: self.visibility.unwrap_or(BucketVisibility::Private),
+            features: self.features.unwrap_or(BucketFeaturesFlags::empty()),
+            description: self.description,
+            display_name: self.display_name,
+            color: self.color,
+            icon: self.icon,
+            write_conflict: self.write_conflict.unwrap_or_default(),
+            indexing: self.indexing.unwrap_or_default(),
+        })
+    }
+}
+
+impl BucketSettings {
+    pub fn builder() -> BucketSettingsBuilder {
+        BucketSettingsBuilder::default()
+    }
+
+    pub fn validate(&self) -> Result<(), BucketSettingsError> {
+        self.region.known().map_err(|err| BucketSettingsError::UnknownValue {
+            field: "region",
+            value: err.0,
+        })?;
+        if let WriteConflictStrategy::RejectIfChanged { .. } = self.write_conflict {
+            return Err(BucketSettingsError::PerWriteOnly("reject_if_changed"));
+        }
+        // Only public buckets have a listing search engines can reach.
+        if !self.indexing.is_default() && self.visibility != BucketVisibility::Public {
+            return Err(BucketSettingsError::PublicOnly("indexing"));
+        }
+        Ok(())
+    }
+}
+
+/*
+* Partial update of BucketSettings, every field is Keep unless the request names it, see Patch.
+* Clearing region, storage_class, compression, encryption, visibility or features is refused, they have no unset
+* state. Cleared write_conflict and indexing go back to their defaults.
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BucketSettingsPatch {
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub region: Patch<MaybeUnknown<BucketRegion>>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub storage_class: Patch<BucketStorageClass>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub compression: Patch<BucketCompression>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub encryption: Patch<BucketEncryption>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub visibility: Patch<BucketVisibility>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub features: Patch<BucketFeaturesFlags>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub description: Patch<BucketDescription>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub display_name: Patch<BucketDisplayName>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub color: Patch<BucketColor>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub icon: Patch<BucketIcon>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub write_conflict: Patch<WriteConflictStrategy>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub indexing: Patch<IndexingPolicy>,
+}
+
+impl BucketSettingsPatch {
+    pub fn region(mut self, region: Patch<MaybeUnknown<BucketRegion>>) -> Self {
+        self.region = region;
+        self
+    }
+
+    pub fn storage_class(mut self, storage_class: Patch<BucketStorageClass>) -> Self {
+        self.storage_class = storage_class;
+        self
+    }
+
+    pub fn compression(mut self, compression: Patch<BucketCompression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn encryption(mut self, encryption: Patch<BucketEncryption>) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    pub fn visibility(mut self, visibility: Patch<BucketVisibility>) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    pub fn features(mut self, features: Patch<BucketFeaturesFlags>) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn description(mut self, description: Patch<BucketDescription>) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn display_name(mut self, display_name: Patch<BucketDisplayName>) -> Self {
+        self.display_name = display_name;
+        self
+    }
+
+    pub fn color(mut self, color: Patch<BucketColor>) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn icon(mut self, icon: Patch<BucketIcon>) -> Self {
+        self.icon = icon;
+        self
+    }
+
+    pub fn write_conflict(mut self, write_conflict: Patch<WriteConflictStrategy>) -> Self {
+        self.write_conflict = write_conflict;
+        self
+    }
+
+    pub fn indexing(mut self, indexing: Patch<IndexingPolicy>) -> Self {
+        self.indexing = indexing;
+        self
+    }
+
+    // Applies the patch and validates the result, settings are only changed when both succeed.
+    pub fn apply_to(&self, settings: &mut BucketSettings) -> Result<(), BucketSettingsError> {
+        let current = settings.clone();
+        let required = |field| BucketSettingsError::MissingValue(field);
+        let patched = BucketSettings {
+            region: self.region.apply(Some(current.region)).ok_or(required("region"))?,
+            storage_class: self.storage_class.apply(Some(current.storage_class)).ok_or(required("storage_class"))?,
+            compression: self.compression.apply(Some(current.compression)).ok_or(required("compression"))?,
+            encryption: self.encryption.apply(Some(current.encryption)).ok_or(required("encryption"))?,
+            visibility: self.visibility.apply(Some(current.visibility)).ok_or(required("visibility"))?,
+            features: self.features.apply(Some(current.features)).ok_or(required("features"))?,
+            description: self.description.apply(current.description),
+            display_name: self.display_name.apply(current.display_name),
+            color: self.color.apply(current.color),
+            icon: self.icon.apply(current.icon),
+            write_conflict: self.write_conflict.apply(Some(current.write_conflict)).unwrap_or_default(),
+            indexing: self.indexing.apply(Some(current.indexing)).unwrap_or_default(),
+        };
+        patched.validate()?;
+        *settings = patched;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_json(region: &str) -> String {
+        format!(
+            r#"{{"region":"{}","storage_class":"General","compression":"Zstd","encryption":"AES256","visibility":"Private","features":"IS_SEARCHABLE"}}"#,
+            region
+        )
+    }
+
+    #[test]
+    fn known_region_is_valid() {
+        let settings: BucketSettings = serde_json::from_str(&settings_json("eu-north")).unwrap();
+        assert_eq!(settings.region, MaybeUnknown::Known(BucketRegion::EuropeNorth(0)));
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn unknown_region_deserializes_but_is_rejected() {
+        let json = settings_json("mars-central");
+        let settings: BucketSettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(settings.region, MaybeUnknown::Unknown("mars-central".to_string()));
+        assert_eq!(serde_json::to_string(&settings).unwrap(), json);
+        assert_eq!(
+            settings.validate(),
+            Err(BucketSettingsError::UnknownValue {
+                field: "region",
+                value: "mars-central".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn description_and_display_name() {
+        let settings: BucketSettings = serde_json::from_str(&settings_json("eu-north")).unwrap();
+        assert_eq!(settings.description, None);
+        assert_eq!(settings.display_name, None);
+
+        let json = settings_json("eu-north").replace(
+            "}",
+            r#","description":" Team files\nShared with everyone ","display_name":"Team"}"#,
+        );
+        let settings: BucketSettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(settings.description.as_ref().unwrap().as_str(), "Team files\nShared with everyone");
+        assert_eq!(settings.display_name.as_ref().unwrap().as_str(), "Team");
+        let round_trip: BucketSettings = serde_json::from_str(&serde_json::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(round_trip, settings);
+
+        let json = settings_json("eu-north").replace("}", r#","display_name":"Two\nlines"}"#);
+        assert!(serde_json::from_str::<BucketSettings>(&json).is_err());
+    }
+
+    #[test]
+    fn color_and_icon() {
+        let settings: BucketSettings = serde_json::from_str(&settings_json("eu-north")).unwrap();
+        assert_eq!((settings.color, settings.icon), (None, None));
+
+        let json = settings_json("eu-north").replace("}", r##","color":"#00ff7f","icon":"photos"}"##);
+        let settings: BucketSettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(settings.color, Some(BucketColor::Rgb(0, 0xff, 0x7f)));
+        assert_eq!(settings.icon, Some(BucketIcon::Photos));
+        assert_eq!(serde_json::to_string(&settings).unwrap(), json);
+
+        let json = settings_json("eu-north").replace("}", r#","color":"javascript:alert(1)"}"#);
+        assert!(serde_json::from_str::<BucketSettings>(&json).is_err());
+        let json = settings_json("eu-north").replace("}", r#","icon":"javascript:alert(1)"}"#);
+        assert!(serde_json::from_str::<BucketSettings>(&json).is_err());
+
+        let settings = BucketSettings::builder()
+            .region(BucketRegion::EuropeNorth(0))
+            .color(BucketColor::Palette(crate::bucket_appearance::PaletteColor::Teal))
+            .icon("🚀".parse().unwrap())
+            .build()
+            .unwrap();
+        assert!(serde_json::to_string(&settings).unwrap().contains(r#""color":"teal","icon":"🚀""#));
+    }
+
+    #[test]
+    fn builder_defaults() {
+        assert_eq!(BucketSettings::builder().build(), Err(BucketSettingsError::MissingValue("region")));
+        let settings = BucketSettings::builder()
+            .region(BucketRegion::EuropeNorth(0))
+            .visibility(BucketVisibility::Public)
+            .build()
+            .unwrap();
+        assert_eq!(settings.region, MaybeUnknown::Known(BucketRegion::EuropeNorth(0)));
+        assert_eq!(settings.visibility, BucketVisibility::Public);
+        assert_eq!(settings.storage_class, BucketStorageClass::General);
+        assert_eq!(settings.compression, BucketCompression::None);
+        assert_eq!(settings.encryption, BucketEncryption::AES256);
+        assert_eq!(settings.features, BucketFeaturesFlags::empty());
+        assert_eq!(settings.write_conflict, WriteConflictStrategy::LastWriterWins);
+    }
+
+    #[test]
+    fn write_conflict_default() {
+        let json = settings_json("eu-north");
+        let settings: BucketSettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(settings.write_conflict, WriteConflictStrategy::LastWriterWins);
+        assert_eq!(serde_json::to_string(&settings).unwrap(), json);
+
+        let versioned = BucketSettings {
+            write_conflict: WriteConflictStrategy::VersionBoth,
+            ..settings.clone()
+        };
+        let versioned_json = serde_json::to_string(&versioned).unwrap();
+        assert!(versioned_json.ends_with(r#","write_conflict":{"strategy":"version_both"}}"#), "{}", versioned_json);
+        assert_eq!(serde_json::from_str::<BucketSettings>(&versioned_json).unwrap(), versioned);
+        assert_eq!(versioned.validate(), Ok(()));
+
+        let per_write = BucketSettings {
+            write_conflict: WriteConflictStrategy::RejectIfChanged {
+                expected: crate::bucket_object::ContentHash::of(b""),
+            },
+            ..settings
+        };
+        assert_eq!(per_write.validate(), Err(BucketSettingsError::PerWriteOnly("reject_if_changed")));
+    }
+
+    #[test]
+    fn indexing_policy() {
+        let settings: BucketSettings = serde_json::from_str(&settings_json("eu-north")).unwrap();
+        assert_eq!(settings.indexing, IndexingPolicy::Allow);
+
+        let public = BucketSettings::builder()
+            .region(BucketRegion::EuropeNorth(0))
+            .visibility(BucketVisibility::Public)
+            .indexing(IndexingPolicy::NoIndex)
+            .build()
+            .unwrap();
+        assert_eq!(public.validate(), Ok(()));
+        let json = serde_json::to_string(&public).unwrap();
+        assert!(json.ends_with(r#","indexing":"no_index"}"#), "{}", json);
+        assert_eq!(serde_json::from_str::<BucketSettings>(&json).unwrap(), public);
+
+        for visibility in [BucketVisibility::PrivateShared, BucketVisibility::Private] {
+            let hidden = BucketSettings {
+                visibility,
+                ..public.clone()
+            };
+            assert_eq!(hidden.validate(), Err(BucketSettingsError::PublicOnly("indexing")));
+            let allowed = BucketSettings {
+                indexing: IndexingPolicy::Allow,
+                ..hidden
+            };
+            assert_eq!(allowed.validate(), Ok(()));
+        }
+    }
+
+    // Internal search and external indexing are set independently, every combination is valid on a public bucket.
+    #[test]
+    fn indexing_policy_is_independent_of_internal_search() {
+        for indexing in [IndexingPolicy::Allow, IndexingPolicy::NoIndex, IndexingPolicy::NoIndexNoFollow] {
+            for features in [BucketFeaturesFlags::empty(), BucketFeaturesFlags::IS_SEARCH_INDEXED] {
+                let settings = BucketSettings::builder()
+                    .region(BucketRegion::EuropeNorth(0))
+                    .visibility(BucketVisibility::Public)
+                    .features(features)
+                    .indexing(indexing)
+                    .build()
+                    .unwrap();
+                assert_eq!(settings.validate(), Ok(()), "{} {:?}", indexing, features);
+                assert_eq!(settings.features, features);
+            }
+        }
+        // Internal search on a private bucket does not need an indexing policy.
+        let private = BucketSettings::builder()
+            .region(BucketRegion::EuropeNorth(0))
+            .features(BucketFeaturesFlags::IS_SEARCH_INDEXED)
+            .build()
+            .unwrap();
+        assert_eq!(private.validate(), Ok(()));
+    }
+
+    #[test]
+    fn patch_serde_per_field() {
+        assert_eq!(serde_json::from_str::<BucketSettingsPatch>("{}").unwrap(), BucketSettingsPatch::default());
+        assert_eq!(serde_json::to_string(&BucketSettingsPatch::default()).unwrap(), "{}");
+        for (field, value) in [
+            ("region", r#""eu-north""#),
+            ("storage_class", r#""General""#),
+            ("compression", r#""Zstd""#),
+            ("encryption", r#""AES256""#),
+            ("visibility", r#""Public""#),
+            ("features", r#""IS_SEARCHABLE""#),
+            ("description", r#""Team files""#),
+            ("display_name", r#""Team""#),
+            ("color", r##""#00ff7f""##),
+            ("icon", r#""photos""#),
+            ("write_conflict", r#"{"strategy":"version_both"}"#),
+            ("indexing", r#""no_index""#),
+        ] {
+            let clear_json = format!(r#"{{"{}":null}}"#, field);
+            let clear = serde_json::from_str::<BucketSettingsPatch>(&clear_json).unwrap();
+            assert_ne!(clear, BucketSettingsPatch::default(), "{}", field);
+            assert_eq!(serde_json::to_string(&clear).unwrap(), clear_json);
+
+            let set_json = format!(r#"{{"{}":{}}}"#, field, value);
+            let set = serde_json::from_str::<BucketSettingsPatch>(&set_json).unwrap();
+            assert_ne!(set, BucketSettingsPatch::default(), "{}", field);
+            assert_ne!(set, clear, "{}", field);
+            assert_eq!(serde_json::to_string(&set).unwrap(), set_json);
+        }
+
+        let patch: BucketSettingsPatch =
+            serde_json::from_str(r#"{"description":null,"visibility":"Public","indexing":"no_index"}"#).unwrap();
+        assert_eq!(
+            patch,
+            BucketSettingsPatch::default()
+                .description(Patch::Clear)
+                .visibility(Patch::Set(BucketVisibility::Public))
+                .indexing(Patch::Set(IndexingPolicy::NoIndex))
+        );
+    }
+
+    #[test]
+    fn apply_patch() {
+        let team = || BucketDisplayName::try_from("Team".to_string()).unwrap();
+        let mut settings = BucketSettings::builder()
+            .region(BucketRegion::EuropeNorth(0))
+            .description(BucketDescription::try_from("Team files".to_string()).unwrap())
+            .color(BucketColor::Rgb(0, 0xff, 0x7f))
+            .build()
+            .unwrap();
+        let patch = BucketSettingsPatch::default()
+            .visibility(Patch::Set(BucketVisibility::Public))
+            .indexing(Patch::Set(IndexingPolicy::NoIndex))
+            .display_name(Patch::Set(team()))
+            .color(Patch::Clear);
+        let expected = BucketSettings {
+            visibility: BucketVisibility::Public,
+            indexing: IndexingPolicy::NoIndex,
+            display_name: Some(team()),
+            color: None,
+            ..settings.clone()
+        };
+        assert_eq!(patch.apply_to(&mut settings), Ok(()));
+        assert_eq!(settings, expected);
+        assert_eq!(settings.description.as_ref().unwrap().as_str(), "Team files");
+
+        // Cleared fields with a default go back to it.
+        let patch = BucketSettingsPatch::default().indexing(Patch::Clear);
+        assert_eq!(patch.apply_to(&mut settings), Ok(()));
+        assert_eq!(settings.indexing, IndexingPolicy::Allow);
+    }
+
+    #[test]
+    fn patch_is_validated() {
+        let public = BucketSettings::builder()
+            .region(BucketRegion::EuropeNorth(0))
+            .visibility(BucketVisibility::Public)
+            .indexing(IndexingPolicy::NoIndex)
+            .build()
+            .unwrap();
+
+        let mut settings = public.clone();
+        let private = BucketSettingsPatch::default().visibility(Patch::Set(BucketVisibility::Private));
+        assert_eq!(private.apply_to(&mut settings), Err(BucketSettingsError::PublicOnly("indexing")));
+        assert_eq!(settings, public);
+
+        let no_encryption = BucketSettingsPatch::default().encryption(Patch::Clear);
+        assert_eq!(no_encryption.apply_to(&mut settings), Err(BucketSettingsError::MissingValue("encryption")));
+        let unknown_region = BucketSettingsPatch::default().region(Patch::Set(MaybeUnknown::Unknown("mars".into())));
+        assert!(matches!(
+            unknown_region.apply_to(&mut settings),
+            Err(BucketSettingsError::UnknownValue { field: "region", .. })
+        ));
+        assert_eq!(settings, public);
+    }
+}
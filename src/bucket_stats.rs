@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::bucket_guid::BucketGuid;
+use crate::storage_quota::StorageQuota;
+use crate::ClusterId;
+
+// Statistics one region cluster computed for its part of a bucket at as_of.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BucketStats {
+    pub bucket: BucketGuid,
+    pub cluster: ClusterId,
+    pub object_count: u64,
+    pub total_size: StorageQuota,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_modified: Option<OffsetDateTime>,
+    pub downloads: u64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub as_of: OffsetDateTime,
+}
+
+/*
+* Statistics of a bucket over all its clusters, see merge. Every cluster counts once, the snapshot it contributed is
+* kept in contributions so the totals can be audited.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BucketStatsAggregate {
+    pub bucket: BucketGuid,
+    pub object_count: u64,
+    pub total_size: StorageQuota,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_modified: Option<OffsetDateTime>,
+    pub downloads: u64,
+    // Oldest as_of of the contributions, every part of the aggregate is at least this recent.
+    #[serde(with = "time::serde::rfc3339")]
+    pub as_of: OffsetDateTime,
+    pub contributions: BTreeMap<ClusterId, BucketStats>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum MergeError {
+    #[error("no bucket statistics to merge")]
+    Empty,
+    #[error("statistics of bucket {actual} can not be merged into bucket {expected}")]
+    BucketMismatch { expected: BucketGuid, actual: BucketGuid },
+    #[error("cluster {cluster} reported statistics as of {as_of} more than once")]
+    DuplicateCluster { cluster: ClusterId, as_of: OffsetDateTime },
+    #[error("{0} overflows when summed over clusters")]
+    Overflow(&'static str),
+}
+
+impl BucketStatsAggregate {
+    /*
+    * Sums the statistics of one bucket over distinct clusters, last_modified is the latest of them.
+    * A cluster reporting twice for the same as_of is a retry that would be counted twice and is refused. Of reports
+    * for different as_of the newest one is used, older ones are outdated.
+    */
+    pub fn merge(stats: impl IntoIterator<Item = BucketStats>) -> Result<Self, MergeError> {
+        let mut stats = stats.into_iter();
+        let first = stats.next().ok_or(MergeError::Empty)?;
+        let bucket = first.bucket;
+        let mut contributions = BTreeMap::from([(first.cluster, first)]);
+        for report in stats {
+            if report.bucket != bucket {
+                return Err(MergeError::BucketMismatch { expected: bucket, actual: report.bucket });
+            }
+            match contributions.get(&report.cluster) {
+                Some(existing) if existing.as_of == report.as_of => {
+                    return Err(MergeError::DuplicateCluster { cluster: report.cluster, as_of: report.as_of });
+                }
+                Some(existing) if existing.as_of > report.as_of => {}
+                _ => {
+                    contributions.insert(report.cluster, report);
+                }
+            }
+        }
+
+        let Some(as_of) = contributions.values().map(|report| report.as_of).min() else {
+            return Err(MergeError::Empty);
+        };
+        let mut aggregate = Self {
+            bucket,
+            object_count: 0,
+            total_size: StorageQuota::ZERO,
+            last_modified: None,
+            downloads: 0,
+            as_of,
+            contributions: BTreeMap::new(),
+        };
+        for report in contributions.values() {
+            aggregate.object_count = aggregate
+                .object_count
+                .checked_add(report.object_count)
+                .ok_or(MergeError::Overflow("object_count"))?;
+            aggregate.total_size = aggregate
+                .total_size
+                .checked_add(report.total_size)
+                .ok_or(MergeError::Overflow("total_size"))?;
+            aggregate.downloads =
+                aggregate.downloads.checked_add(report.downloads).ok_or(MergeError::Overflow("downloads"))?;
+            aggregate.last_modified = aggregate.last_modified.max(report.last_modified);
+        }
+        aggregate.contributions = contributions;
+        Ok(aggregate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time::Duration;
+
+    use super::*;
+
+    const AS_OF: OffsetDateTime = datetime!(2024-06-01 0:00 UTC);
+
+    fn bucket() -> BucketGuid {
+        BucketGuid::new(
+            "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+        )
+    }
+
+    fn stats(cluster: ClusterId, object_count: u64, size: u64, last_modified: Option<OffsetDateTime>) -> BucketStats {
+        BucketStats {
+            bucket: bucket(),
+            cluster,
+            object_count,
+            total_size: StorageQuota::from_bytes(size),
+            last_modified,
+            downloads: object_count * 10,
+            as_of: AS_OF,
+        }
+    }
+
+    #[test]
+    fn three_clusters_golden() {
+        let aggregate = BucketStatsAggregate::merge([
+            stats(3, 5, 500, Some(AS_OF - Duration::days(2))),
+            stats(1, 10, 1_000, Some(AS_OF - Duration::HOUR)),
+            BucketStats { as_of: AS_OF - Duration::MINUTE, ..stats(2, 0, 0, None) },
+        ])
+        .unwrap();
+        assert_eq!(
+            (aggregate.object_count, aggregate.total_size, aggregate.downloads),
+            (15, StorageQuota::from_bytes(1_500), 150)
+        );
+        assert_eq!(aggregate.last_modified, Some(AS_OF - Duration::HOUR));
+        assert_eq!(aggregate.as_of, AS_OF - Duration::MINUTE);
+        assert_eq!(aggregate.contributions.keys().copied().collect::<Vec<_>>(), [1, 2, 3]);
+
+        let json = serde_json::to_value(&aggregate).unwrap();
+        let contribution = |cluster, object_count, size, last_modified: Option<&str>, as_of| {
+            serde_json::json!({
+                "bucket": serde_json::to_value(bucket()).unwrap(),
+                "cluster": cluster,
+                "object_count": object_count,
+                "total_size": size,
+                "last_modified": last_modified,
+                "downloads": object_count * 10,
+                "as_of": as_of,
+            })
+        };
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "bucket": serde_json::to_value(bucket()).unwrap(),
+                "object_count": 15,
+                "total_size": 1500,
+                "last_modified": "2024-05-31T23:00:00Z",
+                "downloads": 150,
+                "as_of": "2024-05-31T23:59:00Z",
+                "contributions": {
+                    "1": contribution(1, 10, 1000, Some("2024-05-31T23:00:00Z"), "2024-06-01T00:00:00Z"),
+                    "2": contribution(2, 0, 0, None, "2024-05-31T23:59:00Z"),
+                    "3": contribution(3, 5, 500, Some("2024-05-30T00:00:00Z"), "2024-06-01T00:00:00Z"),
+                },
+            })
+        );
+        assert_eq!(serde_json::from_value::<BucketStatsAggregate>(json).unwrap(), aggregate);
+    }
+
+    #[test]
+    fn clusters_count_once() {
+        assert_eq!(
+            BucketStatsAggregate::merge([stats(1, 10, 1_000, None), stats(2, 1, 1, None), stats(1, 10, 1_000, None)]),
+            Err(MergeError::DuplicateCluster { cluster: 1, as_of: AS_OF })
+        );
+
+        // A newer report of a cluster replaces its older one, in either order.
+        let newer = BucketStats { as_of: AS_OF + Duration::HOUR, ..stats(1, 12, 1_200, None) };
+        for reports in [[stats(1, 10, 1_000, None), newer.clone()], [newer.clone(), stats(1, 10, 1_000, None)]] {
+            let aggregate = BucketStatsAggregate::merge(reports).unwrap();
+            assert_eq!((aggregate.object_count, aggregate.as_of), (12, AS_OF + Duration::HOUR));
+            assert_eq!(aggregate.contributions[&1], newer);
+        }
+    }
+
+    #[test]
+    fn merge_errors() {
+        assert_eq!(BucketStatsAggregate::merge([]), Err(MergeError::Empty));
+
+        let other = BucketStats {
+            bucket: BucketGuid::new(uuid::Uuid::nil(), uuid::Uuid::nil()),
+            ..stats(2, 1, 1, None)
+        };
+        assert_eq!(
+            BucketStatsAggregate::merge([stats(1, 1, 1, None), other.clone()]),
+            Err(MergeError::BucketMismatch { expected: bucket(), actual: other.bucket })
+        );
+
+        let huge = stats(2, 1, u64::MAX, None);
+        assert_eq!(
+            BucketStatsAggregate::merge([stats(1, 1, 1, None), huge]),
+            Err(MergeError::Overflow("total_size"))
+        );
+        let many = BucketStats { object_count: u64::MAX, ..stats(2, 0, 0, None) };
+        assert_eq!(
+            BucketStatsAggregate::merge([stats(1, 1, 1, None), many]),
+            Err(MergeError::Overflow("object_count"))
+        );
+        let popular = BucketStats { downloads: u64::MAX, ..stats(2, 0, 0, None) };
+        assert_eq!(
+            BucketStatsAggregate::merge([stats(1, 1, 1, None), popular]),
+            Err(MergeError::Overflow("downloads"))
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::bucket_guid::BucketGuid;
+use crate::expiry::ExpiryTimestamp;
+use crate::UserId;
+
+pub const BUCKET_TRANSFER_VERSION: u8 = 1;
+// Prepended with the signing role to the canonical bytes, a transfer signature can never pass as another signature.
+const BUCKET_TRANSFER_CONTEXT: &[u8] = b"bucketdrive-bucket-transfer-v1";
+const SENDER_ROLE: u8 = 1;
+const RECIPIENT_ROLE: u8 = 2;
+
+/*
+* Handshake moving a bucket from one user to another.
+* The sender signs the transfer, the recipient countersigns the same canonical bytes. Only a completed transfer
+* verified with both public keys before expires_at may be applied.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BucketTransfer {
+    pub bucket: BucketGuid,
+    pub from: UserId,
+    pub to: UserId,
+    pub initiated_at: ExpiryTimestamp,
+    pub expires_at: ExpiryTimestamp,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SenderSignedTransfer {
+    pub transfer: BucketTransfer,
+    #[serde(with = "crate::util::signature_base64")]
+    pub sender_signature: ed25519_compact::Signature,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CompletedTransfer {
+    pub transfer: BucketTransfer,
+    #[serde(with = "crate::util::signature_base64")]
+    pub sender_signature: ed25519_compact::Signature,
+    #[serde(with = "crate::util::signature_base64")]
+    pub recipient_signature: ed25519_compact::Signature,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum BucketTransferError {
+    #[error("transfer expired at {expires_at}")]
+    Expired { expires_at: ExpiryTimestamp },
+    #[error("transfer expires at {expires_at}, not after it was initiated at {initiated_at}")]
+    InvalidTimeWindow { initiated_at: ExpiryTimestamp, expires_at: ExpiryTimestamp },
+    #[error("sender signature does not cover the transfer")]
+    SenderSignatureInvalid,
+    #[error("recipient signature does not cover the transfer")]
+    RecipientSignatureInvalid,
+    // Sender and recipient are the same user or sign with the same key.
+    #[error("sender and recipient are the same party")]
+    PartyMismatch,
+}
+
+impl BucketTransfer {
+    /*
+    * Version, bucket user id and bucket id, from, to as 16 byte UUIDs, then initiated_at and expires_at as
+    * big endian unix seconds. 81 bytes.
+    */
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![BUCKET_TRANSFER_VERSION];
+        bytes.extend_from_slice(self.bucket.user_id.as_bytes());
+        bytes.extend_from_slice(self.bucket.bucket_id.as_bytes());
+        bytes.extend_from_slice(self.from.as_bytes());
+        bytes.extend_from_slice(self.to.as_bytes());
+        bytes.extend_from_slice(&self.initiated_at.unix_timestamp().to_be_bytes());
+        bytes.extend_from_slice(&self.expires_at.unix_timestamp().to_be_bytes());
+        bytes
+    }
+
+    pub fn sign_as_sender(&self, secret_key: &ed25519_compact::SecretKey) -> SenderSignedTransfer {
+        SenderSignedTransfer {
+            transfer: self.clone(),
+            sender_signature: secret_key.sign(self.signed_message(SENDER_ROLE), Some(ed25519_compact::Noise::generate())),
+        }
+    }
+
+    fn signed_message(&self, role: u8) -> Vec<u8> {
+        [BUCKET_TRANSFER_CONTEXT, &[role], &self.canonical_bytes()].concat()
+    }
+
+    // Checks the parties and the time window, not the signatures.
+    fn check(&self, now: OffsetDateTime) -> Result<(), BucketTransferError> {
+        if self.from == self.to {
+            return Err(BucketTransferError::PartyMismatch);
+        }
+        if self.expires_at <= self.initiated_at {
+            return Err(BucketTransferError::InvalidTimeWindow {
+                initiated_at: self.initiated_at,
+                expires_at: self.expires_at,
+            });
+        }
+        if now >= self.expires_at.as_offset_date_time() {
+            return Err(BucketTransferError::Expired {
+                expires_at: self.expires_at,
+            });
+        }
+        Ok(())
+    }
+
+    fn verify_signature(
+        &self,
+        role: u8,
+        public_key: &ed25519_compact::PublicKey,
+        signature: &ed25519_compact::Signature,
+    ) -> bool {
+        public_key.verify(self.signed_message(role), signature).is_ok()
+    }
+}
+
+impl SenderSignedTransfer {
+    // What the recipient checks before countersigning.
+    pub fn verify(
+        &self,
+        sender_public_key: &ed25519_compact::PublicKey,
+        now: OffsetDateTime,
+    ) -> Result<&BucketTransfer, BucketTransferError> {
+        self.transfer.check(now)?;
+        if !self.transfer.verify_signature(SENDER_ROLE, sender_public_key, &self.sender_signature) {
+            return Err(BucketTransferError::SenderSignatureInvalid);
+        }
+        Ok(&self.transfer)
+    }
+
+    // Signs the transfer as it is now, a changed transfer no longer matches the sender signature.
+    pub fn countersign_as_recipient(&self, secret_key: &ed25519_compact::SecretKey) -> CompletedTransfer {
+        CompletedTransfer {
+            transfer: self.transfer.clone(),
+            sender_signature: self.sender_signature,
+            recipient_signature: secret_key.sign(
+                self.transfer.signed_message(RECIPIENT_ROLE),
+                Some(ed25519_compact::Noise::generate()),
+            ),
+        }
+    }
+}
+
+impl CompletedTransfer {
+    // The transfer is only handed out once both signatures cover it and it has not expired.
+    pub fn verify(
+        &self,
+        sender_public_key: &ed25519_compact::PublicKey,
+        recipient_public_key: &ed25519_compact::PublicKey,
+        now: OffsetDateTime,
+    ) -> Result<&BucketTransfer, BucketTransferError> {
+        self.transfer.check(now)?;
+        if sender_public_key == recipient_public_key {
+            return Err(BucketTransferError::PartyMismatch);
+        }
+        if !self.transfer.verify_signature(SENDER_ROLE, sender_public_key, &self.sender_signature) {
+            return Err(BucketTransferError::SenderSignatureInvalid);
+        }
+        if !self.transfer.verify_signature(RECIPIENT_ROLE, recipient_public_key, &self.recipient_signature) {
+            return Err(BucketTransferError::RecipientSignatureInvalid);
+        }
+        Ok(&self.transfer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time::Duration;
+
+    use super::*;
+
+    fn key_pair(seed: u8) -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([seed; 32]))
+    }
+
+    fn transfer() -> BucketTransfer {
+        let from = "11111111-0000-4000-8000-000000000001".parse().unwrap();
+        BucketTransfer {
+            bucket: BucketGuid::new(from, "22222222-0000-4000-8000-000000000002".parse().unwrap()),
+            from,
+            to: "33333333-0000-4000-8000-000000000003".parse().unwrap(),
+            initiated_at: ExpiryTimestamp::try_from(datetime!(2024-06-01 12:00 UTC)).unwrap(),
+            expires_at: ExpiryTimestamp::try_from(datetime!(2024-06-08 12:00 UTC)).unwrap(),
+        }
+    }
+
+    fn now() -> OffsetDateTime {
+        datetime!(2024-06-02 12:00 UTC)
+    }
+
+    #[test]
+    fn two_party_handshake() {
+        let (sender, recipient) = (key_pair(1), key_pair(2));
+        let signed = transfer().sign_as_sender(&sender.sk);
+        assert_eq!(signed.verify(&sender.pk, now()), Ok(&transfer()));
+        assert_eq!(signed.verify(&recipient.pk, now()), Err(BucketTransferError::SenderSignatureInvalid));
+
+        let completed = signed.countersign_as_recipient(&recipient.sk);
+        assert_eq!(completed.verify(&sender.pk, &recipient.pk, now()), Ok(&transfer()));
+        assert_eq!(
+            completed.verify(&sender.pk, &key_pair(3).pk, now()),
+            Err(BucketTransferError::RecipientSignatureInvalid)
+        );
+        // Keys swapped, neither signature covers its role.
+        assert_eq!(
+            completed.verify(&recipient.pk, &sender.pk, now()),
+            Err(BucketTransferError::SenderSignatureInvalid)
+        );
+        assert_eq!(transfer().canonical_bytes().len(), 81);
+    }
+
+    #[test]
+    fn recipient_can_not_countersign_an_altered_payload() {
+        let (sender, recipient) = (key_pair(1), key_pair(2));
+        let signed = transfer().sign_as_sender(&sender.sk);
+        let alterations = [
+            BucketTransfer {
+                expires_at: ExpiryTimestamp::try_from(datetime!(2025-06-08 12:00 UTC)).unwrap(),
+                ..transfer()
+            },
+            BucketTransfer {
+                to: "44444444-0000-4000-8000-000000000004".parse().unwrap(),
+                ..transfer()
+            },
+            BucketTransfer {
+                bucket: BucketGuid::new(transfer().from, "22222222-0000-4000-8000-000000000009".parse().unwrap()),
+                ..transfer()
+            },
+        ];
+        for altered in alterations {
+            let mut tampered = signed.clone();
+            tampered.transfer = altered.clone();
+            assert_eq!(tampered.verify(&sender.pk, now()), Err(BucketTransferError::SenderSignatureInvalid));
+
+            let completed = tampered.countersign_as_recipient(&recipient.sk);
+            assert_eq!(
+                completed.verify(&sender.pk, &recipient.pk, now()),
+                Err(BucketTransferError::SenderSignatureInvalid),
+                "{:?}",
+                altered
+            );
+        }
+
+        // Altering after countersigning breaks both signatures, the sender's is checked first.
+        let mut completed = signed.countersign_as_recipient(&recipient.sk);
+        completed.transfer.initiated_at = ExpiryTimestamp::try_from(datetime!(2024-06-01 12:00:01 UTC)).unwrap();
+        assert_eq!(
+            completed.verify(&sender.pk, &recipient.pk, now()),
+            Err(BucketTransferError::SenderSignatureInvalid)
+        );
+        // A recipient signature made as sender does not count.
+        let mut completed = signed.countersign_as_recipient(&recipient.sk);
+        completed.recipient_signature = transfer().sign_as_sender(&recipient.sk).sender_signature;
+        assert_eq!(
+            completed.verify(&sender.pk, &recipient.pk, now()),
+            Err(BucketTransferError::RecipientSignatureInvalid)
+        );
+    }
+
+    #[test]
+    fn expiry_and_parties() {
+        let (sender, recipient) = (key_pair(1), key_pair(2));
+        let completed = transfer().sign_as_sender(&sender.sk).countersign_as_recipient(&recipient.sk);
+        let expires_at = transfer().expires_at;
+        let last_second = expires_at.as_offset_date_time() - Duration::seconds(1);
+        assert!(completed.verify(&sender.pk, &recipient.pk, last_second).is_ok());
+        assert_eq!(
+            completed.verify(&sender.pk, &recipient.pk, expires_at.as_offset_date_time()),
+            Err(BucketTransferError::Expired { expires_at })
+        );
+
+        let to_self = BucketTransfer {
+            to: transfer().from,
+            ..transfer()
+        };
+        let completed = to_self.sign_as_sender(&sender.sk).countersign_as_recipient(&recipient.sk);
+        assert_eq!(completed.verify(&sender.pk, &recipient.pk, now()), Err(BucketTransferError::PartyMismatch));
+
+        let same_key = transfer().sign_as_sender(&sender.sk).countersign_as_recipient(&sender.sk);
+        assert_eq!(same_key.verify(&sender.pk, &sender.pk, now()), Err(BucketTransferError::PartyMismatch));
+
+        let inverted = BucketTransfer {
+            expires_at: transfer().initiated_at,
+            ..transfer()
+        };
+        assert_eq!(
+            inverted.sign_as_sender(&sender.sk).verify(&sender.pk, now()),
+            Err(BucketTransferError::InvalidTimeWindow {
+                initiated_at: transfer().initiated_at,
+                expires_at: transfer().initiated_at
+            })
+        );
+    }
+
+    #[test]
+    fn every_stage_round_trips_through_serde() {
+        let (sender, recipient) = (key_pair(1), key_pair(2));
+        let json = serde_json::to_string(&transfer()).unwrap();
+        assert_eq!(serde_json::from_str::<BucketTransfer>(&json).unwrap(), transfer());
+
+        let signed = transfer().sign_as_sender(&sender.sk);
+        let json = serde_json::to_string(&signed).unwrap();
+        assert!(json.contains(r#""initiated_at":"2024-06-01T12:00:00Z""#), "{}", json);
+        let parsed = serde_json::from_str::<SenderSignedTransfer>(&json).unwrap();
+        assert_eq!(parsed, signed);
+
+        let completed = parsed.countersign_as_recipient(&recipient.sk);
+        let json = serde_json::to_string(&completed).unwrap();
+        let parsed = serde_json::from_str::<CompletedTransfer>(&json).unwrap();
+        assert_eq!(parsed.verify(&sender.pk, &recipient.pk, now()), Ok(&transfer()));
+        assert!(serde_json::from_str::<CompletedTransfer>(&json.replace("sender_signature", "signature")).is_err());
+    }
+}
@@ -0,0 +1,876 @@
+use std::fmt::Display;
+
+use serde::ser::{self, Serialize};
+
+// 2^53 - 1, the largest integer every JSON implementation reads back exactly (I-JSON, RFC 7493).
+pub const MAX_SAFE_INTEGER: i128 = (1 << 53) - 1;
+
+/*
+* Canonical JSON bytes of anything that gets signed, the subset of JCS (RFC 8785) serde values need:
+* - object keys sorted by their UTF-16 code units, no duplicate keys,
+* - no whitespace between tokens,
+* - numbers in the shortest form that reads back to the same double, formatted like ECMAScript's
+*   Number.prototype.toString, -0 as 0,
+* - strings as UTF-8, only '"', '\' and control characters escaped, \b \t \n \f \r or \u00xx lowercase.
+* Rejected instead of guessed: NaN and infinities, integers beyond MAX_SAFE_INTEGER and map keys that are not strings.
+* Structs are objects, unit variants strings and other variants single key objects, the same shapes as serde_json.
+* Bytes are arrays of numbers.
+*/
+pub fn to_canonical_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, CanonicalJsonError> {
+    let mut out = Vec::new();
+    value.serialize(ValueSerializer)?.write(&mut out);
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum CanonicalJsonError {
+    #[error("{0} has no JSON representation")]
+    NonFiniteNumber(String),
+    #[error("integer {0} is beyond 2^53 - 1 and does not survive a round trip through a double")]
+    UnsafeInteger(i128),
+    #[error("map keys have to be strings")]
+    NonStringKey,
+    #[error("key \"{0}\" appears more than once")]
+    DuplicateKey(String),
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl ser::Error for CanonicalJsonError {
+    fn custom<T: Display>(msg: T) -> Self {
+        CanonicalJsonError::Custom(msg.to_string())
+    }
+}
+
+// Numbers are formatted when the value is built, objects sorted when they are written.
+enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn write(self, out: &mut Vec<u8>) {
+        match self {
+            Value::Null => out.extend_from_slice(b"null"),
+            Value::Bool(value) => out.extend_from_slice(if value { b"true" } else { b"false" }),
+            Value::Number(number) => out.extend_from_slice(number.as_bytes()),
+            Value::String(value) => write_string(&value, out),
+            Value::Array(values) => {
+                out.push(b'[');
+                for (index, value) in values.into_iter().enumerate() {
+                    if index > 0 {
+                        out.push(b',');
+                    }
+                    value.write(out);
+                }
+                out.push(b']');
+            }
+            Value::Object(mut members) => {
+                members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+                out.push(b'{');
+                for (index, (key, value)) in members.into_iter().enumerate() {
+                    if index > 0 {
+                        out.push(b',');
+                    }
+                    write_string(&key, out);
+                    out.push(b':');
+                    value.write(out);
+                }
+                out.push(b'}');
+            }
+        }
+    }
+}
+
+fn write_string(value: &str, out: &mut Vec<u8>) {
+    out.push(b'"');
+    for char in value.chars() {
+        match char {
+            '"' => out.extend_from_slice(b"\\\""),
+            '\\' => out.extend_from_slice(b"\\\\"),
+            '\u{8}' => out.extend_from_slice(b"\\b"),
+            '\t' => out.extend_from_slice(b"\\t"),
+            '\n' => out.extend_from_slice(b"\\n"),
+            '\u{c}' => out.extend_from_slice(b"\\f"),
+            '\r' => out.extend_from_slice(b"\\r"),
+            control if control < ' ' => out.extend_from_slice(format!("\\u{:04x}", control as u32).as_bytes()),
+            other => out.extend_from_slice(other.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    out.push(b'"');
+}
+
+fn integer(value: i128) -> Result<Value, CanonicalJsonError> {
+    match value.unsigned_abs() <= MAX_SAFE_INTEGER as u128 {
+        true => Ok(Value::Number(value.to_string())),
+        false => Err(CanonicalJsonError::UnsafeInteger(value)),
+    }
+}
+
+/*
+* ECMAScript Number.prototype.toString of a finite double, as JCS requires.
+* Rust's {:e} already gives the shortest digits that read back to the same double, only the layout differs:
+* with digits d of length k and the value 0.d * 10^n, plain digits up to 21 integer digits, a plain fraction down to
+* 6 leading zeros, exponent form otherwise.
+*/
+fn number(value: f64) -> Result<Value, CanonicalJsonError> {
+    if !value.is_finite() {
+        return Err(CanonicalJsonError::NonFiniteNumber(value.to_string()));
+    }
+    if value == 0.0 {
+        return Ok(Value::Number("0".to_string()));
+    }
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').expect("{:e} always has an exponent");
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().expect("{:e} exponents are integers") + 1;
+    let formatted = match n {
+        n if k <= n && n <= 21 => format!("{}{}", digits, "0".repeat((n - k) as usize)),
+        n if 0 < n && n <= 21 => format!("{}.{}", &digits[..n as usize], &digits[n as usize..]),
+        n if -6 < n && n <= 0 => format!("0.{}{}", "0".repeat(-n as usize), digits),
+        n => {
+            let sign = if n - 1 > 0 { "+" } else { "-" };
+            match k {
+                1 => format!("{}e{}{}", digits, sign, (n - 1).abs()),
+                _ => format!("{}.{}e{}{}", &digits[..1], &digits[1..], sign, (n - 1).abs()),
+            }
+        }
+    };
+    let sign = if value < 0.0 { "-" } else { "" };
+    Ok(Value::Number(format!("{}{}", sign, formatted)))
+}
+
+fn object(members: Vec<(String, Value)>) -> Result<Value, CanonicalJsonError> {
+    let mut keys = members.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>();
+    keys.sort_unstable();
+    match keys.windows(2).find(|pair| pair[0] == pair[1]) {
+        Some(pair) => Err(CanonicalJsonError::DuplicateKey(pair[0].to_string())),
+        None => Ok(Value::Object(members)),
+    }
+}
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = CanonicalJsonError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, CanonicalJsonError> {
+        integer(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, CanonicalJsonError> {
+        integer(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, CanonicalJsonError> {
+        integer(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, CanonicalJsonError> {
+        integer(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, CanonicalJsonError> {
+        integer(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, CanonicalJsonError> {
+        integer(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, CanonicalJsonError> {
+        integer(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, CanonicalJsonError> {
+        integer(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, CanonicalJsonError> {
+        integer(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, CanonicalJsonError> {
+        integer(i128::try_from(v).unwrap_or(i128::MAX))
+    }
+
+    // JCS is defined on doubles, f32 values are widened first.
+    fn serialize_f32(self, v: f32) -> Result<Value, CanonicalJsonError> {
+        number(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, CanonicalJsonError> {
+        number(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::Array(
+            v.iter().map(|byte| Value::Number(byte.to_string())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, CanonicalJsonError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, CanonicalJsonError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, CanonicalJsonError> {
+        Ok(Value::Object(vec![(variant.to_string(), value.serialize(self)?)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, CanonicalJsonError> {
+        Ok(SeqSerializer {
+            variant: None,
+            values: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, CanonicalJsonError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer, CanonicalJsonError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, CanonicalJsonError> {
+        Ok(SeqSerializer {
+            variant: Some(variant),
+            values: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, CanonicalJsonError> {
+        Ok(MapSerializer {
+            variant: None,
+            members: Vec::with_capacity(len.unwrap_or(0)),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, CanonicalJsonError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, CanonicalJsonError> {
+        Ok(MapSerializer {
+            variant: Some(variant),
+            members: Vec::with_capacity(len),
+            next_key: None,
+        })
+    }
+}
+
+// Arrays, and the array inside a tuple variant's single key object.
+struct SeqSerializer {
+    variant: Option<&'static str>,
+    values: Vec<Value>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CanonicalJsonError> {
+        self.values.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, CanonicalJsonError> {
+        let array = Value::Array(self.values);
+        Ok(match self.variant {
+            Some(variant) => Value::Object(vec![(variant.to_string(), array)]),
+            None => array,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = CanonicalJsonError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CanonicalJsonError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, CanonicalJsonError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = CanonicalJsonError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CanonicalJsonError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, CanonicalJsonError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = CanonicalJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CanonicalJsonError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, CanonicalJsonError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Value;
+    type Error = CanonicalJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CanonicalJsonError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, CanonicalJsonError> {
+        self.finish()
+    }
+}
+
+// Objects, and the object inside a struct variant's single key object.
+struct MapSerializer {
+    variant: Option<&'static str>,
+    members: Vec<(String, Value)>,
+    next_key: Option<String>,
+}
+
+impl MapSerializer {
+    fn finish(self) -> Result<Value, CanonicalJsonError> {
+        let object = object(self.members)?;
+        Ok(match self.variant {
+            Some(variant) => Value::Object(vec![(variant.to_string(), object)]),
+            None => object,
+        })
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = CanonicalJsonError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), CanonicalJsonError> {
+        self.next_key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CanonicalJsonError> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| CanonicalJsonError::Custom("map value without a key".to_string()))?;
+        self.members.push((key, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, CanonicalJsonError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = CanonicalJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), CanonicalJsonError> {
+        self.members.push((key.to_string(), value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, CanonicalJsonError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Value;
+    type Error = CanonicalJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), CanonicalJsonError> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Value, CanonicalJsonError> {
+        self.finish()
+    }
+}
+
+/*
+* Map keys, only strings are keys. Unlike serde_json, numbers and bools are not turned into strings, the same map
+* would otherwise sign the same as one keyed by their string forms.
+* Unit variants count as strings, they serialize as one everywhere else too.
+*/
+struct KeySerializer;
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = CanonicalJsonError;
+    type SerializeSeq = ser::Impossible<String, CanonicalJsonError>;
+    type SerializeTuple = ser::Impossible<String, CanonicalJsonError>;
+    type SerializeTupleStruct = ser::Impossible<String, CanonicalJsonError>;
+    type SerializeTupleVariant = ser::Impossible<String, CanonicalJsonError>;
+    type SerializeMap = ser::Impossible<String, CanonicalJsonError>;
+    type SerializeStruct = ser::Impossible<String, CanonicalJsonError>;
+    type SerializeStructVariant = ser::Impossible<String, CanonicalJsonError>;
+
+    fn serialize_str(self, v: &str) -> Result<String, CanonicalJsonError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, CanonicalJsonError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, CanonicalJsonError> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, CanonicalJsonError> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_none(self) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_unit(self) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, CanonicalJsonError> {
+        Err(CanonicalJsonError::NonStringKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    fn canonical<T: Serialize + ?Sized>(value: &T) -> String {
+        String::from_utf8(to_canonical_vec(value).unwrap()).unwrap()
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Line(i32, i32),
+        Box { width: u32, height: u32 },
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Fixture {
+        name: String,
+        tags: Vec<String>,
+        shapes: Vec<Shape>,
+        attributes: HashMap<String, Option<f64>>,
+        nested: BTreeMap<String, BTreeMap<String, bool>>,
+        unit: (),
+        bytes: [u8; 3],
+    }
+
+    fn fixture() -> Fixture {
+        Fixture {
+            name: "caf\u{e9} \"quoted\"\n".to_string(),
+            tags: vec!["b".to_string(), "a".to_string()],
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(0.5),
+                Shape::Line(-3, 4),
+                Shape::Box { width: 2, height: 1 },
+            ],
+            attributes: HashMap::from([
+                ("zeta".to_string(), Some(1e21)),
+                ("alpha".to_string(), None),
+                ("mid".to_string(), Some(-0.0)),
+            ]),
+            nested: BTreeMap::from([(
+                "z".to_string(),
+                BTreeMap::from([("b".to_string(), true), ("a".to_string(), false)]),
+            )]),
+            unit: (),
+            bytes: [0, 127, 255],
+        }
+    }
+
+    #[test]
+    fn golden_bytes() {
+        assert_eq!(
+            canonical(&fixture()),
+            concat!(
+                r#"{"attributes":{"alpha":null,"mid":0,"zeta":1e+21},"bytes":[0,127,255],"#,
+                r#""name":"café \"quoted\"\n","nested":{"z":{"a":false,"b":true}},"#,
+                r#""shapes":["Empty",{"Circle":0.5},{"Line":[-3,4]},{"Box":{"height":1,"width":2}}],"#,
+                r#""tags":["b","a"],"unit":null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn key_order_does_not_matter() {
+        let forward = (0..50)
+            .map(|index| (format!("key-{}", index), index))
+            .collect::<HashMap<_, _>>();
+        let backward = (0..50)
+            .rev()
+            .map(|index| (format!("key-{}", index), index))
+            .collect::<HashMap<_, _>>();
+        let sorted = forward.clone().into_iter().collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            to_canonical_vec(&forward).unwrap(),
+            to_canonical_vec(&backward).unwrap()
+        );
+        assert_eq!(to_canonical_vec(&forward).unwrap(), to_canonical_vec(&sorted).unwrap());
+
+        // A struct and a map with the same members are the same JSON.
+        #[derive(Serialize)]
+        struct Point {
+            y: i32,
+            x: i32,
+        }
+        assert_eq!(
+            canonical(&Point { y: 2, x: 1 }),
+            canonical(&HashMap::from([("x", 1), ("y", 2)]))
+        );
+    }
+
+    // Keys sort by UTF-16 code units, U+1F600 is a surrogate pair and sorts before U+FB33 unlike by code point.
+    #[test]
+    fn keys_sort_by_utf16() {
+        let map = BTreeMap::from([
+            ("\u{fb33}", 1),
+            ("\u{1f600}", 2),
+            ("\u{20ac}", 3),
+            ("\r", 4),
+            ("1", 5),
+            ("a", 6),
+        ]);
+        assert_eq!(
+            canonical(&map),
+            "{\"\\r\":4,\"1\":5,\"a\":6,\"\u{20ac}\":3,\"\u{1f600}\":2,\"\u{fb33}\":1}"
+        );
+    }
+
+    // Number serialization samples of RFC 8785 and ECMAScript.
+    #[test]
+    fn numbers() {
+        let samples = [
+            (0.0, "0"),
+            (-0.0, "0"),
+            (1.0, "1"),
+            (-1.5, "-1.5"),
+            (4.50, "4.5"),
+            (2e-3, "0.002"),
+            (0.000001, "0.000001"),
+            (1e-7, "1e-7"),
+            (1e-27, "1e-27"),
+            (5e-324, "5e-324"),
+            (1e20, "100000000000000000000"),
+            (1e21, "1e+21"),
+            (123456789012345680000.0, "123456789012345680000"),
+            (333333333.3333333, "333333333.3333333"),
+            (1.7976931348623157e308, "1.7976931348623157e+308"),
+            (9007199254740992.0, "9007199254740992"),
+            (295147905179352830000.0, "295147905179352830000"),
+            (-1.25e-10, "-1.25e-10"),
+        ];
+        for (value, expected) in samples {
+            assert_eq!(canonical(&value), expected, "{:e}", value);
+        }
+        assert_eq!(canonical(&0.1f32), "0.10000000149011612");
+        assert_eq!(canonical(&MAX_SAFE_INTEGER), "9007199254740991");
+        assert_eq!(canonical(&-42i8), "-42");
+        assert_eq!(canonical(&u64::from(u32::MAX)), "4294967295");
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            canonical("\u{0}\u{8}\t\n\u{b}\u{c}\r\u{1f}"),
+            r#""\u0000\b\t\n\u000b\f\r\u001f""#
+        );
+        assert_eq!(canonical("\u{7f}\u{2028}/</script>"), "\"\u{7f}\u{2028}/</script>\"");
+        assert_eq!(canonical(&'"'), r#""\"""#);
+        assert_eq!(canonical("\\"), r#""\\""#);
+    }
+
+    #[test]
+    fn rejections() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                to_canonical_vec(&value),
+                Err(CanonicalJsonError::NonFiniteNumber(_))
+            ));
+        }
+        assert!(matches!(
+            to_canonical_vec(&vec![Some(f32::NAN)]),
+            Err(CanonicalJsonError::NonFiniteNumber(_))
+        ));
+        assert_eq!(
+            to_canonical_vec(&(MAX_SAFE_INTEGER as u64 + 1)),
+            Err(CanonicalJsonError::UnsafeInteger(MAX_SAFE_INTEGER + 1))
+        );
+        assert_eq!(
+            to_canonical_vec(&i64::MIN),
+            Err(CanonicalJsonError::UnsafeInteger(i64::MIN.into()))
+        );
+        assert_eq!(
+            to_canonical_vec(&HashMap::from([(1u32, "a")])),
+            Err(CanonicalJsonError::NonStringKey)
+        );
+        assert_eq!(
+            to_canonical_vec(&HashMap::from([(true, "a")])),
+            Err(CanonicalJsonError::NonStringKey)
+        );
+        assert_eq!(
+            to_canonical_vec(&HashMap::from([((), 1)])),
+            Err(CanonicalJsonError::NonStringKey)
+        );
+
+        #[derive(Serialize)]
+        struct Inner {
+            id: u32,
+        }
+        #[derive(Serialize)]
+        struct Outer {
+            id: u32,
+            #[serde(flatten)]
+            inner: Inner,
+        }
+        assert_eq!(
+            to_canonical_vec(&Outer {
+                id: 1,
+                inner: Inner { id: 2 }
+            }),
+            Err(CanonicalJsonError::DuplicateKey("id".to_string()))
+        );
+    }
+
+    // What a signer signs is what a verifier rebuilds after the value went through JSON and back.
+    #[test]
+    fn signatures_survive_a_json_round_trip() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([171; 32]));
+        let original = fixture();
+        let signature = key_pair.sk.sign(to_canonical_vec(&original).unwrap(), None);
+
+        let json = serde_json::to_string_pretty(&original).unwrap();
+        let received: Fixture = serde_json::from_str(&json).unwrap();
+        let rebuilt = to_canonical_vec(&received).unwrap();
+        assert_eq!(key_pair.pk.verify(&rebuilt, &signature), Ok(()));
+
+        // Parsing into an untyped value and reordering does not change the bytes either.
+        let untyped: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(to_canonical_vec(&untyped).unwrap(), rebuilt);
+
+        let mut tampered = received;
+        tampered.tags.reverse();
+        assert!(key_pair
+            .pk
+            .verify(to_canonical_vec(&tampered).unwrap(), &signature)
+            .is_err());
+    }
+}
@@ -0,0 +1,231 @@
+#![cfg(feature = "cli")]
+
+use std::fmt::Write;
+
+use crate::share_link::{BucketSharePermissionFlags, SHORT_PERMISSIONS};
+use crate::BucketStatus;
+
+// Whether badges get escape codes. Auto colors only when the caller found stdout to be a terminal.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ColorMode {
+    Never,
+    Always,
+    Auto { is_tty: bool },
+}
+
+impl ColorMode {
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorMode::Never => false,
+            ColorMode::Always => true,
+            ColorMode::Auto { is_tty } => *is_tty,
+        }
+    }
+}
+
+// What a badge means, a Theme decides how that looks.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Style {
+    Good,
+    Info,
+    Caution,
+    Danger,
+    Accent,
+    // Unset permissions.
+    Muted,
+}
+
+/*
+* SGR parameters of every Style, the only place escape sequences are chosen. A badge is written as
+* ESC [ <parameters> m, the text and ESC [ 0 m.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Theme {
+    pub good: &'static str,
+    pub info: &'static str,
+    pub caution: &'static str,
+    pub danger: &'static str,
+    pub accent: &'static str,
+    pub muted: &'static str,
+}
+
+impl Theme {
+    // The 8 basic colors every terminal has, dim for muted.
+    pub const ANSI: Theme = Theme {
+        good: "32",
+        info: "34",
+        caution: "33",
+        danger: "31",
+        accent: "35",
+        muted: "2",
+    };
+
+    pub fn sgr(&self, style: Style) -> &'static str {
+        match style {
+            Style::Good => self.good,
+            Style::Info => self.info,
+            Style::Caution => self.caution,
+            Style::Danger => self.danger,
+            Style::Accent => self.accent,
+            Style::Muted => self.muted,
+        }
+    }
+
+    fn paint(&self, out: &mut String, style: Style, text: &str, color: ColorMode) {
+        match color.enabled() {
+            true => write!(out, "\x1b[{}m{}\x1b[0m", self.sgr(style), text).expect("writing to a String never fails"),
+            false => out.push_str(text),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::ANSI
+    }
+}
+
+// Same order as SHORT_PERMISSIONS, the more a permission allows the louder its color.
+const PERMISSION_STYLES: [Style; 8] = [
+    Style::Good,
+    Style::Info,
+    Style::Caution,
+    Style::Danger,
+    Style::Danger,
+    Style::Accent,
+    Style::Accent,
+    Style::Info,
+];
+
+// Longest status name, status badges are padded to it.
+const STATUS_BADGE_WIDTH: usize = 11;
+
+impl BucketSharePermissionFlags {
+    /*
+    * to_short_string with a color per permission, e.g. "vr-d----". Always 8 characters wide without the escape codes,
+    * unset permissions are dim dashes.
+    */
+    pub fn render_badges(&self, color: ColorMode) -> String {
+        self.render_badges_with(color, &Theme::default())
+    }
+
+    pub fn render_badges_with(&self, color: ColorMode, theme: &Theme) -> String {
+        let mut out = String::new();
+        for ((character, flag), style) in SHORT_PERMISSIONS.iter().zip(PERMISSION_STYLES) {
+            match self.contains(*flag) {
+                true => theme.paint(&mut out, style, character.encode_utf8(&mut [0; 4]), color),
+                false => theme.paint(&mut out, Style::Muted, "-", color),
+            }
+        }
+        out
+    }
+}
+
+impl BucketStatus {
+    // Follows severity: available is good, ongoing operations info, deletion caution and outages danger.
+    pub fn badge_style(&self) -> Style {
+        match self.severity() {
+            0 => Style::Good,
+            1..=4 => Style::Info,
+            5..=6 => Style::Caution,
+            _ => Style::Danger,
+        }
+    }
+
+    // The status name padded to the longest one, so status columns line up.
+    pub fn render_badge(&self, color: ColorMode) -> String {
+        self.render_badge_with(color, &Theme::default())
+    }
+
+    pub fn render_badge_with(&self, color: ColorMode, theme: &Theme) -> String {
+        let mut out = String::new();
+        let name = format!("{:<width$}", self.to_string(), width = STATUS_BADGE_WIDTH);
+        theme.paint(&mut out, self.badge_style(), &name, color);
+        out
+    }
+}
+
+#[allow(deprecated)]
+impl crate::AvailabilityStatus {
+    // Same as BucketStatus::render_badge.
+    pub fn render_badge(&self, color: ColorMode) -> String {
+        BucketStatus::from(self.clone()).render_badge(color)
+    }
+
+    pub fn render_badge_with(&self, color: ColorMode, theme: &Theme) -> String {
+        BucketStatus::from(self.clone()).render_badge_with(color, theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    fn every_combination() -> impl Iterator<Item = BucketSharePermissionFlags> {
+        (0..=BucketSharePermissionFlags::all().bits()).map(BucketSharePermissionFlags::from_bits_truncate)
+    }
+
+    #[test]
+    fn plain_badges_have_a_fixed_width() {
+        for flags in every_combination() {
+            for color in [ColorMode::Never, ColorMode::Auto { is_tty: false }] {
+                let badges = flags.render_badges(color);
+                assert_eq!(badges, flags.to_short_string());
+                assert_eq!(badges.chars().count(), SHORT_PERMISSIONS.len());
+                assert!(!badges.contains('\x1b'), "{:?}", badges);
+            }
+        }
+        for status in BucketStatus::iter() {
+            let badge = status.render_badge(ColorMode::Never);
+            assert_eq!(badge.chars().count(), STATUS_BADGE_WIDTH, "{:?}", badge);
+            assert_eq!(badge.trim_end(), status.to_string());
+        }
+    }
+
+    #[test]
+    fn colored_badges_use_the_theme() {
+        let flags = BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ;
+        let badges = flags.render_badges(ColorMode::Always);
+        assert_eq!(badges, "\x1b[32mv\x1b[0m\x1b[34mr\x1b[0m".to_string() + &"\x1b[2m-\x1b[0m".repeat(6));
+        assert_eq!(flags.render_badges(ColorMode::Auto { is_tty: true }), badges);
+
+        let delete = BucketSharePermissionFlags::DELETE_FILE | BucketSharePermissionFlags::DELETE_BUCKET;
+        assert_eq!(delete.render_badges(ColorMode::Always).matches("\x1b[31m").count(), 2);
+        for flags in every_combination() {
+            let badges = flags.render_badges(ColorMode::Always);
+            assert_eq!(badges.matches("\x1b[0m").count(), SHORT_PERMISSIONS.len());
+        }
+
+        let mono = Theme { good: "1", info: "1", caution: "1", danger: "1;4", accent: "1", muted: "2" };
+        assert_eq!(
+            BucketSharePermissionFlags::DELETE_FILE.render_badges_with(ColorMode::Always, &mono),
+            "\x1b[2m-\x1b[0m".repeat(3) + "\x1b[1;4md\x1b[0m" + &"\x1b[2m-\x1b[0m".repeat(4)
+        );
+    }
+
+    #[test]
+    fn status_badges_follow_severity() {
+        assert_eq!(BucketStatus::Available.render_badge(ColorMode::Always), "\x1b[32mAvailable  \x1b[0m");
+        assert_eq!(BucketStatus::Restoring.badge_style(), Style::Info);
+        assert_eq!(BucketStatus::Deleted.badge_style(), Style::Caution);
+        assert_eq!(BucketStatus::Corrupted.render_badge(ColorMode::Always), "\x1b[31mCorrupted  \x1b[0m");
+        let mut previous = Style::Good;
+        let ranks = [Style::Good, Style::Info, Style::Caution, Style::Danger];
+        let mut by_severity = BucketStatus::iter().collect::<Vec<_>>();
+        by_severity.sort_by_key(BucketStatus::severity);
+        for status in by_severity {
+            let style = status.badge_style();
+            let rank = |style| ranks.iter().position(|ranked| *ranked == style).unwrap();
+            assert!(rank(style) >= rank(previous), "{}", status);
+            previous = style;
+        }
+
+        #[allow(deprecated)]
+        for status in crate::AvailabilityStatus::iter() {
+            let color = ColorMode::Always;
+            assert_eq!(status.render_badge(color), BucketStatus::from(status.clone()).render_badge(color));
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{BucketStatus, RegionCluster};
+
+/*
+* Health of one region cluster as reported by its monitoring, for the public status page and request routing.
+* Field names are part of the public status API and never change. Metrics a cluster could not measure are null.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ClusterHealth {
+    pub cluster: RegionCluster,
+    pub status: BucketStatus,
+    pub latency_p50_ms: Option<u32>,
+    pub latency_p99_ms: Option<u32>,
+    // Failed requests per million.
+    pub error_rate_ppm: Option<u32>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub observed_at: OffsetDateTime,
+}
+
+impl ClusterHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.status == BucketStatus::Available
+    }
+}
+
+// Latest report of every cluster of a region.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RegionHealthSummary {
+    pub clusters: Vec<ClusterHealth>,
+}
+
+impl RegionHealthSummary {
+    // Keeps the newest report per cluster, clusters stay in the order they were first reported in.
+    pub fn from_reports(reports: impl IntoIterator<Item = ClusterHealth>) -> Self {
+        let mut clusters: Vec<ClusterHealth> = Vec::new();
+        for report in reports {
+            match clusters.iter_mut().find(|existing| existing.cluster == report.cluster) {
+                Some(existing) if existing.observed_at < report.observed_at => *existing = report,
+                Some(_) => {}
+                None => clusters.push(report),
+            }
+        }
+        Self { clusters }
+    }
+
+    // None without any reports, an empty region is not known to be healthy.
+    pub fn worst_status(&self) -> Option<&BucketStatus> {
+        self.clusters
+            .iter()
+            .map(|health| &health.status)
+            .max_by_key(|status| status.severity())
+    }
+
+    pub fn healthy_clusters(&self) -> impl Iterator<Item = &RegionCluster> {
+        self.clusters
+            .iter()
+            .filter(|health| health.is_healthy())
+            .map(|health| &health.cluster)
+    }
+}
+
+impl BucketStatus {
+    /*
+    * How bad the status is for users, 0 is fully available. Every status has its own severity so the ordering is total:
+    * Available < Creating < Updating < Archiving < Restoring < Deleting < Deleted < Unavailable < Unreachable < Corrupted.
+    * Ongoing operations come first as data stays readable, then deletion, then outages.
+    * Unreachable ranks above Unavailable since nothing is known about the cluster, Corrupted is the only status losing data.
+    */
+    pub fn severity(&self) -> u8 {
+        match self {
+            BucketStatus::Available => 0,
+            BucketStatus::Creating => 1,
+            BucketStatus::Updating => 2,
+            BucketStatus::Archiving => 3,
+            BucketStatus::Restoring => 4,
+            BucketStatus::Deleting => 5,
+            BucketStatus::Deleted => 6,
+            BucketStatus::Unavailable => 7,
+            BucketStatus::Unreachable => 8,
+            BucketStatus::Corrupted => 9,
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl crate::AvailabilityStatus {
+    // Same ordering as BucketStatus::severity.
+    pub fn severity(&self) -> u8 {
+        BucketStatus::from(self.clone()).severity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+    use time::macros::datetime;
+
+    use super::*;
+
+    const SUMMARY_FIXTURE: &str = include_str!("../testdata/region_health_summary.json");
+
+    fn health(cluster: &str, status: BucketStatus, observed_at: OffsetDateTime) -> ClusterHealth {
+        ClusterHealth {
+            cluster: cluster.parse().unwrap(),
+            status,
+            latency_p50_ms: Some(12),
+            latency_p99_ms: Some(180),
+            error_rate_ppm: Some(40),
+            observed_at,
+        }
+    }
+
+    fn summary() -> RegionHealthSummary {
+        let observed_at = datetime!(2024-06-01 12:00 UTC);
+        RegionHealthSummary::from_reports([
+            health("eu-north-1", BucketStatus::Available, observed_at),
+            health("eu-north-2", BucketStatus::Updating, observed_at),
+            ClusterHealth {
+                latency_p50_ms: None,
+                latency_p99_ms: None,
+                error_rate_ppm: None,
+                ..health("eu-north-3", BucketStatus::Unreachable, observed_at)
+            },
+        ])
+    }
+
+    #[test]
+    fn severity_ordering_is_total() {
+        let documented = [
+            BucketStatus::Available,
+            BucketStatus::Creating,
+            BucketStatus::Updating,
+            BucketStatus::Archiving,
+            BucketStatus::Restoring,
+            BucketStatus::Deleting,
+            BucketStatus::Deleted,
+            BucketStatus::Unavailable,
+            BucketStatus::Unreachable,
+            BucketStatus::Corrupted,
+        ];
+        assert_eq!(documented.len(), BucketStatus::iter().count());
+        for pair in documented.windows(2) {
+            assert!(pair[0].severity() < pair[1].severity(), "{} < {}", pair[0], pair[1]);
+        }
+        #[allow(deprecated)]
+        for status in crate::AvailabilityStatus::iter() {
+            assert_eq!(status.severity(), BucketStatus::from(status.clone()).severity());
+        }
+    }
+
+    #[test]
+    fn aggregation_picks_the_worst() {
+        let summary = summary();
+        assert_eq!(summary.worst_status(), Some(&BucketStatus::Unreachable));
+        let healthy = summary
+            .healthy_clusters()
+            .map(|cluster| cluster.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(healthy, ["eu-north-1"]);
+
+        // A newer report replaces the older one of the same cluster, an older one is ignored.
+        let recovered = RegionHealthSummary::from_reports(summary.clusters.iter().cloned().chain([
+            health("eu-north-3", BucketStatus::Available, datetime!(2024-06-01 12:01 UTC)),
+            health("eu-north-2", BucketStatus::Corrupted, datetime!(2024-06-01 11:59 UTC)),
+        ]));
+        assert_eq!(recovered.clusters.len(), 3);
+        assert_eq!(recovered.worst_status(), Some(&BucketStatus::Updating));
+        assert_eq!(recovered.healthy_clusters().count(), 2);
+    }
+
+    #[test]
+    fn empty_region() {
+        let summary = RegionHealthSummary::from_reports([]);
+        assert_eq!(summary.worst_status(), None);
+        assert_eq!(summary.healthy_clusters().count(), 0);
+        assert_eq!(serde_json::to_string(&summary).unwrap(), r#"{"clusters":[]}"#);
+    }
+
+    #[test]
+    fn golden_json() {
+        assert_eq!(
+            serde_json::to_string_pretty(&summary()).unwrap() + "\n",
+            SUMMARY_FIXTURE
+        );
+        assert_eq!(
+            serde_json::from_str::<RegionHealthSummary>(SUMMARY_FIXTURE).unwrap(),
+            summary()
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::util::truncate_input;
+use crate::{BucketCompression, BucketStorageClass};
+
+/*
+* Compression algorithm with an optional level, None uses the algorithm's own default.
+* String form is <algorithm>[:<level>], e.g. "zstd:19" or "gzip", and is used for serde as well.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CompressionSpec {
+    pub algorithm: BucketCompression,
+    pub level: Option<i32>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum CompressionSpecError {
+    #[error("{algorithm} does not take a compression level")]
+    LevelNotSupported { algorithm: BucketCompression },
+    #[error("{algorithm} level {level} is outside {min}..={max}")]
+    LevelOutOfRange { algorithm: BucketCompression, level: i32, min: i32, max: i32 },
+    #[error("unknown compression algorithm \"{0}\"")]
+    UnknownAlgorithm(String),
+    #[error("invalid compression level \"{0}\"")]
+    InvalidLevel(String),
+}
+
+impl SpannedParseError for CompressionSpecError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            CompressionSpecError::UnknownAlgorithm(input) | CompressionSpecError::InvalidLevel(input) => {
+                ParseFailure::whole(self, input)
+            }
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl CompressionSpec {
+    pub fn new(algorithm: BucketCompression, level: Option<i32>) -> Result<Self, CompressionSpecError> {
+        let spec = Self { algorithm, level };
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    // Levels accepted by the algorithm, None if it does not take one.
+    pub fn level_range(algorithm: &BucketCompression) -> Option<RangeInclusive<i32>> {
+        match algorithm {
+            BucketCompression::None => None,
+            BucketCompression::Gzip => Some(1..=9),
+            BucketCompression::Brotli => Some(0..=11),
+            // Negative levels are zstd's fast modes.
+            BucketCompression::Zstd => Some(-7..=22),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), CompressionSpecError> {
+        let Some(level) = self.level else {
+            return Ok(());
+        };
+        let range = Self::level_range(&self.algorithm).ok_or_else(|| CompressionSpecError::LevelNotSupported {
+            algorithm: self.algorithm.clone(),
+        })?;
+        if !range.contains(&level) {
+            return Err(CompressionSpecError::LevelOutOfRange {
+                algorithm: self.algorithm.clone(),
+                level,
+                min: *range.start(),
+                max: *range.end(),
+            });
+        }
+        Ok(())
+    }
+
+    // Archive classes are rarely read, so they trade compression time for size.
+    pub fn default_for(storage_class: &BucketStorageClass) -> Self {
+        let level = match storage_class {
+            BucketStorageClass::General | BucketStorageClass::ReducedRedundancy => 3,
+            BucketStorageClass::Archive => 19,
+            BucketStorageClass::DeepArchive => 22,
+        };
+        Self {
+            algorithm: BucketCompression::Zstd,
+            level: Some(level),
+        }
+    }
+
+    fn algorithm_name(algorithm: &BucketCompression) -> &'static str {
+        match algorithm {
+            BucketCompression::None => "none",
+            BucketCompression::Gzip => "gzip",
+            BucketCompression::Brotli => "brotli",
+            BucketCompression::Zstd => "zstd",
+        }
+    }
+}
+
+impl fmt::Display for CompressionSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(Self::algorithm_name(&self.algorithm))?;
+        if let Some(level) = self.level {
+            write!(f, ":{}", level)?;
+        }
+        Ok(())
+    }
+}
+
+// Algorithm names are case-insensitive, the level is validated against the algorithm.
+impl FromStr for CompressionSpec {
+    type Err = CompressionSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, level) = match s.split_once(':') {
+            Some((algorithm, level)) => (algorithm, Some(level)),
+            None => (s, None),
+        };
+        let algorithm = [
+            BucketCompression::None,
+            BucketCompression::Gzip,
+            BucketCompression::Brotli,
+            BucketCompression::Zstd,
+        ]
+        .into_iter()
+        .find(|candidate| Self::algorithm_name(candidate).eq_ignore_ascii_case(algorithm))
+        .ok_or_else(|| CompressionSpecError::UnknownAlgorithm(truncate_input(algorithm)))?;
+        let level = level
+            .map(|level| {
+                level
+                    .parse::<i32>()
+                    .map_err(|_| CompressionSpecError::InvalidLevel(truncate_input(level)))
+            })
+            .transpose()?;
+        Self::new(algorithm, level)
+    }
+}
+
+impl Serialize for CompressionSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CompressionSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_boundaries() {
+        for (algorithm, min, max) in [
+            (BucketCompression::Gzip, 1, 9),
+            (BucketCompression::Brotli, 0, 11),
+            (BucketCompression::Zstd, -7, 22),
+        ] {
+            assert!(CompressionSpec::new(algorithm.clone(), Some(min)).is_ok());
+            assert!(CompressionSpec::new(algorithm.clone(), Some(max)).is_ok());
+            assert_eq!(
+                CompressionSpec::new(algorithm.clone(), Some(min - 1)),
+                Err(CompressionSpecError::LevelOutOfRange {
+                    algorithm: algorithm.clone(),
+                    level: min - 1,
+                    min,
+                    max
+                })
+            );
+            assert!(matches!(
+                CompressionSpec::new(algorithm, Some(max + 1)),
+                Err(CompressionSpecError::LevelOutOfRange { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn algorithm_without_level() {
+        let spec: CompressionSpec = "gzip".parse().unwrap();
+        assert_eq!(spec, CompressionSpec::new(BucketCompression::Gzip, None).unwrap());
+        assert_eq!(spec.to_string(), "gzip");
+        assert_eq!("none".parse::<CompressionSpec>().unwrap().algorithm, BucketCompression::None);
+    }
+
+    #[test]
+    fn none_rejects_level() {
+        assert_eq!(
+            CompressionSpec::new(BucketCompression::None, Some(0)),
+            Err(CompressionSpecError::LevelNotSupported {
+                algorithm: BucketCompression::None
+            })
+        );
+        assert!("none:1".parse::<CompressionSpec>().is_err());
+    }
+
+    #[test]
+    fn string_and_serde_round_trip() {
+        let spec: CompressionSpec = "Zstd:-7".parse().unwrap();
+        assert_eq!(spec.to_string(), "zstd:-7");
+        let json = serde_json::to_string(&spec).unwrap();
+        assert_eq!(json, "\"zstd:-7\"");
+        assert_eq!(serde_json::from_str::<CompressionSpec>(&json).unwrap(), spec);
+        assert!(serde_json::from_str::<CompressionSpec>("\"zstd:23\"").is_err());
+
+        assert!(matches!("lz4".parse::<CompressionSpec>(), Err(CompressionSpecError::UnknownAlgorithm(_))));
+        assert!(matches!("zstd:high".parse::<CompressionSpec>(), Err(CompressionSpecError::InvalidLevel(_))));
+        assert!(matches!("zstd:".parse::<CompressionSpec>(), Err(CompressionSpecError::InvalidLevel(_))));
+    }
+
+    #[test]
+    fn archive_classes_default_to_higher_levels() {
+        let general = CompressionSpec::default_for(&BucketStorageClass::General);
+        let archive = CompressionSpec::default_for(&BucketStorageClass::Archive);
+        let deep_archive = CompressionSpec::default_for(&BucketStorageClass::DeepArchive);
+        assert_eq!(general.to_string(), "zstd:3");
+        assert!(archive.level > general.level);
+        assert!(deep_archive.level > archive.level);
+        for spec in [general, archive, deep_archive] {
+            assert_eq!(spec.validate(), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::errors::{is_truncated, ParseFailure, SpannedParseError};
+use crate::util::{truncate_input, Fingerprint};
+use crate::BucketEncryption;
+
+/*
+* Tracks which key generation encrypted an object, needed to re-encrypt objects after a key rotation.
+* The header form is <scheme>.g<generation>[.fp:<fingerprint>], e.g. aes256.g3.fp:abcd1234, for object metadata headers.
+* rotated_at is not part of the header form, objects only need the generation and key fingerprint.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EncryptionMetadata {
+    pub scheme: BucketEncryption,
+    pub key_generation: u32,
+    pub key_fingerprint: Option<Fingerprint>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub rotated_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum EncryptionMetadataError {
+    #[error("unencrypted buckets must use key generation 0, got {0}")]
+    UnencryptedKeyGeneration(u32),
+    #[error("unencrypted buckets can not have a key fingerprint")]
+    UnencryptedKeyFingerprint,
+    #[error("custom encryption name \"{0}\" is not header safe")]
+    InvalidCustomName(String),
+    #[error("invalid encryption metadata \"{0}\"")]
+    InvalidFormat(String),
+}
+
+impl SpannedParseError for EncryptionMetadataError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            EncryptionMetadataError::InvalidCustomName(name) => ParseFailure::whole(self, name),
+            EncryptionMetadataError::InvalidFormat(input) if !is_truncated(input) => {
+                ParseFailure::spanned(self, input, invalid_field_span(input))
+            }
+            EncryptionMetadataError::InvalidFormat(input) => ParseFailure::whole(self, input),
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+// The first header field from_str rejects, an empty span at the end if one is missing.
+fn invalid_field_span(input: &str) -> std::ops::Range<usize> {
+    let mut start = 0;
+    for (position, field) in input.split('.').enumerate() {
+        let valid = match position {
+            0 => matches!(field, "none" | "aes256" | "zk") || field.parse::<BucketEncryption>().is_ok(),
+            1 => field.strip_prefix('g').is_some_and(|generation| generation.parse::<u32>().is_ok()),
+            2 => field.strip_prefix("fp:").is_some_and(|fingerprint| fingerprint.parse::<Fingerprint>().is_ok()),
+            _ => return start..input.len(),
+        };
+        if !valid {
+            return start..start + field.len();
+        }
+        start += field.len() + 1;
+    }
+    input.len()..input.len()
+}
+
+impl EncryptionMetadata {
+    pub fn new(scheme: BucketEncryption, key_fingerprint: Option<Fingerprint>) -> Self {
+        Self {
+            scheme,
+            key_generation: 0,
+            key_fingerprint,
+            rotated_at: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), EncryptionMetadataError> {
+        match &self.scheme {
+            BucketEncryption::None if self.key_generation != 0 => {
+                Err(EncryptionMetadataError::UnencryptedKeyGeneration(self.key_generation))
+            }
+            BucketEncryption::None if self.key_fingerprint.is_some() => {
+                Err(EncryptionMetadataError::UnencryptedKeyFingerprint)
+            }
+            // '.' separates the header fields.
+            // Only stored names from before the charset check, or from BucketEncryption::from_unchecked, can fail this.
+            BucketEncryption::Custom(name)
+                if !name.as_str().bytes().all(|byte| byte.is_ascii_graphic() && byte != b'.') =>
+            {
+                Err(EncryptionMetadataError::InvalidCustomName(truncate_input(name.as_str())))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn rotate(&self) -> EncryptionMetadata {
+        self.rotate_at(OffsetDateTime::now_utc())
+    }
+
+    /*
+    * Bumps the generation, the fingerprint is cleared since it belongs to the previous key.
+    * Unencrypted buckets have no key to rotate and are returned unchanged.
+    */
+    pub fn rotate_at(&self, now: OffsetDateTime) -> EncryptionMetadata {
+        if self.scheme == BucketEncryption::None {
+            return self.clone();
+        }
+        Self {
+            scheme: self.scheme.clone(),
+            // A bucket will never see 4 billion rotations.
+            key_generation: self.key_generation.saturating_add(1),
+            key_fingerprint: None,
+            rotated_at: Some(now),
+        }
+    }
+
+    fn scheme_name(scheme: &BucketEncryption) -> String {
+        match scheme {
+            BucketEncryption::None => "none".to_string(),
+            BucketEncryption::AES256 => "aes256".to_string(),
+            BucketEncryption::ZeroKnowledge => "zk".to_string(),
+            custom => custom.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for EncryptionMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.g{}", Self::scheme_name(&self.scheme), self.key_generation)?;
+        if let Some(fingerprint) = self.key_fingerprint {
+            write!(f, ".fp:{}", fingerprint)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for EncryptionMetadata {
+    type Err = EncryptionMetadataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || EncryptionMetadataError::InvalidFormat(truncate_input(s));
+        let mut fields = s.split('.');
+        let scheme = match fields.next().ok_or_else(invalid)? {
+            "none" => BucketEncryption::None,
+            "aes256" => BucketEncryption::AES256,
+            "zk" => BucketEncryption::ZeroKnowledge,
+            custom => custom.parse().map_err(|_| invalid())?,
+        };
+        let key_generation = fields
+            .next()
+            .and_then(|field| field.strip_prefix('g'))
+            .and_then(|generation| generation.parse().ok())
+            .ok_or_else(invalid)?;
+        let key_fingerprint = fields
+            .next()
+            .map(|field| {
+                field
+                    .strip_prefix("fp:")
+                    .and_then(|fingerprint| fingerprint.parse().ok())
+                    .ok_or_else(invalid)
+            })
+            .transpose()?;
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        let metadata = Self {
+            scheme,
+            key_generation,
+            key_fingerprint,
+            rotated_at: None,
+        };
+        metadata.validate()?;
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let metadata = EncryptionMetadata {
+            scheme: BucketEncryption::AES256,
+            key_generation: 3,
+            key_fingerprint: Some(Fingerprint([0xab, 0xcd, 0x12, 0x34])),
+            rotated_at: None,
+        };
+        assert_eq!(metadata.to_string(), "aes256.g3.fp:abcd1234");
+        assert_eq!("aes256.g3.fp:abcd1234".parse(), Ok(metadata));
+
+        for header in ["none.g0", "zk.g12", "Custom-acme.g1.fp:00000000"] {
+            assert_eq!(header.parse::<EncryptionMetadata>().unwrap().to_string(), header);
+        }
+        for header in ["", "aes256", "aes256.3", "aes256.g3.abcd1234", "aes256.g3.fp:abcd", "aes256.g3.fp:abcd1234.x", "rot13.g1"] {
+            assert!(matches!(
+                header.parse::<EncryptionMetadata>(),
+                Err(EncryptionMetadataError::InvalidFormat(_))
+            ), "{}", header);
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let metadata = EncryptionMetadata {
+            scheme: BucketEncryption::ZeroKnowledge,
+            key_generation: 2,
+            key_fingerprint: Some(Fingerprint::of(b"key")),
+            rotated_at: Some(datetime!(2024-06-02 10:30:15 UTC)),
+        };
+        let json = serde_json::to_string(&metadata).unwrap();
+        assert!(json.contains("\"2024-06-02T10:30:15Z\""));
+        assert_eq!(serde_json::from_str::<EncryptionMetadata>(&json).unwrap(), metadata);
+    }
+
+    #[test]
+    fn unencrypted_buckets_have_no_key() {
+        let mut metadata = EncryptionMetadata::new(BucketEncryption::None, None);
+        assert_eq!(metadata.validate(), Ok(()));
+        assert_eq!(metadata.rotate(), metadata);
+
+        metadata.key_generation = 1;
+        assert_eq!(metadata.validate(), Err(EncryptionMetadataError::UnencryptedKeyGeneration(1)));
+        assert!("none.g1".parse::<EncryptionMetadata>().is_err());
+
+        let metadata = EncryptionMetadata::new(BucketEncryption::None, Some(Fingerprint::of(b"key")));
+        assert_eq!(metadata.validate(), Err(EncryptionMetadataError::UnencryptedKeyFingerprint));
+        assert!("none.g0.fp:abcd1234".parse::<EncryptionMetadata>().is_err());
+    }
+
+    #[cfg(feature = "danger")]
+    #[test]
+    #[allow(deprecated)]
+    fn unchecked_custom_names_are_still_validated() {
+        let metadata = EncryptionMetadata::new(BucketEncryption::from_unchecked("Custom-a b"), None);
+        assert!(matches!(metadata.validate(), Err(EncryptionMetadataError::InvalidCustomName(_))));
+        let metadata = EncryptionMetadata::new(BucketEncryption::from_unchecked("acme.v2"), None);
+        assert!(matches!(metadata.validate(), Err(EncryptionMetadataError::InvalidCustomName(_))));
+    }
+
+    #[test]
+    fn rotation_is_monotonic() {
+        let mut metadata = EncryptionMetadata::new(BucketEncryption::AES256, Some(Fingerprint::of(b"key")));
+        let mut now = datetime!(2024-01-01 0:00 UTC);
+        for expected in 1..=5 {
+            let rotated = metadata.rotate_at(now);
+            assert_eq!(rotated.key_generation, expected);
+            assert!(rotated.key_generation > metadata.key_generation);
+            assert_eq!(rotated.key_fingerprint, None);
+            assert_eq!(rotated.rotated_at, Some(now));
+            metadata = rotated;
+            now += time::Duration::days(30);
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::util::MAX_ERROR_INPUT_LEN;
+
+/*
+* What went wrong parsing some input, the same shape for every parser of the crate so support tooling can show it
+* uniformly.
+* input_excerpt is the input as echoed by the error, truncated like every error message. It is empty for errors that
+* never echo their input, e.g. links which may hold a key.
+* span is the byte range of input_excerpt at fault, None when the error is about the input as a whole, e.g. its length.
+* kind is the name of the error variant, e.g. "ForbiddenCharacter".
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseFailure {
+    pub input_excerpt: String,
+    pub span: Option<Range<usize>>,
+    pub kind: &'static str,
+    pub message: String,
+}
+
+pub trait SpannedParseError: std::error::Error {
+    fn failure(&self) -> ParseFailure;
+}
+
+impl ParseFailure {
+    // For errors not pointing at any part of the input.
+    pub fn unspanned<E>(error: &E) -> Self
+    where
+        E: fmt::Display,
+        for<'a> &'a E: Into<&'static str>,
+    {
+        Self {
+            input_excerpt: String::new(),
+            span: None,
+            kind: error.into(),
+            message: error.to_string(),
+        }
+    }
+
+    // A span outside the excerpt or not on char boundaries is dropped, the excerpt is kept.
+    pub fn spanned<E>(error: &E, input_excerpt: &str, span: impl Into<Option<Range<usize>>>) -> Self
+    where
+        E: fmt::Display,
+        for<'a> &'a E: Into<&'static str>,
+    {
+        let span = span.into().filter(|span| {
+            span.start <= span.end
+                && span.end <= input_excerpt.len()
+                && input_excerpt.is_char_boundary(span.start)
+                && input_excerpt.is_char_boundary(span.end)
+        });
+        Self {
+            input_excerpt: input_excerpt.to_string(),
+            span,
+            ..Self::unspanned(error)
+        }
+    }
+
+    // Points at the whole excerpt.
+    pub fn whole<E>(error: &E, input_excerpt: &str) -> Self
+    where
+        E: fmt::Display,
+        for<'a> &'a E: Into<&'static str>,
+    {
+        Self::spanned(error, input_excerpt, 0..input_excerpt.len())
+    }
+}
+
+/*
+* The message followed by the excerpt with the span underlined, e.g.
+*
+* invalid duration "3x"
+*   3x
+*    ^
+*
+* Carets are aligned by chars, a span that cuts a multibyte char is widened to the whole char. Control characters are
+* shown as U+FFFD so they can not break the layout, an empty span gets a single caret.
+*/
+pub fn render_caret(failure: &ParseFailure) -> String {
+    let mut rendered = failure.message.clone();
+    if failure.input_excerpt.is_empty() {
+        return rendered;
+    }
+    let excerpt = &failure.input_excerpt;
+    rendered.push_str("\n  ");
+    rendered.extend(excerpt.chars().map(|char| match char.is_control() {
+        true => '\u{fffd}',
+        false => char,
+    }));
+    if let Some(span) = &failure.span {
+        let start = floor_char_boundary(excerpt, span.start.min(excerpt.len()));
+        let end = ceil_char_boundary(excerpt, span.end.clamp(start, excerpt.len()));
+        let offset = excerpt[..start].chars().count();
+        let width = excerpt[start..end].chars().count().max(1);
+        rendered.push_str("\n  ");
+        rendered.push_str(&" ".repeat(offset));
+        rendered.push_str(&"^".repeat(width));
+    }
+    rendered
+}
+
+fn floor_char_boundary(s: &str, mut index: usize) -> usize {
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(s: &str, mut index: usize) -> usize {
+    while !s.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/*
+* Byte range of a forbidden char found at byte index of the original input.
+* None when the excerpt does not hold that char there, e.g. it was truncated before it.
+*/
+pub(crate) fn char_span(input: &str, index: usize, expected: char) -> Option<Range<usize>> {
+    let found = input.get(index..)?.chars().next()?;
+    (found == expected).then(|| index..index + found.len_utf8())
+}
+
+// Same as char_span for errors counting chars instead of bytes.
+pub(crate) fn char_index_span(input: &str, index: usize, expected: char) -> Option<Range<usize>> {
+    let (start, found) = input.char_indices().nth(index)?;
+    (found == expected).then(|| start..start + found.len_utf8())
+}
+
+// First char that is not hex, or the whole input when its length is wrong.
+pub(crate) fn hex_span(input: &str, expected_len: usize) -> Range<usize> {
+    match (input.len() == expected_len, input.char_indices().find(|(_, char)| !char.is_ascii_hexdigit())) {
+        (true, Some((index, char))) => index..index + char.len_utf8(),
+        _ => 0..input.len(),
+    }
+}
+
+// Whether truncate_input cut the excerpt, spans counted from its end are meaningless then.
+pub(crate) fn is_truncated(input_excerpt: &str) -> bool {
+    input_excerpt.chars().count() > MAX_ERROR_INPUT_LEN
+}
+
+// BucketRegion keeps the error type of the strum implementation it replaced.
+impl SpannedParseError for strum::ParseError {
+    fn failure(&self) -> ParseFailure {
+        ParseFailure {
+            input_excerpt: String::new(),
+            span: None,
+            kind: "VariantNotFound",
+            message: self.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::bucket_description::{BucketDisplayName, BucketName};
+    use crate::bucket_guid::BucketGuid;
+    use crate::bucket_object::{BucketObjectKey, ContentHash};
+    use crate::compression::CompressionSpec;
+    use crate::encryption::EncryptionMetadata;
+    use crate::expiry::ExpiryTimestamp;
+    use crate::idempotency::IdempotencyKey;
+    use crate::list_objects::SortSpec;
+    use crate::payments::Money;
+    use crate::public_bucket_id::PublicBucketId;
+    use crate::retry::RetryAfter;
+    use crate::util::{Fingerprint, HumanDuration};
+    use crate::{BucketEncryption, BucketRegion, RegionCluster};
+
+    // Kind, excerpt and the spanned part of the excerpt of parsing input.
+    fn spanned<T>(input: &str) -> (&'static str, String, Option<String>)
+    where
+        T: FromStr + fmt::Debug,
+        T::Err: SpannedParseError,
+    {
+        let failure = input.parse::<T>().unwrap_err().failure();
+        let spanned = failure.span.clone().map(|span| failure.input_excerpt[span].to_string());
+        (failure.kind, failure.input_excerpt, spanned)
+    }
+
+    fn at(kind: &'static str, excerpt: &str, spanned: &str) -> (&'static str, String, Option<String>) {
+        (kind, excerpt.to_string(), Some(spanned.to_string()))
+    }
+
+    #[test]
+    fn identifier_spans() {
+        let user_id = "11111111-0000-4000-8000-000000000001";
+        assert_eq!(spanned::<BucketGuid>(user_id), at("InvalidFormat", user_id, user_id));
+        let guid = format!("{}/not-a-uuid", user_id);
+        assert_eq!(spanned::<BucketGuid>(&guid), at("InvalidFormat", &guid, "not-a-uuid"));
+        let guid = format!("nope/{}", user_id);
+        assert_eq!(spanned::<BucketGuid>(&guid), at("InvalidFormat", &guid, "nope"));
+
+        let hash = format!("{}g{}", "0".repeat(10), "0".repeat(53));
+        assert_eq!(spanned::<ContentHash>(&hash), at("InvalidFormat", &hash, "g"));
+        assert_eq!(spanned::<ContentHash>("00"), at("InvalidFormat", "00", "00"));
+        assert_eq!(spanned::<Fingerprint>("0123456z"), at("InvalidFormat", "0123456z", "z"));
+        assert_eq!(spanned::<Fingerprint>("0123"), at("InvalidFormat", "0123", "0123"));
+
+        let id = format!("{}1{}", "a".repeat(5), "a".repeat(14));
+        assert_eq!(spanned::<PublicBucketId>(&id), at("InvalidFormat", &id, "1"));
+        assert_eq!(spanned::<PublicBucketId>("abc"), at("InvalidFormat", "abc", "abc"));
+
+        assert_eq!(spanned::<IdempotencyKey>("req\u{e9}st-1"), at("InvalidCharacter", "req\u{e9}st-1", "\u{e9}"));
+        assert_eq!(spanned::<IdempotencyKey>("abc"), ("InvalidLength", String::new(), None));
+    }
+
+    #[test]
+    fn text_spans() {
+        assert_eq!(spanned::<BucketObjectKey>("/caf\u{e9}/\tx"), at("ForbiddenCharacter", "/caf\u{e9}/\tx", "\t"));
+        assert_eq!(spanned::<BucketObjectKey>(""), ("Empty", String::new(), None));
+        let failure = BucketName::try_from("caf\u{e9}\u{7}".to_string()).unwrap_err().failure();
+        assert_eq!(failure.kind, "ForbiddenCharacter");
+        assert_eq!(&failure.input_excerpt[failure.span.unwrap()], "\u{7}");
+        let failure = BucketDisplayName::try_from(" Two\nlines".to_string()).unwrap_err().failure();
+        assert_eq!((failure.input_excerpt.as_str(), failure.span), ("Two\nlines", Some(3..4)));
+    }
+
+    #[cfg(feature = "share_link")]
+    #[test]
+    fn email_spans() {
+        use crate::share_invitation::EmailAddress;
+        let failure = |input: &str| input.parse::<EmailAddress>().unwrap_err().failure();
+        let excerpt = |input| {
+            let failure = failure(input);
+            failure.span.map(|span| failure.input_excerpt[span].to_string())
+        };
+        assert_eq!(excerpt("a\u{e9} b@example.com").as_deref(), Some(" "));
+        assert_eq!(excerpt("a@b@example.com").as_deref(), Some("@"));
+        assert_eq!(excerpt("ab.example.com").as_deref(), Some("ab.example.com"));
+        assert_eq!(excerpt("@example.com").as_deref(), Some(""));
+        assert_eq!(excerpt("user@example..com").as_deref(), Some("example..com"));
+        assert_eq!(failure("\"quoted\"@example.com").span, None);
+    }
+
+    #[cfg(feature = "secret_share_link")]
+    #[test]
+    fn key_id_spans() {
+        use crate::keyring::KeyId;
+        assert_eq!(spanned::<KeyId>("key 1"), at("InvalidKeyId", "key 1", " "));
+        assert_eq!(spanned::<KeyId>(""), at("InvalidKeyId", "", ""));
+    }
+
+    #[test]
+    fn value_spans() {
+        assert_eq!(spanned::<HumanDuration>("-3d"), at("Negative", "-3d", "-"));
+        assert_eq!(spanned::<HumanDuration>("3x"), at("InvalidFormat", "3x", "3x"));
+        assert_eq!(spanned::<HumanDuration>("0s").2, None);
+        assert_eq!(spanned::<CompressionSpec>("lz4:3"), at("UnknownAlgorithm", "lz4", "lz4"));
+        assert_eq!(spanned::<CompressionSpec>("zstd:high"), at("InvalidLevel", "high", "high"));
+        assert_eq!(spanned::<CompressionSpec>("zstd:99").2, None);
+        assert_eq!(spanned::<ExpiryTimestamp>("yesterday"), at("InvalidFormat", "yesterday", "yesterday"));
+        assert_eq!(spanned::<ExpiryTimestamp>("1970-01-01T00:00:00Z").0, "TooEarly");
+        assert_eq!(spanned::<Money>("12.3.4 USD"), at("InvalidFormat", "12.3.4 USD", "12.3.4"));
+        assert_eq!(spanned::<Money>("12.34 XYZ"), at("InvalidFormat", "12.34 XYZ", "XYZ"));
+        assert_eq!(spanned::<RetryAfter>("soon"), at("InvalidFormat", "soon", "soon"));
+        assert_eq!(spanned::<SortSpec>("size_sideways"), at("InvalidSort", "size_sideways", "size_sideways"));
+        assert_eq!(spanned::<EncryptionMetadata>("aes256.x"), at("InvalidFormat", "aes256.x", "x"));
+        let metadata = "aes256.g1.fp:zz";
+        assert_eq!(spanned::<EncryptionMetadata>(metadata), at("InvalidFormat", metadata, "fp:zz"));
+        assert_eq!(spanned::<EncryptionMetadata>("aes256"), at("InvalidFormat", "aes256", ""));
+        assert_eq!(spanned::<BucketEncryption>("Rot13"), at("InvalidCustomFormat", "Rot13", "Rot13"));
+    }
+
+    #[test]
+    fn region_spans() {
+        assert_eq!(spanned::<RegionCluster>("eu-north"), at("InvalidClusterId", "eu-north", "north"));
+        assert_eq!(spanned::<RegionCluster>("mars-1"), at("InvalidRegion", "mars-1", "mars"));
+        assert_eq!(spanned::<RegionCluster>("eu-north-x"), at("InvalidClusterId", "eu-north-x", "x"));
+        let failure = "eunorth".parse::<RegionCluster>().unwrap_err().failure();
+        assert_eq!((failure.kind, failure.span), ("MissingClusterId", Some(7..7)));
+        assert_eq!(spanned::<BucketRegion>("eu-mars"), ("VariantNotFound", String::new(), None));
+
+        // A span counted from the end of a truncated excerpt would point at the wrong part.
+        let long = format!("{}-x", "a".repeat(100));
+        assert_eq!(long.parse::<RegionCluster>().unwrap_err().failure().span, None);
+    }
+
+    #[cfg(feature = "secret_share_link")]
+    #[test]
+    fn links_are_never_echoed() {
+        use crate::secret_share_link::SecretShareLink;
+        let failure = "ftp://bucketdrive.co/share/secret".parse::<SecretShareLink>().unwrap_err().failure();
+        assert_eq!(failure.input_excerpt, "");
+        assert_eq!(failure.span, None);
+        assert_eq!(failure.kind, "InvalidScheme");
+    }
+
+    #[test]
+    fn caret_rendering() {
+        let failure = "3x".parse::<HumanDuration>().unwrap_err().failure();
+        assert_eq!(render_caret(&failure), "invalid duration \"3x\"\n  3x\n  ^^");
+
+        let failure = "/caf\u{e9}/\tx".parse::<BucketObjectKey>().unwrap_err().failure();
+        assert_eq!(failure.span, Some(7..8));
+        let rendered = render_caret(&failure);
+        assert!(rendered.ends_with("\n  /caf\u{e9}/\u{fffd}x\n        ^"), "{}", rendered);
+
+        let failure = "eunorth".parse::<RegionCluster>().unwrap_err().failure();
+        assert!(render_caret(&failure).ends_with("\n  eunorth\n         ^"));
+
+        let failure = "abc".parse::<IdempotencyKey>().unwrap_err().failure();
+        assert_eq!(render_caret(&failure), failure.message);
+    }
+
+    #[test]
+    fn caret_rendering_never_slices_mid_char() {
+        let failure = |span| ParseFailure {
+            input_excerpt: "a\u{e9}\u{1f600}b".to_string(),
+            span: Some(span),
+            kind: "Test",
+            message: "test".to_string(),
+        };
+        // a is 1 byte, é 2 and the emoji 4.
+        assert!(render_caret(&failure(1..3)).ends_with("\n   ^"));
+        assert!(render_caret(&failure(2..3)).ends_with("\n   ^"));
+        assert!(render_caret(&failure(2..5)).ends_with("\n   ^^"));
+        assert!(render_caret(&failure(4..4)).ends_with("\n    ^"));
+        assert!(render_caret(&failure(8..100)).ends_with("\n      ^"));
+        assert!(render_caret(&failure(100..200)).ends_with("\n      ^"));
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = failure(5..2);
+        assert!(render_caret(&reversed).ends_with("\n    ^"));
+    }
+}
@@ -0,0 +1,426 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use time::format_description::well_known::Rfc3339;
+use time::macros::datetime;
+use time::{Duration, OffsetDateTime, UtcOffset};
+
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::util::{truncate_input, HumanDuration};
+
+// Nothing in the BucketDrive ecosystem existed before this, anything earlier is a client bug.
+pub const MIN_EXPIRY: OffsetDateTime = datetime!(2020-01-01 0:00 UTC);
+// 100 years, counting leap days.
+pub const MAX_EXPIRY_HORIZON: Duration = Duration::days(36525);
+
+/*
+* Timestamp used for link expiries and other signed points in time.
+* Always UTC, truncated to whole seconds, not before MIN_EXPIRY and at most MAX_EXPIRY_HORIZON into the future.
+* Encoded as RFC3339 in human-readable formats and as unix seconds in binary formats.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ExpiryTimestamp(OffsetDateTime);
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum ExpiryTimestampError {
+    #[error("timestamp {0} is before {MIN_EXPIRY}")]
+    TooEarly(OffsetDateTime),
+    #[error("timestamp {0} is more than 100 years in the future")]
+    TooFarInFuture(OffsetDateTime),
+    #[error("timestamp {0} is out of range")]
+    OutOfRange(i64),
+    #[error("invalid RFC3339 timestamp \"{0}\"")]
+    InvalidFormat(String),
+    #[error("invalid binary timestamp of {0} bytes")]
+    InvalidEncoding(usize),
+}
+
+impl SpannedParseError for ExpiryTimestampError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            ExpiryTimestampError::InvalidFormat(input) => ParseFailure::whole(self, input),
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl ExpiryTimestamp {
+    pub const MIN: Self = Self(MIN_EXPIRY);
+
+    // Same as TryFrom but with an explicit "now", used for the future horizon check.
+    pub fn new_at(value: OffsetDateTime, now: OffsetDateTime) -> Result<Self, ExpiryTimestampError> {
+        let value = value.to_offset(UtcOffset::UTC);
+        let value = value - Duration::nanoseconds(value.nanosecond() as i64);
+        if value < MIN_EXPIRY {
+            return Err(ExpiryTimestampError::TooEarly(value));
+        }
+        if value > now + MAX_EXPIRY_HORIZON {
+            return Err(ExpiryTimestampError::TooFarInFuture(value));
+        }
+        Ok(Self(value))
+    }
+
+    pub fn from_unix_timestamp(timestamp: i64) -> Result<Self, ExpiryTimestampError> {
+        let value = OffsetDateTime::from_unix_timestamp(timestamp)
+            .map_err(|_| ExpiryTimestampError::OutOfRange(timestamp))?;
+        Self::try_from(value)
+    }
+
+    pub fn unix_timestamp(&self) -> i64 {
+        self.0.unix_timestamp()
+    }
+
+    pub fn as_offset_date_time(&self) -> OffsetDateTime {
+        self.0
+    }
+
+    // Migration shim for code still holding an optional OffsetDateTime.
+    pub fn from_optional(value: Option<OffsetDateTime>) -> Result<Option<Self>, ExpiryTimestampError> {
+        value.map(Self::try_from).transpose()
+    }
+}
+
+impl TryFrom<OffsetDateTime> for ExpiryTimestamp {
+    type Error = ExpiryTimestampError;
+
+    fn try_from(value: OffsetDateTime) -> Result<Self, Self::Error> {
+        Self::new_at(value, OffsetDateTime::now_utc())
+    }
+}
+
+impl From<ExpiryTimestamp> for OffsetDateTime {
+    fn from(value: ExpiryTimestamp) -> Self {
+        value.0
+    }
+}
+
+/*
+* chrono interop, follows the same rules as TryFrom<OffsetDateTime>: normalized to UTC and truncated to whole seconds.
+* A leap second (23:59:60) is truncated to 23:59:59, dates time can not represent are OutOfRange.
+*/
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> TryFrom<chrono::DateTime<Tz>> for ExpiryTimestamp {
+    type Error = ExpiryTimestampError;
+
+    fn try_from(value: chrono::DateTime<Tz>) -> Result<Self, Self::Error> {
+        Self::from_unix_timestamp(value.timestamp())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<ExpiryTimestamp> for chrono::DateTime<chrono::Utc> {
+    fn from(value: ExpiryTimestamp) -> Self {
+        // Every ExpiryTimestamp is within the years chrono supports.
+        chrono::DateTime::from_timestamp(value.unix_timestamp(), 0).expect("expiry timestamps fit chrono")
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<ExpiryTimestamp> for chrono::DateTime<chrono::FixedOffset> {
+    fn from(value: ExpiryTimestamp) -> Self {
+        chrono::DateTime::<chrono::Utc>::from(value).fixed_offset()
+    }
+}
+
+/*
+* rkyv archives hold little-endian unix seconds. check_bytes applies the bounds of from_unix_timestamp,
+* so every timestamp in a validated archive is a valid ExpiryTimestamp.
+*/
+#[cfg(feature = "rkyv")]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(transparent)]
+pub struct ArchivedExpiryTimestamp(rkyv::Archived<i64>);
+
+#[cfg(feature = "rkyv")]
+impl ArchivedExpiryTimestamp {
+    pub fn unix_timestamp(&self) -> i64 {
+        self.0.value()
+    }
+
+    // Panics on a timestamp out of range, which only an archive accessed without validation can hold.
+    pub fn get(&self) -> ExpiryTimestamp {
+        let value = OffsetDateTime::from_unix_timestamp(self.unix_timestamp()).expect("archived expiry timestamp");
+        ExpiryTimestamp(value)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl rkyv::Archive for ExpiryTimestamp {
+    type Archived = ArchivedExpiryTimestamp;
+    type Resolver = ();
+
+    unsafe fn resolve(&self, pos: usize, resolver: Self::Resolver, out: *mut Self::Archived) {
+        // ArchivedExpiryTimestamp is a transparent Archived<i64>.
+        rkyv::Archive::resolve(&self.unix_timestamp(), pos, resolver, out.cast());
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<S: rkyv::Fallible + ?Sized> rkyv::Serialize<S> for ExpiryTimestamp {
+    fn serialize(&self, _: &mut S) -> Result<Self::Resolver, S::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<D: rkyv::Fallible + ?Sized> rkyv::Deserialize<ExpiryTimestamp, D> for ArchivedExpiryTimestamp {
+    fn deserialize(&self, _: &mut D) -> Result<ExpiryTimestamp, D::Error> {
+        Ok(self.get())
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<C: ?Sized> rkyv::bytecheck::CheckBytes<C> for ArchivedExpiryTimestamp {
+    type Error = ExpiryTimestampError;
+
+    unsafe fn check_bytes<'a>(value: *const Self, _: &mut C) -> Result<&'a Self, Self::Error> {
+        // Any 8 bytes are an i64, only the range needs checking.
+        let value = &*value;
+        ExpiryTimestamp::from_unix_timestamp(value.unix_timestamp())?;
+        Ok(value)
+    }
+}
+
+impl fmt::Display for ExpiryTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Formatting a UTC timestamp between 2020 and 100 years from now as RFC3339 can not fail.
+        f.write_str(&self.0.format(&Rfc3339).map_err(|_| fmt::Error)?)
+    }
+}
+
+impl FromStr for ExpiryTimestamp {
+    type Err = ExpiryTimestampError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = OffsetDateTime::parse(s, &Rfc3339)
+            .map_err(|_| ExpiryTimestampError::InvalidFormat(truncate_input(s)))?;
+        Self::try_from(value)
+    }
+}
+
+impl Serialize for ExpiryTimestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_i64(self.unix_timestamp())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExpiryTimestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            s.parse().map_err(serde::de::Error::custom)
+        } else {
+            let timestamp = i64::deserialize(deserializer)?;
+            Self::from_unix_timestamp(timestamp).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/*
+* Link lifetimes a payment plan allows. max_lifetime None allows any lifetime up to MAX_EXPIRY_HORIZON,
+* require_expiry refuses links that never expire.
+*/
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ExpiryPolicy {
+    pub max_lifetime: Option<HumanDuration>,
+    pub require_expiry: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum ExpiryPolicyError {
+    #[error("links have to expire")]
+    ExpiryRequired,
+    #[error("lifetime {requested} is longer than the allowed {max}")]
+    LifetimeTooLong { requested: HumanDuration, max: HumanDuration },
+}
+
+impl ExpiryPolicy {
+    // lifetime None is a link that never expires.
+    pub fn check(&self, lifetime: Option<HumanDuration>) -> Result<(), ExpiryPolicyError> {
+        match (lifetime, self.max_lifetime) {
+            (None, _) if self.require_expiry => Err(ExpiryPolicyError::ExpiryRequired),
+            (Some(requested), Some(max)) if requested > max => Err(ExpiryPolicyError::LifetimeTooLong { requested, max }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower_bound() {
+        let now = OffsetDateTime::now_utc();
+        assert!(matches!(
+            ExpiryTimestamp::new_at(datetime!(1969-12-31 23:59:59 UTC), now),
+            Err(ExpiryTimestampError::TooEarly(_))
+        ));
+        assert!(matches!(
+            ExpiryTimestamp::new_at(datetime!(2019-12-31 23:59:59 UTC), now),
+            Err(ExpiryTimestampError::TooEarly(_))
+        ));
+        assert!(ExpiryTimestamp::new_at(MIN_EXPIRY, now).is_ok());
+    }
+
+    #[test]
+    fn upper_bound() {
+        let now = datetime!(2024-06-01 12:00 UTC);
+        assert!(ExpiryTimestamp::new_at(now + MAX_EXPIRY_HORIZON, now).is_ok());
+        assert!(matches!(
+            ExpiryTimestamp::new_at(now + MAX_EXPIRY_HORIZON + Duration::SECOND, now),
+            Err(ExpiryTimestampError::TooFarInFuture(_))
+        ));
+        assert!(matches!(
+            ExpiryTimestamp::from_unix_timestamp(i64::MAX),
+            Err(ExpiryTimestampError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn truncates_to_seconds_and_utc() {
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let value = datetime!(2024-06-02 14:30:15.999_999_999 +02:00);
+        let expiry = ExpiryTimestamp::new_at(value, now).unwrap();
+        assert_eq!(OffsetDateTime::from(expiry), datetime!(2024-06-02 12:30:15 UTC));
+        assert_eq!(expiry.to_string(), "2024-06-02T12:30:15Z");
+    }
+
+    #[test]
+    fn string_and_serde_round_trip() {
+        let expiry: ExpiryTimestamp = "2024-06-02T12:30:15+02:00".parse().unwrap();
+        assert_eq!(expiry.to_string(), "2024-06-02T10:30:15Z");
+        assert_eq!(expiry.to_string().parse::<ExpiryTimestamp>(), Ok(expiry));
+
+        let json = serde_json::to_string(&expiry).unwrap();
+        assert_eq!(json, "\"2024-06-02T10:30:15Z\"");
+        assert_eq!(serde_json::from_str::<ExpiryTimestamp>(&json).unwrap(), expiry);
+        assert!(serde_json::from_str::<ExpiryTimestamp>("\"2019-12-31T23:59:59Z\"").is_err());
+
+        let bytes = bincode::serialize(&expiry).unwrap();
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(bincode::deserialize::<ExpiryTimestamp>(&bytes).unwrap(), expiry);
+
+        assert!(matches!(
+            "tomorrow".parse::<ExpiryTimestamp>(),
+            Err(ExpiryTimestampError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn from_optional() {
+        assert_eq!(ExpiryTimestamp::from_optional(None), Ok(None));
+        assert!(ExpiryTimestamp::from_optional(Some(OffsetDateTime::now_utc())).unwrap().is_some());
+        assert!(ExpiryTimestamp::from_optional(Some(OffsetDateTime::UNIX_EPOCH)).is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_round_trip() {
+        let horizon = (OffsetDateTime::now_utc() + MAX_EXPIRY_HORIZON).unix_timestamp();
+        let timestamps = (MIN_EXPIRY.unix_timestamp()..horizon).step_by(7_919_993).chain([horizon - 60]);
+        for timestamp in timestamps {
+            let expiry = ExpiryTimestamp::from_unix_timestamp(timestamp).unwrap();
+            let utc = chrono::DateTime::<chrono::Utc>::from(expiry);
+            assert_eq!(utc.timestamp(), timestamp);
+            assert_eq!(ExpiryTimestamp::try_from(utc), Ok(expiry));
+            let fixed = chrono::DateTime::<chrono::FixedOffset>::from(expiry);
+            assert_eq!(ExpiryTimestamp::try_from(fixed), Ok(expiry));
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_truncates_to_seconds_and_utc() {
+        let value = chrono::DateTime::parse_from_rfc3339("2024-06-02T14:30:15.999999999+02:00").unwrap();
+        let expiry = ExpiryTimestamp::try_from(value).unwrap();
+        assert_eq!(expiry.to_string(), "2024-06-02T12:30:15Z");
+        assert_eq!(Ok(expiry), "2024-06-02T14:30:15.999999999+02:00".parse());
+        assert_eq!(chrono::DateTime::<chrono::Utc>::from(expiry).to_rfc3339(), "2024-06-02T12:30:15+00:00");
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_range_errors() {
+        let parse = |s| chrono::DateTime::parse_from_rfc3339(s).unwrap();
+        assert!(matches!(
+            ExpiryTimestamp::try_from(parse("1969-12-31T23:59:59Z")),
+            Err(ExpiryTimestampError::TooEarly(_))
+        ));
+        assert!(matches!(
+            ExpiryTimestamp::try_from(parse("2019-12-31T23:59:59.999Z")),
+            Err(ExpiryTimestampError::TooEarly(_))
+        ));
+        assert!(matches!(
+            ExpiryTimestamp::try_from(chrono::DateTime::<chrono::Utc>::MIN_UTC),
+            Err(ExpiryTimestampError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            ExpiryTimestamp::try_from(chrono::DateTime::<chrono::Utc>::MAX_UTC),
+            Err(ExpiryTimestampError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            ExpiryTimestamp::try_from(parse("2500-01-01T00:00:00Z")),
+            Err(ExpiryTimestampError::TooFarInFuture(_))
+        ));
+    }
+
+    // chrono keeps a leap second as 23:59:59 with more than a second of nanoseconds, truncation drops it.
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_leap_second() {
+        let leap = chrono::DateTime::parse_from_rfc3339("2030-06-30T23:59:60.5Z").unwrap();
+        let expiry = ExpiryTimestamp::try_from(leap).unwrap();
+        assert_eq!(expiry.to_string(), "2030-06-30T23:59:59Z");
+        assert_eq!(Ok(expiry), "2030-06-30T23:59:60Z".parse());
+        let next = chrono::DateTime::parse_from_rfc3339("2030-07-01T00:00:00Z").unwrap();
+        assert_eq!(ExpiryTimestamp::try_from(next).unwrap().unix_timestamp(), expiry.unix_timestamp() + 1);
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn archive_round_trip_checks_range() {
+        use rkyv::Deserialize;
+
+        let expiry = ExpiryTimestamp::from_unix_timestamp(1_717_200_000).unwrap();
+        let bytes = rkyv::to_bytes::<_, 16>(&expiry).unwrap();
+        assert_eq!(bytes.as_slice(), 1_717_200_000i64.to_le_bytes());
+        let archived = rkyv::check_archived_root::<ExpiryTimestamp>(&bytes).unwrap();
+        assert_eq!(archived.unix_timestamp(), 1_717_200_000);
+        assert_eq!(archived.get(), expiry);
+        assert_eq!(archived.deserialize(&mut rkyv::Infallible), Ok(expiry));
+        assert!(rkyv::check_archived_root::<ExpiryTimestamp>(&bytes[..7]).is_err());
+
+        // Bytes of a plain i64 outside the ExpiryTimestamp range.
+        for timestamp in [0, i64::MAX] {
+            let bytes = rkyv::to_bytes::<_, 16>(&timestamp).unwrap();
+            assert!(rkyv::check_archived_root::<ExpiryTimestamp>(&bytes).is_err(), "{}", timestamp);
+        }
+    }
+
+    #[test]
+    fn expiry_policy() {
+        let week: HumanDuration = "7d".parse().unwrap();
+        let policy = ExpiryPolicy {
+            max_lifetime: Some(week),
+            require_expiry: true,
+        };
+        assert_eq!(policy.check(Some(week)), Ok(()));
+        assert_eq!(policy.check(Some("1h".parse().unwrap())), Ok(()));
+        assert_eq!(
+            policy.check(Some("7d1s".parse().unwrap())),
+            Err(ExpiryPolicyError::LifetimeTooLong {
+                requested: "7d1s".parse().unwrap(),
+                max: week
+            })
+        );
+        assert_eq!(policy.check(None), Err(ExpiryPolicyError::ExpiryRequired));
+        assert_eq!(ExpiryPolicy::default().check(None), Ok(()));
+        assert_eq!(ExpiryPolicy::default().check(Some(week)), Ok(()));
+    }
+}
@@ -0,0 +1,324 @@
+#![cfg(feature = "ffi")]
+
+use std::collections::BTreeMap;
+use std::ffi::{c_char, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use crate::secret_share_link::{
+    SecretShareLink, SecretShareLinkForm, SecretShareLinkParsingError, SecretShareLinkVerifySignatureError,
+};
+
+/*
+* C API to verify secret share links, declared in include/bucket_common_types.h (regenerate with cbindgen).
+* Link it as a static library: cargo rustc --lib --release --features ffi --crate-type staticlib.
+* A BdctLink is an opaque handle, never a pointer to the link. Handles index a registry and are never reused, so a
+* handle used after bdct_link_free, or freed twice, gives BDCT_STATUS_INVALID_HANDLE instead of touching freed memory.
+* The bucket key stays in the registry, no function hands it out.
+*/
+pub struct BdctLink {
+    _private: [u8; 0],
+}
+
+/*
+* Result of every bdct_ function. Values are stable, new ones are only appended to a range.
+* 1-15 misuse of the API, 16-31 the URL is not a secret share link, 32-47 the link does not verify.
+*/
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BdctStatus {
+    Ok = 0,
+    NullArgument = 1,
+    InvalidUtf8 = 2,
+    InvalidHandle = 3,
+    Panic = 4,
+    InvalidArgument = 5,
+
+    InvalidUrl = 16,
+    InvalidScheme = 17,
+    InvalidHost = 18,
+    InvalidPath = 19,
+    InvalidFragment = 20,
+    InvalidBucketKey = 21,
+    InvalidPermission = 22,
+    InvalidTime = 23,
+    InvalidPrefix = 24,
+    InvalidChannel = 25,
+    InvalidByteCap = 26,
+    InvalidSignatureEncoding = 27,
+    // The link lost characters or has a typo, see SecretShareLinkParsingError::ChecksumMismatch.
+    ChecksumMismatch = 28,
+
+    InvalidSignature = 32,
+    NotYetValid = 33,
+    Expired = 34,
+    MissingExpiry = 35,
+    UnknownIssueTime = 36,
+    LinkTooOld = 37,
+}
+
+impl From<&SecretShareLinkParsingError> for BdctStatus {
+    fn from(value: &SecretShareLinkParsingError) -> Self {
+        type Error = SecretShareLinkParsingError;
+        match value {
+            Error::UrlParse(_) => BdctStatus::InvalidUrl,
+            Error::InvalidScheme => BdctStatus::InvalidScheme,
+            Error::InvalidHostDomain => BdctStatus::InvalidHost,
+            Error::InvalidVersionFormat | Error::InvalidPath | Error::Uuid(_) => BdctStatus::InvalidPath,
+            Error::InvalidFragment
+            | Error::InvalidField(_)
+            | Error::DuplicateParameter(_)
+            | Error::MissingParameter(_)
+            | Error::Base64Decoding(_)
+            | Error::Utf8Error(_) => BdctStatus::InvalidFragment,
+            Error::InvalidBucketKey => BdctStatus::InvalidBucketKey,
+            Error::InvalidPermission => BdctStatus::InvalidPermission,
+            Error::InvalidExpiry | Error::InvalidNotBefore | Error::InvalidIssuedAt => BdctStatus::InvalidTime,
+            Error::InvalidPrefix(_) | Error::TooManyPrefixes(_) => BdctStatus::InvalidPrefix,
+            Error::InvalidChannel | Error::UnknownChannel(_) => BdctStatus::InvalidChannel,
+            Error::InvalidMaxTotalBytes => BdctStatus::InvalidByteCap,
+            Error::Signature(_) => BdctStatus::InvalidSignatureEncoding,
+            Error::ChecksumMismatch { .. } => BdctStatus::ChecksumMismatch,
+        }
+    }
+}
+
+impl From<&SecretShareLinkVerifySignatureError> for BdctStatus {
+    fn from(value: &SecretShareLinkVerifySignatureError) -> Self {
+        type Error = SecretShareLinkVerifySignatureError;
+        match value {
+            Error::InvalidSignature(_) => BdctStatus::InvalidSignature,
+            Error::NotYetValid { .. } => BdctStatus::NotYetValid,
+            Error::Expired { .. } => BdctStatus::Expired,
+            Error::MissingExpiry => BdctStatus::MissingExpiry,
+            Error::UnknownIssueTime => BdctStatus::UnknownIssueTime,
+            Error::LinkTooOld { .. } => BdctStatus::LinkTooOld,
+        }
+    }
+}
+
+static LINKS: Mutex<BTreeMap<usize, SecretShareLink>> = Mutex::new(BTreeMap::new());
+// 0 is never a handle, it would be NULL.
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(1);
+
+// A panic must not unwind into C, it becomes BDCT_STATUS_PANIC.
+fn guarded(f: impl FnOnce() -> Result<(), BdctStatus>) -> BdctStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => BdctStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => BdctStatus::Panic,
+    }
+}
+
+// A panic while the registry was locked leaves it consistent, every change to it is a single insert or remove.
+fn with_link<T>(link: *const BdctLink, f: impl FnOnce(&SecretShareLink) -> T) -> Result<T, BdctStatus> {
+    if link.is_null() {
+        return Err(BdctStatus::NullArgument);
+    }
+    let links = LINKS.lock().unwrap_or_else(PoisonError::into_inner);
+    links.get(&link.addr()).map(f).ok_or(BdctStatus::InvalidHandle)
+}
+
+fn parse(url: &str) -> Result<SecretShareLink, SecretShareLinkParsingError> {
+    let url = url::Url::parse(url)?;
+    match SecretShareLinkForm::of(&url) {
+        SecretShareLinkForm::Fragment => SecretShareLink::try_from(url),
+        SecretShareLinkForm::Query => SecretShareLink::from_query_url(&url),
+    }
+}
+
+/// Parses a secret share link in either URL form. On success `*out` is a handle to free with `bdct_link_free`,
+/// on failure it is NULL.
+///
+/// # Safety
+/// `url` must be NULL or a NUL-terminated string, `out` must be NULL or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn bdct_parse_secret_link(url: *const c_char, out: *mut *mut BdctLink) -> BdctStatus {
+    guarded(|| {
+        if out.is_null() {
+            return Err(BdctStatus::NullArgument);
+        }
+        *out = std::ptr::null_mut();
+        if url.is_null() {
+            return Err(BdctStatus::NullArgument);
+        }
+        let url = CStr::from_ptr(url).to_str().map_err(|_| BdctStatus::InvalidUtf8)?;
+        let link = parse(url).map_err(|error| BdctStatus::from(&error))?;
+        let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        LINKS.lock().unwrap_or_else(PoisonError::into_inner).insert(handle, link);
+        *out = std::ptr::without_provenance_mut(handle);
+        Ok(())
+    })
+}
+
+/// Checks the signature against the 32 byte Ed25519 public key and that `now_unix` is inside the link's
+/// not before and expiry window.
+///
+/// # Safety
+/// `public_key` must be NULL or valid for 32 byte reads.
+#[no_mangle]
+pub unsafe extern "C" fn bdct_verify_link(
+    link: *const BdctLink,
+    public_key: *const u8,
+    now_unix: i64,
+) -> BdctStatus {
+    guarded(|| {
+        if public_key.is_null() {
+            return Err(BdctStatus::NullArgument);
+        }
+        let public_key = ed25519_compact::PublicKey::new(*public_key.cast::<[u8; 32]>());
+        let now = time::OffsetDateTime::from_unix_timestamp(now_unix).map_err(|_| BdctStatus::InvalidArgument)?;
+        with_link(link, |link| link.verify(public_key, now))?.map_err(|error| BdctStatus::from(&error))
+    })
+}
+
+/// Writes the 32 byte token the server knows the link by to `out`.
+///
+/// # Safety
+/// `out` must be NULL or valid for 32 byte writes.
+#[no_mangle]
+pub unsafe extern "C" fn bdct_link_token(link: *const BdctLink, out: *mut u8) -> BdctStatus {
+    guarded(|| {
+        if out.is_null() {
+            return Err(BdctStatus::NullArgument);
+        }
+        let token = with_link(link, SecretShareLink::get_token)?;
+        *out.cast::<[u8; 32]>() = token;
+        Ok(())
+    })
+}
+
+/// Frees a handle from `bdct_parse_secret_link`. NULL is ignored like in `free`, a handle that was already freed
+/// gives `BDCT_STATUS_INVALID_HANDLE`.
+#[no_mangle]
+pub extern "C" fn bdct_link_free(link: *mut BdctLink) -> BdctStatus {
+    guarded(|| {
+        if link.is_null() {
+            return Ok(());
+        }
+        let mut links = LINKS.lock().unwrap_or_else(PoisonError::into_inner);
+        links.remove(&link.addr()).map(drop).ok_or(BdctStatus::InvalidHandle)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::expiry::ExpiryTimestamp;
+    use crate::share_link::BucketSharePermissionFlags;
+
+    const NOW: i64 = 1_717_200_000;
+
+    fn key_pair() -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]))
+    }
+
+    fn link() -> SecretShareLink {
+        let at = |timestamp| ExpiryTimestamp::from_unix_timestamp(timestamp).unwrap();
+        SecretShareLink::builder(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<aes_gcm::Aes256Gcm>::from_slice(&[7u8; 32]),
+        )
+        .permission(BucketSharePermissionFlags::READ)
+        .not_before(at(NOW - 60))
+        .expires(at(NOW + 60))
+        .build_with_clock(&key_pair().sk, &crate::clock::FixedClock(OffsetDateTime::from_unix_timestamp(NOW).unwrap()))
+        .unwrap()
+    }
+
+    fn parse_url(url: &str) -> (BdctStatus, *mut BdctLink) {
+        let url = CString::new(url).unwrap();
+        let mut out = std::ptr::without_provenance_mut(1);
+        let status = unsafe { bdct_parse_secret_link(url.as_ptr(), &mut out) };
+        (status, out)
+    }
+
+    #[test]
+    fn parse_verify_and_free() {
+        let link = link();
+        let public_key = *key_pair().pk;
+        for url in [link.to_string(), link.to_query_url(&Default::default()).unwrap().to_string()] {
+            let (status, handle) = parse_url(&url);
+            assert_eq!(status, BdctStatus::Ok);
+            assert!(!handle.is_null());
+
+            assert_eq!(unsafe { bdct_verify_link(handle, public_key.as_ptr(), NOW) }, BdctStatus::Ok);
+            let mut token = [0; 32];
+            assert_eq!(unsafe { bdct_link_token(handle, token.as_mut_ptr()) }, BdctStatus::Ok);
+            assert_eq!(token, link.get_token());
+
+            assert_eq!(bdct_link_free(handle), BdctStatus::Ok);
+            // The handle is dead, not dangling.
+            assert_eq!(bdct_link_free(handle), BdctStatus::InvalidHandle);
+            assert_eq!(unsafe { bdct_verify_link(handle, public_key.as_ptr(), NOW) }, BdctStatus::InvalidHandle);
+            assert_eq!(unsafe { bdct_link_token(handle, token.as_mut_ptr()) }, BdctStatus::InvalidHandle);
+        }
+        assert_eq!(bdct_link_free(std::ptr::null_mut()), BdctStatus::Ok);
+        assert_eq!(bdct_link_free(std::ptr::without_provenance_mut(usize::MAX)), BdctStatus::InvalidHandle);
+    }
+
+    #[test]
+    fn verify_errors() {
+        let (status, handle) = parse_url(&link().to_string());
+        assert_eq!(status, BdctStatus::Ok);
+        let public_key = *key_pair().pk;
+        let verify = |public_key: &[u8; 32], now| unsafe { bdct_verify_link(handle, public_key.as_ptr(), now) };
+
+        assert_eq!(verify(&public_key, NOW - 61), BdctStatus::NotYetValid);
+        assert_eq!(verify(&public_key, NOW + 60), BdctStatus::Expired);
+        assert_eq!(verify(&[9; 32], NOW), BdctStatus::InvalidSignature);
+        assert_eq!(verify(&public_key, i64::MAX), BdctStatus::InvalidArgument);
+        assert_eq!(unsafe { bdct_verify_link(handle, std::ptr::null(), NOW) }, BdctStatus::NullArgument);
+        assert_eq!(
+            unsafe { bdct_verify_link(std::ptr::null(), public_key.as_ptr(), NOW) },
+            BdctStatus::NullArgument
+        );
+        assert_eq!(unsafe { bdct_link_token(handle, std::ptr::null_mut()) }, BdctStatus::NullArgument);
+        assert_eq!(bdct_link_free(handle), BdctStatus::Ok);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let url = link().to_string();
+        let table = [
+            ("not a url", BdctStatus::InvalidUrl),
+            ("http://bucketdrive.co/api/v1/share/a/b#c", BdctStatus::InvalidScheme),
+            ("https://example.com/api/v1/share/a/b#c", BdctStatus::InvalidHost),
+            ("https://bucketdrive.co/api/v1/share/a/b#c", BdctStatus::InvalidPath),
+        ];
+        for (url, expected) in table {
+            assert_eq!(parse_url(url), (expected, std::ptr::null_mut()), "{}", url);
+        }
+        let (user_and_bucket, fragment) = url.split_once('#').unwrap();
+        let (_, rest) = fragment.split_once('#').unwrap();
+        let short_key = format!("{}#AAAA#{}", user_and_bucket, rest);
+        assert_eq!(parse_url(&short_key).0, BdctStatus::InvalidBucketKey);
+        assert_eq!(parse_url(user_and_bucket).0, BdctStatus::InvalidFragment);
+        assert_eq!(parse_url(&url[..url.len() - 5]).0, BdctStatus::ChecksumMismatch);
+
+        let mut out = std::ptr::without_provenance_mut(1);
+        assert_eq!(unsafe { bdct_parse_secret_link(std::ptr::null(), &mut out) }, BdctStatus::NullArgument);
+        assert!(out.is_null());
+        let url = CString::new(url).unwrap();
+        let status = unsafe { bdct_parse_secret_link(url.as_ptr(), std::ptr::null_mut()) };
+        assert_eq!(status, BdctStatus::NullArgument);
+        let invalid_utf8 = CString::new(vec![b'h', 0xff]).unwrap();
+        assert_eq!(
+            unsafe { bdct_parse_secret_link(invalid_utf8.as_ptr(), &mut out) },
+            BdctStatus::InvalidUtf8
+        );
+    }
+
+    #[test]
+    fn panics_become_a_status() {
+        assert_eq!(guarded(|| panic!("boom")), BdctStatus::Panic);
+        assert_eq!(guarded(|| Err(BdctStatus::InvalidHandle)), BdctStatus::InvalidHandle);
+        assert_eq!(guarded(|| Ok(())), BdctStatus::Ok);
+    }
+}
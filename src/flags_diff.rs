@@ -0,0 +1,152 @@
+use std::fmt;
+
+use bitflags::parser::WriteHex;
+use bitflags::Flags;
+use serde::{Deserialize, Serialize};
+
+use crate::{BucketFeaturesFlags, Verification};
+
+/*
+* What changed between two flag sets, for the audit log.
+* Displayed as the added then the removed flags in bit order, e.g. "+WRITE +DELETE_FILE -SHARE_BUCKET".
+* Bits without a name are written in hex, e.g. "+0x100". An empty diff displays as "no change".
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FlagsDiff<F> {
+    pub added: F,
+    pub removed: F,
+    pub unchanged: F,
+}
+
+#[cfg(feature = "share_link")]
+pub type PermissionDiff = FlagsDiff<crate::share_link::BucketSharePermissionFlags>;
+
+impl<F: Flags + Copy> FlagsDiff<F> {
+    pub fn between(old: F, new: F) -> Self {
+        Self {
+            added: new.difference(old),
+            removed: old.difference(new),
+            unchanged: old.intersection(new),
+        }
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    // Any added flag counts, even when others are removed at the same time.
+    pub fn is_escalation(&self) -> bool {
+        !self.added.is_empty()
+    }
+}
+
+impl<F: Flags + Copy> fmt::Display for FlagsDiff<F>
+where
+    F::Bits: WriteHex,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_noop() {
+            return f.write_str("no change");
+        }
+        let mut separator = "";
+        for (sign, flags) in [('+', self.added), ('-', self.removed)] {
+            for flag in flags.iter() {
+                write!(f, "{}{}", separator, sign)?;
+                bitflags::parser::to_writer(&flag, &mut *f)?;
+                separator = " ";
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "share_link")]
+impl crate::share_link::BucketSharePermissionFlags {
+    pub fn diff(&self, new: Self) -> PermissionDiff {
+        FlagsDiff::between(*self, new)
+    }
+}
+
+impl BucketFeaturesFlags {
+    pub fn diff(&self, new: Self) -> FlagsDiff<Self> {
+        FlagsDiff::between(*self, new)
+    }
+}
+
+impl Verification {
+    pub fn diff(&self, new: Self) -> FlagsDiff<Self> {
+        FlagsDiff::between(*self, new)
+    }
+}
+
+#[cfg(all(test, feature = "share_link"))]
+mod tests {
+    use super::*;
+    use crate::share_link::BucketSharePermissionFlags as P;
+
+    #[test]
+    fn noop() {
+        let diff = (P::READ | P::WRITE).diff(P::READ | P::WRITE);
+        assert!(diff.is_noop());
+        assert!(!diff.is_escalation());
+        assert_eq!(diff.unchanged, P::READ | P::WRITE);
+        assert_eq!(diff.to_string(), "no change");
+        assert!(P::empty().diff(P::empty()).is_noop());
+    }
+
+    #[test]
+    fn pure_addition() {
+        let diff = P::READ.diff(P::READ | P::WRITE | P::DELETE_FILE);
+        assert_eq!(diff.added, P::WRITE | P::DELETE_FILE);
+        assert_eq!(diff.removed, P::empty());
+        assert_eq!(diff.unchanged, P::READ);
+        assert!(diff.is_escalation());
+        assert_eq!(diff.to_string(), "+WRITE +DELETE_FILE");
+    }
+
+    #[test]
+    fn pure_removal() {
+        let diff = (P::VIEW | P::READ | P::SHARE_BUCKET).diff(P::VIEW);
+        assert_eq!(diff.added, P::empty());
+        assert_eq!(diff.removed, P::READ | P::SHARE_BUCKET);
+        assert!(!diff.is_noop());
+        assert!(!diff.is_escalation());
+        assert_eq!(diff.to_string(), "-READ -SHARE_BUCKET");
+    }
+
+    #[test]
+    fn mixed() {
+        let diff = (P::READ | P::SHARE_BUCKET).diff(P::READ | P::WRITE | P::DELETE_FILE);
+        assert_eq!(
+            diff,
+            FlagsDiff {
+                added: P::WRITE | P::DELETE_FILE,
+                removed: P::SHARE_BUCKET,
+                unchanged: P::READ,
+            }
+        );
+        assert!(diff.is_escalation());
+        let json = serde_json::to_string(&diff).unwrap();
+        assert_eq!(json, r#"{"added":"WRITE | DELETE_FILE","removed":"SHARE_BUCKET","unchanged":"READ"}"#);
+        assert_eq!(serde_json::from_str::<PermissionDiff>(&json).unwrap(), diff);
+    }
+
+    #[test]
+    fn display_format_is_stable() {
+        let cases = [
+            (P::all(), P::empty(), "-VIEW -READ -WRITE -DELETE_FILE -DELETE_BUCKET -SHARE_BUCKET -CLONE -SEARCH"),
+            (P::SEARCH, P::VIEW | P::CLONE, "+VIEW +CLONE -SEARCH"),
+            (P::READ, P::READ | P::from_bits_retain(1 << 8), "+0x100"),
+        ];
+        for (old, new, expected) in cases {
+            assert_eq!(old.diff(new).to_string(), expected);
+        }
+
+        let diff = BucketFeaturesFlags::IS_SEARCHABLE.diff(BucketFeaturesFlags::IS_SHARABLE);
+        assert_eq!(diff.to_string(), "+IS_SHARABLE -IS_SEARCHABLE");
+        let diff = Verification::EMAIL.diff(Verification::EMAIL | Verification::TOTP);
+        assert_eq!(diff.to_string(), "+TOTP");
+        assert_eq!(diff.unchanged, Verification::EMAIL);
+        assert!(Verification::UNVERIFIED.diff(Verification::UNVERIFIED).is_noop());
+    }
+}
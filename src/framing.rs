@@ -0,0 +1,261 @@
+use std::fmt;
+
+pub const FRAME_MAGIC_LEN: usize = 4;
+// Magic followed by the format version.
+pub const FRAME_HEADER_LEN: usize = FRAME_MAGIC_LEN + 1;
+
+/*
+* Binary encodings of the crate, each starts with its 4 byte magic and a 1 byte version, so bytes of one format fed to
+* the decoder of another are rejected up front instead of failing somewhere in the body.
+* Magics are append-only, a retired format keeps its magic so it is never read as something else.
+* Signed bytes that are never decoded, the canonical bytes of links and bucket transfers, are not framed: framing them
+* would invalidate every signature issued so far.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumIter)]
+pub enum BinaryFormat {
+    #[strum(serialize = "revocation list")]
+    RevocationList,
+    #[strum(serialize = "revocation bloom")]
+    RevocationBloom,
+    #[strum(serialize = "version vector")]
+    VersionVector,
+    #[strum(serialize = "keyring")]
+    Keyring,
+    #[strum(serialize = "manifest delta")]
+    ManifestDelta,
+    #[strum(serialize = "upload session state")]
+    UploadSessionState,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum FramingError {
+    #[error("input is {0} bytes, shorter than the {FRAME_HEADER_LEN} byte format header")]
+    Truncated(usize),
+    #[error("unknown binary format magic {}", Magic(.0))]
+    UnknownMagic([u8; FRAME_MAGIC_LEN]),
+    #[error("expected a {expected} but found a {found}")]
+    WrongFormat {
+        expected: BinaryFormat,
+        found: BinaryFormat,
+    },
+}
+
+// Printable magics as text, anything else as hex.
+struct Magic<'a>(&'a [u8; FRAME_MAGIC_LEN]);
+
+impl fmt::Display for Magic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.iter().all(u8::is_ascii_graphic) {
+            true => write!(f, "\"{}\"", String::from_utf8_lossy(self.0)),
+            false => write!(
+                f,
+                "0x{}",
+                self.0.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()
+            ),
+        }
+    }
+}
+
+impl BinaryFormat {
+    pub const fn magic(self) -> [u8; FRAME_MAGIC_LEN] {
+        match self {
+            BinaryFormat::RevocationList => *b"BDRL",
+            BinaryFormat::RevocationBloom => *b"BDRB",
+            BinaryFormat::VersionVector => *b"BDVV",
+            BinaryFormat::Keyring => *b"BDKR",
+            BinaryFormat::ManifestDelta => *b"BDMD",
+            BinaryFormat::UploadSessionState => *b"BDUS",
+        }
+    }
+
+    pub fn from_magic(magic: [u8; FRAME_MAGIC_LEN]) -> Option<Self> {
+        <Self as strum::IntoEnumIterator>::iter().find(|format| format.magic() == magic)
+    }
+
+    pub const fn header(self, version: u8) -> [u8; FRAME_HEADER_LEN] {
+        let [a, b, c, d] = self.magic();
+        [a, b, c, d, version]
+    }
+}
+
+pub fn write_header(format: BinaryFormat, version: u8, out: &mut Vec<u8>) {
+    out.extend_from_slice(&format.header(version));
+}
+
+// Format and version of framed input, the body starts at FRAME_HEADER_LEN.
+pub fn read_header(input: &[u8]) -> Result<(BinaryFormat, u8), FramingError> {
+    let header = input
+        .get(..FRAME_HEADER_LEN)
+        .ok_or(FramingError::Truncated(input.len()))?;
+    let magic = header[..FRAME_MAGIC_LEN].try_into().unwrap();
+    let format = BinaryFormat::from_magic(magic).ok_or(FramingError::UnknownMagic(magic))?;
+    Ok((format, header[FRAME_MAGIC_LEN]))
+}
+
+// read_header for a decoder of one format, returns the version and the body.
+pub fn expect_header(input: &[u8], expected: BinaryFormat) -> Result<(u8, &[u8]), FramingError> {
+    match read_header(input)? {
+        (found, version) if found == expected => Ok((version, &input[FRAME_HEADER_LEN..])),
+        (found, _) => Err(FramingError::WrongFormat { expected, found }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn golden_headers() {
+        let headers = [
+            (BinaryFormat::RevocationList, b"BDRL\x01"),
+            (BinaryFormat::RevocationBloom, b"BDRB\x01"),
+            (BinaryFormat::VersionVector, b"BDVV\x01"),
+            (BinaryFormat::Keyring, b"BDKR\x01"),
+            (BinaryFormat::ManifestDelta, b"BDMD\x01"),
+            (BinaryFormat::UploadSessionState, b"BDUS\x01"),
+        ];
+        assert_eq!(headers.len(), BinaryFormat::iter().count());
+        for (format, header) in headers {
+            assert_eq!(&format.header(1), header, "{}", format);
+            let mut out = vec![0xff];
+            write_header(format, 1, &mut out);
+            assert_eq!(&out[1..], header);
+            assert_eq!(read_header(header), Ok((format, 1)));
+            assert_eq!(expect_header(header, format), Ok((1, &[][..])));
+        }
+    }
+
+    #[test]
+    fn header_errors() {
+        assert_eq!(read_header(b"BDRL"), Err(FramingError::Truncated(4)));
+        assert_eq!(read_header(b"BDXX\x01"), Err(FramingError::UnknownMagic(*b"BDXX")));
+        assert_eq!(
+            expect_header(b"BDRB\x01rest", BinaryFormat::RevocationList),
+            Err(FramingError::WrongFormat {
+                expected: BinaryFormat::RevocationList,
+                found: BinaryFormat::RevocationBloom,
+            })
+        );
+        assert_eq!(
+            FramingError::UnknownMagic([1, 0, 0, 0]).to_string(),
+            "unknown binary format magic 0x01000000"
+        );
+        assert_eq!(
+            FramingError::WrongFormat {
+                expected: BinaryFormat::Keyring,
+                found: BinaryFormat::VersionVector,
+            }
+            .to_string(),
+            "expected a keyring but found a version vector"
+        );
+    }
+
+    // Bytes of every format fed to every decoder, only the matching decoder may accept them.
+    #[cfg(feature = "secret_share_link")]
+    #[test]
+    fn cross_feeding() {
+        use crate::keyring::{Keyring, KeyringEntry, KeyringError};
+        use crate::manifest::{BucketManifest, ManifestDelta, ManifestDeltaError};
+        use crate::multipart_upload::MultipartUpload;
+        use crate::revocation::{RevocationBloom, RevocationBloomError, RevocationList, RevocationListError};
+        use crate::share_link::ShareLinkToken;
+        use crate::upload_session::{SessionStateError, UploadSessionState};
+        use crate::version_vector::{VersionVector, VersionVectorError};
+
+        let mut revocation_list = Vec::new();
+        RevocationList::new(1, vec![ShareLinkToken([1; 32])])
+            .write_to(&mut revocation_list)
+            .unwrap();
+        let keyring = Keyring::new(vec![KeyringEntry {
+            key_id: "links-2024".parse().unwrap(),
+            public_key: [3; 32],
+            valid_from: crate::expiry::ExpiryTimestamp::from_unix_timestamp(1_717_200_000).unwrap(),
+            valid_until: None,
+        }])
+        .unwrap();
+        let mut vector = VersionVector::new();
+        vector.observe(1, 42);
+        let encoded = [
+            (BinaryFormat::RevocationList, revocation_list),
+            (
+                BinaryFormat::RevocationBloom,
+                RevocationBloom::from_tokens([ShareLinkToken([1; 32])], 1, 0.01)
+                    .unwrap()
+                    .to_bytes(),
+            ),
+            (BinaryFormat::VersionVector, vector.to_bytes()),
+            (BinaryFormat::Keyring, keyring.canonical_bytes()),
+            (
+                BinaryFormat::ManifestDelta,
+                BucketManifest::new().delta_from(&BucketManifest::new()).to_bytes(),
+            ),
+            (
+                BinaryFormat::UploadSessionState,
+                UploadSessionState::new(
+                    MultipartUpload {
+                        upload_id: uuid::Uuid::nil(),
+                        bucket: crate::bucket_guid::BucketGuid::new(uuid::Uuid::nil(), uuid::Uuid::nil()),
+                        key: "/a".parse().unwrap(),
+                        part_size: 1,
+                        total_size: None,
+                    },
+                    crate::expiry::ExpiryTimestamp::from_unix_timestamp(1_717_200_000).unwrap(),
+                    crate::expiry::ExpiryTimestamp::from_unix_timestamp(1_717_286_400).unwrap(),
+                )
+                .to_bytes(),
+            ),
+        ];
+        assert_eq!(encoded.len(), BinaryFormat::iter().count());
+
+        // The framing error of a decoder, None if it accepted the bytes.
+        let decode = |format, bytes: &[u8]| match format {
+            BinaryFormat::RevocationList => match RevocationList::read_from(bytes) {
+                Ok(_) => None,
+                Err(RevocationListError::Framing(error)) => Some(error),
+                Err(error) => panic!("{}", error),
+            },
+            BinaryFormat::RevocationBloom => match RevocationBloom::from_bytes(bytes) {
+                Ok(_) => None,
+                Err(RevocationBloomError::Framing(error)) => Some(error),
+                Err(error) => panic!("{}", error),
+            },
+            BinaryFormat::VersionVector => match VersionVector::from_bytes(bytes) {
+                Ok(_) => None,
+                Err(VersionVectorError::Framing(error)) => Some(error),
+                Err(error) => panic!("{}", error),
+            },
+            BinaryFormat::Keyring => match Keyring::from_canonical_bytes(bytes) {
+                Ok(_) => None,
+                Err(KeyringError::Framing(error)) => Some(error),
+                Err(error) => panic!("{}", error),
+            },
+            BinaryFormat::ManifestDelta => match ManifestDelta::from_bytes(bytes) {
+                Ok(_) => None,
+                Err(ManifestDeltaError::Framing(error)) => Some(error),
+                Err(error) => panic!("{}", error),
+            },
+            BinaryFormat::UploadSessionState => match UploadSessionState::from_bytes(bytes) {
+                Ok(_) => None,
+                Err(SessionStateError::Framing(error)) => Some(error),
+                Err(error) => panic!("{}", error),
+            },
+        };
+        for (found, bytes) in &encoded {
+            for expected in BinaryFormat::iter() {
+                let error = decode(expected, bytes);
+                match expected == *found {
+                    true => assert_eq!(error, None, "{}", expected),
+                    false => assert_eq!(
+                        error,
+                        Some(FramingError::WrongFormat {
+                            expected,
+                            found: *found
+                        })
+                    ),
+                }
+            }
+        }
+    }
+}
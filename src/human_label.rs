@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+/*
+* Language of text shown to users, e.g. tooltips in the web UI.
+* Only what the crate itself phrases is localized, errors and logs stay English.
+*/
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Eq,
+    PartialEq,
+    Hash,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Sv,
+}
+
+// Text for users in their language, the same value and locale always give the same text.
+pub trait HumanLabel {
+    fn human_label(&self, locale: Locale) -> String;
+}
+
+impl Locale {
+    fn and(self) -> &'static str {
+        match self {
+            Locale::En => "and",
+            Locale::De => "und",
+            Locale::Sv => "och",
+        }
+    }
+
+    // "a", "a and b", "a, b and c". None of the supported locales uses a serial comma.
+    pub fn join_list<S: AsRef<str>>(self, items: &[S]) -> String {
+        match items {
+            [] => String::new(),
+            [only] => only.as_ref().to_string(),
+            [rest @ .., last] => {
+                let rest = rest.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(", ");
+                format!("{} {} {}", rest, self.and(), last.as_ref())
+            }
+        }
+    }
+}
+
+// "1 file", "3 files". Forms are (singular, plural), the count is never localized beyond that.
+#[cfg_attr(not(feature = "secret_share_link"), allow(dead_code))]
+pub(crate) fn count_of(count: u64, forms: (&str, &str)) -> String {
+    match count {
+        1 => format!("1 {}", forms.0),
+        count => format!("{} {}", count, forms.1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists() {
+        assert_eq!(Locale::En.join_list::<&str>(&[]), "");
+        assert_eq!(Locale::En.join_list(&["view"]), "view");
+        assert_eq!(Locale::En.join_list(&["view", "download"]), "view and download");
+        assert_eq!(Locale::De.join_list(&["a", "b", "c"]), "a, b und c");
+        assert_eq!(Locale::Sv.join_list(&["a".to_string(), "b".to_string()]), "a och b");
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(count_of(1, ("file", "files")), "1 file");
+        assert_eq!(count_of(0, ("file", "files")), "0 files");
+        assert_eq!(count_of(12, ("Datei", "Dateien")), "12 Dateien");
+    }
+
+    #[test]
+    fn locale_forms() {
+        assert_eq!(Locale::default(), Locale::En);
+        assert_eq!("sv".parse::<Locale>(), Ok(Locale::Sv));
+        assert_eq!(Locale::De.to_string(), "de");
+        assert_eq!(serde_json::to_string(&Locale::Sv).unwrap(), "\"sv\"");
+    }
+}
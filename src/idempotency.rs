@@ -0,0 +1,211 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Sha3_256};
+
+use crate::errors::{char_index_span, ParseFailure, SpannedParseError};
+use crate::util::truncate_input;
+
+pub const MIN_IDEMPOTENCY_KEY_LEN: usize = 16;
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 64;
+#[cfg(feature = "http")]
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/*
+* Client supplied key that makes a mutating request safe to retry, e.g. creating a bucket or a share link.
+* 16 to 64 characters of [A-Za-z0-9_-], a hyphenated UUID fits.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct IdempotencyKey(String);
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum IdempotencyKeyError {
+    #[error("idempotency key is {len} characters long, expected {MIN_IDEMPOTENCY_KEY_LEN}..={MAX_IDEMPOTENCY_KEY_LEN}")]
+    InvalidLength { len: usize },
+    #[error("invalid character {char:?} at index {index} in idempotency key \"{key}\"")]
+    InvalidCharacter { key: String, index: usize, char: char },
+    #[error("idempotency key header is not valid ASCII")]
+    InvalidHeaderValue,
+    #[error("idempotency key header is given more than once")]
+    DuplicateHeader,
+}
+
+impl SpannedParseError for IdempotencyKeyError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            IdempotencyKeyError::InvalidCharacter { key, index, char } => {
+                ParseFailure::spanned(self, key, char_index_span(key, *index, *char))
+            }
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl IdempotencyKey {
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /*
+    * Fingerprint a server stores with the key, a retry with the same key but another fingerprint is a client bug.
+    * SHA3-256 over the key length as one byte, the key and the canonical request body.
+    * The length prefix keeps key and body apart, otherwise a key could absorb the start of the body.
+    */
+    pub fn fingerprint_request(&self, canonical_body: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        // At most MAX_IDEMPOTENCY_KEY_LEN, always fits.
+        hasher.update([self.0.len() as u8]);
+        hasher.update(self.0.as_bytes());
+        hasher.update(canonical_body);
+        hasher.finalize().into()
+    }
+
+    // None when the header is missing, an error when it is invalid or repeated.
+    #[cfg(feature = "http")]
+    pub fn from_headers(headers: &http::HeaderMap) -> Result<Option<Self>, IdempotencyKeyError> {
+        let mut values = headers.get_all(IDEMPOTENCY_KEY_HEADER).iter();
+        let Some(value) = values.next() else {
+            return Ok(None);
+        };
+        if values.next().is_some() {
+            return Err(IdempotencyKeyError::DuplicateHeader);
+        }
+        let value = value.to_str().map_err(|_| IdempotencyKeyError::InvalidHeaderValue)?;
+        Ok(Some(value.parse()?))
+    }
+}
+
+impl fmt::Display for IdempotencyKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for IdempotencyKey {
+    type Err = IdempotencyKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((index, char)) = s
+            .chars()
+            .enumerate()
+            .find(|(_, char)| !(char.is_ascii_alphanumeric() || *char == '-' || *char == '_'))
+        {
+            return Err(IdempotencyKeyError::InvalidCharacter {
+                key: truncate_input(s),
+                index,
+                char,
+            });
+        }
+        if !(MIN_IDEMPOTENCY_KEY_LEN..=MAX_IDEMPOTENCY_KEY_LEN).contains(&s.len()) {
+            return Err(IdempotencyKeyError::InvalidLength { len: s.len() });
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl Serialize for IdempotencyKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for IdempotencyKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation_table() {
+        let cases: [(&str, Result<(), IdempotencyKeyError>); 9] = [
+            ("abcdefghijklmnop", Ok(())),
+            ("0123456789_ABCDEF-xyz", Ok(())),
+            ("550e8400-e29b-41d4-a716-446655440000", Ok(())),
+            (&"a".repeat(64), Ok(())),
+            ("abcdefghijklmno", Err(IdempotencyKeyError::InvalidLength { len: 15 })),
+            (&"a".repeat(65), Err(IdempotencyKeyError::InvalidLength { len: 65 })),
+            ("", Err(IdempotencyKeyError::InvalidLength { len: 0 })),
+            (
+                "abcdefgh ijklmnop",
+                Err(IdempotencyKeyError::InvalidCharacter {
+                    key: "abcdefgh ijklmnop".to_string(),
+                    index: 8,
+                    char: ' ',
+                }),
+            ),
+            (
+                "abcdefghijklmnop\u{e9}",
+                Err(IdempotencyKeyError::InvalidCharacter {
+                    key: "abcdefghijklmnop\u{e9}".to_string(),
+                    index: 16,
+                    char: '\u{e9}',
+                }),
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<IdempotencyKey>().map(|_| ()), expected, "{}", input);
+        }
+        for char in ['.', ':', '/', '+', '=', '\n'] {
+            assert!(format!("abcdefghijklmnop{}", char).parse::<IdempotencyKey>().is_err(), "{:?}", char);
+        }
+    }
+
+    #[test]
+    fn generated_keys_are_valid() {
+        let key = IdempotencyKey::generate();
+        assert_eq!(key.as_str().parse(), Ok(key.clone()));
+        assert_ne!(key, IdempotencyKey::generate());
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(serde_json::from_str::<IdempotencyKey>(&json).unwrap(), key);
+        assert!(serde_json::from_str::<IdempotencyKey>("\"short\"").is_err());
+    }
+
+    #[test]
+    fn fingerprint_golden_vector() {
+        let key: IdempotencyKey = "550e8400-e29b-41d4-a716-446655440000".parse().unwrap();
+        let fingerprint = key.fingerprint_request(br#"{"name":"photos"}"#);
+        assert_eq!(
+            fingerprint,
+            hex_literal::hex!("5e79bc807ba71f48bed96ee8a2cc51f6be53aaf0a7d2db7a844390ec83c3d556")
+        );
+        assert_ne!(key.fingerprint_request(br#"{"name":"videos"}"#), fingerprint);
+        let other: IdempotencyKey = "550e8400-e29b-41d4-a716-446655440001".parse().unwrap();
+        assert_ne!(other.fingerprint_request(br#"{"name":"photos"}"#), fingerprint);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn header_extraction() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(IdempotencyKey::from_headers(&headers), Ok(None));
+
+        headers.insert(IDEMPOTENCY_KEY_HEADER, http::HeaderValue::from_static("550e8400-e29b-41d4-a716-446655440000"));
+        assert_eq!(
+            IdempotencyKey::from_headers(&headers),
+            Ok(Some("550e8400-e29b-41d4-a716-446655440000".parse().unwrap()))
+        );
+
+        headers.insert(IDEMPOTENCY_KEY_HEADER, http::HeaderValue::from_static("too-short"));
+        assert_eq!(
+            IdempotencyKey::from_headers(&headers),
+            Err(IdempotencyKeyError::InvalidLength { len: 9 })
+        );
+
+        headers.insert(IDEMPOTENCY_KEY_HEADER, http::HeaderValue::from_bytes(b"abcdefghijklmnop\xe9").unwrap());
+        assert_eq!(IdempotencyKey::from_headers(&headers), Err(IdempotencyKeyError::InvalidHeaderValue));
+
+        headers.insert(IDEMPOTENCY_KEY_HEADER, http::HeaderValue::from_static("abcdefghijklmnop"));
+        headers.append(IDEMPOTENCY_KEY_HEADER, http::HeaderValue::from_static("qrstuvwxyzabcdef"));
+        assert_eq!(IdempotencyKey::from_headers(&headers), Err(IdempotencyKeyError::DuplicateHeader));
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+use crate::public_bucket_id::PublicBucketId;
+use crate::util::PUBLIC_BUCKET_PATH_URL;
+
+#[cfg(feature = "http")]
+pub const X_ROBOTS_TAG_HEADER: &str = "X-Robots-Tag";
+
+/*
+* Whether search engines may index the listing of a public bucket, sent as X-Robots-Tag and a robots.txt fragment.
+* Independent of BucketFeaturesFlags::IS_SEARCH_INDEXED, which is BucketDrive's own search: a bucket can be in the
+* internal index and still opt out of external ones.
+*/
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Eq,
+    PartialEq,
+    Hash,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum IndexingPolicy {
+    #[default]
+    Allow,
+    NoIndex,
+    NoIndexNoFollow,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum IndexingPolicyError {
+    #[error("nofollow without noindex can not be expressed as an indexing policy")]
+    NoFollowWithoutNoIndex,
+}
+
+impl IndexingPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == IndexingPolicy::Allow
+    }
+
+    // X-Robots-Tag value, None when no header is needed.
+    pub fn to_robots_header(&self) -> Option<&'static str> {
+        match self {
+            IndexingPolicy::Allow => None,
+            IndexingPolicy::NoIndex => Some("noindex"),
+            IndexingPolicy::NoIndexNoFollow => Some("noindex, nofollow"),
+        }
+    }
+
+    /*
+    * Inverse of to_robots_header for comma separated directives, case-insensitive. "none" is noindex, nofollow and
+    * directives other than noindex, nofollow, none and all are ignored. A missing header is Allow.
+    */
+    pub fn from_robots_header(value: Option<&str>) -> Result<Self, IndexingPolicyError> {
+        let (mut no_index, mut no_follow) = (false, false);
+        for directive in value.unwrap_or_default().split(',') {
+            match directive.trim().to_ascii_lowercase().as_str() {
+                "noindex" => no_index = true,
+                "nofollow" => no_follow = true,
+                "none" => (no_index, no_follow) = (true, true),
+                _ => {}
+            }
+        }
+        match (no_index, no_follow) {
+            (false, false) => Ok(IndexingPolicy::Allow),
+            (true, false) => Ok(IndexingPolicy::NoIndex),
+            (true, true) => Ok(IndexingPolicy::NoIndexNoFollow),
+            (false, true) => Err(IndexingPolicyError::NoFollowWithoutNoIndex),
+        }
+    }
+
+    /*
+    * Rule for the listing of a public bucket, to go into the gateway's "User-agent: *" group.
+    * robots.txt can only keep crawlers out, so both opt-outs disallow the listing and the header covers crawlers that
+    * reach it anyway. Public ids have a fixed length, so the prefix rule never matches another bucket.
+    */
+    pub fn to_robots_txt_fragment(&self, public_id: &PublicBucketId) -> String {
+        let rule = match self {
+            IndexingPolicy::Allow => "Allow",
+            IndexingPolicy::NoIndex | IndexingPolicy::NoIndexNoFollow => "Disallow",
+        };
+        format!("{}: {}/{}\n", rule, PUBLIC_BUCKET_PATH_URL, public_id)
+    }
+
+    #[cfg(feature = "http")]
+    pub fn to_header_value(&self) -> Option<http::HeaderValue> {
+        self.to_robots_header().map(http::HeaderValue::from_static)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::bucket_guid::BucketGuid;
+    use crate::public_bucket_id::PublicBucketIdPepper;
+
+    #[test]
+    fn robots_header() {
+        assert_eq!(IndexingPolicy::Allow.to_robots_header(), None);
+        assert_eq!(IndexingPolicy::NoIndex.to_robots_header(), Some("noindex"));
+        assert_eq!(IndexingPolicy::NoIndexNoFollow.to_robots_header(), Some("noindex, nofollow"));
+        for policy in IndexingPolicy::iter() {
+            assert_eq!(IndexingPolicy::from_robots_header(policy.to_robots_header()), Ok(policy));
+        }
+    }
+
+    #[test]
+    fn parse_robots_header() {
+        let parse = |value| IndexingPolicy::from_robots_header(Some(value));
+        assert_eq!(parse(""), Ok(IndexingPolicy::Allow));
+        assert_eq!(parse("all"), Ok(IndexingPolicy::Allow));
+        assert_eq!(parse("NoIndex"), Ok(IndexingPolicy::NoIndex));
+        assert_eq!(parse("noarchive, noindex"), Ok(IndexingPolicy::NoIndex));
+        assert_eq!(parse("nofollow,noindex"), Ok(IndexingPolicy::NoIndexNoFollow));
+        assert_eq!(parse(" none "), Ok(IndexingPolicy::NoIndexNoFollow));
+        assert_eq!(parse("nofollow"), Err(IndexingPolicyError::NoFollowWithoutNoIndex));
+    }
+
+    #[test]
+    fn robots_txt_fragment() {
+        let public_id = PublicBucketId::derive(
+            &BucketGuid::new(uuid::Uuid::nil(), uuid::Uuid::nil()),
+            &PublicBucketIdPepper::new([1; 32]),
+        );
+        let path = format!("/b/{}", public_id);
+        assert_eq!(IndexingPolicy::Allow.to_robots_txt_fragment(&public_id), format!("Allow: {}\n", path));
+        assert_eq!(IndexingPolicy::NoIndex.to_robots_txt_fragment(&public_id), format!("Disallow: {}\n", path));
+        assert_eq!(
+            IndexingPolicy::NoIndexNoFollow.to_robots_txt_fragment(&public_id),
+            format!("Disallow: {}\n", path)
+        );
+    }
+
+    #[test]
+    fn serde_and_strings() {
+        assert_eq!(IndexingPolicy::default(), IndexingPolicy::Allow);
+        for (policy, name) in [
+            (IndexingPolicy::Allow, "allow"),
+            (IndexingPolicy::NoIndex, "no_index"),
+            (IndexingPolicy::NoIndexNoFollow, "no_index_no_follow"),
+        ] {
+            assert_eq!(policy.to_string(), name);
+            assert_eq!(name.parse(), Ok(policy));
+            assert_eq!(serde_json::to_string(&policy).unwrap(), format!("\"{}\"", name));
+            assert_eq!(serde_json::from_str::<IndexingPolicy>(&format!("\"{}\"", name)).unwrap(), policy);
+        }
+        assert!(serde_json::from_str::<IndexingPolicy>("\"noindex\"").is_err());
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn header_value() {
+        assert_eq!(IndexingPolicy::Allow.to_header_value(), None);
+        let mut headers = http::HeaderMap::new();
+        headers.insert(X_ROBOTS_TAG_HEADER, IndexingPolicy::NoIndexNoFollow.to_header_value().unwrap());
+        assert_eq!(headers.get("x-robots-tag").unwrap(), "noindex, nofollow");
+        let value = headers.get(X_ROBOTS_TAG_HEADER).and_then(|value| value.to_str().ok());
+        assert_eq!(IndexingPolicy::from_robots_header(value), Ok(IndexingPolicy::NoIndexNoFollow));
+    }
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "secret_share_link")]
+
+use aes_gcm::Aes256Gcm;
+use hkdf::Hkdf;
+use sha3::Sha3_256;
+
+use crate::bucket_object::BucketObjectKey;
+
+pub const OBJECT_KEY_INFO: &[u8] = b"bucketdrive/object-key/v1";
+pub const SEARCH_TOKEN_KEY_INFO: &[u8] = b"bucketdrive/search-token-key/v1";
+
+/*
+* Keys of zero-knowledge buckets derived on the client from the bucket key, committed as testdata/key_derivation_vectors.json.
+* HKDF-SHA3-256 with the bucket key as input key material, no salt and 32 bytes of output. The info is
+* <info string> || <object key as UTF-8> || <generation as 4 bytes big endian>, where the object key is used exactly
+* as stored, without any normalization. The generation has a fixed length, so the info can not be ambiguous.
+* Object and search token keys only differ in the info string, which keeps the two domain separated.
+*/
+fn derive(
+    info_prefix: &[u8],
+    bucket_key: &aes_gcm::Key<Aes256Gcm>,
+    object: &BucketObjectKey,
+    generation: u32,
+) -> aes_gcm::Key<Aes256Gcm> {
+    let hkdf = Hkdf::<Sha3_256>::new(None, bucket_key.as_slice());
+    let mut key = aes_gcm::Key::<Aes256Gcm>::default();
+    hkdf.expand_multi_info(
+        &[info_prefix, object.as_str().as_bytes(), &generation.to_be_bytes()],
+        key.as_mut_slice(),
+    )
+    .expect("32 bytes is a valid HKDF-SHA3-256 output length");
+    key
+}
+
+// Key encrypting the contents of one object. generation is the bucket key generation, see EncryptionMetadata.
+pub fn derive_object_key(
+    bucket_key: &aes_gcm::Key<Aes256Gcm>,
+    object: &BucketObjectKey,
+    generation: u32,
+) -> aes_gcm::Key<Aes256Gcm> {
+    derive(OBJECT_KEY_INFO, bucket_key, object, generation)
+}
+
+// Key for the search tokens of one object in the searchable encryption index.
+pub fn derive_search_token_key(
+    bucket_key: &aes_gcm::Key<Aes256Gcm>,
+    object: &BucketObjectKey,
+    generation: u32,
+) -> aes_gcm::Key<Aes256Gcm> {
+    derive(SEARCH_TOKEN_KEY_INFO, bucket_key, object, generation)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    const VECTORS: &str = include_str!("../testdata/key_derivation_vectors.json");
+
+    // Binary fields are lowercase hex.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct KeyDerivationVector {
+        bucket_key: String,
+        object: String,
+        generation: u32,
+        object_key: String,
+        search_token_key: String,
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn vectors() -> Vec<KeyDerivationVector> {
+        let bucket_keys = [[0u8; 32], std::array::from_fn(|index| index as u8)];
+        let objects = ["/photos/2024/beach.jpg", "/caf\u{e9}/menu.pdf", "a"];
+        let mut vectors = Vec::new();
+        for bucket_key in bucket_keys {
+            let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key);
+            for object in objects {
+                for generation in [0, 1, u32::MAX] {
+                    let object = object.parse().unwrap();
+                    vectors.push(KeyDerivationVector {
+                        bucket_key: hex(&bucket_key),
+                        object_key: hex(&derive_object_key(&bucket_key, &object, generation)),
+                        search_token_key: hex(&derive_search_token_key(&bucket_key, &object, generation)),
+                        object: object.to_string(),
+                        generation,
+                    });
+                }
+            }
+        }
+        vectors
+    }
+
+    #[test]
+    fn committed_vectors_are_up_to_date() {
+        assert_eq!(serde_json::to_string_pretty(&vectors()).unwrap() + "\n", VECTORS);
+    }
+
+    #[test]
+    fn derivations_are_domain_separated() {
+        for vector in vectors() {
+            assert_ne!(vector.object_key, vector.search_token_key, "{:?}", vector);
+        }
+        let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7; 32]);
+        let object = "/notes.txt".parse().unwrap();
+        let key = derive_object_key(&bucket_key, &object, 3);
+        assert_eq!(derive_object_key(&bucket_key, &object, 3), key);
+        assert_ne!(derive_object_key(&bucket_key, &object, 4), key);
+        assert_ne!(derive_object_key(&bucket_key, &"/notes.txt ".parse().unwrap(), 3), key);
+        let other_bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&[8; 32]);
+        assert_ne!(derive_object_key(&other_bucket_key, &object, 3), key);
+    }
+}
@@ -0,0 +1,529 @@
+#![cfg(feature = "secret_share_link")]
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use time::OffsetDateTime;
+
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::expiry::{ExpiryTimestamp, ExpiryTimestampError};
+use crate::framing::{expect_header, write_header, BinaryFormat, FramingError, FRAME_MAGIC_LEN};
+use crate::secret_share_link::{SecretShareLink, SecretShareLinkVerifySignatureError};
+use crate::util::truncate_input;
+
+pub const KEYRING_VERSION: u8 = 1;
+pub const MAX_KEY_ID_LEN: usize = 64;
+// Prepended to the canonical bytes before the root key signs them, a keyring signature can never pass as a link signature.
+const SIGNED_KEYRING_CONTEXT: &[u8] = b"bucketdrive-keyring-v1";
+
+// Name of a link signing key, 1 to 64 characters of [A-Za-z0-9_.-].
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct KeyId(String);
+
+impl KeyId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for KeyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for KeyId {
+    type Err = KeyringError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = (1..=MAX_KEY_ID_LEN).contains(&s.len())
+            && s.chars().all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '.' | '-'));
+        if !valid {
+            return Err(KeyringError::InvalidKeyId(truncate_input(s)));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl Serialize for KeyId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/*
+* A public key links are verified with, active from valid_from until valid_until (exclusive).
+* valid_until None means the key has not been retired yet.
+* public_key is base64url without padding in JSON.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct KeyringEntry {
+    pub key_id: KeyId,
+    #[serde(with = "public_key_base64")]
+    pub public_key: [u8; 32],
+    pub valid_from: ExpiryTimestamp,
+    pub valid_until: Option<ExpiryTimestamp>,
+}
+
+impl KeyringEntry {
+    pub fn is_valid_at(&self, now: OffsetDateTime) -> bool {
+        self.valid_from.as_offset_date_time() <= now
+            && self.valid_until.is_none_or(|valid_until| now < valid_until.as_offset_date_time())
+    }
+
+    pub(crate) fn verifies(&self, link: &SecretShareLink) -> bool {
+        link.verify_signature(ed25519_compact::PublicKey::new(self.public_key)).is_ok()
+    }
+}
+
+/*
+* Every link signing key with its active window, fetched once by clients and cached for offline verification.
+* Entries are kept sorted by key id and key ids are unique. JSON is an array of entries.
+* Binary format: version (u8), count (u32 BE), then per entry in key id order: key id length (u8), key id,
+* public key (32 bytes), valid_from (i64 BE unix seconds), valid_until (i64 BE unix seconds, 0 when unset).
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<KeyringEntry>", into = "Vec<KeyringEntry>")]
+pub struct Keyring {
+    entries: Vec<KeyringEntry>,
+}
+
+#[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
+pub enum KeyringError {
+    #[error("no key in the keyring verifies the link")]
+    NoValidKey,
+    #[error("key {0} verifies the link but is not valid at the time it was checked")]
+    KeyExpiredAtIssueTime(KeyId),
+    #[error("keyring is not signed by the root key")]
+    RootSignatureInvalid,
+    #[error("invalid key id \"{0}\"")]
+    InvalidKeyId(String),
+    #[error("key id {0} appears more than once")]
+    DuplicateKeyId(KeyId),
+    #[error("key {0} has valid_until before or at valid_from")]
+    EmptyWindow(KeyId),
+    #[error("key {index} is not after the key before it in key id order")]
+    Unsorted { index: u32 },
+    #[error("unsupported keyring version {0}")]
+    UnsupportedVersion(u8),
+    #[error("keyring ended before the last entry")]
+    Truncated,
+    #[error("keyring has data after the last entry")]
+    TrailingData,
+    #[error(transparent)]
+    Framing(#[from] FramingError),
+    #[error(transparent)]
+    Timestamp(#[from] ExpiryTimestampError),
+    #[error(transparent)]
+    Link(#[from] SecretShareLinkVerifySignatureError),
+}
+
+// Points at the first character a key id can not hold, or the whole id when its length is wrong.
+impl SpannedParseError for KeyringError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            KeyringError::InvalidKeyId(input) => {
+                let invalid = input
+                    .char_indices()
+                    .find(|(_, char)| !(char.is_ascii_alphanumeric() || matches!(char, '_' | '.' | '-')));
+                let span = match ((1..=MAX_KEY_ID_LEN).contains(&input.len()), invalid) {
+                    (true, Some((index, char))) => index..index + char.len_utf8(),
+                    _ => 0..input.len(),
+                };
+                ParseFailure::spanned(self, input, span)
+            }
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl Keyring {
+    pub fn new(mut entries: Vec<KeyringEntry>) -> Result<Self, KeyringError> {
+        entries.sort_by(|a, b| a.key_id.cmp(&b.key_id));
+        for pair in entries.windows(2) {
+            if pair[0].key_id == pair[1].key_id {
+                return Err(KeyringError::DuplicateKeyId(pair[0].key_id.clone()));
+            }
+        }
+        if let Some(entry) = entries
+            .iter()
+            .find(|entry| entry.valid_until.is_some_and(|valid_until| valid_until <= entry.valid_from))
+        {
+            return Err(KeyringError::EmptyWindow(entry.key_id.clone()));
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[KeyringEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, key_id: &KeyId) -> Option<&KeyringEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.key_id.cmp(key_id))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+
+    /*
+    * Links do not carry a key id or their issue time, so every key valid at now is tried.
+    * A key outside its window that still verifies the signature is reported as KeyExpiredAtIssueTime rather than NoValidKey.
+    * Once a key verifies the signature the link's own not_before..expires window is checked against now.
+    */
+    pub fn verify_link(&self, link: &SecretShareLink, now: OffsetDateTime) -> Result<(), KeyringError> {
+        let (active, inactive): (Vec<_>, Vec<_>) = self.entries.iter().partition(|entry| entry.is_valid_at(now));
+        if let Some(entry) = active.into_iter().find(|entry| entry.verifies(link)) {
+            return Ok(link.verify(ed25519_compact::PublicKey::new(entry.public_key), now)?);
+        }
+        match inactive.into_iter().find(|entry| entry.verifies(link)) {
+            Some(entry) => Err(KeyringError::KeyExpiredAtIssueTime(entry.key_id.clone())),
+            None => Err(KeyringError::NoValidKey),
+        }
+    }
+
+    // For callers that learned the key id next to the link, only that key is tried.
+    pub fn verify_link_with_key_id(
+        &self,
+        link: &SecretShareLink,
+        key_id: &KeyId,
+        now: OffsetDateTime,
+    ) -> Result<(), KeyringError> {
+        let entry = self.get(key_id).ok_or(KeyringError::NoValidKey)?;
+        if !entry.is_valid_at(now) {
+            return Err(KeyringError::KeyExpiredAtIssueTime(key_id.clone()));
+        }
+        Ok(link.verify(ed25519_compact::PublicKey::new(entry.public_key), now)?)
+    }
+
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_header(BinaryFormat::Keyring, KEYRING_VERSION, &mut bytes);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            // At most MAX_KEY_ID_LEN, always fits.
+            bytes.push(entry.key_id.0.len() as u8);
+            bytes.extend_from_slice(entry.key_id.0.as_bytes());
+            bytes.extend_from_slice(&entry.public_key);
+            bytes.extend_from_slice(&entry.valid_from.unix_timestamp().to_be_bytes());
+            let valid_until = entry.valid_until.map_or(0, |valid_until| valid_until.unix_timestamp());
+            bytes.extend_from_slice(&valid_until.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, KeyringError> {
+        let (version, body) = expect_header(bytes, BinaryFormat::Keyring)?;
+        let mut reader = ByteReader(body);
+        if version != KEYRING_VERSION {
+            return Err(KeyringError::UnsupportedVersion(version));
+        }
+        let count = u32::from_be_bytes(reader.take()?);
+        // The count comes from untrusted input, don't preallocate for it.
+        let mut entries = Vec::new();
+        for index in 0..count {
+            let key_id_len = reader.take::<1>()?[0] as usize;
+            let key_id = std::str::from_utf8(reader.take_slice(key_id_len)?)
+                .map_err(|_| KeyringError::InvalidKeyId("<not utf-8>".to_string()))?
+                .parse::<KeyId>()?;
+            // Signed bytes must have a single encoding, so entries have to be in strictly ascending key id order.
+            if entries.last().is_some_and(|last: &KeyringEntry| last.key_id >= key_id) {
+                return Err(KeyringError::Unsorted { index });
+            }
+            let public_key = reader.take()?;
+            let valid_from = ExpiryTimestamp::from_unix_timestamp(i64::from_be_bytes(reader.take()?))?;
+            let valid_until = match i64::from_be_bytes(reader.take()?) {
+                0 => None,
+                timestamp => Some(ExpiryTimestamp::from_unix_timestamp(timestamp)?),
+            };
+            entries.push(KeyringEntry {
+                key_id,
+                public_key,
+                valid_from,
+                valid_until,
+            });
+        }
+        if !reader.0.is_empty() {
+            return Err(KeyringError::TrailingData);
+        }
+        Self::new(entries)
+    }
+}
+
+impl TryFrom<Vec<KeyringEntry>> for Keyring {
+    type Error = KeyringError;
+
+    fn try_from(entries: Vec<KeyringEntry>) -> Result<Self, Self::Error> {
+        Self::new(entries)
+    }
+}
+
+impl From<Keyring> for Vec<KeyringEntry> {
+    fn from(keyring: Keyring) -> Self {
+        keyring.entries
+    }
+}
+
+/*
+* Keyring signed by the offline root key, what clients download and cache.
+* The root key signs SIGNED_KEYRING_CONTEXT followed by the canonical bytes of the keyring without the format magic.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SignedKeyring {
+    pub keyring: Keyring,
+    #[serde(with = "crate::util::signature_base64")]
+    pub signature: ed25519_compact::Signature,
+}
+
+impl SignedKeyring {
+    pub fn sign(keyring: Keyring, root_key: &ed25519_compact::SecretKey) -> Self {
+        let signature = root_key.sign(Self::signed_message(&keyring), Some(ed25519_compact::Noise::generate()));
+        Self { keyring, signature }
+    }
+
+    // The keyring is only handed out after its signature checks out.
+    pub fn verify(&self, root_public_key: &ed25519_compact::PublicKey) -> Result<&Keyring, KeyringError> {
+        root_public_key
+            .verify(Self::signed_message(&self.keyring), &self.signature)
+            .map_err(|_| KeyringError::RootSignatureInvalid)?;
+        Ok(&self.keyring)
+    }
+
+    // Without the magic, keyrings signed before the bytes were framed still verify.
+    fn signed_message(keyring: &Keyring) -> Vec<u8> {
+        [SIGNED_KEYRING_CONTEXT, &keyring.canonical_bytes()[FRAME_MAGIC_LEN..]].concat()
+    }
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8], KeyringError> {
+        if self.0.len() < len {
+            return Err(KeyringError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], KeyringError> {
+        Ok(self.take_slice(N)?.try_into().expect("slice has length N"))
+    }
+}
+
+mod public_key_base64 {
+    use base64::{engine::general_purpose, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&general_purpose::URL_SAFE_NO_PAD.encode(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let s = String::deserialize(deserializer)?;
+        general_purpose::URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(serde::de::Error::custom)?
+            .try_into()
+            .map_err(|_| serde::de::Error::custom("public key is not 32 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes_gcm::Aes256Gcm;
+    use time::Duration;
+
+    use super::*;
+    use crate::framing::FRAME_HEADER_LEN;
+    use crate::share_link::BucketSharePermissionFlags;
+
+    fn key_pair(seed: u8) -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([seed; 32]))
+    }
+
+    fn timestamp(unix: i64) -> ExpiryTimestamp {
+        ExpiryTimestamp::from_unix_timestamp(unix).unwrap()
+    }
+
+    fn entry(key_id: &str, seed: u8, valid_from: i64, valid_until: Option<i64>) -> KeyringEntry {
+        KeyringEntry {
+            key_id: key_id.parse().unwrap(),
+            public_key: *key_pair(seed).pk,
+            valid_from: timestamp(valid_from),
+            valid_until: valid_until.map(timestamp),
+        }
+    }
+
+    // 2024-01-01, 2025-01-01 and 2026-01-01.
+    const Y2024: i64 = 1_704_067_200;
+    const Y2025: i64 = 1_735_689_600;
+    const Y2026: i64 = 1_767_225_600;
+
+    // The 2024 key was retired when the 2025 key took over.
+    fn keyring() -> Keyring {
+        Keyring::new(vec![
+            entry("links-2025", 2, Y2025, None),
+            entry("links-2024", 1, Y2024, Some(Y2025)),
+        ])
+        .unwrap()
+    }
+
+    fn link(seed: u8) -> SecretShareLink {
+        SecretShareLink::new(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7u8; 32]),
+            BucketSharePermissionFlags::READ,
+            None,
+            &key_pair(seed).sk,
+        )
+    }
+
+    fn at(unix: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(unix).unwrap()
+    }
+
+    #[test]
+    fn json_and_binary_round_trip() {
+        let keyring = keyring();
+        assert_eq!(keyring.entries()[0].key_id.as_str(), "links-2024");
+
+        let json = serde_json::to_string(&keyring).unwrap();
+        assert!(json.starts_with(r#"[{"key_id":"links-2024","public_key":""#), "{}", json);
+        assert_eq!(serde_json::from_str::<Keyring>(&json).unwrap(), keyring);
+
+        let bytes = keyring.canonical_bytes();
+        assert_eq!(bytes.len(), FRAME_HEADER_LEN + 4 + 2 * (1 + 10 + 32 + 8 + 8));
+        assert_eq!(Keyring::from_canonical_bytes(&bytes).unwrap(), keyring);
+
+        let signed = SignedKeyring::sign(keyring, &key_pair(9).sk);
+        let json = serde_json::to_string(&signed).unwrap();
+        let parsed = serde_json::from_str::<SignedKeyring>(&json).unwrap();
+        assert_eq!(parsed, signed);
+        assert!(parsed.verify(&key_pair(9).pk).is_ok());
+    }
+
+    #[test]
+    fn invalid_documents_are_rejected() {
+        let duplicate = Keyring::new(duplicate_entries());
+        assert!(matches!(duplicate, Err(KeyringError::DuplicateKeyId(key_id)) if key_id.as_str() == "a"));
+        let empty_window = Keyring::new(vec![entry("a", 1, Y2025, Some(Y2025))]);
+        assert!(matches!(empty_window, Err(KeyringError::EmptyWindow(_))));
+        for key_id in ["", "a b", "ä", &"a".repeat(65)] {
+            assert!(matches!(key_id.parse::<KeyId>(), Err(KeyringError::InvalidKeyId(_))), "{}", key_id);
+        }
+        assert!(serde_json::from_str::<Keyring>(&serde_json::to_string(&duplicate_entries()).unwrap()).is_err());
+
+        let bytes = keyring().canonical_bytes();
+        assert!(matches!(Keyring::from_canonical_bytes(&bytes[..bytes.len() - 1]), Err(KeyringError::Truncated)));
+        assert!(matches!(
+            Keyring::from_canonical_bytes(&[bytes.as_slice(), &[0]].concat()),
+            Err(KeyringError::TrailingData)
+        ));
+        let mut version = bytes.clone();
+        version[FRAME_MAGIC_LEN] = 2;
+        assert!(matches!(Keyring::from_canonical_bytes(&version), Err(KeyringError::UnsupportedVersion(2))));
+        assert!(matches!(
+            Keyring::from_canonical_bytes(&bytes[FRAME_MAGIC_LEN..]),
+            Err(KeyringError::Framing(FramingError::UnknownMagic(_)))
+        ));
+
+        // Entries swapped, the same keyring but not its canonical encoding.
+        let entries = FRAME_HEADER_LEN + 4;
+        let entry_len = (bytes.len() - entries) / 2;
+        let swapped = [&bytes[..entries], &bytes[entries + entry_len..], &bytes[entries..entries + entry_len]].concat();
+        assert!(matches!(Keyring::from_canonical_bytes(&swapped), Err(KeyringError::Unsorted { index: 1 })));
+    }
+
+    fn duplicate_entries() -> Vec<KeyringEntry> {
+        vec![entry("a", 1, Y2024, None), entry("a", 2, Y2025, None)]
+    }
+
+    #[test]
+    fn root_signature_covers_the_whole_document() {
+        let root = key_pair(9);
+        let signed = SignedKeyring::sign(keyring(), &root.sk);
+        assert_eq!(signed.verify(&root.pk).unwrap(), &keyring());
+        assert!(matches!(signed.verify(&key_pair(8).pk), Err(KeyringError::RootSignatureInvalid)));
+
+        // Extending the retired key's window is caught.
+        let mut tampered = signed.clone();
+        tampered.keyring.entries[0].valid_until = None;
+        assert!(matches!(tampered.verify(&root.pk), Err(KeyringError::RootSignatureInvalid)));
+
+        let mut added = signed;
+        added.keyring = Keyring::new([keyring().entries, vec![entry("rogue", 3, Y2024, None)]].concat()).unwrap();
+        assert!(matches!(added.verify(&root.pk), Err(KeyringError::RootSignatureInvalid)));
+    }
+
+    #[test]
+    fn key_selection() {
+        let keyring = keyring();
+        let now = at(Y2025 + 100);
+        assert!(keyring.verify_link(&link(2), now).is_ok());
+        assert!(keyring.verify_link_with_key_id(&link(2), &"links-2025".parse().unwrap(), now).is_ok());
+        assert!(matches!(
+            keyring.verify_link_with_key_id(&link(2), &"links-2024".parse().unwrap(), now),
+            Err(KeyringError::KeyExpiredAtIssueTime(_))
+        ));
+        assert!(matches!(
+            keyring.verify_link_with_key_id(&link(2), &"unknown".parse().unwrap(), now),
+            Err(KeyringError::NoValidKey)
+        ));
+        // Signed by a key that is not in the keyring at all.
+        assert!(matches!(keyring.verify_link(&link(5), now), Err(KeyringError::NoValidKey)));
+
+        // The retired key still verifies the signature, reported as expired instead of unknown.
+        assert!(matches!(
+            keyring.verify_link(&link(1), now),
+            Err(KeyringError::KeyExpiredAtIssueTime(key_id)) if key_id.as_str() == "links-2024"
+        ));
+        assert!(keyring.verify_link(&link(1), at(Y2024 + 100)).is_ok());
+    }
+
+    #[test]
+    fn window_boundaries() {
+        let keyring = keyring();
+        let retired = &keyring.entries()[0];
+        assert!(!retired.is_valid_at(at(Y2024) - Duration::seconds(1)));
+        assert!(retired.is_valid_at(at(Y2024)));
+        assert!(retired.is_valid_at(at(Y2025) - Duration::seconds(1)));
+        assert!(!retired.is_valid_at(at(Y2025)));
+        assert!(keyring.entries()[1].is_valid_at(at(Y2026)));
+
+        assert!(keyring.verify_link(&link(1), at(Y2025) - Duration::seconds(1)).is_ok());
+        assert!(matches!(keyring.verify_link(&link(1), at(Y2025)), Err(KeyringError::KeyExpiredAtIssueTime(_))));
+        assert!(matches!(
+            keyring.verify_link(&link(2), at(Y2025) - Duration::seconds(1)),
+            Err(KeyringError::KeyExpiredAtIssueTime(_))
+        ));
+        assert!(keyring.verify_link(&link(2), at(Y2025)).is_ok());
+    }
+
+    #[test]
+    fn link_window_is_still_checked() {
+        let expires = timestamp(Y2025 + 3600);
+        let link = SecretShareLink::builder(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7u8; 32]),
+        )
+        .expires(expires)
+        .build(&key_pair(2).sk)
+        .unwrap();
+        assert!(keyring().verify_link(&link, at(Y2025)).is_ok());
+        assert!(matches!(
+            keyring().verify_link(&link, at(Y2025 + 3600)),
+            Err(KeyringError::Link(SecretShareLinkVerifySignatureError::Expired { .. }))
+        ));
+    }
+}
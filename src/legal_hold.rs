@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::bucket_description::BucketDisplayName;
+use crate::bucket_guid::BucketGuid;
+use crate::expiry::ExpiryTimestamp;
+use crate::UserId;
+
+/*
+* Compliance lock on a bucket. While active nothing in the bucket may be deleted, expired by lifecycle rules or
+* garbage collected, see LifecyclePolicy::next_action, Tombstone::validate and BucketStatus::transition_to.
+* Released holds are kept for the record, released_at is when the hold stopped applying.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct LegalHold {
+    pub id: Uuid,
+    pub bucket: BucketGuid,
+    pub placed_by: UserId,
+    pub placed_at: ExpiryTimestamp,
+    pub case_reference: BucketDisplayName,
+    #[serde(default)]
+    pub released_at: Option<ExpiryTimestamp>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[error("blocked by legal hold {hold_id}")]
+pub struct BlockedByLegalHold {
+    pub hold_id: Uuid,
+}
+
+impl LegalHold {
+    // Active from placed_at until released_at, not including released_at.
+    pub fn is_active(&self, now: OffsetDateTime) -> bool {
+        now >= self.placed_at.as_offset_date_time()
+            && self.released_at.is_none_or(|released_at| now < released_at.as_offset_date_time())
+    }
+
+    // Err with the first hold in holds that is active at now, the caller passes the holds of one bucket.
+    pub fn check(holds: &[LegalHold], now: OffsetDateTime) -> Result<(), BlockedByLegalHold> {
+        match holds.iter().find(|hold| hold.is_active(now)) {
+            Some(hold) => Err(BlockedByLegalHold { hold_id: hold.id }),
+            None => Ok(()),
+        }
+    }
+
+    // Same as check for holds of any number of buckets, only the holds of bucket count.
+    pub fn check_bucket(
+        holds: &[LegalHold],
+        bucket: &BucketGuid,
+        now: OffsetDateTime,
+    ) -> Result<(), BlockedByLegalHold> {
+        match holds.iter().find(|hold| hold.bucket == *bucket && hold.is_active(now)) {
+            Some(hold) => Err(BlockedByLegalHold { hold_id: hold.id }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time::Duration;
+
+    use super::*;
+
+    const PLACED_AT: OffsetDateTime = datetime!(2024-06-01 0:00 UTC);
+
+    fn bucket() -> BucketGuid {
+        BucketGuid::new(
+            "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+        )
+    }
+
+    fn hold(index: u8, released_after: Option<Duration>) -> LegalHold {
+        LegalHold {
+            id: Uuid::from_bytes([index; 16]),
+            bucket: bucket(),
+            placed_by: Uuid::nil(),
+            placed_at: PLACED_AT.try_into().unwrap(),
+            case_reference: BucketDisplayName::try_from(format!("Case {}", index)).unwrap(),
+            released_at: released_after.map(|after| (PLACED_AT + after).try_into().unwrap()),
+        }
+    }
+
+    #[test]
+    fn active_until_released() {
+        let held = hold(1, None);
+        assert!(!held.is_active(PLACED_AT - Duration::SECOND));
+        assert!(held.is_active(PLACED_AT));
+        assert!(held.is_active(PLACED_AT + Duration::days(3650)));
+
+        let released = hold(2, Some(Duration::days(30)));
+        assert!(released.is_active(PLACED_AT + Duration::days(30) - Duration::SECOND));
+        assert!(!released.is_active(PLACED_AT + Duration::days(30)));
+    }
+
+    #[test]
+    fn only_active_holds_block() {
+        let now = PLACED_AT + Duration::days(60);
+        let released = hold(1, Some(Duration::days(30)));
+        let active = hold(2, None);
+        let holds = [released.clone(), active.clone()];
+        assert_eq!(LegalHold::check(&[], now), Ok(()));
+        assert_eq!(LegalHold::check(&holds[..1], now), Ok(()));
+        assert_eq!(LegalHold::check(&holds, now), Err(BlockedByLegalHold { hold_id: active.id }));
+        // Before the first release both are active, the first one is reported.
+        assert_eq!(
+            LegalHold::check(&holds, PLACED_AT),
+            Err(BlockedByLegalHold { hold_id: released.id })
+        );
+
+        let other_bucket = LegalHold {
+            bucket: BucketGuid::new(Uuid::nil(), Uuid::nil()),
+            ..active.clone()
+        };
+        assert_eq!(LegalHold::check_bucket(&[other_bucket.clone(), released], &bucket(), now), Ok(()));
+        assert_eq!(
+            LegalHold::check_bucket(&[other_bucket, active.clone()], &bucket(), now),
+            Err(BlockedByLegalHold { hold_id: active.id })
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let released = hold(1, Some(Duration::days(30)));
+        let json = serde_json::to_string(&released).unwrap();
+        assert!(json.contains(r#""case_reference":"Case 1""#), "{}", json);
+        assert!(json.contains(r#""released_at":"2024-07-01T00:00:00Z""#), "{}", json);
+        assert_eq!(serde_json::from_str::<LegalHold>(&json).unwrap(), released);
+
+        let active = hold(2, None);
+        let json = serde_json::to_string(&active).unwrap().replace(r#","released_at":null"#, "");
+        assert_eq!(serde_json::from_str::<LegalHold>(&json).unwrap(), active);
+    }
+}
@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::legal_hold::{BlockedByLegalHold, LegalHold};
+use crate::BucketStorageClass;
+
+/*
+* Lifecycle rules are evaluated against the age of an object.
+* TransitionToClass: Move the object to another storage class once it is older than `after`.
+* Expire: Delete the object once it is older than `after`.
+* AbortIncompleteUploads: Abort multipart uploads that have not been completed within `after`.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LifecycleRule {
+    TransitionToClass { after: Duration, class: BucketStorageClass },
+    Expire { after: Duration },
+    AbortIncompleteUploads { after: Duration },
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, Serialize, Deserialize)]
+pub enum LifecycleRuleKind {
+    TransitionToClass,
+    Expire,
+    AbortIncompleteUploads,
+}
+
+// What the storage service should do with an object once a rule applies.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LifecycleAction {
+    TransitionToClass(BucketStorageClass),
+    Expire,
+    AbortIncompleteUpload,
+}
+
+impl LifecycleRule {
+    pub fn kind(&self) -> LifecycleRuleKind {
+        match self {
+            LifecycleRule::TransitionToClass { .. } => LifecycleRuleKind::TransitionToClass,
+            LifecycleRule::Expire { .. } => LifecycleRuleKind::Expire,
+            LifecycleRule::AbortIncompleteUploads { .. } => LifecycleRuleKind::AbortIncompleteUploads,
+        }
+    }
+
+    pub fn after(&self) -> Duration {
+        match self {
+            LifecycleRule::TransitionToClass { after, .. }
+            | LifecycleRule::Expire { after }
+            | LifecycleRule::AbortIncompleteUploads { after } => *after,
+        }
+    }
+
+    pub fn action(&self) -> LifecycleAction {
+        match self {
+            LifecycleRule::TransitionToClass { class, .. } => LifecycleAction::TransitionToClass(class.clone()),
+            LifecycleRule::Expire { .. } => LifecycleAction::Expire,
+            LifecycleRule::AbortIncompleteUploads { .. } => LifecycleAction::AbortIncompleteUpload,
+        }
+    }
+
+    // Used to break ties between rules with the same threshold. Expire wins since it makes the other actions moot.
+    fn priority(&self) -> u8 {
+        match self {
+            LifecycleRule::Expire { .. } => 0,
+            LifecycleRule::TransitionToClass { .. } => 1,
+            LifecycleRule::AbortIncompleteUploads { .. } => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum LifecyclePolicyError {
+    #[error("duplicate lifecycle rule of kind {0}")]
+    DuplicateRuleKind(LifecycleRuleKind),
+    #[error("lifecycle rule of kind {0} must have a positive duration")]
+    NonPositiveDuration(LifecycleRuleKind),
+    #[error("can not transition from {from} to {to}")]
+    ForbiddenTransition {
+        from: BucketStorageClass,
+        to: BucketStorageClass,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub struct LifecyclePolicy(pub Vec<LifecycleRule>);
+
+impl LifecyclePolicy {
+    // At most one rule per kind, every duration positive and any transition has to be allowed from the bucket's storage class.
+    pub fn validate(&self, bucket_class: &BucketStorageClass) -> Result<(), LifecyclePolicyError> {
+        let mut seen: Vec<LifecycleRuleKind> = Vec::with_capacity(self.0.len());
+        for rule in &self.0 {
+            let kind = rule.kind();
+            if seen.contains(&kind) {
+                return Err(LifecyclePolicyError::DuplicateRuleKind(kind));
+            }
+            seen.push(kind);
+            if !rule.after().is_positive() {
+                return Err(LifecyclePolicyError::NonPositiveDuration(kind));
+            }
+            if let LifecycleRule::TransitionToClass { class, .. } = rule {
+                if !bucket_class.can_transition_to(class) {
+                    return Err(LifecyclePolicyError::ForbiddenTransition {
+                        from: bucket_class.clone(),
+                        to: class.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    * A rule applies once the object is at least as old as the rule threshold.
+    * Transitions only apply when the object is allowed to move from its current class.
+    * The applicable rule with the shortest threshold wins, ties are broken by Expire > TransitionToClass > AbortIncompleteUploads.
+    * holds are the legal holds of the object's bucket, an Expire is refused while one of them is active at now.
+    */
+    pub fn next_action(
+        &self,
+        object_age: Duration,
+        current_class: &BucketStorageClass,
+        holds: &[LegalHold],
+        now: OffsetDateTime,
+    ) -> Result<Option<LifecycleAction>, BlockedByLegalHold> {
+        let action = self
+            .0
+            .iter()
+            .filter(|rule| object_age >= rule.after())
+            .filter(|rule| match rule {
+                LifecycleRule::TransitionToClass { class, .. } => current_class.can_transition_to(class),
+                _ => true,
+            })
+            .min_by_key(|rule| (rule.after(), rule.priority()))
+            .map(LifecycleRule::action);
+        if action == Some(LifecycleAction::Expire) {
+            LegalHold::check(holds, now)?;
+        }
+        Ok(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::bucket_guid::BucketGuid;
+
+    const NOW: OffsetDateTime = datetime!(2024-06-01 12:00 UTC);
+
+    fn hold(released_at: Option<OffsetDateTime>) -> LegalHold {
+        LegalHold {
+            id: uuid::Uuid::from_bytes([7; 16]),
+            bucket: BucketGuid::new(uuid::Uuid::nil(), uuid::Uuid::nil()),
+            placed_by: uuid::Uuid::nil(),
+            placed_at: datetime!(2024-01-01 0:00 UTC).try_into().unwrap(),
+            case_reference: "Case 7".to_string().try_into().unwrap(),
+            released_at: released_at.map(|released_at| released_at.try_into().unwrap()),
+        }
+    }
+
+    fn policy() -> LifecyclePolicy {
+        LifecyclePolicy(vec![
+            LifecycleRule::TransitionToClass {
+                after: Duration::days(90),
+                class: BucketStorageClass::Archive,
+            },
+            LifecycleRule::Expire {
+                after: Duration::days(365),
+            },
+            LifecycleRule::AbortIncompleteUploads {
+                after: Duration::days(7),
+            },
+        ])
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let policy = policy();
+        let json = serde_json::to_string(&policy).unwrap();
+        let parsed: LifecyclePolicy = serde_json::from_str(&json).unwrap();
+        assert_eq!(policy, parsed);
+    }
+
+    #[test]
+    fn validation() {
+        assert_eq!(policy().validate(&BucketStorageClass::General), Ok(()));
+
+        let duplicate = LifecyclePolicy(vec![
+            LifecycleRule::Expire { after: Duration::days(1) },
+            LifecycleRule::Expire { after: Duration::days(2) },
+        ]);
+        assert_eq!(
+            duplicate.validate(&BucketStorageClass::General),
+            Err(LifecyclePolicyError::DuplicateRuleKind(LifecycleRuleKind::Expire))
+        );
+
+        for after in [Duration::ZERO, Duration::days(-1)] {
+            let policy = LifecyclePolicy(vec![LifecycleRule::Expire { after }]);
+            assert_eq!(
+                policy.validate(&BucketStorageClass::General),
+                Err(LifecyclePolicyError::NonPositiveDuration(LifecycleRuleKind::Expire))
+            );
+        }
+
+        let forbidden = LifecyclePolicy(vec![LifecycleRule::TransitionToClass {
+            after: Duration::days(1),
+            class: BucketStorageClass::ReducedRedundancy,
+        }]);
+        assert_eq!(
+            forbidden.validate(&BucketStorageClass::Archive),
+            Err(LifecyclePolicyError::ForbiddenTransition {
+                from: BucketStorageClass::Archive,
+                to: BucketStorageClass::ReducedRedundancy,
+            })
+        );
+    }
+
+    #[test]
+    fn next_action_boundaries() {
+        let policy = policy();
+        let general = BucketStorageClass::General;
+        assert_eq!(policy.next_action(Duration::days(7) - Duration::SECOND, &general, &[], NOW), Ok(None));
+        assert_eq!(
+            policy.next_action(Duration::days(7), &general, &[], NOW),
+            Ok(Some(LifecycleAction::AbortIncompleteUpload))
+        );
+        // Shortest threshold wins, even when more rules apply.
+        assert_eq!(
+            policy.next_action(Duration::days(400), &general, &[], NOW),
+            Ok(Some(LifecycleAction::AbortIncompleteUpload))
+        );
+
+        let policy = LifecyclePolicy(policy.0[..2].to_vec());
+        assert_eq!(policy.next_action(Duration::days(90) - Duration::SECOND, &general, &[], NOW), Ok(None));
+        assert_eq!(
+            policy.next_action(Duration::days(90), &general, &[], NOW),
+            Ok(Some(LifecycleAction::TransitionToClass(BucketStorageClass::Archive)))
+        );
+        // Already archived objects skip the transition and wait for expiry.
+        assert_eq!(policy.next_action(Duration::days(200), &BucketStorageClass::Archive, &[], NOW), Ok(None));
+        assert_eq!(
+            policy.next_action(Duration::days(365), &BucketStorageClass::Archive, &[], NOW),
+            Ok(Some(LifecycleAction::Expire))
+        );
+    }
+
+    #[test]
+    fn next_action_tie_prefers_expire() {
+        let policy = LifecyclePolicy(vec![
+            LifecycleRule::TransitionToClass {
+                after: Duration::days(30),
+                class: BucketStorageClass::Archive,
+            },
+            LifecycleRule::Expire { after: Duration::days(30) },
+        ]);
+        assert_eq!(
+            policy.next_action(Duration::days(30), &BucketStorageClass::General, &[], NOW),
+            Ok(Some(LifecycleAction::Expire))
+        );
+    }
+
+    #[test]
+    fn legal_hold_blocks_expiry_only() {
+        let expiring = LifecyclePolicy(policy().0[..2].to_vec());
+        // Released first, active second.
+        let holds = [hold(Some(NOW - Duration::days(1))), hold(None)];
+        let general = BucketStorageClass::General;
+        let archive = BucketStorageClass::Archive;
+        assert_eq!(
+            expiring.next_action(Duration::days(365), &archive, &holds, NOW),
+            Err(BlockedByLegalHold { hold_id: holds[1].id })
+        );
+        assert_eq!(
+            expiring.next_action(Duration::days(365), &archive, &holds[..1], NOW),
+            Ok(Some(LifecycleAction::Expire))
+        );
+        // Other actions go ahead under a hold.
+        assert_eq!(
+            expiring.next_action(Duration::days(90), &general, &holds[1..], NOW),
+            Ok(Some(LifecycleAction::TransitionToClass(BucketStorageClass::Archive)))
+        );
+        assert_eq!(
+            policy().next_action(Duration::days(400), &general, &holds[1..], NOW),
+            Ok(Some(LifecycleAction::AbortIncompleteUpload))
+        );
+    }
+}
@@ -0,0 +1,152 @@
+#![cfg(feature = "share_link")]
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::expiry::ExpiryTimestamp;
+use crate::flags_diff::PermissionDiff;
+use crate::share_link::{BucketSharePermissionFlags, ShareChannel, ShareLinkToken};
+use crate::UserId;
+
+// Record of a share link being issued, kept by the server for security analytics. Never holds key material.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LinkAuditRecord {
+    pub token: ShareLinkToken,
+    pub user_id: UserId,
+    pub bucket_id: uuid::Uuid,
+    pub permission: BucketSharePermissionFlags,
+    pub expires: Option<ExpiryTimestamp>,
+    pub recipient: Option<UserId>,
+    pub channel: Option<ShareChannel>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub issued_at: OffsetDateTime,
+}
+
+/*
+* Entry of the share link audit log, tagged with "event".
+* Permission edits record the diff rather than both flag sets so reviewers see escalations directly.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LinkAuditEvent {
+    Issued(LinkAuditRecord),
+    PermissionsChanged {
+        token: ShareLinkToken,
+        // Who edited the permissions, not necessarily the bucket owner.
+        changed_by: UserId,
+        diff: PermissionDiff,
+        #[serde(with = "time::serde::rfc3339")]
+        changed_at: OffsetDateTime,
+    },
+    // A presented link was refused. The client only got the public reason, cause and detail stay in the log.
+    Rejected {
+        // None when the link did not parse far enough to derive it.
+        token: Option<ShareLinkToken>,
+        cause: String,
+        detail: String,
+        #[serde(with = "time::serde::rfc3339")]
+        rejected_at: OffsetDateTime,
+    },
+}
+
+#[cfg(feature = "secret_share_link")]
+impl LinkAuditRecord {
+    pub fn for_secret_share_link(link: &crate::secret_share_link::SecretShareLink, issued_at: OffsetDateTime) -> Self {
+        Self {
+            token: link.share_link_token(),
+            user_id: link.user_id,
+            bucket_id: link.bucket_id,
+            permission: link.permission,
+            expires: link.expires,
+            recipient: link.recipient,
+            channel: link.channel,
+            issued_at,
+        }
+    }
+}
+
+#[cfg(feature = "secret_share_link")]
+impl LinkAuditEvent {
+    pub fn rejected(
+        error: &crate::link_rejection::SecretShareLinkVerifyError,
+        token: Option<ShareLinkToken>,
+        rejected_at: OffsetDateTime,
+    ) -> Self {
+        LinkAuditEvent::Rejected {
+            token,
+            cause: error.cause().to_string(),
+            detail: error.to_string(),
+            rejected_at,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "secret_share_link"))]
+mod tests {
+    use aes_gcm::Aes256Gcm;
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::secret_share_link::SecretShareLink;
+
+    #[test]
+    fn record_carries_channel() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7u8; 32]);
+        let link = SecretShareLink::builder(uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), bucket_key)
+            .permission(BucketSharePermissionFlags::VIEW)
+            .channel(ShareChannel::Qr)
+            .build(&key_pair.sk)
+            .unwrap();
+        let record = LinkAuditRecord::for_secret_share_link(&link, datetime!(2024-06-01 12:00 UTC));
+        assert_eq!(record.channel, Some(ShareChannel::Qr));
+        assert_eq!(record.token, link.share_link_token());
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains("\"channel\":\"qr\""));
+        assert_eq!(serde_json::from_str::<LinkAuditRecord>(&json).unwrap(), record);
+    }
+
+    #[test]
+    fn permissions_changed_event() {
+        let token = ShareLinkToken([3u8; 32]);
+        let event = LinkAuditEvent::PermissionsChanged {
+            token,
+            changed_by: "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            diff: BucketSharePermissionFlags::READ.diff(BucketSharePermissionFlags::READ | BucketSharePermissionFlags::WRITE),
+            changed_at: datetime!(2024-06-01 12:00 UTC),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.starts_with(r#"{"event":"permissions_changed","token":"#), "{}", json);
+        assert!(json.contains(r#""diff":{"added":"WRITE","removed":"","unchanged":"READ"}"#), "{}", json);
+        assert_eq!(serde_json::from_str::<LinkAuditEvent>(&json).unwrap(), event);
+    }
+
+    #[test]
+    fn rejected_event_keeps_the_detail() {
+        use crate::expiry::ExpiryTimestamp;
+        use crate::link_rejection::{ApiError, SecretShareLinkVerifyError};
+        use crate::secret_share_link::SecretShareLinkVerifySignatureError;
+
+        let expired_at = ExpiryTimestamp::from_unix_timestamp(1_717_200_000).unwrap();
+        let error = SecretShareLinkVerifyError::from(SecretShareLinkVerifySignatureError::Expired { expired_at });
+        let token = ShareLinkToken([3u8; 32]);
+        let event = LinkAuditEvent::rejected(&error, Some(token), datetime!(2024-06-02 12:00 UTC));
+        assert_eq!(
+            event,
+            LinkAuditEvent::Rejected {
+                token: Some(token),
+                cause: "Expired".to_string(),
+                detail: format!("Link expired at {}", expired_at),
+                rejected_at: datetime!(2024-06-02 12:00 UTC),
+            }
+        );
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.starts_with(r#"{"event":"rejected","token":"#), "{}", json);
+        assert_eq!(serde_json::from_str::<LinkAuditEvent>(&json).unwrap(), event);
+
+        // The client's body carries none of it.
+        let body = serde_json::to_string(&ApiError::from(&error)).unwrap();
+        assert!(!body.contains(&expired_at.to_string()) && !body.contains("Expired"), "{}", body);
+    }
+}
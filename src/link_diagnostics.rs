@@ -0,0 +1,113 @@
+#![cfg(feature = "share_link")]
+
+use std::fmt;
+
+// What a URL segment holds in a share link.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum SegmentRole {
+    UserId,
+    BucketId,
+    Token,
+    Key,
+    Permissions,
+    Expiry,
+    NotBefore,
+    Prefixes,
+    Channel,
+    Recipient,
+    IssuedAt,
+    MaxTotalBytes,
+    Signature,
+    // Format version and length of a checked link, see SecretShareLink.
+    Version,
+    Checksum,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum UrlPart {
+    Path,
+    Fragment,
+}
+
+/*
+* One decoded segment. Path indexes count from the first segment after the share path, fragment indexes from the first '#'.
+* error is the error variant name, e.g. "Base64Decoding", None when the segment decoded.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SegmentDiagnostic {
+    pub part: UrlPart,
+    pub index: usize,
+    pub role: SegmentRole,
+    pub len: usize,
+    pub error: Option<&'static str>,
+}
+
+/*
+* Collected while parsing a link, for support to see which segment of a broken link is at fault.
+* Only roles, lengths and error variant names are kept, never the segment contents, so it is safe to log and show.
+* Parsing stops at the first error, segments after it are not listed.
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ParseDiagnostics {
+    pub segments: Vec<SegmentDiagnostic>,
+    pub error: Option<&'static str>,
+}
+
+impl ParseDiagnostics {
+    pub fn failed_segment(&self) -> Option<&SegmentDiagnostic> {
+        self.segments.iter().find(|segment| segment.error.is_some())
+    }
+
+    pub(crate) fn record<T, E>(
+        &mut self,
+        part: UrlPart,
+        index: usize,
+        role: SegmentRole,
+        raw: &str,
+        result: Result<T, E>,
+    ) -> Result<T, E>
+    where
+        for<'a> &'a E: Into<&'static str>,
+    {
+        self.segments.push(SegmentDiagnostic {
+            part,
+            index,
+            role,
+            len: raw.len(),
+            error: result.as_ref().err().map(Into::into),
+        });
+        result
+    }
+
+    pub(crate) fn finish<T, E>(&mut self, result: &Result<T, E>)
+    where
+        for<'a> &'a E: Into<&'static str>,
+    {
+        self.error = result.as_ref().err().map(Into::into);
+    }
+}
+
+// e.g. "error: InvalidBucketKey" followed by one "fragment[0] key: 40 bytes, InvalidBucketKey" line per segment.
+impl fmt::Display for ParseDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error {
+            Some(error) => write!(f, "error: {}", error)?,
+            None => f.write_str("ok")?,
+        }
+        for segment in &self.segments {
+            write!(
+                f,
+                "\n{}[{}] {}: {} bytes, {}",
+                segment.part,
+                segment.index,
+                segment.role,
+                segment.len,
+                segment.error.unwrap_or("ok")
+            )?;
+        }
+        Ok(())
+    }
+}
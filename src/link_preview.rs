@@ -0,0 +1,175 @@
+#![cfg(feature = "share_link")]
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::expiry::ExpiryTimestamp;
+use crate::share_link::ShareLink;
+use crate::util::Fingerprint;
+
+/*
+* Public descriptor of a share link, returned to link unfurlers (Slack, Discord, ...).
+* Deliberately never contains the bucket key, the signature or the share link token.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LinkPreview {
+    pub bucket_id: Option<uuid::Uuid>,
+    pub token_fingerprint: Option<Fingerprint>,
+    pub permissions_summary: String,
+    pub expires: Option<ExpiryTimestamp>,
+    pub is_secret: bool,
+}
+
+// "never expires", "expired", "expires in 3 days", ...
+fn expiry_phrase(expires: Option<ExpiryTimestamp>, now: OffsetDateTime) -> String {
+    let Some(expires) = expires else {
+        return "never expires".to_string();
+    };
+    let remaining = OffsetDateTime::from(expires) - now;
+    if !remaining.is_positive() {
+        return "expired".to_string();
+    }
+    let (amount, unit) = if remaining.whole_days() > 0 {
+        (remaining.whole_days(), "day")
+    } else if remaining.whole_hours() > 0 {
+        (remaining.whole_hours(), "hour")
+    } else {
+        (remaining.whole_minutes().max(1), "minute")
+    };
+    match amount {
+        1 => format!("expires in 1 {}", unit),
+        _ => format!("expires in {} {}s", amount, unit),
+    }
+}
+
+impl LinkPreview {
+    pub fn to_og_properties(&self) -> Vec<(String, String)> {
+        self.to_og_properties_at(OffsetDateTime::now_utc())
+    }
+
+    pub fn to_og_properties_at(&self, now: OffsetDateTime) -> Vec<(String, String)> {
+        let title = match self.is_secret {
+            true => "Shared encrypted bucket on BucketDrive",
+            false => "Shared bucket on BucketDrive",
+        };
+        vec![
+            ("og:title".to_string(), title.to_string()),
+            (
+                "og:description".to_string(),
+                format!("{}, {}", self.permissions_summary, expiry_phrase(self.expires, now)),
+            ),
+        ]
+    }
+}
+
+impl ShareLink {
+    // Plain share links only carry the token, so only its fingerprint is exposed.
+    pub fn public_preview(&self) -> LinkPreview {
+        LinkPreview {
+            bucket_id: None,
+            token_fingerprint: Some(Fingerprint::of(&self.token)),
+            permissions_summary: "Shared bucket".to_string(),
+            expires: None,
+            is_secret: false,
+        }
+    }
+}
+
+#[cfg(feature = "secret_share_link")]
+impl crate::secret_share_link::SecretShareLink {
+    pub fn public_preview(&self) -> LinkPreview {
+        LinkPreview {
+            bucket_id: Some(self.bucket_id),
+            token_fingerprint: Some(Fingerprint::of(&self.get_token())),
+            permissions_summary: self.permission.summary(),
+            expires: self.expires,
+            is_secret: true,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "secret_share_link"))]
+mod tests {
+    use aes_gcm::Aes256Gcm;
+    use base64::{Engine, engine::general_purpose};
+    use time::Duration;
+
+    use super::*;
+    use crate::secret_share_link::SecretShareLink;
+    use crate::share_link::BucketSharePermissionFlags;
+
+    fn contains_any_window(haystack: &str, needle: &str, window: usize) -> bool {
+        let needle = needle.as_bytes();
+        needle
+            .windows(window)
+            .any(|part| haystack.contains(std::str::from_utf8(part).unwrap()))
+    }
+
+    #[test]
+    fn preview_never_contains_secrets() {
+        for _ in 0..50 {
+            let key_pair = ed25519_compact::KeyPair::generate();
+            let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&rand::random::<[u8; 32]>());
+            let link = SecretShareLink::new(
+                uuid::Uuid::new_v4(),
+                uuid::Uuid::new_v4(),
+                bucket_key,
+                BucketSharePermissionFlags::from_bits_truncate(rand::random()),
+                Some(ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + Duration::days(3)).unwrap()),
+                &key_pair.sk,
+            );
+            let preview = link.public_preview();
+            let serialized = format!(
+                "{}{:?}{:?}",
+                serde_json::to_string(&preview).unwrap(),
+                preview,
+                preview.to_og_properties()
+            );
+            let key = general_purpose::URL_SAFE_NO_PAD.encode(bucket_key.as_slice());
+            let signature = general_purpose::URL_SAFE_NO_PAD.encode(link.signature.as_slice());
+            assert!(!contains_any_window(&serialized, &key, 8));
+            assert!(!contains_any_window(&serialized, &signature, 8));
+        }
+    }
+
+    #[test]
+    fn plain_preview_never_contains_token() {
+        for _ in 0..50 {
+            let link = ShareLink::new();
+            let preview = link.public_preview();
+            let serialized = format!("{}{:?}", serde_json::to_string(&preview).unwrap(), preview);
+            let token = general_purpose::URL_SAFE_NO_PAD.encode(link.token);
+            assert!(!contains_any_window(&serialized, &token, 8));
+            assert_eq!(preview.token_fingerprint, Some(Fingerprint::of(&link.token)));
+        }
+    }
+
+    #[test]
+    fn og_properties() {
+        let now = OffsetDateTime::now_utc();
+        let preview = LinkPreview {
+            bucket_id: None,
+            token_fingerprint: None,
+            permissions_summary: (BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ).summary(),
+            expires: Some(ExpiryTimestamp::new_at(now + Duration::days(3) + Duration::minutes(1), now).unwrap()),
+            is_secret: true,
+        };
+        assert_eq!(
+            preview.to_og_properties_at(now)[1],
+            (
+                "og:description".to_string(),
+                "View & download, expires in 3 days".to_string()
+            )
+        );
+        assert_eq!(expiry_phrase(None, now), "never expires");
+        let at = |offset: Duration| Some(ExpiryTimestamp::new_at(now + offset, now).unwrap());
+        assert_eq!(expiry_phrase(at(Duration::seconds(-1)), now), "expired");
+        assert_eq!(expiry_phrase(at(Duration::hours(1) + Duration::SECOND), now), "expires in 1 hour");
+        assert_eq!(BucketSharePermissionFlags::empty().summary(), "No access");
+        assert_eq!(
+            (BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ | BucketSharePermissionFlags::WRITE)
+                .summary(),
+            "View, download & upload"
+        );
+    }
+}
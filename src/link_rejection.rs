@@ -0,0 +1,219 @@
+#![cfg(feature = "secret_share_link")]
+
+use serde::{Deserialize, Serialize};
+
+use crate::secret_share_link::{SecretShareLinkParsingError, SecretShareLinkVerifySignatureError};
+
+/*
+* Everything that can reject a secret share link a client presents. The detail is for server logs and the audit log,
+* see LinkAuditEvent::rejected, clients only get the PublicRejectionReason through ApiError.
+*/
+#[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
+pub enum SecretShareLinkVerifyError {
+    #[error(transparent)]
+    Parse(#[from] SecretShareLinkParsingError),
+    #[error(transparent)]
+    Verify(#[from] SecretShareLinkVerifySignatureError),
+    #[error("too many link verifications from this client")]
+    RateLimited,
+}
+
+// What an anonymous client is told about a rejected link, nothing it could probe the verification with.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Hash,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum PublicRejectionReason {
+    LinkInvalid,
+    LinkExpired,
+    RateLimited,
+}
+
+impl PublicRejectionReason {
+    // Fixed per reason, so responses never carry more than the reason itself.
+    pub fn message(&self) -> &'static str {
+        match self {
+            PublicRejectionReason::LinkInvalid => "This link is invalid or incomplete",
+            PublicRejectionReason::LinkExpired => "This link has expired",
+            PublicRejectionReason::RateLimited => "Too many requests, try again later",
+        }
+    }
+
+    // Invalid links are 404 like links that never existed.
+    #[cfg(feature = "http")]
+    pub fn status(&self) -> http::StatusCode {
+        match self {
+            PublicRejectionReason::LinkInvalid => http::StatusCode::NOT_FOUND,
+            PublicRejectionReason::LinkExpired => http::StatusCode::GONE,
+            PublicRejectionReason::RateLimited => http::StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+impl SecretShareLinkVerifyError {
+    /*
+    * Parse, signature and time window failures other than expiry are all LinkInvalid, so a forged link can not be told
+    * from a mistyped one or one that is not valid yet. Expiry is only checked once the signature verified, reporting
+    * it reveals nothing about forged links. Links older than the server's max_age count as expired.
+    * Every variant is listed, a new one has to be placed here on purpose.
+    */
+    pub fn public_reason(&self) -> PublicRejectionReason {
+        match self {
+            SecretShareLinkVerifyError::Parse(_) => PublicRejectionReason::LinkInvalid,
+            SecretShareLinkVerifyError::Verify(error) => match error {
+                SecretShareLinkVerifySignatureError::InvalidSignature(_) => PublicRejectionReason::LinkInvalid,
+                SecretShareLinkVerifySignatureError::NotYetValid { .. } => PublicRejectionReason::LinkInvalid,
+                SecretShareLinkVerifySignatureError::MissingExpiry => PublicRejectionReason::LinkInvalid,
+                SecretShareLinkVerifySignatureError::UnknownIssueTime => PublicRejectionReason::LinkInvalid,
+                SecretShareLinkVerifySignatureError::Expired { .. } => PublicRejectionReason::LinkExpired,
+                SecretShareLinkVerifySignatureError::LinkTooOld { .. } => PublicRejectionReason::LinkExpired,
+            },
+            SecretShareLinkVerifyError::RateLimited => PublicRejectionReason::RateLimited,
+        }
+    }
+
+    // Name of the most specific variant, e.g. "NotYetValid", for the audit log and metrics.
+    pub fn cause(&self) -> &'static str {
+        match self {
+            SecretShareLinkVerifyError::Parse(error) => error.into(),
+            SecretShareLinkVerifyError::Verify(error) => error.into(),
+            SecretShareLinkVerifyError::RateLimited => self.into(),
+        }
+    }
+}
+
+// Body of an error response to a client, built only from a PublicRejectionReason.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ApiError {
+    pub error: PublicRejectionReason,
+    pub message: String,
+}
+
+impl From<PublicRejectionReason> for ApiError {
+    fn from(reason: PublicRejectionReason) -> Self {
+        Self {
+            error: reason,
+            message: reason.message().to_string(),
+        }
+    }
+}
+
+impl From<&SecretShareLinkVerifyError> for ApiError {
+    fn from(error: &SecretShareLinkVerifyError) -> Self {
+        error.public_reason().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::expiry::ExpiryTimestamp;
+
+    fn timestamp(unix: i64) -> ExpiryTimestamp {
+        ExpiryTimestamp::from_unix_timestamp(unix).unwrap()
+    }
+
+    // Every variant, parse errors by example, see public_reason_is_exhaustive.
+    fn every_error() -> Vec<SecretShareLinkVerifyError> {
+        vec![
+            SecretShareLinkParsingError::InvalidScheme.into(),
+            SecretShareLinkParsingError::ChecksumMismatch { expected_len_hint: 180 }.into(),
+            SecretShareLinkParsingError::Signature(ed25519_compact::Error::InvalidSignature).into(),
+            SecretShareLinkVerifySignatureError::InvalidSignature(ed25519_compact::Error::SignatureMismatch).into(),
+            SecretShareLinkVerifySignatureError::NotYetValid { starts_at: timestamp(1_717_200_000) }.into(),
+            SecretShareLinkVerifySignatureError::NotYetValid { starts_at: timestamp(1_717_300_000) }.into(),
+            SecretShareLinkVerifySignatureError::MissingExpiry.into(),
+            SecretShareLinkVerifySignatureError::UnknownIssueTime.into(),
+            SecretShareLinkVerifySignatureError::Expired { expired_at: timestamp(1_717_200_000) }.into(),
+            SecretShareLinkVerifySignatureError::Expired { expired_at: timestamp(1_717_300_000) }.into(),
+            SecretShareLinkVerifySignatureError::LinkTooOld {
+                issued_at: timestamp(1_717_200_000),
+                max: time::Duration::days(30),
+            }
+            .into(),
+            SecretShareLinkVerifyError::RateLimited,
+        ]
+    }
+
+    #[test]
+    fn public_reason_is_exhaustive() {
+        for error in every_error() {
+            let expected = match &error {
+                SecretShareLinkVerifyError::Parse(_) => PublicRejectionReason::LinkInvalid,
+                SecretShareLinkVerifyError::Verify(error) => match error {
+                    SecretShareLinkVerifySignatureError::InvalidSignature(_) => PublicRejectionReason::LinkInvalid,
+                    SecretShareLinkVerifySignatureError::NotYetValid { .. } => PublicRejectionReason::LinkInvalid,
+                    SecretShareLinkVerifySignatureError::MissingExpiry => PublicRejectionReason::LinkInvalid,
+                    SecretShareLinkVerifySignatureError::UnknownIssueTime => PublicRejectionReason::LinkInvalid,
+                    SecretShareLinkVerifySignatureError::Expired { .. } => PublicRejectionReason::LinkExpired,
+                    SecretShareLinkVerifySignatureError::LinkTooOld { .. } => PublicRejectionReason::LinkExpired,
+                },
+                SecretShareLinkVerifyError::RateLimited => PublicRejectionReason::RateLimited,
+            };
+            assert_eq!(error.public_reason(), expected, "{:?}", error);
+        }
+        let source = include_str!("link_rejection.rs");
+        let body = &source[source.find("fn public_reason").unwrap()..source.find("fn cause").unwrap()];
+        assert!(!body.contains("_ =>"));
+    }
+
+    #[test]
+    fn collapsed_causes_serialize_identically() {
+        for reason in PublicRejectionReason::iter() {
+            let bodies = every_error()
+                .iter()
+                .filter(|error| error.public_reason() == reason)
+                .map(|error| serde_json::to_vec(&ApiError::from(error)).unwrap())
+                .collect::<Vec<_>>();
+            assert!(!bodies.is_empty(), "{}", reason);
+            assert!(bodies.iter().all(|body| *body == bodies[0]), "{}", reason);
+        }
+        let invalid = ApiError::from(PublicRejectionReason::LinkInvalid);
+        let json = serde_json::to_string(&invalid).unwrap();
+        assert_eq!(json, r#"{"error":"link_invalid","message":"This link is invalid or incomplete"}"#);
+        assert_eq!(serde_json::from_str::<ApiError>(&json).unwrap(), invalid);
+    }
+
+    #[test]
+    fn causes() {
+        let causes = every_error().iter().map(SecretShareLinkVerifyError::cause).collect::<Vec<_>>();
+        assert_eq!(
+            causes,
+            [
+                "InvalidScheme",
+                "ChecksumMismatch",
+                "Signature",
+                "InvalidSignature",
+                "NotYetValid",
+                "NotYetValid",
+                "MissingExpiry",
+                "UnknownIssueTime",
+                "Expired",
+                "Expired",
+                "LinkTooOld",
+                "RateLimited",
+            ]
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn status() {
+        assert_eq!(PublicRejectionReason::LinkInvalid.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(PublicRejectionReason::LinkExpired.status(), http::StatusCode::GONE);
+        assert_eq!(PublicRejectionReason::RateLimited.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    }
+}
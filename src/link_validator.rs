@@ -0,0 +1,390 @@
+#![cfg(feature = "secret_share_link")]
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::keyring::{Keyring, KeyringEntry};
+use crate::revocation::RevocationList;
+use crate::secret_share_link::{SecretShareLink, VerificationPolicy};
+
+/*
+* Every check LinkValidator runs, in the order it runs them.
+* Policy is the require_expiry part of the plan's VerificationPolicy, MaxAge its max_age part.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumIter, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LinkCheck {
+    Parse,
+    Signature,
+    KeyWindow,
+    NotBefore,
+    Expiry,
+    MaxAge,
+    Revocation,
+    Policy,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum CheckOutcome {
+    Pass,
+    Fail,
+    // The check does not apply, or depends on one that failed.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub check: LinkCheck,
+    pub outcome: CheckOutcome,
+    // Why it failed or was skipped, or what it passed with, for people not for matching.
+    pub detail: Option<String>,
+}
+
+/*
+* Result of LinkValidator::validate, serialized as JSON for support tooling.
+* checks holds every LinkCheck in order. first_failure is the check that makes the link invalid,
+* later failures are still listed so one run shows everything that is wrong.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub first_failure: Option<LinkCheck>,
+    pub checks: Vec<CheckResult>,
+}
+
+impl ValidationReport {
+    fn new(checks: Vec<CheckResult>) -> Self {
+        let first_failure = checks
+            .iter()
+            .find(|result| result.outcome == CheckOutcome::Fail)
+            .map(|result| result.check);
+        Self {
+            valid: first_failure.is_none(),
+            first_failure,
+            checks,
+        }
+    }
+
+    pub fn get(&self, check: LinkCheck) -> Option<&CheckResult> {
+        self.checks.iter().find(|result| result.check == check)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = LinkCheck> + '_ {
+        self.checks
+            .iter()
+            .filter(|result| result.outcome == CheckOutcome::Fail)
+            .map(|result| result.check)
+    }
+}
+
+/*
+* Answers "is this link valid, and if not, exactly why" for support engineers, combining what
+* KeyringVerifier, verify_with_policy and the revocation list check in one report.
+* Unlike them it does not stop at the first failure, only a link that does not parse skips everything else.
+* The fields of an unverified link can not be trusted, they are still checked to explain what the link claims.
+*/
+pub struct LinkValidator {
+    pub keyring: Keyring,
+    pub revocations: Option<RevocationList>,
+    pub policy: VerificationPolicy,
+    pub clock: Box<dyn Clock>,
+}
+
+impl LinkValidator {
+    pub fn validate(&self, input: &str) -> ValidationReport {
+        let now = self.clock.now();
+        let link = match input.trim().parse::<SecretShareLink>() {
+            Ok(link) => link,
+            Err(error) => {
+                let checks = <LinkCheck as strum::IntoEnumIterator>::iter()
+                    .map(|check| match check {
+                        LinkCheck::Parse => fail(check, error.to_string()),
+                        check => skipped(check, "link did not parse"),
+                    })
+                    .collect();
+                return ValidationReport::new(checks);
+            }
+        };
+
+        let signer = self.keyring.entries().iter().find(|entry| entry.verifies(&link));
+        let checks = vec![
+            pass(LinkCheck::Parse, None),
+            match signer {
+                Some(entry) => pass(LinkCheck::Signature, Some(format!("signed by key {}", entry.key_id))),
+                None => fail(LinkCheck::Signature, "no key in the keyring verifies the signature"),
+            },
+            match signer {
+                Some(entry) if entry.is_valid_at(now) => pass(LinkCheck::KeyWindow, None),
+                Some(entry) => fail(LinkCheck::KeyWindow, key_window(entry)),
+                None => skipped(LinkCheck::KeyWindow, "no key verifies the signature"),
+            },
+            match link.not_before {
+                None => skipped(LinkCheck::NotBefore, "link has no not before"),
+                Some(starts_at) if now < starts_at.as_offset_date_time() => {
+                    fail(LinkCheck::NotBefore, format!("link is not valid before {}", starts_at))
+                }
+                Some(starts_at) => pass(LinkCheck::NotBefore, Some(format!("valid since {}", starts_at))),
+            },
+            match link.expires {
+                None => skipped(LinkCheck::Expiry, "link never expires"),
+                Some(expired_at) if now >= expired_at.as_offset_date_time() => {
+                    fail(LinkCheck::Expiry, format!("link expired at {}", expired_at))
+                }
+                Some(expires) => pass(LinkCheck::Expiry, Some(format!("expires at {}", expires))),
+            },
+            match self.policy.max_age {
+                None => skipped(LinkCheck::MaxAge, "policy has no max age"),
+                Some(max) => match link.enforce_max_age(max, now) {
+                    Ok(()) => pass(LinkCheck::MaxAge, None),
+                    Err(error) => fail(LinkCheck::MaxAge, error.to_string()),
+                },
+            },
+            match &self.revocations {
+                None => skipped(LinkCheck::Revocation, "no revocation list given"),
+                Some(revocations) if revocations.contains(&link.share_link_token()) => fail(
+                    LinkCheck::Revocation,
+                    format!("revoked in revocation list serial {}", revocations.serial()),
+                ),
+                Some(revocations) => pass(
+                    LinkCheck::Revocation,
+                    Some(format!("not in revocation list serial {}", revocations.serial())),
+                ),
+            },
+            match (self.policy.require_expiry, link.expires) {
+                (false, _) => skipped(LinkCheck::Policy, "policy does not require an expiry"),
+                (true, None) => fail(LinkCheck::Policy, "policy requires links to expire"),
+                (true, Some(_)) => pass(LinkCheck::Policy, None),
+            },
+        ];
+        ValidationReport::new(checks)
+    }
+}
+
+fn key_window(entry: &KeyringEntry) -> String {
+    match entry.valid_until {
+        Some(valid_until) => format!(
+            "key {} is only valid from {} until {}",
+            entry.key_id, entry.valid_from, valid_until
+        ),
+        None => format!("key {} is only valid from {}", entry.key_id, entry.valid_from),
+    }
+}
+
+fn pass(check: LinkCheck, detail: Option<String>) -> CheckResult {
+    CheckResult {
+        check,
+        outcome: CheckOutcome::Pass,
+        detail,
+    }
+}
+
+fn fail(check: LinkCheck, detail: impl Into<String>) -> CheckResult {
+    CheckResult {
+        check,
+        outcome: CheckOutcome::Fail,
+        detail: Some(detail.into()),
+    }
+}
+
+fn skipped(check: LinkCheck, detail: &str) -> CheckResult {
+    CheckResult {
+        check,
+        outcome: CheckOutcome::Skipped,
+        detail: Some(detail.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+    use time::{Duration, OffsetDateTime};
+
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::expiry::ExpiryTimestamp;
+    use crate::share_link::BucketSharePermissionFlags;
+
+    const NOW: i64 = 1_717_200_000;
+
+    fn at(offset: Duration) -> ExpiryTimestamp {
+        ExpiryTimestamp::try_from(OffsetDateTime::from_unix_timestamp(NOW).unwrap() + offset).unwrap()
+    }
+
+    fn key_pair(seed: u8) -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([seed; 32]))
+    }
+
+    fn validator() -> LinkValidator {
+        let entry = |key_id: &str, seed: u8, valid_until: Option<ExpiryTimestamp>| KeyringEntry {
+            key_id: key_id.parse().unwrap(),
+            public_key: *key_pair(seed).pk,
+            valid_from: at(-Duration::days(365)),
+            valid_until,
+        };
+        LinkValidator {
+            keyring: Keyring::new(vec![
+                entry("links-2024", 1, None),
+                entry("links-2023", 2, Some(at(-Duration::days(30)))),
+            ])
+            .unwrap(),
+            revocations: Some(RevocationList::new(4, Vec::new())),
+            policy: VerificationPolicy {
+                require_expiry: true,
+                max_age: Some(Duration::days(30)),
+            },
+            clock: Box::new(FixedClock(OffsetDateTime::from_unix_timestamp(NOW).unwrap())),
+        }
+    }
+
+    // Valid for validator() unless a test changes one thing.
+    fn link(seed: u8, not_before: Option<ExpiryTimestamp>, expires: Option<ExpiryTimestamp>) -> SecretShareLink {
+        issued(seed, not_before, expires, Duration::days(1))
+    }
+
+    fn issued(
+        seed: u8,
+        not_before: Option<ExpiryTimestamp>,
+        expires: Option<ExpiryTimestamp>,
+        age: Duration,
+    ) -> SecretShareLink {
+        let mut builder =
+            SecretShareLink::builder(uuid::Uuid::from_u128(1), uuid::Uuid::from_u128(2), [7u8; 32].into())
+                .permission(BucketSharePermissionFlags::READ);
+        if let Some(not_before) = not_before {
+            builder = builder.not_before(not_before);
+        }
+        if let Some(expires) = expires {
+            builder = builder.expires(expires);
+        }
+        let clock = FixedClock(at(-age).as_offset_date_time());
+        builder.build_with_clock(&key_pair(seed).sk, &clock).unwrap()
+    }
+
+    fn valid_link() -> SecretShareLink {
+        link(1, Some(at(-Duration::days(1))), Some(at(Duration::days(7))))
+    }
+
+    #[test]
+    fn valid_link_passes_every_check() {
+        let report = validator().validate(&valid_link().to_string());
+        assert!(report.valid, "{:?}", report);
+        assert_eq!(report.first_failure, None);
+        assert_eq!(
+            report.checks.iter().map(|result| result.check).collect::<Vec<_>>(),
+            LinkCheck::iter().collect::<Vec<_>>()
+        );
+        assert!(report.checks.iter().all(|result| result.outcome == CheckOutcome::Pass));
+        assert_eq!(
+            report.get(LinkCheck::Signature).unwrap().detail.as_deref(),
+            Some("signed by key links-2024")
+        );
+    }
+
+    #[test]
+    fn each_failing_check_is_flagged_alone() {
+        let mut tampered = valid_link().to_string();
+        tampered.push('A');
+        let mut revoked = validator();
+        revoked.revocations = Some(RevocationList::new(5, vec![valid_link().share_link_token()]));
+        let too_old = issued(1, None, Some(at(Duration::days(7))), Duration::days(40));
+
+        let cases = [
+            (
+                validator(),
+                "https://example.com/not-a-link".to_string(),
+                LinkCheck::Parse,
+            ),
+            (
+                validator(),
+                link(3, None, Some(at(Duration::days(7)))).to_string(),
+                LinkCheck::Signature,
+            ),
+            (
+                validator(),
+                link(2, None, Some(at(Duration::days(7)))).to_string(),
+                LinkCheck::KeyWindow,
+            ),
+            (
+                validator(),
+                link(1, Some(at(Duration::hours(1))), Some(at(Duration::days(7)))).to_string(),
+                LinkCheck::NotBefore,
+            ),
+            (
+                validator(),
+                link(1, None, Some(at(-Duration::seconds(1)))).to_string(),
+                LinkCheck::Expiry,
+            ),
+            (validator(), too_old.to_string(), LinkCheck::MaxAge),
+            (revoked, valid_link().to_string(), LinkCheck::Revocation),
+            (validator(), link(1, None, None).to_string(), LinkCheck::Policy),
+        ];
+        assert_eq!(cases.len(), LinkCheck::iter().count());
+        for (validator, input, check) in cases {
+            let report = validator.validate(&input);
+            assert!(!report.valid);
+            assert_eq!(report.first_failure, Some(check));
+            assert_eq!(report.failures().collect::<Vec<_>>(), [check], "{:?}", report);
+            assert!(report.get(check).unwrap().detail.is_some());
+        }
+    }
+
+    #[test]
+    fn dependent_checks_are_skipped() {
+        let report = validator().validate("not a link");
+        assert_eq!(report.first_failure, Some(LinkCheck::Parse));
+        assert!(report.checks[1..]
+            .iter()
+            .all(|result| result.outcome == CheckOutcome::Skipped));
+
+        let report = validator().validate(&link(3, None, Some(at(Duration::days(7)))).to_string());
+        assert_eq!(report.get(LinkCheck::KeyWindow).unwrap().outcome, CheckOutcome::Skipped);
+
+        // Nothing optional configured and nothing optional set on the link.
+        let mut lenient = validator();
+        lenient.revocations = None;
+        lenient.policy = VerificationPolicy::default();
+        let report = lenient.validate(&link(1, None, None).to_string());
+        assert!(report.valid);
+        let skipped = report
+            .checks
+            .iter()
+            .filter(|result| result.outcome == CheckOutcome::Skipped)
+            .map(|result| result.check)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            skipped,
+            [
+                LinkCheck::NotBefore,
+                LinkCheck::Expiry,
+                LinkCheck::MaxAge,
+                LinkCheck::Revocation,
+                LinkCheck::Policy
+            ]
+        );
+    }
+
+    #[test]
+    fn report_json() {
+        let report = validator().validate(&link(1, None, None).to_string());
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["valid"], false);
+        assert_eq!(json["first_failure"], "policy");
+        assert_eq!(
+            json["checks"][0],
+            serde_json::json!({"check": "parse", "outcome": "pass", "detail": null})
+        );
+        assert_eq!(json["checks"][7]["outcome"], "fail");
+        assert_eq!(serde_json::from_value::<ValidationReport>(json).unwrap(), report);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn validate_never_panics(s in "\\PC*") {
+            let report = validator().validate(&s);
+            proptest::prop_assert_eq!(report.checks.len(), LinkCheck::iter().count());
+        }
+    }
+}
@@ -0,0 +1,244 @@
+#![cfg(feature = "secret_share_link")]
+
+use crate::clock::{Clock, SystemClock};
+use crate::keyring::{Keyring, KeyringError};
+use crate::secret_share_link::{SecretShareLink, SecretShareLinkVerifySignatureError};
+
+/*
+* Checks a secret share link, signature verification is CPU bound.
+* Async services should not call it on the runtime, see SpawnBlockingVerifier.
+*/
+pub trait LinkVerifier {
+    type Error;
+
+    fn verify(&self, link: &SecretShareLink) -> Result<(), Self::Error>;
+}
+
+// Signature and time window against a single key.
+#[derive(Debug, Clone)]
+pub struct PublicKeyVerifier<C = SystemClock> {
+    pub public_key: ed25519_compact::PublicKey,
+    pub clock: C,
+}
+
+impl PublicKeyVerifier {
+    pub fn new(public_key: ed25519_compact::PublicKey) -> Self {
+        Self {
+            public_key,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<C: Clock> LinkVerifier for PublicKeyVerifier<C> {
+    type Error = SecretShareLinkVerifySignatureError;
+
+    fn verify(&self, link: &SecretShareLink) -> Result<(), Self::Error> {
+        link.verify(self.public_key, self.clock.now())
+    }
+}
+
+// Picks the key from a keyring, see Keyring::verify_link.
+#[derive(Debug, Clone)]
+pub struct KeyringVerifier<C = SystemClock> {
+    pub keyring: Keyring,
+    pub clock: C,
+}
+
+impl KeyringVerifier {
+    pub fn new(keyring: Keyring) -> Self {
+        Self {
+            keyring,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<C: Clock> LinkVerifier for KeyringVerifier<C> {
+    type Error = KeyringError;
+
+    fn verify(&self, link: &SecretShareLink) -> Result<(), Self::Error> {
+        self.keyring.verify_link(link, self.clock.now())
+    }
+}
+
+#[cfg(feature = "async")]
+pub use offload::{AsyncLinkVerifier, SpawnBlockingVerifier};
+
+#[cfg(feature = "async")]
+mod offload {
+    use std::future::Future;
+    use std::sync::Arc;
+
+    use super::LinkVerifier;
+    use crate::secret_share_link::SecretShareLink;
+
+    pub trait AsyncLinkVerifier {
+        type Error;
+
+        fn verify(&self, link: &SecretShareLink) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    }
+
+    /*
+    * Runs a LinkVerifier on the tokio blocking pool, the errors are the ones of the wrapped verifier.
+    * The link is cloned into the blocking task. A panic in the verifier is resumed in the awaiting task.
+    */
+    #[derive(Debug)]
+    pub struct SpawnBlockingVerifier<T> {
+        inner: Arc<T>,
+    }
+
+    impl<T> SpawnBlockingVerifier<T> {
+        pub fn new(inner: T) -> Self {
+            Self { inner: Arc::new(inner) }
+        }
+
+        pub fn inner(&self) -> &T {
+            &self.inner
+        }
+    }
+
+    impl<T> Clone for SpawnBlockingVerifier<T> {
+        fn clone(&self) -> Self {
+            Self {
+                inner: Arc::clone(&self.inner),
+            }
+        }
+    }
+
+    impl<T> AsyncLinkVerifier for SpawnBlockingVerifier<T>
+    where
+        T: LinkVerifier + Send + Sync + 'static,
+        T::Error: Send + 'static,
+    {
+        type Error = T::Error;
+
+        fn verify(&self, link: &SecretShareLink) -> impl Future<Output = Result<(), Self::Error>> + Send {
+            let inner = Arc::clone(&self.inner);
+            let link = link.clone();
+            async move {
+                match tokio::task::spawn_blocking(move || inner.verify(&link)).await {
+                    Ok(result) => result,
+                    Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+                    // Only happens while the runtime shuts down.
+                    Err(error) => panic!("link verification task did not finish: {}", error),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes_gcm::Aes256Gcm;
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::expiry::ExpiryTimestamp;
+    use crate::keyring::KeyringEntry;
+    use crate::share_link::BucketSharePermissionFlags;
+
+    fn key_pair(seed: u8) -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([seed; 32]))
+    }
+
+    fn link(key_pair: &ed25519_compact::KeyPair) -> SecretShareLink {
+        SecretShareLink::new(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7u8; 32]),
+            BucketSharePermissionFlags::READ,
+            None,
+            &key_pair.sk,
+        )
+    }
+
+    fn keyring_verifier() -> KeyringVerifier<FixedClock> {
+        let keyring = Keyring::new(vec![KeyringEntry {
+            key_id: "links-2024".parse().unwrap(),
+            public_key: *key_pair(1).pk,
+            valid_from: ExpiryTimestamp::MIN,
+            valid_until: None,
+        }])
+        .unwrap();
+        KeyringVerifier {
+            keyring,
+            clock: FixedClock(OffsetDateTime::from_unix_timestamp(1_717_200_000).unwrap()),
+        }
+    }
+
+    #[test]
+    fn sync_verifiers() {
+        assert!(keyring_verifier().verify(&link(&key_pair(1))).is_ok());
+        assert!(matches!(keyring_verifier().verify(&link(&key_pair(2))), Err(KeyringError::NoValidKey)));
+
+        let verifier = PublicKeyVerifier::new(key_pair(1).pk);
+        assert_eq!(verifier.verify(&link(&key_pair(1))), Ok(()));
+        assert!(matches!(
+            verifier.verify(&link(&key_pair(2))),
+            Err(SecretShareLinkVerifySignatureError::InvalidSignature(_))
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    mod offload {
+        use std::sync::mpsc;
+        use std::sync::Mutex;
+        use std::time::Duration;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn errors_are_preserved() {
+            let verifier = SpawnBlockingVerifier::new(keyring_verifier());
+            assert!(AsyncLinkVerifier::verify(&verifier, &link(&key_pair(1))).await.is_ok());
+            let result = AsyncLinkVerifier::verify(&verifier, &link(&key_pair(2))).await;
+            assert!(matches!(result, Err(KeyringError::NoValidKey)));
+
+            let verifier = SpawnBlockingVerifier::new(PublicKeyVerifier::new(key_pair(1).pk));
+            let result = AsyncLinkVerifier::verify(&verifier, &link(&key_pair(2))).await;
+            assert!(matches!(result, Err(SecretShareLinkVerifySignatureError::InvalidSignature(_))));
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+        async fn many_links_concurrently() {
+            let verifier = SpawnBlockingVerifier::new(keyring_verifier());
+            let mut tasks = tokio::task::JoinSet::new();
+            for index in 0..256 {
+                let verifier = verifier.clone();
+                let link = link(&key_pair(if index % 4 == 0 { 2 } else { 1 }));
+                tasks.spawn(async move { AsyncLinkVerifier::verify(&verifier, &link).await.is_ok() });
+            }
+            let results = tokio::time::timeout(Duration::from_secs(30), tasks.join_all()).await.unwrap();
+            assert_eq!(results.iter().filter(|ok| **ok).count(), 192);
+        }
+
+        // Blocks until released from the runtime, or fails after a timeout if it was run on the runtime thread.
+        struct Gate(Mutex<mpsc::Receiver<()>>);
+
+        impl LinkVerifier for Gate {
+            type Error = mpsc::RecvTimeoutError;
+
+            fn verify(&self, _link: &SecretShareLink) -> Result<(), Self::Error> {
+                self.0.lock().unwrap().recv_timeout(Duration::from_secs(5))
+            }
+        }
+
+        // Single threaded runtime, the release can only be sent if verification is not blocking it.
+        #[tokio::test(flavor = "current_thread")]
+        async fn does_not_block_the_runtime() {
+            let (release, gate) = mpsc::channel();
+            let verifier = SpawnBlockingVerifier::new(Gate(Mutex::new(gate)));
+            let link = link(&key_pair(1));
+            let task = tokio::spawn({
+                let verifier = verifier.clone();
+                async move { AsyncLinkVerifier::verify(&verifier, &link).await }
+            });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(!task.is_finished());
+            release.send(()).unwrap();
+            assert_eq!(task.await.unwrap(), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bucket_object::BucketObjectKey;
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::manifest::ManifestEntry;
+use crate::util::truncate_input;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Hash, Default, strum::EnumString, strum::Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Hash, Default, strum::EnumString, strum::Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/*
+* Sort key and order as one string: "name" sorts ascending and "-name" descending.
+* The legacy "name_asc" and "name_desc" forms are accepted as well, Display always uses the short form.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct SortSpec {
+    pub key: SortKey,
+    pub order: SortOrder,
+}
+
+// Cursor is the opaque continuation token returned with the previous page.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PageRequest {
+    pub limit: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+// One page of a listing, next_cursor is passed as the cursor of the following PageRequest and missing on the last page.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListObjectsOptions {
+    pub prefix: Option<BucketObjectKey>,
+    // Groups keys up to the next delimiter into a single entry, like folders.
+    pub delimiter: Option<char>,
+    pub sort: SortKey,
+    pub order: SortOrder,
+    pub include_deleted: bool,
+    pub page: PageRequest,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum ListObjectsError {
+    #[error("invalid sort \"{0}\"")]
+    InvalidSort(String),
+    #[error("delimiter {0:?} is not supported, use '/' or '.'")]
+    InvalidDelimiter(char),
+    #[error("page limit {0} is outside 1..={MAX_PAGE_SIZE}")]
+    InvalidPageLimit(u32),
+}
+
+impl SpannedParseError for ListObjectsError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            ListObjectsError::InvalidSort(input) => ParseFailure::whole(self, input),
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_PAGE_SIZE,
+            cursor: None,
+        }
+    }
+}
+
+impl<T> Page<T> {
+    pub fn is_last(&self) -> bool {
+        self.next_cursor.is_none()
+    }
+
+    // Request for the page after this one, None on the last page.
+    pub fn next_request(&self, limit: u32) -> Option<PageRequest> {
+        self.next_cursor.clone().map(|cursor| PageRequest {
+            limit,
+            cursor: Some(cursor),
+        })
+    }
+}
+
+impl fmt::Display for SortSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.order == SortOrder::Desc {
+            f.write_str("-")?;
+        }
+        self.key.fmt(f)
+    }
+}
+
+impl FromStr for SortSpec {
+    type Err = ListObjectsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ListObjectsError::InvalidSort(truncate_input(s));
+        let (key, order) = match (s.strip_prefix('-'), s.split_once('_')) {
+            (Some(key), _) => (key, SortOrder::Desc),
+            (None, Some((key, order))) => (key, order.parse().map_err(|_| invalid())?),
+            (None, None) => (s, SortOrder::Asc),
+        };
+        Ok(Self {
+            key: key.parse().map_err(|_| invalid())?,
+            order,
+        })
+    }
+}
+
+impl ListObjectsOptions {
+    pub fn sort_spec(&self) -> SortSpec {
+        SortSpec {
+            key: self.sort,
+            order: self.order,
+        }
+    }
+
+    pub fn with_sort(mut self, spec: SortSpec) -> Self {
+        self.sort = spec.key;
+        self.order = spec.order;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ListObjectsError> {
+        if let Some(delimiter) = self.delimiter {
+            if delimiter != '/' && delimiter != '.' {
+                return Err(ListObjectsError::InvalidDelimiter(delimiter));
+            }
+        }
+        if self.page.limit == 0 || self.page.limit > MAX_PAGE_SIZE {
+            return Err(ListObjectsError::InvalidPageLimit(self.page.limit));
+        }
+        Ok(())
+    }
+
+    /*
+    * Total order used for listings: entries are compared by the sort key, then by object key.
+    * Desc reverses the whole comparison including the tiebreaker, so a descending listing is exactly the ascending one reversed.
+    * Keys are unique within a listing, which makes the object key tiebreaker final.
+    */
+    pub fn compare(&self, a: &ManifestEntry, b: &ManifestEntry) -> Ordering {
+        let ordering = match self.sort {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        }
+        .then_with(|| a.key.cmp(&b.key));
+        match self.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::bucket_object::ContentHash;
+
+    fn entry(key: &str, size: u64, modified: OffsetDateTime) -> ManifestEntry {
+        ManifestEntry {
+            key: key.parse().unwrap(),
+            size,
+            hash: ContentHash::of(key.as_bytes()),
+            modified,
+            deleted: false,
+        }
+    }
+
+    #[test]
+    fn combined_sort_strings() {
+        let spec = |key, order| Ok(SortSpec { key, order });
+        for (input, expected) in [
+            ("name", spec(SortKey::Name, SortOrder::Asc)),
+            ("-name", spec(SortKey::Name, SortOrder::Desc)),
+            ("-size", spec(SortKey::Size, SortOrder::Desc)),
+            ("modified", spec(SortKey::Modified, SortOrder::Asc)),
+            ("name_desc", spec(SortKey::Name, SortOrder::Desc)),
+            ("size_asc", spec(SortKey::Size, SortOrder::Asc)),
+        ] {
+            assert_eq!(input.parse::<SortSpec>(), expected, "{}", input);
+        }
+        for input in ["", "-", "--name", "Name", "owner", "name_up", "-name_desc", "name_"] {
+            assert_eq!(
+                input.parse::<SortSpec>(),
+                Err(ListObjectsError::InvalidSort(input.to_string())),
+                "{}",
+                input
+            );
+        }
+        assert_eq!("name_desc".parse::<SortSpec>().unwrap().to_string(), "-name");
+        assert_eq!("size".parse::<SortSpec>().unwrap().to_string(), "size");
+    }
+
+    #[test]
+    fn key_is_final_tiebreaker() {
+        let noon = datetime!(2024-06-01 12:00 UTC);
+        let mut entries = vec![
+            entry("/c", 10, noon),
+            entry("/a", 20, noon),
+            entry("/b", 10, noon),
+            entry("/d", 5, datetime!(2024-06-02 12:00 UTC)),
+        ];
+        let keys = |entries: &[ManifestEntry]| entries.iter().map(|entry| entry.key.to_string()).collect::<Vec<_>>();
+
+        let by_size = ListObjectsOptions::default().with_sort("size".parse().unwrap());
+        entries.sort_by(|a, b| by_size.compare(a, b));
+        assert_eq!(keys(&entries), ["/d", "/b", "/c", "/a"]);
+
+        let by_size_desc = ListObjectsOptions::default().with_sort("-size".parse().unwrap());
+        entries.sort_by(|a, b| by_size_desc.compare(a, b));
+        assert_eq!(keys(&entries), ["/a", "/c", "/b", "/d"]);
+
+        let by_modified = ListObjectsOptions::default().with_sort("modified".parse().unwrap());
+        entries.sort_by(|a, b| by_modified.compare(a, b));
+        assert_eq!(keys(&entries), ["/a", "/b", "/c", "/d"]);
+
+        let by_name_desc = ListObjectsOptions::default().with_sort("-name".parse().unwrap());
+        entries.sort_by(|a, b| by_name_desc.compare(a, b));
+        assert_eq!(keys(&entries), ["/d", "/c", "/b", "/a"]);
+    }
+
+    #[test]
+    fn validate_delimiter_and_page() {
+        let mut options = ListObjectsOptions::default();
+        assert_eq!(options.validate(), Ok(()));
+        for delimiter in ['/', '.'] {
+            options.delimiter = Some(delimiter);
+            assert_eq!(options.validate(), Ok(()));
+        }
+        options.delimiter = Some('-');
+        assert_eq!(options.validate(), Err(ListObjectsError::InvalidDelimiter('-')));
+
+        let mut options = ListObjectsOptions::default();
+        options.page.limit = 0;
+        assert_eq!(options.validate(), Err(ListObjectsError::InvalidPageLimit(0)));
+        options.page.limit = MAX_PAGE_SIZE + 1;
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn serde_defaults() {
+        let options: ListObjectsOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options, ListObjectsOptions::default());
+        assert_eq!(options.sort, SortKey::Name);
+        assert_eq!(options.order, SortOrder::Asc);
+        assert_eq!(options.page.limit, DEFAULT_PAGE_SIZE);
+        assert!(!options.include_deleted);
+
+        let options: ListObjectsOptions =
+            serde_json::from_str(r#"{"prefix":"/photos/","sort":"modified","order":"desc","page":{"cursor":"abc"}}"#)
+                .unwrap();
+        assert_eq!(options.sort_spec().to_string(), "-modified");
+        assert_eq!(options.page.limit, DEFAULT_PAGE_SIZE);
+        assert_eq!(options.page.cursor.as_deref(), Some("abc"));
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(serde_json::from_str::<ListObjectsOptions>(&json).unwrap(), options);
+    }
+}
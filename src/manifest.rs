@@ -0,0 +1,515 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::bucket_object::{BucketObjectKey, BucketObjectKeyError, ContentHash};
+use crate::framing::{expect_header, write_header, BinaryFormat, FramingError};
+
+pub const MANIFEST_DELTA_VERSION: u8 = 1;
+
+// One object in a bucket listing. Deleted entries are kept as tombstones until the deletion has been synced.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub key: BucketObjectKey,
+    pub size: u64,
+    pub hash: ContentHash,
+    #[serde(with = "time::serde::rfc3339")]
+    pub modified: OffsetDateTime,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+/*
+* Every object of a bucket by key, serialized as the entries in key order.
+* Deserializing entries with the same key keeps the last one, the same as inserting them one after another.
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<ManifestEntry>", into = "Vec<ManifestEntry>")]
+pub struct BucketManifest(BTreeMap<BucketObjectKey, ManifestEntry>);
+
+impl BucketManifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &BucketObjectKey) -> Option<&ManifestEntry> {
+        self.0.get(key)
+    }
+
+    // Entries in key order.
+    pub fn entries(&self) -> impl Iterator<Item = &ManifestEntry> + '_ {
+        self.0.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Replaces and returns the entry with the same key.
+    pub fn insert(&mut self, entry: ManifestEntry) -> Option<ManifestEntry> {
+        self.0.insert(entry.key.clone(), entry)
+    }
+
+    pub fn remove(&mut self, key: &BucketObjectKey) -> Option<ManifestEntry> {
+        self.0.remove(key)
+    }
+
+    // SHA3-256 over the binary encoding of every entry in key order, the same encoding ManifestDelta uses.
+    pub fn digest(&self) -> ContentHash {
+        let mut hasher = Sha3_256::new();
+        let mut buffer = Vec::new();
+        for entry in self.entries() {
+            buffer.clear();
+            write_entry(entry, &mut buffer);
+            hasher.update(&buffer);
+        }
+        ContentHash(hasher.finalize().into())
+    }
+
+    // What turns base into self, apply it to base to get self back.
+    pub fn delta_from(&self, base: &BucketManifest) -> ManifestDelta {
+        let mut delta = ManifestDelta {
+            base_digest: base.digest(),
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+        for entry in self.entries() {
+            match base.get(&entry.key) {
+                None => delta.added.push(entry.clone()),
+                Some(base_entry) if base_entry != entry => delta.changed.push(entry.clone()),
+                Some(_) => {}
+            }
+        }
+        delta.removed = base.0.keys().filter(|key| !self.0.contains_key(*key)).cloned().collect();
+        delta
+    }
+}
+
+impl FromIterator<ManifestEntry> for BucketManifest {
+    fn from_iter<T: IntoIterator<Item = ManifestEntry>>(iter: T) -> Self {
+        let mut manifest = Self::new();
+        for entry in iter {
+            manifest.insert(entry);
+        }
+        manifest
+    }
+}
+
+impl From<Vec<ManifestEntry>> for BucketManifest {
+    fn from(value: Vec<ManifestEntry>) -> Self {
+        value.into_iter().collect()
+    }
+}
+
+impl From<BucketManifest> for Vec<ManifestEntry> {
+    fn from(value: BucketManifest) -> Self {
+        value.0.into_values().collect()
+    }
+}
+
+/*
+* Difference between two manifests, so clients that hold the base only download what changed.
+* Every list is in ascending key order and no key appears in more than one list.
+* Binary form: frame header (see framing), base digest, then added, removed and changed, each a count (u32 BE) and
+* its items. Entries are the key length (u16 BE) and key, size (u64 BE), hash, modified as unix seconds (i64 BE),
+* nanoseconds (u32 BE) and UTC offset seconds (i32 BE), and a deleted byte of 0 or 1. Removed keys are length and key.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ManifestDelta {
+    pub base_digest: ContentHash,
+    pub added: Vec<ManifestEntry>,
+    pub removed: Vec<BucketObjectKey>,
+    pub changed: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum DeltaApplyError {
+    #[error("delta is for a manifest with digest {expected}, not {actual}")]
+    BaseMismatch { expected: ContentHash, actual: ContentHash },
+    #[error("delta adds {0}, which is already in the manifest")]
+    AlreadyPresent(BucketObjectKey),
+    #[error("delta removes or changes {0}, which is not in the manifest")]
+    NotPresent(BucketObjectKey),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum ManifestDeltaError {
+    #[error("unsupported manifest delta version {0}")]
+    UnsupportedVersion(u8),
+    #[error("manifest delta ended early")]
+    Truncated,
+    #[error("manifest delta has data after the changed entries")]
+    TrailingData,
+    #[error("object key is not utf-8")]
+    KeyNotUtf8,
+    #[error(transparent)]
+    InvalidKey(#[from] BucketObjectKeyError),
+    #[error("{0} is out of order or appears more than once")]
+    Unsorted(BucketObjectKey),
+    #[error("modified time of {0} is out of range")]
+    InvalidModified(BucketObjectKey),
+    #[error("deleted flag of {key} is {flag}, expected 0 or 1")]
+    InvalidDeletedFlag { key: BucketObjectKey, flag: u8 },
+    #[error(transparent)]
+    Framing(#[from] FramingError),
+}
+
+impl ManifestDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    // Refuses any base but the one the delta was made from.
+    pub fn apply(&self, base: &BucketManifest) -> Result<BucketManifest, DeltaApplyError> {
+        let actual = base.digest();
+        if actual != self.base_digest {
+            return Err(DeltaApplyError::BaseMismatch {
+                expected: self.base_digest,
+                actual,
+            });
+        }
+        let mut manifest = base.clone();
+        for key in &self.removed {
+            manifest.remove(key).ok_or_else(|| DeltaApplyError::NotPresent(key.clone()))?;
+        }
+        for entry in &self.changed {
+            if manifest.insert(entry.clone()).is_none() {
+                return Err(DeltaApplyError::NotPresent(entry.key.clone()));
+            }
+        }
+        for entry in &self.added {
+            if manifest.insert(entry.clone()).is_some() {
+                return Err(DeltaApplyError::AlreadyPresent(entry.key.clone()));
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_header(BinaryFormat::ManifestDelta, MANIFEST_DELTA_VERSION, &mut bytes);
+        bytes.extend_from_slice(&self.base_digest.0);
+        bytes.extend_from_slice(&(self.added.len() as u32).to_be_bytes());
+        for entry in &self.added {
+            write_entry(entry, &mut bytes);
+        }
+        bytes.extend_from_slice(&(self.removed.len() as u32).to_be_bytes());
+        for key in &self.removed {
+            write_key(key, &mut bytes);
+        }
+        bytes.extend_from_slice(&(self.changed.len() as u32).to_be_bytes());
+        for entry in &self.changed {
+            write_entry(entry, &mut bytes);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ManifestDeltaError> {
+        let (version, body) = expect_header(bytes, BinaryFormat::ManifestDelta)?;
+        if version != MANIFEST_DELTA_VERSION {
+            return Err(ManifestDeltaError::UnsupportedVersion(version));
+        }
+        let mut reader = ByteReader(body);
+        let base_digest = ContentHash(reader.take()?);
+        let added = reader.sorted(ByteReader::entry)?;
+        let removed = reader.sorted(ByteReader::key)?;
+        let changed = reader.sorted(ByteReader::entry)?;
+        if !reader.0.is_empty() {
+            return Err(ManifestDeltaError::TrailingData);
+        }
+        Ok(Self {
+            base_digest,
+            added,
+            removed,
+            changed,
+        })
+    }
+}
+
+fn write_key(key: &BucketObjectKey, out: &mut Vec<u8>) {
+    // At most MAX_OBJECT_KEY_LEN, always fits.
+    out.extend_from_slice(&(key.as_str().len() as u16).to_be_bytes());
+    out.extend_from_slice(key.as_str().as_bytes());
+}
+
+fn write_entry(entry: &ManifestEntry, out: &mut Vec<u8>) {
+    write_key(&entry.key, out);
+    out.extend_from_slice(&entry.size.to_be_bytes());
+    out.extend_from_slice(&entry.hash.0);
+    out.extend_from_slice(&entry.modified.unix_timestamp().to_be_bytes());
+    out.extend_from_slice(&entry.modified.nanosecond().to_be_bytes());
+    out.extend_from_slice(&entry.modified.offset().whole_seconds().to_be_bytes());
+    out.push(entry.deleted as u8);
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8], ManifestDeltaError> {
+        if self.0.len() < len {
+            return Err(ManifestDeltaError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ManifestDeltaError> {
+        Ok(self.take_slice(N)?.try_into().expect("slice has length N"))
+    }
+
+    fn key(&mut self) -> Result<BucketObjectKey, ManifestDeltaError> {
+        let len = u16::from_be_bytes(self.take()?) as usize;
+        let key = std::str::from_utf8(self.take_slice(len)?).map_err(|_| ManifestDeltaError::KeyNotUtf8)?;
+        Ok(key.parse()?)
+    }
+
+    fn entry(&mut self) -> Result<ManifestEntry, ManifestDeltaError> {
+        let key = self.key()?;
+        let size = u64::from_be_bytes(self.take()?);
+        let hash = ContentHash(self.take()?);
+        let seconds = i64::from_be_bytes(self.take()?);
+        let nanoseconds = u32::from_be_bytes(self.take()?);
+        let offset = i32::from_be_bytes(self.take()?);
+        let modified = OffsetDateTime::from_unix_timestamp(seconds)
+            .and_then(|modified| modified.replace_nanosecond(nanoseconds))
+            .ok()
+            .zip(UtcOffset::from_whole_seconds(offset).ok())
+            .map(|(modified, offset)| modified.to_offset(offset))
+            .ok_or_else(|| ManifestDeltaError::InvalidModified(key.clone()))?;
+        let deleted = match self.take::<1>()?[0] {
+            0 => false,
+            1 => true,
+            flag => return Err(ManifestDeltaError::InvalidDeletedFlag { key, flag }),
+        };
+        Ok(ManifestEntry {
+            key,
+            size,
+            hash,
+            modified,
+            deleted,
+        })
+    }
+
+    // A count followed by that many items in strictly ascending key order, so every delta has a single encoding.
+    fn sorted<T: Keyed>(
+        &mut self,
+        item: fn(&mut Self) -> Result<T, ManifestDeltaError>,
+    ) -> Result<Vec<T>, ManifestDeltaError> {
+        let count = u32::from_be_bytes(self.take()?);
+        // The count comes from untrusted input, don't preallocate for it.
+        let mut items: Vec<T> = Vec::new();
+        for _ in 0..count {
+            let next = item(self)?;
+            if items.last().is_some_and(|last| last.key() >= next.key()) {
+                return Err(ManifestDeltaError::Unsorted(next.key().clone()));
+            }
+            items.push(next);
+        }
+        Ok(items)
+    }
+}
+
+trait Keyed {
+    fn key(&self) -> &BucketObjectKey;
+}
+
+impl Keyed for BucketObjectKey {
+    fn key(&self) -> &BucketObjectKey {
+        self
+    }
+}
+
+impl Keyed for ManifestEntry {
+    fn key(&self) -> &BucketObjectKey {
+        &self.key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::canonical_json::to_canonical_vec;
+
+    fn entry(index: u32, version: u8) -> ManifestEntry {
+        ManifestEntry {
+            key: format!("/objects/{:06}.bin", index).parse().unwrap(),
+            size: index as u64 * 100 + version as u64,
+            hash: ContentHash::of(&[index.to_be_bytes().as_slice(), &[version]].concat()),
+            modified: datetime!(2024-06-01 12:00 UTC) + time::Duration::seconds(version as i64),
+            deleted: false,
+        }
+    }
+
+    fn manifest(len: u32) -> BucketManifest {
+        (0..len).map(|index| entry(index, 0)).collect()
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let entry = ManifestEntry {
+            key: "/photos/beach.jpg".parse().unwrap(),
+            size: 1024,
+            hash: ContentHash::of(b"beach"),
+            modified: datetime!(2024-06-01 12:00 UTC),
+            deleted: false,
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains("\"2024-06-01T12:00:00Z\""));
+        assert_eq!(serde_json::from_str::<ManifestEntry>(&json).unwrap(), entry);
+        let without_deleted = json.replace(",\"deleted\":false", "");
+        assert_eq!(serde_json::from_str::<ManifestEntry>(&without_deleted).unwrap(), entry);
+    }
+
+    #[test]
+    fn manifest_serde() {
+        let manifest = manifest(2);
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert!(json.starts_with(r#"[{"key":"/objects/000000.bin""#), "{}", json);
+        assert_eq!(serde_json::from_str::<BucketManifest>(&json).unwrap(), manifest);
+
+        // Later entries replace earlier ones with the same key.
+        let entries = vec![entry(1, 0), entry(0, 0), entry(1, 1)];
+        let parsed = serde_json::from_str::<BucketManifest>(&serde_json::to_string(&entries).unwrap()).unwrap();
+        assert_eq!(parsed.entries().collect::<Vec<_>>(), [&entry(0, 0), &entry(1, 1)]);
+    }
+
+    #[test]
+    fn one_change_in_a_large_manifest_is_a_small_delta() {
+        let base = manifest(100_000);
+        let mut target = base.clone();
+        target.insert(entry(50_000, 1));
+        let delta = target.delta_from(&base);
+        assert_eq!((delta.added.len(), delta.removed.len(), delta.changed.len()), (0, 0, 1));
+        let bytes = delta.to_bytes();
+        assert!(bytes.len() < 200, "{} bytes", bytes.len());
+        let decoded = ManifestDelta::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, delta);
+        assert_eq!(decoded.apply(&base), Ok(target.clone()));
+        assert!(target.delta_from(&target).is_empty());
+    }
+
+    #[test]
+    fn base_mismatch_is_rejected() {
+        let base = manifest(3);
+        let mut target = base.clone();
+        target.remove(&entry(1, 0).key);
+        let delta = target.delta_from(&base);
+        assert_eq!(delta.removed, [entry(1, 0).key]);
+        assert_eq!(delta.apply(&base), Ok(target.clone()));
+        assert_eq!(
+            delta.apply(&target),
+            Err(DeltaApplyError::BaseMismatch {
+                expected: base.digest(),
+                actual: target.digest(),
+            })
+        );
+        // A tombstone is a change like any other.
+        let mut deleted = base.clone();
+        deleted.insert(ManifestEntry {
+            deleted: true,
+            ..entry(0, 0)
+        });
+        assert_ne!(deleted.digest(), base.digest());
+        assert!(matches!(delta.apply(&deleted), Err(DeltaApplyError::BaseMismatch { .. })));
+    }
+
+    #[test]
+    fn deltas_that_do_not_fit_their_base() {
+        let base = manifest(2);
+        let delta = |added, removed, changed| ManifestDelta {
+            base_digest: base.digest(),
+            added,
+            removed,
+            changed,
+        };
+        assert_eq!(
+            delta(vec![entry(0, 1)], vec![], vec![]).apply(&base),
+            Err(DeltaApplyError::AlreadyPresent(entry(0, 0).key))
+        );
+        assert_eq!(
+            delta(vec![], vec![entry(5, 0).key], vec![]).apply(&base),
+            Err(DeltaApplyError::NotPresent(entry(5, 0).key))
+        );
+        assert_eq!(
+            delta(vec![], vec![], vec![entry(5, 0)]).apply(&base),
+            Err(DeltaApplyError::NotPresent(entry(5, 0).key))
+        );
+    }
+
+    #[test]
+    fn invalid_bytes_are_rejected() {
+        let base = manifest(3);
+        let mut target: BucketManifest = (1..5).map(|index| entry(index, 1)).collect();
+        target.insert(ManifestEntry {
+            modified: datetime!(2024-06-01 14:00:00.5 +02:00),
+            ..entry(9, 0)
+        });
+        let delta = target.delta_from(&base);
+        let bytes = delta.to_bytes();
+        assert_eq!(ManifestDelta::from_bytes(&bytes), Ok(delta.clone()));
+        assert_eq!(ManifestDelta::from_bytes(&bytes[..bytes.len() - 1]), Err(ManifestDeltaError::Truncated));
+        assert_eq!(
+            ManifestDelta::from_bytes(&[bytes.as_slice(), &[0]].concat()),
+            Err(ManifestDeltaError::TrailingData)
+        );
+        let mut version = bytes.clone();
+        version[4] = 2;
+        assert_eq!(ManifestDelta::from_bytes(&version), Err(ManifestDeltaError::UnsupportedVersion(2)));
+        let mut flag = bytes.clone();
+        *flag.last_mut().unwrap() = 2;
+        assert!(matches!(
+            ManifestDelta::from_bytes(&flag),
+            Err(ManifestDeltaError::InvalidDeletedFlag { flag: 2, .. })
+        ));
+
+        let unsorted = ManifestDelta {
+            added: vec![entry(2, 0), entry(1, 0)],
+            ..delta.clone()
+        };
+        assert_eq!(
+            ManifestDelta::from_bytes(&unsorted.to_bytes()),
+            Err(ManifestDeltaError::Unsorted(entry(1, 0).key))
+        );
+        let duplicated = ManifestDelta {
+            removed: vec![entry(0, 0).key, entry(0, 0).key],
+            ..delta
+        };
+        assert_eq!(
+            ManifestDelta::from_bytes(&duplicated.to_bytes()),
+            Err(ManifestDeltaError::Unsorted(entry(0, 0).key))
+        );
+    }
+
+    fn any_manifest() -> impl Strategy<Value = BucketManifest> {
+        proptest::collection::btree_map(0u32..40, (0u8..3, any::<bool>()), 0..30).prop_map(|entries| {
+            entries
+                .into_iter()
+                .map(|(index, (version, deleted))| ManifestEntry {
+                    deleted,
+                    ..entry(index, version)
+                })
+                .collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn apply_reproduces_the_target(base in any_manifest(), target in any_manifest()) {
+            let delta = ManifestDelta::from_bytes(&target.delta_from(&base).to_bytes()).unwrap();
+            let applied = delta.apply(&base).unwrap();
+            prop_assert_eq!(to_canonical_vec(&applied).unwrap(), to_canonical_vec(&target).unwrap());
+            prop_assert_eq!(applied, target);
+        }
+    }
+}
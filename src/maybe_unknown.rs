@@ -0,0 +1,107 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::util::truncate_input;
+
+/*
+* Wrapper for enums in API payloads that may gain new variants.
+* Older services deserializing a variant they don't know yet get Unknown with the original string instead of an error,
+* and serializing it again echoes the string back unchanged. Callers decide whether to reject with is_known().
+* Serialized using the string form of T (Display/FromStr).
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum MaybeUnknown<T> {
+    Known(T),
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("unknown value \"{0}\"")]
+pub struct UnknownValueError(pub String);
+
+impl<T> MaybeUnknown<T> {
+    pub fn is_known(&self) -> bool {
+        matches!(self, MaybeUnknown::Known(_))
+    }
+
+    pub fn known(&self) -> Result<&T, UnknownValueError> {
+        match self {
+            MaybeUnknown::Known(value) => Ok(value),
+            MaybeUnknown::Unknown(value) => Err(UnknownValueError(truncate_input(value))),
+        }
+    }
+
+    pub fn into_known(self) -> Result<T, UnknownValueError> {
+        match self {
+            MaybeUnknown::Known(value) => Ok(value),
+            MaybeUnknown::Unknown(value) => Err(UnknownValueError(truncate_input(&value))),
+        }
+    }
+}
+
+impl<T> From<T> for MaybeUnknown<T> {
+    fn from(value: T) -> Self {
+        MaybeUnknown::Known(value)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for MaybeUnknown<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaybeUnknown::Known(value) => value.fmt(f),
+            MaybeUnknown::Unknown(value) => f.write_str(value),
+        }
+    }
+}
+
+impl<T: FromStr> FromStr for MaybeUnknown<T> {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(value) => MaybeUnknown::Known(value),
+            Err(_) => MaybeUnknown::Unknown(s.to_string()),
+        })
+    }
+}
+
+impl<T: fmt::Display> Serialize for MaybeUnknown<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de, T: FromStr> Deserialize<'de> for MaybeUnknown<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let Ok(value) = s.parse();
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BucketRegion;
+
+    #[test]
+    fn unknown_region_round_trips() {
+        let region: MaybeUnknown<BucketRegion> = serde_json::from_str("\"mars-central\"").unwrap();
+        assert_eq!(region, MaybeUnknown::Unknown("mars-central".to_string()));
+        assert!(!region.is_known());
+        assert!(region.known().is_err());
+        assert_eq!(region.to_string(), "mars-central");
+        assert_eq!(serde_json::to_string(&region).unwrap(), "\"mars-central\"");
+    }
+
+    #[test]
+    fn known_region_round_trips() {
+        let region: MaybeUnknown<BucketRegion> = serde_json::from_str("\"eu-north\"").unwrap();
+        assert_eq!(region, MaybeUnknown::Known(BucketRegion::EuropeNorth(0)));
+        assert_eq!(region.known(), Ok(&BucketRegion::EuropeNorth(0)));
+        assert_eq!(serde_json::to_string(&region).unwrap(), "\"eu-north\"");
+    }
+}
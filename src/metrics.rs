@@ -0,0 +1,212 @@
+#[allow(deprecated)]
+use crate::{AvailabilityStatus, BucketRegion, BucketStatus, BucketStorageClass, PaymentPlan};
+
+/*
+* Prometheus metric names shared by every service, all prefixed with bucketdrive_.
+* Counters end in _total and sizes in _bytes, following the Prometheus naming conventions.
+*/
+pub const BUCKET_SIZE_BYTES: &str = "bucketdrive_bucket_size_bytes";
+pub const BUCKET_OBJECTS: &str = "bucketdrive_bucket_objects";
+pub const BUCKETS_TOTAL: &str = "bucketdrive_buckets_total";
+pub const SHARE_LINK_REDEMPTIONS_TOTAL: &str = "bucketdrive_share_link_redemptions_total";
+pub const LINK_VERIFICATION_FAILURES_TOTAL: &str = "bucketdrive_link_verification_failures_total";
+pub const SUBSCRIPTIONS_TOTAL: &str = "bucketdrive_subscriptions_total";
+
+// Label names, the values come from MetricLabel.
+pub const REGION_LABEL: &str = "region";
+pub const STORAGE_CLASS_LABEL: &str = "storage_class";
+pub const STATUS_LABEL: &str = "status";
+pub const PLAN_LABEL: &str = "plan";
+pub const REASON_LABEL: &str = "reason";
+
+/*
+* Label value of a type, the same string the crate uses for it elsewhere (Display or the variant name) so dashboards line up.
+* Every value only uses [A-Za-z0-9_-] and each type has a small fixed set, data carried by a variant is never part of the label.
+*/
+pub trait MetricLabel {
+    fn metric_label(&self) -> &'static str;
+}
+
+// The zone number is left out, it would make the label unbounded.
+impl MetricLabel for BucketRegion {
+    fn metric_label(&self) -> &'static str {
+        self.into()
+    }
+}
+
+impl MetricLabel for BucketStorageClass {
+    fn metric_label(&self) -> &'static str {
+        self.into()
+    }
+}
+
+impl MetricLabel for BucketStatus {
+    fn metric_label(&self) -> &'static str {
+        self.into()
+    }
+}
+
+#[allow(deprecated)]
+impl MetricLabel for AvailabilityStatus {
+    fn metric_label(&self) -> &'static str {
+        self.into()
+    }
+}
+
+impl MetricLabel for PaymentPlan {
+    fn metric_label(&self) -> &'static str {
+        self.into()
+    }
+}
+
+// Verification and parsing failures are labelled with the error variant, never with the error message.
+#[cfg(feature = "share_link")]
+impl MetricLabel for crate::share_link::ShareLinkParsingError {
+    fn metric_label(&self) -> &'static str {
+        self.into()
+    }
+}
+
+#[cfg(feature = "secret_share_link")]
+impl MetricLabel for crate::secret_share_link::SecretShareLinkParsingError {
+    fn metric_label(&self) -> &'static str {
+        self.into()
+    }
+}
+
+#[cfg(feature = "secret_share_link")]
+impl MetricLabel for crate::secret_share_link::SecretShareLinkVerifySignatureError {
+    fn metric_label(&self) -> &'static str {
+        self.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    fn assert_valid_labels(labels: Vec<&'static str>) {
+        let label_value = regex::Regex::new("^[A-Za-z0-9_-]+$").unwrap();
+        for label in &labels {
+            assert!(label_value.is_match(label), "{}", label);
+        }
+        assert_eq!(labels.iter().collect::<HashSet<_>>().len(), labels.len(), "{:?}", labels);
+    }
+
+    #[test]
+    fn metric_and_label_names_are_valid() {
+        let metric_name = regex::Regex::new("^bucketdrive_[a-z_]+[a-z]$").unwrap();
+        let names = [
+            BUCKET_SIZE_BYTES,
+            BUCKET_OBJECTS,
+            BUCKETS_TOTAL,
+            SHARE_LINK_REDEMPTIONS_TOTAL,
+            LINK_VERIFICATION_FAILURES_TOTAL,
+            SUBSCRIPTIONS_TOTAL,
+        ];
+        for name in names {
+            assert!(metric_name.is_match(name), "{}", name);
+        }
+        let label_name = regex::Regex::new("^[a-z][a-z_]*$").unwrap();
+        for name in [REGION_LABEL, STORAGE_CLASS_LABEL, STATUS_LABEL, PLAN_LABEL, REASON_LABEL] {
+            assert!(label_name.is_match(name), "{}", name);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn enum_labels_are_valid_and_unique() {
+        assert_valid_labels(BucketRegion::iter().map(|region| region.metric_label()).collect());
+        assert_valid_labels(BucketStorageClass::iter().map(|class| class.metric_label()).collect());
+        assert_valid_labels(BucketStatus::iter().map(|status| status.metric_label()).collect());
+        assert_valid_labels(AvailabilityStatus::iter().map(|status| status.metric_label()).collect());
+        assert_valid_labels(PaymentPlan::iter().map(|plan| plan.metric_label()).collect());
+
+        assert_eq!(BucketRegion::EuropeNorth(3).metric_label(), "eu-north");
+        assert_eq!(BucketRegion::EuropeNorth(3).metric_label(), BucketRegion::EuropeNorth(0).metric_label());
+        assert_eq!(BucketStorageClass::DeepArchive.metric_label(), BucketStorageClass::DeepArchive.to_string());
+        assert_eq!(PaymentPlan::MeteredSubscription.metric_label(), "MeteredSubscription");
+    }
+
+    #[cfg(feature = "secret_share_link")]
+    #[test]
+    fn error_labels_are_valid_and_unique() {
+        use crate::expiry::ExpiryTimestamp;
+        use crate::secret_share_link::{SecretShareLinkParsingError, SecretShareLinkVerifySignatureError};
+        use crate::share_link::{ShareLinkParsingError, UnknownShareChannel};
+        use crate::share_prefix::SharePrefixError;
+
+        assert_valid_labels(
+            [
+                SecretShareLinkVerifySignatureError::InvalidSignature(ed25519_compact::Error::SignatureMismatch),
+                SecretShareLinkVerifySignatureError::NotYetValid {
+                    starts_at: ExpiryTimestamp::MIN,
+                },
+                SecretShareLinkVerifySignatureError::Expired {
+                    expired_at: ExpiryTimestamp::MIN,
+                },
+                SecretShareLinkVerifySignatureError::MissingExpiry,
+                SecretShareLinkVerifySignatureError::UnknownIssueTime,
+                SecretShareLinkVerifySignatureError::LinkTooOld {
+                    issued_at: ExpiryTimestamp::MIN,
+                    max: time::Duration::days(365),
+                },
+            ]
+            .iter()
+            .map(MetricLabel::metric_label)
+            .collect(),
+        );
+        assert_valid_labels(
+            [
+                SecretShareLinkParsingError::InvalidScheme,
+                SecretShareLinkParsingError::InvalidHostDomain,
+                SecretShareLinkParsingError::InvalidVersionFormat,
+                SecretShareLinkParsingError::ChecksumMismatch { expected_len_hint: 0 },
+                SecretShareLinkParsingError::InvalidPath,
+                SecretShareLinkParsingError::InvalidFragment,
+                SecretShareLinkParsingError::InvalidBucketKey,
+                SecretShareLinkParsingError::InvalidPermission,
+                SecretShareLinkParsingError::InvalidExpiry,
+                SecretShareLinkParsingError::InvalidNotBefore,
+                SecretShareLinkParsingError::InvalidIssuedAt,
+                SecretShareLinkParsingError::InvalidField("x".to_string()),
+                SecretShareLinkParsingError::DuplicateParameter("x".to_string()),
+                SecretShareLinkParsingError::MissingParameter("sig"),
+                SecretShareLinkParsingError::InvalidPrefix("x".to_string()),
+                SecretShareLinkParsingError::TooManyPrefixes(SharePrefixError::TooManyPrefixes(17)),
+                SecretShareLinkParsingError::InvalidChannel,
+                SecretShareLinkParsingError::UnknownChannel(UnknownShareChannel(9)),
+                SecretShareLinkParsingError::Base64Decoding(base64::DecodeError::InvalidLength),
+                SecretShareLinkParsingError::Utf8Error(String::from_utf8(vec![0xff]).unwrap_err()),
+                SecretShareLinkParsingError::Uuid("x".parse::<uuid::Uuid>().unwrap_err()),
+                SecretShareLinkParsingError::Signature(ed25519_compact::Error::InvalidSignature),
+                SecretShareLinkParsingError::UrlParse(url::ParseError::EmptyHost),
+            ]
+            .iter()
+            .map(MetricLabel::metric_label)
+            .collect(),
+        );
+        assert_valid_labels(
+            [
+                ShareLinkParsingError::InvalidScheme,
+                ShareLinkParsingError::InvalidHostDomain,
+                ShareLinkParsingError::InvalidPath,
+                ShareLinkParsingError::InvalidTokenLength,
+                ShareLinkParsingError::Base64Decoding(base64::DecodeError::InvalidLength),
+                ShareLinkParsingError::UrlParse(url::ParseError::EmptyHost),
+            ]
+            .iter()
+            .map(MetricLabel::metric_label)
+            .collect(),
+        );
+        // The label does not depend on the data the variant carries.
+        assert_eq!(
+            SecretShareLinkParsingError::InvalidField("secret".to_string()).metric_label(),
+            "InvalidField"
+        );
+    }
+}
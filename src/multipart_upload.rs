@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+
+use crate::BucketStorageClass;
+use crate::bucket_guid::BucketGuid;
+use crate::bucket_object::{BucketObjectKey, ContentHash};
+
+pub const MIB: u64 = 1024 * 1024;
+pub const GIB: u64 = 1024 * MIB;
+
+pub const MIN_PART_SIZE: u64 = 5 * MIB;
+// Archive classes are optimized for few large objects, small parts only add overhead.
+pub const MIN_ARCHIVE_PART_SIZE: u64 = 64 * MIB;
+pub const MAX_PART_SIZE: u64 = 5 * GIB;
+pub const MAX_PARTS: u64 = 10_000;
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MultipartUpload {
+    pub upload_id: uuid::Uuid,
+    pub bucket: BucketGuid,
+    pub key: BucketObjectKey,
+    pub part_size: u64,
+    // Unknown when the client streams the object.
+    pub total_size: Option<u64>,
+}
+
+// Parts are numbered from 1.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PartRef {
+    pub number: u16,
+    pub size: u64,
+    pub hash: ContentHash,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CompleteUpload {
+    pub upload_id: uuid::Uuid,
+    pub parts: Vec<PartRef>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum MultipartUploadError {
+    #[error("part size {part_size} is smaller than the minimum {min}")]
+    PartSizeTooSmall { part_size: u64, min: u64 },
+    #[error("part size {part_size} is larger than the maximum {max}")]
+    PartSizeTooLarge { part_size: u64, max: u64 },
+    #[error("upload needs {parts} parts, max is {MAX_PARTS}")]
+    TooManyParts { parts: u64 },
+    #[error("upload id {got} does not match {expected}")]
+    UploadIdMismatch { expected: uuid::Uuid, got: uuid::Uuid },
+    #[error("upload has no parts")]
+    NoParts,
+    #[error("part number {number} found where part {expected} was expected")]
+    NonContiguousPart { number: u16, expected: u64 },
+    #[error("part {number} is {size} bytes, expected {expected}")]
+    InvalidPartSize { number: u16, size: u64, expected: u64 },
+    #[error("last part {number} is {size} bytes, expected 1..={max}")]
+    InvalidLastPartSize { number: u16, size: u64, max: u64 },
+    #[error("parts add up to {actual} bytes, expected {expected}")]
+    TotalSizeMismatch { expected: u64, actual: u64 },
+}
+
+impl MultipartUpload {
+    pub fn min_part_size(storage_class: &BucketStorageClass) -> u64 {
+        match storage_class.is_archive() {
+            true => MIN_ARCHIVE_PART_SIZE,
+            false => MIN_PART_SIZE,
+        }
+    }
+
+    pub fn validate(&self, storage_class: &BucketStorageClass) -> Result<(), MultipartUploadError> {
+        let min = Self::min_part_size(storage_class);
+        if self.part_size < min {
+            return Err(MultipartUploadError::PartSizeTooSmall {
+                part_size: self.part_size,
+                min,
+            });
+        }
+        if self.part_size > MAX_PART_SIZE {
+            return Err(MultipartUploadError::PartSizeTooLarge {
+                part_size: self.part_size,
+                max: MAX_PART_SIZE,
+            });
+        }
+        if let Some(total_size) = self.total_size {
+            let parts = total_size.div_ceil(self.part_size);
+            if parts > MAX_PARTS {
+                return Err(MultipartUploadError::TooManyParts { parts });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CompleteUpload {
+    /*
+    * Parts must be numbered 1, 2, 3, ... without gaps.
+    * Every part except the last must be exactly upload.part_size, the last one 1..=part_size.
+    * Returns the total size of the object, which has to match the announced total size if there was one.
+    */
+    pub fn validate(&self, upload: &MultipartUpload) -> Result<u64, MultipartUploadError> {
+        if self.upload_id != upload.upload_id {
+            return Err(MultipartUploadError::UploadIdMismatch {
+                expected: upload.upload_id,
+                got: self.upload_id,
+            });
+        }
+        let (last, rest) = self.parts.split_last().ok_or(MultipartUploadError::NoParts)?;
+        if self.parts.len() as u64 > MAX_PARTS {
+            return Err(MultipartUploadError::TooManyParts {
+                parts: self.parts.len() as u64,
+            });
+        }
+        for (index, part) in self.parts.iter().enumerate() {
+            let expected = index as u64 + 1;
+            if part.number as u64 != expected {
+                return Err(MultipartUploadError::NonContiguousPart {
+                    number: part.number,
+                    expected,
+                });
+            }
+        }
+        for part in rest {
+            if part.size != upload.part_size {
+                return Err(MultipartUploadError::InvalidPartSize {
+                    number: part.number,
+                    size: part.size,
+                    expected: upload.part_size,
+                });
+            }
+        }
+        if last.size == 0 || last.size > upload.part_size {
+            return Err(MultipartUploadError::InvalidLastPartSize {
+                number: last.number,
+                size: last.size,
+                max: upload.part_size,
+            });
+        }
+        let actual = upload.part_size * rest.len() as u64 + last.size;
+        if let Some(expected) = upload.total_size {
+            if expected != actual {
+                return Err(MultipartUploadError::TotalSizeMismatch { expected, actual });
+            }
+        }
+        Ok(actual)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(part_size: u64, total_size: Option<u64>) -> MultipartUpload {
+        MultipartUpload {
+            upload_id: uuid::Uuid::new_v4(),
+            bucket: BucketGuid::generate(),
+            key: "/videos/holiday.mp4".parse().unwrap(),
+            part_size,
+            total_size,
+        }
+    }
+
+    fn complete(upload: &MultipartUpload, sizes: &[u64]) -> CompleteUpload {
+        CompleteUpload {
+            upload_id: upload.upload_id,
+            parts: sizes
+                .iter()
+                .enumerate()
+                .map(|(index, size)| PartRef {
+                    number: index as u16 + 1,
+                    size: *size,
+                    hash: ContentHash::of(&index.to_be_bytes()),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn part_size_limits() {
+        let general = BucketStorageClass::General;
+        assert!(upload(MIN_PART_SIZE, None).validate(&general).is_ok());
+        assert!(upload(MAX_PART_SIZE, None).validate(&general).is_ok());
+        assert_eq!(
+            upload(MIN_PART_SIZE - 1, None).validate(&general),
+            Err(MultipartUploadError::PartSizeTooSmall {
+                part_size: MIN_PART_SIZE - 1,
+                min: MIN_PART_SIZE
+            })
+        );
+        assert!(matches!(
+            upload(MAX_PART_SIZE + 1, None).validate(&general),
+            Err(MultipartUploadError::PartSizeTooLarge { .. })
+        ));
+
+        let archive = BucketStorageClass::Archive;
+        assert!(upload(MIN_PART_SIZE, None).validate(&archive).is_err());
+        assert!(upload(MIN_ARCHIVE_PART_SIZE, None).validate(&archive).is_ok());
+    }
+
+    #[test]
+    fn part_count_limits() {
+        let general = BucketStorageClass::General;
+        assert!(upload(MIN_PART_SIZE, Some(MIN_PART_SIZE * MAX_PARTS)).validate(&general).is_ok());
+        assert_eq!(
+            upload(MIN_PART_SIZE, Some(MIN_PART_SIZE * MAX_PARTS + 1)).validate(&general),
+            Err(MultipartUploadError::TooManyParts { parts: MAX_PARTS + 1 })
+        );
+    }
+
+    #[test]
+    fn complete_upload_totals() {
+        let upload = upload(MIN_PART_SIZE, Some(2 * MIN_PART_SIZE + 1));
+        let done = complete(&upload, &[MIN_PART_SIZE, MIN_PART_SIZE, 1]);
+        assert_eq!(done.validate(&upload), Ok(2 * MIN_PART_SIZE + 1));
+
+        let done = complete(&upload, &[MIN_PART_SIZE, MIN_PART_SIZE, 2]);
+        assert_eq!(
+            done.validate(&upload),
+            Err(MultipartUploadError::TotalSizeMismatch {
+                expected: 2 * MIN_PART_SIZE + 1,
+                actual: 2 * MIN_PART_SIZE + 2
+            })
+        );
+    }
+
+    #[test]
+    fn complete_upload_names_first_offending_part() {
+        let upload = upload(MIN_PART_SIZE, None);
+
+        let mut done = complete(&upload, &[MIN_PART_SIZE, MIN_PART_SIZE, MIN_PART_SIZE]);
+        done.parts[1].number = 3;
+        done.parts[2].number = 2;
+        assert_eq!(
+            done.validate(&upload),
+            Err(MultipartUploadError::NonContiguousPart { number: 3, expected: 2 })
+        );
+
+        let done = complete(&upload, &[MIN_PART_SIZE, 1, 2, 1]);
+        assert_eq!(
+            done.validate(&upload),
+            Err(MultipartUploadError::InvalidPartSize {
+                number: 2,
+                size: 1,
+                expected: MIN_PART_SIZE
+            })
+        );
+
+        let done = complete(&upload, &[MIN_PART_SIZE, MIN_PART_SIZE + 1]);
+        assert!(matches!(
+            done.validate(&upload),
+            Err(MultipartUploadError::InvalidLastPartSize { number: 2, .. })
+        ));
+        assert_eq!(complete(&upload, &[]).validate(&upload), Err(MultipartUploadError::NoParts));
+
+        let mut done = complete(&upload, &[1]);
+        done.upload_id = uuid::Uuid::new_v4();
+        assert!(matches!(
+            done.validate(&upload),
+            Err(MultipartUploadError::UploadIdMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn complete_upload_part_count_limit() {
+        let upload = upload(MIN_PART_SIZE, None);
+        let sizes = vec![MIN_PART_SIZE; MAX_PARTS as usize];
+        assert_eq!(complete(&upload, &sizes).validate(&upload), Ok(MIN_PART_SIZE * MAX_PARTS));
+        let sizes = vec![MIN_PART_SIZE; MAX_PARTS as usize + 1];
+        assert_eq!(
+            complete(&upload, &sizes).validate(&upload),
+            Err(MultipartUploadError::TooManyParts { parts: MAX_PARTS + 1 })
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let upload = upload(MIN_PART_SIZE, Some(MIN_PART_SIZE));
+        let json = serde_json::to_string(&upload).unwrap();
+        assert_eq!(serde_json::from_str::<MultipartUpload>(&json).unwrap(), upload);
+        let done = complete(&upload, &[MIN_PART_SIZE]);
+        let json = serde_json::to_string(&done).unwrap();
+        assert_eq!(serde_json::from_str::<CompleteUpload>(&json).unwrap(), done);
+    }
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/*
+* Change to one field of a partial update: leave it unchanged, set it or clear it.
+* In JSON an absent field is Keep, null is Clear and any other value is Set. Fields of this type need
+* #[serde(default, skip_serializing_if = "Patch::is_keep")], Keep has no JSON form and fails to serialize rather than
+* turning into a Clear.
+*/
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum Patch<T> {
+    #[default]
+    Keep,
+    Set(T),
+    Clear,
+}
+
+impl<T: Clone> Patch<T> {
+    pub fn is_keep(&self) -> bool {
+        matches!(self, Patch::Keep)
+    }
+
+    pub fn apply(&self, current: Option<T>) -> Option<T> {
+        match self {
+            Patch::Keep => current,
+            Patch::Set(value) => Some(value.clone()),
+            Patch::Clear => None,
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Patch::Keep => Err(serde::ser::Error::custom("Patch::Keep must be skipped, see Patch::is_keep")),
+            Patch::Set(value) => serializer.serialize_some(value),
+            Patch::Clear => serializer.serialize_none(),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Patch::Set(value),
+            None => Patch::Clear,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+    struct NamePatch {
+        #[serde(default, skip_serializing_if = "Patch::is_keep")]
+        name: Patch<String>,
+    }
+
+    #[test]
+    fn three_states() {
+        for (json, name) in [
+            ("{}", Patch::Keep),
+            (r#"{"name":null}"#, Patch::Clear),
+            (r#"{"name":"docs"}"#, Patch::Set("docs".to_string())),
+        ] {
+            let patch = NamePatch { name };
+            assert_eq!(serde_json::from_str::<NamePatch>(json).unwrap(), patch);
+            assert_eq!(serde_json::to_string(&patch).unwrap(), json);
+        }
+        assert!(serde_json::to_string(&Patch::<String>::Keep).is_err());
+        assert!(serde_json::from_str::<NamePatch>(r#"{"name":1}"#).is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let current = Some("old".to_string());
+        assert_eq!(Patch::Keep.apply(current.clone()), current);
+        assert_eq!(Patch::Keep.apply(None::<String>), None);
+        assert_eq!(Patch::Set("new".to_string()).apply(current.clone()), Some("new".to_string()));
+        assert_eq!(Patch::Set("new".to_string()).apply(None), Some("new".to_string()));
+        assert_eq!(Patch::Clear.apply(current), None);
+    }
+}
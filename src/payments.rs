@@ -0,0 +1,573 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::errors::{is_truncated, ParseFailure, SpannedParseError};
+use crate::util::truncate_input;
+use crate::{PaymentPlan, UserId};
+
+// Pricing is computed in nano-USD, 1 USD is 10^9 nano-USD.
+pub const NANO_USD_EXPONENT: u32 = 9;
+
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumString, strum::EnumIter, Serialize, Deserialize,
+)]
+#[non_exhaustive]
+pub enum Currency {
+    USD,
+    EUR,
+    SEK,
+    GBP,
+}
+
+impl Currency {
+    // Digits after the decimal point, 2 for cents, öre and pence.
+    pub fn minor_unit_exponent(&self) -> u32 {
+        match self {
+            Currency::USD | Currency::EUR | Currency::SEK | Currency::GBP => 2,
+        }
+    }
+}
+
+/*
+* An amount in the smallest unit of its currency, e.g. cents, never a float.
+* Serialized as { "amount": 1234, "currency": "USD" } and displayed as "12.34 USD".
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Money {
+    #[serde(rename = "amount")]
+    pub minor_units: i64,
+    pub currency: Currency,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum MoneyError {
+    #[error("can not combine {left} with {right}")]
+    CurrencyMismatch { left: Currency, right: Currency },
+    #[error("amount overflows")]
+    Overflow,
+    #[error("ratio denominator is zero")]
+    ZeroDenominator,
+    #[error("{nano_usd} nano-USD is not a whole number of cents")]
+    InexactConversion { nano_usd: i64 },
+    #[error("invalid amount \"{0}\"")]
+    InvalidFormat(String),
+}
+
+// Points at the currency when it is unknown, otherwise at the amount.
+impl SpannedParseError for MoneyError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            MoneyError::InvalidFormat(input) if !is_truncated(input) => {
+                let span = match input.split_once(' ') {
+                    Some((amount, currency)) if currency.parse::<Currency>().is_err() => amount.len() + 1..input.len(),
+                    Some((amount, _)) => 0..amount.len(),
+                    None => 0..input.len(),
+                };
+                ParseFailure::spanned(self, input, span)
+            }
+            MoneyError::InvalidFormat(input) => ParseFailure::whole(self, input),
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl Money {
+    pub fn new(minor_units: i64, currency: Currency) -> Self {
+        Self { minor_units, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Self::new(0, currency)
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let minor_units = self.minor_units.checked_add(other.minor_units).ok_or(MoneyError::Overflow)?;
+        Ok(Self::new(minor_units, self.currency))
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let minor_units = self.minor_units.checked_sub(other.minor_units).ok_or(MoneyError::Overflow)?;
+        Ok(Self::new(minor_units, self.currency))
+    }
+
+    /*
+    * Multiplies by numer / denom, rounding half to even (banker's rounding) so that rounding errors
+    * do not drift in one direction when summing many prorated amounts.
+    */
+    pub fn mul_ratio(self, numer: i64, denom: i64) -> Result<Money, MoneyError> {
+        if denom == 0 {
+            return Err(MoneyError::ZeroDenominator);
+        }
+        // i64 * i64 always fits in i128.
+        let product = self.minor_units as i128 * numer as i128;
+        let denom = denom as i128;
+        let mut quotient = product / denom;
+        let remainder = (product % denom).abs();
+        let double_remainder = remainder * 2;
+        let round_away = double_remainder > denom.abs() || (double_remainder == denom.abs() && quotient % 2 != 0);
+        if round_away {
+            quotient += match (product < 0) == (denom < 0) {
+                true => 1,
+                false => -1,
+            };
+        }
+        let minor_units = i64::try_from(quotient).map_err(|_| MoneyError::Overflow)?;
+        Ok(Self::new(minor_units, self.currency))
+    }
+
+    // Lossless or an error, nano-USD amounts with fractions of a cent are rejected.
+    pub fn from_nano_usd(nano_usd: i64) -> Result<Money, MoneyError> {
+        let per_minor_unit = 10i64.pow(NANO_USD_EXPONENT - Currency::USD.minor_unit_exponent());
+        match nano_usd % per_minor_unit {
+            0 => Ok(Self::new(nano_usd / per_minor_unit, Currency::USD)),
+            _ => Err(MoneyError::InexactConversion { nano_usd }),
+        }
+    }
+
+    pub fn to_nano_usd(&self) -> Result<i64, MoneyError> {
+        self.same_currency(Self::zero(Currency::USD))?;
+        let per_minor_unit = 10i64.pow(NANO_USD_EXPONENT - Currency::USD.minor_unit_exponent());
+        self.minor_units.checked_mul(per_minor_unit).ok_or(MoneyError::Overflow)
+    }
+
+    fn same_currency(&self, other: Money) -> Result<(), MoneyError> {
+        match self.currency == other.currency {
+            true => Ok(()),
+            false => Err(MoneyError::CurrencyMismatch {
+                left: self.currency,
+                right: other.currency,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let exponent = self.currency.minor_unit_exponent();
+        let sign = if self.minor_units < 0 { "-" } else { "" };
+        // unsigned_abs handles i64::MIN.
+        let units = self.minor_units.unsigned_abs();
+        let scale = 10u64.pow(exponent);
+        match exponent {
+            0 => write!(f, "{}{} {}", sign, units, self.currency),
+            _ => write!(
+                f,
+                "{}{}.{:0width$} {}",
+                sign,
+                units / scale,
+                units % scale,
+                self.currency,
+                width = exponent as usize
+            ),
+        }
+    }
+}
+
+/*
+* Parses "<amount> <currency>", e.g. "12.34 USD", "0.1 EUR" or "-5 SEK".
+* More fraction digits than the currency has are an error instead of being rounded.
+*/
+impl FromStr for Money {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MoneyError::InvalidFormat(truncate_input(s));
+        let (amount, currency) = s.split_once(' ').ok_or_else(invalid)?;
+        let currency: Currency = currency.parse().map_err(|_| invalid())?;
+        let exponent = currency.minor_unit_exponent();
+
+        let (negative, amount) = match amount.strip_prefix('-') {
+            Some(amount) => (true, amount),
+            None => (false, amount),
+        };
+        let (whole, fraction) = match amount.split_once('.') {
+            Some((whole, fraction)) if !fraction.is_empty() => (whole, fraction),
+            Some(_) => return Err(invalid()),
+            None => (amount, ""),
+        };
+        let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if whole.is_empty() || !all_digits(whole) || !all_digits(fraction) || fraction.len() > exponent as usize {
+            return Err(invalid());
+        }
+
+        // Accumulated with the sign applied per digit so that i64::MIN parses.
+        let sign = if negative { -1 } else { 1 };
+        let mut minor_units: i64 = 0;
+        for digit in whole.bytes().chain(fraction.bytes()) {
+            minor_units = minor_units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add(sign * (digit - b'0') as i64))
+                .ok_or(MoneyError::Overflow)?;
+        }
+        let minor_units = minor_units
+            .checked_mul(10i64.pow(exponent - fraction.len() as u32))
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Self::new(minor_units, currency))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum UsageMetric {
+    StorageByteHours,
+    EgressBytes,
+    Requests,
+}
+
+// Metered usage a line item bills for.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub metric: UsageMetric,
+    pub quantity: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct InvoiceLineItem {
+    pub description: String,
+    pub usage: Option<UsageRecord>,
+    pub quantity: u64,
+    pub unit_price: Money,
+    pub total: Money,
+}
+
+/*
+* Same lifecycle as Stripe invoices: a draft is finalized to open, an open invoice ends up paid, void or uncollectible.
+* Uncollectible invoices can still be paid or voided later. Paid and void are final.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum InvoiceStatus {
+    Draft,
+    Open,
+    Paid,
+    Void,
+    Uncollectible,
+}
+
+impl InvoiceStatus {
+    // Staying in the same status is not a transition.
+    pub fn can_transition_to(&self, target: &InvoiceStatus) -> bool {
+        use InvoiceStatus::*;
+        matches!(
+            (self, target),
+            (Draft, Open) | (Open, Paid | Void | Uncollectible) | (Uncollectible, Paid | Void)
+        )
+    }
+}
+
+// Monthly statement, the billing service and the dashboard share these field names.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Invoice {
+    pub id: uuid::Uuid,
+    pub user_id: UserId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub period_start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub period_end: OffsetDateTime,
+    pub items: Vec<InvoiceLineItem>,
+    pub subtotal: Money,
+    pub tax: Money,
+    pub total: Money,
+    pub plan: PaymentPlan,
+    pub status: InvoiceStatus,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum InvoiceDiscrepancy {
+    #[error("line item {index} totals {actual}, quantity times unit price is {expected}")]
+    LineItemTotal { index: usize, expected: Money, actual: Money },
+    #[error("subtotal is {actual}, line items add up to {expected}")]
+    Subtotal { expected: Money, actual: Money },
+    #[error("total is {actual}, subtotal plus tax is {expected}")]
+    Total { expected: Money, actual: Money },
+    #[error("line item {index}: {error}")]
+    LineItem { index: usize, error: MoneyError },
+    #[error(transparent)]
+    Money(#[from] MoneyError),
+}
+
+impl Invoice {
+    /*
+    * Recomputes every line item, the subtotal and the total and reports each stored amount that disagrees.
+    * A line item that can not be computed, e.g. in another currency, is reported and left out of the subtotal.
+    */
+    pub fn recompute_totals(&self) -> Result<(), Vec<InvoiceDiscrepancy>> {
+        let mut discrepancies = Vec::new();
+        let mut subtotal = Ok(Money::zero(self.subtotal.currency));
+        for (index, item) in self.items.iter().enumerate() {
+            let computed = i64::try_from(item.quantity)
+                .map_err(|_| MoneyError::Overflow)
+                .and_then(|quantity| item.unit_price.mul_ratio(quantity, 1));
+            match computed {
+                Ok(expected) if expected != item.total => discrepancies.push(InvoiceDiscrepancy::LineItemTotal {
+                    index,
+                    expected,
+                    actual: item.total,
+                }),
+                Ok(_) => {}
+                Err(error) => discrepancies.push(InvoiceDiscrepancy::LineItem { index, error }),
+            }
+            if let Ok(current) = subtotal {
+                subtotal = match current.checked_add(item.total) {
+                    Err(error @ MoneyError::CurrencyMismatch { .. }) => {
+                        discrepancies.push(InvoiceDiscrepancy::LineItem { index, error });
+                        Ok(current)
+                    }
+                    next => next,
+                };
+            }
+        }
+        match subtotal {
+            Ok(expected) if expected != self.subtotal => discrepancies.push(InvoiceDiscrepancy::Subtotal {
+                expected,
+                actual: self.subtotal,
+            }),
+            Ok(_) => {}
+            Err(error) => discrepancies.push(error.into()),
+        }
+        match self.subtotal.checked_add(self.tax) {
+            Ok(expected) if expected != self.total => discrepancies.push(InvoiceDiscrepancy::Total {
+                expected,
+                actual: self.total,
+            }),
+            Ok(_) => {}
+            Err(error) => discrepancies.push(error.into()),
+        }
+        match discrepancies.is_empty() {
+            true => Ok(()),
+            false => Err(discrepancies),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    const INVOICE_FIXTURE: &str = include_str!("../testdata/invoice.json");
+
+    fn usd(minor_units: i64) -> Money {
+        Money::new(minor_units, Currency::USD)
+    }
+
+    #[test]
+    fn banker_rounding_on_halfway() {
+        // 5 / 2 = 2.5 rounds to 2, 7 / 2 = 3.5 rounds to 4.
+        assert_eq!(usd(5).mul_ratio(1, 2), Ok(usd(2)));
+        assert_eq!(usd(7).mul_ratio(1, 2), Ok(usd(4)));
+        assert_eq!(usd(-5).mul_ratio(1, 2), Ok(usd(-2)));
+        assert_eq!(usd(-7).mul_ratio(1, 2), Ok(usd(-4)));
+        assert_eq!(usd(7).mul_ratio(-1, 2), Ok(usd(-4)));
+        assert_eq!(usd(7).mul_ratio(1, -2), Ok(usd(-4)));
+        // Not halfway, rounds to nearest.
+        assert_eq!(usd(100).mul_ratio(1, 3), Ok(usd(33)));
+        assert_eq!(usd(200).mul_ratio(1, 3), Ok(usd(67)));
+        assert_eq!(usd(-200).mul_ratio(1, 3), Ok(usd(-67)));
+        // Proration of a 9.99 plan for 15 of 30 days.
+        assert_eq!(usd(999).mul_ratio(15, 30), Ok(usd(500)));
+        assert_eq!(usd(1234).mul_ratio(1, 0), Err(MoneyError::ZeroDenominator));
+    }
+
+    #[test]
+    fn mixed_currencies_are_rejected() {
+        let euros = Money::new(100, Currency::EUR);
+        assert_eq!(
+            usd(100).checked_add(euros),
+            Err(MoneyError::CurrencyMismatch {
+                left: Currency::USD,
+                right: Currency::EUR
+            })
+        );
+        assert!(usd(100).checked_sub(euros).is_err());
+        assert_eq!(usd(100).checked_add(usd(23)), Ok(usd(123)));
+        assert_eq!(usd(100).checked_sub(usd(123)), Ok(usd(-23)));
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        assert_eq!(usd(i64::MAX).checked_add(usd(1)), Err(MoneyError::Overflow));
+        assert_eq!(usd(i64::MIN).checked_sub(usd(1)), Err(MoneyError::Overflow));
+        assert_eq!(usd(i64::MAX).mul_ratio(2, 1), Err(MoneyError::Overflow));
+        // The intermediate product does not overflow.
+        assert_eq!(usd(i64::MAX).mul_ratio(3, 3), Ok(usd(i64::MAX)));
+        assert_eq!("92233720368547758.08 USD".parse::<Money>(), Err(MoneyError::Overflow));
+        assert_eq!(usd(i64::MAX).to_nano_usd(), Err(MoneyError::Overflow));
+    }
+
+    #[test]
+    fn display_and_parse() {
+        assert_eq!(usd(1234).to_string(), "12.34 USD");
+        assert_eq!(usd(-5).to_string(), "-0.05 USD");
+        assert_eq!(usd(i64::MIN).to_string(), "-92233720368547758.08 USD");
+        assert_eq!(Money::new(100, Currency::SEK).to_string(), "1.00 SEK");
+
+        assert_eq!("12.34 USD".parse(), Ok(usd(1234)));
+        assert_eq!("0.1 USD".parse(), Ok(usd(10)));
+        assert_eq!("0.01 GBP".parse(), Ok(Money::new(1, Currency::GBP)));
+        assert_eq!("5 EUR".parse(), Ok(Money::new(500, Currency::EUR)));
+        assert_eq!("-0.5 USD".parse(), Ok(usd(-50)));
+        for money in [usd(0), usd(1), usd(-1234), usd(i64::MAX), usd(i64::MIN)] {
+            assert_eq!(money.to_string().parse(), Ok(money));
+        }
+        for invalid in ["", "12.34", "12.345 USD", ".5 USD", "5. USD", "1,5 USD", "12.34 usd", "12.34 XYZ", "+1 USD", "1  USD", "- USD"] {
+            assert!(matches!(invalid.parse::<Money>(), Err(MoneyError::InvalidFormat(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn serde_shape() {
+        let json = serde_json::to_string(&usd(1234)).unwrap();
+        assert_eq!(json, r#"{"amount":1234,"currency":"USD"}"#);
+        assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), usd(1234));
+    }
+
+    #[test]
+    fn nano_usd_conversion_is_lossless() {
+        assert_eq!(Money::from_nano_usd(12_340_000_000), Ok(usd(1234)));
+        assert_eq!(Money::from_nano_usd(-10_000_000), Ok(usd(-1)));
+        assert_eq!(
+            Money::from_nano_usd(12_345_000_001),
+            Err(MoneyError::InexactConversion { nano_usd: 12_345_000_001 })
+        );
+        assert_eq!(usd(1234).to_nano_usd(), Ok(12_340_000_000));
+        assert!(Money::new(1234, Currency::EUR).to_nano_usd().is_err());
+    }
+
+    fn invoice() -> Invoice {
+        Invoice {
+            id: uuid::Uuid::from_u128(0x0c0ffee0_0000_4000_8000_000000000001),
+            user_id: uuid::Uuid::from_u128(0x11111111_0000_4000_8000_000000000001),
+            period_start: datetime!(2024-05-01 0:00 UTC),
+            period_end: datetime!(2024-06-01 0:00 UTC),
+            items: vec![
+                InvoiceLineItem {
+                    description: "Monthly subscription".to_string(),
+                    usage: None,
+                    quantity: 1,
+                    unit_price: usd(999),
+                    total: usd(999),
+                },
+                InvoiceLineItem {
+                    description: "Egress".to_string(),
+                    usage: Some(UsageRecord {
+                        metric: UsageMetric::EgressBytes,
+                        quantity: 250 * 1024 * 1024 * 1024,
+                    }),
+                    quantity: 250,
+                    unit_price: usd(2),
+                    total: usd(500),
+                },
+            ],
+            subtotal: usd(1499),
+            tax: usd(375),
+            total: usd(1874),
+            plan: PaymentPlan::MonthlySubscription,
+            status: InvoiceStatus::Open,
+        }
+    }
+
+    #[test]
+    fn invoice_golden_json() {
+        assert_eq!(serde_json::to_string_pretty(&invoice()).unwrap() + "\n", INVOICE_FIXTURE);
+        assert_eq!(serde_json::from_str::<Invoice>(INVOICE_FIXTURE).unwrap(), invoice());
+    }
+
+    #[test]
+    fn consistent_invoice_has_no_discrepancies() {
+        assert_eq!(invoice().recompute_totals(), Ok(()));
+        let empty = Invoice {
+            items: Vec::new(),
+            subtotal: usd(0),
+            tax: usd(0),
+            total: usd(0),
+            ..invoice()
+        };
+        assert_eq!(empty.recompute_totals(), Ok(()));
+    }
+
+    #[test]
+    fn inconsistent_invoice_totals_are_reported() {
+        let mut wrong_line = invoice();
+        wrong_line.items[1].total = usd(499);
+        assert_eq!(
+            wrong_line.recompute_totals(),
+            Err(vec![
+                InvoiceDiscrepancy::LineItemTotal {
+                    index: 1,
+                    expected: usd(500),
+                    actual: usd(499)
+                },
+                InvoiceDiscrepancy::Subtotal {
+                    expected: usd(1498),
+                    actual: usd(1499)
+                },
+            ])
+        );
+
+        let wrong_total = Invoice {
+            total: usd(1499),
+            ..invoice()
+        };
+        assert_eq!(
+            wrong_total.recompute_totals(),
+            Err(vec![InvoiceDiscrepancy::Total {
+                expected: usd(1874),
+                actual: usd(1499)
+            }])
+        );
+
+        let mut mixed = invoice();
+        mixed.items[0].unit_price = Money::new(999, Currency::EUR);
+        mixed.items[0].total = Money::new(999, Currency::EUR);
+        assert_eq!(
+            mixed.recompute_totals(),
+            Err(vec![
+                InvoiceDiscrepancy::LineItem {
+                    index: 0,
+                    error: MoneyError::CurrencyMismatch {
+                        left: Currency::USD,
+                        right: Currency::EUR
+                    }
+                },
+                InvoiceDiscrepancy::Subtotal {
+                    expected: usd(500),
+                    actual: usd(1499)
+                },
+            ])
+        );
+
+        let mut overflowing = invoice();
+        overflowing.items[0].quantity = u64::MAX;
+        assert_eq!(
+            overflowing.recompute_totals(),
+            Err(vec![InvoiceDiscrepancy::LineItem {
+                index: 0,
+                error: MoneyError::Overflow
+            }])
+        );
+    }
+
+    #[test]
+    fn invoice_status_transitions() {
+        use InvoiceStatus::*;
+        assert!(Draft.can_transition_to(&Open));
+        assert!(Open.can_transition_to(&Paid));
+        assert!(Open.can_transition_to(&Uncollectible));
+        assert!(Uncollectible.can_transition_to(&Paid));
+        assert!(!Draft.can_transition_to(&Paid));
+        assert!(!Paid.can_transition_to(&Open));
+        assert!(!Void.can_transition_to(&Paid));
+        assert!(!Open.can_transition_to(&Open));
+        assert_eq!(serde_json::to_string(&Uncollectible).unwrap(), "\"uncollectible\"");
+    }
+}
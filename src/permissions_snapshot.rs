@@ -0,0 +1,200 @@
+#![cfg(feature = "share_link")]
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+use crate::bucket_guid::BucketGuid;
+use crate::share_link::ShareLinkToken;
+use crate::util::Fingerprint;
+use crate::UserId;
+
+const PERMISSIONS_SNAPSHOT_CONTEXT: &[u8] = b"bucketdrive-permissions-snapshot-v1";
+
+/*
+* Length of PermissionsSnapshotKey::to_bytes:
+* actor tag (1), actor (16), bucket user id (16), bucket id (16), access path tag (1), access path id (32).
+* Absent parts are zero, the tags tell them apart from a zero id.
+*/
+pub const PERMISSIONS_SNAPSHOT_KEY_LEN: usize = 82;
+
+// How the actor reached the bucket, decisions for different paths are never interchangeable.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum AccessPath {
+    Owner,
+    Link(ShareLinkToken),
+    // Id of the accepted ShareInvitation.
+    Invitation(uuid::Uuid),
+}
+
+impl AccessPath {
+    // Tags are part of the cache key and never change.
+    fn tag(&self) -> u8 {
+        match self {
+            AccessPath::Owner => 1,
+            AccessPath::Link(_) => 2,
+            AccessPath::Invitation(_) => 3,
+        }
+    }
+}
+
+/*
+* Key of a cached authorization decision on a storage node, replaces keys assembled from strings by hand.
+* Every part of the decision input is a field, so none can be left out, and cache_key is computed from the fixed
+* layout of to_bytes, so equal keys always hash the same. The layout and context are frozen, the golden vector in the
+* tests pins them for nodes on different crate versions.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct PermissionsSnapshotKey {
+    // None for anonymous access, e.g. a link opened without an account.
+    pub actor: Option<UserId>,
+    pub bucket: BucketGuid,
+    pub via: AccessPath,
+}
+
+impl PermissionsSnapshotKey {
+    pub fn new(actor: Option<UserId>, bucket: BucketGuid, via: AccessPath) -> Self {
+        Self { actor, bucket, via }
+    }
+
+    pub fn to_bytes(&self) -> [u8; PERMISSIONS_SNAPSHOT_KEY_LEN] {
+        let mut bytes = [0; PERMISSIONS_SNAPSHOT_KEY_LEN];
+        if let Some(actor) = self.actor {
+            bytes[0] = 1;
+            bytes[1..17].copy_from_slice(actor.as_bytes());
+        }
+        bytes[17..33].copy_from_slice(self.bucket.user_id.as_bytes());
+        bytes[33..49].copy_from_slice(self.bucket.bucket_id.as_bytes());
+        bytes[49] = self.via.tag();
+        match &self.via {
+            AccessPath::Owner => {}
+            AccessPath::Link(token) => bytes[50..82].copy_from_slice(&token.0),
+            AccessPath::Invitation(id) => bytes[50..66].copy_from_slice(id.as_bytes()),
+        }
+        bytes
+    }
+
+    // SHA3-256 over the context and to_bytes.
+    pub fn cache_key(&self) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(PERMISSIONS_SNAPSHOT_CONTEXT);
+        hasher.update(self.to_bytes());
+        hasher.finalize().into()
+    }
+}
+
+// Safe to log, link tokens only appear as fingerprints.
+impl fmt::Display for PermissionsSnapshotKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.actor {
+            Some(actor) => write!(f, "{} on {} ", actor, self.bucket)?,
+            None => write!(f, "anonymous on {} ", self.bucket)?,
+        }
+        match &self.via {
+            AccessPath::Owner => f.write_str("as owner"),
+            AccessPath::Link(token) => write!(f, "via link fp:{}", Fingerprint::of(&token.0)),
+            AccessPath::Invitation(id) => write!(f, "via invitation {}", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn bucket() -> BucketGuid {
+        BucketGuid::new(
+            "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+        )
+    }
+
+    fn actor() -> UserId {
+        "33333333-0000-4000-8000-000000000003".parse().unwrap()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn golden_cache_key() {
+        let key = PermissionsSnapshotKey::new(Some(actor()), bucket(), AccessPath::Link(ShareLinkToken([7; 32])));
+        assert_eq!(
+            hex(&key.to_bytes()),
+            "0133333333000040008000000000000003\
+            1111111100004000800000000000000122222222000040008000000000000002\
+            020707070707070707070707070707070707070707070707070707070707070707"
+        );
+        assert_eq!(hex(&key.cache_key()), "2776beefb3964be866ff86c2878a39be310b00d75c7483c154191018cc9caf4a");
+    }
+
+    #[test]
+    fn equal_inputs_give_equal_keys() {
+        let via = AccessPath::Invitation(uuid::Uuid::from_u128(9));
+        let key = PermissionsSnapshotKey::new(Some(actor()), bucket(), via);
+        let literal = PermissionsSnapshotKey { via, bucket: bucket(), actor: Some(actor()) };
+        let json = serde_json::to_string(&key).unwrap();
+        let deserialized = serde_json::from_str::<PermissionsSnapshotKey>(&json).unwrap();
+        for other in [literal, deserialized] {
+            assert_eq!(other, key);
+            assert_eq!(other.cache_key(), key.cache_key());
+        }
+    }
+
+    #[test]
+    fn distinct_inputs_never_collide() {
+        let nil = uuid::Uuid::nil();
+        let paths = [
+            AccessPath::Owner,
+            AccessPath::Link(ShareLinkToken([0; 32])),
+            AccessPath::Link(ShareLinkToken([1; 32])),
+            AccessPath::Invitation(nil),
+            AccessPath::Invitation(uuid::Uuid::from_u128(1)),
+        ];
+        let actors = [None, Some(nil), Some(actor())];
+        let buckets = [bucket(), BucketGuid::new(nil, nil), BucketGuid::new(bucket().bucket_id, bucket().user_id)];
+        let mut bytes = HashSet::new();
+        let mut cache_keys = HashSet::new();
+        for via in paths {
+            for actor in actors {
+                for bucket in buckets {
+                    let key = PermissionsSnapshotKey::new(actor, bucket, via);
+                    assert!(bytes.insert(key.to_bytes()), "{}", key);
+                    assert!(cache_keys.insert(key.cache_key()), "{}", key);
+                }
+            }
+        }
+        assert_eq!(cache_keys.len(), paths.len() * actors.len() * buckets.len());
+    }
+
+    #[test]
+    fn display_hides_tokens() {
+        let token = ShareLinkToken([7; 32]);
+        let key = PermissionsSnapshotKey::new(None, bucket(), AccessPath::Link(token));
+        let display = key.to_string();
+        assert_eq!(display, format!("anonymous on {} via link fp:{}", bucket(), Fingerprint::of(&token.0)));
+        assert!(!display.contains(&token.to_string()));
+        assert_eq!(
+            PermissionsSnapshotKey::new(Some(actor()), bucket(), AccessPath::Owner).to_string(),
+            format!("{} on {} as owner", actor(), bucket())
+        );
+    }
+
+    #[test]
+    fn serde() {
+        let key = PermissionsSnapshotKey::new(None, bucket(), AccessPath::Owner);
+        assert_eq!(
+            serde_json::to_value(key).unwrap(),
+            serde_json::json!({"actor": null, "bucket": serde_json::to_value(bucket()).unwrap(), "via": {"kind": "owner"}})
+        );
+        let token = ShareLinkToken([7; 32]);
+        let via = serde_json::to_value(AccessPath::Link(token)).unwrap();
+        assert_eq!(via, serde_json::json!({"kind": "link", "id": token.to_string()}));
+        assert_eq!(serde_json::from_value::<AccessPath>(via).unwrap(), AccessPath::Link(token));
+    }
+}
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cluster_health::RegionHealthSummary;
+use crate::{BucketRegion, BucketStatus, RegionCluster};
+
+/*
+* Where a bucket's data lives: writes go to the primary, reads may be served by read-only mirrors.
+* The order of mirrors is the operator's preference and breaks every tie in select_for_read.
+* Serialized as {"primary": "eu-north-1", "mirrors": ["eu-west-2"]}, see validate for what a valid set is.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PlacementSet {
+    pub primary: RegionCluster,
+    #[serde(default)]
+    pub mirrors: Vec<RegionCluster>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum PlacementSetError {
+    #[error("mirror {0} is the primary")]
+    MirrorIsPrimary(RegionCluster),
+    #[error("mirror {0} appears more than once")]
+    DuplicateMirror(RegionCluster),
+}
+
+impl PlacementSet {
+    pub fn new(primary: RegionCluster, mirrors: Vec<RegionCluster>) -> Result<Self, PlacementSetError> {
+        let placement = Self { primary, mirrors };
+        placement.validate()?;
+        Ok(placement)
+    }
+
+    pub fn validate(&self) -> Result<(), PlacementSetError> {
+        for (index, mirror) in self.mirrors.iter().enumerate() {
+            if *mirror == self.primary {
+                return Err(PlacementSetError::MirrorIsPrimary(mirror.clone()));
+            }
+            if self.mirrors[..index].contains(mirror) {
+                return Err(PlacementSetError::DuplicateMirror(mirror.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    // Mirrors are read-only, writes always go to the primary whatever its health.
+    pub fn select_for_write(&self) -> RegionCluster {
+        self.primary.clone()
+    }
+
+    /*
+    * The cluster to serve a read from, the first that applies:
+    * 1. a healthy mirror in client_region, the first one in mirrors order,
+    * 2. the primary if it is healthy,
+    * 3. the mirror with the lowest BucketStatus::severity if it is below the primary's, the first one in mirrors
+    *    order on equal severity,
+    * 4. the primary regardless of its health.
+    * A cluster without a report in health counts as Unreachable, nothing is known about it.
+    * Regions in 1 are compared with BucketRegion::same_region, the zone number is ignored.
+    */
+    pub fn select_for_read(&self, client_region: Option<BucketRegion>, health: &RegionHealthSummary) -> RegionCluster {
+        let severity = |cluster: &RegionCluster| {
+            health
+                .clusters
+                .iter()
+                .find(|report| report.cluster == *cluster)
+                .map_or(BucketStatus::Unreachable.severity(), |report| report.status.severity())
+        };
+        let healthy = |cluster: &RegionCluster| severity(cluster) == BucketStatus::Available.severity();
+
+        let local_mirror = client_region.and_then(|region| {
+            self.mirrors
+                .iter()
+                .find(|mirror| mirror.region.same_region(&region) && healthy(mirror))
+        });
+        if let Some(mirror) = local_mirror {
+            return mirror.clone();
+        }
+        if healthy(&self.primary) {
+            return self.primary.clone();
+        }
+        // min_by_key keeps the first of equal keys, so mirrors order breaks ties.
+        match self.mirrors.iter().min_by_key(|mirror| severity(mirror)) {
+            Some(mirror) if severity(mirror) < severity(&self.primary) => mirror.clone(),
+            _ => self.primary.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::cluster_health::ClusterHealth;
+
+    fn cluster(name: &str) -> RegionCluster {
+        name.parse().unwrap()
+    }
+
+    fn placement() -> PlacementSet {
+        PlacementSet::new(
+            cluster("eu-north-1"),
+            vec![
+                cluster("eu-west-1"),
+                cluster("us-east-1"),
+                cluster("eu-west-2"),
+                cluster("us-east-2"),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn health(statuses: &[(&str, BucketStatus)]) -> RegionHealthSummary {
+        RegionHealthSummary::from_reports(statuses.iter().map(|(name, status)| ClusterHealth {
+            cluster: cluster(name),
+            status: status.clone(),
+            latency_p50_ms: None,
+            latency_p99_ms: None,
+            error_rate_ppm: None,
+            observed_at: datetime!(2024-06-01 12:00 UTC),
+        }))
+    }
+
+    fn all(status: BucketStatus) -> Vec<(&'static str, BucketStatus)> {
+        ["eu-north-1", "eu-west-1", "us-east-1", "eu-west-2", "us-east-2"]
+            .into_iter()
+            .map(|name| (name, status.clone()))
+            .collect()
+    }
+
+    fn with(
+        mut statuses: Vec<(&'static str, BucketStatus)>,
+        name: &str,
+        status: BucketStatus,
+    ) -> Vec<(&'static str, BucketStatus)> {
+        statuses.iter_mut().find(|(cluster, _)| *cluster == name).unwrap().1 = status;
+        statuses
+    }
+
+    #[test]
+    fn read_preference_order() {
+        use BucketStatus::*;
+        let eu_west = Some(BucketRegion::EuropeWest(3));
+        let us_east = Some(BucketRegion::AmericaEast(0));
+        let table = [
+            // A healthy local mirror wins over the healthy primary.
+            (eu_west.clone(), all(Available), "eu-west-1"),
+            (us_east.clone(), all(Available), "us-east-1"),
+            // The first healthy local mirror in mirrors order.
+            (
+                eu_west.clone(),
+                with(all(Available), "eu-west-1", Updating),
+                "eu-west-2",
+            ),
+            // No healthy local mirror, or no region known, the healthy primary.
+            (
+                eu_west.clone(),
+                with(with(all(Available), "eu-west-1", Unreachable), "eu-west-2", Deleting),
+                "eu-north-1",
+            ),
+            (None, all(Available), "eu-north-1"),
+            (Some(BucketRegion::AsiaPacificCentral(1)), all(Available), "eu-north-1"),
+            // The primary is down, a healthy mirror in another region is the healthiest.
+            (None, with(all(Unavailable), "us-east-2", Available), "us-east-2"),
+            (
+                eu_west.clone(),
+                with(all(Available), "eu-north-1", Unavailable),
+                "eu-west-1",
+            ),
+            (None, with(all(Available), "eu-north-1", Unavailable), "eu-west-1"),
+            // Equal severity, the first mirror in mirrors order.
+            (None, with(all(Updating), "eu-north-1", Corrupted), "eu-west-1"),
+            (
+                us_east.clone(),
+                with(with(all(Corrupted), "eu-west-2", Restoring), "us-east-2", Restoring),
+                "eu-west-2",
+            ),
+            // Everything unhealthy, a mirror only if it is healthier than the primary.
+            (None, all(Unreachable), "eu-north-1"),
+            (
+                eu_west.clone(),
+                with(all(Unreachable), "eu-north-1", Updating),
+                "eu-north-1",
+            ),
+            (
+                eu_west.clone(),
+                with(all(Unreachable), "us-east-1", Archiving),
+                "us-east-1",
+            ),
+        ];
+        for (client_region, statuses, expected) in table {
+            assert_eq!(
+                placement()
+                    .select_for_read(client_region.clone(), &health(&statuses))
+                    .to_string(),
+                expected,
+                "{:?} {:?}",
+                client_region,
+                statuses
+            );
+        }
+    }
+
+    #[test]
+    fn unreported_clusters_are_unreachable() {
+        let placement = placement();
+        assert_eq!(
+            placement.select_for_read(None, &RegionHealthSummary::default()),
+            placement.primary
+        );
+        // Only a mirror reports, even though it is updating it is healthier than the unknown primary.
+        let summary = health(&[("us-east-1", BucketStatus::Updating)]);
+        assert_eq!(placement.select_for_read(None, &summary), cluster("us-east-1"));
+        let summary = health(&[("us-east-1", BucketStatus::Unreachable)]);
+        assert_eq!(placement.select_for_read(None, &summary), placement.primary);
+
+        let no_mirrors = PlacementSet::new(cluster("eu-north-1"), Vec::new()).unwrap();
+        let region = Some(BucketRegion::EuropeNorth(1));
+        assert_eq!(
+            no_mirrors.select_for_read(region, &health(&all(BucketStatus::Corrupted))),
+            no_mirrors.primary
+        );
+    }
+
+    #[test]
+    fn writes_go_to_the_primary() {
+        let placement = placement();
+        assert_eq!(placement.select_for_write(), cluster("eu-north-1"));
+        let primary_down = health(&with(
+            all(BucketStatus::Available),
+            "eu-north-1",
+            BucketStatus::Unreachable,
+        ));
+        assert_ne!(placement.select_for_read(None, &primary_down), placement.primary);
+        assert_eq!(placement.select_for_write(), placement.primary);
+    }
+
+    #[test]
+    fn validation() {
+        assert_eq!(
+            PlacementSet::new(cluster("eu-north-1"), vec![cluster("eu-west-1"), cluster("eu-north-1")]),
+            Err(PlacementSetError::MirrorIsPrimary(cluster("eu-north-1")))
+        );
+        assert_eq!(
+            PlacementSet::new(cluster("eu-north-1"), vec![cluster("eu-west-1"), cluster("eu-west-1")]),
+            Err(PlacementSetError::DuplicateMirror(cluster("eu-west-1")))
+        );
+        // Another cluster of the primary's region is a valid mirror.
+        assert!(PlacementSet::new(cluster("eu-north-1"), vec![cluster("eu-north-2")]).is_ok());
+    }
+
+    #[test]
+    fn serde_form() {
+        let json = serde_json::to_string(&placement()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"primary":"eu-north-1","mirrors":["eu-west-1","us-east-1","eu-west-2","us-east-2"]}"#
+        );
+        assert_eq!(serde_json::from_str::<PlacementSet>(&json).unwrap(), placement());
+        let primary_only = serde_json::from_str::<PlacementSet>(r#"{"primary":"eu-north-1"}"#).unwrap();
+        assert_eq!(primary_only.mirrors, []);
+        assert!(serde_json::from_str::<PlacementSet>(r#"{"primary":"eu-north"}"#).is_err());
+    }
+}
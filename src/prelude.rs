@@ -0,0 +1,47 @@
+/*
+* The commonly used types under paths that stay put, `use bucket_common_types::prelude::*;`.
+* Types may move between modules of the crate, their prelude paths never change. Removing an item from here is a
+* breaking change, tests/prelude.rs imports every item through this module only.
+* The third-party types the crate uses in its public API are re-exported too, a downstream crate on another major
+* version of uuid, time or url then fails to compile here instead of somewhere in its own code.
+*/
+
+#[doc(inline)]
+pub use crate::{
+    BucketCompression, BucketEncryption, BucketFeaturesFlags, BucketId, BucketRegion, BucketStatus, BucketStorageClass,
+    BucketVisibility, Capability, ClusterId, CustomEncryptionName, DataResidency, DownloadFormat, PaymentMethod,
+    PaymentModel, PaymentPlan, RegionCluster, UserId, Verification, VideoCodec,
+};
+
+#[doc(inline)]
+pub use crate::bucket_description::{BucketDescription, BucketDisplayName, BucketName};
+#[doc(inline)]
+pub use crate::bucket_guid::BucketGuid;
+#[doc(inline)]
+pub use crate::errors::{ParseFailure, SpannedParseError};
+#[doc(inline)]
+pub use crate::expiry::ExpiryTimestamp;
+#[doc(inline)]
+pub use crate::public_bucket_id::PublicBucketId;
+#[doc(inline)]
+pub use crate::storage_quota::StorageQuota;
+#[doc(inline)]
+pub use crate::util::{ByteSpan, Fingerprint, HumanDuration, LinkOrigin};
+
+#[cfg(feature = "share_link")]
+#[doc(inline)]
+pub use crate::share_link::{BucketSharePermissionFlags, ShareLink, ShareLinkToken};
+
+#[cfg(feature = "secret_share_link")]
+#[doc(inline)]
+pub use crate::keyring::{KeyId, Keyring};
+#[cfg(feature = "secret_share_link")]
+#[doc(inline)]
+pub use crate::secret_share_link::SecretShareLink;
+
+#[doc(inline)]
+pub use time::OffsetDateTime;
+#[doc(inline)]
+pub use url::Url;
+#[doc(inline)]
+pub use uuid::Uuid;
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Sha3_256};
+
+use crate::bucket_guid::BucketGuid;
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::util::{
+    constant_time_eq, strip_trailing_slash, truncate_input, Fingerprint, LinkOrigin, PUBLIC_BUCKET_PATH_URL,
+};
+
+pub const PUBLIC_BUCKET_ID_LEN: usize = 20;
+// RFC 4648 base32 alphabet in lowercase, 5 bits per character.
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+const PUBLIC_BUCKET_ID_CONTEXT: &[u8] = b"bucketdrive-public-bucket-id-v1";
+
+// Server-held secret keying the public bucket id derivation. Rotating it changes every public bucket URL.
+#[derive(Clone)]
+pub struct PublicBucketIdPepper([u8; 32]);
+
+impl PublicBucketIdPepper {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+// Never prints the pepper, only its length and a fingerprint.
+impl fmt::Debug for PublicBucketIdPepper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicBucketIdPepper(<{} bytes, fp:{}>)", self.0.len(), Fingerprint::of(&self.0))
+    }
+}
+
+/*
+* Identifier of a public bucket shown in URLs instead of its BucketGuid, so leaked ids can not be cross-referenced or enumerated.
+* The first 100 bits of SHA3-256(context || pepper || user_id || bucket_id) as 20 lowercase base32 characters.
+* SHA3 is not subject to length extension, so prefixing the pepper keys it. Without the pepper ids can neither be reversed nor guessed.
+* Collisions are expected after about 2^50 buckets, servers keep a unique index of the public ids and refuse to publish a colliding bucket.
+*/
+#[derive(Clone, Copy)]
+pub struct PublicBucketId([u8; PUBLIC_BUCKET_ID_LEN]);
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum PublicBucketIdError {
+    #[error("invalid public bucket id \"{0}\", expected {PUBLIC_BUCKET_ID_LEN} characters of [a-z2-7]")]
+    InvalidFormat(String),
+    #[error("invalid scheme")]
+    InvalidScheme,
+    #[error("invalid host domain")]
+    InvalidHostDomain,
+    #[error("invalid path, expected {PUBLIC_BUCKET_PATH_URL}/<public_bucket_id>")]
+    InvalidPath,
+}
+
+// Points at the first character outside the alphabet, or the whole id when its length is wrong.
+impl SpannedParseError for PublicBucketIdError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            PublicBucketIdError::InvalidFormat(input) => {
+                let invalid = input
+                    .char_indices()
+                    .find(|(_, char)| !char.is_ascii() || !BASE32_ALPHABET.contains(&(*char as u8)));
+                let span = match (input.len() == PUBLIC_BUCKET_ID_LEN, invalid) {
+                    (true, Some((index, char))) => index..index + char.len_utf8(),
+                    _ => 0..input.len(),
+                };
+                ParseFailure::spanned(self, input, span)
+            }
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl PublicBucketId {
+    pub fn derive(guid: &BucketGuid, pepper: &PublicBucketIdPepper) -> Self {
+        let mut hasher = Sha3_256::new();
+        hasher.update(PUBLIC_BUCKET_ID_CONTEXT);
+        hasher.update(pepper.0);
+        hasher.update(guid.user_id.as_bytes());
+        hasher.update(guid.bucket_id.as_bytes());
+        let digest = hasher.finalize();
+
+        let mut bits = [0u8; 16];
+        bits[3..].copy_from_slice(&digest[..13]);
+        // 104 bits, the lowest 4 are dropped.
+        let bits = u128::from_be_bytes(bits) >> 4;
+        let mut id = [0u8; PUBLIC_BUCKET_ID_LEN];
+        for (index, char) in id.iter_mut().enumerate() {
+            let shift = 5 * (PUBLIC_BUCKET_ID_LEN - 1 - index);
+            *char = BASE32_ALPHABET[((bits >> shift) & 0x1f) as usize];
+        }
+        Self(id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever holds characters of BASE32_ALPHABET.
+        std::str::from_utf8(&self.0).expect("public bucket id is ASCII")
+    }
+
+    // https://bucketdrive.co/b/<public_bucket_id>
+    pub fn to_url(&self, origin: &LinkOrigin) -> Result<url::Url, url::ParseError> {
+        url::Url::parse(&format!("{}://{}{}/{}", origin.scheme, origin.host, PUBLIC_BUCKET_PATH_URL, self))
+    }
+
+    pub fn from_url(url: &url::Url) -> Result<Self, PublicBucketIdError> {
+        Self::from_url_with_origin(url, &LinkOrigin::default())
+    }
+
+    pub fn from_url_with_origin(url: &url::Url, origin: &LinkOrigin) -> Result<Self, PublicBucketIdError> {
+        if !origin.matches_scheme(url) {
+            return Err(PublicBucketIdError::InvalidScheme);
+        }
+        if !origin.matches_host(url) {
+            return Err(PublicBucketIdError::InvalidHostDomain);
+        }
+        strip_trailing_slash(url.path())
+            .strip_prefix(PUBLIC_BUCKET_PATH_URL)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .ok_or(PublicBucketIdError::InvalidPath)?
+            .parse()
+    }
+}
+
+impl fmt::Display for PublicBucketId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for PublicBucketId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicBucketId({})", self.as_str())
+    }
+}
+
+// Compared in constant time, a server looking up an id should not leak how much of a guess matched.
+impl PartialEq for PublicBucketId {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for PublicBucketId {}
+
+impl std::hash::Hash for PublicBucketId {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+// Only checks the format, whether an id belongs to a bucket is known by deriving it again.
+impl FromStr for PublicBucketId {
+    type Err = PublicBucketIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let id = <[u8; PUBLIC_BUCKET_ID_LEN]>::try_from(s.as_bytes())
+            .ok()
+            .filter(|id| id.iter().all(|char| BASE32_ALPHABET.contains(char)))
+            .ok_or_else(|| PublicBucketIdError::InvalidFormat(truncate_input(s)))?;
+        Ok(Self(id))
+    }
+}
+
+impl Serialize for PublicBucketId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicBucketId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guid() -> BucketGuid {
+        BucketGuid::new(
+            "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn derivation_golden_vector() {
+        let pepper = PublicBucketIdPepper::new([0x42; 32]);
+        let id = PublicBucketId::derive(&guid(), &pepper);
+        assert_eq!(id.to_string(), "q7ixbhxfxah2eosnntl5");
+        assert_eq!(PublicBucketId::derive(&guid(), &pepper), id);
+
+        let other_bucket = BucketGuid::new(guid().user_id, "22222222-0000-4000-8000-000000000003".parse().unwrap());
+        assert_ne!(PublicBucketId::derive(&other_bucket, &pepper), id);
+        assert_ne!(PublicBucketId::derive(&guid(), &PublicBucketIdPepper::new([0x43; 32])), id);
+    }
+
+    #[test]
+    fn format_validation() {
+        let id = PublicBucketId::derive(&guid(), &PublicBucketIdPepper::new([0x42; 32]));
+        assert_eq!(id.as_str().parse(), Ok(id));
+        assert_eq!("abcdefghijklmnopqr27".parse::<PublicBucketId>().unwrap().as_str(), "abcdefghijklmnopqr27");
+        for invalid in [
+            "",
+            "abcdefghijklmnopqr2",
+            "abcdefghijklmnopqr278",
+            "ABCDEFGHIJKLMNOPQR27",
+            "abcdefghijklmnopqr18",
+            "abcdefghijklmnopqr2\u{e9}",
+        ] {
+            assert_eq!(
+                invalid.parse::<PublicBucketId>(),
+                Err(PublicBucketIdError::InvalidFormat(invalid.to_string())),
+                "{:?}",
+                invalid
+            );
+        }
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{}\"", id));
+        assert_eq!(serde_json::from_str::<PublicBucketId>(&json).unwrap(), id);
+        assert!(serde_json::from_str::<PublicBucketId>("\"abc\"").is_err());
+    }
+
+    #[test]
+    fn debug_never_prints_the_pepper() {
+        let pepper = PublicBucketIdPepper::new([0xab; 32]);
+        let debug = format!("{:?}", pepper);
+        assert!(!debug.contains("ab, ") && !debug.contains("171"), "{}", debug);
+        assert!(debug.starts_with("PublicBucketIdPepper(<32 bytes, fp:"), "{}", debug);
+        let id = PublicBucketId::derive(&guid(), &pepper);
+        assert_eq!(format!("{:?}", id), format!("PublicBucketId({})", id));
+    }
+
+    #[test]
+    fn url_round_trip() {
+        let id = PublicBucketId::derive(&guid(), &PublicBucketIdPepper::new([0x42; 32]));
+        let url = id.to_url(&LinkOrigin::default()).unwrap();
+        assert_eq!(url.as_str(), format!("https://bucketdrive.co/b/{}", id));
+        assert_eq!(PublicBucketId::from_url(&url), Ok(id));
+        let trailing = url::Url::parse(&format!("{}/", url)).unwrap();
+        assert_eq!(PublicBucketId::from_url(&trailing), Ok(id));
+
+        let staging = LinkOrigin::new("https", "staging.bucketdrive.co");
+        let url = id.to_url(&staging).unwrap();
+        assert_eq!(PublicBucketId::from_url_with_origin(&url, &staging), Ok(id));
+        assert_eq!(PublicBucketId::from_url(&url), Err(PublicBucketIdError::InvalidHostDomain));
+
+        let cases = [
+            ("http://bucketdrive.co/b/abcdefghijklmnopqr27", PublicBucketIdError::InvalidScheme),
+            ("https://bucketdrive.co/bucket/abcdefghijklmnopqr27", PublicBucketIdError::InvalidPath),
+            ("https://bucketdrive.co/b", PublicBucketIdError::InvalidPath),
+            ("https://bucketdrive.co/b/abc", PublicBucketIdError::InvalidFormat("abc".to_string())),
+        ];
+        for (url, error) in cases {
+            assert_eq!(PublicBucketId::from_url(&url::Url::parse(url).unwrap()), Err(error), "{}", url);
+        }
+    }
+}
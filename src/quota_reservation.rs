@@ -0,0 +1,412 @@
+#![cfg(feature = "secret_share_link")]
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::bucket_guid::BucketGuid;
+use crate::expiry::ExpiryTimestamp;
+use crate::keyring::{KeyId, Keyring};
+use crate::storage_quota::StorageQuota;
+use crate::UserId;
+
+pub const QUOTA_RESERVATION_VERSION: u8 = 1;
+// Prepended to the canonical bytes, a reservation signature can never pass as another signature.
+const QUOTA_RESERVATION_CONTEXT: &[u8] = b"bucketdrive-quota-reservation-v1";
+
+/*
+* Quota the control plane holds back for one upload, storage nodes accept the upload up to reserved bytes until
+* expires_at. Signed with a keyring key so every node can check it offline.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QuotaReservation {
+    pub id: uuid::Uuid,
+    pub user: UserId,
+    pub bucket: BucketGuid,
+    pub reserved: StorageQuota,
+    pub issued_at: ExpiryTimestamp,
+    pub expires_at: ExpiryTimestamp,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SignedQuotaReservation {
+    pub reservation: QuotaReservation,
+    pub key_id: KeyId,
+    #[serde(with = "crate::util::signature_base64")]
+    pub signature: ed25519_compact::Signature,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum QuotaReservationError {
+    #[error("reservation expired at {expires_at}")]
+    Expired { expires_at: ExpiryTimestamp },
+    #[error("reservation expires at {expires_at}, not after it was issued at {issued_at}")]
+    InvalidTimeWindow { issued_at: ExpiryTimestamp, expires_at: ExpiryTimestamp },
+    #[error("key {0} is not in the keyring")]
+    UnknownKey(KeyId),
+    #[error("key {0} was not valid when the reservation was issued")]
+    KeyNotValid(KeyId),
+    #[error("signature does not cover the reservation")]
+    SignatureInvalid,
+}
+
+impl QuotaReservation {
+    /*
+    * Version, id, user, bucket user id and bucket id as 16 byte UUIDs, reserved as big endian u64, then issued_at and
+    * expires_at as big endian unix seconds. 89 bytes.
+    */
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![QUOTA_RESERVATION_VERSION];
+        bytes.extend_from_slice(self.id.as_bytes());
+        bytes.extend_from_slice(self.user.as_bytes());
+        bytes.extend_from_slice(self.bucket.user_id.as_bytes());
+        bytes.extend_from_slice(self.bucket.bucket_id.as_bytes());
+        bytes.extend_from_slice(&self.reserved.bytes().to_be_bytes());
+        bytes.extend_from_slice(&self.issued_at.unix_timestamp().to_be_bytes());
+        bytes.extend_from_slice(&self.expires_at.unix_timestamp().to_be_bytes());
+        bytes
+    }
+
+    // Expired from expires_at on.
+    pub fn is_expired(&self, now: OffsetDateTime) -> bool {
+        now >= self.expires_at.as_offset_date_time()
+    }
+
+    // Deterministic, signing the same reservation twice gives the same signature.
+    pub fn sign(self, key_id: KeyId, secret_key: &ed25519_compact::SecretKey) -> SignedQuotaReservation {
+        let signature = secret_key.sign(self.signed_message(), None);
+        SignedQuotaReservation {
+            reservation: self,
+            key_id,
+            signature,
+        }
+    }
+
+    fn signed_message(&self) -> Vec<u8> {
+        [QUOTA_RESERVATION_CONTEXT, &self.canonical_bytes()].concat()
+    }
+}
+
+impl SignedQuotaReservation {
+    // The reservation is only handed out once a key valid at issued_at signed it and it has not expired at now.
+    pub fn verify(&self, keyring: &Keyring, now: OffsetDateTime) -> Result<&QuotaReservation, QuotaReservationError> {
+        let reservation = &self.reservation;
+        if reservation.expires_at <= reservation.issued_at {
+            return Err(QuotaReservationError::InvalidTimeWindow {
+                issued_at: reservation.issued_at,
+                expires_at: reservation.expires_at,
+            });
+        }
+        let entry = keyring
+            .get(&self.key_id)
+            .ok_or_else(|| QuotaReservationError::UnknownKey(self.key_id.clone()))?;
+        if !entry.is_valid_at(reservation.issued_at.as_offset_date_time()) {
+            return Err(QuotaReservationError::KeyNotValid(self.key_id.clone()));
+        }
+        ed25519_compact::PublicKey::new(entry.public_key)
+            .verify(reservation.signed_message(), &self.signature)
+            .map_err(|_| QuotaReservationError::SignatureInvalid)?;
+        if reservation.is_expired(now) {
+            return Err(QuotaReservationError::Expired {
+                expires_at: reservation.expires_at,
+            });
+        }
+        Ok(reservation)
+    }
+}
+
+/*
+* Where a reservation is in its life: Draft until signed, then exactly one of Committed (the upload completed),
+* Released (the upload was abandoned) or Expired. Only Draft and Signed hold quota.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumIter, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationState {
+    Draft,
+    Signed,
+    Committed,
+    Released,
+    Expired,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[error("reservation can not go from {from} to {to}")]
+pub struct IllegalReservationTransition {
+    pub from: ReservationState,
+    pub to: ReservationState,
+}
+
+impl ReservationState {
+    pub fn transition_to(self, to: ReservationState) -> Result<ReservationState, IllegalReservationTransition> {
+        use ReservationState::*;
+        match (self, to) {
+            (Draft, Signed) | (Signed, Committed | Released | Expired) => Ok(to),
+            (from, to) => Err(IllegalReservationTransition { from, to }),
+        }
+    }
+
+    pub fn holds_quota(&self) -> bool {
+        matches!(self, ReservationState::Draft | ReservationState::Signed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[error("reserving {} exceeds the remaining {}", requested.bytes(), available.bytes())]
+pub struct QuotaExceeded {
+    pub requested: StorageQuota,
+    pub available: StorageQuota,
+}
+
+// Bytes try_reserve held back and the state of the reservation made for them.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct QuotaReservationDraft {
+    reserved: StorageQuota,
+    state: ReservationState,
+}
+
+impl QuotaReservationDraft {
+    pub fn reserved(&self) -> StorageQuota {
+        self.reserved
+    }
+
+    pub fn state(&self) -> ReservationState {
+        self.state
+    }
+
+    // Once the QuotaReservation for these bytes has been signed.
+    pub fn mark_signed(&mut self) -> Result<(), IllegalReservationTransition> {
+        self.state = self.state.transition_to(ReservationState::Signed)?;
+        Ok(())
+    }
+}
+
+/*
+* Quota left to a user while uploads are in flight, pure bookkeeping with no storage behind it.
+* try_reserve takes bytes away right away, so concurrent uploads can never reserve more than is left. Committed bytes
+* stay taken, released and expired ones become available again.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RemainingQuota {
+    pub available: StorageQuota,
+}
+
+impl RemainingQuota {
+    pub fn new(available: StorageQuota) -> Self {
+        Self { available }
+    }
+
+    // Reserving exactly what is left is allowed.
+    pub fn try_reserve(&mut self, amount: StorageQuota) -> Result<QuotaReservationDraft, QuotaExceeded> {
+        let available = self.available.bytes().checked_sub(amount.bytes()).ok_or(QuotaExceeded {
+            requested: amount,
+            available: self.available,
+        })?;
+        self.available = StorageQuota::from_bytes(available);
+        Ok(QuotaReservationDraft {
+            reserved: amount,
+            state: ReservationState::Draft,
+        })
+    }
+
+    pub fn commit(&mut self, draft: &mut QuotaReservationDraft) -> Result<(), IllegalReservationTransition> {
+        draft.state = draft.state.transition_to(ReservationState::Committed)?;
+        Ok(())
+    }
+
+    pub fn release(&mut self, draft: &mut QuotaReservationDraft) -> Result<(), IllegalReservationTransition> {
+        self.give_back(draft, ReservationState::Released)
+    }
+
+    pub fn expire(&mut self, draft: &mut QuotaReservationDraft) -> Result<(), IllegalReservationTransition> {
+        self.give_back(draft, ReservationState::Expired)
+    }
+
+    // Saturates, the quota may have been lowered while the reservation was held.
+    fn give_back(
+        &mut self,
+        draft: &mut QuotaReservationDraft,
+        to: ReservationState,
+    ) -> Result<(), IllegalReservationTransition> {
+        draft.state = draft.state.transition_to(to)?;
+        self.available = StorageQuota::from_bytes(self.available.bytes().saturating_add(draft.reserved.bytes()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::keyring::KeyringEntry;
+
+    fn key_pair(seed: u8) -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([seed; 32]))
+    }
+
+    fn timestamp(at: OffsetDateTime) -> ExpiryTimestamp {
+        ExpiryTimestamp::try_from(at).unwrap()
+    }
+
+    // reservations-2024 signs from 2024-01-01 until 2025-01-01.
+    fn keyring() -> Keyring {
+        Keyring::new(vec![KeyringEntry {
+            key_id: "reservations-2024".parse().unwrap(),
+            public_key: *key_pair(1).pk,
+            valid_from: timestamp(datetime!(2024-01-01 0:00 UTC)),
+            valid_until: Some(timestamp(datetime!(2025-01-01 0:00 UTC))),
+        }])
+        .unwrap()
+    }
+
+    fn reservation() -> QuotaReservation {
+        let user = "11111111-0000-4000-8000-000000000001".parse().unwrap();
+        QuotaReservation {
+            id: "44444444-0000-4000-8000-000000000004".parse().unwrap(),
+            user,
+            bucket: BucketGuid::new(user, "22222222-0000-4000-8000-000000000002".parse().unwrap()),
+            reserved: StorageQuota::from_bytes(5 << 30),
+            issued_at: timestamp(datetime!(2024-06-01 0:00 UTC)),
+            expires_at: timestamp(datetime!(2024-06-01 1:00 UTC)),
+        }
+    }
+
+    fn signed() -> SignedQuotaReservation {
+        reservation().sign("reservations-2024".parse().unwrap(), &key_pair(1).sk)
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn golden_signed_reservation() {
+        let signed = signed();
+        assert_eq!(
+            hex(&signed.reservation.canonical_bytes()),
+            "01\
+            44444444000040008000000000000004\
+            11111111000040008000000000000001\
+            11111111000040008000000000000001\
+            22222222000040008000000000000002\
+            0000000140000000\
+            00000000665a6480\
+            00000000665a7290"
+        );
+        assert_eq!(
+            hex(signed.signature.as_slice()),
+            "9c235703c262c7a9d064081139541d3ea3dceca83f519e6919bf00854736dadb\
+            9bd7698392c786c84b5c7969dade7b508f8191d3df8eed9fa59606c2ef04930b"
+        );
+        assert_eq!(signed, self::signed());
+    }
+
+    #[test]
+    fn serde() {
+        let signed = signed();
+        let json = serde_json::to_value(&signed).unwrap();
+        assert_eq!(json["reservation"]["reserved"], 5u64 << 30);
+        assert_eq!(json["reservation"]["issued_at"], serde_json::to_value(reservation().issued_at).unwrap());
+        assert_eq!(json["key_id"], "reservations-2024");
+        let parsed = serde_json::from_value::<SignedQuotaReservation>(json).unwrap();
+        assert_eq!(parsed, signed);
+        assert_eq!(parsed.verify(&keyring(), datetime!(2024-06-01 0:30 UTC)), Ok(&reservation()));
+    }
+
+    #[test]
+    fn verification() {
+        let now = datetime!(2024-06-01 0:30 UTC);
+        assert_eq!(signed().verify(&keyring(), now), Ok(&reservation()));
+
+        let mut enlarged = signed();
+        enlarged.reservation.reserved = StorageQuota::from_bytes(50 << 30);
+        assert_eq!(enlarged.verify(&keyring(), now), Err(QuotaReservationError::SignatureInvalid));
+
+        let forged = reservation().sign("reservations-2024".parse().unwrap(), &key_pair(2).sk);
+        assert_eq!(forged.verify(&keyring(), now), Err(QuotaReservationError::SignatureInvalid));
+
+        let unknown = reservation().sign("reservations-2023".parse().unwrap(), &key_pair(1).sk);
+        assert!(matches!(unknown.verify(&keyring(), now), Err(QuotaReservationError::UnknownKey(_))));
+
+        let mut late = reservation();
+        late.issued_at = timestamp(datetime!(2025-01-01 0:00 UTC));
+        late.expires_at = timestamp(datetime!(2025-01-01 1:00 UTC));
+        let late = late.sign("reservations-2024".parse().unwrap(), &key_pair(1).sk);
+        let late_now = datetime!(2025-01-01 0:30 UTC);
+        assert!(matches!(late.verify(&keyring(), late_now), Err(QuotaReservationError::KeyNotValid(_))));
+
+        let mut empty = reservation();
+        empty.expires_at = empty.issued_at;
+        let empty = empty.sign("reservations-2024".parse().unwrap(), &key_pair(1).sk);
+        assert!(matches!(empty.verify(&keyring(), now), Err(QuotaReservationError::InvalidTimeWindow { .. })));
+    }
+
+    #[test]
+    fn expiry_boundary() {
+        let expires = datetime!(2024-06-01 1:00 UTC);
+        let reservation = reservation();
+        assert!(!reservation.is_expired(expires - time::Duration::nanoseconds(1)));
+        assert!(reservation.is_expired(expires));
+        let signed = signed();
+        assert!(signed.verify(&keyring(), expires - time::Duration::SECOND).is_ok());
+        assert_eq!(
+            signed.verify(&keyring(), expires),
+            Err(QuotaReservationError::Expired { expires_at: reservation.expires_at })
+        );
+    }
+
+    #[test]
+    fn transition_matrix() {
+        use ReservationState::*;
+        let allowed = [(Draft, Signed), (Signed, Committed), (Signed, Released), (Signed, Expired)];
+        for from in ReservationState::iter() {
+            for to in ReservationState::iter() {
+                let result = from.transition_to(to);
+                match allowed.contains(&(from, to)) {
+                    true => assert_eq!(result, Ok(to)),
+                    false => assert_eq!(result, Err(IllegalReservationTransition { from, to })),
+                }
+            }
+        }
+        let holding = ReservationState::iter().filter(ReservationState::holds_quota).collect::<Vec<_>>();
+        assert_eq!(holding, [Draft, Signed]);
+    }
+
+    #[test]
+    fn bookkeeping() {
+        let mut remaining = RemainingQuota::new(StorageQuota::from_bytes(100));
+        let mut committed = remaining.try_reserve(StorageQuota::from_bytes(60)).unwrap();
+        assert_eq!(
+            remaining.try_reserve(StorageQuota::from_bytes(41)),
+            Err(QuotaExceeded {
+                requested: StorageQuota::from_bytes(41),
+                available: StorageQuota::from_bytes(40),
+            })
+        );
+        let mut released = remaining.try_reserve(StorageQuota::from_bytes(40)).unwrap();
+        assert_eq!(remaining.available, StorageQuota::ZERO);
+
+        // Neither can finish before the reservation was signed.
+        assert!(remaining.commit(&mut committed).is_err());
+        assert!(remaining.release(&mut released).is_err());
+        assert_eq!(remaining.available, StorageQuota::ZERO);
+
+        committed.mark_signed().unwrap();
+        released.mark_signed().unwrap();
+        remaining.commit(&mut committed).unwrap();
+        remaining.release(&mut released).unwrap();
+        assert_eq!(remaining.available, StorageQuota::from_bytes(40));
+        assert_eq!((committed.state(), released.state()), (ReservationState::Committed, ReservationState::Released));
+
+        // Finished reservations give nothing back a second time.
+        assert!(remaining.release(&mut released).is_err());
+        assert!(remaining.expire(&mut committed).is_err());
+        assert_eq!(remaining.available, StorageQuota::from_bytes(40));
+
+        let mut expired = remaining.try_reserve(StorageQuota::from_bytes(40)).unwrap();
+        expired.mark_signed().unwrap();
+        remaining.expire(&mut expired).unwrap();
+        assert_eq!(remaining.available, StorageQuota::from_bytes(40));
+        assert_eq!(expired.reserved(), StorageQuota::from_bytes(40));
+    }
+}
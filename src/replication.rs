@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{BucketRegion, DataResidency, RegionCluster};
+
+/*
+* How a bucket has to be replicated, e.g. 3 copies across at least 2 EU regions.
+* Regions are compared without their zone number, EuropeNorth(1) and EuropeNorth(2) are the same region.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReplicationPolicy {
+    pub min_copies: u8,
+    pub allowed_regions: Vec<BucketRegion>,
+    pub min_distinct_regions: u8,
+    pub residency: DataResidency,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum ReplicationPolicyError {
+    #[error("at least one copy is required")]
+    NoCopies,
+    #[error("{min_distinct_regions} distinct regions can not be reached with {min_copies} copies")]
+    TooManyDistinctRegions { min_distinct_regions: u8, min_copies: u8 },
+    #[error("no regions allowed")]
+    NoAllowedRegions,
+    #[error("allowed region {region} is outside the {residency} residency")]
+    RegionOutsideResidency { region: BucketRegion, residency: DataResidency },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum PlacementViolation {
+    #[error("placement {placement} is outside the {residency} residency")]
+    ResidencyBreach { placement: RegionCluster, residency: DataResidency },
+    #[error("placement {placement} is not in an allowed region")]
+    RegionNotAllowed { placement: RegionCluster },
+    #[error("placement {placement} is listed more than once")]
+    DuplicatePlacement { placement: RegionCluster },
+    #[error("{copies} copies placed, at least {min_copies} required")]
+    TooFewCopies { copies: usize, min_copies: u8 },
+    // The placement shares its region with an earlier one.
+    #[error("placement {placement} does not add a new region, {distinct} of {min_distinct_regions} distinct regions")]
+    TooFewDistinctRegions { placement: RegionCluster, distinct: usize, min_distinct_regions: u8 },
+}
+
+impl ReplicationPolicy {
+    pub fn validate(&self) -> Result<(), ReplicationPolicyError> {
+        if self.min_copies == 0 {
+            return Err(ReplicationPolicyError::NoCopies);
+        }
+        if self.min_distinct_regions > self.min_copies {
+            return Err(ReplicationPolicyError::TooManyDistinctRegions {
+                min_distinct_regions: self.min_distinct_regions,
+                min_copies: self.min_copies,
+            });
+        }
+        if self.allowed_regions.is_empty() {
+            return Err(ReplicationPolicyError::NoAllowedRegions);
+        }
+        if let Some(region) = self.allowed_regions.iter().find(|region| !self.residency.allows(region)) {
+            return Err(ReplicationPolicyError::RegionOutsideResidency {
+                region: region.clone(),
+                residency: self.residency,
+            });
+        }
+        Ok(())
+    }
+
+    /*
+    * Every placement is checked for residency, allowed region and duplicates first, then the copy count and
+    * the number of distinct regions. Returns the first violation.
+    */
+    pub fn satisfied_by(&self, placements: &[RegionCluster]) -> Result<(), PlacementViolation> {
+        for (index, placement) in placements.iter().enumerate() {
+            if !self.residency.allows(&placement.region) {
+                return Err(PlacementViolation::ResidencyBreach {
+                    placement: placement.clone(),
+                    residency: self.residency,
+                });
+            }
+            if !self.allowed_regions.iter().any(|region| region.same_region(&placement.region)) {
+                return Err(PlacementViolation::RegionNotAllowed {
+                    placement: placement.clone(),
+                });
+            }
+            if placements[..index].contains(placement) {
+                return Err(PlacementViolation::DuplicatePlacement {
+                    placement: placement.clone(),
+                });
+            }
+        }
+        if placements.len() < self.min_copies as usize {
+            return Err(PlacementViolation::TooFewCopies {
+                copies: placements.len(),
+                min_copies: self.min_copies,
+            });
+        }
+        let mut distinct = Vec::<&BucketRegion>::new();
+        let mut first_repeat = None;
+        for placement in placements {
+            match distinct.iter().any(|region| region.same_region(&placement.region)) {
+                true => first_repeat = first_repeat.or(Some(placement)),
+                false => distinct.push(&placement.region),
+            }
+        }
+        if distinct.len() < self.min_distinct_regions as usize {
+            // With fewer distinct regions than copies at least one placement repeats a region.
+            let placement = first_repeat.unwrap_or(&placements[0]).clone();
+            return Err(PlacementViolation::TooFewDistinctRegions {
+                placement,
+                distinct: distinct.len(),
+                min_distinct_regions: self.min_distinct_regions,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eu_policy() -> ReplicationPolicy {
+        ReplicationPolicy {
+            min_copies: 3,
+            allowed_regions: vec![
+                BucketRegion::EuropeNorth(0),
+                BucketRegion::EuropeWest(0),
+                BucketRegion::EuropeCentral(0),
+            ],
+            min_distinct_regions: 2,
+            residency: DataResidency::Europe,
+        }
+    }
+
+    fn placements(placements: &[&str]) -> Vec<RegionCluster> {
+        placements.iter().map(|placement| placement.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn satisfied() {
+        let policy = eu_policy();
+        assert_eq!(policy.validate(), Ok(()));
+        assert_eq!(policy.satisfied_by(&placements(&["eu-north-1", "eu-north-2", "eu-west-1"])), Ok(()));
+        assert_eq!(policy.satisfied_by(&placements(&["eu-north-1", "eu-west-1", "eu-center-4"])), Ok(()));
+    }
+
+    #[test]
+    fn too_few_copies() {
+        assert_eq!(
+            eu_policy().satisfied_by(&placements(&["eu-north-1", "eu-west-1"])),
+            Err(PlacementViolation::TooFewCopies { copies: 2, min_copies: 3 })
+        );
+        assert_eq!(
+            eu_policy().satisfied_by(&placements(&["eu-north-1", "eu-west-1", "eu-north-1"])),
+            Err(PlacementViolation::DuplicatePlacement {
+                placement: "eu-north-1".parse().unwrap()
+            })
+        );
+    }
+
+    #[test]
+    fn same_region_twice_counts_once() {
+        let mut policy = eu_policy();
+        policy.min_distinct_regions = 3;
+        assert_eq!(
+            policy.satisfied_by(&placements(&["eu-north-1", "eu-north-2", "eu-west-1"])),
+            Err(PlacementViolation::TooFewDistinctRegions {
+                placement: "eu-north-2".parse().unwrap(),
+                distinct: 2,
+                min_distinct_regions: 3
+            })
+        );
+    }
+
+    #[test]
+    fn residency_breach() {
+        assert_eq!(
+            eu_policy().satisfied_by(&placements(&["eu-north-1", "us-east-1", "eu-west-1"])),
+            Err(PlacementViolation::ResidencyBreach {
+                placement: "us-east-1".parse().unwrap(),
+                residency: DataResidency::Europe
+            })
+        );
+        assert_eq!(
+            eu_policy().satisfied_by(&placements(&["eu-north-1", "eu-south-1", "eu-west-1"])),
+            Err(PlacementViolation::RegionNotAllowed {
+                placement: "eu-south-1".parse().unwrap()
+            })
+        );
+    }
+
+    #[test]
+    fn validate_policy() {
+        let mut policy = eu_policy();
+        policy.min_copies = 0;
+        assert_eq!(policy.validate(), Err(ReplicationPolicyError::NoCopies));
+
+        let mut policy = eu_policy();
+        policy.min_distinct_regions = 4;
+        assert!(matches!(policy.validate(), Err(ReplicationPolicyError::TooManyDistinctRegions { .. })));
+
+        let mut policy = eu_policy();
+        policy.allowed_regions.clear();
+        assert_eq!(policy.validate(), Err(ReplicationPolicyError::NoAllowedRegions));
+
+        let mut policy = eu_policy();
+        policy.allowed_regions.push(BucketRegion::AmericaEast(0));
+        assert_eq!(
+            policy.validate(),
+            Err(ReplicationPolicyError::RegionOutsideResidency {
+                region: BucketRegion::AmericaEast(0),
+                residency: DataResidency::Europe
+            })
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let policy = eu_policy();
+        let json = serde_json::to_string(&policy).unwrap();
+        assert!(json.contains("\"residency\":\"Europe\""));
+        assert_eq!(serde_json::from_str::<ReplicationPolicy>(&json).unwrap(), policy);
+    }
+}
@@ -0,0 +1,345 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::util::truncate_input;
+
+// IMF-fixdate, the only HTTP-date form servers may send, e.g. "Sun, 06 Nov 1994 08:49:37 GMT".
+const HTTP_DATE_FORMAT: &[FormatItem<'static>] =
+    format_description!("[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT");
+
+/*
+* How far apart the retries of one request are spread. With None all clients hit by the same 429 retry in lockstep.
+* Full picks a delay in [0, computed], Equal in [computed / 2, computed].
+*/
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Hash, Default, strum::Display, strum::EnumString, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum JitterMode {
+    None,
+    #[default]
+    Full,
+    Equal,
+}
+
+/*
+* Capped exponential backoff for requests that failed with a 429 or 503. The computed delay before retrying after the
+* n-th attempt is min(max_delay, base_delay * 2^(n - 1)), jitter is applied on top of that.
+* max_attempts counts every request including the first one. Delays are configured in milliseconds.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub max_attempts: u8,
+    #[serde(rename = "base_delay_ms", with = "duration_millis")]
+    pub base_delay: Duration,
+    #[serde(rename = "max_delay_ms", with = "duration_millis")]
+    pub max_delay: Duration,
+    pub jitter: JitterMode,
+}
+
+// The recommended policy: 5 attempts, starting at 200ms, never waiting more than 30s between two, full jitter.
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(30),
+            jitter: JitterMode::Full,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /*
+    * Delay before the next attempt after attempt number `attempt` (starting at 1) failed, None once all attempts are used.
+    * Pure apart from the random numbers drawn from rng, the same rng state always gives the same delay.
+    */
+    pub fn delay_for_attempt(&self, attempt: u8, rng: &mut impl RngCore) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let exponent = u32::from(attempt.saturating_sub(1));
+        let computed = 2u32
+            .checked_pow(exponent)
+            .and_then(|factor| self.base_delay.checked_mul(factor))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        // Delays beyond u64::MAX nanoseconds, 584 years, are not worth drawing exactly.
+        let nanos = u64::try_from(computed.as_nanos()).unwrap_or(u64::MAX);
+        let delay = match self.jitter {
+            JitterMode::None => nanos,
+            JitterMode::Full => rng.gen_range(0..=nanos),
+            JitterMode::Equal => nanos - nanos / 2 + rng.gen_range(0..=nanos / 2),
+        };
+        Some(Duration::from_nanos(delay))
+    }
+
+    /*
+    * Like delay_for_attempt, but a Retry-After given by the server replaces the computed delay.
+    * The server knows best when it can take the request again, so its delay is neither capped nor jittered.
+    */
+    pub fn delay_with_retry_after(
+        &self,
+        attempt: u8,
+        retry_after: Option<&RetryAfter>,
+        now: OffsetDateTime,
+        rng: &mut impl RngCore,
+    ) -> Option<Duration> {
+        let computed = self.delay_for_attempt(attempt, rng)?;
+        Some(retry_after.map_or(computed, |retry_after| retry_after.delay_at(now)))
+    }
+}
+
+// Value of the Retry-After header, either a number of seconds or an HTTP-date.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RetryAfter {
+    Delay(Duration),
+    At(OffsetDateTime),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum RetryAfterError {
+    #[error("invalid Retry-After \"{0}\", expected seconds or an HTTP-date")]
+    InvalidFormat(String),
+}
+
+impl SpannedParseError for RetryAfterError {
+    fn failure(&self) -> ParseFailure {
+        let RetryAfterError::InvalidFormat(input) = self;
+        ParseFailure::whole(self, input)
+    }
+}
+
+impl RetryAfter {
+    // A date in the past means the request can be retried right away.
+    pub fn delay_at(&self, now: OffsetDateTime) -> Duration {
+        match self {
+            RetryAfter::Delay(delay) => *delay,
+            RetryAfter::At(at) => Duration::try_from(*at - now).unwrap_or(Duration::ZERO),
+        }
+    }
+}
+
+impl fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetryAfter::Delay(delay) => write!(f, "{}", delay.as_secs()),
+            RetryAfter::At(at) => {
+                let formatted = at.format(HTTP_DATE_FORMAT).map_err(|_| fmt::Error)?;
+                f.write_str(&formatted)
+            }
+        }
+    }
+}
+
+impl FromStr for RetryAfter {
+    type Err = RetryAfterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        if !trimmed.is_empty() && trimmed.bytes().all(|b| b.is_ascii_digit()) {
+            // Only digits, so parsing can only fail on overflow.
+            let seconds = trimmed
+                .parse()
+                .map_err(|_| RetryAfterError::InvalidFormat(truncate_input(s)))?;
+            return Ok(RetryAfter::Delay(Duration::from_secs(seconds)));
+        }
+        PrimitiveDateTime::parse(trimmed, HTTP_DATE_FORMAT)
+            .map(|at| RetryAfter::At(at.assume_utc()))
+            .map_err(|_| RetryAfterError::InvalidFormat(truncate_input(s)))
+    }
+}
+
+mod duration_millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        // Sub-millisecond precision is dropped, configured delays are whole milliseconds.
+        serializer.serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn policy(jitter: JitterMode) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn backoff_is_monotone_and_capped() {
+        let policy = policy(JitterMode::None);
+        let mut rng = StepRng::new(0, 1);
+        let delays = (1..10)
+            .map(|attempt| policy.delay_for_attempt(attempt, &mut rng).unwrap().as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [100, 200, 400, 800, 1600, 2000, 2000, 2000, 2000]);
+
+        // Far beyond the cap and overflowing the factor, still capped.
+        let long = RetryPolicy {
+            max_attempts: u8::MAX,
+            ..policy
+        };
+        let mut previous = Duration::ZERO;
+        for attempt in 1..u8::MAX {
+            let delay = long.delay_for_attempt(attempt, &mut rng).unwrap();
+            assert!(previous <= delay && delay <= long.max_delay, "{}", attempt);
+            previous = delay;
+        }
+        assert_eq!(previous, long.max_delay);
+    }
+
+    #[test]
+    fn attempts_are_exhausted() {
+        let policy = RetryPolicy::default();
+        let mut rng = StdRng::seed_from_u64(7);
+        for attempt in 1..policy.max_attempts {
+            assert!(policy.delay_for_attempt(attempt, &mut rng).is_some(), "{}", attempt);
+        }
+        assert_eq!(policy.delay_for_attempt(policy.max_attempts, &mut rng), None);
+        assert_eq!(policy.delay_for_attempt(u8::MAX, &mut rng), None);
+        let single = RetryPolicy {
+            max_attempts: 1,
+            ..policy
+        };
+        assert_eq!(single.delay_for_attempt(1, &mut rng), None);
+    }
+
+    #[test]
+    fn jitter_bounds() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let without_jitter = policy(JitterMode::None);
+        for attempt in 1..10 {
+            let computed = without_jitter.delay_for_attempt(attempt, &mut rng).unwrap();
+            for _ in 0..200 {
+                let full = policy(JitterMode::Full).delay_for_attempt(attempt, &mut rng).unwrap();
+                assert!(full <= computed, "{:?} > {:?}", full, computed);
+                let equal = policy(JitterMode::Equal).delay_for_attempt(attempt, &mut rng).unwrap();
+                assert!(
+                    computed / 2 <= equal && equal <= computed,
+                    "{:?} for {:?}",
+                    equal,
+                    computed
+                );
+            }
+        }
+
+        // Deterministic given the rng.
+        let draw = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (1..10)
+                .map(|attempt| policy(JitterMode::Full).delay_for_attempt(attempt, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(draw(1), draw(1));
+        assert_ne!(draw(1), draw(2));
+        // Lowest draws.
+        assert_eq!(
+            policy(JitterMode::Full).delay_for_attempt(3, &mut StepRng::new(0, 0)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            policy(JitterMode::Equal).delay_for_attempt(3, &mut StepRng::new(0, 0)),
+            Some(Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn retry_after_parsing() {
+        assert_eq!("120".parse(), Ok(RetryAfter::Delay(Duration::from_secs(120))));
+        assert_eq!(" 0 ".parse(), Ok(RetryAfter::Delay(Duration::ZERO)));
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(date.parse(), Ok(RetryAfter::At(datetime!(1994-11-06 08:49:37 UTC))));
+        assert_eq!(date.parse::<RetryAfter>().unwrap().to_string(), date);
+        assert_eq!(RetryAfter::Delay(Duration::from_secs(120)).to_string(), "120");
+
+        for invalid in [
+            "",
+            "-1",
+            "1.5",
+            "+5",
+            "99999999999999999999999",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Sun, 06 Nov 1994 08:49:37 CET",
+        ] {
+            assert_eq!(
+                invalid.parse::<RetryAfter>(),
+                Err(RetryAfterError::InvalidFormat(invalid.to_string())),
+                "{}",
+                invalid
+            );
+        }
+
+        let now = datetime!(1994-11-06 08:49:00 UTC);
+        assert_eq!(
+            date.parse::<RetryAfter>().unwrap().delay_at(now),
+            Duration::from_secs(37)
+        );
+        let past = datetime!(1994-11-06 09:00 UTC);
+        assert_eq!(date.parse::<RetryAfter>().unwrap().delay_at(past), Duration::ZERO);
+    }
+
+    #[test]
+    fn retry_after_overrides_the_computed_delay() {
+        let policy = policy(JitterMode::Full);
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let mut rng = StdRng::seed_from_u64(3);
+        let retry_after = RetryAfter::Delay(Duration::from_secs(60));
+        assert_eq!(
+            policy.delay_with_retry_after(1, Some(&retry_after), now, &mut rng),
+            Some(Duration::from_secs(60))
+        );
+        let at = RetryAfter::At(datetime!(2024-06-01 12:00:05 UTC));
+        assert_eq!(
+            policy.delay_with_retry_after(2, Some(&at), now, &mut rng),
+            Some(Duration::from_secs(5))
+        );
+        assert!(policy.delay_with_retry_after(2, None, now, &mut rng).unwrap() <= Duration::from_millis(200));
+        // Exhausted attempts stay exhausted, whatever the server says.
+        assert_eq!(
+            policy.delay_with_retry_after(10, Some(&retry_after), now, &mut rng),
+            None
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let json = serde_json::to_string(&RetryPolicy::default()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"max_attempts":5,"base_delay_ms":200,"max_delay_ms":30000,"jitter":"full"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<RetryPolicy>(&json).unwrap(),
+            RetryPolicy::default()
+        );
+        assert_eq!(JitterMode::Equal.to_string(), "equal");
+        assert_eq!("none".parse(), Ok(JitterMode::None));
+    }
+}
@@ -0,0 +1,566 @@
+#![cfg(feature = "share_link")]
+
+use std::io::{self, Read, Write};
+
+use sha3::{Digest, Sha3_256};
+
+use crate::framing::{expect_header, write_header, BinaryFormat, FramingError, FRAME_HEADER_LEN};
+use crate::share_link::ShareLinkToken;
+
+pub const REVOCATION_LIST_VERSION: u8 = 1;
+// Frame header, serial and count.
+const HEADER_LEN: usize = FRAME_HEADER_LEN + 8 + 8;
+
+pub const REVOCATION_BLOOM_VERSION: u8 = 1;
+// Frame header, hash count and bit count.
+const BLOOM_HEADER_LEN: usize = FRAME_HEADER_LEN + 1 + 8;
+
+/*
+* Full set of revoked share link tokens, pulled by storage nodes from the control plane.
+* Binary format: frame header (see framing), serial (u64 BE), count (u64 BE), then count tokens of 32 bytes in strictly
+* ascending order.
+* The serial increases with every change to the list so nodes can tell whether their copy is stale.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RevocationList {
+    serial: u64,
+    tokens: Vec<ShareLinkToken>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RevocationListError {
+    #[error("unsupported revocation list version {0}")]
+    UnsupportedVersion(u8),
+    #[error("token {index} is not greater than the token before it")]
+    Unsorted { index: u64 },
+    #[error("revocation list ended after {read} of {count} tokens")]
+    Truncated { read: u64, count: u64 },
+    #[error("revocation list has data after the last token")]
+    TrailingData,
+    #[error("revocation list serial went backwards from {since} to {current}")]
+    SerialRegression { since: u64, current: u64 },
+    #[error(transparent)]
+    Framing(#[from] FramingError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// What a node holding the list at since_serial has to do to catch up.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RevocationDelta<'a> {
+    UpToDate,
+    // The format has no per-token history, a stale node replaces its whole copy.
+    Replace { serial: u64, tokens: &'a [ShareLinkToken] },
+}
+
+impl RevocationList {
+    // Tokens are sorted and deduplicated.
+    pub fn new(serial: u64, mut tokens: Vec<ShareLinkToken>) -> Self {
+        tokens.sort_unstable();
+        tokens.dedup();
+        Self { serial, tokens }
+    }
+
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    pub fn tokens(&self) -> &[ShareLinkToken] {
+        &self.tokens
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn contains(&self, token: &ShareLinkToken) -> bool {
+        self.tokens.binary_search(token).is_ok()
+    }
+
+    pub fn delta(&self, since_serial: u64) -> Result<RevocationDelta<'_>, RevocationListError> {
+        match since_serial.cmp(&self.serial) {
+            std::cmp::Ordering::Equal => Ok(RevocationDelta::UpToDate),
+            std::cmp::Ordering::Less => Ok(RevocationDelta::Replace {
+                serial: self.serial,
+                tokens: &self.tokens,
+            }),
+            std::cmp::Ordering::Greater => Err(RevocationListError::SerialRegression {
+                since: since_serial,
+                current: self.serial,
+            }),
+        }
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&BinaryFormat::RevocationList.header(REVOCATION_LIST_VERSION))?;
+        writer.write_all(&self.serial.to_be_bytes())?;
+        writer.write_all(&(self.tokens.len() as u64).to_be_bytes())?;
+        for token in &self.tokens {
+            writer.write_all(&token.0)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(reader: impl Read) -> Result<Self, RevocationListError> {
+        let mut reader = RevocationListReader::new(reader)?;
+        let serial = reader.serial();
+        // The count comes from untrusted input, don't preallocate for it.
+        let tokens = reader.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { serial, tokens })
+    }
+}
+
+/*
+* Reads a revocation list one token at a time, memory use does not depend on the list size.
+* Yields an error and then stops if the input is unsorted, truncated or has trailing data.
+*/
+pub struct RevocationListReader<R: Read> {
+    reader: R,
+    serial: u64,
+    count: u64,
+    read: u64,
+    previous: Option<ShareLinkToken>,
+    done: bool,
+}
+
+impl<R: Read> RevocationListReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, RevocationListError> {
+        let mut header = [0u8; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let (version, body) = expect_header(&header, BinaryFormat::RevocationList)?;
+        if version != REVOCATION_LIST_VERSION {
+            return Err(RevocationListError::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            reader,
+            serial: u64::from_be_bytes(body[..8].try_into().unwrap()),
+            count: u64::from_be_bytes(body[8..].try_into().unwrap()),
+            read: 0,
+            previous: None,
+            done: false,
+        })
+    }
+
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    // Number of tokens announced in the header.
+    pub fn token_count(&self) -> u64 {
+        self.count
+    }
+
+    fn next_token(&mut self) -> Result<Option<ShareLinkToken>, RevocationListError> {
+        if self.read == self.count {
+            let mut trailing = [0u8; 1];
+            return match self.reader.read(&mut trailing)? {
+                0 => Ok(None),
+                _ => Err(RevocationListError::TrailingData),
+            };
+        }
+        let mut token = [0u8; 32];
+        self.reader.read_exact(&mut token).map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => RevocationListError::Truncated {
+                read: self.read,
+                count: self.count,
+            },
+            _ => error.into(),
+        })?;
+        let token = ShareLinkToken(token);
+        if self.previous.is_some_and(|previous| previous >= token) {
+            return Err(RevocationListError::Unsorted { index: self.read });
+        }
+        self.previous = Some(token);
+        self.read += 1;
+        Ok(Some(token))
+    }
+}
+
+impl<R: Read> Iterator for RevocationListReader<R> {
+    type Item = Result<ShareLinkToken, RevocationListError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_token().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+/*
+* Bloom filter over revoked tokens so edges can skip the control plane for tokens that are definitely not revoked.
+* A miss is certain, a hit only means the token might be revoked and has to be checked against the full list.
+* Probes use double hashing, index i is h1 + i * h2 with h1 and h2 taken from the SHA3 digest of the token.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RevocationBloom {
+    bit_count: u64,
+    hash_count: u8,
+    words: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RevocationBloomError {
+    #[error("false positive rate {0} is not between 0 and 1")]
+    InvalidFalsePositiveRate(f64),
+    #[error("unsupported revocation bloom version {0}")]
+    UnsupportedVersion(u8),
+    #[error(transparent)]
+    Framing(#[from] FramingError),
+    #[error("revocation bloom is {actual} bytes, expected {expected}")]
+    InvalidLength { expected: usize, actual: usize },
+    #[error("revocation bloom has {bit_count} bits and {hash_count} hashes")]
+    InvalidParameters { bit_count: u64, hash_count: u8 },
+}
+
+impl RevocationBloom {
+    // Sized for expected_count tokens, a filter holding more than that has a higher false positive rate.
+    pub fn new(expected_count: usize, false_positive_rate: f64) -> Result<Self, RevocationBloomError> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(RevocationBloomError::InvalidFalsePositiveRate(false_positive_rate));
+        }
+        let expected_count = expected_count.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bit_count = (-expected_count * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(1.0) as u64;
+        let hash_count = (bit_count as f64 / expected_count * ln2).round().clamp(1.0, u8::MAX as f64) as u8;
+        Ok(Self {
+            bit_count,
+            hash_count,
+            words: vec![0; bit_count.div_ceil(64) as usize],
+        })
+    }
+
+    pub fn from_tokens(
+        tokens: impl IntoIterator<Item = ShareLinkToken>,
+        expected_count: usize,
+        false_positive_rate: f64,
+    ) -> Result<Self, RevocationBloomError> {
+        let mut bloom = Self::new(expected_count, false_positive_rate)?;
+        for token in tokens {
+            bloom.insert(&token);
+        }
+        Ok(bloom)
+    }
+
+    pub fn insert(&mut self, token: &ShareLinkToken) {
+        for index in self.indices(token) {
+            self.words[(index / 64) as usize] |= 1 << (index % 64);
+        }
+    }
+
+    pub fn contains(&self, token: &ShareLinkToken) -> bool {
+        self.indices(token)
+            .all(|index| self.words[(index / 64) as usize] & (1 << (index % 64)) != 0)
+    }
+
+    // Share of bits set, a filter close to 0.5 or above is holding more tokens than it was sized for.
+    pub fn fill_ratio(&self) -> f64 {
+        let set = self.words.iter().map(|word| word.count_ones() as u64).sum::<u64>();
+        set as f64 / self.bit_count as f64
+    }
+
+    pub fn bit_count(&self) -> u64 {
+        self.bit_count
+    }
+
+    pub fn hash_count(&self) -> u8 {
+        self.hash_count
+    }
+
+    fn indices(&self, token: &ShareLinkToken) -> impl Iterator<Item = u64> {
+        let digest = Sha3_256::digest(token.0);
+        let h1 = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        // Odd so consecutive probes never collapse onto the same bit.
+        let h2 = u64::from_be_bytes(digest[8..16].try_into().unwrap()) | 1;
+        let bit_count = self.bit_count;
+        (0..self.hash_count as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bit_count)
+    }
+
+    // Format: frame header (see framing), hash count (u8), bit count (u64 BE), then the bits as u64 BE words.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(BLOOM_HEADER_LEN + self.words.len() * 8);
+        write_header(BinaryFormat::RevocationBloom, REVOCATION_BLOOM_VERSION, &mut bytes);
+        bytes.push(self.hash_count);
+        bytes.extend_from_slice(&self.bit_count.to_be_bytes());
+        for word in &self.words {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RevocationBloomError> {
+        let (version, _) = expect_header(bytes, BinaryFormat::RevocationBloom)?;
+        if version != REVOCATION_BLOOM_VERSION {
+            return Err(RevocationBloomError::UnsupportedVersion(version));
+        }
+        let header = bytes.get(..BLOOM_HEADER_LEN).ok_or(RevocationBloomError::InvalidLength {
+            expected: BLOOM_HEADER_LEN,
+            actual: bytes.len(),
+        })?;
+        let hash_count = header[FRAME_HEADER_LEN];
+        let bit_count = u64::from_be_bytes(header[FRAME_HEADER_LEN + 1..].try_into().unwrap());
+        let invalid_parameters = RevocationBloomError::InvalidParameters { bit_count, hash_count };
+        if bit_count == 0 || hash_count == 0 {
+            return Err(invalid_parameters);
+        }
+        let expected = usize::try_from(bit_count.div_ceil(64))
+            .ok()
+            .and_then(|words| words.checked_mul(8))
+            .and_then(|body| body.checked_add(BLOOM_HEADER_LEN))
+            .ok_or(invalid_parameters.clone())?;
+        if bytes.len() != expected {
+            return Err(RevocationBloomError::InvalidLength {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        let words = bytes[BLOOM_HEADER_LEN..]
+            .chunks_exact(8)
+            .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        // Bits past bit_count are never set by insert.
+        let padding = words.len() as u64 * 64 - bit_count;
+        if padding > 0 && words.last().is_some_and(|last| last >> (64 - padding) != 0) {
+            return Err(invalid_parameters);
+        }
+        Ok(Self {
+            bit_count,
+            hash_count,
+            words,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::FRAME_MAGIC_LEN;
+
+    fn token(index: u64) -> ShareLinkToken {
+        let mut token = [0u8; 32];
+        token[24..].copy_from_slice(&index.to_be_bytes());
+        ShareLinkToken(token)
+    }
+
+    fn round_trip(list: &RevocationList) -> RevocationList {
+        let mut bytes = Vec::new();
+        list.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN + 32 * list.len());
+        RevocationList::read_from(bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn round_trip_sizes() {
+        let empty = RevocationList::new(1, Vec::new());
+        assert_eq!(round_trip(&empty), empty);
+
+        let one = RevocationList::new(2, vec![token(7)]);
+        assert_eq!(round_trip(&one), one);
+        assert!(one.contains(&token(7)));
+        assert!(!one.contains(&token(8)));
+
+        let tokens = (0..100_000).map(|_| ShareLinkToken(rand::random())).collect::<Vec<_>>();
+        let large = RevocationList::new(3, tokens.clone());
+        let parsed = round_trip(&large);
+        assert_eq!(parsed, large);
+        assert_eq!(parsed.serial(), 3);
+        assert!(tokens.iter().all(|token| parsed.contains(token)));
+    }
+
+    #[test]
+    fn new_sorts_and_deduplicates() {
+        let list = RevocationList::new(1, vec![token(3), token(1), token(3), token(2)]);
+        assert_eq!(list.tokens(), &[token(1), token(2), token(3)]);
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let mut bytes = Vec::new();
+        RevocationList::new(1, vec![token(1), token(2), token(3)]).write_to(&mut bytes).unwrap();
+
+        let mut unsorted = bytes.clone();
+        unsorted[HEADER_LEN + 32..HEADER_LEN + 64].copy_from_slice(&token(5).0);
+        assert!(matches!(
+            RevocationList::read_from(unsorted.as_slice()),
+            Err(RevocationListError::Unsorted { index: 2 })
+        ));
+
+        let mut duplicate = bytes.clone();
+        duplicate[HEADER_LEN + 32..HEADER_LEN + 64].copy_from_slice(&token(1).0);
+        assert!(matches!(
+            RevocationList::read_from(duplicate.as_slice()),
+            Err(RevocationListError::Unsorted { index: 1 })
+        ));
+
+        assert!(matches!(
+            RevocationList::read_from(&bytes[..bytes.len() - 1]),
+            Err(RevocationListError::Truncated { read: 2, count: 3 })
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            RevocationList::read_from(trailing.as_slice()),
+            Err(RevocationListError::TrailingData)
+        ));
+
+        let mut version = bytes.clone();
+        version[FRAME_MAGIC_LEN] = 2;
+        assert!(matches!(
+            RevocationList::read_from(version.as_slice()),
+            Err(RevocationListError::UnsupportedVersion(2))
+        ));
+
+        // A list written before framing starts with its version byte.
+        assert!(matches!(
+            RevocationList::read_from(&bytes[FRAME_MAGIC_LEN..]),
+            Err(RevocationListError::Framing(FramingError::UnknownMagic(_)))
+        ));
+
+        assert!(matches!(RevocationList::read_from(&bytes[..4]), Err(RevocationListError::Io(_))));
+    }
+
+    #[test]
+    fn delta_by_serial() {
+        let list = RevocationList::new(5, vec![token(1)]);
+        assert_eq!(list.delta(5).unwrap(), RevocationDelta::UpToDate);
+        assert_eq!(
+            list.delta(4).unwrap(),
+            RevocationDelta::Replace {
+                serial: 5,
+                tokens: &[token(1)]
+            }
+        );
+        assert!(matches!(
+            list.delta(6),
+            Err(RevocationListError::SerialRegression { since: 6, current: 5 })
+        ));
+    }
+
+    // Generates a sorted list on the fly and records the largest read, so nothing of the list is ever held in memory.
+    struct GeneratedList {
+        header: Vec<u8>,
+        position: u64,
+        count: u64,
+        largest_read: usize,
+    }
+
+    impl Read for GeneratedList {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.largest_read = self.largest_read.max(buf.len());
+            let header_len = self.header.len() as u64;
+            let end = header_len + self.count * 32;
+            let mut written = 0;
+            while written < buf.len() && self.position < end {
+                let source = match self.position.checked_sub(header_len) {
+                    None => self.header[self.position as usize..].to_vec(),
+                    Some(offset) => token(offset / 32).0[(offset % 32) as usize..].to_vec(),
+                };
+                let len = source.len().min(buf.len() - written);
+                buf[written..written + len].copy_from_slice(&source[..len]);
+                written += len;
+                self.position += len as u64;
+            }
+            Ok(written)
+        }
+    }
+
+    #[test]
+    fn streaming_reader_is_memory_bound() {
+        let count = 1_000_000u64;
+        let mut header = BinaryFormat::RevocationList.header(REVOCATION_LIST_VERSION).to_vec();
+        header.extend_from_slice(&9u64.to_be_bytes());
+        header.extend_from_slice(&count.to_be_bytes());
+        let mut source = GeneratedList {
+            header,
+            position: 0,
+            count,
+            largest_read: 0,
+        };
+
+        let mut reader = RevocationListReader::new(&mut source).unwrap();
+        assert_eq!(reader.serial(), 9);
+        assert_eq!(reader.token_count(), count);
+        let mut read = 0u64;
+        for next in reader.by_ref() {
+            assert_eq!(next.unwrap(), token(read));
+            read += 1;
+        }
+        assert_eq!(read, count);
+        assert!(source.largest_read <= HEADER_LEN.max(32));
+    }
+
+    #[test]
+    fn bloom_has_no_false_negatives() {
+        let tokens = (0..10_000).map(|_| ShareLinkToken(rand::random())).collect::<Vec<_>>();
+        let bloom = RevocationBloom::from_tokens(tokens.iter().copied(), tokens.len(), 0.01).unwrap();
+        assert!(tokens.iter().all(|token| bloom.contains(token)));
+        assert!(bloom.fill_ratio() > 0.4 && bloom.fill_ratio() < 0.6, "{}", bloom.fill_ratio());
+    }
+
+    #[test]
+    fn bloom_false_positive_rate() {
+        let target = 0.01;
+        let bloom = RevocationBloom::from_tokens((0..10_000).map(token), 10_000, target).unwrap();
+        let absent = 100_000;
+        let false_positives = (10_000..10_000 + absent).filter(|index| bloom.contains(&token(*index))).count();
+        let rate = false_positives as f64 / absent as f64;
+        assert!(rate < 2.0 * target, "{}", rate);
+    }
+
+    #[test]
+    fn bloom_serialization() {
+        let empty = RevocationBloom::new(100, 0.01).unwrap();
+        assert_eq!(empty.fill_ratio(), 0.0);
+        assert!(!empty.contains(&token(1)));
+
+        let bloom = RevocationBloom::from_tokens((0..100).map(token), 100, 0.01).unwrap();
+        let bytes = bloom.to_bytes();
+        let parsed = RevocationBloom::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, bloom);
+        assert!((0..100).all(|index| parsed.contains(&token(index))));
+
+        assert!(matches!(
+            RevocationBloom::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(RevocationBloomError::InvalidLength { .. })
+        ));
+        assert!(matches!(
+            RevocationBloom::from_bytes(&bytes[..FRAME_HEADER_LEN + 3]),
+            Err(RevocationBloomError::InvalidLength { .. })
+        ));
+        assert_eq!(
+            RevocationBloom::from_bytes(&bytes[..3]),
+            Err(RevocationBloomError::Framing(FramingError::Truncated(3)))
+        );
+        let mut version = bytes.clone();
+        version[FRAME_MAGIC_LEN] = 2;
+        assert_eq!(RevocationBloom::from_bytes(&version), Err(RevocationBloomError::UnsupportedVersion(2)));
+        let mut no_hashes = bytes.clone();
+        no_hashes[FRAME_HEADER_LEN] = 0;
+        assert!(matches!(
+            RevocationBloom::from_bytes(&no_hashes),
+            Err(RevocationBloomError::InvalidParameters { hash_count: 0, .. })
+        ));
+        let mut huge = bytes[..BLOOM_HEADER_LEN].to_vec();
+        huge[FRAME_HEADER_LEN + 1..].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(RevocationBloom::from_bytes(&huge).is_err());
+    }
+
+    #[test]
+    fn bloom_rejects_invalid_rate() {
+        for rate in [0.0, 1.0, -0.5, f64::NAN] {
+            assert!(matches!(
+                RevocationBloom::new(10, rate),
+                Err(RevocationBloomError::InvalidFalsePositiveRate(_))
+            ));
+        }
+    }
+}
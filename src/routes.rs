@@ -0,0 +1,302 @@
+#![cfg(feature = "share_link")]
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::bucket_guid::{BucketGuid, BucketGuidParsingError};
+use crate::bucket_object::{BucketObjectKey, BucketObjectKeyError};
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::share_link::{BucketSharePermissionFlags, ShareLinkToken, ShareLinkTokenParsingError};
+use crate::snapshot::SnapshotId;
+use crate::util::{strip_trailing_slash, truncate_input, SHARE_PATH_URL};
+
+// Every gateway route starts with this, SHARE_PATH_URL included.
+pub const API_PATH_URL: &str = "/api/v1";
+
+const BUCKET_SEGMENT: &str = "bucket";
+const OBJECT_SEGMENT: &str = "object";
+const SEARCH_SEGMENT: &str = "search";
+const SHARE_SEGMENT: &str = "share";
+const SNAPSHOT_SEGMENT: &str = "snapshot";
+
+// Everything but the unreserved characters of RFC 3986, '/' included so an object key is always a single segment.
+const OBJECT_KEY_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/*
+* Gateway routes with their typed parameters, paths are only built with to_path and read with parse_path.
+* Bucket routes are API_PATH_URL/bucket/<user_id>/<bucket_id>[/...], share links are served under SHARE_PATH_URL with
+* the path of their link, a token for a ShareLink and user_id/bucket_id for a SecretShareLink.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ApiRoute {
+    Bucket { bucket: BucketGuid },
+    // Creating share links of the bucket.
+    ShareBucket { bucket: BucketGuid },
+    Object { bucket: BucketGuid, key: BucketObjectKey },
+    Search { bucket: BucketGuid },
+    Snapshots { bucket: BucketGuid },
+    Snapshot { bucket: BucketGuid, snapshot: SnapshotId },
+    Share { token: ShareLinkToken },
+    SecretShare { bucket: BucketGuid },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum RouteParseError {
+    #[error("\"{0}\" is not under {API_PATH_URL}")]
+    NotAnApiPath(String),
+    #[error("no route matches \"{0}\"")]
+    UnknownRoute(String),
+    #[error(transparent)]
+    InvalidBucket(#[from] BucketGuidParsingError),
+    #[error("object key \"{0}\" is not percent-encoded UTF-8")]
+    InvalidEncoding(String),
+    #[error(transparent)]
+    InvalidObjectKey(#[from] BucketObjectKeyError),
+    #[error(transparent)]
+    InvalidToken(#[from] ShareLinkTokenParsingError),
+    #[error("invalid snapshot id \"{0}\"")]
+    InvalidSnapshotId(String),
+}
+
+impl SpannedParseError for RouteParseError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            RouteParseError::NotAnApiPath(input)
+            | RouteParseError::UnknownRoute(input)
+            | RouteParseError::InvalidEncoding(input)
+            | RouteParseError::InvalidSnapshotId(input) => ParseFailure::whole(self, input),
+            RouteParseError::InvalidBucket(error) => error.failure(),
+            RouteParseError::InvalidObjectKey(error) => error.failure(),
+            RouteParseError::InvalidToken(error) => error.failure(),
+        }
+    }
+}
+
+/*
+* "." and ".." would be removed as dot-segments by URL normalization, they are the only keys whose dots are encoded.
+* WHATWG URL parsers, the url crate included, remove "%2E" segments as well, these two keys only survive raw paths.
+*/
+fn encode_object_key(key: &BucketObjectKey) -> String {
+    match key.as_str() {
+        "." | ".." => key.as_str().replace('.', "%2E"),
+        key => utf8_percent_encode(key, OBJECT_KEY_SEGMENT).to_string(),
+    }
+}
+
+// Any percent-encoding is accepted, not only the one encode_object_key writes.
+fn decode_object_key(segment: &str) -> Result<BucketObjectKey, RouteParseError> {
+    let key = percent_decode_str(segment)
+        .decode_utf8()
+        .map_err(|_| RouteParseError::InvalidEncoding(truncate_input(segment)))?;
+    Ok(key.parse()?)
+}
+
+fn parse_bucket(user_id: &str, bucket_id: &str) -> Result<BucketGuid, RouteParseError> {
+    Ok(format!("{}/{}", user_id, bucket_id).parse()?)
+}
+
+fn parse_snapshot(segment: &str) -> Result<SnapshotId, RouteParseError> {
+    segment.parse().map_err(|_| RouteParseError::InvalidSnapshotId(truncate_input(segment)))
+}
+
+impl ApiRoute {
+    pub fn to_path(&self) -> String {
+        let bucket_path = |bucket: &BucketGuid| format!("{}/{}/{}", API_PATH_URL, BUCKET_SEGMENT, bucket);
+        match self {
+            ApiRoute::Bucket { bucket } => bucket_path(bucket),
+            ApiRoute::ShareBucket { bucket } => format!("{}/{}", bucket_path(bucket), SHARE_SEGMENT),
+            ApiRoute::Object { bucket, key } => {
+                format!("{}/{}/{}", bucket_path(bucket), OBJECT_SEGMENT, encode_object_key(key))
+            }
+            ApiRoute::Search { bucket } => format!("{}/{}", bucket_path(bucket), SEARCH_SEGMENT),
+            ApiRoute::Snapshots { bucket } => format!("{}/{}", bucket_path(bucket), SNAPSHOT_SEGMENT),
+            ApiRoute::Snapshot { bucket, snapshot } => {
+                format!("{}/{}/{}", bucket_path(bucket), SNAPSHOT_SEGMENT, snapshot)
+            }
+            ApiRoute::Share { token } => format!("{}/{}", SHARE_PATH_URL, token),
+            ApiRoute::SecretShare { bucket } => format!("{}/{}/{}", SHARE_PATH_URL, bucket.user_id, bucket.bucket_id),
+        }
+    }
+
+    // Path without query or fragment, a single trailing slash is accepted.
+    pub fn parse_path(path: &str) -> Result<ApiRoute, RouteParseError> {
+        let rest = strip_trailing_slash(path)
+            .strip_prefix(API_PATH_URL)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .ok_or_else(|| RouteParseError::NotAnApiPath(truncate_input(path)))?;
+        let segments = rest.split('/').collect::<Vec<_>>();
+        let route = match segments.as_slice() {
+            [BUCKET_SEGMENT, user_id, bucket_id, tail @ ..] => {
+                let bucket = parse_bucket(user_id, bucket_id)?;
+                match tail {
+                    [] => ApiRoute::Bucket { bucket },
+                    [SHARE_SEGMENT] => ApiRoute::ShareBucket { bucket },
+                    [OBJECT_SEGMENT, key] => ApiRoute::Object { bucket, key: decode_object_key(key)? },
+                    [SEARCH_SEGMENT] => ApiRoute::Search { bucket },
+                    [SNAPSHOT_SEGMENT] => ApiRoute::Snapshots { bucket },
+                    [SNAPSHOT_SEGMENT, snapshot] => ApiRoute::Snapshot { bucket, snapshot: parse_snapshot(snapshot)? },
+                    _ => return Err(RouteParseError::UnknownRoute(truncate_input(path))),
+                }
+            }
+            [SHARE_SEGMENT, token] => ApiRoute::Share { token: token.parse()? },
+            [SHARE_SEGMENT, user_id, bucket_id] => ApiRoute::SecretShare { bucket: parse_bucket(user_id, bucket_id)? },
+            _ => return Err(RouteParseError::UnknownRoute(truncate_input(path))),
+        };
+        Ok(route)
+    }
+
+    /*
+    * Permissions a caller needs on the bucket for the route, for authorization middleware.
+    * Share routes only need VIEW, what a link grants beyond that is checked against the link itself.
+    */
+    pub fn required_permissions(&self) -> BucketSharePermissionFlags {
+        match self {
+            ApiRoute::Bucket { .. } => BucketSharePermissionFlags::VIEW,
+            ApiRoute::ShareBucket { .. } => BucketSharePermissionFlags::SHARE_BUCKET,
+            ApiRoute::Object { .. } => BucketSharePermissionFlags::READ,
+            ApiRoute::Search { .. } => BucketSharePermissionFlags::SEARCH,
+            ApiRoute::Snapshots { .. } => BucketSharePermissionFlags::VIEW,
+            ApiRoute::Snapshot { .. } => BucketSharePermissionFlags::READ,
+            ApiRoute::Share { .. } => BucketSharePermissionFlags::VIEW,
+            ApiRoute::SecretShare { .. } => BucketSharePermissionFlags::VIEW,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket() -> BucketGuid {
+        BucketGuid::new(
+            "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+        )
+    }
+
+    const BUCKET_PATH: &str =
+        "/api/v1/bucket/11111111-0000-4000-8000-000000000001/22222222-0000-4000-8000-000000000002";
+
+    fn object(key: &str) -> ApiRoute {
+        ApiRoute::Object { bucket: bucket(), key: key.parse().unwrap() }
+    }
+
+    // One route of every variant, see required_permissions_is_exhaustive.
+    fn every_route() -> Vec<ApiRoute> {
+        vec![
+            ApiRoute::Bucket { bucket: bucket() },
+            ApiRoute::ShareBucket { bucket: bucket() },
+            object("/photos/beach.jpg"),
+            ApiRoute::Search { bucket: bucket() },
+            ApiRoute::Snapshots { bucket: bucket() },
+            ApiRoute::Snapshot { bucket: bucket(), snapshot: SnapshotId(uuid::Uuid::from_u128(7)) },
+            ApiRoute::Share { token: ShareLinkToken([3; 32]) },
+            ApiRoute::SecretShare { bucket: bucket() },
+        ]
+    }
+
+    #[test]
+    fn paths() {
+        let paths = every_route().iter().map(ApiRoute::to_path).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                BUCKET_PATH.to_string(),
+                format!("{}/share", BUCKET_PATH),
+                format!("{}/object/%2Fphotos%2Fbeach.jpg", BUCKET_PATH),
+                format!("{}/search", BUCKET_PATH),
+                format!("{}/snapshot", BUCKET_PATH),
+                format!("{}/snapshot/00000000-0000-0000-0000-000000000007", BUCKET_PATH),
+                "/api/v1/share/AwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwM".to_string(),
+                "/api/v1/share/11111111-0000-4000-8000-000000000001/22222222-0000-4000-8000-000000000002".to_string(),
+            ]
+        );
+        assert!(paths[6..].iter().all(|path| path.starts_with(SHARE_PATH_URL)));
+    }
+
+    #[test]
+    fn round_trip() {
+        for route in every_route() {
+            assert_eq!(ApiRoute::parse_path(&route.to_path()), Ok(route.clone()), "{}", route.to_path());
+            assert_eq!(ApiRoute::parse_path(&format!("{}/", route.to_path())), Ok(route));
+        }
+    }
+
+    #[test]
+    fn object_keys_are_one_segment() {
+        for (key, encoded) in [
+            ("a b", "a%20b"),
+            ("100%.txt", "100%25.txt"),
+            ("/caf\u{e9}/\u{1f600}", "%2Fcaf%C3%A9%2F%F0%9F%98%80"),
+            ("a/b/", "a%2Fb%2F"),
+            ("?#&=+", "%3F%23%26%3D%2B"),
+            ("~-_.", "~-_."),
+            (".", "%2E"),
+            ("..", "%2E%2E"),
+        ] {
+            let route = object(key);
+            let path = route.to_path();
+            assert_eq!(path, format!("{}/object/{}", BUCKET_PATH, encoded));
+            assert_eq!(ApiRoute::parse_path(&path), Ok(route));
+            // The path survives URL normalization unchanged, but for the dot keys, see encode_object_key.
+            if !matches!(key, "." | "..") {
+                let url = url::Url::parse(&format!("https://bucketdrive.co{}", path)).unwrap();
+                assert_eq!(url.path(), path);
+            }
+        }
+        // Decoding is lenient, lowercase hex and unencoded characters are accepted.
+        assert_eq!(ApiRoute::parse_path(&format!("{}/object/a%2fb c", BUCKET_PATH)), Ok(object("a/b c")));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |path: &str| ApiRoute::parse_path(path).unwrap_err();
+        assert_eq!(parse("/api/v2/share/x"), RouteParseError::NotAnApiPath("/api/v2/share/x".to_string()));
+        assert_eq!(parse("/api/v1bucket"), RouteParseError::NotAnApiPath("/api/v1bucket".to_string()));
+        assert!(matches!(parse("/api/v1"), RouteParseError::NotAnApiPath(_)));
+        assert!(matches!(parse("/api/v1/"), RouteParseError::NotAnApiPath(_)));
+        for path in [
+            "/api/v1/bucket".to_string(),
+            format!("{}/objects", BUCKET_PATH),
+            format!("{}/object", BUCKET_PATH),
+            format!("{}/object/a/b", BUCKET_PATH),
+            format!("{}/snapshot/x/y", BUCKET_PATH),
+            "/api/v1/share/a/b/c".to_string(),
+        ] {
+            assert_eq!(parse(&path), RouteParseError::UnknownRoute(truncate_input(&path)));
+        }
+        assert!(matches!(parse("/api/v1/bucket/nope/x"), RouteParseError::InvalidBucket(_)));
+        assert!(matches!(parse("/api/v1/share/nope/x"), RouteParseError::InvalidBucket(_)));
+        assert!(matches!(parse("/api/v1/share/short"), RouteParseError::InvalidToken(_)));
+        assert_eq!(
+            parse(&format!("{}/snapshot/7", BUCKET_PATH)),
+            RouteParseError::InvalidSnapshotId("7".to_string())
+        );
+        assert_eq!(parse(&format!("{}/object/%FF", BUCKET_PATH)), RouteParseError::InvalidEncoding("%FF".to_string()));
+        assert_eq!(parse(&format!("{}/object/a%09b", BUCKET_PATH)).failure().kind, "ForbiddenCharacter");
+    }
+
+    /*
+    * required_permissions has no wildcard arm, neither has this match, so a new route does not compile until both
+    * give it permissions. every_route must then get one of it too.
+    */
+    #[test]
+    fn required_permissions_is_exhaustive() {
+        for route in every_route() {
+            let expected = match &route {
+                ApiRoute::Bucket { .. } => BucketSharePermissionFlags::VIEW,
+                ApiRoute::ShareBucket { .. } => BucketSharePermissionFlags::SHARE_BUCKET,
+                ApiRoute::Object { .. } => BucketSharePermissionFlags::READ,
+                ApiRoute::Search { .. } => BucketSharePermissionFlags::SEARCH,
+                ApiRoute::Snapshots { .. } => BucketSharePermissionFlags::VIEW,
+                ApiRoute::Snapshot { .. } => BucketSharePermissionFlags::READ,
+                ApiRoute::Share { .. } => BucketSharePermissionFlags::VIEW,
+                ApiRoute::SecretShare { .. } => BucketSharePermissionFlags::VIEW,
+            };
+            assert_eq!(route.required_permissions(), expected, "{:?}", route);
+            assert!(!expected.is_empty());
+        }
+        let source = include_str!("routes.rs");
+        let body = &source[source.find("fn required_permissions").unwrap()..source.find("#[cfg(test)]").unwrap()];
+        assert!(!body.contains("_ =>"));
+    }
+}
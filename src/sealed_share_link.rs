@@ -0,0 +1,455 @@
+#![cfg(feature = "secret_share_link")]
+
+use std::fmt;
+use std::str::FromStr;
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use base64::{Engine, engine::general_purpose};
+use ed25519_compact::x25519;
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+use crate::UserId;
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::expiry::ExpiryTimestamp;
+use crate::secret_share_link::decode_expiry;
+use crate::share_link::BucketSharePermissionFlags;
+use crate::util::{strip_trailing_slash, LinkOrigin, DOMAIN_URL, SECRET_SHARE_PATH_URL};
+
+const WRAP_KEY_INFO: &[u8] = b"bucketdrive/wrap-key/v1";
+/*
+* Prepended to the signed hash input. SecretShareLink's input also starts with user_id and bucket_id and is signed by
+* the same key, without this a sealed link signature could pass as a secret share link signature or the reverse.
+*/
+const SIGNED_SEALED_LINK_CONTEXT: &[u8] = b"bucketdrive-sealed-share-link-v1";
+
+/*
+* The bucket key wrapped to a single recipient.
+* A fresh ephemeral X25519 key pair is generated per recipient, the shared secret is run through HKDF-SHA3-256
+* to get a key encryption key and nonce, and the bucket key is then encrypted with AES-256-GCM using the recipient id as associated data.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RecipientWrappedKey {
+    pub recipient: UserId,
+    pub wrapped: Vec<u8>,
+    pub ephemeral_pk: [u8; 32],
+}
+
+fn derive_key_encryption_key(
+    shared_secret: &[u8],
+    ephemeral_pk: &[u8; 32],
+    recipient_pk: &[u8; 32],
+) -> (aes_gcm::Key<Aes256Gcm>, [u8; 12]) {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral_pk);
+    salt[32..].copy_from_slice(recipient_pk);
+    let hkdf = Hkdf::<Sha3_256>::new(Some(&salt), shared_secret);
+    let mut okm = [0u8; 44];
+    hkdf.expand(WRAP_KEY_INFO, &mut okm)
+        .expect("44 bytes is a valid HKDF-SHA3-256 output length");
+    let mut nonce = [0u8; 12];
+    nonce.copy_from_slice(&okm[32..]);
+    (*aes_gcm::Key::<Aes256Gcm>::from_slice(&okm[..32]), nonce)
+}
+
+impl RecipientWrappedKey {
+    pub fn wrap(
+        recipient: UserId,
+        recipient_pk: &x25519::PublicKey,
+        bucket_key: &aes_gcm::Key<Aes256Gcm>,
+    ) -> Result<Self, UnsealError> {
+        let ephemeral = x25519::KeyPair::generate();
+        let shared_secret = recipient_pk.dh(&ephemeral.sk)?;
+        let (kek, nonce) = derive_key_encryption_key(shared_secret.as_slice(), &ephemeral.pk, recipient_pk);
+        let wrapped = Aes256Gcm::new(&kek)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: bucket_key.as_slice(),
+                    aad: recipient.as_bytes(),
+                },
+            )
+            .map_err(|_| UnsealError::Decryption)?;
+        Ok(Self {
+            recipient,
+            wrapped,
+            ephemeral_pk: *ephemeral.pk,
+        })
+    }
+
+    pub fn unwrap_key(&self, recipient_sk: &x25519::SecretKey) -> Result<aes_gcm::Key<Aes256Gcm>, UnsealError> {
+        let recipient_pk = recipient_sk.recover_public_key()?;
+        let shared_secret = x25519::PublicKey::new(self.ephemeral_pk).dh(recipient_sk)?;
+        let (kek, nonce) = derive_key_encryption_key(shared_secret.as_slice(), &self.ephemeral_pk, &recipient_pk);
+        let key = Aes256Gcm::new(&kek)
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: self.wrapped.as_slice(),
+                    aad: self.recipient.as_bytes(),
+                },
+            )
+            .map_err(|_| UnsealError::Decryption)?;
+        if key.len() != 32 {
+            return Err(UnsealError::Decryption);
+        }
+        Ok(*aes_gcm::Key::<Aes256Gcm>::from_slice(&key))
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum UnsealError {
+    #[error("Not a recipient of this link")]
+    NotARecipient,
+    #[error("Failed to decrypt wrapped key")]
+    Decryption,
+    #[error(transparent)]
+    KeyExchange(#[from] ed25519_compact::Error),
+}
+
+// Same as SecretShareLink but the bucket key is never part of the link, only the keys wrapped to each recipient.
+#[derive(Debug, Clone)]
+pub struct SecretShareLinkSealed {
+    pub user_id: uuid::Uuid,
+    pub bucket_id: uuid::Uuid,
+    pub recipients: Vec<RecipientWrappedKey>,
+    pub permission: BucketSharePermissionFlags,
+    pub expires: Option<ExpiryTimestamp>,
+    pub signature: ed25519_compact::Signature,
+}
+
+// Does not include the signature in the hash. The wrapped keys are part of the hash so they can not be swapped or altered.
+fn hash_sealed_share_link<D: Digest>(
+    user_id: uuid::Uuid,
+    bucket_id: uuid::Uuid,
+    recipients: &[RecipientWrappedKey],
+    permission: BucketSharePermissionFlags,
+    expires: Option<ExpiryTimestamp>,
+    output: &mut [u8],
+) {
+    let mut hasher = D::new();
+    hasher.update(SIGNED_SEALED_LINK_CONTEXT);
+    hasher.update(user_id.as_bytes());
+    hasher.update(bucket_id.as_bytes());
+    hasher.update((recipients.len() as u32).to_be_bytes());
+    for recipient in recipients {
+        hasher.update(recipient.recipient.as_bytes());
+        hasher.update(recipient.ephemeral_pk);
+        hasher.update((recipient.wrapped.len() as u32).to_be_bytes());
+        hasher.update(&recipient.wrapped);
+    }
+    hasher.update(permission.bits().to_be_bytes());
+    if let Some(expires) = expires {
+        hasher.update(expires.unix_timestamp().to_be_bytes());
+    }
+    output.copy_from_slice(&hasher.finalize());
+}
+
+impl SecretShareLinkSealed {
+    pub fn new(
+        user_id: uuid::Uuid,
+        bucket_id: uuid::Uuid,
+        bucket_key: aes_gcm::Key<Aes256Gcm>,
+        recipients: &[(UserId, x25519::PublicKey)],
+        permission: BucketSharePermissionFlags,
+        expires: Option<ExpiryTimestamp>,
+        secret_key: &ed25519_compact::SecretKey,
+    ) -> Result<Self, UnsealError> {
+        let recipients = recipients
+            .iter()
+            .map(|(recipient, pk)| RecipientWrappedKey::wrap(*recipient, pk, &bucket_key))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut hash_output = [0; 32];
+        hash_sealed_share_link::<Sha3_256>(user_id, bucket_id, &recipients, permission, expires, &mut hash_output);
+        let signature = secret_key.sign(hash_output, None);
+        Ok(Self {
+            user_id,
+            bucket_id,
+            recipients,
+            permission,
+            expires,
+            signature,
+        })
+    }
+
+    pub fn verify_signature(&self, public_signing_key: ed25519_compact::PublicKey) -> Result<(), ed25519_compact::Error> {
+        public_signing_key.verify(self.get_token(), &self.signature)
+    }
+
+    // Tries every wrapped key, the recipient id is authenticated as associated data so only the matching entry decrypts.
+    pub fn unseal(&self, recipient_sk: &x25519::SecretKey) -> Result<aes_gcm::Key<Aes256Gcm>, UnsealError> {
+        self.recipients
+            .iter()
+            .find_map(|recipient| recipient.unwrap_key(recipient_sk).ok())
+            .ok_or(UnsealError::NotARecipient)
+    }
+
+    pub fn get_token(&self) -> [u8; 32] {
+        let mut hash_output = [0; 32];
+        hash_sealed_share_link::<Sha3_256>(
+            self.user_id,
+            self.bucket_id,
+            &self.recipients,
+            self.permission,
+            self.expires,
+            &mut hash_output,
+        );
+        hash_output
+    }
+}
+
+impl fmt::Display for SecretShareLinkSealed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "https://{}{}/{}/{}#{}#{}",
+            DOMAIN_URL,
+            SECRET_SHARE_PATH_URL,
+            self.user_id,
+            self.bucket_id,
+            general_purpose::URL_SAFE_NO_PAD.encode(bincode::serialize(&self.recipients).unwrap()),
+            general_purpose::URL_SAFE_NO_PAD.encode(self.permission.bits().to_be_bytes()),
+        )?;
+        if let Some(expires) = self.expires {
+            write!(
+                f,
+                "#{}",
+                general_purpose::URL_SAFE_NO_PAD.encode(expires.unix_timestamp().to_be_bytes())
+            )?;
+        }
+        write!(f, "#{}", general_purpose::URL_SAFE_NO_PAD.encode(self.signature.as_slice()))
+    }
+}
+
+#[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
+pub enum SecretShareLinkSealedParsingError {
+    #[error("Invalid scheme")]
+    InvalidScheme,
+    #[error("Invalid host")]
+    InvalidHostDomain,
+    #[error("Invalid path")]
+    InvalidPath,
+    #[error("Invalid fragment")]
+    InvalidFragment,
+    #[error("Invalid recipients")]
+    InvalidRecipients,
+    #[error("Invalid permission")]
+    InvalidPermission,
+    #[error("Invalid expiry")]
+    InvalidExpiry,
+
+    #[error(transparent)]
+    Base64Decoding(#[from] base64::DecodeError),
+    #[error(transparent)]
+    Uuid(#[from] uuid::Error),
+    #[error(transparent)]
+    Signature(#[from] ed25519_compact::Error),
+    #[error(transparent)]
+    UrlParse(#[from] url::ParseError),
+}
+
+// Like SecretShareLinkParsingError, sealed links are never echoed.
+impl SpannedParseError for SecretShareLinkSealedParsingError {
+    fn failure(&self) -> ParseFailure {
+        ParseFailure::unspanned(self)
+    }
+}
+
+impl TryFrom<url::Url> for SecretShareLinkSealed {
+    type Error = SecretShareLinkSealedParsingError;
+
+    fn try_from(value: url::Url) -> Result<Self, Self::Error> {
+        Self::from_url_with_origin(&value, &LinkOrigin::default())
+    }
+}
+
+impl FromStr for SecretShareLinkSealed {
+    type Err = SecretShareLinkSealedParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(url::Url::parse(s)?)
+    }
+}
+
+impl SecretShareLinkSealed {
+    // Scheme and host are compared case-insensitively, path and fragment are not.
+    pub fn from_url_with_origin(value: &url::Url, origin: &LinkOrigin) -> Result<Self, SecretShareLinkSealedParsingError> {
+        type Error = SecretShareLinkSealedParsingError;
+        if !origin.matches_scheme(value) {
+            return Err(Error::InvalidScheme);
+        }
+        if !origin.matches_host(value) {
+            return Err(Error::InvalidHostDomain);
+        }
+        let path = strip_trailing_slash(value.path())
+            .strip_prefix(SECRET_SHARE_PATH_URL)
+            .ok_or(Error::InvalidPath)?;
+        let parts = path.split('/').collect::<Vec<&str>>();
+        if parts.len() != 3 || !parts[0].is_empty() {
+            return Err(Error::InvalidPath);
+        }
+        let user_id = parts[1].parse::<uuid::Uuid>()?;
+        let bucket_id = parts[2].parse::<uuid::Uuid>()?;
+
+        // Fragment is recipients#permission[#expires]#signature.
+        let fragments = value
+            .fragment()
+            .ok_or(Error::InvalidFragment)?
+            .split('#')
+            .collect::<Vec<&str>>();
+        let has_expires_field = match fragments.len() {
+            3 => false,
+            4 => true,
+            _ => return Err(Error::InvalidFragment),
+        };
+        let recipients: Vec<RecipientWrappedKey> =
+            bincode::deserialize(general_purpose::URL_SAFE_NO_PAD.decode(fragments[0])?.as_slice())
+                .map_err(|_| Error::InvalidRecipients)?;
+        let permission = BucketSharePermissionFlags::from_bits(u32::from_be_bytes(
+            general_purpose::URL_SAFE_NO_PAD
+                .decode(fragments[1])?
+                .try_into()
+                .map_err(|_| Error::InvalidPermission)?,
+        ))
+        .ok_or(Error::InvalidPermission)?;
+        let expires = match has_expires_field {
+            true => Some(
+                decode_expiry(&general_purpose::URL_SAFE_NO_PAD.decode(fragments[2])?)
+                    .map_err(|_| Error::InvalidExpiry)?,
+            ),
+            false => None,
+        };
+        let signature = ed25519_compact::Signature::from_slice(
+            general_purpose::URL_SAFE_NO_PAD
+                .decode(fragments[fragments.len() - 1])?
+                .as_slice(),
+        )?;
+        Ok(Self {
+            user_id,
+            bucket_id,
+            recipients,
+            permission,
+            expires,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sealed_link(
+        recipients: &[(UserId, x25519::PublicKey)],
+        key_pair: &ed25519_compact::KeyPair,
+    ) -> (aes_gcm::Key<Aes256Gcm>, SecretShareLinkSealed) {
+        let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&rand::random::<[u8; 32]>());
+        let link = SecretShareLinkSealed::new(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            bucket_key,
+            recipients,
+            BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            Some(ExpiryTimestamp::try_from(time::OffsetDateTime::now_utc()).unwrap()),
+            &key_pair.sk,
+        )
+        .unwrap();
+        (bucket_key, link)
+    }
+
+    #[test]
+    fn recipients_can_unseal() {
+        let key_pair = ed25519_compact::KeyPair::generate();
+        let alice = x25519::KeyPair::generate();
+        let bob = x25519::KeyPair::generate();
+        let mallory = x25519::KeyPair::generate();
+        let (bucket_key, link) = sealed_link(
+            &[(uuid::Uuid::new_v4(), alice.pk), (uuid::Uuid::new_v4(), bob.pk)],
+            &key_pair,
+        );
+
+        assert_eq!(link.unseal(&alice.sk), Ok(bucket_key));
+        assert_eq!(link.unseal(&bob.sk), Ok(bucket_key));
+        assert_eq!(link.unseal(&mallory.sk), Err(UnsealError::NotARecipient));
+        assert!(link.verify_signature(key_pair.pk).is_ok());
+    }
+
+    #[test]
+    fn signatures_do_not_pass_as_secret_share_link_signatures() {
+        let key_pair = ed25519_compact::KeyPair::generate();
+        let alice = x25519::KeyPair::generate();
+        let (bucket_key, sealed) = sealed_link(&[(uuid::Uuid::new_v4(), alice.pk)], &key_pair);
+        let mut link = crate::secret_share_link::SecretShareLink::new(
+            sealed.user_id,
+            sealed.bucket_id,
+            bucket_key,
+            sealed.permission,
+            sealed.expires,
+            &key_pair.sk,
+        );
+        let mut swapped = sealed.clone();
+        swapped.signature = link.signature;
+        link.signature = sealed.signature;
+        assert!(link.verify_signature(key_pair.pk).is_err());
+        assert!(swapped.verify_signature(key_pair.pk).is_err());
+    }
+
+    #[test]
+    fn link_does_not_contain_raw_key() {
+        let key_pair = ed25519_compact::KeyPair::generate();
+        let alice = x25519::KeyPair::generate();
+        let (bucket_key, link) = sealed_link(&[(uuid::Uuid::new_v4(), alice.pk)], &key_pair);
+        let encoded_key = general_purpose::URL_SAFE_NO_PAD.encode(bucket_key.as_slice());
+        assert!(!link.to_string().contains(&encoded_key));
+    }
+
+    #[test]
+    fn tampering_with_wrapped_key_breaks_signature() {
+        let key_pair = ed25519_compact::KeyPair::generate();
+        let alice = x25519::KeyPair::generate();
+        let bob = x25519::KeyPair::generate();
+        let (_, link) = sealed_link(
+            &[(uuid::Uuid::new_v4(), alice.pk), (uuid::Uuid::new_v4(), bob.pk)],
+            &key_pair,
+        );
+        for index in 0..link.recipients.len() {
+            let mut tampered = link.clone();
+            tampered.recipients[index].wrapped[0] ^= 1;
+            assert!(tampered.verify_signature(key_pair.pk).is_err());
+
+            let mut tampered = link.clone();
+            tampered.recipients[index].ephemeral_pk[0] ^= 1;
+            assert!(tampered.verify_signature(key_pair.pk).is_err());
+        }
+    }
+
+    #[test]
+    fn sealed_link_to_and_from_url() {
+        let key_pair = ed25519_compact::KeyPair::generate();
+        let alice = x25519::KeyPair::generate();
+        let (bucket_key, link) = sealed_link(&[(uuid::Uuid::new_v4(), alice.pk)], &key_pair);
+        let url = url::Url::parse(&link.to_string()).unwrap();
+        let parsed = SecretShareLinkSealed::try_from(url).unwrap();
+
+        assert_eq!(parsed.recipients, link.recipients);
+        assert_eq!(parsed.permission, link.permission);
+        assert_eq!(parsed.unseal(&alice.sk), Ok(bucket_key));
+        assert!(parsed.verify_signature(key_pair.pk).is_ok());
+    }
+
+    #[test]
+    fn sealed_link_case_insensitive_origin() {
+        let key_pair = ed25519_compact::KeyPair::generate();
+        let alice = x25519::KeyPair::generate();
+        let (bucket_key, link) = sealed_link(&[(uuid::Uuid::new_v4(), alice.pk)], &key_pair);
+        let url = link.to_string();
+        let (path, fragment) = url.split_once('#').unwrap();
+        let input = format!("{}/#{}", path.replacen("https://bucketdrive.co", "HTTPS://BucketDrive.CO", 1), fragment);
+
+        let parsed = input.parse::<SecretShareLinkSealed>().unwrap();
+        assert!(parsed.verify_signature(key_pair.pk).is_ok());
+        assert_eq!(parsed.unseal(&alice.sk), Ok(bucket_key));
+    }
+}
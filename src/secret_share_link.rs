@@ -1,304 +1,2724 @@
 #![cfg(feature = "secret_share_link")]
 
+use std::fmt;
+use std::str::FromStr;
+
 use aes_gcm::{self, Aes256Gcm};
 use base64::{Engine, engine::general_purpose};
 use ed25519_compact::Noise;
-use sha3::{Digest, Sha3_224};
-use time::OffsetDateTime;
+use sha3::{Digest, Sha3_256};
 
+use crate::{BucketVisibility, UserId};
+use crate::clock::{Clock, SystemClock};
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::human_label::{count_of, Locale};
+use crate::expiry::{ExpiryTimestamp, ExpiryTimestampError};
+use crate::share_link::{
+    clamp_to_visibility, ShareChannel, ShareLink, ShareLinkParsingError, ShareLinkToken, UnknownShareChannel,
+};
+use crate::share_prefix::{effective_keys_filter, SharePrefix, SharePrefixError};
+use crate::storage_quota::StorageQuota;
+use crate::bucket_object::BucketObjectKey;
+use crate::link_diagnostics::{ParseDiagnostics, SegmentRole, UrlPart};
 use crate::{share_link::BucketSharePermissionFlags, util::DOMAIN_URL};
-use crate::util::SECRET_SHARE_PATH_URL;
+use crate::util::{
+    constant_time_eq, truncate_input, Fingerprint, LinkOrigin, LinkOriginError, OriginMismatch, Redact, SECRET_SHARE_PATH_URL,
+};
 
 
 // Only difference between ShareLink and SecretShareLink is that SecretShareLink has a bucket key Aes256Gcm.
 // And that SecretShareLink use
-#[derive(Debug, Copy, Clone)]
+#[derive(Clone)]
 pub struct SecretShareLink {
     pub user_id: uuid::Uuid,
     pub bucket_id: uuid::Uuid,
     pub bucket_key: aes_gcm::Key<Aes256Gcm>,
     pub permission: BucketSharePermissionFlags,
-    pub expires: Option<OffsetDateTime>,
+    // Permission bits this version does not know, set by newer issuers. Signed along with permission, never granted.
+    pub unknown_permission_bits: u32,
+    pub expires: Option<ExpiryTimestamp>,
+    // The link is not usable before this, for embargoed releases. Always before expires.
+    pub not_before: Option<ExpiryTimestamp>,
+    // Empty shares the whole bucket, at most MAX_SHARE_PREFIXES.
+    pub prefixes: Vec<SharePrefix>,
+    // How the link was distributed, None for links created before channels were recorded.
+    pub channel: Option<ShareChannel>,
+    // Set when the link was issued to a specific user, see SecretShareLinkBatch.
+    pub recipient: Option<UserId>,
+    // When the builder signed the link, None for links created before it was recorded. See enforce_max_age.
+    pub issued_at: Option<ExpiryTimestamp>,
+    // Bytes the link may serve in total, None is unlimited. Enforced by the server with check_bandwidth.
+    pub max_total_bytes: Option<StorageQuota>,
     // Recommended to always have an expiration date. because reuse of an old share-link to create signature signature.
     pub signature: ed25519_compact::Signature, // The signature is stored in the link. This makes sure that the link is not tampered with.
 }
 
+// Never prints key or signature bytes, only their length and a fingerprint of the key.
+impl fmt::Debug for SecretShareLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut permission = String::new();
+        bitflags::parser::to_writer(&self.permission, &mut permission)?;
+        f.debug_struct("SecretShareLink")
+            .field("user_id", &self.user_id)
+            .field("bucket_id", &self.bucket_id)
+            .field(
+                "bucket_key",
+                &format_args!("<{} bytes, fp:{}>", self.bucket_key.len(), Fingerprint::of(self.bucket_key.as_slice())),
+            )
+            .field("permission", &format_args!("{}", permission))
+            .field("unknown_permission_bits", &format_args!("{:#x}", self.unknown_permission_bits))
+            .field("expires", &self.expires.map(|expires| expires.to_string()))
+            .field("not_before", &self.not_before.map(|not_before| not_before.to_string()))
+            .field("prefixes", &self.prefixes.iter().map(SharePrefix::as_str).collect::<Vec<_>>())
+            .field("channel", &self.channel)
+            .field("recipient", &self.recipient)
+            .field("issued_at", &self.issued_at.map(|issued_at| issued_at.to_string()))
+            .field("max_total_bytes", &self.max_total_bytes)
+            .field("signature", &format_args!("<{} bytes>", self.signature.as_slice().len()))
+            .finish()
+    }
+}
+
+/*
+* Two links are equal when they grant the same thing, i.e. all signed fields are equal. The signature is ignored,
+* two valid signatures over the same payload are the same grant. Use identical_including_signature to compare it as well.
+* The bucket key is compared in constant time.
+*/
+impl PartialEq for SecretShareLink {
+    fn eq(&self, other: &Self) -> bool {
+        self.user_id == other.user_id
+            && self.bucket_id == other.bucket_id
+            && constant_time_eq(self.bucket_key.as_slice(), other.bucket_key.as_slice())
+            && self.permission == other.permission
+            && self.unknown_permission_bits == other.unknown_permission_bits
+            && self.expires == other.expires
+            && self.not_before == other.not_before
+            && self.prefixes == other.prefixes
+            && self.channel == other.channel
+            && self.recipient == other.recipient
+            && self.issued_at == other.issued_at
+            && self.max_total_bytes == other.max_total_bytes
+    }
+}
+
+impl Eq for SecretShareLink {}
+
+// Same fields as PartialEq, the signature is left out.
+impl std::hash::Hash for SecretShareLink {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.user_id.hash(state);
+        self.bucket_id.hash(state);
+        self.bucket_key.as_slice().hash(state);
+        self.permission.hash(state);
+        self.unknown_permission_bits.hash(state);
+        self.expires.hash(state);
+        self.not_before.hash(state);
+        self.prefixes.hash(state);
+        self.channel.hash(state);
+        self.recipient.hash(state);
+        self.issued_at.hash(state);
+        self.max_total_bytes.hash(state);
+    }
+}
+
 // Hash the secret share link to get a unique identifier that is then signed with ed22219 key to create the signature.
 // Does not include the signature in the hash.
 // https://github.com/RustCrypto/hashes
-fn hash_secret_share_link<D: Digest>(user_id: uuid::Uuid, bucket_id: uuid::Uuid, bucket_key: aes_gcm::Key<Aes256Gcm>, permission: BucketSharePermissionFlags, expires: Option<OffsetDateTime>, output: &mut [u8]) {
+fn hash_secret_share_link<D: Digest>(link: &SecretShareLink, output: &mut [u8]) {
+    let mut hasher = hash_secret_share_link_prefix::<D>(link);
+    hash_secret_share_link_recipient(&mut hasher, link.recipient);
+    output.copy_from_slice(&hasher.finalize());
+}
+
+// Everything except the recipient. The recipient is hashed last so a batch of links can share this prefix.
+pub(crate) fn hash_secret_share_link_prefix<D: Digest>(link: &SecretShareLink) -> D {
     let mut hasher = D::new();
-    hasher.update(user_id.as_bytes());
-    hasher.update(bucket_id.as_bytes());
-    hasher.update(bucket_key.as_slice());
-    hasher.update(permission.bits().to_be_bytes());
-    if let Some(expires) = expires {
-        hasher.update(bincode::serialize(&expires).unwrap());
+    write_canonical_prefix(link, &mut |bytes| hasher.update(bytes));
+    hasher
+}
+
+// Optional fields added after the first link format are prefixed with their tag to keep the hash input unambiguous.
+pub(crate) fn hash_secret_share_link_recipient<D: Digest>(hasher: &mut D, recipient: Option<UserId>) {
+    write_canonical_recipient(recipient, &mut |bytes| hasher.update(bytes));
+}
+
+fn write_canonical_prefix(link: &SecretShareLink, write: &mut impl FnMut(&[u8])) {
+    write(link.user_id.as_bytes());
+    write(link.bucket_id.as_bytes());
+    write(link.bucket_key.as_slice());
+    write(&link.permission_bits().to_be_bytes());
+    if let Some(expires) = link.expires {
+        write(&expires.unix_timestamp().to_be_bytes());
+    }
+    if !link.prefixes.is_empty() {
+        write(PREFIXES_FIELD.as_bytes());
+        write(&(link.prefixes.len() as u32).to_be_bytes());
+        for prefix in &link.prefixes {
+            write(&(prefix.as_str().len() as u32).to_be_bytes());
+            write(prefix.as_str().as_bytes());
+        }
+    }
+    if let Some(not_before) = link.not_before {
+        write(NOT_BEFORE_FIELD.as_bytes());
+        write(&not_before.unix_timestamp().to_be_bytes());
+    }
+    if let Some(channel) = link.channel {
+        write(CHANNEL_FIELD.as_bytes());
+        write(&[channel.to_byte()]);
+    }
+    if let Some(issued_at) = link.issued_at {
+        write(ISSUED_AT_FIELD.as_bytes());
+        write(&issued_at.unix_timestamp().to_be_bytes());
+    }
+    if let Some(max_total_bytes) = link.max_total_bytes {
+        write(MAX_TOTAL_BYTES_FIELD.as_bytes());
+        write(&max_total_bytes.bytes().to_be_bytes());
     }
-    output.copy_from_slice(&hasher.finalize());
 }
 
-impl ToString for SecretShareLink {
-    fn to_string(&self) -> String {
-        match self.expires {
-            Some(expires) => {
-                format!(
-                    "{}{}/{}/{}#{}#{}#{}#{}",
-                    DOMAIN_URL,
-                    SECRET_SHARE_PATH_URL,
-                    self.user_id,
-                    self.bucket_id,
-                    general_purpose::URL_SAFE_NO_PAD.encode(self.bucket_key.as_slice()),
-                    general_purpose::URL_SAFE_NO_PAD.encode(self.permission.bits().to_be_bytes()),
-                    general_purpose::URL_SAFE_NO_PAD
-                        .encode(bincode::serialize(&expires).unwrap().as_slice()),
-                    general_purpose::URL_SAFE_NO_PAD.encode(self.signature.as_slice()),
-                )
-            }
-            None => {
-                format!(
-                    "{}{}/{}/{}#{}#{}#{}",
-                    DOMAIN_URL,
-                    SECRET_SHARE_PATH_URL,
-                    self.user_id,
-                    self.bucket_id,
-                    general_purpose::URL_SAFE_NO_PAD.encode(self.bucket_key.as_slice()),
-                    general_purpose::URL_SAFE_NO_PAD.encode(self.permission.bits().to_be_bytes()),
-                    general_purpose::URL_SAFE_NO_PAD.encode(self.signature.as_slice()),
-                )
-            }
+fn write_canonical_recipient(recipient: Option<UserId>, write: &mut impl FnMut(&[u8])) {
+    if let Some(recipient) = recipient {
+        write(RECIPIENT_FIELD.as_bytes());
+        write(recipient.as_bytes());
+    }
+}
+
+/*
+* Optional fields are encoded as tag=value fragments between the positional fields and the signature.
+* The base64 alphabet used has no '=' so they can not be confused with the positional fields.
+*/
+const RECIPIENT_FIELD: &str = "r";
+const PREFIXES_FIELD: &str = "p";
+const NOT_BEFORE_FIELD: &str = "nb";
+const CHANNEL_FIELD: &str = "c";
+const ISSUED_AT_FIELD: &str = "ia";
+const MAX_TOTAL_BYTES_FIELD: &str = "mb";
+
+/*
+* Since format version 2 the fragment starts with "v2.<len>", len being the length of the rest of the fragment, and
+* ends with "#ck=<checksum>", the first 4 bytes of SHA3-256 over "user_id/bucket_id#v2.<len>#<fields>". Both are
+* checked before any field is decoded, so a truncated or mistyped link fails with ChecksumMismatch up front.
+* The checksum only catches accidents, anyone can compute it. Whether a link is authentic is decided by the signature.
+* Fragments without the marker are from before version 2 and have no checksum.
+*/
+const CHECKED_FORMAT_MARKER: &str = "v2.";
+const CHECKSUM_FIELD: &str = "ck";
+const CHECKSUM_LEN: usize = 4;
+// "#ck=" and the base64 checksum.
+const CHECKSUM_SEGMENT_LEN: usize = 4 + (CHECKSUM_LEN * 4).div_ceil(3);
+
+// Query form parameters for the positional fragment fields, optional fields keep their tag.
+const QUERY_KEY_PARAM: &str = "sk";
+const QUERY_PERMISSION_PARAM: &str = "p";
+const QUERY_EXPIRES_PARAM: &str = "e";
+const QUERY_SIGNATURE_PARAM: &str = "sig";
+// "p" is taken by the permission in the query form.
+const QUERY_PREFIXES_PARAM: &str = "sp";
+
+// Prefixes are joined with NUL before base64 encoding, object keys can not contain control characters.
+fn encode_prefixes(prefixes: &[SharePrefix]) -> String {
+    let joined = prefixes.iter().map(SharePrefix::as_str).collect::<Vec<_>>().join("\0");
+    general_purpose::URL_SAFE_NO_PAD.encode(joined)
+}
+
+fn decode_prefixes(value: &str) -> Result<Vec<SharePrefix>, SecretShareLinkParsingError> {
+    let joined = String::from_utf8(general_purpose::URL_SAFE_NO_PAD.decode(value)?)?;
+    let prefixes = joined
+        .split('\0')
+        .map(|prefix| prefix.parse().map_err(|_| SecretShareLinkParsingError::InvalidPrefix(truncate_input(prefix))))
+        .collect::<Result<Vec<SharePrefix>, _>>()?;
+    SharePrefix::validate_count(&prefixes)?;
+    Ok(prefixes)
+}
+
+/*
+* Expiry is encoded as big endian unix seconds.
+* Links created before ExpiryTimestamp used bincode encoded OffsetDateTime (16 bytes), these are still accepted.
+*/
+pub(crate) fn decode_expiry(bytes: &[u8]) -> Result<ExpiryTimestamp, ExpiryTimestampError> {
+    match <[u8; 8]>::try_from(bytes) {
+        Ok(timestamp) => ExpiryTimestamp::from_unix_timestamp(i64::from_be_bytes(timestamp)),
+        Err(_) => {
+            let legacy: time::OffsetDateTime = bincode::deserialize(bytes)
+                .map_err(|_| ExpiryTimestampError::InvalidEncoding(bytes.len()))?;
+            ExpiryTimestamp::try_from(legacy)
         }
     }
 }
 
-#[derive(Debug, thiserror::Error)]
+impl fmt::Display for SecretShareLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "https://{}{}", DOMAIN_URL, SECRET_SHARE_PATH_URL)?;
+        self.write_path_and_fragment(f)
+    }
+}
+
+impl SecretShareLink {
+    // Everything after the share path, "/user_id/bucket_id#v2.<len>#<fields>#ck=<checksum>", see CHECKED_FORMAT_MARKER.
+    fn write_path_and_fragment(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        let mut fields = String::new();
+        // Writing to a String can not fail.
+        let _ = self.write_fields(&mut fields);
+        let marker = format!("{}{}", CHECKED_FORMAT_MARKER, fields.len() + CHECKSUM_SEGMENT_LEN);
+        write!(
+            f,
+            "/{}/{}#{}#{}#{}={}",
+            self.user_id,
+            self.bucket_id,
+            marker,
+            fields,
+            CHECKSUM_FIELD,
+            link_checksum(self.user_id, self.bucket_id, &marker, &fields)
+        )
+    }
+
+    // "key#permission[#expires][#tag=value]*#signature", the whole fragment of links from before format version 2.
+    fn write_fields(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        write!(
+            f,
+            "{}#{}",
+            general_purpose::URL_SAFE_NO_PAD.encode(self.bucket_key.as_slice()),
+            general_purpose::URL_SAFE_NO_PAD.encode(self.permission_bits().to_be_bytes()),
+        )?;
+        if let Some(expires) = self.expires {
+            write!(f, "#{}", encode_timestamp(expires))?;
+        }
+        if let Some(not_before) = self.not_before {
+            write!(f, "#{}={}", NOT_BEFORE_FIELD, encode_timestamp(not_before))?;
+        }
+        if !self.prefixes.is_empty() {
+            write!(f, "#{}={}", PREFIXES_FIELD, encode_prefixes(&self.prefixes))?;
+        }
+        if let Some(channel) = self.channel {
+            write!(f, "#{}={}", CHANNEL_FIELD, encode_channel(channel))?;
+        }
+        if let Some(issued_at) = self.issued_at {
+            write!(f, "#{}={}", ISSUED_AT_FIELD, encode_timestamp(issued_at))?;
+        }
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            write!(f, "#{}={}", MAX_TOTAL_BYTES_FIELD, encode_byte_count(max_total_bytes))?;
+        }
+        if let Some(recipient) = self.recipient {
+            write!(
+                f,
+                "#{}={}",
+                RECIPIENT_FIELD,
+                general_purpose::URL_SAFE_NO_PAD.encode(recipient.as_bytes())
+            )?;
+        }
+        write!(f, "#{}", general_purpose::URL_SAFE_NO_PAD.encode(self.signature.as_slice()))
+    }
+}
+
+#[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
 pub enum SecretShareLinkParsingError {
+    #[error("Invalid scheme")]
+    InvalidScheme,
     #[error("Invalid host")]
     InvalidHostDomain,
     #[error("Invalid version format")]
     InvalidVersionFormat,
+    #[error("Link is truncated or mistyped, it should be {expected_len_hint} characters long")]
+    ChecksumMismatch { expected_len_hint: usize },
+    #[error("Invalid path")]
+    InvalidPath,
+    #[error("Invalid fragment")]
+    InvalidFragment,
+    #[error("Invalid bucket key")]
+    InvalidBucketKey,
+    #[error("Invalid permission")]
+    InvalidPermission,
+    #[error("Invalid expiry")]
+    InvalidExpiry,
+    #[error("Invalid not before")]
+    InvalidNotBefore,
+    #[error("Invalid issue time")]
+    InvalidIssuedAt,
+    #[error("Invalid byte cap")]
+    InvalidMaxTotalBytes,
+    #[error("Unknown or duplicate field \"{0}\"")]
+    InvalidField(String),
+    #[error("Duplicate query parameter \"{0}\"")]
+    DuplicateParameter(String),
+    #[error("Missing query parameter \"{0}\"")]
+    MissingParameter(&'static str),
+    #[error("Invalid share prefix \"{0}\"")]
+    InvalidPrefix(String),
+    #[error(transparent)]
+    TooManyPrefixes(#[from] SharePrefixError),
+    #[error("Invalid channel")]
+    InvalidChannel,
+    #[error(transparent)]
+    UnknownChannel(#[from] UnknownShareChannel),
 
     #[error(transparent)]
     Base64Decoding(#[from] base64::DecodeError),
     #[error(transparent)]
     Utf8Error(#[from] std::string::FromUtf8Error),
+    #[error(transparent)]
+    Uuid(#[from] uuid::Error),
+    #[error(transparent)]
+    Signature(#[from] ed25519_compact::Error),
+    #[error(transparent)]
+    UrlParse(#[from] url::ParseError),
+}
+
+// Links are never echoed since they carry the bucket key, ParseDiagnostics points at the failing part instead.
+impl SpannedParseError for SecretShareLinkParsingError {
+    fn failure(&self) -> ParseFailure {
+        ParseFailure::unspanned(self)
+    }
 }
 
 impl TryFrom<url::Url> for SecretShareLink {
     type Error = SecretShareLinkParsingError;
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "SecretShareLink::try_from", level = "debug", skip_all))]
     fn try_from(value: url::Url) -> Result<Self, Self::Error> {
-        let domain = value.domain().ok_or(Self::Error::InvalidHostDomain)?;
-        if domain != DOMAIN_URL {
-            return Err(Self::Error::InvalidHostDomain);
-        }
-        let path = value.path();
-        let parts = path.split('/').take(1).collect::<Vec<&str>>(); // First element should be empty.
-        let user_id = parts[0].parse::<uuid::Uuid>().unwrap();
-        let bucket_id = parts[1].parse::<uuid::Uuid>().unwrap();
-        let fragments = parts[3].split('#').take(1).collect::<Vec<&str>>(); // Guessing first part is just the path.
-        let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(
-            general_purpose::URL_SAFE_NO_PAD
-                .decode(fragments[1].as_bytes())
-                .unwrap()
-                .as_slice(),
-        );
-        let permission = BucketSharePermissionFlags::from_bits(u32::from_be_bytes(
-            base64::engine::general_purpose::URL_SAFE_NO_PAD
-                .decode(fragments[2].as_bytes())
-                .unwrap()
-                .try_into()
-                .unwrap(),
-        ))
-            .unwrap();
-        let has_expires_field = fragments.len() == 4;
-        let expires: Option<OffsetDateTime> = match has_expires_field {
-            true => Some(
-                bincode::deserialize(
-                    base64::engine::general_purpose::URL_SAFE_NO_PAD
-                        .decode(fragments[3])
-                        .unwrap()
-                        .as_slice(),
-                )
-                    .unwrap(),
-            ),
-            false => None,
-        };
-        let mut signature_index = 5;
-        if !has_expires_field {
-            signature_index -= 1;
-        }
-        let signature = ed25519_compact::Signature::from_slice(
-            base64::engine::general_purpose::URL_SAFE_NO_PAD
-                .decode(fragments[signature_index])
-                .unwrap()
-                .as_slice(),
-        )
-            .unwrap();
-        Ok(Self {
-            user_id,
-            bucket_id,
-            bucket_key,
-            permission,
-            expires,
-            signature,
-        })
+        Self::from_url_with_origin(&value, &LinkOrigin::default())
     }
 }
 
-#[derive(Debug, thiserror::Error, PartialEq)]
-pub enum SecretShareLinkVerifySignatureError {
-    #[error("Invalid signature")]
-    InvalidSignature(#[from] ed25519_compact::Error),
-}
+impl FromStr for SecretShareLink {
+    type Err = SecretShareLinkParsingError;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(url::Url::parse(s)?)
+    }
+}
 
 impl SecretShareLink {
-    // Verify the signature against the signature file with special identifier.
-    pub fn verify_signature(
-        &self,
-        public_signing_key: ed25519_compact::PublicKey,
-    ) -> Result<(), SecretShareLinkVerifySignatureError> {
-        let mut hash_output = [0; 64];
-        hash_secret_share_link::<Sha3_224>(self.user_id, self.bucket_id, self.bucket_key, self.permission, self.expires, &mut hash_output);
-        Ok(public_signing_key.verify(hash_output, &self.signature)?)
+    // Scheme and host are compared case-insensitively, path and fragment are not.
+    pub fn from_url_with_origin(value: &url::Url, origin: &LinkOrigin) -> Result<Self, SecretShareLinkParsingError> {
+        Self::parse_with_diagnostics_and_origin(value, origin).0
     }
 
+    pub fn parse_with_diagnostics(value: &url::Url) -> (Result<Self, SecretShareLinkParsingError>, ParseDiagnostics) {
+        Self::parse_with_diagnostics_and_origin(value, &LinkOrigin::default())
+    }
 
-    pub fn new(user_id: uuid::Uuid,
-               bucket_id: uuid::Uuid,
-               bucket_key: aes_gcm::Key<Aes256Gcm>,
-               permission: BucketSharePermissionFlags,
-               expires: Option<OffsetDateTime>,
-               secret_key: &ed25519_compact::SecretKey) -> Self {
-        let mut hash_output = [0; 64];
-        hash_secret_share_link::<Sha3_224>(user_id, bucket_id, bucket_key, permission, expires, &mut hash_output);
+    pub fn parse_with_diagnostics_and_origin(
+        value: &url::Url,
+        origin: &LinkOrigin,
+    ) -> (Result<Self, SecretShareLinkParsingError>, ParseDiagnostics) {
+        let mut diagnostics = ParseDiagnostics::default();
+        let result = Self::parse_fragment_form(value, origin, &mut diagnostics);
+        diagnostics.finish(&result);
+        // Only the variant and position are logged, the input holds the bucket key.
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            let failed = diagnostics.failed_segment();
+            tracing::debug!(
+                error = <&'static str>::from(error),
+                segment = failed.map(|segment| segment.index),
+                role = failed.map(|segment| tracing::field::display(segment.role)),
+                "rejected secret share link"
+            );
+        }
+        (result, diagnostics)
+    }
 
-        let noise = Noise::from_slice(bucket_id.as_bytes().as_slice()).unwrap(); // Do we even need it?
-        let signature = secret_key.sign(hash_output, Some(noise));
-        Self {
-            user_id,
-            bucket_id,
-            bucket_key,
-            permission,
-            expires,
-            signature,
+    fn parse_fragment_form(
+        value: &url::Url,
+        origin: &LinkOrigin,
+        diagnostics: &mut ParseDiagnostics,
+    ) -> Result<Self, SecretShareLinkParsingError> {
+        type Error = SecretShareLinkParsingError;
+        let (user_id, bucket_id) = parse_link_path(value, origin, diagnostics)?;
+
+        let fragment = value.fragment().ok_or(Error::InvalidFragment)?;
+        // Diagnostics count the version marker, indexes stay positions in the fragment.
+        let (fields, offset) = match fragment.starts_with(CHECKED_FORMAT_MARKER) {
+            true => (checked_fields(value, user_id, bucket_id, fragment, diagnostics)?, 1),
+            false => (fragment, 0),
+        };
+
+        // Fields are key#permission[#expires][#tag=value]*#signature.
+        let fragments = fields.split('#').collect::<Vec<&str>>();
+        if fragments.len() < 3 {
+            return Err(Error::InvalidFragment);
         }
+        let bucket_key = diagnostics.record(
+            UrlPart::Fragment,
+            offset,
+            SegmentRole::Key,
+            fragments[0],
+            decode_bucket_key(fragments[0]),
+        )?;
+        let mut link = Self::unsigned(user_id, bucket_id, bucket_key);
+        (link.permission, link.unknown_permission_bits) = diagnostics.record(
+            UrlPart::Fragment,
+            offset + 1,
+            SegmentRole::Permissions,
+            fragments[1],
+            decode_permission(fragments[1]),
+        )?;
+        let signature_index = fragments.len() - 1;
+        for (index, field) in fragments[2..signature_index].iter().enumerate() {
+            let (role, result) = match field.split_once('=') {
+                None if index == 0 => (
+                    SegmentRole::Expiry,
+                    decode_timestamp(field, Error::InvalidExpiry).map(|expires| link.expires = Some(expires)),
+                ),
+                None => (SegmentRole::Unknown, Err(Error::InvalidFragment)),
+                Some((tag, value)) => (optional_field_role(tag), link.decode_optional_field(tag, value)),
+            };
+            diagnostics.record(UrlPart::Fragment, offset + index + 2, role, field, result)?;
+        }
+        link.signature = diagnostics.record(
+            UrlPart::Fragment,
+            offset + signature_index,
+            SegmentRole::Signature,
+            fragments[signature_index],
+            decode_signature(fragments[signature_index]),
+        )?;
+        Ok(link)
     }
-    // TODO: There is no way for the server to invalidate a secret share link.
+
     /*
-    Generate a token that is used by the server to identify the link.
+    * Same link as Display under another origin, e.g. LinkOrigin::deep_link() for bucketdrive://share/user_id/bucket_id#...
+    * The key stays in the fragment under every scheme.
     */
-    pub fn get_token(&self) -> [u8; 32] {
-        let mut hash_output = [0; 64];
-        hash_secret_share_link::<Sha3_224>(self.user_id, self.bucket_id, self.bucket_key, self.permission, self.expires, &mut hash_output);
-        let mut output: [u8; 32] = [0; 32];
-        output.clone_from_slice(&hash_output[0..32]);
-        output
+    pub fn to_url_with(&self, origin: &LinkOrigin) -> Result<url::Url, LinkOriginError> {
+        let mut url = origin.share_link_base()?;
+        // Writing to a String can not fail.
+        let _ = self.write_path_and_fragment(&mut url);
+        Ok(url::Url::parse(&url)?)
+    }
+
+    /*
+    * to_url_with that refuses URLs longer than origin.max_url_len. The error suggests which optional fields to drop,
+    * the fewest that bring the link under the limit, found by serializing the link without each of them.
+    */
+    pub fn to_url_checked(&self, origin: &LinkOrigin) -> Result<url::Url, SecretShareLinkUrlError> {
+        let url = self.to_url_with(origin)?;
+        let (len, max) = (url.as_str().len(), origin.max_url_len);
+        if len <= max {
+            return Ok(url);
+        }
+        let mut savings = self
+            .without_each_optional_field()
+            .into_iter()
+            .map(|(field, link)| Ok((field, len - link.to_url_with(origin)?.as_str().len())))
+            .collect::<Result<Vec<_>, LinkOriginError>>()?;
+        savings.sort_by_key(|(_, saved)| std::cmp::Reverse(*saved));
+        let mut remaining = len;
+        let mut suggestion = Vec::new();
+        for (field, saved) in savings {
+            if remaining <= max {
+                break;
+            }
+            remaining -= saved;
+            suggestion.push(field);
+        }
+        if remaining > max {
+            suggestion.clear();
+        }
+        Err(UrlTooLong { len, max, suggestion }.into())
+    }
+
+    /*
+    * Copies of the link with one optional field cleared each, named as the field. expires and issued_at are never
+    * suggested, dropping them makes a link valid forever.
+    */
+    fn without_each_optional_field(&self) -> Vec<(&'static str, SecretShareLink)> {
+        let mut stripped = Vec::new();
+        if !self.prefixes.is_empty() {
+            stripped.push(("prefixes", SecretShareLink { prefixes: Vec::new(), ..self.clone() }));
+        }
+        if self.recipient.is_some() {
+            stripped.push(("recipient", SecretShareLink { recipient: None, ..self.clone() }));
+        }
+        if self.max_total_bytes.is_some() {
+            stripped.push(("max_total_bytes", SecretShareLink { max_total_bytes: None, ..self.clone() }));
+        }
+        if self.not_before.is_some() {
+            stripped.push(("not_before", SecretShareLink { not_before: None, ..self.clone() }));
+        }
+        if self.channel.is_some() {
+            stripped.push(("channel", SecretShareLink { channel: None, ..self.clone() }));
+        }
+        stripped
+    }
+
+    // Length of the Display form computed from the field sizes, without formatting the link.
+    pub fn url_len_estimate(&self) -> usize {
+        let base = "https://".len() + DOMAIN_URL.len() + SECRET_SHARE_PATH_URL.len();
+        let rest = self.fields_len() + CHECKSUM_SEGMENT_LEN;
+        // "/user_id/bucket_id#v2.<rest>#", hyphenated uuids are 36 characters.
+        base + 2 + 36 + 36 + 1 + CHECKED_FORMAT_MARKER.len() + rest.to_string().len() + 1 + rest
+    }
+
+    // Length of what write_fields writes.
+    fn fields_len(&self) -> usize {
+        let timestamp = base64_len(8);
+        // "#tag=value"
+        let tagged = |tag: &str, value_len: usize| 2 + tag.len() + value_len;
+        // "key#permission"
+        let mut len = base64_len(self.bucket_key.len()) + 1 + base64_len(4);
+        if self.expires.is_some() {
+            len += 1 + timestamp;
+        }
+        if self.not_before.is_some() {
+            len += tagged(NOT_BEFORE_FIELD, timestamp);
+        }
+        if !self.prefixes.is_empty() {
+            // Joined with NUL, see encode_prefixes.
+            let joined = self.prefixes.iter().map(|prefix| prefix.as_str().len() + 1).sum::<usize>() - 1;
+            len += tagged(PREFIXES_FIELD, base64_len(joined));
+        }
+        if self.channel.is_some() {
+            len += tagged(CHANNEL_FIELD, base64_len(1));
+        }
+        if self.issued_at.is_some() {
+            len += tagged(ISSUED_AT_FIELD, timestamp);
+        }
+        if self.max_total_bytes.is_some() {
+            len += tagged(MAX_TOTAL_BYTES_FIELD, timestamp);
+        }
+        if self.recipient.is_some() {
+            len += tagged(RECIPIENT_FIELD, base64_len(16));
+        }
+        len + 1 + base64_len(self.signature.len())
+    }
+
+    /*
+    * WARNING: the query form puts the bucket key where servers, proxies and logs can see it.
+    * Only for redirect flows that drop the fragment, e.g. OAuth intermediaries. Never use it as the default link form.
+    * Format is SECRET_SHARE_PATH_URL/user_id/bucket_id?sk=<key>&p=<permission>[&e=<expires>][&<tag>=<value>]*&sig=<signature>,
+    * with the same base64url encodings as the fragment form.
+    */
+    pub fn to_query_url(&self, origin: &LinkOrigin) -> Result<url::Url, LinkOriginError> {
+        let mut url = url::Url::parse(&format!("{}/{}/{}", origin.share_link_base()?, self.user_id, self.bucket_id))?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair(QUERY_KEY_PARAM, &general_purpose::URL_SAFE_NO_PAD.encode(self.bucket_key.as_slice()));
+            query.append_pair(
+                QUERY_PERMISSION_PARAM,
+                &general_purpose::URL_SAFE_NO_PAD.encode(self.permission_bits().to_be_bytes()),
+            );
+            if let Some(expires) = self.expires {
+                query.append_pair(QUERY_EXPIRES_PARAM, &encode_timestamp(expires));
+            }
+            if let Some(not_before) = self.not_before {
+                query.append_pair(NOT_BEFORE_FIELD, &encode_timestamp(not_before));
+            }
+            if !self.prefixes.is_empty() {
+                query.append_pair(QUERY_PREFIXES_PARAM, &encode_prefixes(&self.prefixes));
+            }
+            if let Some(channel) = self.channel {
+                query.append_pair(CHANNEL_FIELD, &encode_channel(channel));
+            }
+            if let Some(issued_at) = self.issued_at {
+                query.append_pair(ISSUED_AT_FIELD, &encode_timestamp(issued_at));
+            }
+            if let Some(max_total_bytes) = self.max_total_bytes {
+                query.append_pair(MAX_TOTAL_BYTES_FIELD, &encode_byte_count(max_total_bytes));
+            }
+            if let Some(recipient) = self.recipient {
+                query.append_pair(RECIPIENT_FIELD, &general_purpose::URL_SAFE_NO_PAD.encode(recipient.as_bytes()));
+            }
+            query.append_pair(QUERY_SIGNATURE_PARAM, &general_purpose::URL_SAFE_NO_PAD.encode(self.signature.as_slice()));
+        }
+        Ok(url)
+    }
+
+    // Parameters may come in any order and percent-encoded, but every parameter may only appear once.
+    pub fn from_query_url(value: &url::Url) -> Result<Self, SecretShareLinkParsingError> {
+        Self::from_query_url_with_origin(value, &LinkOrigin::default())
+    }
+
+    pub fn from_query_url_with_origin(value: &url::Url, origin: &LinkOrigin) -> Result<Self, SecretShareLinkParsingError> {
+        type Error = SecretShareLinkParsingError;
+        let (user_id, bucket_id) = parse_link_path(value, origin, &mut ParseDiagnostics::default())?;
+        let mut params = Vec::<(String, String)>::new();
+        for (name, param) in value.query_pairs() {
+            if params.iter().any(|(seen, _)| *seen == name) {
+                return Err(Error::DuplicateParameter(truncate_input(&name)));
+            }
+            params.push((name.into_owned(), param.into_owned()));
+        }
+        let mut take = |name: &'static str| {
+            let index = params.iter().position(|(seen, _)| seen == name)?;
+            Some(params.swap_remove(index).1)
+        };
+        let bucket_key = take(QUERY_KEY_PARAM).ok_or(Error::MissingParameter(QUERY_KEY_PARAM))?;
+        let permission = take(QUERY_PERMISSION_PARAM).ok_or(Error::MissingParameter(QUERY_PERMISSION_PARAM))?;
+        let signature = take(QUERY_SIGNATURE_PARAM).ok_or(Error::MissingParameter(QUERY_SIGNATURE_PARAM))?;
+        let expires = take(QUERY_EXPIRES_PARAM);
+
+        let mut link = Self::unsigned(user_id, bucket_id, decode_bucket_key(&bucket_key)?);
+        (link.permission, link.unknown_permission_bits) = decode_permission(&permission)?;
+        link.expires = expires
+            .map(|expires| decode_timestamp(&expires, Error::InvalidExpiry))
+            .transpose()?;
+        for (name, param) in params {
+            let tag = match name.as_str() {
+                QUERY_PREFIXES_PARAM => PREFIXES_FIELD,
+                tag => tag,
+            };
+            link.decode_optional_field(tag, &param)?;
+        }
+        link.signature = decode_signature(&signature)?;
+        Ok(link)
+    }
+
+    // Optional tag=value fields, each may only appear once.
+    fn decode_optional_field(&mut self, tag: &str, value: &str) -> Result<(), SecretShareLinkParsingError> {
+        match tag {
+            NOT_BEFORE_FIELD if self.not_before.is_none() => {
+                self.not_before = Some(decode_timestamp(value, SecretShareLinkParsingError::InvalidNotBefore)?);
+            }
+            PREFIXES_FIELD if self.prefixes.is_empty() => {
+                self.prefixes = decode_prefixes(value)?;
+            }
+            CHANNEL_FIELD if self.channel.is_none() => {
+                self.channel = Some(decode_channel(value)?);
+            }
+            ISSUED_AT_FIELD if self.issued_at.is_none() => {
+                self.issued_at = Some(decode_timestamp(value, SecretShareLinkParsingError::InvalidIssuedAt)?);
+            }
+            MAX_TOTAL_BYTES_FIELD if self.max_total_bytes.is_none() => {
+                self.max_total_bytes = Some(decode_byte_count(value)?);
+            }
+            RECIPIENT_FIELD if self.recipient.is_none() => {
+                self.recipient = Some(uuid::Uuid::from_slice(&general_purpose::URL_SAFE_NO_PAD.decode(value)?)?);
+            }
+            tag => return Err(SecretShareLinkParsingError::InvalidField(truncate_input(tag))),
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, thiserror::Error)]
-pub enum SecretShareLinkFormatError {
-    #[error(transparent)]
-    SecretShareLinkFormatError(#[from] SecretShareLinkParsingError),
+// Which of the two URL forms a link is in. Only the fragment form keeps the bucket key away from servers.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SecretShareLinkForm {
+    Fragment,
+    Query,
 }
 
-impl TryInto<url::Url> for SecretShareLink {
-    type Error = SecretShareLinkFormatError;
+impl SecretShareLinkForm {
+    pub fn of(url: &url::Url) -> Self {
+        match url.query_pairs().any(|(name, _)| name == QUERY_KEY_PARAM) {
+            true => Self::Query,
+            false => Self::Fragment,
+        }
+    }
 
-    fn try_into(self) -> Result<url::Url, Self::Error> {
-        let res: String = self.to_string();
-        Ok(url::Url::parse(&res).unwrap())
+    pub fn is_secret_preserving(&self) -> bool {
+        matches!(self, Self::Fragment)
     }
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display)]
+pub enum LinkKind {
+    Plain,
+    Secret,
+}
 
-#[cfg(test)]
-mod tests {
-    use rand::random;
-    use rand::rngs::OsRng;
-    use super::*;
+#[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
+pub enum AnyLinkParsingError {
+    #[error(transparent)]
+    Plain(#[from] ShareLinkParsingError),
+    #[error(transparent)]
+    Secret(#[from] SecretShareLinkParsingError),
+}
 
-    #[test]
-    fn create_secret_share_link() {
-        //Generate pseudo random bytes for the base64 conversion
-        let bucket_key_bytes = rand::random::<[u8; 32]>();
-        let bucket_key = aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key_bytes);
+/*
+* Kind and token of a plain or secret link, for the redirect service of downgraded links.
+* A URL with a fragment or an sk query parameter is a secret link, anything else has to be a plain link.
+* The token of a secret link is a hash over all of its fields, so the bucket key is read to derive it, but it never
+* leaves this function and errors never hold the input. Browsers do not send fragments, the redirect service needs
+* the whole URL from the client.
+*/
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn parse_any(url: &url::Url) -> Result<(LinkKind, ShareLinkToken), AnyLinkParsingError> {
+    let link = match (SecretShareLinkForm::of(url), url.fragment()) {
+        (SecretShareLinkForm::Query, _) => SecretShareLink::from_query_url(url)?,
+        (SecretShareLinkForm::Fragment, Some(_)) => SecretShareLink::try_from(url.clone())?,
+        (SecretShareLinkForm::Fragment, None) => {
+            return Ok((LinkKind::Plain, ShareLink::try_from(url.clone())?.share_link_token()));
+        }
+    };
+    Ok((LinkKind::Secret, link.share_link_token()))
+}
 
-        let permission = BucketSharePermissionFlags::VIEW;
+// Scheme and host are compared case-insensitively, path is SECRET_SHARE_PATH_URL/user_id/bucket_id or the deep link route.
+fn parse_link_path(
+    value: &url::Url,
+    origin: &LinkOrigin,
+    diagnostics: &mut ParseDiagnostics,
+) -> Result<(uuid::Uuid, uuid::Uuid), SecretShareLinkParsingError> {
+    type Error = SecretShareLinkParsingError;
+    let path = origin.share_link_path(value).map_err(|mismatch| match mismatch {
+        OriginMismatch::Scheme => Error::InvalidScheme,
+        OriginMismatch::Host => Error::InvalidHostDomain,
+        OriginMismatch::Path => Error::InvalidPath,
+    })?;
+    let parts = path.split('/').collect::<Vec<&str>>(); // First element should be empty.
+    if parts.len() != 3 || !parts[0].is_empty() {
+        return Err(Error::InvalidPath);
+    }
+    let mut parse_id = |index: usize, role: SegmentRole| {
+        let result = parts[index + 1].parse::<uuid::Uuid>().map_err(Error::from);
+        diagnostics.record(UrlPart::Path, index, role, parts[index + 1], result)
+    };
+    Ok((parse_id(0, SegmentRole::UserId)?, parse_id(1, SegmentRole::BucketId)?))
+}
 
-        //Create a dummy secret key for the signing process
-        let secret_key_bytes = random::<[u8;32]>();
-        let secret_key = ed25519_compact::SecretKey::from_slice(&secret_key_bytes).unwrap();
+// Role of a tag=value fragment field, see decode_optional_field.
+fn optional_field_role(tag: &str) -> SegmentRole {
+    match tag {
+        NOT_BEFORE_FIELD => SegmentRole::NotBefore,
+        PREFIXES_FIELD => SegmentRole::Prefixes,
+        CHANNEL_FIELD => SegmentRole::Channel,
+        ISSUED_AT_FIELD => SegmentRole::IssuedAt,
+        MAX_TOTAL_BYTES_FIELD => SegmentRole::MaxTotalBytes,
+        RECIPIENT_FIELD => SegmentRole::Recipient,
+        _ => SegmentRole::Unknown,
+    }
+}
 
-        let ssl = SecretShareLink::new(
-            uuid::Uuid::new_v4(),
-            uuid::Uuid::new_v4(),
-            *bucket_key,
-            permission,
-            Some(OffsetDateTime::now_utc()),
-            &secret_key,
-        );
-        assert!(ssl.bucket_key != *aes_gcm::Key::<Aes256Gcm>::from_slice(&[0u8; 32]));
-        assert!(ssl.permission == permission);
+fn decode_bucket_key(value: &str) -> Result<aes_gcm::Key<Aes256Gcm>, SecretShareLinkParsingError> {
+    let bucket_key_bytes = general_purpose::URL_SAFE_NO_PAD.decode(value)?;
+    if bucket_key_bytes.len() != 32 {
+        return Err(SecretShareLinkParsingError::InvalidBucketKey);
     }
+    Ok(*aes_gcm::Key::<Aes256Gcm>::from_slice(bucket_key_bytes.as_slice()))
+}
 
-    #[test]
-    fn secret_share_link_to_and_from_url() {
-        // Set up values for a SecretShareLink
-        let user_id = uuid::Uuid::new_v4();
-        let bucket_id = uuid::Uuid::new_v4();
-        let bucket_key_bytes = [0u8; 32];
-        let bucket_key = aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key_bytes);
-        let permission = BucketSharePermissionFlags::VIEW; //You need to replace ValorA
-        let expires = Some(OffsetDateTime::now_utc());
-        let secret_key = ed25519_compact::SecretKey::from_slice(&[0u8; 32]).unwrap();
+// Unknown bits are kept so links from newer issuers still parse and verify.
+fn decode_permission(value: &str) -> Result<(BucketSharePermissionFlags, u32), SecretShareLinkParsingError> {
+    let bits = u32::from_be_bytes(
+        general_purpose::URL_SAFE_NO_PAD
+            .decode(value)?
+            .try_into()
+            .map_err(|_| SecretShareLinkParsingError::InvalidPermission)?,
+    );
+    Ok(BucketSharePermissionFlags::from_bits_lenient(bits))
+}
 
-        // Create a SecretShareLink
-        let original_link = SecretShareLink::new(
-            user_id,
-            bucket_id,
-            *bucket_key,
-            permission,
-            expires,
-            &secret_key,
-        );
+fn link_checksum(user_id: uuid::Uuid, bucket_id: uuid::Uuid, marker: &str, fields: &str) -> String {
+    let digest = Sha3_256::new().chain_update(format!("{}/{}#{}#{}", user_id, bucket_id, marker, fields)).finalize();
+    general_purpose::URL_SAFE_NO_PAD.encode(&digest[..CHECKSUM_LEN])
+}
 
-        // Convert it to a URL and back to a SecretShareLink
-        let url: url::Url = original_link.try_into().unwrap();
-        let parsed_link: SecretShareLink = url.try_into().unwrap();
+// Fields of a version 2 fragment once its length and checksum match, nothing in it is decoded before.
+fn checked_fields<'a>(
+    value: &url::Url,
+    user_id: uuid::Uuid,
+    bucket_id: uuid::Uuid,
+    fragment: &'a str,
+    diagnostics: &mut ParseDiagnostics,
+) -> Result<&'a str, SecretShareLinkParsingError> {
+    let (marker, rest) = fragment.split_once('#').unwrap_or((fragment, ""));
+    let len = &marker[CHECKED_FORMAT_MARKER.len()..];
+    let expected_len = match !len.is_empty() && len.bytes().all(|byte| byte.is_ascii_digit()) {
+        true => len.parse::<usize>().map_err(|_| SecretShareLinkParsingError::InvalidVersionFormat),
+        false => Err(SecretShareLinkParsingError::InvalidVersionFormat),
+    };
+    let expected_len = diagnostics.record(UrlPart::Fragment, 0, SegmentRole::Version, marker, expected_len)?;
+    // Length of the complete URL, everything up to the marker and its '#' followed by expected_len characters.
+    let expected_len_hint = (value.as_str().len() - fragment.len() + marker.len() + 1).saturating_add(expected_len);
+    let (fields, checksum) = rest.rsplit_once(&format!("#{}=", CHECKSUM_FIELD)).unwrap_or((rest, ""));
+    let result = match rest.len() == expected_len && checksum == link_checksum(user_id, bucket_id, marker, fields) {
+        true => Ok(fields),
+        false => Err(SecretShareLinkParsingError::ChecksumMismatch { expected_len_hint }),
+    };
+    let checksum_index = fields.split('#').count() + 1;
+    diagnostics.record(UrlPart::Fragment, checksum_index, SegmentRole::Checksum, checksum, result)
+}
 
-        // Assert that both links are equivalent
-        assert_eq!(original_link.user_id, parsed_link.user_id);
-        assert_eq!(original_link.bucket_id, parsed_link.bucket_id);
-        assert_eq!(original_link.bucket_key, parsed_link.bucket_key);
-        assert_eq!(original_link.permission, parsed_link.permission);
-        assert_eq!(original_link.expires.unwrap().date(), parsed_link.expires.unwrap().date());
-    }
+fn encode_timestamp(timestamp: ExpiryTimestamp) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(timestamp.unix_timestamp().to_be_bytes())
+}
 
-    #[test]
-    fn signature_verification() {
-        let user_id = uuid::Uuid::new_v4();
-        let bucket_id = uuid::Uuid::new_v4();
-        // Create a SecretKey and corresponding PublicKey for the signing process
-        let bytes = random::<[u8;32]>();
-        let key_pair = ed25519_compact::KeyPair::from_slice(&bytes).unwrap();
+fn decode_timestamp(value: &str, error: SecretShareLinkParsingError) -> Result<ExpiryTimestamp, SecretShareLinkParsingError> {
+    decode_expiry(&general_purpose::URL_SAFE_NO_PAD.decode(value)?).map_err(|_| error)
+}
 
-        let bucket_key_bytes = rand::random::<[u8; 32]>();
-        let bucket_key = aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key_bytes);
-        let permission = BucketSharePermissionFlags::VIEW; //You need to replace ValorA
-        let expires = Some(OffsetDateTime::now_utc());
+// Unpadded base64 length of bytes bytes.
+fn base64_len(bytes: usize) -> usize {
+    (bytes * 4).div_ceil(3)
+}
 
-        let link = SecretShareLink::new(
-            user_id,
-            bucket_id,
-            *bucket_key,
-            permission,
-            expires,
+// A single byte, see ShareChannel::to_byte.
+fn encode_channel(channel: ShareChannel) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode([channel.to_byte()])
+}
+
+// 8 bytes big endian, like the timestamps.
+fn encode_byte_count(bytes: StorageQuota) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes.bytes().to_be_bytes())
+}
+
+fn decode_byte_count(value: &str) -> Result<StorageQuota, SecretShareLinkParsingError> {
+    let bytes = <[u8; 8]>::try_from(general_purpose::URL_SAFE_NO_PAD.decode(value)?)
+        .map_err(|_| SecretShareLinkParsingError::InvalidMaxTotalBytes)?;
+    Ok(StorageQuota::from_bytes(u64::from_be_bytes(bytes)))
+}
+
+fn decode_channel(value: &str) -> Result<ShareChannel, SecretShareLinkParsingError> {
+    let [byte] = <[u8; 1]>::try_from(general_purpose::URL_SAFE_NO_PAD.decode(value)?)
+        .map_err(|_| SecretShareLinkParsingError::InvalidChannel)?;
+    Ok(ShareChannel::try_from(byte)?)
+}
+
+fn decode_signature(value: &str) -> Result<ed25519_compact::Signature, SecretShareLinkParsingError> {
+    Ok(ed25519_compact::Signature::from_slice(
+        general_purpose::URL_SAFE_NO_PAD.decode(value)?.as_slice(),
+    )?)
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, strum::IntoStaticStr)]
+pub enum SecretShareLinkVerifySignatureError {
+    #[error("Invalid signature")]
+    InvalidSignature(#[from] ed25519_compact::Error),
+    #[error("Link is not valid before {starts_at}")]
+    NotYetValid { starts_at: ExpiryTimestamp },
+    #[error("Link expired at {expired_at}")]
+    Expired { expired_at: ExpiryTimestamp },
+    #[error("Link has no expiry")]
+    MissingExpiry,
+    #[error("Link does not record when it was issued")]
+    UnknownIssueTime,
+    #[error("Link issued at {issued_at} is older than {max}")]
+    LinkTooOld { issued_at: ExpiryTimestamp, max: time::Duration },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("link URL is {len} bytes, longer than {max}, {}", describe_suggestion(.suggestion))]
+pub struct UrlTooLong {
+    pub len: usize,
+    pub max: usize,
+    // Optional fields to drop, empty when dropping all of them is not enough.
+    pub suggestion: Vec<&'static str>,
+}
+
+fn describe_suggestion(suggestion: &[&str]) -> String {
+    match suggestion.is_empty() {
+        true => "dropping optional fields is not enough".to_string(),
+        false => format!("drop {}", suggestion.join(", ")),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum SecretShareLinkUrlError {
+    #[error(transparent)]
+    Origin(#[from] LinkOriginError),
+    #[error(transparent)]
+    UrlTooLong(#[from] UrlTooLong),
+}
+
+// Checks a server adds on top of the signature and time window, the default adds none.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct VerificationPolicy {
+    pub require_expiry: bool,
+    pub max_age: Option<time::Duration>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum SecretShareLinkBuildError {
+    #[error("not before {not_before} must be earlier than the expiry {expires}")]
+    InvalidTimeWindow { not_before: ExpiryTimestamp, expires: ExpiryTimestamp },
+    #[error(transparent)]
+    TooManyPrefixes(#[from] SharePrefixError),
+    #[error("invalid issue time: {0}")]
+    InvalidIssueTime(#[from] ExpiryTimestampError),
+    #[error("a link to a {visibility} bucket can not grant {}", .removed.to_short_string())]
+    ExceedsVisibility {
+        visibility: BucketVisibility,
+        removed: BucketSharePermissionFlags,
+    },
+}
+
+/*
+* Builds and signs a SecretShareLink, only needed for the optional fields.
+* Without a permission the link grants nothing.
+*/
+#[derive(Debug, Clone)]
+pub struct SecretShareLinkBuilder {
+    link: SecretShareLink,
+    determinism: SignatureDeterminism,
+    visibility: Option<BucketVisibility>,
+    owner_override: bool,
+}
+
+/*
+* Deterministic is plain RFC 8032 Ed25519, signing the same link twice gives the same signature bytes.
+* That keeps the test vectors reproducible and issuing a link again idempotent.
+* Randomized mixes fresh noise into the nonce, equal links then get different signature bytes. Both verify the same
+* way and equality ignores the signature, so a randomized link still equals a deterministic one of the same grant.
+*/
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum SignatureDeterminism {
+    #[default]
+    Deterministic,
+    Randomized,
+}
+
+impl SignatureDeterminism {
+    // Noise::generate always has the length ed25519 expects, no noise is built from other data.
+    fn noise(self) -> Option<Noise> {
+        match self {
+            SignatureDeterminism::Deterministic => None,
+            SignatureDeterminism::Randomized => Some(Noise::generate()),
+        }
+    }
+}
+
+impl SecretShareLinkBuilder {
+    pub fn permission(mut self, permission: BucketSharePermissionFlags) -> Self {
+        self.link.permission = permission;
+        self
+    }
+
+    pub fn expires(mut self, expires: ExpiryTimestamp) -> Self {
+        self.link.expires = Some(expires);
+        self
+    }
+
+    pub fn not_before(mut self, not_before: ExpiryTimestamp) -> Self {
+        self.link.not_before = Some(not_before);
+        self
+    }
+
+    // Same as expires, with the conversion rules of ExpiryTimestamp's TryFrom.
+    #[cfg(feature = "chrono")]
+    pub fn expires_at_chrono<Tz: chrono::TimeZone>(
+        self,
+        expires: chrono::DateTime<Tz>,
+    ) -> Result<Self, ExpiryTimestampError> {
+        Ok(self.expires(ExpiryTimestamp::try_from(expires)?))
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn not_before_chrono<Tz: chrono::TimeZone>(
+        self,
+        not_before: chrono::DateTime<Tz>,
+    ) -> Result<Self, ExpiryTimestampError> {
+        Ok(self.not_before(ExpiryTimestamp::try_from(not_before)?))
+    }
+
+    pub fn prefixes(mut self, prefixes: Vec<SharePrefix>) -> Self {
+        self.link.prefixes = prefixes;
+        self
+    }
+
+    pub fn channel(mut self, channel: ShareChannel) -> Self {
+        self.link.channel = Some(channel);
+        self
+    }
+
+    pub fn recipient(mut self, recipient: UserId) -> Self {
+        self.link.recipient = Some(recipient);
+        self
+    }
+
+    pub fn max_total_bytes(mut self, max_total_bytes: StorageQuota) -> Self {
+        self.link.max_total_bytes = Some(max_total_bytes);
+        self
+    }
+
+    pub fn signature_determinism(mut self, determinism: SignatureDeterminism) -> Self {
+        self.determinism = determinism;
+        self
+    }
+
+    // Refuse permissions beyond BucketVisibility::max_allowed_share_permissions on build.
+    pub fn visibility(mut self, visibility: BucketVisibility) -> Self {
+        self.visibility = Some(visibility);
+        self
+    }
+
+    // Skips the visibility check, only for links the bucket owner issues.
+    pub fn owner_override(mut self, owner_override: bool) -> Self {
+        self.owner_override = owner_override;
+        self
+    }
+
+    pub fn build(self, secret_key: &ed25519_compact::SecretKey) -> Result<SecretShareLink, SecretShareLinkBuildError> {
+        self.build_with_clock(secret_key, &SystemClock)
+    }
+
+    // issued_at is taken from the clock.
+    pub fn build_with_clock(
+        mut self,
+        secret_key: &ed25519_compact::SecretKey,
+        clock: &impl Clock,
+    ) -> Result<SecretShareLink, SecretShareLinkBuildError> {
+        self.link.issued_at = Some(ExpiryTimestamp::try_from(clock.now())?);
+        if let (Some(not_before), Some(expires)) = (self.link.not_before, self.link.expires) {
+            if not_before >= expires {
+                return Err(SecretShareLinkBuildError::InvalidTimeWindow { not_before, expires });
+            }
+        }
+        SharePrefix::validate_count(&self.link.prefixes)?;
+        if let Some(visibility) = self.visibility.filter(|_| !self.owner_override) {
+            let (_, removed) = clamp_to_visibility(self.link.permission, visibility);
+            if !removed.is_empty() {
+                return Err(SecretShareLinkBuildError::ExceedsVisibility { visibility, removed });
+            }
+        }
+        Ok(self.link.signed_with(secret_key, self.determinism))
+    }
+}
+
+
+impl SecretShareLink {
+    // Verify the signature against the signature file with special identifier.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "SecretShareLink::verify_signature",
+            level = "debug",
+            skip_all,
+            fields(
+                bucket_id = %self.bucket_id,
+                permission = self.permission_bits(),
+                expires = self.expires.map(|expires| expires.unix_timestamp()),
+            )
+        )
+    )]
+    pub fn verify_signature(
+        &self,
+        public_signing_key: ed25519_compact::PublicKey,
+    ) -> Result<(), SecretShareLinkVerifySignatureError> {
+        let result = public_signing_key
+            .verify(self.get_token(), &self.signature)
+            .map_err(SecretShareLinkVerifySignatureError::from);
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            tracing::debug!(error = <&'static str>::from(error), "rejected secret share link signature");
+        }
+        result
+    }
+
+
+    // Checks the signature and that now is inside the not_before..expires window.
+    pub fn verify(
+        &self,
+        public_signing_key: ed25519_compact::PublicKey,
+        now: time::OffsetDateTime,
+    ) -> Result<(), SecretShareLinkVerifySignatureError> {
+        self.verify_signature(public_signing_key)?;
+        match (self.not_before, self.expires) {
+            (Some(starts_at), _) if now < starts_at.as_offset_date_time() => {
+                Err(SecretShareLinkVerifySignatureError::NotYetValid { starts_at })
+            }
+            (_, Some(expired_at)) if now >= expired_at.as_offset_date_time() => {
+                Err(SecretShareLinkVerifySignatureError::Expired { expired_at })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /*
+    * verify with the server side policy on top. max_age is counted from issued_at and applies even if the link
+    * expires later or never. Links without issued_at fail any max_age, their age can not be known.
+    */
+    pub fn verify_with_policy(
+        &self,
+        public_signing_key: ed25519_compact::PublicKey,
+        now: time::OffsetDateTime,
+        policy: &VerificationPolicy,
+    ) -> Result<(), SecretShareLinkVerifySignatureError> {
+        self.verify(public_signing_key, now)?;
+        if policy.require_expiry && self.expires.is_none() {
+            return Err(SecretShareLinkVerifySignatureError::MissingExpiry);
+        }
+        match policy.max_age {
+            Some(max) => self.enforce_max_age(max, now),
+            None => Ok(()),
+        }
+    }
+
+    // None when the link does not record its issue time. Negative when issued_at is ahead of now.
+    pub fn age(&self, now: time::OffsetDateTime) -> Option<time::Duration> {
+        self.issued_at.map(|issued_at| now - issued_at.as_offset_date_time())
+    }
+
+    // Usable while the age is below max, like expires the end is exclusive.
+    pub fn enforce_max_age(&self, max: time::Duration, now: time::OffsetDateTime) -> Result<(), SecretShareLinkVerifySignatureError> {
+        let issued_at = self.issued_at.ok_or(SecretShareLinkVerifySignatureError::UnknownIssueTime)?;
+        if now - issued_at.as_offset_date_time() >= max {
+            return Err(SecretShareLinkVerifySignatureError::LinkTooOld { issued_at, max });
+        }
+        Ok(())
+    }
+
+    // Active from not_before (inclusive) until expires (exclusive).
+    pub fn is_active_at(&self, now: time::OffsetDateTime) -> bool {
+        self.not_before.is_none_or(|not_before| now >= not_before.as_offset_date_time())
+            && self.expires.is_none_or(|expires| now < expires.as_offset_date_time())
+    }
+
+    /*
+    * Tooltip for the grant, the permissions, the scope and the expiry as of now, e.g.
+    * "Can view and download files · 2 files and 1 folder · Expires in 3 days".
+    * Time left is rounded down to whole days, hours or minutes. Without any permission only "No access".
+    */
+    pub fn describe_grant(&self, locale: Locale, now: time::OffsetDateTime) -> String {
+        let permissions = self.permission.describe(locale);
+        if !self.permission.intersects(BucketSharePermissionFlags::all()) {
+            return permissions;
+        }
+        [permissions, describe_scope(&self.prefixes, locale), describe_expiry(self.expires, locale, now)].join(" · ")
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "SecretShareLink::new",
+            level = "debug",
+            skip_all,
+            fields(
+                %bucket_id,
+                permission = permission.bits(),
+                expires = expires.map(|expires| expires.unix_timestamp()),
+            )
+        )
+    )]
+    pub fn new(user_id: uuid::Uuid,
+               bucket_id: uuid::Uuid,
+               bucket_key: aes_gcm::Key<Aes256Gcm>,
+               permission: BucketSharePermissionFlags,
+               expires: Option<ExpiryTimestamp>,
+               secret_key: &ed25519_compact::SecretKey) -> Self {
+        let mut link = Self::unsigned(user_id, bucket_id, bucket_key);
+        link.permission = permission;
+        link.expires = expires;
+        link.signed(secret_key)
+    }
+
+    // Same as new but only shares the keys matched by the given prefixes, see SharePrefix.
+    pub fn new_scoped(user_id: uuid::Uuid,
+                      bucket_id: uuid::Uuid,
+                      bucket_key: aes_gcm::Key<Aes256Gcm>,
+                      permission: BucketSharePermissionFlags,
+                      expires: Option<ExpiryTimestamp>,
+                      prefixes: Vec<SharePrefix>,
+                      secret_key: &ed25519_compact::SecretKey) -> Result<Self, SharePrefixError> {
+        SharePrefix::validate_count(&prefixes)?;
+        let mut link = Self::unsigned(user_id, bucket_id, bucket_key);
+        link.permission = permission;
+        link.expires = expires;
+        link.prefixes = prefixes;
+        Ok(link.signed(secret_key))
+    }
+
+    pub fn builder(user_id: uuid::Uuid, bucket_id: uuid::Uuid, bucket_key: aes_gcm::Key<Aes256Gcm>) -> SecretShareLinkBuilder {
+        SecretShareLinkBuilder {
+            link: Self::unsigned(user_id, bucket_id, bucket_key),
+            determinism: SignatureDeterminism::default(),
+            visibility: None,
+            owner_override: false,
+        }
+    }
+
+    // The signature is a placeholder until signed is called.
+    pub(crate) fn unsigned(user_id: uuid::Uuid, bucket_id: uuid::Uuid, bucket_key: aes_gcm::Key<Aes256Gcm>) -> Self {
+        Self {
+            user_id,
+            bucket_id,
+            bucket_key,
+            permission: BucketSharePermissionFlags::empty(),
+            unknown_permission_bits: 0,
+            expires: None,
+            not_before: None,
+            prefixes: Vec::new(),
+            channel: None,
+            recipient: None,
+            issued_at: None,
+            max_total_bytes: None,
+            signature: ed25519_compact::Signature::new([0; 64]),
+        }
+    }
+
+    // new and new_scoped sign deterministically, see SignatureDeterminism.
+    fn signed(self, secret_key: &ed25519_compact::SecretKey) -> Self {
+        self.signed_with(secret_key, SignatureDeterminism::default())
+    }
+
+    fn signed_with(mut self, secret_key: &ed25519_compact::SecretKey, determinism: SignatureDeterminism) -> Self {
+        self.signature = secret_key.sign(self.get_token(), determinism.noise());
+        self
+    }
+
+    // Filter for the objects this link gives access to, call it for every listed object.
+    pub fn effective_keys_filter(&self) -> impl Fn(&BucketObjectKey) -> bool + '_ {
+        effective_keys_filter(&self.prefixes)
+    }
+    // TODO: There is no way for the server to invalidate a secret share link.
+    /*
+    Generate a token that is used by the server to identify the link.
+    */
+    pub fn get_token(&self) -> [u8; 32] {
+        let mut hash_output = [0; 32];
+        hash_secret_share_link::<Sha3_256>(self, &mut hash_output);
+        hash_output
+    }
+
+    // Every permission bit as signed, including the ones this version does not know.
+    pub fn permission_bits(&self) -> u32 {
+        self.permission.bits() | self.unknown_permission_bits
+    }
+
+    /*
+    * Permissions to authorize requests with. Unknown bits are never granted,
+    * a permission added later only takes effect on services that know it.
+    */
+    pub fn effective_known(&self) -> BucketSharePermissionFlags {
+        self.permission
+    }
+
+    // The exact bytes hashed into the token, for implementations in other languages to compare against.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_canonical_prefix(self, &mut |chunk| bytes.extend_from_slice(chunk));
+        write_canonical_recipient(self.recipient, &mut |chunk| bytes.extend_from_slice(chunk));
+        bytes
+    }
+
+    pub fn share_link_token(&self) -> ShareLinkToken {
+        ShareLinkToken(self.get_token())
+    }
+
+    /*
+    * The plain link for a bucket leaving zero-knowledge mode. It has the same token, so a redirect service can send
+    * recipients of the secret link there, see parse_any.
+    */
+    pub fn to_share_link(&self) -> ShareLink {
+        ShareLink {
+            token: self.get_token(),
+        }
+    }
+
+    pub fn identical_including_signature(&self, other: &Self) -> bool {
+        self == other && self.signature == other.signature
+    }
+}
+
+fn describe_scope(prefixes: &[SharePrefix], locale: Locale) -> String {
+    if prefixes.is_empty() {
+        return match locale {
+            Locale::En => "Whole bucket",
+            Locale::De => "Ganzer Bucket",
+            Locale::Sv => "Hela bucketen",
+        }
+        .to_string();
+    }
+    let (file, folder) = match locale {
+        Locale::En => (("file", "files"), ("folder", "folders")),
+        Locale::De => (("Datei", "Dateien"), ("Ordner", "Ordner")),
+        Locale::Sv => (("fil", "filer"), ("mapp", "mappar")),
+    };
+    let folders = prefixes.iter().filter(|prefix| prefix.is_subtree()).count() as u64;
+    let files = prefixes.len() as u64 - folders;
+    let parts = [(files, file), (folders, folder)]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, forms)| count_of(count, forms))
+        .collect::<Vec<_>>();
+    locale.join_list(&parts)
+}
+
+fn describe_expiry(expires: Option<ExpiryTimestamp>, locale: Locale, now: time::OffsetDateTime) -> String {
+    let left = match expires {
+        None => {
+            return match locale {
+                Locale::En => "Never expires",
+                Locale::De => "Läuft nie ab",
+                Locale::Sv => "Går aldrig ut",
+            }
+            .to_string()
+        }
+        Some(expires) if now >= expires.as_offset_date_time() => {
+            return match locale {
+                Locale::En => "Expired",
+                Locale::De => "Abgelaufen",
+                Locale::Sv => "Har gått ut",
+            }
+            .to_string()
+        }
+        Some(expires) => expires.as_offset_date_time() - now,
+    };
+    let (days, hours, minutes, less_than_a_minute) = match locale {
+        Locale::En => (("day", "days"), ("hour", "hours"), ("minute", "minutes"), "less than a minute"),
+        Locale::De => (("Tag", "Tagen"), ("Stunde", "Stunden"), ("Minute", "Minuten"), "weniger als einer Minute"),
+        Locale::Sv => (("dag", "dagar"), ("timme", "timmar"), ("minut", "minuter"), "mindre än en minut"),
+    };
+    let left = match (left.whole_days(), left.whole_hours(), left.whole_minutes()) {
+        (0, 0, 0) => less_than_a_minute.to_string(),
+        (0, 0, minutes_left) => count_of(minutes_left as u64, minutes),
+        (0, hours_left, _) => count_of(hours_left as u64, hours),
+        (days_left, _, _) => count_of(days_left as u64, days),
+    };
+    match locale {
+        Locale::En => format!("Expires in {}", left),
+        Locale::De => format!("Läuft in {} ab", left),
+        Locale::Sv => format!("Går ut om {}", left),
+    }
+}
+
+impl Redact for SecretShareLink {
+    fn redacted(&self) -> String {
+        let expires = match self.expires {
+            Some(expires) => expires.to_string(),
+            None => "never".to_string(),
+        };
+        format!(
+            "SecretShareLink {{ user_id: {}, bucket_id: {}, permission: {:?}, expires: {}, key: fp:{} }}",
+            self.user_id,
+            self.bucket_id,
+            self.permission,
+            expires,
+            Fingerprint::of(self.bucket_key.as_slice()),
+        )
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretShareLinkFormatError {
+    #[error(transparent)]
+    SecretShareLinkFormatError(#[from] SecretShareLinkParsingError),
+    #[error(transparent)]
+    UrlParse(#[from] url::ParseError),
+}
+
+impl TryInto<url::Url> for SecretShareLink {
+    type Error = SecretShareLinkFormatError;
+
+    fn try_into(self) -> Result<url::Url, Self::Error> {
+        let res: String = self.to_string();
+        Ok(url::Url::parse(&res)?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::random;
+    use time::OffsetDateTime;
+    use crate::clock::FixedClock;
+    use crate::share_prefix::MAX_SHARE_PREFIXES;
+    use crate::util::MAX_LINK_URL_LEN;
+    use super::*;
+
+    #[test]
+    fn create_secret_share_link() {
+        //Generate pseudo random bytes for the base64 conversion
+        let bucket_key_bytes = rand::random::<[u8; 32]>();
+        let bucket_key = aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key_bytes);
+
+        let permission = BucketSharePermissionFlags::VIEW;
+
+        //Create a dummy secret key for the signing process
+        let seed = ed25519_compact::Seed::new(random::<[u8; 32]>());
+        let secret_key = ed25519_compact::KeyPair::from_seed(seed).sk;
+
+        let ssl = SecretShareLink::new(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *bucket_key,
+            permission,
+            Some(ExpiryTimestamp::try_from(OffsetDateTime::now_utc()).unwrap()),
+            &secret_key,
+        );
+        assert!(ssl.bucket_key != *aes_gcm::Key::<Aes256Gcm>::from_slice(&[0u8; 32]));
+        assert!(ssl.permission == permission);
+    }
+
+    #[test]
+    fn secret_share_link_to_and_from_url() {
+        // Set up values for a SecretShareLink
+        let user_id = uuid::Uuid::new_v4();
+        let bucket_id = uuid::Uuid::new_v4();
+        let bucket_key_bytes = [0u8; 32];
+        let bucket_key = aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key_bytes);
+        let permission = BucketSharePermissionFlags::VIEW; //You need to replace ValorA
+        let expires = Some(ExpiryTimestamp::try_from(OffsetDateTime::now_utc()).unwrap());
+        let secret_key = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32])).sk;
+
+        // Create a SecretShareLink
+        let original_link = SecretShareLink::new(
+            user_id,
+            bucket_id,
+            *bucket_key,
+            permission,
+            expires,
+            &secret_key,
+        );
+
+        // Convert it to a URL and back to a SecretShareLink
+        let url: url::Url = original_link.clone().try_into().unwrap();
+        let parsed_link: SecretShareLink = url.try_into().unwrap();
+
+        // Assert that both links are equivalent
+        assert_eq!(original_link.user_id, parsed_link.user_id);
+        assert_eq!(original_link.bucket_id, parsed_link.bucket_id);
+        assert_eq!(original_link.bucket_key, parsed_link.bucket_key);
+        assert_eq!(original_link.permission, parsed_link.permission);
+        assert_eq!(original_link.expires, parsed_link.expires);
+    }
+
+    #[test]
+    fn secret_share_link_case_insensitive_origin() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = SecretShareLink::new(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7u8; 32]),
+            BucketSharePermissionFlags::VIEW,
+            None,
+            &key_pair.sk,
+        );
+        let url = link.to_string();
+        let (path, fragment) = url.split_once('#').unwrap();
+        for input in [
+            url.replacen("https://bucketdrive.co", "HTTPS://BUCKETDRIVE.CO", 1),
+            url.replacen("https", "HtTpS", 1),
+            format!("{}/#{}", path, fragment),
+        ] {
+            let parsed = input.parse::<SecretShareLink>().unwrap();
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()), "{}", input);
+            assert_eq!(parsed.get_token(), link.get_token());
+        }
+
+        // The payload is not normalized, an uppercased key must not verify.
+        let uppercased = format!("{}#{}", path, fragment.to_ascii_uppercase());
+        let verified = uppercased
+            .parse::<SecretShareLink>()
+            .map(|parsed| parsed.verify_signature(key_pair.pk));
+        assert!(!matches!(verified, Ok(Ok(()))));
+
+        assert!(matches!(
+            url.replacen("https", "ftp", 1).parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::InvalidScheme)
+        ));
+    }
+
+    fn scoped_link(prefixes: &[&str], key_pair: &ed25519_compact::KeyPair) -> Result<SecretShareLink, SharePrefixError> {
+        SecretShareLink::new_scoped(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7u8; 32]),
+            BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            None,
+            prefixes.iter().map(|prefix| prefix.parse().unwrap()).collect(),
+            &key_pair.sk,
+        )
+    }
+
+    #[test]
+    fn scoped_link_round_trip() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = scoped_link(&["/photos/2024/", "/notes/todo.txt"], &key_pair).unwrap();
+        assert_eq!(link.verify_signature(key_pair.pk), Ok(()));
+
+        let parsed = link.to_string().parse::<SecretShareLink>().unwrap();
+        assert_eq!(parsed.prefixes, link.prefixes);
+        assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+
+        let filter = parsed.effective_keys_filter();
+        assert!(filter(&"/photos/2024/beach.jpg".parse().unwrap()));
+        assert!(filter(&"/notes/todo.txt".parse().unwrap()));
+        assert!(!filter(&"/photos/2023/beach.jpg".parse().unwrap()));
+        assert!(!filter(&"/notes/todo.txt.bak".parse().unwrap()));
+    }
+
+    #[test]
+    fn adding_prefix_after_signing_breaks_signature() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let mut link = scoped_link(&["/photos/"], &key_pair).unwrap();
+        link.prefixes.push("/private/".parse().unwrap());
+        assert!(link.verify_signature(key_pair.pk).is_err());
+
+        // Widening a scoped link to the whole bucket breaks it as well.
+        let mut link = scoped_link(&["/photos/"], &key_pair).unwrap();
+        link.prefixes.clear();
+        assert!(link.verify_signature(key_pair.pk).is_err());
+        assert!(link.to_string().parse::<SecretShareLink>().unwrap().verify_signature(key_pair.pk).is_err());
+    }
+
+    #[test]
+    fn too_many_prefixes_are_rejected() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let prefixes = (0..=MAX_SHARE_PREFIXES).map(|index| format!("/{}/", index)).collect::<Vec<_>>();
+        let prefixes = prefixes.iter().map(String::as_str).collect::<Vec<_>>();
+        assert!(scoped_link(&prefixes[..MAX_SHARE_PREFIXES], &key_pair).is_ok());
+        assert_eq!(
+            scoped_link(&prefixes, &key_pair).unwrap_err(),
+            SharePrefixError::TooManyPrefixes(MAX_SHARE_PREFIXES + 1)
+        );
+
+        // A link with too many prefixes can not be created through the URL either.
+        let mut link = scoped_link(&prefixes[..1], &key_pair).unwrap();
+        link.prefixes = prefixes.iter().map(|prefix| prefix.parse().unwrap()).collect();
+        assert!(matches!(
+            link.to_string().parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::TooManyPrefixes(_))
+        ));
+    }
+
+    fn builder() -> SecretShareLinkBuilder {
+        SecretShareLink::builder(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7u8; 32]),
+        )
+        .permission(BucketSharePermissionFlags::VIEW)
+    }
+
+    #[test]
+    fn time_window_boundaries() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let starts_at = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(1)).unwrap();
+        let expired_at = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(2)).unwrap();
+        let link = builder().not_before(starts_at).expires(expired_at).build(&key_pair.sk).unwrap();
+        let second = time::Duration::SECOND;
+
+        assert!(!link.is_active_at(starts_at.as_offset_date_time() - second));
+        assert!(link.is_active_at(starts_at.as_offset_date_time()));
+        assert!(link.is_active_at(expired_at.as_offset_date_time() - second));
+        assert!(!link.is_active_at(expired_at.as_offset_date_time()));
+
+        assert_eq!(
+            link.verify(key_pair.pk, starts_at.as_offset_date_time() - second),
+            Err(SecretShareLinkVerifySignatureError::NotYetValid { starts_at })
+        );
+        assert_eq!(link.verify(key_pair.pk, starts_at.as_offset_date_time()), Ok(()));
+        assert_eq!(
+            link.verify(key_pair.pk, expired_at.as_offset_date_time()),
+            Err(SecretShareLinkVerifySignatureError::Expired { expired_at })
+        );
+    }
+
+    #[test]
+    fn builder_rejects_empty_time_window() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let at = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(1)).unwrap();
+        let earlier = ExpiryTimestamp::try_from(OffsetDateTime::now_utc()).unwrap();
+        assert_eq!(
+            builder().not_before(at).expires(at).build(&key_pair.sk).unwrap_err(),
+            SecretShareLinkBuildError::InvalidTimeWindow { not_before: at, expires: at }
+        );
+        assert!(builder().not_before(at).expires(earlier).build(&key_pair.sk).is_err());
+        assert!(builder().not_before(earlier).expires(at).build(&key_pair.sk).is_ok());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn builder_takes_chrono_times() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let tomorrow = chrono::DateTime::from_timestamp(OffsetDateTime::now_utc().unix_timestamp() + 86400, 0).unwrap();
+        let offset = chrono::FixedOffset::east_opt(5 * 3600).unwrap();
+        let link = builder()
+            .not_before_chrono(tomorrow.with_timezone(&offset))
+            .unwrap()
+            .expires_at_chrono(tomorrow + chrono::Duration::days(1))
+            .unwrap()
+            .build(&key_pair.sk)
+            .unwrap();
+        assert_eq!(link.not_before.unwrap().unix_timestamp(), tomorrow.timestamp());
+        assert_eq!(link.expires.unwrap().unix_timestamp(), tomorrow.timestamp() + 86400);
+
+        let before_1970 = chrono::DateTime::parse_from_rfc3339("1969-07-20T20:17:00Z").unwrap();
+        assert!(matches!(
+            builder().expires_at_chrono(before_1970),
+            Err(ExpiryTimestampError::TooEarly(_))
+        ));
+    }
+
+    #[test]
+    fn describe_grant_covers_expiry_and_scope() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let now = OffsetDateTime::from_unix_timestamp(1_717_200_000).unwrap();
+        let at = |offset: time::Duration| Some(ExpiryTimestamp::try_from(now + offset).unwrap());
+        let link = |expires: Option<ExpiryTimestamp>, prefixes: &[&str]| {
+            let mut link = builder()
+                .permission(BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ)
+                .prefixes(prefixes.iter().map(|prefix| prefix.parse().unwrap()).collect());
+            if let Some(expires) = expires {
+                link = link.expires(expires);
+            }
+            link.build(&key_pair.sk).unwrap()
+        };
+        let table = [
+            (None, &[][..], "Can view and download files · Whole bucket · Never expires"),
+            (at(time::Duration::days(3)), &[], "Can view and download files · Whole bucket · Expires in 3 days"),
+            (
+                at(time::Duration::days(1) + time::Duration::hours(23)),
+                &["/a.txt"],
+                "Can view and download files · 1 file · Expires in 1 day",
+            ),
+            (
+                at(time::Duration::hours(5) + time::Duration::minutes(59)),
+                &["/a.txt", "/b.txt", "/photos/"],
+                "Can view and download files · 2 files and 1 folder · Expires in 5 hours",
+            ),
+            (
+                at(time::Duration::minutes(1)),
+                &["/photos/", "/music/"],
+                "Can view and download files · 2 folders · Expires in 1 minute",
+            ),
+            (
+                at(time::Duration::seconds(30)),
+                &[],
+                "Can view and download files · Whole bucket · Expires in less than a minute",
+            ),
+            (at(time::Duration::ZERO), &[], "Can view and download files · Whole bucket · Expired"),
+            (at(-time::Duration::days(2)), &[], "Can view and download files · Whole bucket · Expired"),
+        ];
+        for (expires, prefixes, expected) in table {
+            assert_eq!(link(expires, prefixes).describe_grant(Locale::En, now), expected);
+        }
+
+        let mut no_access = link(at(time::Duration::days(3)), &["/a.txt"]);
+        no_access.permission = BucketSharePermissionFlags::empty();
+        assert_eq!(no_access.describe_grant(Locale::En, now), "No access");
+
+        let scoped = link(at(time::Duration::days(3)), &["/a.txt", "/photos/"]);
+        assert_eq!(
+            scoped.describe_grant(Locale::De, now),
+            "Kann Dateien ansehen und herunterladen · 1 Datei und 1 Ordner · Läuft in 3 Tagen ab"
+        );
+        assert_eq!(
+            scoped.describe_grant(Locale::Sv, now),
+            "Kan visa och ladda ner filer · 1 fil och 1 mapp · Går ut om 3 dagar"
+        );
+        assert_eq!(scoped.describe_grant(Locale::En, now), scoped.describe_grant(Locale::En, now));
+    }
+
+    #[test]
+    fn time_window_round_trip() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let not_before = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(1)).unwrap();
+        let expires = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(2)).unwrap();
+        for (not_before, expires) in [
+            (None, None),
+            (Some(not_before), None),
+            (None, Some(expires)),
+            (Some(not_before), Some(expires)),
+        ] {
+            let mut builder = builder();
+            if let Some(not_before) = not_before {
+                builder = builder.not_before(not_before);
+            }
+            if let Some(expires) = expires {
+                builder = builder.expires(expires);
+            }
+            let link = builder.build(&key_pair.sk).unwrap();
+            let parsed = link.to_string().parse::<SecretShareLink>().unwrap();
+            assert_eq!(parsed.not_before, not_before);
+            assert_eq!(parsed.expires, expires);
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+        }
+    }
+
+    #[test]
+    fn tampering_with_not_before_breaks_signature() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let not_before = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(1)).unwrap();
+        let link = builder().not_before(not_before).build(&key_pair.sk).unwrap();
+
+        let mut tampered = link.clone();
+        tampered.not_before = None;
+        assert!(tampered.verify_signature(key_pair.pk).is_err());
+        let mut tampered = link.clone();
+        tampered.not_before = Some(ExpiryTimestamp::try_from(OffsetDateTime::now_utc()).unwrap());
+        assert!(tampered.verify_signature(key_pair.pk).is_err());
+
+        // Dropping the field from the URL does not turn it into a valid link without embargo.
+        let url = link.to_string();
+        let stripped = reseal(&url.split('#').filter(|field| !field.starts_with("nb=")).collect::<Vec<_>>().join("#"));
+        assert_ne!(stripped, url);
+        assert!(stripped.parse::<SecretShareLink>().unwrap().verify_signature(key_pair.pk).is_err());
+    }
+
+    // The link as issued before format version 2, without version marker and checksum.
+    fn legacy_url(link: &SecretShareLink) -> String {
+        let mut fields = String::new();
+        link.write_fields(&mut fields).unwrap();
+        format!("https://{}{}/{}/{}#{}", DOMAIN_URL, SECRET_SHARE_PATH_URL, link.user_id, link.bucket_id, fields)
+    }
+
+    // Recomputes length and checksum of a link whose fields were edited, so only the signature can catch the edit.
+    fn reseal(url: &str) -> String {
+        let (base, fragment) = url.split_once('#').unwrap();
+        let fields = fragment.split_once('#').unwrap().1.rsplit_once("#ck=").unwrap().0;
+        let mut ids = base.rsplit('/');
+        let (bucket_id, user_id) = (ids.next().unwrap().parse().unwrap(), ids.next().unwrap().parse().unwrap());
+        let marker = format!("{}{}", CHECKED_FORMAT_MARKER, fields.len() + CHECKSUM_SEGMENT_LEN);
+        let checksum = link_checksum(user_id, bucket_id, &marker, fields);
+        format!("{}#{}#{}#{}={}", base, marker, fields, CHECKSUM_FIELD, checksum)
+    }
+
+    fn full_link(key_pair: &ed25519_compact::KeyPair) -> SecretShareLink {
+        let not_before = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(1)).unwrap();
+        let expires = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(2)).unwrap();
+        builder()
+            .not_before(not_before)
+            .expires(expires)
+            .prefixes(vec!["/photos/".parse().unwrap(), "/a b+c.txt".parse().unwrap()])
+            .recipient(uuid::Uuid::new_v4())
+            .build(&key_pair.sk)
+            .unwrap()
+    }
+
+    #[test]
+    fn query_url_round_trip() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        for link in [full_link(&key_pair), builder().build(&key_pair.sk).unwrap()] {
+            let url = link.to_query_url(&LinkOrigin::default()).unwrap();
+            assert_eq!(url.fragment(), None);
+            assert_eq!(SecretShareLinkForm::of(&url), SecretShareLinkForm::Query);
+            assert!(!SecretShareLinkForm::of(&url).is_secret_preserving());
+
+            let parsed = SecretShareLink::from_query_url(&url).unwrap();
+            assert!(parsed.identical_including_signature(&link));
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+        }
+
+        let fragment_url: url::Url = builder().build(&key_pair.sk).unwrap().try_into().unwrap();
+        assert!(SecretShareLinkForm::of(&fragment_url).is_secret_preserving());
+    }
+
+    fn long_prefixes() -> Vec<SharePrefix> {
+        (0..MAX_SHARE_PREFIXES).map(|index| format!("/{}/{}/", index, "x".repeat(80)).parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn url_length_budget() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = builder()
+            .prefixes(long_prefixes())
+            .recipient(uuid::Uuid::new_v4())
+            .channel(ShareChannel::Email)
+            .build(&key_pair.sk)
+            .unwrap();
+        let len = link.to_string().len();
+        assert!(len > MAX_LINK_URL_LEN, "{}", len);
+        assert_eq!(LinkOrigin::default().max_url_len, MAX_LINK_URL_LEN);
+
+        let at_limit = LinkOrigin::default().with_max_url_len(len);
+        assert_eq!(link.to_url_checked(&at_limit).unwrap().as_str(), link.to_string());
+
+        let error = link.to_url_checked(&LinkOrigin::default()).unwrap_err();
+        assert_eq!(
+            error,
+            SecretShareLinkUrlError::UrlTooLong(UrlTooLong { len, max: MAX_LINK_URL_LEN, suggestion: vec!["prefixes"] })
+        );
+        assert_eq!(
+            error.to_string(),
+            format!("link URL is {} bytes, longer than {}, drop prefixes", len, MAX_LINK_URL_LEN)
+        );
+        let without_prefixes = SecretShareLink { prefixes: Vec::new(), ..link.clone() };
+        assert!(without_prefixes.to_url_checked(&LinkOrigin::default()).is_ok());
+
+        // The largest field goes first, the next ones only while still over the limit.
+        let short = without_prefixes.to_string().len();
+        let tight = LinkOrigin::default().with_max_url_len(short - 1);
+        let Err(SecretShareLinkUrlError::UrlTooLong(too_long)) = link.to_url_checked(&tight) else {
+            panic!("expected UrlTooLong");
+        };
+        assert_eq!(too_long.suggestion, vec!["prefixes", "recipient"]);
+
+        let tiny = LinkOrigin::default().with_max_url_len(100);
+        let Err(SecretShareLinkUrlError::UrlTooLong(too_long)) = link.to_url_checked(&tiny) else {
+            panic!("expected UrlTooLong");
+        };
+        assert!(too_long.suggestion.is_empty());
+        assert!(too_long.to_string().ends_with("dropping optional fields is not enough"));
+
+        let origin = LinkOrigin::new("javascript", "share");
+        assert_eq!(
+            link.to_url_checked(&origin),
+            Err(SecretShareLinkUrlError::Origin(LinkOriginError::SchemeNotAllowed("javascript".to_string())))
+        );
+    }
+
+    #[test]
+    fn url_len_estimate_matches_display() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let not_before = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(1)).unwrap();
+        let expires = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(2)).unwrap();
+        let prefix_sets = [Vec::new(), vec!["/photos/".parse().unwrap()], long_prefixes()];
+        for fields in 0..32u8 {
+            for prefixes in &prefix_sets {
+                let mut builder = builder().prefixes(prefixes.clone());
+                if fields & 1 != 0 {
+                    builder = builder.expires(expires);
+                }
+                if fields & 2 != 0 {
+                    builder = builder.not_before(not_before);
+                }
+                if fields & 4 != 0 {
+                    builder = builder.channel(ShareChannel::Qr);
+                }
+                if fields & 8 != 0 {
+                    builder = builder.recipient(uuid::Uuid::new_v4());
+                }
+                if fields & 16 != 0 {
+                    builder = builder.max_total_bytes(StorageQuota::from_bytes(1 << 30));
+                }
+                let link = builder.build(&key_pair.sk).unwrap();
+                assert_eq!(link.url_len_estimate(), link.to_string().len(), "{}", link);
+
+                let legacy = SecretShareLink { issued_at: None, ..link };
+                assert_eq!(legacy.url_len_estimate(), legacy.to_string().len(), "{}", legacy);
+            }
+        }
+    }
+
+    #[test]
+    fn deep_link_keeps_the_key_in_the_fragment() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let deep_link = LinkOrigin::deep_link();
+        for link in [full_link(&key_pair), builder().build(&key_pair.sk).unwrap()] {
+            let url = link.to_url_with(&deep_link).unwrap();
+            assert_eq!((url.scheme(), url.host_str()), ("bucketdrive", Some("share")));
+            assert_eq!(url.path(), format!("/{}/{}", link.user_id, link.bucket_id));
+            let key = general_purpose::URL_SAFE_NO_PAD.encode(link.bucket_key.as_slice());
+            assert!(url.fragment().unwrap().split('#').nth(1) == Some(key.as_str()));
+            assert_eq!(url.query(), None);
+
+            // Reparsed from its text, as an app receives it from the OS.
+            let reparsed = url::Url::parse(url.as_str()).unwrap();
+            let parsed = SecretShareLink::from_url_with_origin(&reparsed, &deep_link).unwrap();
+            assert!(parsed.identical_including_signature(&link));
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+            assert!(matches!(SecretShareLink::try_from(reparsed), Err(SecretShareLinkParsingError::InvalidScheme)));
+
+            let web = link.to_url_with(&LinkOrigin::default()).unwrap();
+            assert_eq!(web.as_str(), link.to_string());
+            assert!(SecretShareLink::try_from(web).unwrap().identical_including_signature(&link));
+
+            let query = link.to_query_url(&deep_link).unwrap();
+            assert!(query.as_str().starts_with("bucketdrive://share/"));
+            let parsed = SecretShareLink::from_query_url_with_origin(&query, &deep_link).unwrap();
+            assert!(parsed.identical_including_signature(&link));
+        }
+
+        let link = builder().build(&key_pair.sk).unwrap();
+        let origin = LinkOrigin::new("javascript", "share");
+        assert_eq!(link.to_url_with(&origin), Err(LinkOriginError::SchemeNotAllowed("javascript".to_string())));
+        let url = url::Url::parse(&link.to_string().replace("https://bucketdrive.co/api/v1", "javascript:/")).unwrap();
+        assert!(matches!(
+            SecretShareLink::from_url_with_origin(&url, &origin),
+            Err(SecretShareLinkParsingError::InvalidScheme)
+        ));
+    }
+
+    #[test]
+    fn builder_checks_visibility() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let permission = BucketSharePermissionFlags::READ | BucketSharePermissionFlags::DELETE_BUCKET;
+        let error = builder()
+            .permission(permission)
+            .visibility(BucketVisibility::Public)
+            .build(&key_pair.sk)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            SecretShareLinkBuildError::ExceedsVisibility {
+                visibility: BucketVisibility::Public,
+                removed,
+            } if removed == BucketSharePermissionFlags::DELETE_BUCKET
+        ));
+        assert_eq!(error.to_string(), "a link to a Public bucket can not grant ----D---");
+        assert!(matches!(
+            builder().visibility(BucketVisibility::Private).build(&key_pair.sk),
+            Err(SecretShareLinkBuildError::ExceedsVisibility { removed, .. })
+                if removed == BucketSharePermissionFlags::VIEW
+        ));
+
+        // Within the visibility, without one, or issued by the owner.
+        let suggested = BucketVisibility::Public.suggested_share_permissions();
+        let link = builder().permission(suggested).visibility(BucketVisibility::Public).build(&key_pair.sk);
+        assert_eq!(link.unwrap().permission, suggested);
+        assert!(builder().permission(permission).build(&key_pair.sk).is_ok());
+        let link = builder()
+            .permission(permission)
+            .visibility(BucketVisibility::Public)
+            .owner_override(true)
+            .build(&key_pair.sk)
+            .unwrap();
+        assert_eq!(link.permission, permission);
+    }
+
+    #[test]
+    fn downgrade_keeps_the_token() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        for link in [full_link(&key_pair), builder().build(&key_pair.sk).unwrap()] {
+            let plain = link.to_share_link();
+            assert_eq!(plain.share_link_token(), link.share_link_token());
+            assert_eq!(plain.token, link.get_token());
+
+            let secret_url = url::Url::parse(&link.to_string()).unwrap();
+            let query_url = link.to_query_url(&LinkOrigin::default()).unwrap();
+            let plain_url = url::Url::parse(&plain.to_string()).unwrap();
+            assert_eq!(parse_any(&secret_url).unwrap(), (LinkKind::Secret, link.share_link_token()));
+            assert_eq!(parse_any(&query_url).unwrap(), (LinkKind::Secret, link.share_link_token()));
+            assert_eq!(parse_any(&plain_url).unwrap(), (LinkKind::Plain, link.share_link_token()));
+        }
+    }
+
+    #[test]
+    fn parse_any_rejects_garbage() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = builder().build(&key_pair.sk).unwrap();
+        let parse = |url: &str| parse_any(&url::Url::parse(url).unwrap());
+
+        assert!(matches!(parse("https://bucketdrive.co/api/v1/share/AAAA"), Err(AnyLinkParsingError::Plain(_))));
+        assert!(matches!(parse("https://example.com/"), Err(AnyLinkParsingError::Plain(_))));
+        assert!(matches!(
+            parse("https://bucketdrive.co/api/v1/share/a/b#c"),
+            Err(AnyLinkParsingError::Secret(_))
+        ));
+        let foreign = link.to_string().replace("bucketdrive.co", "example.com");
+        assert!(matches!(
+            parse(&foreign),
+            Err(AnyLinkParsingError::Secret(SecretShareLinkParsingError::InvalidHostDomain))
+        ));
+        let query_url = link.to_query_url(&LinkOrigin::default()).unwrap();
+        let truncated = query_url.as_str().split('&').next().unwrap();
+        assert!(matches!(parse(truncated), Err(AnyLinkParsingError::Secret(_))));
+    }
+
+    #[test]
+    fn parse_any_never_exposes_the_key() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = full_link(&key_pair);
+        let key = general_purpose::URL_SAFE_NO_PAD.encode(link.bucket_key.as_slice());
+        let secret_url = url::Url::parse(&link.to_string()).unwrap();
+        assert!(secret_url.as_str().contains(&key));
+
+        let parsed = parse_any(&secret_url).unwrap();
+        assert!(!format!("{:?}", parsed).contains(&key));
+        // The token is a hash, neither it nor its text form is the key.
+        assert_ne!(parsed.1 .0.as_slice(), link.bucket_key.as_slice());
+        assert!(!parsed.1.to_string().contains(&key));
+
+        // A broken signature fails the parse, the error carries nothing of the URL.
+        let broken = url::Url::parse(&format!("{}!", secret_url)).unwrap();
+        let error = parse_any(&broken).unwrap_err();
+        assert!(!format!("{:?} {}", error, error).contains(&key));
+    }
+
+    #[test]
+    fn query_url_parameters_can_be_reordered_and_percent_encoded() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = full_link(&key_pair);
+        let url = link.to_query_url(&LinkOrigin::default()).unwrap();
+        let mut pairs = url.query_pairs().into_owned().collect::<Vec<_>>();
+        pairs.reverse();
+        // Percent-encode every byte of every value, decoding must give the same link.
+        let query = pairs
+            .iter()
+            .map(|(name, value)| {
+                let encoded = value.bytes().map(|byte| format!("%{:02X}", byte)).collect::<String>();
+                format!("{}={}", name, encoded)
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut reordered = url.clone();
+        reordered.set_query(Some(&query));
+        assert_ne!(reordered, url);
+
+        let parsed = SecretShareLink::from_query_url(&reordered).unwrap();
+        assert!(parsed.identical_including_signature(&link));
+    }
+
+    #[test]
+    fn query_url_rejects_duplicate_and_missing_parameters() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let url = full_link(&key_pair).to_query_url(&LinkOrigin::default()).unwrap();
+
+        for name in ["sk", "p", "e", "nb", "sp", "r", "sig"] {
+            let value = url.query_pairs().find(|(seen, _)| seen == name).unwrap().1.into_owned();
+            let mut duplicated = url.clone();
+            duplicated.query_pairs_mut().append_pair(name, &value);
+            assert!(matches!(
+                SecretShareLink::from_query_url(&duplicated),
+                Err(SecretShareLinkParsingError::DuplicateParameter(duplicate)) if duplicate == name
+            ));
+        }
+
+        let mut missing = url.clone();
+        let pairs = url.query_pairs().into_owned().filter(|(name, _)| name != "sig").collect::<Vec<_>>();
+        missing.query_pairs_mut().clear().extend_pairs(pairs);
+        assert!(matches!(
+            SecretShareLink::from_query_url(&missing),
+            Err(SecretShareLinkParsingError::MissingParameter("sig"))
+        ));
+
+        let mut unknown = url.clone();
+        unknown.query_pairs_mut().append_pair("utm_source", "mail");
+        assert!(matches!(
+            SecretShareLink::from_query_url(&unknown),
+            Err(SecretShareLinkParsingError::InvalidField(_))
+        ));
+    }
+
+    #[test]
+    fn equality_ignores_signature() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let other_key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([43u8; 32]));
+        let link = full_link(&key_pair);
+        let resigned = link.clone().signed(&other_key_pair.sk);
+        assert_ne!(resigned.signature, link.signature);
+        assert_eq!(resigned, link);
+        assert!(!resigned.identical_including_signature(&link));
+        assert!(link.identical_including_signature(&link.clone()));
+
+        let mut other_permission = link.clone();
+        other_permission.permission |= BucketSharePermissionFlags::WRITE;
+        assert_ne!(other_permission, link);
+        let mut other_key = link.clone();
+        other_key.bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&[8u8; 32]);
+        assert_ne!(other_key, link);
+
+        let set = [link.clone(), resigned, other_permission.clone(), link.clone()]
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&link));
+        assert!(set.contains(&other_permission));
+    }
+
+    #[test]
+    fn channel_round_trip() {
+        use strum::IntoEnumIterator;
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        for channel in ShareChannel::iter() {
+            let link = builder().channel(channel).build(&key_pair.sk).unwrap();
+            let parsed = link.to_string().parse::<SecretShareLink>().unwrap();
+            assert_eq!(parsed.channel, Some(channel));
+            assert!(parsed.identical_including_signature(&link));
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+
+            let query_url = link.to_query_url(&LinkOrigin::default()).unwrap();
+            let parsed = SecretShareLink::from_query_url(&query_url).unwrap();
+            assert_eq!(parsed.channel, Some(channel));
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+        }
+    }
+
+    #[test]
+    fn tampering_with_channel_breaks_signature() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = builder().channel(ShareChannel::Email).build(&key_pair.sk).unwrap();
+        let url = link.to_string();
+        let email = format!("#c={}", encode_channel(ShareChannel::Email));
+        assert!(url.contains(&email));
+
+        let flipped = reseal(&url.replace(&email, &format!("#c={}", encode_channel(ShareChannel::Api))));
+        let parsed = flipped.parse::<SecretShareLink>().unwrap();
+        assert_eq!(parsed.channel, Some(ShareChannel::Api));
+        assert!(parsed.verify_signature(key_pair.pk).is_err());
+
+        let dropped = reseal(&url.replace(&email, ""));
+        assert!(dropped.parse::<SecretShareLink>().unwrap().verify_signature(key_pair.pk).is_err());
+
+        // Unknown bytes are an error, not Other.
+        let unknown = reseal(&url.replace(&email, &format!("#c={}", general_purpose::URL_SAFE_NO_PAD.encode([0x2a]))));
+        assert!(matches!(
+            unknown.parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::UnknownChannel(UnknownShareChannel(0x2a)))
+        ));
+        let too_long = reseal(&url.replace(&email, &format!("#c={}", general_purpose::URL_SAFE_NO_PAD.encode([1, 1]))));
+        assert!(matches!(
+            too_long.parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::InvalidChannel)
+        ));
+    }
+
+    #[test]
+    fn truncated_links_fail_the_checksum() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        for link in [full_link(&key_pair), builder().build(&key_pair.sk).unwrap()] {
+            let url = link.to_string();
+            // Once the whole "v2.<len>" marker is left, the hint is the length of the complete link.
+            let marker_end = url.find("#v2.").unwrap() + 1 + url.split('#').nth(1).unwrap().len();
+            for cut in 0..url.len() {
+                let error = match url[..cut].parse::<SecretShareLink>() {
+                    Ok(_) => panic!("{} parsed", &url[..cut]),
+                    Err(error) => error,
+                };
+                assert!(!matches!(error, SecretShareLinkParsingError::Signature(_)), "{}: {}", cut, error);
+                if cut >= marker_end {
+                    assert!(
+                        matches!(
+                            error,
+                            SecretShareLinkParsingError::ChecksumMismatch { expected_len_hint }
+                                if expected_len_hint == url.len()
+                        ),
+                        "{}: {}",
+                        cut,
+                        error
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mistyped_links_fail_the_checksum() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = full_link(&key_pair);
+        let url = link.to_string();
+        let fields_start = url.find("#v2.").unwrap() + 1;
+        for index in fields_start + CHECKED_FORMAT_MARKER.len()..url.len() {
+            let mut mistyped = url.clone().into_bytes();
+            mistyped[index] = match mistyped[index] {
+                b'A' => b'B',
+                _ => b'A',
+            };
+            let mistyped = String::from_utf8(mistyped).unwrap();
+            assert!(
+                matches!(
+                    mistyped.parse::<SecretShareLink>(),
+                    Err(SecretShareLinkParsingError::ChecksumMismatch { .. })
+                        | Err(SecretShareLinkParsingError::InvalidVersionFormat)
+                ),
+                "{}",
+                index
+            );
+        }
+        let error = url.replacen("#AAAA", "#AAAB", 1).parse::<SecretShareLink>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Link is truncated or mistyped, it should be {} characters long", url.len())
+        );
+
+        // The checksum is not a secret, a recomputed one passes and the signature catches the edit.
+        let permission = |bits: u32| format!("#{}#", general_purpose::URL_SAFE_NO_PAD.encode(bits.to_be_bytes()));
+        let widened = url.replacen(&permission(link.permission_bits()), &permission(0xff), 1);
+        assert_ne!(widened, url);
+        let resealed = reseal(&widened);
+        assert!(resealed.parse::<SecretShareLink>().unwrap().verify_signature(key_pair.pk).is_err());
+    }
+
+    #[test]
+    fn legacy_links_have_no_checksum() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = full_link(&key_pair);
+        let url = link.to_string();
+        let marker = url.split('#').nth(1).unwrap();
+        let rest = &url[url.find(marker).unwrap() + marker.len() + 1..];
+        assert_eq!(marker, format!("v2.{}", rest.len()));
+        assert!(url.rsplit('#').next().unwrap().starts_with("ck="));
+        assert_eq!(url.rsplit('#').next().unwrap().len(), CHECKSUM_SEGMENT_LEN - 1);
+
+        let legacy = legacy_url(&link);
+        assert!(!legacy.contains("#v2.") && !legacy.contains("#ck="));
+        let parsed = legacy.parse::<SecretShareLink>().unwrap();
+        assert!(parsed.identical_including_signature(&link));
+        assert_eq!(parsed.to_string(), url);
+        // Legacy links are only checked by their signature.
+        assert!(matches!(
+            legacy[..legacy.len() - 3].parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::Base64Decoding(_) | SecretShareLinkParsingError::Signature(_))
+        ));
+        let bad_marker = url.replacen("#v2.", "#v2.+", 1);
+        assert!(matches!(
+            bad_marker.parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::InvalidVersionFormat)
+        ));
+    }
+
+    // Created before channels existed, must keep parsing and verifying.
+    const CHANNEL_LESS_LINK: &str = "https://bucketdrive.co/api/v1/share/6f1c2a4e-0b7d-4c1e-9a3f-2d5e8b7c1a90/b3e4f5a6-7c8d-4e9f-a0b1-c2d3e4f5a6b7#BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc#AAAAAw#AAAAAHDb2IA#r=ChssPU5fSmuMfZ4PGis8TQ#QAiemhyr76q1c0mv66iakbSQuy0cDuUM2_Qhh29_zJH1wXvk6Hjh45iE4GMTyM6iD6rAZmhJm97ZtMoRqGxqBw";
+
+    #[test]
+    fn channel_less_links_still_verify() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = CHANNEL_LESS_LINK.parse::<SecretShareLink>().unwrap();
+        assert_eq!(link.channel, None);
+        assert_eq!(link.verify_signature(key_pair.pk), Ok(()));
+        assert_eq!(legacy_url(&link), CHANNEL_LESS_LINK);
+        assert!(link.to_string().parse::<SecretShareLink>().unwrap().identical_including_signature(&link));
+    }
+
+    #[test]
+    fn signature_determinism() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let clock = FixedClock(OffsetDateTime::from_unix_timestamp(1_717_200_000).unwrap());
+        let template = builder();
+        let sign = |determinism| {
+            template
+                .clone()
+                .signature_determinism(determinism)
+                .build_with_clock(&key_pair.sk, &clock)
+                .unwrap()
+        };
+
+        let deterministic = sign(SignatureDeterminism::Deterministic);
+        assert!(deterministic.identical_including_signature(&sign(SignatureDeterminism::Deterministic)));
+        let default = template.clone().build_with_clock(&key_pair.sk, &clock).unwrap();
+        assert!(deterministic.identical_including_signature(&default));
+        assert_eq!(deterministic.verify_signature(key_pair.pk), Ok(()));
+        let new = |key_pair: &ed25519_compact::KeyPair| {
+            let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&[7; 32]);
+            let permission = BucketSharePermissionFlags::READ;
+            SecretShareLink::new(uuid::Uuid::nil(), uuid::Uuid::nil(), bucket_key, permission, None, &key_pair.sk)
+        };
+        assert!(new(&key_pair).identical_including_signature(&new(&key_pair)));
+
+        let randomized = sign(SignatureDeterminism::Randomized);
+        let again = sign(SignatureDeterminism::Randomized);
+        assert_ne!(randomized.signature, again.signature);
+        assert_ne!(randomized.signature, deterministic.signature);
+        for link in [&randomized, &again] {
+            assert_eq!(link.verify_signature(key_pair.pk), Ok(()));
+            assert_eq!(*link, deterministic);
+            let parsed = link.to_string().parse::<SecretShareLink>().unwrap();
+            assert!(parsed.identical_including_signature(link));
+        }
+    }
+
+    #[test]
+    fn max_total_bytes_round_trip() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        for cap in [0, 1, 5_000_000_000, u64::MAX] {
+            let link = builder().max_total_bytes(StorageQuota::from_bytes(cap)).build(&key_pair.sk).unwrap();
+            let parsed = link.to_string().parse::<SecretShareLink>().unwrap();
+            assert_eq!(parsed.max_total_bytes, Some(StorageQuota::from_bytes(cap)));
+            assert!(parsed.identical_including_signature(&link));
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+
+            let query_url = link.to_query_url(&LinkOrigin::default()).unwrap();
+            let parsed = SecretShareLink::from_query_url(&query_url).unwrap();
+            assert_eq!(parsed.max_total_bytes, Some(StorageQuota::from_bytes(cap)));
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+        }
+        // Old links without the field keep parsing and verifying.
+        let link = CHANNEL_LESS_LINK.parse::<SecretShareLink>().unwrap();
+        assert_eq!(link.max_total_bytes, None);
+        assert_eq!(link.verify_signature(key_pair.pk), Ok(()));
+    }
+
+    #[test]
+    fn tampering_with_max_total_bytes_breaks_signature() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let cap = StorageQuota::from_bytes(1_000_000);
+        let link = builder().max_total_bytes(cap).build(&key_pair.sk).unwrap();
+        let url = link.to_string();
+        let field = format!("#mb={}", encode_byte_count(cap));
+        assert!(url.contains(&field));
+
+        let raised =
+            reseal(&url.replace(&field, &format!("#mb={}", encode_byte_count(StorageQuota::from_bytes(1_000_001)))));
+        let parsed = raised.parse::<SecretShareLink>().unwrap();
+        assert_eq!(parsed.max_total_bytes, Some(StorageQuota::from_bytes(1_000_001)));
+        assert!(parsed.verify_signature(key_pair.pk).is_err());
+
+        let dropped = reseal(&url.replace(&field, ""));
+        assert!(dropped.parse::<SecretShareLink>().unwrap().verify_signature(key_pair.pk).is_err());
+
+        let short_cap = format!("#mb={}", general_purpose::URL_SAFE_NO_PAD.encode([1, 2, 3]));
+        let short = reseal(&url.replace(&field, &short_cap));
+        assert!(matches!(
+            short.parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::InvalidMaxTotalBytes)
+        ));
+        let duplicate = reseal(&url.replace(&field, &format!("{}{}", field, field)));
+        assert!(matches!(
+            duplicate.parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::InvalidField(_))
+        ));
+    }
+
+    #[test]
+    fn signature_verification() {
+        let user_id = uuid::Uuid::new_v4();
+        let bucket_id = uuid::Uuid::new_v4();
+        // Create a SecretKey and corresponding PublicKey for the signing process
+        let bytes = random::<[u8; 32]>();
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(bytes));
+
+        let bucket_key_bytes = rand::random::<[u8; 32]>();
+        let bucket_key = aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key_bytes);
+        let permission = BucketSharePermissionFlags::VIEW; //You need to replace ValorA
+        let expires = Some(ExpiryTimestamp::try_from(OffsetDateTime::now_utc()).unwrap());
+
+        let link = SecretShareLink::new(
+            user_id,
+            bucket_id,
+            *bucket_key,
+            permission,
+            expires,
             &key_pair.sk,
         );
 
         assert_eq!(link.verify_signature(key_pair.pk), Ok(()));
     }
+
+    #[test]
+    fn redacted_link_does_not_leak_secrets() {
+        for _ in 0..100 {
+            let key_pair = ed25519_compact::KeyPair::generate();
+            let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&random::<[u8; 32]>());
+            let link = SecretShareLink::new(
+                uuid::Uuid::new_v4(),
+                uuid::Uuid::new_v4(),
+                bucket_key,
+                BucketSharePermissionFlags::from_bits_truncate(random()),
+                Some(ExpiryTimestamp::try_from(OffsetDateTime::now_utc()).unwrap()),
+                &key_pair.sk,
+            );
+            let redacted = link.redacted_display().to_string();
+            for secret in [
+                general_purpose::URL_SAFE_NO_PAD.encode(bucket_key.as_slice()),
+                general_purpose::URL_SAFE_NO_PAD.encode(link.signature.as_slice()),
+                general_purpose::STANDARD.encode(bucket_key.as_slice()),
+            ] {
+                assert!(!crate::util::shares_substring(&redacted, &secret, 12));
+            }
+            assert!(redacted.contains(&link.bucket_id.to_string()));
+        }
+    }
+
+    #[test]
+    fn debug_does_not_leak_secrets() {
+        // Any long base64 or hex run, ids are replaced before matching since they are fine to log.
+        let secret_like = regex::Regex::new("[A-Za-z0-9+/=_-]{16,}").unwrap();
+        for _ in 0..50 {
+            let key_pair = ed25519_compact::KeyPair::generate();
+            let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&random::<[u8; 32]>());
+            let link = SecretShareLink::builder(uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), bucket_key)
+                .permission(BucketSharePermissionFlags::from_bits_truncate(random()))
+                .expires(ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(1)).unwrap())
+                .prefixes(vec!["/photos/".parse().unwrap()])
+                .channel(ShareChannel::Email)
+                .recipient(uuid::Uuid::new_v4())
+                .build(&key_pair.sk)
+                .unwrap();
+            for debug in [format!("{:?}", link), format!("{:#?}", link)] {
+                let mut debug = debug;
+                for id in [link.user_id, link.bucket_id, link.recipient.unwrap()] {
+                    debug = debug.replace(&id.to_string(), "<id>");
+                }
+                // The one field name long enough to look like a secret.
+                let debug = debug.replace("unknown_permission_bits", "<field>");
+                assert!(!secret_like.is_match(&debug), "{}", debug);
+            }
+            let debug = format!("{:?}", link);
+            assert!(debug.contains(&format!("bucket_key: <32 bytes, fp:{}>", Fingerprint::of(bucket_key.as_slice()))));
+            assert!(debug.contains("signature: <64 bytes>"));
+            assert!(debug.contains(&link.user_id.to_string()));
+        }
+        let link = builder().permission(BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ).build(
+            &ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32])).sk,
+        );
+        assert!(format!("{:?}", link.unwrap()).contains("permission: VIEW | READ"));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_logs_rejections_without_secrets() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = SecretShareLink::new(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<Aes256Gcm>::from_slice(&random::<[u8; 32]>()),
+            BucketSharePermissionFlags::READ,
+            None,
+            &key_pair.sk,
+        );
+        let url = link.to_string();
+        let encoded_key = general_purpose::URL_SAFE_NO_PAD.encode(link.bucket_key.as_slice());
+        let encoded_signature = general_purpose::URL_SAFE_NO_PAD.encode(link.signature.as_slice());
+        tracing::subscriber::with_default(subscriber, || {
+            let _ = SecretShareLink::new(
+                link.user_id,
+                link.bucket_id,
+                link.bucket_key,
+                link.permission,
+                None,
+                &key_pair.sk,
+            );
+            assert!(url.parse::<SecretShareLink>().is_ok());
+            // Permission segment cut short, the checksum catches it first unless the link is resealed.
+            let permission = general_purpose::URL_SAFE_NO_PAD.encode(link.permission.bits().to_be_bytes());
+            let broken = url.replace(&format!("#{}#", permission), "#AA#");
+            assert!(broken.parse::<SecretShareLink>().is_err());
+            assert!(reseal(&broken).parse::<SecretShareLink>().is_err());
+            let other_key = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([7u8; 32]));
+            assert!(link.verify_signature(other_key.pk).is_err());
+            assert!(format!("https://{}{}/AAAA", DOMAIN_URL, crate::util::SHARE_PATH_URL)
+                .parse::<crate::share_link::ShareLink>()
+                .is_err());
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let rejections = [
+            "rejected secret share link error=\"ChecksumMismatch\" segment=4 role=checksum",
+            "rejected secret share link error=\"InvalidPermission\" segment=2 role=permissions",
+        ];
+        for rejection in rejections {
+            assert!(output.contains(rejection), "{}", output);
+        }
+        assert!(output.contains("rejected secret share link signature error=\"InvalidSignature\""), "{}", output);
+        assert!(output.contains(&format!("bucket_id={}", link.bucket_id)), "{}", output);
+        assert!(output.contains("rejected share link error=\"InvalidTokenLength\" segment=0"), "{}", output);
+        assert!(!output.contains(&encoded_key), "{}", output);
+        assert!(!output.contains(&encoded_signature), "{}", output);
+    }
+
+    // A newer issuer adds a permission bit this version does not know.
+    #[test]
+    fn unknown_permission_bits_round_trip_and_verify() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let mut newer = SecretShareLink::unsigned(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            *aes_gcm::Key::<Aes256Gcm>::from_slice(&random::<[u8; 32]>()),
+        );
+        newer.permission = BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ;
+        newer.unknown_permission_bits = 1 << 30;
+        let newer = newer.signed(&key_pair.sk);
+
+        for url in [newer.to_string(), newer.to_query_url(&LinkOrigin::default()).unwrap().to_string()] {
+            let parsed = match SecretShareLinkForm::of(&url.parse().unwrap()) {
+                SecretShareLinkForm::Fragment => url.parse::<SecretShareLink>().unwrap(),
+                SecretShareLinkForm::Query => SecretShareLink::from_query_url(&url.parse().unwrap()).unwrap(),
+            };
+            assert_eq!(parsed.permission_bits(), (1 << 30) | 0b11);
+            assert_eq!(parsed.unknown_permission_bits, 1 << 30);
+            assert_eq!(parsed.effective_known(), BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ);
+            assert!(parsed.identical_including_signature(&newer));
+            assert!(parsed.verify_signature(key_pair.pk).is_ok());
+        }
+        assert_eq!(newer.to_string().parse::<SecretShareLink>().unwrap().to_string(), newer.to_string());
+
+        // Dropping the unknown bit changes the signed payload.
+        let mut stripped = newer.to_string().parse::<SecretShareLink>().unwrap();
+        stripped.unknown_permission_bits = 0;
+        assert!(stripped.verify_signature(key_pair.pk).is_err());
+    }
+
+    #[test]
+    fn diagnostics_pinpoint_the_corrupted_segment() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = full_link(&key_pair);
+        let url: url::Url = link.to_string().parse().unwrap();
+        let (result, diagnostics) = SecretShareLink::parse_with_diagnostics(&url);
+        assert!(result.is_ok());
+        assert_eq!(diagnostics.failed_segment(), None);
+        assert_eq!(diagnostics.error, None);
+        let roles = diagnostics.segments.iter().map(|segment| segment.role).collect::<Vec<_>>();
+        assert_eq!(
+            roles,
+            [
+                SegmentRole::UserId,
+                SegmentRole::BucketId,
+                SegmentRole::Version,
+                SegmentRole::Checksum,
+                SegmentRole::Key,
+                SegmentRole::Permissions,
+                SegmentRole::Expiry,
+                SegmentRole::NotBefore,
+                SegmentRole::Prefixes,
+                SegmentRole::IssuedAt,
+                SegmentRole::Recipient,
+                SegmentRole::Signature,
+            ]
+        );
+
+        let path = url.path().rsplitn(3, '/').collect::<Vec<_>>();
+        let fragments = url.fragment().unwrap().split('#').collect::<Vec<_>>();
+        let with_path = |index: usize, value: &str| {
+            let mut ids = [path[1], path[0]];
+            ids[index] = value;
+            let mut broken = url.clone();
+            broken.set_path(&format!("{}/{}/{}", path[2], ids[0], ids[1]));
+            broken
+        };
+        // Resealed so the checksum matches and the corrupted field itself fails.
+        let with_fragment = |index: usize, value: &str| {
+            let mut parts = fragments.clone();
+            parts[index] = value;
+            let mut broken = url.clone();
+            broken.set_fragment(Some(&parts.join("#")));
+            reseal(broken.as_str()).parse::<url::Url>().unwrap()
+        };
+        let checksum_index = fragments.len() - 1;
+        let signature_index = checksum_index - 1;
+        let mistyped = url.as_str().replacen("#AAAA", "#AAAB", 1).parse().unwrap();
+        let cases = [
+            (with_path(0, "not-a-uuid"), UrlPart::Path, 0, SegmentRole::UserId),
+            (with_path(1, "not-a-uuid"), UrlPart::Path, 1, SegmentRole::BucketId),
+            (mistyped, UrlPart::Fragment, checksum_index, SegmentRole::Checksum),
+            (with_fragment(1, "AAAA"), UrlPart::Fragment, 1, SegmentRole::Key),
+            (with_fragment(2, "AA"), UrlPart::Fragment, 2, SegmentRole::Permissions),
+            (with_fragment(3, "!!"), UrlPart::Fragment, 3, SegmentRole::Expiry),
+            (with_fragment(4, "nb=!!"), UrlPart::Fragment, 4, SegmentRole::NotBefore),
+            (with_fragment(6, "ia=!!"), UrlPart::Fragment, 6, SegmentRole::IssuedAt),
+            (with_fragment(7, "r=!!"), UrlPart::Fragment, 7, SegmentRole::Recipient),
+            (with_fragment(signature_index, "AAAA"), UrlPart::Fragment, signature_index, SegmentRole::Signature),
+        ];
+        let encoded_key = general_purpose::URL_SAFE_NO_PAD.encode(link.bucket_key.as_slice());
+        for (broken, part, index, role) in cases {
+            let (result, diagnostics) = SecretShareLink::parse_with_diagnostics(&broken);
+            let error = result.unwrap_err();
+            let failed = diagnostics.failed_segment().unwrap();
+            assert_eq!((failed.part, failed.index, failed.role), (part, index, role), "{}", diagnostics);
+            assert_eq!(failed.error, Some(<&'static str>::from(&error)));
+            assert_eq!(diagnostics.error, failed.error);
+            // Every segment before the corrupted one decoded.
+            assert_eq!(diagnostics.segments.last(), Some(failed));
+
+            let shown = diagnostics.to_string();
+            assert!(shown.contains(&format!("{}[{}] {}: ", part, index, role)), "{}", shown);
+            assert!(!shown.contains(&encoded_key), "{}", shown);
+            assert!(!shown.contains(&link.user_id.to_string()), "{}", shown);
+            assert!(!shown.contains(fragments[signature_index]), "{}", shown);
+        }
+    }
+
+    // 2024-06-01T00:00:00Z.
+    fn issued_link(key_pair: &ed25519_compact::KeyPair) -> SecretShareLink {
+        let issued_at = OffsetDateTime::from_unix_timestamp(1_717_200_000).unwrap();
+        builder().build_with_clock(&key_pair.sk, &FixedClock(issued_at)).unwrap()
+    }
+
+    #[test]
+    fn issued_at_round_trip() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = issued_link(&key_pair);
+        assert_eq!(link.issued_at.map(|issued_at| issued_at.unix_timestamp()), Some(1_717_200_000));
+        assert!(link.to_string().contains("#ia="));
+        for parsed in [
+            link.to_string().parse::<SecretShareLink>().unwrap(),
+            SecretShareLink::from_query_url(&link.to_query_url(&LinkOrigin::default()).unwrap()).unwrap(),
+        ] {
+            assert!(parsed.identical_including_signature(&link));
+            assert!(parsed.verify_signature(key_pair.pk).is_ok());
+        }
+
+        // Links from before issue times were recorded still parse and verify.
+        let legacy = SecretShareLink::new(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            link.bucket_key,
+            BucketSharePermissionFlags::VIEW,
+            None,
+            &key_pair.sk,
+        );
+        let parsed = legacy.to_string().parse::<SecretShareLink>().unwrap();
+        assert_eq!(parsed.issued_at, None);
+        assert!(parsed.verify_signature(key_pair.pk).is_ok());
+        assert!(builder().build(&key_pair.sk).unwrap().issued_at.is_some());
+    }
+
+    #[test]
+    fn tampering_with_issued_at_breaks_signature() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = issued_link(&key_pair);
+        let encoded = encode_timestamp(link.issued_at.unwrap());
+        let later = encode_timestamp(ExpiryTimestamp::from_unix_timestamp(1_767_225_600).unwrap());
+        let url = link.to_string();
+
+        let moved = reseal(&url.replace(&format!("#ia={}", encoded), &format!("#ia={}", later)));
+        assert_ne!(moved, url);
+        assert!(moved.parse::<SecretShareLink>().unwrap().verify_signature(key_pair.pk).is_err());
+        let stripped = reseal(&url.replace(&format!("#ia={}", encoded), ""));
+        assert!(stripped.parse::<SecretShareLink>().unwrap().verify_signature(key_pair.pk).is_err());
+        let duplicated = reseal(&url.replace(&format!("#ia={}", encoded), &format!("#ia={}#ia={}", encoded, encoded)));
+        assert!(matches!(
+            duplicated.parse::<SecretShareLink>(),
+            Err(SecretShareLinkParsingError::InvalidField(_))
+        ));
+    }
+
+    #[test]
+    fn max_age_policy_boundaries() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42u8; 32]));
+        let link = issued_link(&key_pair);
+        let issued_at = link.issued_at.unwrap();
+        let year = time::Duration::days(365);
+        let at = |offset: time::Duration| issued_at.as_offset_date_time() + offset;
+
+        assert_eq!(link.age(at(time::Duration::hours(1))), Some(time::Duration::hours(1)));
+        assert_eq!(link.enforce_max_age(year, at(year - time::Duration::seconds(1))), Ok(()));
+        assert_eq!(
+            link.enforce_max_age(year, at(year)),
+            Err(SecretShareLinkVerifySignatureError::LinkTooOld { issued_at, max: year })
+        );
+
+        let policy = VerificationPolicy {
+            require_expiry: false,
+            max_age: Some(year),
+        };
+        assert_eq!(link.verify_with_policy(key_pair.pk, at(time::Duration::days(364)), &policy), Ok(()));
+        // The link never expires, max age still applies.
+        assert!(matches!(
+            link.verify_with_policy(key_pair.pk, at(year * 2), &policy),
+            Err(SecretShareLinkVerifySignatureError::LinkTooOld { .. })
+        ));
+        assert_eq!(link.verify_with_policy(key_pair.pk, at(year * 2), &VerificationPolicy::default()), Ok(()));
+
+        let require_expiry = VerificationPolicy {
+            require_expiry: true,
+            max_age: None,
+        };
+        assert_eq!(
+            link.verify_with_policy(key_pair.pk, at(time::Duration::ZERO), &require_expiry),
+            Err(SecretShareLinkVerifySignatureError::MissingExpiry)
+        );
+        let expiring = builder()
+            .expires(ExpiryTimestamp::try_from(at(time::Duration::days(30))).unwrap())
+            .build_with_clock(&key_pair.sk, &FixedClock(issued_at.as_offset_date_time()))
+            .unwrap();
+        assert_eq!(expiring.verify_with_policy(key_pair.pk, at(time::Duration::ZERO), &require_expiry), Ok(()));
+        // The stated expiry is checked before the policy.
+        assert!(matches!(
+            expiring.verify_with_policy(key_pair.pk, at(time::Duration::days(30)), &policy),
+            Err(SecretShareLinkVerifySignatureError::Expired { .. })
+        ));
+
+        let legacy = SecretShareLink::new(link.user_id, link.bucket_id, link.bucket_key, link.permission, None, &key_pair.sk);
+        assert_eq!(legacy.age(at(time::Duration::ZERO)), None);
+        assert_eq!(
+            legacy.verify_with_policy(key_pair.pk, at(time::Duration::ZERO), &policy),
+            Err(SecretShareLinkVerifySignatureError::UnknownIssueTime)
+        );
+    }
+
+    #[test]
+    fn legacy_expiry_encoding_is_accepted() {
+        let now = OffsetDateTime::now_utc();
+        let legacy = bincode::serialize(&now).unwrap();
+        assert_eq!(decode_expiry(&legacy), ExpiryTimestamp::try_from(now));
+
+        let expiry = ExpiryTimestamp::try_from(now).unwrap();
+        assert_eq!(decode_expiry(&expiry.unix_timestamp().to_be_bytes()), Ok(expiry));
+        assert!(decode_expiry(&0i64.to_be_bytes()).is_err());
+        assert!(decode_expiry(&[1, 2, 3]).is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,325 @@
+#![cfg(feature = "secret_share_link")]
+
+use std::collections::BTreeMap;
+
+use aes_gcm::Aes256Gcm;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use time::OffsetDateTime;
+
+use crate::UserId;
+use crate::bucket_guid::BucketGuid;
+use crate::expiry::ExpiryTimestamp;
+use crate::keyring::{Keyring, KeyringError};
+use crate::secret_share_link::{
+    hash_secret_share_link_prefix, hash_secret_share_link_recipient, SecretShareLink, SecretShareLinkParsingError,
+    SecretShareLinkVerifySignatureError,
+};
+use crate::share_link::{BucketSharePermissionFlags, ShareLinkToken};
+use crate::util::LinkOrigin;
+
+/*
+* Issues one signed secret share link per recipient for the same bucket key.
+* Every link is signed separately, but the shared part of the signed hash is only computed once.
+* Signatures are deterministic like SecretShareLink::new, duplicate recipients get identical links.
+*/
+pub struct SecretShareLinkBatch;
+
+impl SecretShareLinkBatch {
+    pub fn issue(
+        bucket: BucketGuid,
+        bucket_key: aes_gcm::Key<Aes256Gcm>,
+        base_permissions: BucketSharePermissionFlags,
+        expires: Option<ExpiryTimestamp>,
+        recipients: &[UserId],
+        secret_key: &ed25519_compact::SecretKey,
+    ) -> Vec<SecretShareLink> {
+        let mut template = SecretShareLink::unsigned(bucket.user_id, bucket.bucket_id, bucket_key);
+        template.permission = base_permissions;
+        template.expires = expires;
+        let prefix = hash_secret_share_link_prefix::<Sha3_256>(&template);
+        recipients
+            .iter()
+            .map(|recipient| {
+                let mut hasher = prefix.clone();
+                hash_secret_share_link_recipient(&mut hasher, Some(*recipient));
+                SecretShareLink {
+                    recipient: Some(*recipient),
+                    signature: secret_key.sign(hasher.finalize(), None),
+                    ..template.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+// Maps every recipient of a batch to the token of its link, so the server can revoke links individually.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BatchManifest {
+    pub bucket: BucketGuid,
+    pub tokens: BTreeMap<UserId, ShareLinkToken>,
+}
+
+impl BatchManifest {
+    // Links without a recipient are skipped.
+    pub fn from_links(bucket: BucketGuid, links: &[SecretShareLink]) -> Self {
+        let tokens = links
+            .iter()
+            .filter_map(|link| Some((link.recipient?, link.share_link_token())))
+            .collect();
+        Self { bucket, tokens }
+    }
+
+    pub fn token_for(&self, recipient: &UserId) -> Option<&ShareLinkToken> {
+        self.tokens.get(recipient)
+    }
+}
+
+// Result for one line of an imported batch. kind is the parsing error variant name, e.g. "InvalidBucketKey".
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LinkValidationOutcome {
+    Valid { token: ShareLinkToken },
+    ParseError { kind: &'static str },
+    SignatureInvalid,
+    Expired,
+    NotYetValid,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct BatchValidationSummary {
+    pub valid: usize,
+    pub parse_errors: usize,
+    pub signature_invalid: usize,
+    pub expired: usize,
+    pub not_yet_valid: usize,
+}
+
+// outcomes[i] belongs to the i-th input line, with or without the rayon feature.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BatchValidationReport {
+    pub outcomes: Vec<LinkValidationOutcome>,
+    pub summary: BatchValidationSummary,
+}
+
+/*
+* Parses and verifies links imported from outside, e.g. a CSV of links re-registered after a database restore.
+* Each line is parsed in place, only the decoded link is allocated. Lines that are not links are reported, never a panic.
+* With the rayon feature the lines are collected first and verified in parallel.
+*/
+pub fn validate_links_batch(
+    urls: impl Iterator<Item = String>,
+    keyring: &Keyring,
+    now: OffsetDateTime,
+) -> BatchValidationReport {
+    #[cfg(feature = "rayon")]
+    let outcomes: Vec<_> = {
+        use rayon::prelude::*;
+        urls.collect::<Vec<_>>().par_iter().map(|url| validate_link(url, keyring, now)).collect()
+    };
+    #[cfg(not(feature = "rayon"))]
+    let outcomes: Vec<_> = urls.map(|url| validate_link(&url, keyring, now)).collect();
+
+    let mut summary = BatchValidationSummary::default();
+    for outcome in &outcomes {
+        let count = match outcome {
+            LinkValidationOutcome::Valid { .. } => &mut summary.valid,
+            LinkValidationOutcome::ParseError { .. } => &mut summary.parse_errors,
+            LinkValidationOutcome::SignatureInvalid => &mut summary.signature_invalid,
+            LinkValidationOutcome::Expired => &mut summary.expired,
+            LinkValidationOutcome::NotYetValid => &mut summary.not_yet_valid,
+        };
+        *count += 1;
+    }
+    BatchValidationReport { outcomes, summary }
+}
+
+fn validate_link(url: &str, keyring: &Keyring, now: OffsetDateTime) -> LinkValidationOutcome {
+    let parsed = url::Url::parse(url.trim())
+        .map_err(SecretShareLinkParsingError::from)
+        .and_then(|url| SecretShareLink::from_url_with_origin(&url, &LinkOrigin::default()));
+    let link = match parsed {
+        Ok(link) => link,
+        Err(error) => return LinkValidationOutcome::ParseError { kind: (&error).into() },
+    };
+    match keyring.verify_link(&link, now) {
+        Ok(()) => LinkValidationOutcome::Valid {
+            token: link.share_link_token(),
+        },
+        Err(KeyringError::Link(SecretShareLinkVerifySignatureError::Expired { .. })) => LinkValidationOutcome::Expired,
+        Err(KeyringError::Link(SecretShareLinkVerifySignatureError::NotYetValid { .. })) => {
+            LinkValidationOutcome::NotYetValid
+        }
+        // No key verifies the link at now, including keys that were retired before it.
+        Err(_) => LinkValidationOutcome::SignatureInvalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::{Duration, Instant};
+
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::clock::FixedClock;
+
+    fn issue(recipients: &[UserId], key_pair: &ed25519_compact::KeyPair) -> (BucketGuid, Vec<SecretShareLink>) {
+        let bucket = BucketGuid::generate();
+        let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&rand::random::<[u8; 32]>());
+        let expires = ExpiryTimestamp::try_from(OffsetDateTime::now_utc() + time::Duration::days(7)).unwrap();
+        let links = SecretShareLinkBatch::issue(
+            bucket,
+            bucket_key,
+            BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            Some(expires),
+            recipients,
+            &key_pair.sk,
+        );
+        (bucket, links)
+    }
+
+    #[test]
+    fn batch_links_verify_independently() {
+        let key_pair = ed25519_compact::KeyPair::generate();
+        let recipients = (0..10).map(|_| uuid::Uuid::new_v4()).collect::<Vec<_>>();
+        let (bucket, links) = issue(&recipients, &key_pair);
+
+        assert_eq!(links.len(), recipients.len());
+        for (link, recipient) in links.iter().zip(&recipients) {
+            assert_eq!(link.recipient, Some(*recipient));
+            assert_eq!(link.verify_signature(key_pair.pk), Ok(()));
+
+            let url: url::Url = link.clone().try_into().unwrap();
+            let parsed = SecretShareLink::try_from(url).unwrap();
+            assert_eq!(parsed.recipient, Some(*recipient));
+            assert_eq!(parsed.verify_signature(key_pair.pk), Ok(()));
+        }
+
+        let mut tampered = links[0].clone();
+        tampered.recipient = Some(recipients[1]);
+        assert!(tampered.verify_signature(key_pair.pk).is_err());
+
+        let manifest = BatchManifest::from_links(bucket, &links);
+        let tokens = manifest.tokens.values().collect::<HashSet<_>>();
+        assert_eq!(tokens.len(), recipients.len());
+        assert_eq!(manifest.token_for(&recipients[3]), Some(&links[3].share_link_token()));
+        assert_eq!(manifest.token_for(&uuid::Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn batch_issuance_of_10k_links_is_fast() {
+        let key_pair = ed25519_compact::KeyPair::generate();
+        let recipients = (0..10_000).map(|_| uuid::Uuid::new_v4()).collect::<Vec<_>>();
+        /*
+        * Signing dominates, so batch issuance is compared against signing 10k messages directly. Both sides run under
+        * the same load and the best of three runs is kept, a wall-clock limit would fail on slow or loaded machines.
+        */
+        let messages = (0..10_000u32).map(|index| index.to_be_bytes()).collect::<Vec<_>>();
+        let (mut fastest_batch, mut fastest_signing) = (Duration::MAX, Duration::MAX);
+        let mut links = Vec::new();
+        for _ in 0..3 {
+            let started = Instant::now();
+            links = issue(&recipients, &key_pair).1;
+            fastest_batch = fastest_batch.min(started.elapsed());
+
+            let started = Instant::now();
+            for message in &messages {
+                key_pair.sk.sign(message, None);
+            }
+            fastest_signing = fastest_signing.min(started.elapsed());
+        }
+        assert!(
+            fastest_batch < fastest_signing * 2,
+            "batch took {:?}, signing alone {:?}",
+            fastest_batch,
+            fastest_signing
+        );
+        assert_eq!(links.len(), 10_000);
+        assert_eq!(links[9_999].verify_signature(key_pair.pk), Ok(()));
+    }
+
+    // 1000 lines cycling through valid, expired, foreign key, not yet valid, garbage and wrong host.
+    fn mixed_batch(key_pair: &ed25519_compact::KeyPair) -> (Vec<String>, Keyring, OffsetDateTime) {
+        let now = OffsetDateTime::from_unix_timestamp(1_717_200_000).unwrap();
+        let at = |days: i64| ExpiryTimestamp::try_from(now + time::Duration::days(days)).unwrap();
+        let foreign = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([2u8; 32]));
+        let issued = FixedClock(now - time::Duration::days(2));
+        let lines = (0..1000u32)
+            .map(|index| {
+                let bucket_key = *aes_gcm::Key::<Aes256Gcm>::from_slice(&[index as u8; 32]);
+                let link = |expires, not_before: Option<ExpiryTimestamp>, sk: &ed25519_compact::SecretKey| {
+                    let builder = SecretShareLink::builder(uuid::Uuid::from_u128(index.into()), uuid::Uuid::nil(), bucket_key)
+                        .permission(BucketSharePermissionFlags::READ)
+                        .expires(expires);
+                    let builder = match not_before {
+                        Some(not_before) => builder.not_before(not_before),
+                        None => builder,
+                    };
+                    builder.build_with_clock(sk, &issued).unwrap().to_string()
+                };
+                match index % 6 {
+                    0 => link(at(7), None, &key_pair.sk),
+                    1 => link(at(-1), None, &key_pair.sk),
+                    2 => link(at(7), None, &foreign.sk),
+                    3 => link(at(7), Some(at(1)), &key_pair.sk),
+                    4 => ["", "not a url", "https://bucketdrive.co/api/v1/share/\u{0}#", "\u{1F600}\n"][index as usize / 6 % 4].to_string(),
+                    _ => link(at(7), None, &key_pair.sk).replace("bucketdrive.co", "example.com"),
+                }
+            })
+            .collect();
+        let keyring = Keyring::new(vec![crate::keyring::KeyringEntry {
+            key_id: "links-2024".parse().unwrap(),
+            public_key: *key_pair.pk,
+            valid_from: ExpiryTimestamp::MIN,
+            valid_until: None,
+        }])
+        .unwrap();
+        (lines, keyring, now)
+    }
+
+    #[test]
+    fn mixed_batch_counts_and_order() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([1u8; 32]));
+        let (lines, keyring, now) = mixed_batch(&key_pair);
+        let report = validate_links_batch(lines.clone().into_iter(), &keyring, now);
+        assert_eq!(
+            report.summary,
+            BatchValidationSummary {
+                valid: 167,
+                parse_errors: 332,
+                signature_invalid: 167,
+                expired: 167,
+                not_yet_valid: 167,
+            }
+        );
+        for (index, (outcome, line)) in report.outcomes.iter().zip(&lines).enumerate() {
+            let expected = match index % 6 {
+                0 => LinkValidationOutcome::Valid {
+                    token: SecretShareLink::try_from(url::Url::parse(line).unwrap()).unwrap().share_link_token(),
+                },
+                1 => LinkValidationOutcome::Expired,
+                2 => LinkValidationOutcome::SignatureInvalid,
+                3 => LinkValidationOutcome::NotYetValid,
+                4 => continue,
+                _ => LinkValidationOutcome::ParseError { kind: "InvalidHostDomain" },
+            };
+            assert_eq!(*outcome, expected, "line {}", index);
+        }
+        assert!(matches!(report.outcomes[4], LinkValidationOutcome::ParseError { kind: "UrlParse" }));
+
+        // Same report on every run, whether or not the rayon feature spreads the work over threads.
+        assert_eq!(validate_links_batch(lines.into_iter(), &keyring, now), report);
+        assert_eq!(validate_links_batch(std::iter::empty(), &keyring, now).summary, BatchValidationSummary::default());
+    }
+
+    #[test]
+    fn batch_validation_throughput() {
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([1u8; 32]));
+        let (lines, keyring, now) = mixed_batch(&key_pair);
+        let started = Instant::now();
+        let report = validate_links_batch(lines.into_iter(), &keyring, now);
+        assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+        assert_eq!(report.outcomes.len(), 1000);
+    }
+}
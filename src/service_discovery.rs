@@ -0,0 +1,270 @@
+#![cfg(feature = "secret_share_link")]
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::canonical_json::{to_canonical_vec, CanonicalJsonError};
+use crate::keyring::KeyId;
+use crate::util::HumanDuration;
+use crate::RegionCluster;
+
+// Prepended to the canonical JSON before the root key signs it, a bundle signature can never pass as a keyring one.
+const SIGNED_DISCOVERY_CONTEXT: &[u8] = b"bucketdrive-discovery-v1";
+
+/*
+* Where a region cluster is reached, published by the control plane and cached by gateways.
+* A record is fresh for ttl after published_at, signing_key_id names the keyring key the cluster signs links with.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ClusterRecord {
+    pub cluster: RegionCluster,
+    pub api_endpoint: url::Url,
+    pub storage_endpoints: Vec<url::Url>,
+    pub signing_key_id: KeyId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub published_at: OffsetDateTime,
+    pub ttl: HumanDuration,
+}
+
+impl ClusterRecord {
+    // Stale from published_at + ttl on.
+    pub fn is_stale(&self, now: OffsetDateTime) -> bool {
+        now >= self.published_at + self.ttl.as_duration()
+    }
+
+    fn endpoints(&self) -> impl Iterator<Item = &url::Url> {
+        std::iter::once(&self.api_endpoint).chain(&self.storage_endpoints)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EndpointPolicy {
+    HttpsOnly,
+    // Local and test setups without certificates, never for bundles gateways fetch.
+    AllowHttp,
+}
+
+#[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
+pub enum DiscoveryError {
+    #[error("cluster {0} has more than one record")]
+    DuplicateCluster(RegionCluster),
+    #[error("endpoint {endpoint} of cluster {cluster} is not https")]
+    InsecureEndpoint { cluster: RegionCluster, endpoint: String },
+    #[error("discovery bundle is not signed by the root key")]
+    RootSignatureInvalid,
+    #[error(transparent)]
+    CanonicalJson(#[from] CanonicalJsonError),
+}
+
+/*
+* Records of every cluster, at most one per cluster. JSON is an array of records, deserializing only accepts https
+* endpoints, bundles with http endpoints can only be built through new with AllowHttp.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<ClusterRecord>", into = "Vec<ClusterRecord>")]
+pub struct DiscoveryBundle(Vec<ClusterRecord>);
+
+impl DiscoveryBundle {
+    pub fn new(records: Vec<ClusterRecord>, policy: EndpointPolicy) -> Result<Self, DiscoveryError> {
+        for (index, record) in records.iter().enumerate() {
+            if records[..index].iter().any(|other| other.cluster == record.cluster) {
+                return Err(DiscoveryError::DuplicateCluster(record.cluster.clone()));
+            }
+            let insecure = record.endpoints().find(|endpoint| endpoint.scheme() != "https");
+            if let (EndpointPolicy::HttpsOnly, Some(endpoint)) = (policy, insecure) {
+                return Err(DiscoveryError::InsecureEndpoint {
+                    cluster: record.cluster.clone(),
+                    endpoint: endpoint.to_string(),
+                });
+            }
+        }
+        Ok(Self(records))
+    }
+
+    pub fn records(&self) -> &[ClusterRecord] {
+        &self.0
+    }
+
+    // Stale records are returned too, see valid_records.
+    pub fn lookup(&self, cluster: &RegionCluster) -> Option<&ClusterRecord> {
+        self.0.iter().find(|record| record.cluster == *cluster)
+    }
+
+    pub fn valid_records(&self, now: OffsetDateTime) -> impl Iterator<Item = &ClusterRecord> {
+        self.0.iter().filter(move |record| !record.is_stale(now))
+    }
+}
+
+impl TryFrom<Vec<ClusterRecord>> for DiscoveryBundle {
+    type Error = DiscoveryError;
+
+    fn try_from(records: Vec<ClusterRecord>) -> Result<Self, Self::Error> {
+        Self::new(records, EndpointPolicy::HttpsOnly)
+    }
+}
+
+impl From<DiscoveryBundle> for Vec<ClusterRecord> {
+    fn from(bundle: DiscoveryBundle) -> Self {
+        bundle.0
+    }
+}
+
+/*
+* Bundle signed by the keyring's offline root key, gateways fetch it over CDNs they do not trust.
+* The root key signs SIGNED_DISCOVERY_CONTEXT followed by the canonical JSON of the bundle.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SignedDiscoveryBundle {
+    pub bundle: DiscoveryBundle,
+    #[serde(with = "crate::util::signature_base64")]
+    pub signature: ed25519_compact::Signature,
+}
+
+impl SignedDiscoveryBundle {
+    pub fn sign(bundle: DiscoveryBundle, root_key: &ed25519_compact::SecretKey) -> Result<Self, DiscoveryError> {
+        let signature = root_key.sign(Self::signed_message(&bundle)?, Some(ed25519_compact::Noise::generate()));
+        Ok(Self { bundle, signature })
+    }
+
+    // The bundle is only handed out after its signature checks out.
+    pub fn verify(&self, root_public_key: &ed25519_compact::PublicKey) -> Result<&DiscoveryBundle, DiscoveryError> {
+        root_public_key
+            .verify(Self::signed_message(&self.bundle)?, &self.signature)
+            .map_err(|_| DiscoveryError::RootSignatureInvalid)?;
+        Ok(&self.bundle)
+    }
+
+    fn signed_message(bundle: &DiscoveryBundle) -> Result<Vec<u8>, DiscoveryError> {
+        Ok([SIGNED_DISCOVERY_CONTEXT, &to_canonical_vec(bundle)?].concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time::Duration;
+
+    use super::*;
+    use crate::BucketRegion;
+
+    fn key_pair(seed: u8) -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([seed; 32]))
+    }
+
+    fn cluster(cluster_id: u32) -> RegionCluster {
+        RegionCluster { region: BucketRegion::EuropeNorth(0), cluster_id }
+    }
+
+    fn record(cluster_id: u32, scheme: &str) -> ClusterRecord {
+        let host = format!("{}://eu-north-{}.bucketdrive.co", scheme, cluster_id);
+        ClusterRecord {
+            cluster: cluster(cluster_id),
+            api_endpoint: format!("{}/api", host).parse().unwrap(),
+            storage_endpoints: vec![format!("{}/storage/1", host).parse().unwrap()],
+            signing_key_id: "links-2024".parse().unwrap(),
+            published_at: datetime!(2024-06-01 0:00 UTC),
+            ttl: "5m".parse().unwrap(),
+        }
+    }
+
+    fn bundle() -> DiscoveryBundle {
+        DiscoveryBundle::new(vec![record(1, "https"), record(2, "https")], EndpointPolicy::HttpsOnly).unwrap()
+    }
+
+    #[test]
+    fn staleness_boundaries() {
+        let record = record(1, "https");
+        let expires = datetime!(2024-06-01 0:05 UTC);
+        assert!(!record.is_stale(datetime!(2024-06-01 0:00 UTC)));
+        assert!(!record.is_stale(expires - Duration::nanoseconds(1)));
+        assert!(record.is_stale(expires));
+
+        let mut fresh = self::record(2, "https");
+        fresh.published_at = expires;
+        let bundle = DiscoveryBundle::new(vec![record, fresh], EndpointPolicy::HttpsOnly).unwrap();
+        let valid = bundle.valid_records(expires).map(|record| record.cluster.clone()).collect::<Vec<_>>();
+        assert_eq!(valid, [cluster(2)]);
+        assert_eq!(bundle.valid_records(expires - Duration::SECOND).count(), 2);
+    }
+
+    #[test]
+    fn duplicate_clusters_are_rejected() {
+        let mut republished = record(1, "https");
+        republished.published_at += Duration::MINUTE;
+        let records = vec![record(1, "https"), record(2, "https"), republished];
+        let duplicate = DiscoveryBundle::new(records.clone(), EndpointPolicy::AllowHttp);
+        assert!(matches!(duplicate, Err(DiscoveryError::DuplicateCluster(cluster)) if cluster.cluster_id == 1));
+        let json = serde_json::to_string(&records).unwrap();
+        assert!(serde_json::from_str::<DiscoveryBundle>(&json).is_err());
+    }
+
+    #[test]
+    fn http_endpoints_need_the_dev_policy() {
+        let mut storage_only = record(2, "https");
+        storage_only.storage_endpoints.push("http://eu-north-2.bucketdrive.co/storage/2".parse().unwrap());
+        for insecure in [record(1, "http"), storage_only] {
+            let records = vec![insecure];
+            let rejected = DiscoveryBundle::new(records.clone(), EndpointPolicy::HttpsOnly);
+            let rejected_endpoint = match rejected {
+                Err(DiscoveryError::InsecureEndpoint { endpoint, .. }) => endpoint,
+                other => panic!("{:?}", other),
+            };
+            assert!(rejected_endpoint.starts_with("http:"), "{}", rejected_endpoint);
+            assert!(DiscoveryBundle::new(records.clone(), EndpointPolicy::AllowHttp).is_ok());
+            let json = serde_json::to_string(&records).unwrap();
+            assert!(serde_json::from_str::<DiscoveryBundle>(&json).is_err());
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let bundle = bundle();
+        assert_eq!(bundle.lookup(&cluster(2)), Some(&bundle.records()[1]));
+        assert_eq!(bundle.lookup(&cluster(3)), None);
+        let other_region = RegionCluster { region: BucketRegion::EuropeSouth(0), cluster_id: 1 };
+        assert_eq!(bundle.lookup(&other_region), None);
+        assert_eq!(DiscoveryBundle::new(Vec::new(), EndpointPolicy::HttpsOnly).unwrap().lookup(&cluster(1)), None);
+    }
+
+    #[test]
+    fn signature_verification() {
+        let bundle = bundle();
+        let root = key_pair(9);
+        let signed = SignedDiscoveryBundle::sign(bundle.clone(), &root.sk).unwrap();
+        assert_eq!(signed.verify(&root.pk).unwrap(), &bundle);
+        assert!(matches!(signed.verify(&key_pair(8).pk), Err(DiscoveryError::RootSignatureInvalid)));
+
+        let json = serde_json::to_string(&signed).unwrap();
+        let parsed = serde_json::from_str::<SignedDiscoveryBundle>(&json).unwrap();
+        assert_eq!(parsed.verify(&root.pk).unwrap(), &bundle);
+
+        // A CDN pointing a cluster at its own host.
+        let mut redirected = signed.clone();
+        let mut records = Vec::from(redirected.bundle);
+        records[1].api_endpoint = "https://attacker.example/api".parse().unwrap();
+        redirected.bundle = DiscoveryBundle::new(records, EndpointPolicy::HttpsOnly).unwrap();
+        assert!(matches!(redirected.verify(&root.pk), Err(DiscoveryError::RootSignatureInvalid)));
+
+        let mut extended = signed;
+        extended.bundle.0.push(record(3, "https"));
+        assert!(matches!(extended.verify(&root.pk), Err(DiscoveryError::RootSignatureInvalid)));
+    }
+
+    #[test]
+    fn serde() {
+        let record = record(1, "https");
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "cluster": "eu-north-1",
+                "api_endpoint": "https://eu-north-1.bucketdrive.co/api",
+                "storage_endpoints": ["https://eu-north-1.bucketdrive.co/storage/1"],
+                "signing_key_id": "links-2024",
+                "published_at": "2024-06-01T00:00:00Z",
+                "ttl": "5m",
+            })
+        );
+        assert_eq!(serde_json::from_value::<ClusterRecord>(json).unwrap(), record);
+    }
+}
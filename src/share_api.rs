@@ -0,0 +1,201 @@
+#![cfg(feature = "share_link")]
+
+use serde::{Deserialize, Serialize};
+
+use crate::bucket_guid::BucketGuid;
+use crate::expiry::{ExpiryPolicy, ExpiryPolicyError, ExpiryTimestamp};
+use crate::list_objects::Page;
+use crate::share_link::{BucketSharePermissionFlags, ShareChannel, ShareLinkToken};
+use crate::util::HumanDuration;
+
+/*
+* Bodies of the gateway's share link management endpoints, shared by the gateway and the CLI.
+* The wire format is pinned by the fixtures in testdata/share_api/, fields can be added but never renamed.
+*/
+
+// secret creates a SecretShareLink, otherwise a ShareLink. expires_in None creates a link that never expires.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CreateShareLinkRequest {
+    pub bucket: BucketGuid,
+    pub permissions: BucketSharePermissionFlags,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<HumanDuration>,
+    #[serde(default)]
+    pub secret: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<ShareChannel>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CreateShareLinkResponse {
+    pub url: String,
+    pub token: ShareLinkToken,
+    pub expires: Option<ExpiryTimestamp>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RevokeShareLinkRequest {
+    pub token: ShareLinkToken,
+}
+
+// Entry of ListShareLinksResponse. The URL is not listed, secret links can not be rebuilt without their key.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ShareLinkSummary {
+    pub token: ShareLinkToken,
+    pub bucket: BucketGuid,
+    pub permissions: BucketSharePermissionFlags,
+    pub created_at: ExpiryTimestamp,
+    pub expires: Option<ExpiryTimestamp>,
+    pub secret: bool,
+    pub channel: Option<ShareChannel>,
+}
+
+pub type ListShareLinksResponse = Page<ShareLinkSummary>;
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum CreateShareLinkError {
+    #[error("share link grants no permissions")]
+    NoPermissions,
+    #[error(transparent)]
+    Expiry(#[from] ExpiryPolicyError),
+}
+
+impl CreateShareLinkRequest {
+    // policy is the ExpiryPolicy of the bucket owner's plan.
+    pub fn validate(&self, policy: &ExpiryPolicy) -> Result<(), CreateShareLinkError> {
+        if self.permissions.is_empty() {
+            return Err(CreateShareLinkError::NoPermissions);
+        }
+        policy.check(self.expires_in)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREATE_REQUEST_FIXTURE: &str = include_str!("../testdata/share_api/create_request.json");
+    const CREATE_RESPONSE_FIXTURE: &str = include_str!("../testdata/share_api/create_response.json");
+    const REVOKE_REQUEST_FIXTURE: &str = include_str!("../testdata/share_api/revoke_request.json");
+    const LIST_RESPONSE_FIXTURE: &str = include_str!("../testdata/share_api/list_response.json");
+
+    fn bucket() -> BucketGuid {
+        BucketGuid::new(
+            "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+        )
+    }
+
+    fn create_request() -> CreateShareLinkRequest {
+        CreateShareLinkRequest {
+            bucket: bucket(),
+            permissions: BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            expires_in: Some("7d".parse().unwrap()),
+            secret: true,
+            channel: Some(ShareChannel::Email),
+        }
+    }
+
+    fn summary(token: u8, secret: bool) -> ShareLinkSummary {
+        ShareLinkSummary {
+            token: ShareLinkToken([token; 32]),
+            bucket: bucket(),
+            permissions: BucketSharePermissionFlags::READ,
+            created_at: "2024-06-01T12:00:00Z".parse().unwrap(),
+            expires: secret.then(|| "2024-06-08T12:00:00Z".parse().unwrap()),
+            secret,
+            channel: secret.then_some(ShareChannel::Qr),
+        }
+    }
+
+    fn assert_golden<T>(value: &T, fixture: &str)
+    where
+        T: Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(serde_json::to_string_pretty(value).unwrap() + "\n", fixture);
+        assert_eq!(&serde_json::from_str::<T>(fixture).unwrap(), value);
+    }
+
+    #[test]
+    fn golden_json() {
+        assert_golden(&create_request(), CREATE_REQUEST_FIXTURE);
+        assert_golden(
+            &CreateShareLinkResponse {
+                url: "https://bucketdrive.co/api/v1/share/AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE".to_string(),
+                token: ShareLinkToken([1; 32]),
+                expires: Some("2024-06-08T12:00:00Z".parse().unwrap()),
+            },
+            CREATE_RESPONSE_FIXTURE,
+        );
+        assert_golden(
+            &RevokeShareLinkRequest {
+                token: ShareLinkToken([1; 32]),
+            },
+            REVOKE_REQUEST_FIXTURE,
+        );
+        assert_golden(
+            &ListShareLinksResponse {
+                items: vec![summary(1, true), summary(2, false)],
+                next_cursor: Some("c2hhcmUtbGlua3MtMg".to_string()),
+            },
+            LIST_RESPONSE_FIXTURE,
+        );
+    }
+
+    #[test]
+    fn optional_request_fields_can_be_omitted() {
+        let json = concat!(
+            r#"{"bucket":{"user_id":"11111111-0000-4000-8000-000000000001","#,
+            r#""bucket_id":"22222222-0000-4000-8000-000000000002"},"permissions":"READ"}"#
+        );
+        let request = serde_json::from_str::<CreateShareLinkRequest>(json).unwrap();
+        assert_eq!(
+            request,
+            CreateShareLinkRequest {
+                bucket: bucket(),
+                permissions: BucketSharePermissionFlags::READ,
+                expires_in: None,
+                secret: false,
+                channel: None,
+            }
+        );
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.ends_with(r#""permissions":"READ","secret":false}"#), "{}", json);
+    }
+
+    #[test]
+    fn validation() {
+        let plan = ExpiryPolicy {
+            max_lifetime: Some("30d".parse().unwrap()),
+            require_expiry: true,
+        };
+        assert_eq!(create_request().validate(&plan), Ok(()));
+        assert_eq!(create_request().validate(&ExpiryPolicy::default()), Ok(()));
+
+        let never_expires = CreateShareLinkRequest {
+            expires_in: None,
+            ..create_request()
+        };
+        assert_eq!(
+            never_expires.validate(&plan),
+            Err(CreateShareLinkError::Expiry(ExpiryPolicyError::ExpiryRequired))
+        );
+        assert_eq!(never_expires.validate(&ExpiryPolicy::default()), Ok(()));
+
+        let too_long = CreateShareLinkRequest {
+            expires_in: Some("31d".parse().unwrap()),
+            ..create_request()
+        };
+        assert!(matches!(
+            too_long.validate(&plan),
+            Err(CreateShareLinkError::Expiry(ExpiryPolicyError::LifetimeTooLong { .. }))
+        ));
+
+        let nothing = CreateShareLinkRequest {
+            permissions: BucketSharePermissionFlags::empty(),
+            ..create_request()
+        };
+        assert_eq!(nothing.validate(&plan), Err(CreateShareLinkError::NoPermissions));
+    }
+}
@@ -0,0 +1,374 @@
+#![cfg(feature = "share_link")]
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use time::OffsetDateTime;
+
+use crate::bucket_description::BucketDescription;
+use crate::bucket_guid::BucketGuid;
+use crate::errors::{char_span, is_truncated, ParseFailure, SpannedParseError};
+use crate::expiry::ExpiryTimestamp;
+use crate::share_link::BucketSharePermissionFlags;
+use crate::util::truncate_input;
+use crate::UserId;
+
+pub const MAX_EMAIL_LEN: usize = 254;
+pub const MAX_EMAIL_LOCAL_PART_LEN: usize = 64;
+
+/*
+* Email address an invitation is sent to. Validation is pragmatic, the only real check is sending the mail:
+* surrounding whitespace is trimmed, then exactly one '@' with a non-empty local part of at most 64 bytes and a domain
+* of non-empty dot separated labels, at most 254 bytes in total, no whitespace or control characters anywhere.
+* The domain is lowercased, the local part keeps its case since mail servers may treat it case-sensitively.
+* Internationalized domains are kept as given, punycode is not decoded and unicode is not encoded.
+* Quoted local parts like "john doe"@example.com are valid RFC 5322 but rejected on purpose, hardly any provider accepts them
+* and they are a common way to smuggle '@' and spaces past other parsers.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct EmailAddress(String);
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum EmailAddressError {
+    #[error("email address is empty")]
+    Empty,
+    #[error("email address is {0} bytes, max is {MAX_EMAIL_LEN}")]
+    TooLong(usize),
+    #[error("email address \"{0}\" needs exactly one '@'")]
+    InvalidAt(String),
+    #[error("forbidden character {character:?} at index {index} in email address")]
+    ForbiddenCharacter { index: usize, character: char, address: String },
+    #[error("quoted local parts are not supported")]
+    QuotedLocalPart,
+    #[error("invalid local part in email address \"{0}\"")]
+    InvalidLocalPart(String),
+    #[error("invalid domain in email address \"{0}\"")]
+    InvalidDomain(String),
+}
+
+/*
+* InvalidAt points at the second '@', or the whole address when there is none.
+* The local part and domain are only pointed at when the '@' between them is in the excerpt.
+*/
+impl SpannedParseError for EmailAddressError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            EmailAddressError::InvalidAt(address) => {
+                let span = match address.match_indices('@').nth(1) {
+                    Some((index, _)) => Some(index..index + 1),
+                    None if !address.contains('@') => Some(0..address.len()),
+                    None => None,
+                };
+                ParseFailure::spanned(self, address, span)
+            }
+            EmailAddressError::ForbiddenCharacter { index, character, address } => {
+                ParseFailure::spanned(self, address, char_span(address, *index, *character))
+            }
+            EmailAddressError::InvalidLocalPart(address) => {
+                ParseFailure::spanned(self, address, address.find('@').map(|at| 0..at))
+            }
+            EmailAddressError::InvalidDomain(address) => {
+                let span = address.find('@').filter(|_| !is_truncated(address)).map(|at| at + 1..address.len());
+                ParseFailure::spanned(self, address, span)
+            }
+            _ => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+impl EmailAddress {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.0.split_once('@').map_or("", |(local, _)| local)
+    }
+
+    pub fn domain(&self) -> &str {
+        self.0.split_once('@').map_or("", |(_, domain)| domain)
+    }
+}
+
+impl FromStr for EmailAddress {
+    type Err = EmailAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        if trimmed.is_empty() {
+            return Err(EmailAddressError::Empty);
+        }
+        if trimmed.len() > MAX_EMAIL_LEN {
+            return Err(EmailAddressError::TooLong(trimmed.len()));
+        }
+        if let Some((index, character)) = trimmed
+            .char_indices()
+            .find(|(_, c)| c.is_whitespace() || c.is_control())
+        {
+            return Err(EmailAddressError::ForbiddenCharacter {
+                index,
+                character,
+                address: truncate_input(trimmed),
+            });
+        }
+        if trimmed.contains('"') {
+            return Err(EmailAddressError::QuotedLocalPart);
+        }
+        let (local, domain) = match trimmed.split('@').collect::<Vec<_>>()[..] {
+            [local, domain] => (local, domain),
+            _ => return Err(EmailAddressError::InvalidAt(truncate_input(trimmed))),
+        };
+        if local.is_empty() || local.len() > MAX_EMAIL_LOCAL_PART_LEN {
+            return Err(EmailAddressError::InvalidLocalPart(truncate_input(trimmed)));
+        }
+        if domain.split('.').count() < 2 || domain.split('.').any(str::is_empty) {
+            return Err(EmailAddressError::InvalidDomain(truncate_input(trimmed)));
+        }
+        Ok(Self(format!("{}@{}", local, domain.to_lowercase())))
+    }
+}
+
+impl fmt::Display for EmailAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for EmailAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for EmailAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// An invitation is answered once: a pending invitation is accepted, declined, revoked by the inviter or expires. All others are final.
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumString, strum::EnumIter, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Revoked,
+    Expired,
+}
+
+impl InvitationStatus {
+    // Staying in the same status is not a transition.
+    pub fn can_transition_to(&self, target: &InvitationStatus) -> bool {
+        use InvitationStatus::*;
+        matches!((self, target), (Pending, Accepted | Declined | Revoked | Expired))
+    }
+}
+
+// Invitation to share a bucket with someone by email instead of handing out a link.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ShareInvitation {
+    pub id: uuid::Uuid,
+    pub bucket: BucketGuid,
+    pub inviter: UserId,
+    pub invitee_email: EmailAddress,
+    pub permissions: BucketSharePermissionFlags,
+    // Personal note shown in the invitation mail.
+    pub message: Option<BucketDescription>,
+    pub expires: ExpiryTimestamp,
+    pub status: InvitationStatus,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum InvitationError {
+    #[error("invitation expired at {0}")]
+    Expired(ExpiryTimestamp),
+    #[error("invitation is {0}, not pending")]
+    NotPending(InvitationStatus),
+}
+
+impl ShareInvitation {
+    // An invitation can be accepted until, but not at, its expiry.
+    pub fn accept(&mut self, at: OffsetDateTime) -> Result<(), InvitationError> {
+        if self.status != InvitationStatus::Pending {
+            return Err(InvitationError::NotPending(self.status));
+        }
+        if at >= self.expires.as_offset_date_time() {
+            return Err(InvitationError::Expired(self.expires));
+        }
+        self.status = InvitationStatus::Accepted;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn invitation() -> ShareInvitation {
+        ShareInvitation {
+            id: uuid::Uuid::from_u128(7),
+            bucket: BucketGuid::new(uuid::Uuid::from_u128(1), uuid::Uuid::from_u128(2)),
+            inviter: uuid::Uuid::from_u128(1),
+            invitee_email: "Jane.Doe+photos@Example.COM".parse().unwrap(),
+            permissions: BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            message: Some(BucketDescription::try_from("Photos from the trip".to_string()).unwrap()),
+            expires: ExpiryTimestamp::try_from(datetime!(2024-06-08 12:00 UTC)).unwrap(),
+            status: InvitationStatus::Pending,
+        }
+    }
+
+    #[test]
+    fn email_validation() {
+        let valid = [
+            ("jane@example.com", "jane@example.com"),
+            ("Jane.Doe@Example.COM", "Jane.Doe@example.com"),
+            ("jane+photos@example.com", "jane+photos@example.com"),
+            ("  jane@example.com\n", "jane@example.com"),
+            ("jane@mail.example.co.uk", "jane@mail.example.co.uk"),
+            ("jane@xn--bcher-kva.example", "jane@xn--bcher-kva.example"),
+            ("jane@B\u{dc}CHER.example", "jane@b\u{fc}cher.example"),
+        ];
+        for (input, expected) in valid {
+            assert_eq!(
+                input.parse::<EmailAddress>().map(|email| email.to_string()),
+                Ok(expected.to_string())
+            );
+        }
+
+        let local = "a".repeat(MAX_EMAIL_LOCAL_PART_LEN);
+        let longest = format!("{}@{}.com", local, "b".repeat(MAX_EMAIL_LEN - local.len() - 5));
+        assert_eq!(longest.len(), MAX_EMAIL_LEN);
+        assert!(longest.parse::<EmailAddress>().is_ok());
+
+        let invalid = [
+            ("", EmailAddressError::Empty),
+            ("   ", EmailAddressError::Empty),
+            ("jane", EmailAddressError::InvalidAt("jane".to_string())),
+            (
+                "jane@doe@example.com",
+                EmailAddressError::InvalidAt("jane@doe@example.com".to_string()),
+            ),
+            (
+                "jane doe@example.com",
+                EmailAddressError::ForbiddenCharacter {
+                    index: 4,
+                    character: ' ',
+                    address: "jane doe@example.com".to_string(),
+                },
+            ),
+            (
+                "jane@exa\tmple.com",
+                EmailAddressError::ForbiddenCharacter {
+                    index: 8,
+                    character: '\t',
+                    address: "jane@exa\tmple.com".to_string(),
+                },
+            ),
+            // Quoted local parts are rejected on purpose, see EmailAddress.
+            ("\"jane\"@example.com", EmailAddressError::QuotedLocalPart),
+            ("\"jane@doe\"@example.com", EmailAddressError::QuotedLocalPart),
+            (
+                "@example.com",
+                EmailAddressError::InvalidLocalPart("@example.com".to_string()),
+            ),
+            ("jane@", EmailAddressError::InvalidDomain("jane@".to_string())),
+            (
+                "jane@localhost",
+                EmailAddressError::InvalidDomain("jane@localhost".to_string()),
+            ),
+            (
+                "jane@example..com",
+                EmailAddressError::InvalidDomain("jane@example..com".to_string()),
+            ),
+            (
+                "jane@.example.com",
+                EmailAddressError::InvalidDomain("jane@.example.com".to_string()),
+            ),
+            (
+                "jane@example.com.",
+                EmailAddressError::InvalidDomain("jane@example.com.".to_string()),
+            ),
+        ];
+        for (input, error) in invalid {
+            assert_eq!(input.parse::<EmailAddress>(), Err(error), "{:?}", input);
+        }
+        let long_local = format!("{}a@example.com", local);
+        assert!(matches!(
+            long_local.parse::<EmailAddress>(),
+            Err(EmailAddressError::InvalidLocalPart(_))
+        ));
+        let too_long = format!("{}b@{}.com", local, "b".repeat(MAX_EMAIL_LEN - local.len() - 5));
+        assert_eq!(
+            too_long.parse::<EmailAddress>(),
+            Err(EmailAddressError::TooLong(MAX_EMAIL_LEN + 1))
+        );
+    }
+
+    #[test]
+    fn status_transitions() {
+        for from in InvitationStatus::iter() {
+            for to in InvitationStatus::iter() {
+                let legal = from == InvitationStatus::Pending && to != InvitationStatus::Pending;
+                assert_eq!(from.can_transition_to(&to), legal, "{} -> {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn accept() {
+        let mut accepted = invitation();
+        assert_eq!(accepted.accept(datetime!(2024-06-08 11:59:59 UTC)), Ok(()));
+        assert_eq!(accepted.status, InvitationStatus::Accepted);
+        assert_eq!(
+            accepted.accept(datetime!(2024-06-01 12:00 UTC)),
+            Err(InvitationError::NotPending(InvitationStatus::Accepted))
+        );
+
+        let mut late = invitation();
+        assert_eq!(
+            late.accept(datetime!(2024-06-08 12:00 UTC)),
+            Err(InvitationError::Expired(late.expires))
+        );
+        assert_eq!(late.status, InvitationStatus::Pending);
+
+        for status in [
+            InvitationStatus::Declined,
+            InvitationStatus::Revoked,
+            InvitationStatus::Expired,
+        ] {
+            let mut answered = ShareInvitation { status, ..invitation() };
+            assert_eq!(
+                answered.accept(datetime!(2024-06-01 12:00 UTC)),
+                Err(InvitationError::NotPending(status))
+            );
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let json = serde_json::to_string(&invitation()).unwrap();
+        assert!(
+            json.contains(r#""invitee_email":"Jane.Doe+photos@example.com","permissions":"VIEW | READ""#),
+            "{}",
+            json
+        );
+        assert!(
+            json.ends_with(r#""expires":"2024-06-08T12:00:00Z","status":"pending"}"#),
+            "{}",
+            json
+        );
+        assert_eq!(serde_json::from_str::<ShareInvitation>(&json).unwrap(), invitation());
+
+        let invalid = json.replace("Jane.Doe+photos@example.com", "\\\"jane\\\"@example.com");
+        assert!(serde_json::from_str::<ShareInvitation>(&invalid).is_err());
+    }
+}
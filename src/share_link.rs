@@ -1,10 +1,21 @@
 #![cfg(feature = "share_link")]
 
+use std::fmt;
+use std::str::FromStr;
+
 use base64::{Engine, engine::general_purpose};
-use serde::{Deserialize, Serialize};
-use crate::util::{DOMAIN_URL, SHARE_PATH_URL};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::errors::{ParseFailure, SpannedParseError};
+use crate::human_label::{HumanLabel, Locale};
+use crate::BucketVisibility;
+use crate::util::{constant_time_eq, truncate_input, Fingerprint, LinkOrigin, LinkOriginError, OriginMismatch, Redact, DOMAIN_URL, SHARE_PATH_URL};
+use crate::link_diagnostics::{ParseDiagnostics, SegmentRole, UrlPart};
 
 
+/*
+* Bit assignments are part of the signed link format and never change, a removed permission keeps its bit reserved.
+* New permissions take the next free bit, older services read them as unknown bits, see from_bits_lenient.
+*/
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
     pub struct BucketSharePermissionFlags : u32 {
@@ -19,6 +30,488 @@ bitflags::bitflags! {
     }
 }
 
+// Fixed order of the compact permission code, e.g. "vrw-----" for view, read and write.
+pub(crate) const SHORT_PERMISSIONS: [(char, BucketSharePermissionFlags); 8] = [
+    ('v', BucketSharePermissionFlags::VIEW),
+    ('r', BucketSharePermissionFlags::READ),
+    ('w', BucketSharePermissionFlags::WRITE),
+    ('d', BucketSharePermissionFlags::DELETE_FILE),
+    ('D', BucketSharePermissionFlags::DELETE_BUCKET),
+    ('s', BucketSharePermissionFlags::SHARE_BUCKET),
+    ('c', BucketSharePermissionFlags::CLONE),
+    ('q', BucketSharePermissionFlags::SEARCH),
+];
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum ShortPermError {
+    #[error("empty permission string")]
+    Empty,
+    #[error("unknown permission '{character}' at position {position}")]
+    UnknownCharacter { position: usize, character: char },
+    #[error("duplicate permission '{character}' at position {position}")]
+    DuplicateCharacter { position: usize, character: char },
+    #[error("expected '{expected}' or '-' at position {position}, found '{character}'")]
+    MisplacedCharacter {
+        position: usize,
+        character: char,
+        expected: char,
+    },
+    #[error("positional permission string must be {} characters, got {0}", SHORT_PERMISSIONS.len())]
+    InvalidLength(usize),
+}
+
+const PERMISSION_PHRASES: [(BucketSharePermissionFlags, &str); 8] = [
+    (BucketSharePermissionFlags::VIEW, "view"),
+    (BucketSharePermissionFlags::READ, "download"),
+    (BucketSharePermissionFlags::WRITE, "upload"),
+    (BucketSharePermissionFlags::DELETE_FILE, "delete files"),
+    (BucketSharePermissionFlags::DELETE_BUCKET, "delete bucket"),
+    (BucketSharePermissionFlags::SHARE_BUCKET, "share"),
+    (BucketSharePermissionFlags::CLONE, "clone"),
+    (BucketSharePermissionFlags::SEARCH, "search"),
+];
+
+// What a permission lets the holder act on, verbs on the same target share it in a sentence.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum PermissionTarget {
+    Files,
+    Bucket,
+}
+
+// Verb of a permission per locale, by target and then in flag order.
+const PERMISSION_VERBS: [(BucketSharePermissionFlags, PermissionTarget, [&str; 3]); 8] = [
+    (BucketSharePermissionFlags::VIEW, PermissionTarget::Files, ["view", "ansehen", "visa"]),
+    (BucketSharePermissionFlags::READ, PermissionTarget::Files, ["download", "herunterladen", "ladda ner"]),
+    (BucketSharePermissionFlags::WRITE, PermissionTarget::Files, ["upload", "hochladen", "ladda upp"]),
+    (BucketSharePermissionFlags::DELETE_FILE, PermissionTarget::Files, ["delete", "löschen", "radera"]),
+    (BucketSharePermissionFlags::SEARCH, PermissionTarget::Files, ["search", "durchsuchen", "söka igenom"]),
+    (BucketSharePermissionFlags::DELETE_BUCKET, PermissionTarget::Bucket, ["delete", "löschen", "radera"]),
+    (BucketSharePermissionFlags::SHARE_BUCKET, PermissionTarget::Bucket, ["share", "teilen", "dela"]),
+    (BucketSharePermissionFlags::CLONE, PermissionTarget::Bucket, ["clone", "klonen", "klona"]),
+];
+
+impl PermissionTarget {
+    fn phrase(self, locale: Locale) -> &'static str {
+        match (self, locale) {
+            (PermissionTarget::Files, Locale::En) => "files",
+            (PermissionTarget::Files, Locale::De) => "Dateien",
+            (PermissionTarget::Files, Locale::Sv) => "filer",
+            (PermissionTarget::Bucket, Locale::En) => "the bucket",
+            (PermissionTarget::Bucket, Locale::De) => "den Bucket",
+            (PermissionTarget::Bucket, Locale::Sv) => "bucketen",
+        }
+    }
+}
+
+impl BucketSharePermissionFlags {
+    // Splits bits into the permissions this version knows and the unknown bits, which are kept instead of rejected.
+    pub fn from_bits_lenient(bits: u32) -> (Self, u32) {
+        (Self::from_bits_truncate(bits), bits & !Self::all().bits())
+    }
+
+    // Human-readable summary, e.g. "View & download" or "View, download & upload".
+    pub fn summary(&self) -> String {
+        let phrases = PERMISSION_PHRASES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, phrase)| *phrase)
+            .collect::<Vec<_>>();
+        let summary = match phrases.split_last() {
+            None => return "No access".to_string(),
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} & {}", rest.join(", "), last),
+        };
+        let mut chars = summary.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => summary,
+        }
+    }
+
+    /*
+    * Sentence for tooltips, e.g. "Can view and download files" or "Kann Dateien ansehen und den Bucket teilen".
+    * Unknown bits are left out, "No access" when no known permission is set.
+    */
+    pub fn describe(&self, locale: Locale) -> String {
+        let verb_index = match locale {
+            Locale::En => 0,
+            Locale::De => 1,
+            Locale::Sv => 2,
+        };
+        let groups = [PermissionTarget::Files, PermissionTarget::Bucket]
+            .into_iter()
+            .filter_map(|target| {
+                let verbs = PERMISSION_VERBS
+                    .iter()
+                    .filter(|(flag, verb_target, _)| *verb_target == target && self.contains(*flag))
+                    .map(|(_, _, verbs)| verbs[verb_index])
+                    .collect::<Vec<_>>();
+                let verbs = locale.join_list(&verbs);
+                match (verbs.is_empty(), locale) {
+                    (true, _) => None,
+                    (false, Locale::De) => Some(format!("{} {}", target.phrase(locale), verbs)),
+                    (false, _) => Some(format!("{} {}", verbs, target.phrase(locale))),
+                }
+            })
+            .collect::<Vec<_>>();
+        let (can, no_access) = match locale {
+            Locale::En => ("Can", "No access"),
+            Locale::De => ("Kann", "Kein Zugriff"),
+            Locale::Sv => ("Kan", "Ingen åtkomst"),
+        };
+        match groups.is_empty() {
+            true => no_access.to_string(),
+            false => format!("{} {}", can, locale.join_list(&groups)),
+        }
+    }
+
+    // Compact positional form in the order "vrwdDscq", unset permissions are shown as '-'.
+    pub fn to_short_string(&self) -> String {
+        SHORT_PERMISSIONS
+            .iter()
+            .map(|(character, flag)| if self.contains(*flag) { *character } else { '-' })
+            .collect()
+    }
+
+    /*
+    * Accepts:
+    * "full": every permission.
+    * "-": no permissions.
+    * The compact positional form produced by to_short_string, e.g. "vr-d----".
+    * Unordered letters, e.g. "rw" or "wrd". 'd' is delete file and 'D' is delete bucket.
+    */
+    pub fn from_short_string(s: &str) -> Result<Self, ShortPermError> {
+        match s {
+            "" => return Err(ShortPermError::Empty),
+            "full" => return Ok(Self::all()),
+            "-" => return Ok(Self::empty()),
+            _ => {}
+        }
+
+        let mut flags = Self::empty();
+        if s.contains('-') {
+            let length = s.chars().count();
+            if length != SHORT_PERMISSIONS.len() {
+                return Err(ShortPermError::InvalidLength(length));
+            }
+            for (position, (character, (expected, flag))) in s.chars().zip(SHORT_PERMISSIONS.iter()).enumerate() {
+                if character == *expected {
+                    flags |= *flag;
+                } else if character != '-' {
+                    return Err(ShortPermError::MisplacedCharacter {
+                        position,
+                        character,
+                        expected: *expected,
+                    });
+                }
+            }
+            return Ok(flags);
+        }
+
+        for (position, character) in s.chars().enumerate() {
+            let (_, flag) = SHORT_PERMISSIONS
+                .iter()
+                .find(|(c, _)| *c == character)
+                .ok_or(ShortPermError::UnknownCharacter { position, character })?;
+            if flags.contains(*flag) {
+                return Err(ShortPermError::DuplicateCharacter { position, character });
+            }
+            flags |= *flag;
+        }
+        Ok(flags)
+    }
+}
+
+// What the console suggests and the gateway allows for links to a bucket, by its visibility.
+impl BucketVisibility {
+    pub fn suggested_share_permissions(&self) -> BucketSharePermissionFlags {
+        match self {
+            BucketVisibility::Public => {
+                BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ | BucketSharePermissionFlags::SEARCH
+            }
+            BucketVisibility::PrivateShared => BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            BucketVisibility::Private => BucketSharePermissionFlags::empty(),
+        }
+    }
+
+    /*
+    * Anyone can find a public bucket and its links get passed around, so they may only read and clone it.
+    * Only the owner ever deletes a bucket. A private bucket has no links, sharing it makes it PrivateShared first.
+    */
+    pub fn max_allowed_share_permissions(&self) -> BucketSharePermissionFlags {
+        match self {
+            BucketVisibility::Public => {
+                BucketSharePermissionFlags::VIEW
+                    | BucketSharePermissionFlags::READ
+                    | BucketSharePermissionFlags::SEARCH
+                    | BucketSharePermissionFlags::CLONE
+            }
+            BucketVisibility::PrivateShared => {
+                BucketSharePermissionFlags::all() - BucketSharePermissionFlags::DELETE_BUCKET
+            }
+            BucketVisibility::Private => BucketSharePermissionFlags::empty(),
+        }
+    }
+}
+
+// The requested permissions a link to a bucket of visibility may grant, and the ones removed from the request.
+pub fn clamp_to_visibility(
+    requested: BucketSharePermissionFlags,
+    visibility: BucketVisibility,
+) -> (BucketSharePermissionFlags, BucketSharePermissionFlags) {
+    let allowed = visibility.max_allowed_share_permissions();
+    (requested & allowed, requested - allowed)
+}
+
+/*
+* rkyv archives hold the little-endian bits. Bits this version does not know are kept in the archive
+* but never granted, get drops them like from_bits_truncate.
+*/
+#[cfg(feature = "rkyv")]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[repr(transparent)]
+pub struct ArchivedBucketSharePermissionFlags(rkyv::Archived<u32>);
+
+#[cfg(feature = "rkyv")]
+impl ArchivedBucketSharePermissionFlags {
+    pub fn bits(&self) -> u32 {
+        self.0.value()
+    }
+
+    pub fn get(&self) -> BucketSharePermissionFlags {
+        BucketSharePermissionFlags::from_bits_truncate(self.bits())
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl rkyv::Archive for BucketSharePermissionFlags {
+    type Archived = ArchivedBucketSharePermissionFlags;
+    type Resolver = ();
+
+    unsafe fn resolve(&self, pos: usize, resolver: Self::Resolver, out: *mut Self::Archived) {
+        // ArchivedBucketSharePermissionFlags is a transparent Archived<u32>.
+        rkyv::Archive::resolve(&self.bits(), pos, resolver, out.cast());
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<S: rkyv::Fallible + ?Sized> rkyv::Serialize<S> for BucketSharePermissionFlags {
+    fn serialize(&self, _: &mut S) -> Result<Self::Resolver, S::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<D> rkyv::Deserialize<BucketSharePermissionFlags, D> for ArchivedBucketSharePermissionFlags
+where
+    D: rkyv::Fallible + ?Sized,
+{
+    fn deserialize(&self, _: &mut D) -> Result<BucketSharePermissionFlags, D::Error> {
+        Ok(self.get())
+    }
+}
+
+// Every bit pattern is valid, there is nothing to check beyond what the validator does for all archived types.
+#[cfg(feature = "rkyv")]
+impl<C: ?Sized> rkyv::bytecheck::CheckBytes<C> for ArchivedBucketSharePermissionFlags {
+    type Error = std::convert::Infallible;
+
+    unsafe fn check_bytes<'a>(value: *const Self, _: &mut C) -> Result<&'a Self, Self::Error> {
+        Ok(&*value)
+    }
+}
+
+impl HumanLabel for BucketSharePermissionFlags {
+    fn human_label(&self, locale: Locale) -> String {
+        self.describe(locale)
+    }
+}
+
+// Token the server uses to identify a share link, either random (ShareLink) or derived from the signed link (SecretShareLink).
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize), archive(check_bytes))]
+#[cfg_attr(feature = "rkyv", archive_attr(derive(Eq, PartialEq, Hash)))]
+pub struct ShareLinkToken(pub [u8; 32]);
+
+// Same as ShareLink, the token grants access and must not end up in logs through {:?} of anything holding it.
+impl fmt::Debug for ShareLinkToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ShareLinkToken")
+            .field(&format_args!("<{} bytes, fp:{}>", self.0.len(), Fingerprint::of(&self.0)))
+            .finish()
+    }
+}
+
+impl From<[u8; 32]> for ShareLinkToken {
+    fn from(value: [u8; 32]) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Display for ShareLinkToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&general_purpose::URL_SAFE_NO_PAD.encode(self.0))
+    }
+}
+
+impl Redact for ShareLinkToken {
+    fn redacted(&self) -> String {
+        format!("ShareLinkToken(fp:{})", Fingerprint::of(&self.0))
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum ShareLinkTokenParsingError {
+    #[error("invalid share link token \"{0}\"")]
+    InvalidFormat(String),
+}
+
+impl SpannedParseError for ShareLinkTokenParsingError {
+    fn failure(&self) -> ParseFailure {
+        let ShareLinkTokenParsingError::InvalidFormat(input) = self;
+        ParseFailure::whole(self, input)
+    }
+}
+
+impl FromStr for ShareLinkToken {
+    type Err = ShareLinkTokenParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ShareLinkTokenParsingError::InvalidFormat(truncate_input(s));
+        let bytes = general_purpose::URL_SAFE_NO_PAD.decode(s).map_err(|_| invalid())?;
+        Ok(Self(bytes.try_into().map_err(|_| invalid())?))
+    }
+}
+
+impl Serialize for ShareLinkToken {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ShareLinkToken {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/*
+* Sharding of token lookup tables, every service must place a token the same way or resharding breaks.
+* The shard is the first 8 token bytes read as a big-endian u64, modulo the shard count. Tokens are uniformly
+* random or hash output, so the modulo bias of at most 2^16 / 2^64 does not matter.
+* This algorithm is frozen, the golden vectors in the tests pin it.
+*/
+impl ShareLinkToken {
+    // Panics when shard_count is 0.
+    pub fn shard(&self, shard_count: u16) -> u16 {
+        assert!(shard_count > 0, "shard count must be at least 1");
+        (self.shard_key() % u64::from(shard_count)) as u16
+    }
+
+    // First chars lowercase hex digits of the token bytes, for stores partitioned by key prefix. At most 64 chars.
+    pub fn shard_prefix_hex(&self, chars: usize) -> String {
+        let mut hex = self.0.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        hex.truncate(chars);
+        hex
+    }
+
+    fn shard_key(&self) -> u64 {
+        u64::from_be_bytes(self.0[..8].try_into().unwrap())
+    }
+}
+
+/*
+* Migration plan for changing the shard count of a token table.
+* A token in old shard a lands in new shard b exactly when a and b are congruent modulo gcd(old, new), so the moves
+* follow from the counts alone. Doubling moves every shard into two, one of them itself, coprime counts scatter every
+* shard across all new shards.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ShardMap {
+    pub old: u16,
+    pub new: u16,
+}
+
+impl ShardMap {
+    // Panics when a count is 0.
+    pub fn new(old: u16, new: u16) -> Self {
+        assert!(old > 0 && new > 0, "shard counts must be at least 1");
+        Self { old, new }
+    }
+
+    // Same as ShardMap::new(old, new).moves().
+    pub fn moved_between(old: u16, new: u16) -> Vec<(u16, u16)> {
+        Self::new(old, new).moves()
+    }
+
+    // Every (old shard, new shard) pair that tokens move along, sorted. Pairs of a shard to itself stay in place.
+    pub fn moves(&self) -> Vec<(u16, u16)> {
+        let gcd = gcd(self.old, self.new);
+        (0..self.old)
+            .flat_map(|from| (from % gcd..self.new).step_by(gcd as usize).map(move |to| (from, to)))
+            .filter(|(from, to)| from != to)
+            .collect()
+    }
+
+    // The shard a token leaves and the one it goes to, None if it stays where it is.
+    pub fn moves_token(&self, token: &ShareLinkToken) -> Option<(u16, u16)> {
+        let (from, to) = (token.shard(self.old), token.shard(self.new));
+        (from != to).then_some((from, to))
+    }
+}
+
+fn gcd(a: u16, b: u16) -> u16 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+/*
+* How a link was handed out, recorded for security analytics.
+* The byte form is part of signed payloads, values must never be reused. Other is only for explicit issuance,
+* unknown bytes are rejected rather than read as Other.
+*/
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Hash, strum::EnumString, strum::Display, strum::EnumIter, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum ShareChannel {
+    Email = 1,
+    Qr = 2,
+    Api = 3,
+    Ui = 4,
+    Other = 5,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("unknown share channel {0}")]
+pub struct UnknownShareChannel(pub u8);
+
+impl ShareChannel {
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+}
+
+impl TryFrom<u8> for ShareChannel {
+    type Error = UnknownShareChannel;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Email),
+            2 => Ok(Self::Qr),
+            3 => Ok(Self::Api),
+            4 => Ok(Self::Ui),
+            5 => Ok(Self::Other),
+            value => Err(UnknownShareChannel(value)),
+        }
+    }
+}
+
 /*
 *  Bucket share link
 *  bucketdrive.co/api/v1/share/user_id/bucket_id#permissions#expires#signature
@@ -27,10 +520,34 @@ pub struct ShareLink {
     pub token: [u8; 32],
 }
 
-impl ToString for ShareLink {
-    fn to_string(&self) -> String {
-        format!(
-            "{}{}/share/{}",
+impl fmt::Debug for ShareLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShareLink")
+            .field("token", &format_args!("<{} bytes, fp:{}>", self.token.len(), Fingerprint::of(&self.token)))
+            .finish()
+    }
+}
+
+// Two share links are equal when their tokens are, compared in constant time since the token grants access.
+impl PartialEq for ShareLink {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.token, &other.token)
+    }
+}
+
+impl Eq for ShareLink {}
+
+impl std::hash::Hash for ShareLink {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.token.hash(state);
+    }
+}
+
+impl fmt::Display for ShareLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "https://{}{}/{}",
             DOMAIN_URL,
             SHARE_PATH_URL,
             general_purpose::URL_SAFE_NO_PAD.encode(self.token),
@@ -38,6 +555,12 @@ impl ToString for ShareLink {
     }
 }
 
+impl Redact for ShareLink {
+    fn redacted(&self) -> String {
+        format!("ShareLink(fp:{})", Fingerprint::of(&self.token))
+    }
+}
+
 impl TryInto<url::Url> for ShareLink {
     type Error = url::ParseError;
     fn try_into(self) -> Result<url::Url, Self::Error> {
@@ -45,22 +568,55 @@ impl TryInto<url::Url> for ShareLink {
     }
 }
 
-pub enum ShareLinkParsingError {}
+#[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
+pub enum ShareLinkParsingError {
+    #[error("Invalid scheme")]
+    InvalidScheme,
+    #[error("Invalid host")]
+    InvalidHostDomain,
+    #[error("Invalid path")]
+    InvalidPath,
+    #[error("Invalid token length")]
+    InvalidTokenLength,
+
+    #[error(transparent)]
+    Base64Decoding(#[from] base64::DecodeError),
+    #[error(transparent)]
+    UrlParse(#[from] url::ParseError),
+}
+
+// Links are never echoed since they carry the token, ParseDiagnostics points at the failing part instead.
+impl SpannedParseError for ShareLinkParsingError {
+    fn failure(&self) -> ParseFailure {
+        ParseFailure::unspanned(self)
+    }
+}
+
+impl From<OriginMismatch> for ShareLinkParsingError {
+    fn from(value: OriginMismatch) -> Self {
+        match value {
+            OriginMismatch::Scheme => Self::InvalidScheme,
+            OriginMismatch::Host => Self::InvalidHostDomain,
+            OriginMismatch::Path => Self::InvalidPath,
+        }
+    }
+}
 
 // Compress Share Link???
-//TODO: FIX THIS
-// Very strict parser.
+// Very strict parser, only scheme and host are case-insensitive.
 impl TryFrom<url::Url> for ShareLink {
     type Error = ShareLinkParsingError;
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "ShareLink::try_from", level = "debug", skip_all))]
     fn try_from(url: url::Url) -> Result<Self, Self::Error> {
-        let path = url.path();
-        let parts = path.split('/').take(1).collect::<Vec<&str>>(); // First element should be empty.
-        let token = base64::engine::general_purpose::URL_SAFE_NO_PAD
-            .decode(parts[1].parse::<String>().unwrap()).unwrap();
+        Self::from_url_with_origin(&url, &LinkOrigin::default())
+    }
+}
 
-        Ok(Self {
-            token: token.try_into().unwrap()
-        })
+impl FromStr for ShareLink {
+    type Err = ShareLinkParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(url::Url::parse(s)?)
     }
 }
 /*
@@ -70,6 +626,12 @@ The hash is signed by the clients private key.
 The hash can then be verified against the created signature by the client.
 This leads to the file being verifiable to the client. Meaning no one can tamper with the file without the client knowing.
 */
+impl Default for ShareLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ShareLink {
     pub fn new() -> Self {
         let token = rand::random::<[u8;32]>(); // 256 bits
@@ -78,10 +640,535 @@ impl ShareLink {
         }
     }
 
+    pub fn from_url_with_origin(url: &url::Url, origin: &LinkOrigin) -> Result<Self, ShareLinkParsingError> {
+        Self::parse_with_diagnostics_and_origin(url, origin).0
+    }
+
+    pub fn parse_with_diagnostics(url: &url::Url) -> (Result<Self, ShareLinkParsingError>, ParseDiagnostics) {
+        Self::parse_with_diagnostics_and_origin(url, &LinkOrigin::default())
+    }
+
+    pub fn parse_with_diagnostics_and_origin(
+        url: &url::Url,
+        origin: &LinkOrigin,
+    ) -> (Result<Self, ShareLinkParsingError>, ParseDiagnostics) {
+        let mut diagnostics = ParseDiagnostics::default();
+        let result = Self::parse(url, origin, &mut diagnostics);
+        diagnostics.finish(&result);
+        // Origin and path errors have no segment, the token is path segment 0.
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            let segment = diagnostics.failed_segment().map(|segment| segment.index);
+            tracing::debug!(error = <&'static str>::from(error), segment, "rejected share link");
+        }
+        (result, diagnostics)
+    }
+
+    fn parse(
+        url: &url::Url,
+        origin: &LinkOrigin,
+        diagnostics: &mut ParseDiagnostics,
+    ) -> Result<Self, ShareLinkParsingError> {
+        let token = origin
+            .share_link_path(url)
+            .map_err(ShareLinkParsingError::from)?
+            .strip_prefix('/')
+            .ok_or(ShareLinkParsingError::InvalidPath)?;
+        let result = general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(ShareLinkParsingError::from)
+            .and_then(|decoded| <[u8; 32]>::try_from(decoded).map_err(|_| ShareLinkParsingError::InvalidTokenLength));
+
+        Ok(Self {
+            token: diagnostics.record(UrlPart::Path, 0, SegmentRole::Token, token, result)?,
+        })
+    }
+
+    // Same link as Display under another origin, e.g. LinkOrigin::deep_link() for bucketdrive://share/<token>.
+    pub fn to_url_with(&self, origin: &LinkOrigin) -> Result<url::Url, LinkOriginError> {
+        let base = origin.share_link_base()?;
+        Ok(url::Url::parse(&format!("{}/{}", base, general_purpose::URL_SAFE_NO_PAD.encode(self.token)))?)
+    }
+
     pub fn get_token(&self) -> [u8;32] {
         self.token
     }
+    pub fn share_link_token(&self) -> ShareLinkToken {
+        ShareLinkToken(self.token)
+    }
     pub fn gen_token() -> [u8; 32] {
         rand::random::<[u8;32]>()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_string_round_trip_single_flags() {
+        for (character, flag) in SHORT_PERMISSIONS {
+            let short = flag.to_short_string();
+            assert_eq!(short.chars().filter(|c| *c != '-').collect::<String>(), character.to_string());
+            assert_eq!(BucketSharePermissionFlags::from_short_string(&short), Ok(flag));
+            assert_eq!(BucketSharePermissionFlags::from_short_string(&character.to_string()), Ok(flag));
+        }
+    }
+
+    #[test]
+    fn short_string_round_trip_combined_flags() {
+        let combinations = [
+            BucketSharePermissionFlags::empty(),
+            BucketSharePermissionFlags::all(),
+            BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ,
+            BucketSharePermissionFlags::DELETE_FILE | BucketSharePermissionFlags::DELETE_BUCKET,
+            BucketSharePermissionFlags::WRITE | BucketSharePermissionFlags::SEARCH | BucketSharePermissionFlags::CLONE,
+        ];
+        for flags in combinations {
+            assert_eq!(BucketSharePermissionFlags::from_short_string(&flags.to_short_string()), Ok(flags));
+        }
+        assert_eq!(BucketSharePermissionFlags::all().to_short_string(), "vrwdDscq");
+        assert_eq!(BucketSharePermissionFlags::empty().to_short_string(), "--------");
+    }
+
+    #[test]
+    fn short_string_cli_forms() {
+        let rw = BucketSharePermissionFlags::READ | BucketSharePermissionFlags::WRITE;
+        assert_eq!(BucketSharePermissionFlags::from_short_string("rw"), Ok(rw));
+        assert_eq!(BucketSharePermissionFlags::from_short_string("wr"), Ok(rw));
+        assert_eq!(
+            BucketSharePermissionFlags::from_short_string("rwd"),
+            Ok(rw | BucketSharePermissionFlags::DELETE_FILE)
+        );
+        assert_eq!(
+            BucketSharePermissionFlags::from_short_string("rwD"),
+            Ok(rw | BucketSharePermissionFlags::DELETE_BUCKET)
+        );
+        assert_eq!(
+            BucketSharePermissionFlags::from_short_string("full"),
+            Ok(BucketSharePermissionFlags::all())
+        );
+        assert_eq!(
+            BucketSharePermissionFlags::from_short_string("-"),
+            Ok(BucketSharePermissionFlags::empty())
+        );
+    }
+
+    #[test]
+    fn share_link_token_round_trip() {
+        let token = ShareLink::new().share_link_token();
+        assert_eq!(token.to_string().parse::<ShareLinkToken>(), Ok(token));
+        let json = serde_json::to_string(&token).unwrap();
+        assert_eq!(serde_json::from_str::<ShareLinkToken>(&json).unwrap(), token);
+        assert!("AAAA".parse::<ShareLinkToken>().is_err());
+        assert!("not base64!".parse::<ShareLinkToken>().is_err());
+    }
+
+    #[test]
+    fn share_link_scheme_and_host_are_case_insensitive() {
+        let link = ShareLink::new();
+        let token = general_purpose::URL_SAFE_NO_PAD.encode(link.token);
+        for input in [
+            format!("HTTPS://BUCKETDRIVE.CO/api/v1/share/{}", token),
+            format!("hTTpS://BucketDrive.co/api/v1/share/{}", token),
+            format!("https://bucketdrive.co/api/v1/share/{}/", token),
+        ] {
+            assert_eq!(input.parse::<ShareLink>().unwrap().token, link.token, "{}", input);
+        }
+        assert!(matches!(
+            format!("https://bucketdrive.co/api/v1/share/{}//", token).parse::<ShareLink>(),
+            Err(ShareLinkParsingError::Base64Decoding(_))
+        ));
+        assert!(matches!(
+            format!("https://bucketdrive.co/API/v1/share/{}", token).parse::<ShareLink>(),
+            Err(ShareLinkParsingError::InvalidPath)
+        ));
+        assert!(matches!(
+            format!("http://bucketdrive.co/api/v1/share/{}", token).parse::<ShareLink>(),
+            Err(ShareLinkParsingError::InvalidScheme)
+        ));
+
+        let staging = LinkOrigin::new("https", "staging.bucketdrive.co");
+        let url = url::Url::parse(&format!("https://STAGING.bucketdrive.co/api/v1/share/{}", token)).unwrap();
+        assert_eq!(ShareLink::from_url_with_origin(&url, &staging).unwrap().token, link.token);
+        assert!(matches!(ShareLink::try_from(url), Err(ShareLinkParsingError::InvalidHostDomain)));
+    }
+
+    #[test]
+    fn deep_link_round_trip() {
+        let link = ShareLink::new();
+        let token = general_purpose::URL_SAFE_NO_PAD.encode(link.token);
+        let deep_link = LinkOrigin::deep_link();
+        let url = link.to_url_with(&deep_link).unwrap();
+        assert_eq!(url.as_str(), format!("bucketdrive://share/{}", token));
+        assert_eq!(ShareLink::from_url_with_origin(&url, &deep_link).unwrap(), link);
+        assert!(matches!(ShareLink::try_from(url.clone()), Err(ShareLinkParsingError::InvalidScheme)));
+
+        let web = link.to_url_with(&LinkOrigin::default()).unwrap();
+        assert_eq!(web.as_str(), link.to_string());
+        assert!(matches!(
+            ShareLink::from_url_with_origin(&web, &deep_link),
+            Err(ShareLinkParsingError::InvalidScheme)
+        ));
+
+        // The host of a deep link is the app route, only the share route is a share link.
+        for input in [format!("BucketDrive://share/{}/", token), format!("bucketdrive://SHARE/{}", token)] {
+            let url = url::Url::parse(&input).unwrap();
+            assert_eq!(ShareLink::from_url_with_origin(&url, &deep_link).unwrap(), link, "{}", input);
+        }
+        for input in [format!("bucketdrive://open/{}", token), format!("bucketdrive:/share/{}", token)] {
+            let url = url::Url::parse(&input).unwrap();
+            assert!(
+                matches!(ShareLink::from_url_with_origin(&url, &deep_link), Err(ShareLinkParsingError::InvalidPath)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn script_schemes_are_rejected() {
+        let link = ShareLink::new();
+        for scheme in ["javascript", "data", "file", "http"] {
+            let origin = LinkOrigin::new(scheme, "share");
+            assert_eq!(origin.link_scheme(), None);
+            assert_eq!(link.to_url_with(&origin), Err(LinkOriginError::SchemeNotAllowed(scheme.to_string())));
+            let url = url::Url::parse(&format!("{}://share/{}", scheme, link.share_link_token())).unwrap();
+            assert!(
+                matches!(ShareLink::from_url_with_origin(&url, &origin), Err(ShareLinkParsingError::InvalidScheme)),
+                "{}",
+                scheme
+            );
+        }
+        let url = url::Url::parse("javascript:alert(1)").unwrap();
+        assert!(matches!(ShareLink::try_from(url), Err(ShareLinkParsingError::InvalidScheme)));
+    }
+
+    #[test]
+    fn share_link_token_is_case_sensitive() {
+        let link = ShareLink { token: [0x5a; 32] };
+        let uppercased = link.to_string().to_ascii_uppercase();
+        let parsed = uppercased.parse::<ShareLink>().map(|parsed| parsed.token);
+        assert!(parsed.is_err() || parsed.unwrap() != link.token);
+    }
+
+    #[test]
+    fn shards_are_pinned() {
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&0x0123_4567_89ab_cdefu64.to_be_bytes());
+        bytes[8..].fill(0xff);
+        let token = ShareLinkToken(bytes);
+        let golden = [(1, 0), (2, 1), (7, 6), (8, 7), (10, 5), (16, 15), (1000, 895), (u16::MAX, 40485)];
+        for (shard_count, shard) in golden {
+            assert_eq!(token.shard(shard_count), shard, "{}", shard_count);
+        }
+        // Only the first 8 bytes count.
+        let mut tail = bytes;
+        tail[31] = 0;
+        assert_eq!(ShareLinkToken(tail).shard(1000), 895);
+        assert_eq!(ShareLinkToken([0; 32]).shard(9), 0);
+        assert_eq!(ShareLinkToken([0xff; 32]).shard(10), 5);
+
+        assert_eq!(token.shard_prefix_hex(0), "");
+        assert_eq!(token.shard_prefix_hex(3), "012");
+        assert_eq!(token.shard_prefix_hex(16), "0123456789abcdef");
+        assert_eq!(token.shard_prefix_hex(100).len(), 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "shard count must be at least 1")]
+    fn zero_shards_panic() {
+        ShareLinkToken([0; 32]).shard(0);
+    }
+
+    #[test]
+    fn shards_are_near_uniform() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(167);
+        let tokens = (0..100_000).map(|_| ShareLinkToken(rng.gen())).collect::<Vec<_>>();
+        for shard_count in [2u16, 7, 16, 100, 1000] {
+            let mut counts = vec![0u32; shard_count as usize];
+            for token in &tokens {
+                counts[token.shard(shard_count) as usize] += 1;
+            }
+            // Six standard deviations of a binomial count.
+            let expected = tokens.len() as f64 / f64::from(shard_count);
+            let tolerance = 6.0 * (expected * (1.0 - 1.0 / f64::from(shard_count))).sqrt();
+            for (shard, count) in counts.iter().enumerate() {
+                assert!(
+                    (f64::from(*count) - expected).abs() <= tolerance,
+                    "shard {} of {} has {} tokens, expected {}",
+                    shard,
+                    shard_count,
+                    count,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shard_migrations() {
+        assert_eq!(ShardMap::moved_between(8, 16), (0..8).map(|shard| (shard, shard + 8)).collect::<Vec<_>>());
+        assert_eq!(ShardMap::moved_between(16, 8), (0..8).map(|shard| (shard + 8, shard)).collect::<Vec<_>>());
+        let moves = ShardMap::moved_between(10, 7);
+        assert_eq!(moves.len(), 10 * 7 - 7);
+        assert!(moves.iter().all(|(from, to)| from != to && *from < 10 && *to < 7));
+        assert_eq!(ShardMap::moved_between(4, 6), [(0, 2), (0, 4), (1, 3), (1, 5), (2, 0), (2, 4), (3, 1), (3, 5)]);
+        assert_eq!(ShardMap::moved_between(5, 5), []);
+        assert_eq!(ShardMap::moved_between(1, 3), [(0, 1), (0, 2)]);
+
+        // The computed plan is exactly the moves real tokens make.
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for (old, new) in [(8, 16), (16, 8), (10, 7), (4, 6), (5, 5), (1, 3)] {
+            let map = ShardMap::new(old, new);
+            let mut seen = (0..20_000)
+                .filter_map(|_| map.moves_token(&ShareLinkToken(rng.gen())))
+                .collect::<Vec<_>>();
+            seen.sort();
+            seen.dedup();
+            assert_eq!(seen, map.moves(), "{} -> {}", old, new);
+        }
+    }
+
+    #[test]
+    fn share_link_equality_and_hash() {
+        let link = ShareLink::new();
+        let same = ShareLink { token: link.token };
+        let mut other = ShareLink { token: link.token };
+        other.token[31] ^= 1;
+        assert!(link == same);
+        assert!(link != other);
+
+        let set = [link, same, other].into_iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), 2);
+    }
+
+    // Bits are part of signed links, changing one would change what existing links grant.
+    #[test]
+    fn permission_bits_are_pinned() {
+        let pinned = [
+            (BucketSharePermissionFlags::VIEW, 1 << 0),
+            (BucketSharePermissionFlags::READ, 1 << 1),
+            (BucketSharePermissionFlags::WRITE, 1 << 2),
+            (BucketSharePermissionFlags::DELETE_FILE, 1 << 3),
+            (BucketSharePermissionFlags::DELETE_BUCKET, 1 << 4),
+            (BucketSharePermissionFlags::SHARE_BUCKET, 1 << 5),
+            (BucketSharePermissionFlags::CLONE, 1 << 6),
+            (BucketSharePermissionFlags::SEARCH, 1 << 7),
+        ];
+        for (flag, bits) in pinned {
+            assert_eq!(flag.bits(), bits, "{:?}", flag);
+        }
+        assert_eq!(BucketSharePermissionFlags::all().iter().count(), pinned.len());
+    }
+
+    #[test]
+    fn describe_permissions() {
+        use BucketSharePermissionFlags as P;
+        let english = [
+            (P::VIEW | P::READ, "Can view and download files"),
+            (P::VIEW, "Can view files"),
+            (P::WRITE | P::VIEW | P::DELETE_FILE, "Can view, upload and delete files"),
+            (P::SHARE_BUCKET | P::CLONE, "Can share and clone the bucket"),
+            (P::DELETE_BUCKET, "Can delete the bucket"),
+            (P::READ | P::CLONE, "Can download files and clone the bucket"),
+            (
+                P::all(),
+                "Can view, download, upload, delete and search files and delete, share and clone the bucket",
+            ),
+            (P::empty(), "No access"),
+            (P::from_bits_retain(1 << 8 | 1 << 31), "No access"),
+            (P::from_bits_retain(1 << 8) | P::SEARCH, "Can search files"),
+        ];
+        for (flags, expected) in english {
+            assert_eq!(flags.describe(Locale::En), expected, "{:?}", flags);
+            assert_eq!(flags.human_label(Locale::En), expected);
+        }
+
+        let flags = P::VIEW | P::READ | P::SHARE_BUCKET;
+        assert_eq!(
+            flags.describe(Locale::De),
+            "Kann Dateien ansehen und herunterladen und den Bucket teilen"
+        );
+        assert_eq!(flags.describe(Locale::Sv), "Kan visa och ladda ner filer och dela bucketen");
+        assert_eq!(P::empty().describe(Locale::De), "Kein Zugriff");
+        assert_eq!(P::empty().describe(Locale::Sv), "Ingen åtkomst");
+    }
+
+    #[test]
+    fn lenient_bits_keep_unknown_bits() {
+        assert_eq!(
+            BucketSharePermissionFlags::from_bits_lenient(0b11),
+            (BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ, 0)
+        );
+        assert_eq!(
+            BucketSharePermissionFlags::from_bits_lenient(1 << 31 | 1 << 8 | 1),
+            (BucketSharePermissionFlags::VIEW, 1 << 31 | 1 << 8)
+        );
+        assert_eq!(
+            BucketSharePermissionFlags::from_bits_lenient(u32::MAX),
+            (BucketSharePermissionFlags::all(), !0xff)
+        );
+    }
+
+    #[test]
+    fn debug_does_not_leak_token() {
+        let secret_like = regex::Regex::new("[A-Za-z0-9+/=_-]{16,}").unwrap();
+        for _ in 0..50 {
+            let link = ShareLink::new();
+            for debug in [format!("{:?}", link), format!("{:#?}", link)] {
+                assert!(!secret_like.is_match(&debug), "{}", debug);
+                assert!(debug.contains(&Fingerprint::of(&link.token).to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn redacted_share_link_does_not_leak_token() {
+        for _ in 0..100 {
+            let link = ShareLink::new();
+            let token = general_purpose::URL_SAFE_NO_PAD.encode(link.token);
+            let redactions = [
+                link.redacted(),
+                link.share_link_token().redacted_display().to_string(),
+                format!("{:?}", link.share_link_token()),
+            ];
+            for redacted in redactions {
+                assert!(!crate::util::shares_substring(&redacted, &token, 12));
+                assert!(redacted.contains(&Fingerprint::of(&link.token).to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn share_channel_forms() {
+        use strum::IntoEnumIterator;
+        for channel in ShareChannel::iter() {
+            assert_eq!(ShareChannel::try_from(channel.to_byte()), Ok(channel));
+            assert_eq!(channel.to_string().parse::<ShareChannel>(), Ok(channel));
+            let json = serde_json::to_string(&channel).unwrap();
+            assert_eq!(json, format!("\"{}\"", channel));
+            assert_eq!(serde_json::from_str::<ShareChannel>(&json).unwrap(), channel);
+        }
+        assert_eq!(ShareChannel::Qr.to_string(), "qr");
+        assert_eq!(ShareChannel::try_from(0), Err(UnknownShareChannel(0)));
+        assert_eq!(ShareChannel::try_from(6), Err(UnknownShareChannel(6)));
+    }
+
+    #[test]
+    fn short_string_errors() {
+        assert_eq!(BucketSharePermissionFlags::from_short_string(""), Err(ShortPermError::Empty));
+        assert_eq!(
+            BucketSharePermissionFlags::from_short_string("rwx"),
+            Err(ShortPermError::UnknownCharacter { position: 2, character: 'x' })
+        );
+        assert_eq!(
+            BucketSharePermissionFlags::from_short_string("rwr"),
+            Err(ShortPermError::DuplicateCharacter { position: 2, character: 'r' })
+        );
+        assert_eq!(
+            BucketSharePermissionFlags::from_short_string("r-"),
+            Err(ShortPermError::InvalidLength(2))
+        );
+        assert_eq!(
+            BucketSharePermissionFlags::from_short_string("rv------"),
+            Err(ShortPermError::MisplacedCharacter {
+                position: 0,
+                character: 'r',
+                expected: 'v'
+            })
+        );
+    }
+
+    #[test]
+    fn diagnostics_point_at_the_token() {
+        let link = ShareLink::new();
+        let url: url::Url = link.to_string().parse().unwrap();
+        let (result, diagnostics) = ShareLink::parse_with_diagnostics(&url);
+        assert_eq!(result.unwrap().get_token(), link.get_token());
+        assert_eq!(diagnostics.to_string(), "ok\npath[0] token: 43 bytes, ok");
+
+        let url: url::Url = format!("https://{}{}/AAAA", DOMAIN_URL, SHARE_PATH_URL).parse().unwrap();
+        let (result, diagnostics) = ShareLink::parse_with_diagnostics(&url);
+        assert!(matches!(result, Err(ShareLinkParsingError::InvalidTokenLength)));
+        assert_eq!(diagnostics.failed_segment().map(|segment| segment.role), Some(SegmentRole::Token));
+        assert_eq!(diagnostics.to_string(), "error: InvalidTokenLength\npath[0] token: 4 bytes, InvalidTokenLength");
+
+        // Origin errors happen before any segment is decoded.
+        let url: url::Url = format!("https://example.com{}/AAAA", SHARE_PATH_URL).parse().unwrap();
+        let (_, diagnostics) = ShareLink::parse_with_diagnostics(&url);
+        assert_eq!(diagnostics.segments, []);
+        assert_eq!(diagnostics.to_string(), "error: InvalidHostDomain");
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn archive_round_trip() {
+        use rkyv::Deserialize;
+
+        let token = ShareLinkToken([7; 32]);
+        let bytes = rkyv::to_bytes::<_, 64>(&token).unwrap();
+        let archived = rkyv::check_archived_root::<ShareLinkToken>(&bytes).unwrap();
+        assert_eq!(archived.0, [7; 32]);
+        assert_eq!(archived.deserialize(&mut rkyv::Infallible), Ok(token));
+        assert!(rkyv::check_archived_root::<ShareLinkToken>(&bytes[..31]).is_err());
+
+        let flags = BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::SEARCH;
+        let bytes = rkyv::to_bytes::<_, 16>(&flags).unwrap();
+        assert_eq!(bytes.as_slice(), 0x81u32.to_le_bytes());
+        let archived = rkyv::check_archived_root::<BucketSharePermissionFlags>(&bytes).unwrap();
+        assert_eq!(archived.get(), flags);
+        assert_eq!(archived.deserialize(&mut rkyv::Infallible), Ok(flags));
+        assert!(rkyv::check_archived_root::<BucketSharePermissionFlags>(&bytes[..3]).is_err());
+
+        // Bits from a newer version stay in the archive but are never granted.
+        let bytes = rkyv::to_bytes::<_, 16>(&0x8000_0002u32).unwrap();
+        let archived = rkyv::check_archived_root::<BucketSharePermissionFlags>(&bytes).unwrap();
+        assert_eq!(archived.bits(), 0x8000_0002);
+        assert_eq!(archived.get(), BucketSharePermissionFlags::READ);
+    }
+
+    #[test]
+    fn visibility_templates() {
+        use BucketSharePermissionFlags as P;
+        let table = [
+            (BucketVisibility::Public, P::VIEW | P::READ | P::SEARCH, P::VIEW | P::READ | P::SEARCH | P::CLONE),
+            (BucketVisibility::PrivateShared, P::VIEW | P::READ, P::all() - P::DELETE_BUCKET),
+            (BucketVisibility::Private, P::empty(), P::empty()),
+        ];
+        for (visibility, suggested, max) in table {
+            assert_eq!(visibility.suggested_share_permissions(), suggested, "{}", visibility);
+            assert_eq!(visibility.max_allowed_share_permissions(), max, "{}", visibility);
+            // A suggestion never needs clamping.
+            assert_eq!(clamp_to_visibility(suggested, visibility), (suggested, P::empty()));
+        }
+        assert!(!BucketVisibility::Public.max_allowed_share_permissions().intersects(P::DELETE_BUCKET));
+    }
+
+    #[test]
+    fn clamp_reports_removed_bits() {
+        use BucketSharePermissionFlags as P;
+        for visibility in [BucketVisibility::Public, BucketVisibility::PrivateShared, BucketVisibility::Private] {
+            let max = visibility.max_allowed_share_permissions();
+            for bits in 0..=P::all().bits() {
+                let requested = P::from_bits(bits).unwrap();
+                let (granted, removed) = clamp_to_visibility(requested, visibility);
+                assert_eq!(granted | removed, requested);
+                assert!(!granted.intersects(removed));
+                assert!(max.contains(granted));
+                assert!(!max.intersects(removed));
+            }
+        }
+        assert_eq!(
+            clamp_to_visibility(P::READ | P::WRITE | P::DELETE_BUCKET, BucketVisibility::Public),
+            (P::READ, P::WRITE | P::DELETE_BUCKET)
+        );
+        assert_eq!(
+            clamp_to_visibility(P::READ | P::WRITE | P::DELETE_BUCKET, BucketVisibility::PrivateShared),
+            (P::READ | P::WRITE, P::DELETE_BUCKET)
+        );
+        assert_eq!(clamp_to_visibility(P::READ, BucketVisibility::Private), (P::empty(), P::READ));
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bucket_object::{BucketObjectKey, BucketObjectKeyError};
+
+// Most prefixes a single share link can be scoped to, keeps links short enough for chat clients.
+pub const MAX_SHARE_PREFIXES: usize = 16;
+
+/*
+* Scopes a share to part of a bucket. No wildcards:
+* A prefix ending in '/' matches every key below it, "/photos/" matches "/photos/2024/beach.jpg" but not "/photosX/file".
+* Any other prefix only matches exactly that key.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct SharePrefix(pub BucketObjectKey);
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum SharePrefixError {
+    #[error("{0} share prefixes given, max is {MAX_SHARE_PREFIXES}")]
+    TooManyPrefixes(usize),
+}
+
+impl SharePrefix {
+    pub fn is_subtree(&self) -> bool {
+        self.0.as_str().ends_with('/')
+    }
+
+    pub fn matches(&self, key: &BucketObjectKey) -> bool {
+        match self.is_subtree() {
+            true => key.as_str().starts_with(self.0.as_str()),
+            false => key == &self.0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn validate_count(prefixes: &[SharePrefix]) -> Result<(), SharePrefixError> {
+        match prefixes.len() > MAX_SHARE_PREFIXES {
+            true => Err(SharePrefixError::TooManyPrefixes(prefixes.len())),
+            false => Ok(()),
+        }
+    }
+}
+
+/*
+* Filter the storage service applies to every listed object of a scoped share.
+* No prefixes means the whole bucket is shared.
+*/
+pub fn effective_keys_filter(prefixes: &[SharePrefix]) -> impl Fn(&BucketObjectKey) -> bool + '_ {
+    move |key| prefixes.is_empty() || prefixes.iter().any(|prefix| prefix.matches(key))
+}
+
+impl fmt::Display for SharePrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for SharePrefix {
+    type Err = BucketObjectKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
+    }
+}
+
+impl From<BucketObjectKey> for SharePrefix {
+    fn from(key: BucketObjectKey) -> Self {
+        Self(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> BucketObjectKey {
+        key.parse().unwrap()
+    }
+
+    #[test]
+    fn subtree_and_exact_matching() {
+        let folder: SharePrefix = "/photos/".parse().unwrap();
+        assert!(folder.matches(&key("/photos/")));
+        assert!(folder.matches(&key("/photos/beach.jpg")));
+        assert!(folder.matches(&key("/photos/2024/beach.jpg")));
+        assert!(!folder.matches(&key("/photosX/file")));
+        assert!(!folder.matches(&key("/photos")));
+        assert!(!folder.matches(&key("/videos/photos/beach.jpg")));
+
+        let file: SharePrefix = "/photos/beach.jpg".parse().unwrap();
+        assert!(file.matches(&key("/photos/beach.jpg")));
+        assert!(!file.matches(&key("/photos/beach.jpg.bak")));
+        assert!(!file.matches(&key("/photos/beach.jpg/")));
+
+        let wildcard: SharePrefix = "/photos/*".parse().unwrap();
+        assert!(!wildcard.matches(&key("/photos/beach.jpg")));
+    }
+
+    #[test]
+    fn keys_filter() {
+        let prefixes = vec!["/photos/2024/".parse().unwrap(), "/notes.txt".parse().unwrap()];
+        let filter = effective_keys_filter(&prefixes);
+        assert!(filter(&key("/photos/2024/beach.jpg")));
+        assert!(filter(&key("/notes.txt")));
+        assert!(!filter(&key("/photos/2023/beach.jpg")));
+
+        let whole_bucket = effective_keys_filter(&[]);
+        assert!(whole_bucket(&key("/anything")));
+    }
+
+    #[test]
+    fn prefix_count_is_capped() {
+        let prefixes = (0..=MAX_SHARE_PREFIXES)
+            .map(|index| format!("/{}/", index).parse().unwrap())
+            .collect::<Vec<SharePrefix>>();
+        assert_eq!(SharePrefix::validate_count(&prefixes[..MAX_SHARE_PREFIXES]), Ok(()));
+        assert_eq!(
+            SharePrefix::validate_count(&prefixes),
+            Err(SharePrefixError::TooManyPrefixes(MAX_SHARE_PREFIXES + 1))
+        );
+    }
+}
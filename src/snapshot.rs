@@ -0,0 +1,261 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bucket_description::BucketName;
+use crate::bucket_guid::BucketGuid;
+use crate::bucket_object::ContentHash;
+use crate::expiry::ExpiryTimestamp;
+use crate::storage_quota::StorageQuota;
+use crate::util::HumanDuration;
+use crate::{BucketRegion, BucketStatus};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SnapshotId(pub uuid::Uuid);
+
+impl SnapshotId {
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4())
+    }
+}
+
+impl fmt::Display for SnapshotId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for SnapshotId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+/*
+* A snapshot is pending while its manifest is written, then complete until its retention runs out.
+* Pending, complete and expired snapshots can be deleted, deleting is final.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotStatus {
+    Pending,
+    Complete,
+    Expired,
+    Deleting,
+}
+
+impl SnapshotStatus {
+    // Staying in the same status is not a transition.
+    pub fn can_transition_to(&self, target: &SnapshotStatus) -> bool {
+        use SnapshotStatus::*;
+        matches!(
+            (self, target),
+            (Pending, Complete | Deleting) | (Complete, Expired | Deleting) | (Expired, Deleting)
+        )
+    }
+}
+
+// Point-in-time copy of a bucket's manifest. retention None keeps the snapshot until it is deleted.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BucketSnapshot {
+    pub id: SnapshotId,
+    pub bucket: BucketGuid,
+    pub taken_at: ExpiryTimestamp,
+    pub manifest_hash: ContentHash,
+    pub object_count: u64,
+    pub size: StorageQuota,
+    pub retention: Option<HumanDuration>,
+    pub status: SnapshotStatus,
+}
+
+impl BucketSnapshot {
+    // Order snapshots are listed in, newest first, snapshots taken in the same second by id.
+    pub fn canonical_cmp(&self, other: &Self) -> Ordering {
+        other.taken_at.cmp(&self.taken_at).then_with(|| self.id.cmp(&other.id))
+    }
+
+    pub fn sort_canonical(snapshots: &mut [BucketSnapshot]) {
+        snapshots.sort_by(|a, b| a.canonical_cmp(b));
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RestoreTarget {
+    // Replaces the contents of the snapshot's own bucket.
+    InPlace,
+    NewBucket { name: BucketName, region: BucketRegion },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RestoreRequest {
+    pub snapshot: SnapshotId,
+    pub target: RestoreTarget,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum RestoreError {
+    #[error("restore is for snapshot {requested}, not {actual}")]
+    SnapshotMismatch { requested: SnapshotId, actual: SnapshotId },
+    #[error("snapshot is {0}, only complete snapshots can be restored")]
+    SnapshotNotComplete(SnapshotStatus),
+    #[error("bucket is {0}, it can not be restored in place")]
+    BucketNotRestorable(BucketStatus),
+}
+
+impl RestoreRequest {
+    // bucket_status is the status of the snapshot's bucket, only checked for in-place restores.
+    pub fn validate(&self, snapshot: &BucketSnapshot, bucket_status: &BucketStatus) -> Result<(), RestoreError> {
+        if self.snapshot != snapshot.id {
+            return Err(RestoreError::SnapshotMismatch {
+                requested: self.snapshot,
+                actual: snapshot.id,
+            });
+        }
+        if snapshot.status != SnapshotStatus::Complete {
+            return Err(RestoreError::SnapshotNotComplete(snapshot.status));
+        }
+        if self.target == RestoreTarget::InPlace
+            && matches!(bucket_status, BucketStatus::Deleting | BucketStatus::Deleted)
+        {
+            return Err(RestoreError::BucketNotRestorable(bucket_status.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    fn snapshot(id: u128, taken_at: i64) -> BucketSnapshot {
+        BucketSnapshot {
+            id: SnapshotId(uuid::Uuid::from_u128(id)),
+            bucket: BucketGuid::new(uuid::Uuid::from_u128(1), uuid::Uuid::from_u128(2)),
+            taken_at: ExpiryTimestamp::from_unix_timestamp(taken_at).unwrap(),
+            manifest_hash: ContentHash([7; 32]),
+            object_count: 12,
+            size: StorageQuota(4096),
+            retention: Some("30d".parse().unwrap()),
+            status: SnapshotStatus::Complete,
+        }
+    }
+
+    #[test]
+    fn status_transitions() {
+        use SnapshotStatus::*;
+        let all = [Pending, Complete, Expired, Deleting];
+        let legal = [
+            (Pending, Complete),
+            (Pending, Deleting),
+            (Complete, Expired),
+            (Complete, Deleting),
+            (Expired, Deleting),
+        ];
+        for from in all {
+            for to in all {
+                assert_eq!(
+                    from.can_transition_to(&to),
+                    legal.contains(&(from, to)),
+                    "{} -> {}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_order_is_newest_first() {
+        let mut snapshots = vec![
+            snapshot(3, 1_717_200_000),
+            snapshot(1, 1_717_300_000),
+            snapshot(9, 1_717_250_000),
+            snapshot(2, 1_717_300_000),
+        ];
+        BucketSnapshot::sort_canonical(&mut snapshots);
+        let ids = snapshots
+            .iter()
+            .map(|snapshot| snapshot.id.0.as_u128())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2, 9, 3]);
+        assert_eq!(
+            snapshot(1, 1_717_200_000).canonical_cmp(&snapshot(1, 1_717_200_000)),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn restore_validation() {
+        let snapshot = snapshot(5, 1_717_200_000);
+        let in_place = RestoreRequest {
+            snapshot: snapshot.id,
+            target: RestoreTarget::InPlace,
+        };
+        let new_bucket = RestoreRequest {
+            snapshot: snapshot.id,
+            target: RestoreTarget::NewBucket {
+                name: "restored photos".to_string().try_into().unwrap(),
+                region: BucketRegion::EuropeCentral(0),
+            },
+        };
+        for status in BucketStatus::iter() {
+            let refused = matches!(status, BucketStatus::Deleting | BucketStatus::Deleted);
+            let expected = match refused {
+                true => Err(RestoreError::BucketNotRestorable(status.clone())),
+                false => Ok(()),
+            };
+            assert_eq!(in_place.validate(&snapshot, &status), expected, "{}", status);
+            assert_eq!(new_bucket.validate(&snapshot, &status), Ok(()), "{}", status);
+        }
+
+        let pending = BucketSnapshot {
+            status: SnapshotStatus::Pending,
+            ..snapshot.clone()
+        };
+        assert_eq!(
+            new_bucket.validate(&pending, &BucketStatus::Available),
+            Err(RestoreError::SnapshotNotComplete(SnapshotStatus::Pending))
+        );
+        let other = RestoreRequest {
+            snapshot: SnapshotId(uuid::Uuid::from_u128(6)),
+            ..in_place
+        };
+        assert_eq!(
+            other.validate(&snapshot, &BucketStatus::Available),
+            Err(RestoreError::SnapshotMismatch {
+                requested: other.snapshot,
+                actual: snapshot.id
+            })
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let snapshot = snapshot(5, 1_717_200_000);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(json.contains(r#""retention":"4w2d","status":"complete""#), "{}", json);
+        assert!(json.contains(r#""taken_at":"2024-06-01T00:00:00Z""#), "{}", json);
+        assert_eq!(serde_json::from_str::<BucketSnapshot>(&json).unwrap(), snapshot);
+
+        let request = RestoreRequest {
+            snapshot: snapshot.id,
+            target: RestoreTarget::NewBucket {
+                name: "restored".to_string().try_into().unwrap(),
+                region: BucketRegion::AmericaWest(2),
+            },
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(serde_json::from_str::<RestoreRequest>(&json).unwrap(), request);
+        let json = serde_json::to_string(&RestoreTarget::InPlace).unwrap();
+        assert_eq!(json, r#""InPlace""#);
+        assert_eq!(snapshot.id.to_string().parse(), Ok(snapshot.id));
+    }
+}
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::{BucketVisibility, PaymentPlan, Verification};
+
+// Share operations that can require the actor to verify again before they go through.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, strum::Display, strum::EnumIter, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OperationSensitivity {
+    CreatePublicLink,
+    CreateSecretLink,
+    ExtendExpiry,
+    GrantDeleteBucket,
+    TransferOwnership,
+}
+
+/*
+* Factors an operation requires by the visibility of the bucket, columns are Public, PrivateShared and Private.
+* Exposing a bucket that is not public needs at least EMAIL, anything that can lose the bucket needs TOTP.
+*/
+const STEP_UP_MATRIX: [(OperationSensitivity, [Verification; 3]); 5] = [
+    (
+        OperationSensitivity::CreatePublicLink,
+        [Verification::UNVERIFIED, Verification::EMAIL, Verification::EMAIL],
+    ),
+    (
+        OperationSensitivity::CreateSecretLink,
+        [Verification::UNVERIFIED, Verification::UNVERIFIED, Verification::EMAIL],
+    ),
+    (
+        OperationSensitivity::ExtendExpiry,
+        [Verification::UNVERIFIED, Verification::EMAIL, Verification::EMAIL],
+    ),
+    (
+        OperationSensitivity::GrantDeleteBucket,
+        [Verification::TOTP, Verification::TOTP, Verification::TOTP],
+    ),
+    (
+        OperationSensitivity::TransferOwnership,
+        [
+            Verification::EMAIL.union(Verification::TOTP),
+            Verification::EMAIL.union(Verification::TOTP),
+            Verification::EMAIL.union(Verification::TOTP),
+        ],
+    ),
+];
+
+// Added to every requirement for accounts without a paid plan, free accounts are where share link abuse comes from.
+const FREE_PLAN_MINIMUM: Verification = Verification::EMAIL;
+
+// Every factor the actor has to have verified recently enough for op, see check_step_up.
+#[allow(deprecated)]
+pub fn required_verification(
+    op: OperationSensitivity,
+    plan: PaymentPlan,
+    bucket_visibility: BucketVisibility,
+) -> Verification {
+    let column = match bucket_visibility {
+        BucketVisibility::Public => 0,
+        BucketVisibility::PrivateShared => 1,
+        BucketVisibility::Private => 2,
+    };
+    let required = STEP_UP_MATRIX
+        .iter()
+        .find(|(operation, _)| *operation == op)
+        .map(|(_, by_visibility)| by_visibility[column])
+        .expect("every operation is in the step-up matrix");
+    match plan {
+        PaymentPlan::Free | PaymentPlan::Canceled => required | FREE_PLAN_MINIMUM,
+        PaymentPlan::MeteredSubscription | PaymentPlan::MonthlySubscription | PaymentPlan::OneTime => required,
+    }
+}
+
+// When the actor last verified each factor in this session, None if never.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct VerificationState {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub email_verified_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub phone_verified_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub totp_verified_at: Option<OffsetDateTime>,
+}
+
+impl VerificationState {
+    // Time of a single factor, None for UNVERIFIED or combined flags.
+    pub fn verified_at(&self, factor: Verification) -> Option<OffsetDateTime> {
+        match factor {
+            Verification::EMAIL => self.email_verified_at,
+            Verification::PHONE => self.phone_verified_at,
+            Verification::TOTP => self.totp_verified_at,
+            _ => None,
+        }
+    }
+}
+
+// Factors never verified are missing, factors verified longer than max_age ago are stale. Never both empty.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[error("step-up authentication required, missing {missing:?}, stale {stale:?}")]
+pub struct StepUpRequired {
+    pub missing: Verification,
+    pub stale: Verification,
+}
+
+/*
+* Whether actual covers required. A factor verified exactly max_age ago is still fresh.
+* A verification time after now, clock skew between services, counts as fresh.
+*/
+pub fn check_step_up(
+    actual: &VerificationState,
+    required: Verification,
+    max_age: Duration,
+    now: OffsetDateTime,
+) -> Result<(), StepUpRequired> {
+    let mut missing = Verification::UNVERIFIED;
+    let mut stale = Verification::UNVERIFIED;
+    for factor in required.iter() {
+        match actual.verified_at(factor) {
+            None => missing |= factor,
+            Some(verified_at) if now - verified_at > max_age => stale |= factor,
+            Some(_) => {}
+        }
+    }
+    match missing.is_empty() && stale.is_empty() {
+        true => Ok(()),
+        false => Err(StepUpRequired { missing, stale }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+    use time::macros::datetime;
+
+    use super::*;
+
+    const VISIBILITIES: [BucketVisibility; 3] =
+        [BucketVisibility::Public, BucketVisibility::PrivateShared, BucketVisibility::Private];
+
+    #[test]
+    fn matrix_covers_every_operation_once() {
+        for op in OperationSensitivity::iter() {
+            assert_eq!(STEP_UP_MATRIX.iter().filter(|(operation, _)| *operation == op).count(), 1, "{}", op);
+        }
+        assert_eq!(STEP_UP_MATRIX.len(), OperationSensitivity::iter().count());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn policy() {
+        use OperationSensitivity::*;
+        for op in OperationSensitivity::iter() {
+            for plan in PaymentPlan::iter() {
+                for visibility in VISIBILITIES {
+                    let required = required_verification(op, plan, visibility);
+                    let paid = required_verification(op, PaymentPlan::MonthlySubscription, visibility);
+                    match plan {
+                        PaymentPlan::Free | PaymentPlan::Canceled => assert_eq!(required, paid | Verification::EMAIL),
+                        _ => assert_eq!(required, paid),
+                    }
+                    if op == GrantDeleteBucket || op == TransferOwnership {
+                        assert!(required.contains(Verification::TOTP), "{} {} {}", op, plan, visibility);
+                    }
+                    if visibility != BucketVisibility::Public && op == CreatePublicLink {
+                        assert!(required.contains(Verification::EMAIL), "{} {}", plan, visibility);
+                    }
+                }
+            }
+        }
+        let paid = PaymentPlan::MeteredSubscription;
+        assert_eq!(required_verification(CreateSecretLink, paid, BucketVisibility::Public), Verification::UNVERIFIED);
+        assert_eq!(required_verification(CreatePublicLink, paid, BucketVisibility::Private), Verification::EMAIL);
+        assert_eq!(
+            required_verification(TransferOwnership, paid, BucketVisibility::Public),
+            Verification::EMAIL | Verification::TOTP
+        );
+        assert_eq!(
+            required_verification(GrantDeleteBucket, PaymentPlan::Free, BucketVisibility::Public),
+            Verification::EMAIL | Verification::TOTP
+        );
+    }
+
+    #[test]
+    fn freshness_boundaries() {
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let max_age = Duration::minutes(15);
+        let state = |totp_verified_at| VerificationState {
+            totp_verified_at: Some(totp_verified_at),
+            ..Default::default()
+        };
+        let stale = Err(StepUpRequired {
+            missing: Verification::UNVERIFIED,
+            stale: Verification::TOTP,
+        });
+        assert_eq!(check_step_up(&state(now), Verification::TOTP, max_age, now), Ok(()));
+        assert_eq!(check_step_up(&state(now - max_age), Verification::TOTP, max_age, now), Ok(()));
+        let just_too_old = now - max_age - Duration::nanoseconds(1);
+        assert_eq!(check_step_up(&state(just_too_old), Verification::TOTP, max_age, now), stale);
+        assert_eq!(check_step_up(&state(now - Duration::days(1)), Verification::TOTP, max_age, now), stale);
+        assert_eq!(check_step_up(&state(now + Duration::minutes(1)), Verification::TOTP, max_age, now), Ok(()));
+        assert_eq!(check_step_up(&state(now), Verification::TOTP, Duration::ZERO, now), Ok(()));
+    }
+
+    #[test]
+    fn missing_and_stale_are_told_apart() {
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let max_age = Duration::minutes(15);
+        let state = VerificationState {
+            email_verified_at: Some(now - Duration::hours(1)),
+            phone_verified_at: Some(now - Duration::minutes(1)),
+            totp_verified_at: None,
+        };
+        let all = Verification::EMAIL | Verification::PHONE | Verification::TOTP;
+        let error = check_step_up(&state, all, max_age, now).unwrap_err();
+        assert_eq!(
+            error,
+            StepUpRequired {
+                missing: Verification::TOTP,
+                stale: Verification::EMAIL
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "step-up authentication required, missing Verification(TOTP), stale Verification(EMAIL)"
+        );
+        assert_eq!(check_step_up(&state, Verification::PHONE, max_age, now), Ok(()));
+        assert_eq!(check_step_up(&VerificationState::default(), Verification::UNVERIFIED, max_age, now), Ok(()));
+    }
+
+    #[test]
+    fn state_serde() {
+        let state = VerificationState {
+            email_verified_at: Some(datetime!(2024-06-01 12:00 UTC)),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(
+            json,
+            r#"{"email_verified_at":"2024-06-01T12:00:00Z","phone_verified_at":null,"totp_verified_at":null}"#
+        );
+        assert_eq!(serde_json::from_str::<VerificationState>(&json).unwrap(), state);
+        assert_eq!(serde_json::from_str::<VerificationState>("{}").unwrap(), VerificationState::default());
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::{format_bytes, parse_bytes, ByteSizeError, ByteStyle};
+
+// Amount of storage in bytes, used for quotas as well as for measured sizes. Serialized as the plain byte count.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StorageQuota(pub u64);
+
+impl StorageQuota {
+    pub const ZERO: Self = Self(0);
+
+    pub const fn from_bytes(bytes: u64) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn bytes(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+}
+
+// Binary units, "9.31 GiB", the alternate form {:#} uses decimal units, "10 GB".
+impl fmt::Display for StorageQuota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let style = match f.alternate() {
+            true => ByteStyle::Decimal,
+            false => ByteStyle::Binary,
+        };
+        f.write_str(&format_bytes(self.0, style))
+    }
+}
+
+// Either style, see parse_bytes.
+impl FromStr for StorageQuota {
+    type Err = ByteSizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_bytes(s).map(Self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[error("serving {attempted} bytes exceeds the cap of {} bytes", cap.bytes())]
+pub struct BandwidthExceeded {
+    pub cap: StorageQuota,
+    pub attempted: u64,
+}
+
+/*
+* Whether next_chunk more bytes may be served by a link that already served already_served, see
+* SecretShareLink::max_total_bytes. Serving exactly up to the cap is allowed, None is unlimited.
+* attempted saturates, a total past u64::MAX can never be within a cap.
+*/
+pub fn check_bandwidth(cap: Option<StorageQuota>, already_served: u64, next_chunk: u64) -> Result<(), BandwidthExceeded> {
+    let Some(cap) = cap else {
+        return Ok(());
+    };
+    let attempted = already_served.saturating_add(next_chunk);
+    match attempted <= cap.bytes() {
+        true => Ok(()),
+        false => Err(BandwidthExceeded { cap, attempted }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bandwidth_boundaries() {
+        let cap = StorageQuota::from_bytes(1_000);
+        assert_eq!(check_bandwidth(Some(cap), 0, 999), Ok(()));
+        assert_eq!(check_bandwidth(Some(cap), 400, 600), Ok(()));
+        assert_eq!(
+            check_bandwidth(Some(cap), 400, 601),
+            Err(BandwidthExceeded { cap, attempted: 1_001 })
+        );
+        // Nothing more once the cap is used up, an empty chunk is still fine.
+        assert_eq!(check_bandwidth(Some(cap), 1_000, 0), Ok(()));
+        assert!(check_bandwidth(Some(cap), 1_000, 1).is_err());
+        assert_eq!(check_bandwidth(Some(StorageQuota::ZERO), 0, 0), Ok(()));
+        assert!(check_bandwidth(Some(StorageQuota::ZERO), 0, 1).is_err());
+    }
+
+    #[test]
+    fn display_and_parse() {
+        let quota = StorageQuota::from_bytes(10_000_000_000);
+        assert_eq!(quota.to_string(), "9.31 GiB");
+        assert_eq!(format!("{:#}", quota), "10 GB");
+        assert_eq!("10 GB".parse(), Ok(quota));
+        assert_eq!("1 KiB".parse(), Ok(StorageQuota::from_bytes(1024)));
+        assert_eq!(StorageQuota::ZERO.to_string(), "0 B");
+        assert!("ten".parse::<StorageQuota>().is_err());
+    }
+
+    #[test]
+    fn unlimited_and_overflow() {
+        assert_eq!(check_bandwidth(None, u64::MAX, u64::MAX), Ok(()));
+        let cap = StorageQuota::from_bytes(u64::MAX);
+        assert_eq!(check_bandwidth(Some(cap), u64::MAX - 1, 1), Ok(()));
+        assert_eq!(
+            check_bandwidth(Some(StorageQuota::from_bytes(10)), u64::MAX, 1),
+            Err(BandwidthExceeded {
+                cap: StorageQuota::from_bytes(10),
+                attempted: u64::MAX
+            })
+        );
+    }
+}
@@ -0,0 +1,201 @@
+#![cfg(any(test, feature = "test-util"))]
+
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+
+use crate::{BucketEncryption, BucketRegion, MAX_CUSTOM_ENCRYPTION_NAME_LEN};
+
+/*
+* String round trips of enums: every value is written with Display, parsed back with FromStr and has to come back
+* equal. Exposed through the test-util feature so crates with their own string enums can check them the same way.
+*/
+
+// Every variant of T, for enums without payloads.
+pub fn assert_roundtrip_all<T>()
+where
+    T: IntoEnumIterator + Display + FromStr + PartialEq + Debug,
+{
+    assert_roundtrip_values(T::iter());
+}
+
+// The given values, for enums with payloads strum can not iterate.
+pub fn assert_roundtrip_values<T>(values: impl IntoIterator<Item = T>)
+where
+    T: Display + FromStr + PartialEq + Debug,
+{
+    assert_roundtrip_with(values, |parsed, value| parsed == value);
+}
+
+// Like assert_roundtrip_values with the comparison of parsed and original value given, for string forms that drop
+// part of the value on purpose.
+pub fn assert_roundtrip_with<T>(values: impl IntoIterator<Item = T>, same: impl Fn(&T, &T) -> bool)
+where
+    T: Display + FromStr + Debug,
+{
+    let mut checked = 0;
+    for value in values {
+        let string = value.to_string();
+        match string.parse::<T>() {
+            Ok(parsed) => {
+                assert!(same(&parsed, &value), "{:?} was written as {:?} and parsed as {:?}", value, string, parsed)
+            }
+            Err(_) => panic!("{:?} was written as {:?} which does not parse", value, string),
+        }
+        checked += 1;
+    }
+    assert!(checked > 0, "no values to round trip");
+}
+
+// Every region with the lowest, a small and the highest zone. The string form has no zone, see region_roundtrip.
+pub fn region_samples() -> Vec<BucketRegion> {
+    let mut samples = Vec::new();
+    for zone in [0, 1, u32::MAX] {
+        for region in BucketRegion::iter() {
+            samples.push(with_zone(region, zone));
+        }
+    }
+    samples
+}
+
+fn with_zone(region: BucketRegion, zone: u32) -> BucketRegion {
+    use BucketRegion::*;
+    match region {
+        EuropeCentral(_) => EuropeCentral(zone),
+        EuropeNorth(_) => EuropeNorth(zone),
+        EuropeSouth(_) => EuropeSouth(zone),
+        EuropeWest(_) => EuropeWest(zone),
+        EuropeEast(_) => EuropeEast(zone),
+        AmericaCentral(_) => AmericaCentral(zone),
+        AmericaNorth(_) => AmericaNorth(zone),
+        AmericaSouth(_) => AmericaSouth(zone),
+        AmericaWest(_) => AmericaWest(zone),
+        AmericaEast(_) => AmericaEast(zone),
+        AfricaCentral(_) => AfricaCentral(zone),
+        AfricaNorth(_) => AfricaNorth(zone),
+        AfricaSouth(_) => AfricaSouth(zone),
+        AfricaWest(_) => AfricaWest(zone),
+        AfricaEast(_) => AfricaEast(zone),
+        AsiaPacificCentral(_) => AsiaPacificCentral(zone),
+        AsiaPacificNorth(_) => AsiaPacificNorth(zone),
+        AsiaPacificSouth(_) => AsiaPacificSouth(zone),
+        AsiaPacificWest(_) => AsiaPacificWest(zone),
+        AsiaPacificEast(_) => AsiaPacificEast(zone),
+        MiddleEastCentral(_) => MiddleEastCentral(zone),
+        MiddleEastNorth(_) => MiddleEastNorth(zone),
+        MiddleEastSouth(_) => MiddleEastSouth(zone),
+        MiddleEastWest(_) => MiddleEastWest(zone),
+        MiddleEastEast(_) => MiddleEastEast(zone),
+        SouthAmericaCentral(_) => SouthAmericaCentral(zone),
+        SouthAmericaNorth(_) => SouthAmericaNorth(zone),
+        SouthAmericaSouth(_) => SouthAmericaSouth(zone),
+        SouthAmericaWest(_) => SouthAmericaWest(zone),
+        SouthAmericaEast(_) => SouthAmericaEast(zone),
+    }
+}
+
+// Regions are written without their zone and parse with zone 0, so only continent and direction come back.
+pub fn region_roundtrip() {
+    assert_roundtrip_with(region_samples(), |parsed, region| {
+        parsed.same_region(region) && *parsed == with_zone(region.clone(), 0)
+    });
+}
+
+// The fixed encryptions and custom names of the shortest and longest length and every allowed kind of char.
+pub fn encryption_samples() -> Vec<BucketEncryption> {
+    let longest = "x".repeat(MAX_CUSTOM_ENCRYPTION_NAME_LEN);
+    let mut samples = vec![BucketEncryption::None, BucketEncryption::AES256, BucketEncryption::ZeroKnowledge];
+    for name in ["a", "acme-hsm", "Vault_2", "None", "-_-", longest.as_str()] {
+        samples.push(BucketEncryption::custom(name).expect("valid custom name"));
+    }
+    samples
+}
+
+pub fn encryption_roundtrip() {
+    assert_roundtrip_values(encryption_samples());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::*;
+
+    // lib.rs enums without a string form.
+    const NO_STRING_FORM: [&str; 3] =
+        ["RegionClusterParsingError", "RegionClusterWireError", "BucketEncryptionParsingError"];
+
+    // Every string enum of lib.rs and its round trip, every_lib_enum_is_covered keeps this complete.
+    #[allow(deprecated)]
+    const ROUNDTRIPS: [(&str, fn()); 14] = [
+        ("BucketRegion", region_roundtrip),
+        ("DataResidency", assert_roundtrip_all::<DataResidency>),
+        ("Capability", assert_roundtrip_all::<Capability>),
+        ("BucketCompression", assert_roundtrip_all::<BucketCompression>),
+        ("VideoCodec", assert_roundtrip_all::<VideoCodec>),
+        ("BucketStatus", assert_roundtrip_all::<BucketStatus>),
+        ("AvailabilityStatus", assert_roundtrip_all::<AvailabilityStatus>),
+        ("BucketStorageClass", assert_roundtrip_all::<BucketStorageClass>),
+        ("PaymentModel", assert_roundtrip_all::<PaymentModel>),
+        ("BucketEncryption", encryption_roundtrip),
+        ("BucketVisibility", assert_roundtrip_all::<BucketVisibility>),
+        ("DownloadFormat", assert_roundtrip_all::<DownloadFormat>),
+        ("PaymentPlan", assert_roundtrip_all::<PaymentPlan>),
+        ("PaymentMethod", assert_roundtrip_all::<PaymentMethod>),
+    ];
+
+    #[test]
+    fn roundtrips() {
+        for (_, roundtrip) in ROUNDTRIPS {
+            roundtrip();
+        }
+    }
+
+    #[test]
+    fn every_lib_enum_is_covered() {
+        let declared = include_str!("lib.rs")
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("pub enum "))
+            .map(|rest| rest.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap())
+            .filter(|name| !NO_STRING_FORM.contains(name))
+            .collect::<BTreeSet<_>>();
+        let covered = ROUNDTRIPS.iter().map(|(name, _)| *name).collect::<BTreeSet<_>>();
+        assert_eq!(covered.len(), ROUNDTRIPS.len());
+        assert_eq!(declared, covered);
+    }
+
+    // Written in upper case, parsed only in lower case.
+    #[derive(Debug, PartialEq)]
+    struct CaseMismatch;
+
+    impl Display for CaseMismatch {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("Mismatch")
+        }
+    }
+
+    impl FromStr for CaseMismatch {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "mismatch" => Ok(CaseMismatch),
+                _ => Err(()),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "which does not parse")]
+    fn catches_case_mismatch() {
+        assert_roundtrip_values([CaseMismatch]);
+    }
+
+    #[test]
+    #[should_panic(expected = "parsed as")]
+    fn catches_lost_zone() {
+        assert_roundtrip_values([BucketRegion::EuropeNorth(3)]);
+    }
+}
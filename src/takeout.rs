@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::bucket_description::BucketName;
+use crate::bucket_object::ContentHash;
+use crate::storage_quota::StorageQuota;
+use crate::{DownloadFormat, UserId};
+
+/*
+* Data export of everything a user stores, requested from the account settings.
+* A requested export is built, then ready for download until it expires. Building can fail, a failed or expired export is final, the user requests a new one.
+* Serialized as an object tagged with "state", e.g. {"state": "failed", "reason": "..."}.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash, strum::Display, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum TakeoutStatus {
+    Requested,
+    Building,
+    Ready,
+    Expired,
+    Failed { reason: String },
+}
+
+impl TakeoutStatus {
+    // Staying in the same status is not a transition.
+    pub fn can_transition_to(&self, target: &TakeoutStatus) -> bool {
+        use TakeoutStatus::*;
+        matches!(
+            (self, target),
+            (Requested, Building | Failed { .. }) | (Building, Ready | Failed { .. }) | (Ready, Expired)
+        )
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, TakeoutStatus::Expired | TakeoutStatus::Failed { .. })
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TakeoutBucket {
+    pub bucket_id: uuid::Uuid,
+    pub name: BucketName,
+    pub object_count: u64,
+    pub size: StorageQuota,
+    // Hash of the bucket's object manifest inside the export.
+    pub manifest_hash: ContentHash,
+}
+
+// Index of an export, stored next to the archives and shown to the user before downloading.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TakeoutManifest {
+    pub user_id: UserId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub requested_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub completed_at: Option<OffsetDateTime>,
+    pub buckets: Vec<TakeoutBucket>,
+    pub total_size: StorageQuota,
+    pub format: DownloadFormat,
+    pub status: TakeoutStatus,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum TakeoutDiscrepancy {
+    #[error("total size is {actual:?}, buckets add up to {expected:?}")]
+    TotalSize { expected: StorageQuota, actual: StorageQuota },
+    #[error("bucket sizes overflow")]
+    SizeOverflow,
+    #[error("object counts overflow")]
+    ObjectCountOverflow,
+    #[error("bucket {index} has no objects but a size of {size:?}")]
+    SizeWithoutObjects { index: usize, size: StorageQuota },
+}
+
+impl TakeoutManifest {
+    // Sum of the bucket object counts, None on overflow.
+    pub fn object_count(&self) -> Option<u64> {
+        self.buckets.iter().try_fold(0u64, |count, bucket| count.checked_add(bucket.object_count))
+    }
+
+    // Reports every count and size that disagrees with the buckets listed.
+    pub fn verify_totals(&self) -> Result<(), Vec<TakeoutDiscrepancy>> {
+        let mut discrepancies = Vec::new();
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            if bucket.object_count == 0 && bucket.size != StorageQuota::ZERO {
+                discrepancies.push(TakeoutDiscrepancy::SizeWithoutObjects { index, size: bucket.size });
+            }
+        }
+        if self.object_count().is_none() {
+            discrepancies.push(TakeoutDiscrepancy::ObjectCountOverflow);
+        }
+        let size = self.buckets.iter().try_fold(StorageQuota::ZERO, |size, bucket| size.checked_add(bucket.size));
+        match size {
+            Some(expected) if expected != self.total_size => {
+                discrepancies.push(TakeoutDiscrepancy::TotalSize { expected, actual: self.total_size })
+            }
+            Some(_) => {}
+            None => discrepancies.push(TakeoutDiscrepancy::SizeOverflow),
+        }
+        if discrepancies.is_empty() {
+            Ok(())
+        } else {
+            Err(discrepancies)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    const TAKEOUT_FIXTURE: &str = include_str!("../testdata/takeout_manifest.json");
+
+    fn bucket(index: u8, object_count: u64, size: u64) -> TakeoutBucket {
+        TakeoutBucket {
+            bucket_id: uuid::Uuid::from_bytes([index; 16]),
+            name: format!("bucket {}", index).try_into().unwrap(),
+            object_count,
+            size: StorageQuota(size),
+            manifest_hash: ContentHash([index; 32]),
+        }
+    }
+
+    fn manifest() -> TakeoutManifest {
+        TakeoutManifest {
+            user_id: "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            requested_at: datetime!(2024-06-01 08:00 UTC),
+            completed_at: Some(datetime!(2024-06-01 08:42:10 UTC)),
+            buckets: vec![bucket(1, 120, 5_368_709_120), bucket(2, 3, 2_048), bucket(3, 0, 0)],
+            total_size: StorageQuota(5_368_711_168),
+            format: DownloadFormat::Zip,
+            status: TakeoutStatus::Ready,
+        }
+    }
+
+    #[test]
+    fn takeout_golden_json() {
+        assert_eq!(serde_json::to_string_pretty(&manifest()).unwrap() + "\n", TAKEOUT_FIXTURE);
+        assert_eq!(serde_json::from_str::<TakeoutManifest>(TAKEOUT_FIXTURE).unwrap(), manifest());
+
+        let failed = TakeoutStatus::Failed { reason: "storage node unavailable".to_string() };
+        let json = serde_json::to_string(&failed).unwrap();
+        assert_eq!(json, r#"{"state":"failed","reason":"storage node unavailable"}"#);
+        assert_eq!(serde_json::from_str::<TakeoutStatus>(&json).unwrap(), failed);
+        assert_eq!(serde_json::to_string(&TakeoutStatus::Requested).unwrap(), r#"{"state":"requested"}"#);
+        assert!(serde_json::from_str::<TakeoutStatus>(r#"{"state":"failed"}"#).is_err());
+    }
+
+    #[test]
+    fn status_transitions() {
+        use TakeoutStatus::*;
+        let failed = Failed { reason: "timeout".to_string() };
+        let all = [Requested, Building, Ready, Expired, failed.clone()];
+        let legal = [
+            (Requested, Building),
+            (Requested, failed.clone()),
+            (Building, Ready),
+            (Building, failed.clone()),
+            (Ready, Expired),
+        ];
+        for from in &all {
+            for to in &all {
+                let expected = legal.iter().any(|(a, b)| a == from && b == to);
+                assert_eq!(from.can_transition_to(to), expected, "{} -> {}", from, to);
+            }
+        }
+        assert!(all
+            .iter()
+            .filter(|status| status.is_final())
+            .all(|status| all.iter().all(|to| !status.can_transition_to(to))));
+        assert_eq!(failed.to_string(), "failed");
+    }
+
+    #[test]
+    fn consistent_totals() {
+        assert_eq!(manifest().verify_totals(), Ok(()));
+        assert_eq!(manifest().object_count(), Some(123));
+        let empty = TakeoutManifest { buckets: Vec::new(), total_size: StorageQuota::ZERO, ..manifest() };
+        assert_eq!(empty.verify_totals(), Ok(()));
+    }
+
+    #[test]
+    fn inconsistent_totals_are_reported() {
+        let mut manifest = manifest();
+        manifest.total_size = StorageQuota(5_368_709_120);
+        manifest.buckets[2].size = StorageQuota(1);
+        assert_eq!(
+            manifest.verify_totals(),
+            Err(vec![
+                TakeoutDiscrepancy::SizeWithoutObjects { index: 2, size: StorageQuota(1) },
+                TakeoutDiscrepancy::TotalSize {
+                    expected: StorageQuota(5_368_711_169),
+                    actual: StorageQuota(5_368_709_120)
+                },
+            ])
+        );
+
+        manifest.buckets = vec![bucket(1, u64::MAX, u64::MAX), bucket(2, 1, 1)];
+        assert_eq!(
+            manifest.verify_totals(),
+            Err(vec![TakeoutDiscrepancy::ObjectCountOverflow, TakeoutDiscrepancy::SizeOverflow])
+        );
+        assert_eq!(manifest.object_count(), None);
+    }
+}
@@ -0,0 +1,161 @@
+#![cfg(feature = "test-util")]
+
+use aes_gcm::Aes256Gcm;
+use sha3::{Digest, Sha3_256};
+use time::macros::datetime;
+use time::OffsetDateTime;
+
+use crate::clock::FixedClock;
+use crate::expiry::ExpiryTimestamp;
+use crate::secret_share_link::SecretShareLink;
+use crate::share_link::BucketSharePermissionFlags;
+use crate::{BucketId, UserId};
+
+const TEST_IDENTITY_CONTEXT: &[u8] = b"bucketdrive-test-identity-v1";
+
+// issued_at of every link made by a TestIdentity, 2024-06-01T00:00:00Z.
+pub const TEST_ISSUED_AT: OffsetDateTime = datetime!(2024-06-01 0:00 UTC);
+// expires of make_expired_link, a day after TEST_ISSUED_AT.
+pub const TEST_EXPIRED_AT: OffsetDateTime = datetime!(2024-06-02 0:00 UTC);
+
+// TestIdentity::deterministic(1) and (2), pinned by tests so fixtures built on them never drift.
+pub const TEST_PUBLIC_KEY_1: [u8; 32] = [
+    85, 107, 140, 167, 26, 224, 12, 156, 34, 199, 175, 78, 96, 128, 177, 114,
+    30, 87, 82, 239, 221, 171, 138, 10, 77, 96, 236, 164, 211, 202, 89, 219,
+];
+pub const TEST_USER_ID_1: UserId = uuid::uuid!("a8e2a512-7b2f-4e5b-8fe6-b2a1976dfd15");
+pub const TEST_BUCKET_ID_1: BucketId = uuid::uuid!("6cc85b0b-4db3-474b-952d-54106fff3f70");
+pub const TEST_PUBLIC_KEY_2: [u8; 32] = [
+    154, 34, 94, 203, 171, 34, 101, 13, 243, 72, 42, 227, 143, 18, 180, 109,
+    147, 20, 169, 178, 175, 64, 113, 210, 34, 236, 251, 199, 71, 78, 134, 208,
+];
+pub const TEST_USER_ID_2: UserId = uuid::uuid!("06cda5f8-2824-477c-8e23-c192a277add9");
+pub const TEST_BUCKET_ID_2: BucketId = uuid::uuid!("8a97f679-191a-4406-b643-828c79871e35");
+
+/*
+* Signing key pair, bucket and bucket key for tests of crates using this one, enable the test-util feature in
+* dev-dependencies. Everything is derived from the seed with SHA3-256, the same seed always gives the same identity.
+* Never use these keys outside of tests, anyone can derive them.
+*/
+#[derive(Debug, Clone)]
+pub struct TestIdentity {
+    pub seed: u64,
+    pub key_pair: ed25519_compact::KeyPair,
+    pub bucket_key: aes_gcm::Key<Aes256Gcm>,
+    pub user_id: UserId,
+    pub bucket_id: BucketId,
+}
+
+// SHA3-256 over the context, label and seed, so every part of an identity comes from its own hash.
+fn derive(label: &[u8], seed: u64) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(TEST_IDENTITY_CONTEXT);
+    hasher.update([label.len() as u8]);
+    hasher.update(label);
+    hasher.update(seed.to_be_bytes());
+    hasher.finalize().into()
+}
+
+// Version 4 uuid from the first 16 bytes.
+fn derive_uuid(label: &[u8], seed: u64) -> uuid::Uuid {
+    let bytes = derive(label, seed)[..16].try_into().expect("16 of 32 bytes");
+    uuid::Builder::from_random_bytes(bytes).into_uuid()
+}
+
+impl TestIdentity {
+    pub fn deterministic(seed: u64) -> Self {
+        Self {
+            seed,
+            key_pair: ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(derive(b"signing key", seed))),
+            bucket_key: derive(b"bucket key", seed).into(),
+            user_id: derive_uuid(b"user id", seed),
+            bucket_id: derive_uuid(b"bucket id", seed),
+        }
+    }
+
+    pub fn public_key(&self) -> ed25519_compact::PublicKey {
+        self.key_pair.pk
+    }
+
+    // Link to the identity's bucket signed by its key, issued at TEST_ISSUED_AT.
+    pub fn make_link(
+        &self,
+        permission: BucketSharePermissionFlags,
+        expires: Option<ExpiryTimestamp>,
+    ) -> SecretShareLink {
+        let builder = SecretShareLink::builder(self.user_id, self.bucket_id, self.bucket_key).permission(permission);
+        let builder = match expires {
+            Some(expires) => builder.expires(expires),
+            None => builder,
+        };
+        builder
+            .build_with_clock(&self.key_pair.sk, &FixedClock(TEST_ISSUED_AT))
+            .expect("links without a time window, prefixes or visibility always build")
+    }
+
+    // VIEW | READ link that expired at TEST_EXPIRED_AT.
+    pub fn make_expired_link(&self) -> SecretShareLink {
+        let expires = ExpiryTimestamp::try_from(TEST_EXPIRED_AT).expect("TEST_EXPIRED_AT is after MIN_EXPIRY");
+        self.make_link(BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ, Some(expires))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret_share_link::SecretShareLinkVerifySignatureError;
+
+    /*
+    * Downstream fixtures are built on these values, a failure here means the derivation changed and every one of
+    * them breaks. Bump TEST_IDENTITY_CONTEXT and the constants together only on purpose.
+    */
+    #[test]
+    fn pinned_identities() {
+        let pinned = [
+            (1, TEST_PUBLIC_KEY_1, TEST_USER_ID_1, TEST_BUCKET_ID_1),
+            (2, TEST_PUBLIC_KEY_2, TEST_USER_ID_2, TEST_BUCKET_ID_2),
+        ];
+        for (seed, public_key, user_id, bucket_id) in pinned {
+            let identity = TestIdentity::deterministic(seed);
+            assert_eq!(*identity.public_key(), public_key, "{}", seed);
+            assert_eq!(identity.user_id, user_id, "{}", seed);
+            assert_eq!(identity.bucket_id, bucket_id, "{}", seed);
+            assert_eq!(identity.user_id.get_version_num(), 4);
+        }
+        assert_ne!(TestIdentity::deterministic(1).bucket_key, TestIdentity::deterministic(2).bucket_key);
+    }
+
+    #[test]
+    fn pinned_link() {
+        let link = TestIdentity::deterministic(1)
+            .make_link(BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ, None);
+        assert_eq!(
+            link.to_string(),
+            "https://bucketdrive.co/api/v1/share/a8e2a512-7b2f-4e5b-8fe6-b2a1976dfd15/\
+            6cc85b0b-4db3-474b-952d-54106fff3f70#v2.162#5_gkJXFPIDv5IeMoyyfDLraeILE3gtfhHWsFATDSzlQ#AAAAAw\
+            #ia=AAAAAGZaZIA#rYRDkw1RINwVVq4WrtwTdmd0z-UDwrXvIPfoPceMf7kDC_ISAPC58yegD4p9jF3tCBAaYBEB1prsBqZnvE8cBw\
+            #ck=pCoQuQ"
+        );
+        let identity = TestIdentity::deterministic(1);
+        assert_eq!(identity.make_link(BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ, None), link);
+    }
+
+    #[test]
+    fn links_verify_with_the_identity_key() {
+        let identity = TestIdentity::deterministic(7);
+        let expires = ExpiryTimestamp::try_from(datetime!(2099-01-01 0:00 UTC)).unwrap();
+        let link = identity.make_link(BucketSharePermissionFlags::all(), Some(expires));
+        assert_eq!(link.issued_at, Some(TEST_ISSUED_AT.try_into().unwrap()));
+        assert_eq!(link.verify(identity.public_key(), OffsetDateTime::now_utc()), Ok(()));
+        assert!(link.verify(TestIdentity::deterministic(8).public_key(), OffsetDateTime::now_utc()).is_err());
+
+        let expired = identity.make_expired_link();
+        assert_eq!(expired.verify(identity.public_key(), TEST_ISSUED_AT), Ok(()));
+        assert_eq!(
+            expired.verify(identity.public_key(), OffsetDateTime::now_utc()),
+            Err(SecretShareLinkVerifySignatureError::Expired {
+                expired_at: TEST_EXPIRED_AT.try_into().unwrap()
+            })
+        );
+    }
+}
@@ -0,0 +1,219 @@
+#![cfg(feature = "secret_share_link")]
+
+use aes_gcm::{self, Aes256Gcm};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::FixedClock;
+use crate::expiry::ExpiryTimestamp;
+use crate::secret_share_link::SecretShareLink;
+use crate::share_link::{BucketSharePermissionFlags, ShareChannel};
+use crate::storage_quota::StorageQuota;
+use crate::UserId;
+
+/*
+* Secret share link test vectors for implementations in other languages, committed as testdata/secret_share_link_vectors.json.
+* Binary fields are lowercase hex, timestamps are unix seconds, ids are hyphenated uuids.
+* canonical_bytes is the input of the SHA3-256 token, the signature is ed25519 over the token by the key derived from seed.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SecretShareLinkVector {
+    pub name: String,
+    pub seed: String,
+    pub public_key: String,
+    pub user_id: uuid::Uuid,
+    pub bucket_id: uuid::Uuid,
+    pub bucket_key: String,
+    pub permission: u32,
+    pub expires: Option<i64>,
+    pub not_before: Option<i64>,
+    pub prefixes: Vec<String>,
+    pub channel: Option<ShareChannel>,
+    pub recipient: Option<UserId>,
+    pub issued_at: Option<i64>,
+    pub max_total_bytes: Option<u64>,
+    pub url: String,
+    pub canonical_bytes: String,
+    pub token: String,
+    pub signature: String,
+}
+
+// Inputs of a single vector, everything else is derived from them.
+struct VectorInput {
+    name: String,
+    permission: BucketSharePermissionFlags,
+    expires: Option<i64>,
+    not_before: Option<i64>,
+    prefixes: Vec<&'static str>,
+    channel: Option<ShareChannel>,
+    recipient: Option<UserId>,
+    // None for a link from before issue times were recorded.
+    issued_at: Option<i64>,
+    max_total_bytes: Option<u64>,
+}
+
+impl VectorInput {
+    fn new(name: &str, permission: BucketSharePermissionFlags) -> Self {
+        Self {
+            name: name.to_string(),
+            permission,
+            expires: None,
+            not_before: None,
+            prefixes: Vec::new(),
+            channel: None,
+            recipient: None,
+            issued_at: Some(ISSUED_AT),
+            max_total_bytes: None,
+        }
+    }
+}
+
+// 2024-06-01T00:00:00Z.
+const ISSUED_AT: i64 = 1_717_200_000;
+// 2025-01-01T00:00:00Z and 2026-01-01T00:00:00Z.
+const EXPIRES: i64 = 1_735_689_600;
+const LATER_EXPIRES: i64 = 1_767_225_600;
+// 2099-12-31T23:59:59Z, inside the expiry horizon until 2199.
+const FAR_FUTURE_EXPIRES: i64 = 4_102_444_799;
+const RECIPIENT: UserId = uuid::Uuid::from_u128(0x5a5a5a5a_0000_4000_8000_00000000000f);
+
+fn inputs() -> Vec<VectorInput> {
+    let view_read = BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ;
+    let mut inputs = vec![
+        VectorInput::new("no_expiry", view_read),
+        VectorInput {
+            expires: Some(EXPIRES),
+            ..VectorInput::new("expiry", view_read)
+        },
+        VectorInput {
+            expires: Some(ExpiryTimestamp::MIN.unix_timestamp()),
+            ..VectorInput::new("expiry_at_minimum", view_read)
+        },
+        VectorInput {
+            expires: Some(FAR_FUTURE_EXPIRES),
+            ..VectorInput::new("expiry_far_future", view_read)
+        },
+        VectorInput {
+            not_before: Some(EXPIRES),
+            ..VectorInput::new("not_before_without_expiry", view_read)
+        },
+        VectorInput {
+            expires: Some(LATER_EXPIRES),
+            not_before: Some(EXPIRES),
+            ..VectorInput::new("not_before_and_expiry", view_read)
+        },
+        VectorInput {
+            prefixes: vec!["/photos/", "/notes.txt"],
+            ..VectorInput::new("prefixes", view_read)
+        },
+        VectorInput {
+            channel: Some(ShareChannel::Qr),
+            ..VectorInput::new("channel", view_read)
+        },
+        VectorInput {
+            recipient: Some(RECIPIENT),
+            ..VectorInput::new("recipient", view_read)
+        },
+        VectorInput {
+            expires: Some(LATER_EXPIRES),
+            not_before: Some(EXPIRES),
+            prefixes: vec!["/shared/"],
+            channel: Some(ShareChannel::Email),
+            recipient: Some(RECIPIENT),
+            ..VectorInput::new("all_optional_fields", BucketSharePermissionFlags::all())
+        },
+        VectorInput {
+            expires: Some(EXPIRES),
+            issued_at: None,
+            ..VectorInput::new("without_issued_at", view_read)
+        },
+        VectorInput::new("no_permissions", BucketSharePermissionFlags::empty()),
+        VectorInput::new("all_permissions", BucketSharePermissionFlags::all()),
+    ];
+    for (name, flag) in BucketSharePermissionFlags::all().iter_names() {
+        inputs.push(VectorInput::new(&format!("permission_{}", name.to_lowercase()), flag));
+    }
+    // Appended, the index of a vector seeds its keys and ids.
+    inputs.push(VectorInput {
+        max_total_bytes: Some(5_000_000_000),
+        ..VectorInput::new("max_total_bytes", view_read)
+    });
+    inputs
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/*
+* Deterministic, every vector has its own seed and key derived from its index.
+* The signature is deterministic too, plain RFC 8032 Ed25519 without noise.
+*/
+pub fn secret_share_link_vectors() -> Vec<SecretShareLinkVector> {
+    inputs()
+        .into_iter()
+        .enumerate()
+        .map(|(index, input)| {
+            let index = index as u8 + 1;
+            let seed = [index; 32];
+            let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(seed));
+            let bucket_key = std::array::from_fn::<u8, 32, _>(|byte| byte as u8 ^ index);
+            let user_id = uuid::Uuid::from_u128(0x11111111_0000_4000_8000_000000000000 | index as u128);
+            let bucket_id = uuid::Uuid::from_u128(0x22222222_0000_4000_8000_000000000000 | index as u128);
+
+            let timestamp = |value: i64| ExpiryTimestamp::from_unix_timestamp(value).expect("vector timestamp in range");
+            let mut builder = SecretShareLink::builder(user_id, bucket_id, *aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key))
+                .permission(input.permission)
+                .prefixes(input.prefixes.iter().map(|prefix| prefix.parse().expect("valid vector prefix")).collect());
+            if let Some(expires) = input.expires {
+                builder = builder.expires(timestamp(expires));
+            }
+            if let Some(not_before) = input.not_before {
+                builder = builder.not_before(timestamp(not_before));
+            }
+            if let Some(channel) = input.channel {
+                builder = builder.channel(channel);
+            }
+            if let Some(recipient) = input.recipient {
+                builder = builder.recipient(recipient);
+            }
+            if let Some(max_total_bytes) = input.max_total_bytes {
+                builder = builder.max_total_bytes(StorageQuota::from_bytes(max_total_bytes));
+            }
+            let link = match input.issued_at {
+                Some(issued_at) => builder
+                    .build_with_clock(&key_pair.sk, &FixedClock(timestamp(issued_at).as_offset_date_time()))
+                    .expect("valid vector input"),
+                // The builder always records the issue time, new does not.
+                None => SecretShareLink::new(
+                    user_id,
+                    bucket_id,
+                    *aes_gcm::Key::<Aes256Gcm>::from_slice(&bucket_key),
+                    input.permission,
+                    input.expires.map(timestamp),
+                    &key_pair.sk,
+                ),
+            };
+
+            SecretShareLinkVector {
+                name: input.name,
+                seed: hex(&seed),
+                public_key: hex(key_pair.pk.as_slice()),
+                user_id,
+                bucket_id,
+                bucket_key: hex(&bucket_key),
+                permission: input.permission.bits(),
+                expires: input.expires,
+                not_before: input.not_before,
+                prefixes: input.prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+                channel: input.channel,
+                recipient: input.recipient,
+                issued_at: input.issued_at,
+                max_total_bytes: input.max_total_bytes,
+                url: link.to_string(),
+                canonical_bytes: hex(&link.canonical_bytes()),
+                token: hex(&link.get_token()),
+                signature: hex(link.signature.as_slice()),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use time::{Date, Duration, Month, OffsetDateTime, UtcOffset};
+
+/*
+* Width of the bins of a statistics time series, in UTC so every consumer bins around the same midnight.
+* A bin starts at its truncated timestamp and includes it, weeks are ISO weeks starting on Monday.
+*/
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Hash,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TimeBucket {
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl TimeBucket {
+    // Start of the bin containing t, in UTC.
+    pub fn truncate(&self, t: OffsetDateTime) -> OffsetDateTime {
+        let t = t.to_offset(UtcOffset::UTC);
+        let date = t.date();
+        match self {
+            TimeBucket::Hour => date.with_hms(t.hour(), 0, 0).expect("hour of a valid time").assume_utc(),
+            TimeBucket::Day => date.midnight().assume_utc(),
+            TimeBucket::Week => {
+                let monday = date
+                    .checked_sub(Duration::days(date.weekday().number_days_from_monday().into()))
+                    .unwrap_or(Date::MIN);
+                monday.midnight().assume_utc()
+            }
+            TimeBucket::Month => {
+                let first = Date::from_calendar_date(date.year(), date.month(), 1).expect("day 1 of a valid month");
+                first.midnight().assume_utc()
+            }
+        }
+    }
+
+    // Start of the bin after the one containing t. Panics when that is after Date::MAX.
+    pub fn next(&self, t: OffsetDateTime) -> OffsetDateTime {
+        let start = self.truncate(t);
+        match self {
+            TimeBucket::Hour => start + Duration::HOUR,
+            TimeBucket::Day => start + Duration::DAY,
+            TimeBucket::Week => start + Duration::WEEK,
+            TimeBucket::Month => {
+                let year = match start.month() {
+                    Month::December => start.year() + 1,
+                    _ => start.year(),
+                };
+                let date = Date::from_calendar_date(year, start.month().next(), 1).expect("next month after Date::MAX");
+                date.midnight().assume_utc()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum TimeSeriesError {
+    #[error("{timestamp} is not the start of a {bucket} bucket")]
+    NotBucketStart { bucket: TimeBucket, timestamp: OffsetDateTime },
+    #[error("bucket {0} appears more than once")]
+    DuplicateBucket(OffsetDateTime),
+}
+
+/*
+* Values per bin, e.g. downloads per day of a bucket. Only bins that were incremented are stored, densify fills in
+* the others for charts.
+* JSON is {"bucket": "day", "points": [["2024-06-01T00:00:00Z", 3], ...]} with points in time order. Deserializing
+* rejects timestamps that are not the start of a bin and bins listed twice.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TimeSeries<V> {
+    bucket: TimeBucket,
+    points: BTreeMap<OffsetDateTime, V>,
+}
+
+impl<V> TimeSeries<V> {
+    pub fn new(bucket: TimeBucket) -> Self {
+        Self { bucket, points: BTreeMap::new() }
+    }
+
+    pub fn bucket(&self) -> TimeBucket {
+        self.bucket
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn get(&self, at: OffsetDateTime) -> Option<&V> {
+        self.points.get(&self.bucket.truncate(at))
+    }
+
+    // Stored bins starting in [truncate(from), to), in time order.
+    pub fn range(&self, from: OffsetDateTime, to: OffsetDateTime) -> impl Iterator<Item = (OffsetDateTime, &V)> {
+        let from = self.bucket.truncate(from);
+        // BTreeMap::range panics on an inverted range.
+        let to = to.max(from);
+        self.points.range(from..to).map(|(start, value)| (*start, value))
+    }
+}
+
+impl<V: Default + AddAssign> TimeSeries<V> {
+    pub fn increment(&mut self, at: OffsetDateTime, by: V) {
+        *self.points.entry(self.bucket.truncate(at)).or_default() += by;
+    }
+}
+
+impl<V: Clone> TimeSeries<V> {
+    // Every bin starting in [truncate(from), to), with default for bins that were never incremented.
+    pub fn densify(
+        &self,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        default: V,
+    ) -> impl Iterator<Item = (OffsetDateTime, V)> + '_ {
+        let bucket = self.bucket;
+        std::iter::successors(Some(bucket.truncate(from)), move |start| Some(bucket.next(*start)))
+            .take_while(move |start| *start < to)
+            .map(move |start| (start, self.points.get(&start).cloned().unwrap_or_else(|| default.clone())))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Point<V>(#[serde(with = "time::serde::rfc3339")] OffsetDateTime, V);
+
+#[derive(Serialize)]
+struct SerializedTimeSeries<'a, V> {
+    bucket: TimeBucket,
+    points: Vec<Point<&'a V>>,
+}
+
+#[derive(Deserialize)]
+struct DeserializedTimeSeries<V> {
+    bucket: TimeBucket,
+    points: Vec<Point<V>>,
+}
+
+impl<V: Serialize> Serialize for TimeSeries<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let points = self.points.iter().map(|(start, value)| Point(*start, value)).collect();
+        SerializedTimeSeries { bucket: self.bucket, points }.serialize(serializer)
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for TimeSeries<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let DeserializedTimeSeries { bucket, points } = DeserializedTimeSeries::deserialize(deserializer)?;
+        let mut series = Self::new(bucket);
+        for Point(timestamp, value) in points {
+            // Compares instants, start is the same bin in UTC.
+            let start = bucket.truncate(timestamp);
+            if start != timestamp {
+                return Err(D::Error::custom(TimeSeriesError::NotBucketStart { bucket, timestamp }));
+            }
+            if series.points.insert(start, value).is_some() {
+                return Err(D::Error::custom(TimeSeriesError::DuplicateBucket(timestamp)));
+            }
+        }
+        Ok(series)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn truncate_and_next() {
+        let ns = Duration::NANOSECOND;
+        let check = |bucket: TimeBucket, cases: &[(OffsetDateTime, OffsetDateTime, OffsetDateTime)]| {
+            for &(t, start, next) in cases {
+                assert_eq!(bucket.truncate(t), start, "{} {}", bucket, t);
+                assert_eq!(bucket.truncate(t).offset(), UtcOffset::UTC);
+                assert_eq!(bucket.next(t), next, "{} {}", bucket, t);
+                assert_eq!(bucket.truncate(start), start, "{} {}", bucket, t);
+            }
+        };
+        check(TimeBucket::Hour, &[
+            (datetime!(2024-06-01 13:00 UTC), datetime!(2024-06-01 13:00 UTC), datetime!(2024-06-01 14:00 UTC)),
+            (datetime!(2024-06-01 13:00 UTC) - ns, datetime!(2024-06-01 12:00 UTC), datetime!(2024-06-01 13:00 UTC)),
+            (datetime!(2024-06-01 23:59:59 UTC), datetime!(2024-06-01 23:00 UTC), datetime!(2024-06-02 0:00 UTC)),
+        ]);
+        check(TimeBucket::Day, &[
+            (datetime!(2024-06-01 0:00 UTC), datetime!(2024-06-01 0:00 UTC), datetime!(2024-06-02 0:00 UTC)),
+            (datetime!(2024-06-01 0:00 UTC) - ns, datetime!(2024-05-31 0:00 UTC), datetime!(2024-06-01 0:00 UTC)),
+            // 01:30 in UTC+2 is still the day before in UTC.
+            (datetime!(2024-06-01 1:30 +2), datetime!(2024-05-31 0:00 UTC), datetime!(2024-06-01 0:00 UTC)),
+            (datetime!(2024-12-31 12:00 UTC), datetime!(2024-12-31 0:00 UTC), datetime!(2025-01-01 0:00 UTC)),
+        ]);
+        check(TimeBucket::Week, &[
+            (datetime!(2024-06-03 0:00 UTC), datetime!(2024-06-03 0:00 UTC), datetime!(2024-06-10 0:00 UTC)),
+            (datetime!(2024-06-03 0:00 UTC) - ns, datetime!(2024-05-27 0:00 UTC), datetime!(2024-06-03 0:00 UTC)),
+            (datetime!(2024-06-09 23:59 UTC), datetime!(2024-06-03 0:00 UTC), datetime!(2024-06-10 0:00 UTC)),
+            // 2021-01-03 is a Sunday in ISO week 53 of 2020.
+            (datetime!(2021-01-03 12:00 UTC), datetime!(2020-12-28 0:00 UTC), datetime!(2021-01-04 0:00 UTC)),
+            (datetime!(2021-01-04 0:00 UTC), datetime!(2021-01-04 0:00 UTC), datetime!(2021-01-11 0:00 UTC)),
+            // 2026-12-31 and 2027-01-03 are both in ISO week 53 of 2026.
+            (datetime!(2026-12-31 0:00 UTC), datetime!(2026-12-28 0:00 UTC), datetime!(2027-01-04 0:00 UTC)),
+            (datetime!(2027-01-03 23:59 UTC), datetime!(2026-12-28 0:00 UTC), datetime!(2027-01-04 0:00 UTC)),
+        ]);
+        check(TimeBucket::Month, &[
+            (datetime!(2024-06-01 0:00 UTC), datetime!(2024-06-01 0:00 UTC), datetime!(2024-07-01 0:00 UTC)),
+            (datetime!(2024-06-01 0:00 UTC) - ns, datetime!(2024-05-01 0:00 UTC), datetime!(2024-06-01 0:00 UTC)),
+            (datetime!(2024-02-29 23:59 UTC), datetime!(2024-02-01 0:00 UTC), datetime!(2024-03-01 0:00 UTC)),
+            (datetime!(2023-02-28 12:00 UTC), datetime!(2023-02-01 0:00 UTC), datetime!(2023-03-01 0:00 UTC)),
+            (datetime!(2024-01-31 12:00 UTC), datetime!(2024-01-01 0:00 UTC), datetime!(2024-02-01 0:00 UTC)),
+            (datetime!(2024-04-30 12:00 UTC), datetime!(2024-04-01 0:00 UTC), datetime!(2024-05-01 0:00 UTC)),
+            (datetime!(2023-12-31 23:59 UTC), datetime!(2023-12-01 0:00 UTC), datetime!(2024-01-01 0:00 UTC)),
+        ]);
+    }
+
+    #[test]
+    fn increment_and_range() {
+        let mut series = TimeSeries::new(TimeBucket::Day);
+        series.increment(datetime!(2024-06-01 0:00 UTC), 1u64);
+        series.increment(datetime!(2024-06-01 23:59 UTC), 2);
+        series.increment(datetime!(2024-06-03 8:00 UTC), 5);
+        assert_eq!(series.get(datetime!(2024-06-01 12:00 UTC)), Some(&3));
+        assert_eq!(series.get(datetime!(2024-06-02 12:00 UTC)), None);
+
+        let range = |from, to| series.range(from, to).map(|(start, value)| (start, *value)).collect::<Vec<_>>();
+        assert_eq!(
+            range(datetime!(2024-06-01 12:00 UTC), datetime!(2024-06-04 0:00 UTC)),
+            [(datetime!(2024-06-01 0:00 UTC), 3), (datetime!(2024-06-03 0:00 UTC), 5)]
+        );
+        // The bin starting at to is excluded.
+        assert_eq!(
+            range(datetime!(2024-05-01 0:00 UTC), datetime!(2024-06-03 0:00 UTC)),
+            [(datetime!(2024-06-01 0:00 UTC), 3)]
+        );
+        assert_eq!(range(datetime!(2024-06-04 0:00 UTC), datetime!(2024-06-01 0:00 UTC)), []);
+    }
+
+    #[test]
+    fn densify_fills_gaps() {
+        let mut series = TimeSeries::new(TimeBucket::Day);
+        series.increment(datetime!(2024-06-01 10:00 UTC), 2u64);
+        series.increment(datetime!(2024-06-03 10:00 UTC), 1);
+        assert_eq!(
+            series.densify(datetime!(2024-05-31 18:00 UTC), datetime!(2024-06-05 0:00 UTC), 0).collect::<Vec<_>>(),
+            [
+                (datetime!(2024-05-31 0:00 UTC), 0),
+                (datetime!(2024-06-01 0:00 UTC), 2),
+                (datetime!(2024-06-02 0:00 UTC), 0),
+                (datetime!(2024-06-03 0:00 UTC), 1),
+                (datetime!(2024-06-04 0:00 UTC), 0),
+            ]
+        );
+        assert_eq!(series.densify(datetime!(2024-06-05 0:00 UTC), datetime!(2024-06-01 0:00 UTC), 0).count(), 0);
+
+        let mut monthly = TimeSeries::new(TimeBucket::Month);
+        monthly.increment(datetime!(2024-02-29 23:00 UTC), 7u64);
+        assert_eq!(
+            monthly.densify(datetime!(2024-01-15 0:00 UTC), datetime!(2024-04-01 0:00 UTC), 0).collect::<Vec<_>>(),
+            [
+                (datetime!(2024-01-01 0:00 UTC), 0),
+                (datetime!(2024-02-01 0:00 UTC), 7),
+                (datetime!(2024-03-01 0:00 UTC), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn serde() {
+        let mut series = TimeSeries::new(TimeBucket::Week);
+        series.increment(datetime!(2024-06-05 0:00 UTC), 4u64);
+        series.increment(datetime!(2024-05-29 0:00 UTC), 1);
+        let json = serde_json::to_string(&series).unwrap();
+        assert_eq!(
+            json,
+            r#"{"bucket":"week","points":[["2024-05-27T00:00:00Z",1],["2024-06-03T00:00:00Z",4]]}"#
+        );
+        assert_eq!(serde_json::from_str::<TimeSeries<u64>>(&json).unwrap(), series);
+
+        // Points may come in any order.
+        let reversed = r#"{"bucket":"week","points":[["2024-06-03T00:00:00Z",4],["2024-05-27T00:00:00Z",1]]}"#;
+        assert_eq!(serde_json::from_str::<TimeSeries<u64>>(reversed).unwrap(), series);
+
+        let not_start = r#"{"bucket":"week","points":[["2024-06-04T00:00:00Z",4]]}"#;
+        let err = serde_json::from_str::<TimeSeries<u64>>(not_start).unwrap_err();
+        assert!(err.to_string().contains("is not the start of a week bucket"), "{}", err);
+        let duplicate = r#"{"bucket":"day","points":[["2024-06-04T00:00:00Z",4],["2024-06-04T00:00:00Z",1]]}"#;
+        let err = serde_json::from_str::<TimeSeries<u64>>(duplicate).unwrap_err();
+        assert!(err.to_string().contains("appears more than once"), "{}", err);
+    }
+}
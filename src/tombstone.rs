@@ -0,0 +1,315 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::bucket_guid::BucketGuid;
+use crate::bucket_object::BucketObjectKey;
+use crate::expiry::ExpiryTimestamp;
+use crate::legal_hold::{BlockedByLegalHold, LegalHold};
+use crate::UserId;
+
+// Legal hold releases are kept for at least this long whatever the configured retention says.
+pub const LEGAL_HOLD_RELEASE_MIN_RETENTION: Duration = Duration::days(90);
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TombstoneTarget {
+    Bucket(BucketGuid),
+    Object { bucket: BucketGuid, key: BucketObjectKey },
+}
+
+impl TombstoneTarget {
+    pub fn bucket(&self) -> &BucketGuid {
+        match self {
+            TombstoneTarget::Bucket(bucket) | TombstoneTarget::Object { bucket, .. } => bucket,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, strum::Display, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteReason {
+    UserRequest,
+    LifecycleExpiry,
+    AdminAction,
+    LegalHoldRelease,
+}
+
+// Minimum time between deletion and garbage collection by reason, the default is what storage runs with.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TombstoneRetention {
+    pub user_request: Duration,
+    pub lifecycle_expiry: Duration,
+    pub admin_action: Duration,
+    pub legal_hold_release: Duration,
+}
+
+impl Default for TombstoneRetention {
+    fn default() -> Self {
+        Self {
+            user_request: Duration::days(30),
+            lifecycle_expiry: Duration::days(1),
+            admin_action: Duration::days(30),
+            legal_hold_release: LEGAL_HOLD_RELEASE_MIN_RETENTION,
+        }
+    }
+}
+
+impl TombstoneRetention {
+    // Configured retention for reason, legal hold releases never go below LEGAL_HOLD_RELEASE_MIN_RETENTION.
+    pub fn minimum(&self, reason: DeleteReason) -> Duration {
+        match reason {
+            DeleteReason::UserRequest => self.user_request,
+            DeleteReason::LifecycleExpiry => self.lifecycle_expiry,
+            DeleteReason::AdminAction => self.admin_action,
+            DeleteReason::LegalHoldRelease => self.legal_hold_release.max(LEGAL_HOLD_RELEASE_MIN_RETENTION),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum TombstoneError {
+    #[error("tombstone is collectable at {delete_after}, before it was deleted at {deleted_at}")]
+    DeleteAfterBeforeDeletedAt {
+        deleted_at: ExpiryTimestamp,
+        delete_after: ExpiryTimestamp,
+    },
+    #[error("tombstone for {reason} is kept for {retention}, at least {minimum} is required")]
+    RetentionTooShort {
+        reason: DeleteReason,
+        retention: Duration,
+        minimum: Duration,
+    },
+    #[error(transparent)]
+    BlockedByLegalHold(#[from] BlockedByLegalHold),
+}
+
+/*
+* Marker left behind by a deleted bucket or object, so a late replica or a stale index entry can not bring it back.
+* Storage and index both keep it until delete_after, after which garbage collection may drop it.
+* Ordered by delete_after first so a BinaryHeap<Reverse<Tombstone>> pops the tombstone that is collectable soonest,
+* the other fields only break ties.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub target: TombstoneTarget,
+    pub deleted_at: ExpiryTimestamp,
+    pub delete_after: ExpiryTimestamp,
+    pub deleted_by: Option<UserId>,
+    pub reason: DeleteReason,
+}
+
+impl Tombstone {
+    // Collectable from delete_after on, not before.
+    pub fn is_collectable(&self, now: OffsetDateTime) -> bool {
+        now >= self.delete_after.as_offset_date_time()
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.delete_after.as_offset_date_time() - self.deleted_at.as_offset_date_time()
+    }
+
+    /*
+    * Checked before collecting. holds may be those of any buckets, a hold on the target's bucket that is active at now
+    * blocks collection however old the tombstone is.
+    */
+    pub fn validate(
+        &self,
+        retention: &TombstoneRetention,
+        holds: &[LegalHold],
+        now: OffsetDateTime,
+    ) -> Result<(), TombstoneError> {
+        if self.delete_after < self.deleted_at {
+            return Err(TombstoneError::DeleteAfterBeforeDeletedAt {
+                deleted_at: self.deleted_at,
+                delete_after: self.delete_after,
+            });
+        }
+        let minimum = retention.minimum(self.reason);
+        if self.retention() < minimum {
+            return Err(TombstoneError::RetentionTooShort {
+                reason: self.reason,
+                retention: self.retention(),
+                minimum,
+            });
+        }
+        Ok(LegalHold::check_bucket(holds, self.target.bucket(), now)?)
+    }
+}
+
+impl Ord for Tombstone {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.delete_after
+            .cmp(&other.delete_after)
+            .then_with(|| self.deleted_at.cmp(&other.deleted_at))
+            .then_with(|| self.target.cmp(&other.target))
+            .then_with(|| self.reason.cmp(&other.reason))
+            .then_with(|| self.deleted_by.cmp(&other.deleted_by))
+    }
+}
+
+impl PartialOrd for Tombstone {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    use time::macros::datetime;
+
+    use super::*;
+
+    const DELETED_AT: i64 = 1_717_200_000;
+    const NOW: OffsetDateTime = datetime!(2024-09-01 0:00 UTC);
+
+    fn bucket() -> BucketGuid {
+        BucketGuid::new(
+            "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+            "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+        )
+    }
+
+    fn tombstone(reason: DeleteReason, retention: Duration) -> Tombstone {
+        Tombstone {
+            target: TombstoneTarget::Bucket(bucket()),
+            deleted_at: ExpiryTimestamp::from_unix_timestamp(DELETED_AT).unwrap(),
+            delete_after: ExpiryTimestamp::from_unix_timestamp(DELETED_AT + retention.whole_seconds()).unwrap(),
+            deleted_by: None,
+            reason,
+        }
+    }
+
+    #[test]
+    fn gc_queue_pops_soonest_first() {
+        let mut queue = BinaryHeap::new();
+        for days in [30, 1, 90, 7] {
+            queue.push(Reverse(tombstone(DeleteReason::UserRequest, Duration::days(days))));
+        }
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop()).map(|Reverse(t)| t.retention().whole_days()).collect();
+        assert_eq!(order, [1, 7, 30, 90]);
+
+        // delete_after wins over everything else.
+        let mut later = tombstone(DeleteReason::AdminAction, Duration::days(2));
+        later.deleted_at = ExpiryTimestamp::from_unix_timestamp(DELETED_AT - 86_400).unwrap();
+        assert!(tombstone(DeleteReason::UserRequest, Duration::days(1)) < later);
+    }
+
+    #[test]
+    fn collectable_from_delete_after() {
+        let tombstone = tombstone(DeleteReason::UserRequest, Duration::days(30));
+        let delete_after = tombstone.delete_after.as_offset_date_time();
+        assert!(!tombstone.is_collectable(tombstone.deleted_at.as_offset_date_time()));
+        assert!(!tombstone.is_collectable(delete_after - Duration::nanoseconds(1)));
+        assert!(tombstone.is_collectable(delete_after));
+        assert!(tombstone.is_collectable(delete_after + Duration::days(365)));
+    }
+
+    #[test]
+    fn minimum_retention_per_reason() {
+        let retention = TombstoneRetention::default();
+        let validate = |reason, days| tombstone(reason, Duration::days(days)).validate(&retention, &[], NOW);
+        assert_eq!(validate(DeleteReason::UserRequest, 30), Ok(()));
+        assert_eq!(
+            validate(DeleteReason::UserRequest, 29),
+            Err(TombstoneError::RetentionTooShort {
+                reason: DeleteReason::UserRequest,
+                retention: Duration::days(29),
+                minimum: Duration::days(30),
+            })
+        );
+        assert_eq!(validate(DeleteReason::LifecycleExpiry, 1), Ok(()));
+        assert!(validate(DeleteReason::AdminAction, 7).is_err());
+        assert_eq!(validate(DeleteReason::LegalHoldRelease, 90), Ok(()));
+        assert!(validate(DeleteReason::LegalHoldRelease, 89).is_err());
+
+        // Configured retention applies, except that legal hold releases can not go below 90 days.
+        let relaxed = TombstoneRetention {
+            user_request: Duration::ZERO,
+            lifecycle_expiry: Duration::ZERO,
+            admin_action: Duration::ZERO,
+            legal_hold_release: Duration::ZERO,
+        };
+        let validate = |reason, days| tombstone(reason, Duration::days(days)).validate(&relaxed, &[], NOW);
+        assert_eq!(validate(DeleteReason::UserRequest, 0), Ok(()));
+        assert_eq!(relaxed.minimum(DeleteReason::LegalHoldRelease), LEGAL_HOLD_RELEASE_MIN_RETENTION);
+        assert!(validate(DeleteReason::LegalHoldRelease, 89).is_err());
+    }
+
+    #[test]
+    fn delete_after_before_deleted_at() {
+        let mut tombstone = tombstone(DeleteReason::LifecycleExpiry, Duration::days(1));
+        std::mem::swap(&mut tombstone.deleted_at, &mut tombstone.delete_after);
+        assert!(matches!(
+            tombstone.validate(&TombstoneRetention::default(), &[], NOW),
+            Err(TombstoneError::DeleteAfterBeforeDeletedAt { .. })
+        ));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let bucket_tombstone = Tombstone {
+            deleted_by: Some(uuid::Uuid::nil()),
+            ..tombstone(DeleteReason::AdminAction, Duration::days(30))
+        };
+        let json = serde_json::to_string(&bucket_tombstone).unwrap();
+        assert!(json.starts_with(r#"{"target":{"bucket":{"user_id":"#), "{}", json);
+        assert!(json.ends_with(r#""reason":"admin_action"}"#), "{}", json);
+        assert_eq!(serde_json::from_str::<Tombstone>(&json).unwrap(), bucket_tombstone);
+
+        let object_tombstone = Tombstone {
+            target: TombstoneTarget::Object {
+                bucket: bucket(),
+                key: "/photos/beach.jpg".parse().unwrap(),
+            },
+            ..tombstone(DeleteReason::LifecycleExpiry, Duration::days(1))
+        };
+        let json = serde_json::to_string(&object_tombstone).unwrap();
+        assert!(json.contains(r#""key":"/photos/beach.jpg"}"#), "{}", json);
+        assert!(json.contains(r#""delete_after":"2024-06-02T00:00:00Z""#), "{}", json);
+        assert_eq!(serde_json::from_str::<Tombstone>(&json).unwrap(), object_tombstone);
+
+        let bytes = bincode::serialize(&object_tombstone).unwrap();
+        assert_eq!(bincode::deserialize::<Tombstone>(&bytes).unwrap(), object_tombstone);
+    }
+
+    #[test]
+    fn legal_hold_blocks_collection() {
+        let hold = |released_at: Option<OffsetDateTime>| LegalHold {
+            id: uuid::Uuid::from_bytes([7; 16]),
+            bucket: bucket(),
+            placed_by: uuid::Uuid::nil(),
+            placed_at: datetime!(2024-01-01 0:00 UTC).try_into().unwrap(),
+            case_reference: "Case 7".to_string().try_into().unwrap(),
+            released_at: released_at.map(|released_at| released_at.try_into().unwrap()),
+        };
+        let retention = TombstoneRetention::default();
+        let object = Tombstone {
+            target: TombstoneTarget::Object {
+                bucket: bucket(),
+                key: "/photos/beach.jpg".parse().unwrap(),
+            },
+            ..tombstone(DeleteReason::UserRequest, Duration::days(30))
+        };
+        // Released first, active second.
+        let holds = [hold(Some(NOW - Duration::days(1))), hold(None)];
+        for tombstone in [tombstone(DeleteReason::UserRequest, Duration::days(30)), object] {
+            assert_eq!(
+                tombstone.validate(&retention, &holds, NOW),
+                Err(TombstoneError::BlockedByLegalHold(BlockedByLegalHold { hold_id: holds[1].id }))
+            );
+            assert_eq!(tombstone.validate(&retention, &holds[..1], NOW), Ok(()));
+            // Holds on other buckets do not matter.
+            let elsewhere = LegalHold {
+                bucket: BucketGuid::new(uuid::Uuid::nil(), uuid::Uuid::nil()),
+                ..holds[1].clone()
+            };
+            assert_eq!(tombstone.validate(&retention, &[elsewhere], NOW), Ok(()));
+        }
+    }
+}
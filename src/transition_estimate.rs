@@ -0,0 +1,345 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::payments::{Currency, Money, MoneyError};
+use crate::storage_quota::StorageQuota;
+use crate::BucketStorageClass;
+
+const GIB: u128 = 1 << 30;
+
+// How long it takes until an object in a storage class can be read.
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, strum::Display, strum::EnumString, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum StorageLatencyClass {
+    Immediate,
+    Hours,
+    Days,
+}
+
+impl BucketStorageClass {
+    pub fn latency_class(&self) -> StorageLatencyClass {
+        match self {
+            BucketStorageClass::General | BucketStorageClass::ReducedRedundancy => StorageLatencyClass::Immediate,
+            BucketStorageClass::Archive => StorageLatencyClass::Hours,
+            BucketStorageClass::DeepArchive => StorageLatencyClass::Days,
+        }
+    }
+}
+
+/*
+* Cost and duration of moving a bucket from one storage class to another.
+* Both are a fixed base plus a part per GiB and a part per 1000 objects, partial GiB and thousands are charged pro rata
+* and rounded up so an estimate never undercuts the bill. Durations are configured in seconds.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TransitionRate {
+    pub from: BucketStorageClass,
+    pub to: BucketStorageClass,
+    pub per_gib: Money,
+    pub per_thousand_objects: Money,
+    pub base_eta_secs: u64,
+    pub eta_secs_per_gib: u64,
+    pub eta_secs_per_thousand_objects: u64,
+}
+
+// Rates for every legal transition. The default is the published price list, ops can load their own from JSON.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TransitionRates {
+    pub rates: Vec<TransitionRate>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TransitionEstimate {
+    pub eta: Duration,
+    pub cost: Money,
+    // How fast the objects can be read once the transition is done.
+    pub retrieval_class: StorageLatencyClass,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum TransitionEstimateError {
+    #[error("can not transition from {from} to {to}")]
+    IllegalTransition {
+        from: BucketStorageClass,
+        to: BucketStorageClass,
+    },
+    #[error("no rate for the transition from {from} to {to}")]
+    MissingRate {
+        from: BucketStorageClass,
+        to: BucketStorageClass,
+    },
+    #[error(transparent)]
+    Money(#[from] MoneyError),
+}
+
+impl Default for TransitionRates {
+    fn default() -> Self {
+        use BucketStorageClass::*;
+        let usd = |cents| Money::new(cents, Currency::USD);
+        let rate = |from, to, per_gib, per_thousand_objects, base_eta_secs, eta_secs_per_unit| TransitionRate {
+            from,
+            to,
+            per_gib: usd(per_gib),
+            per_thousand_objects: usd(per_thousand_objects),
+            base_eta_secs,
+            eta_secs_per_gib: eta_secs_per_unit,
+            eta_secs_per_thousand_objects: eta_secs_per_unit,
+        };
+        // Moving between hot classes and archiving is cheap, restoring is what costs.
+        Self {
+            rates: vec![
+                rate(General, ReducedRedundancy, 0, 1, 60, 1),
+                rate(General, Archive, 1, 5, 300, 2),
+                rate(General, DeepArchive, 1, 5, 300, 2),
+                rate(ReducedRedundancy, General, 0, 1, 60, 1),
+                rate(ReducedRedundancy, Archive, 1, 5, 300, 2),
+                rate(ReducedRedundancy, DeepArchive, 1, 5, 300, 2),
+                rate(Archive, General, 3, 10, 3 * 3600, 5),
+                rate(Archive, DeepArchive, 1, 5, 300, 2),
+                rate(DeepArchive, General, 5, 25, 12 * 3600, 10),
+            ],
+        }
+    }
+}
+
+impl TransitionRates {
+    pub fn rate(&self, from: &BucketStorageClass, to: &BucketStorageClass) -> Option<&TransitionRate> {
+        self.rates.iter().find(|rate| rate.from == *from && rate.to == *to)
+    }
+
+    // Saturates instead of overflowing, absurd sizes give an absurd but finite estimate.
+    pub fn estimate(
+        &self,
+        current: &BucketStorageClass,
+        target: &BucketStorageClass,
+        size: StorageQuota,
+        object_count: u64,
+    ) -> Result<TransitionEstimate, TransitionEstimateError> {
+        if !current.can_transition_to(target) {
+            return Err(TransitionEstimateError::IllegalTransition {
+                from: current.clone(),
+                to: target.clone(),
+            });
+        }
+        let rate = self
+            .rate(current, target)
+            .ok_or_else(|| TransitionEstimateError::MissingRate {
+                from: current.clone(),
+                to: target.clone(),
+            })?;
+        // Only checks the currencies, the sum is done in i128 below.
+        Money::zero(rate.per_gib.currency).checked_add(Money::zero(rate.per_thousand_objects.currency))?;
+
+        let (bytes, objects) = (u128::from(size.bytes()), u128::from(object_count));
+        let storage_cost = pro_rata_cost(rate.per_gib, bytes, GIB);
+        let cost = storage_cost.saturating_add(pro_rata_cost(rate.per_thousand_objects, objects, 1000));
+        let eta_secs = u128::from(rate.base_eta_secs)
+            .saturating_add((u128::from(rate.eta_secs_per_gib) * bytes).div_ceil(GIB))
+            .saturating_add((u128::from(rate.eta_secs_per_thousand_objects) * objects).div_ceil(1000));
+        Ok(TransitionEstimate {
+            eta: Duration::from_secs(u64::try_from(eta_secs).unwrap_or(u64::MAX)),
+            cost: Money::new(cost.clamp(i64::MIN.into(), i64::MAX.into()) as i64, rate.per_gib.currency),
+            retrieval_class: target.latency_class(),
+        })
+    }
+}
+
+// per_unit * amount / unit rounded up, the product always fits in i128.
+fn pro_rata_cost(per_unit: Money, amount: u128, unit: u128) -> i128 {
+    let product = i128::from(per_unit.minor_units) * amount as i128;
+    let unit = unit as i128;
+    match product.rem_euclid(unit) {
+        0 => product / unit,
+        _ => product.div_euclid(unit) + 1,
+    }
+}
+
+// Estimate with the default rates.
+pub fn estimate_transition(
+    current: BucketStorageClass,
+    target: BucketStorageClass,
+    size: StorageQuota,
+    object_count: u64,
+) -> Result<TransitionEstimate, TransitionEstimateError> {
+    TransitionRates::default().estimate(&current, &target, size, object_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    const TIB: u64 = 1 << 40;
+
+    #[test]
+    fn default_rates_cover_exactly_the_legal_transitions() {
+        let rates = TransitionRates::default();
+        for from in BucketStorageClass::iter() {
+            for to in BucketStorageClass::iter() {
+                assert_eq!(
+                    rates.rate(&from, &to).is_some(),
+                    from.can_transition_to(&to),
+                    "{} -> {}",
+                    from,
+                    to
+                );
+            }
+        }
+        assert_eq!(rates.rates.len(), 9);
+    }
+
+    #[test]
+    fn golden_estimates() {
+        use BucketStorageClass::*;
+        use StorageLatencyClass::*;
+        // 1 TiB in 1M objects, (from, to, eta in seconds, cost in cents, retrieval class).
+        let golden = [
+            (General, ReducedRedundancy, 2_084, 1_000, Immediate),
+            (General, Archive, 4_348, 6_024, Hours),
+            (General, DeepArchive, 4_348, 6_024, Days),
+            (ReducedRedundancy, General, 2_084, 1_000, Immediate),
+            (ReducedRedundancy, Archive, 4_348, 6_024, Hours),
+            (ReducedRedundancy, DeepArchive, 4_348, 6_024, Days),
+            (Archive, General, 20_920, 13_072, Immediate),
+            (Archive, DeepArchive, 4_348, 6_024, Days),
+            (DeepArchive, General, 63_440, 30_120, Immediate),
+        ];
+        for (from, to, eta_secs, cents, retrieval_class) in golden {
+            assert_eq!(
+                estimate_transition(from.clone(), to.clone(), StorageQuota::from_bytes(TIB), 1_000_000),
+                Ok(TransitionEstimate {
+                    eta: Duration::from_secs(eta_secs),
+                    cost: Money::new(cents, Currency::USD),
+                    retrieval_class,
+                }),
+                "{} -> {}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn partial_units_round_up() {
+        let estimate = |size, objects| {
+            estimate_transition(
+                BucketStorageClass::Archive,
+                BucketStorageClass::General,
+                StorageQuota::from_bytes(size),
+                objects,
+            )
+            .unwrap()
+        };
+        assert_eq!(estimate(0, 0).cost, Money::new(0, Currency::USD));
+        assert_eq!(estimate(0, 0).eta, Duration::from_secs(3 * 3600));
+        assert_eq!(estimate(1, 1).cost, Money::new(2, Currency::USD));
+        assert_eq!(estimate(1, 1).eta, Duration::from_secs(3 * 3600 + 2));
+        assert_eq!(estimate(1 << 30, 1000).cost, Money::new(13, Currency::USD));
+    }
+
+    #[test]
+    fn absurd_inputs_saturate() {
+        let estimate = estimate_transition(
+            BucketStorageClass::DeepArchive,
+            BucketStorageClass::General,
+            StorageQuota::from_bytes(u64::MAX),
+            u64::MAX,
+        )
+        .unwrap();
+        // Far from saturating with the default rates, but nothing may overflow on the way.
+        assert!(estimate.cost.minor_units > 0);
+        assert!(estimate.eta > Duration::from_secs(u64::from(u32::MAX)));
+
+        let expensive = TransitionRates {
+            rates: vec![TransitionRate {
+                per_gib: Money::new(i64::MAX, Currency::USD),
+                per_thousand_objects: Money::new(i64::MAX, Currency::USD),
+                base_eta_secs: u64::MAX,
+                eta_secs_per_gib: u64::MAX,
+                eta_secs_per_thousand_objects: u64::MAX,
+                ..TransitionRates::default().rates[0].clone()
+            }],
+        };
+        let estimate = expensive
+            .estimate(
+                &BucketStorageClass::General,
+                &BucketStorageClass::ReducedRedundancy,
+                StorageQuota::from_bytes(u64::MAX),
+                u64::MAX,
+            )
+            .unwrap();
+        assert_eq!(estimate.cost, Money::new(i64::MAX, Currency::USD));
+        assert_eq!(estimate.eta, Duration::from_secs(u64::MAX));
+    }
+
+    #[test]
+    fn illegal_and_unpriced_transitions() {
+        assert_eq!(
+            estimate_transition(
+                BucketStorageClass::DeepArchive,
+                BucketStorageClass::Archive,
+                StorageQuota::from_bytes(TIB),
+                1
+            ),
+            Err(TransitionEstimateError::IllegalTransition {
+                from: BucketStorageClass::DeepArchive,
+                to: BucketStorageClass::Archive,
+            })
+        );
+        assert!(matches!(
+            estimate_transition(
+                BucketStorageClass::General,
+                BucketStorageClass::General,
+                StorageQuota::ZERO,
+                0
+            ),
+            Err(TransitionEstimateError::IllegalTransition { .. })
+        ));
+
+        let empty = TransitionRates { rates: Vec::new() };
+        assert_eq!(
+            empty.estimate(
+                &BucketStorageClass::General,
+                &BucketStorageClass::Archive,
+                StorageQuota::ZERO,
+                0
+            ),
+            Err(TransitionEstimateError::MissingRate {
+                from: BucketStorageClass::General,
+                to: BucketStorageClass::Archive,
+            })
+        );
+
+        let mixed = TransitionRates {
+            rates: vec![TransitionRate {
+                per_thousand_objects: Money::new(1, Currency::EUR),
+                ..TransitionRates::default().rates[0].clone()
+            }],
+        };
+        assert!(matches!(
+            mixed.estimate(
+                &BucketStorageClass::General,
+                &BucketStorageClass::ReducedRedundancy,
+                StorageQuota::ZERO,
+                0
+            ),
+            Err(TransitionEstimateError::Money(MoneyError::CurrencyMismatch { .. }))
+        ));
+    }
+
+    #[test]
+    fn rates_serde_round_trip() {
+        let rates = TransitionRates::default();
+        let json = serde_json::to_string(&rates).unwrap();
+        assert!(json.starts_with(concat!(
+            r#"{"rates":[{"from":"General","to":"ReducedRedundancy","per_gib":{"amount":0,"currency":"USD"},"#,
+            r#""per_thousand_objects":{"amount":1,"currency":"USD"},"base_eta_secs":60,"#
+        )));
+        assert_eq!(serde_json::from_str::<TransitionRates>(&json).unwrap(), rates);
+    }
+}
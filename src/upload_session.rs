@@ -0,0 +1,361 @@
+use time::OffsetDateTime;
+
+use crate::bucket_guid::BucketGuid;
+use crate::bucket_object::{BucketObjectKey, BucketObjectKeyError};
+use crate::expiry::{ExpiryTimestamp, ExpiryTimestampError};
+use crate::framing::{expect_header, write_header, BinaryFormat, FramingError};
+use crate::multipart_upload::MultipartUpload;
+use crate::util::{base64_stream, ByteSpan, ByteSpanError, SpanSet};
+
+pub const UPLOAD_SESSION_STATE_VERSION: u8 = 1;
+// Longest sealed state open accepts, in base64 characters. Checked before anything is decoded.
+pub const MAX_SEALED_SESSION_LEN: usize = 8 * 1024;
+const SIGNATURE_LEN: usize = 64;
+
+/*
+* State of a resumable upload the client holds between chunks, so a node can continue the session without looking it
+* up. seal signs the canonical bytes, open only returns states the server sealed and that have not expired.
+* Binary format: header, upload id (16 bytes), bucket user id (16), bucket id (16), key length (u16 BE), key,
+* part size (u64 BE), total size flag (u8, 0 when unknown) and total size (u64 BE, 0 when unknown),
+* issued_at and expires_at (i64 BE unix seconds), span count (u32 BE), then per span start and end_exclusive (u64 BE).
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UploadSessionState {
+    pub upload: MultipartUpload,
+    pub received: SpanSet,
+    pub issued_at: ExpiryTimestamp,
+    pub expires_at: ExpiryTimestamp,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum SessionStateError {
+    #[error("upload session expired at {expired_at}")]
+    Expired { expired_at: ExpiryTimestamp },
+    #[error("upload session state was not sealed by this server or was changed")]
+    Tampered,
+    #[error("upload session state is {len} bytes, max is {max}")]
+    TooLarge { len: usize, max: usize },
+    #[error("unsupported upload session state version {0}")]
+    UnsupportedVersion(u8),
+    #[error("upload session state ended early")]
+    Truncated,
+    #[error("upload session state has data after the last span")]
+    TrailingData,
+    #[error("total size flag is {0}, expected 0 or 1")]
+    InvalidTotalSizeFlag(u8),
+    #[error("object key is not utf-8")]
+    KeyNotUtf8,
+    #[error(transparent)]
+    InvalidKey(#[from] BucketObjectKeyError),
+    #[error(transparent)]
+    InvalidSpan(#[from] ByteSpanError),
+    #[error(transparent)]
+    Timestamp(#[from] ExpiryTimestampError),
+    #[error(transparent)]
+    Framing(#[from] FramingError),
+}
+
+impl UploadSessionState {
+    pub fn new(upload: MultipartUpload, issued_at: ExpiryTimestamp, expires_at: ExpiryTimestamp) -> Self {
+        Self {
+            upload,
+            received: SpanSet::new(),
+            issued_at,
+            expires_at,
+        }
+    }
+
+    // Chunks may arrive in any order and more than once. Spans past total_size are kept but never complete anything.
+    pub fn record_chunk(&mut self, span: ByteSpan) {
+        self.received.insert(span);
+    }
+
+    // Streamed uploads without a total size are never complete, the client finishes them explicitly.
+    pub fn is_complete(&self) -> bool {
+        match self.upload.total_size {
+            Some(total_size) => self.received.is_complete(total_size),
+            None => false,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_header(BinaryFormat::UploadSessionState, UPLOAD_SESSION_STATE_VERSION, &mut bytes);
+        bytes.extend_from_slice(self.upload.upload_id.as_bytes());
+        bytes.extend_from_slice(self.upload.bucket.user_id.as_bytes());
+        bytes.extend_from_slice(self.upload.bucket.bucket_id.as_bytes());
+        // At most MAX_OBJECT_KEY_LEN, always fits.
+        bytes.extend_from_slice(&(self.upload.key.as_str().len() as u16).to_be_bytes());
+        bytes.extend_from_slice(self.upload.key.as_str().as_bytes());
+        bytes.extend_from_slice(&self.upload.part_size.to_be_bytes());
+        bytes.push(self.upload.total_size.is_some() as u8);
+        bytes.extend_from_slice(&self.upload.total_size.unwrap_or(0).to_be_bytes());
+        bytes.extend_from_slice(&self.issued_at.unix_timestamp().to_be_bytes());
+        bytes.extend_from_slice(&self.expires_at.unix_timestamp().to_be_bytes());
+        bytes.extend_from_slice(&(self.received.spans().len() as u32).to_be_bytes());
+        for span in self.received.spans() {
+            bytes.extend_from_slice(&span.start().to_be_bytes());
+            bytes.extend_from_slice(&span.end_exclusive().to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SessionStateError> {
+        let (version, body) = expect_header(bytes, BinaryFormat::UploadSessionState)?;
+        if version != UPLOAD_SESSION_STATE_VERSION {
+            return Err(SessionStateError::UnsupportedVersion(version));
+        }
+        let mut reader = ByteReader(body);
+        let upload_id = uuid::Uuid::from_bytes(reader.take()?);
+        let bucket = BucketGuid::new(uuid::Uuid::from_bytes(reader.take()?), uuid::Uuid::from_bytes(reader.take()?));
+        let key_len = u16::from_be_bytes(reader.take()?) as usize;
+        let key = std::str::from_utf8(reader.take_slice(key_len)?).map_err(|_| SessionStateError::KeyNotUtf8)?;
+        let key = key.parse::<BucketObjectKey>()?;
+        let part_size = u64::from_be_bytes(reader.take()?);
+        let total_size = match (reader.take::<1>()?[0], u64::from_be_bytes(reader.take()?)) {
+            (0, _) => None,
+            (1, total_size) => Some(total_size),
+            (flag, _) => return Err(SessionStateError::InvalidTotalSizeFlag(flag)),
+        };
+        let issued_at = ExpiryTimestamp::from_unix_timestamp(i64::from_be_bytes(reader.take()?))?;
+        let expires_at = ExpiryTimestamp::from_unix_timestamp(i64::from_be_bytes(reader.take()?))?;
+        let count = u32::from_be_bytes(reader.take()?);
+        // The count comes from untrusted input, spans are inserted one by one instead of preallocating.
+        let mut received = SpanSet::new();
+        for _ in 0..count {
+            let start = u64::from_be_bytes(reader.take()?);
+            received.insert(ByteSpan::new(start, u64::from_be_bytes(reader.take()?))?);
+        }
+        if !reader.0.is_empty() {
+            return Err(SessionStateError::TrailingData);
+        }
+        Ok(Self {
+            upload: MultipartUpload {
+                upload_id,
+                bucket,
+                key,
+                part_size,
+                total_size,
+            },
+            received,
+            issued_at,
+            expires_at,
+        })
+    }
+
+    /*
+    * URL-safe base64 of the canonical bytes followed by their ed25519 signature. Fails with TooLarge rather than
+    * handing out a state open would refuse, which takes a few hundred scattered spans.
+    */
+    pub fn seal(&self, secret_key: &ed25519_compact::SecretKey) -> Result<String, SessionStateError> {
+        let mut bytes = self.to_bytes();
+        let signature = secret_key.sign(&bytes, None);
+        bytes.extend_from_slice(signature.as_ref());
+        let mut sealed = Vec::new();
+        base64_stream::encode_to_writer(bytes.as_slice(), &mut sealed).expect("writing to a Vec can not fail");
+        let sealed = String::from_utf8(sealed).expect("base64 is ASCII");
+        match sealed.len() > MAX_SEALED_SESSION_LEN {
+            true => Err(SessionStateError::TooLarge { len: sealed.len(), max: MAX_SEALED_SESSION_LEN }),
+            false => Ok(sealed),
+        }
+    }
+
+    // The state is valid until, but not at, expires_at.
+    pub fn open(
+        sealed: &str,
+        public_key: &ed25519_compact::PublicKey,
+        now: OffsetDateTime,
+    ) -> Result<Self, SessionStateError> {
+        if sealed.len() > MAX_SEALED_SESSION_LEN {
+            return Err(SessionStateError::TooLarge { len: sealed.len(), max: MAX_SEALED_SESSION_LEN });
+        }
+        let mut bytes = Vec::new();
+        base64_stream::decode_to_writer(sealed.as_bytes(), &mut bytes).map_err(|_| SessionStateError::Tampered)?;
+        let split = bytes.len().checked_sub(SIGNATURE_LEN).ok_or(SessionStateError::Tampered)?;
+        let (bytes, signature) = bytes.split_at(split);
+        let signature = ed25519_compact::Signature::from_slice(signature).map_err(|_| SessionStateError::Tampered)?;
+        public_key.verify(bytes, &signature).map_err(|_| SessionStateError::Tampered)?;
+        let state = Self::from_bytes(bytes)?;
+        if now >= state.expires_at.as_offset_date_time() {
+            return Err(SessionStateError::Expired { expired_at: state.expires_at });
+        }
+        Ok(state)
+    }
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8], SessionStateError> {
+        if self.0.len() < len {
+            return Err(SessionStateError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SessionStateError> {
+        Ok(self.take_slice(N)?.try_into().expect("slice has length N"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose, Engine};
+    use time::macros::datetime;
+    use time::Duration;
+
+    use super::*;
+    use crate::multipart_upload::MIB;
+
+    const ISSUED_AT: OffsetDateTime = datetime!(2024-06-01 0:00 UTC);
+
+    fn key_pair() -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([42; 32]))
+    }
+
+    fn state(total_size: Option<u64>) -> UploadSessionState {
+        let upload = MultipartUpload {
+            upload_id: uuid::Uuid::from_u128(7),
+            bucket: BucketGuid::new(
+                "11111111-0000-4000-8000-000000000001".parse().unwrap(),
+                "22222222-0000-4000-8000-000000000002".parse().unwrap(),
+            ),
+            key: "/videos/holiday.mp4".parse().unwrap(),
+            part_size: 5 * MIB,
+            total_size,
+        };
+        UploadSessionState::new(
+            upload,
+            ISSUED_AT.try_into().unwrap(),
+            (ISSUED_AT + Duration::DAY).try_into().unwrap(),
+        )
+    }
+
+    fn span(start: u64, end_exclusive: u64) -> ByteSpan {
+        ByteSpan::new(start, end_exclusive).unwrap()
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let key_pair = key_pair();
+        for total_size in [Some(12 * MIB), None] {
+            let mut state = state(total_size);
+            state.record_chunk(span(0, 5 * MIB));
+            state.record_chunk(span(10 * MIB, 12 * MIB));
+            let sealed = state.seal(&key_pair.sk).unwrap();
+            assert!(sealed.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_'));
+            assert_eq!(UploadSessionState::open(&sealed, &key_pair.pk, ISSUED_AT), Ok(state.clone()));
+            assert_eq!(UploadSessionState::from_bytes(&state.to_bytes()), Ok(state));
+        }
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let key_pair = key_pair();
+        let mut state = state(Some(12 * MIB));
+        state.record_chunk(span(0, 5 * MIB));
+        let sealed = state.seal(&key_pair.sk).unwrap();
+
+        // Claiming more received bytes, with the original signature.
+        let mut bytes = general_purpose::URL_SAFE_NO_PAD.decode(&sealed).unwrap();
+        let end = bytes.len() - SIGNATURE_LEN - 1;
+        bytes[end] ^= 0x80;
+        let forged = general_purpose::URL_SAFE_NO_PAD.encode(&bytes);
+        assert_eq!(UploadSessionState::open(&forged, &key_pair.pk, ISSUED_AT), Err(SessionStateError::Tampered));
+
+        let other = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([1; 32]));
+        assert_eq!(UploadSessionState::open(&sealed, &other.pk, ISSUED_AT), Err(SessionStateError::Tampered));
+        let resealed = state.seal(&other.sk).unwrap();
+        assert_eq!(UploadSessionState::open(&resealed, &key_pair.pk, ISSUED_AT), Err(SessionStateError::Tampered));
+        for garbage in ["", "AAAA", "not base64!", &sealed[..sealed.len() - 4]] {
+            assert_eq!(UploadSessionState::open(garbage, &key_pair.pk, ISSUED_AT), Err(SessionStateError::Tampered));
+        }
+    }
+
+    #[test]
+    fn expiry_boundary() {
+        let key_pair = key_pair();
+        let state = state(Some(MIB));
+        let sealed = state.seal(&key_pair.sk).unwrap();
+        let expires_at = ISSUED_AT + Duration::DAY;
+        let open = |now| UploadSessionState::open(&sealed, &key_pair.pk, now);
+        assert_eq!(open(expires_at - Duration::NANOSECOND), Ok(state.clone()));
+        assert_eq!(open(expires_at), Err(SessionStateError::Expired { expired_at: state.expires_at }));
+        assert_eq!(open(expires_at + Duration::DAY), Err(SessionStateError::Expired { expired_at: state.expires_at }));
+    }
+
+    #[test]
+    fn out_of_order_chunks_complete_the_session() {
+        let key_pair = key_pair();
+        let mut state = state(Some(20 * MIB));
+        let mut sealed = state.seal(&key_pair.sk).unwrap();
+        for (start, end) in [(15, 20), (0, 5), (10, 15), (0, 5), (5, 10)] {
+            assert!(!state.is_complete());
+            // Every chunk goes through the client's copy of the state, like between requests.
+            state = UploadSessionState::open(&sealed, &key_pair.pk, ISSUED_AT).unwrap();
+            state.record_chunk(span(start * MIB, end * MIB));
+            sealed = state.seal(&key_pair.sk).unwrap();
+        }
+        assert!(state.is_complete());
+        assert_eq!(state.received.spans(), [span(0, 20 * MIB)]);
+        assert_eq!(state.received.covered_len(), 20 * MIB);
+
+        // Without a total size the client has to finish the upload itself.
+        let mut streamed = UploadSessionState { upload: MultipartUpload { total_size: None, ..state.upload }, ..state };
+        streamed.record_chunk(span(20 * MIB, 21 * MIB));
+        assert!(!streamed.is_complete());
+    }
+
+    #[test]
+    fn size_cap() {
+        let key_pair = key_pair();
+        let mut state = state(None);
+        // Every other byte, no two spans merge.
+        for index in 0..1_000 {
+            state.record_chunk(span(index * 2, index * 2 + 1));
+        }
+        assert_eq!(state.received.spans().len(), 1_000);
+        assert!(matches!(
+            state.seal(&key_pair.sk),
+            Err(SessionStateError::TooLarge { max: MAX_SEALED_SESSION_LEN, .. })
+        ));
+
+        let oversized = "A".repeat(MAX_SEALED_SESSION_LEN + 1);
+        assert_eq!(
+            UploadSessionState::open(&oversized, &key_pair.pk, ISSUED_AT),
+            Err(SessionStateError::TooLarge { len: MAX_SEALED_SESSION_LEN + 1, max: MAX_SEALED_SESSION_LEN })
+        );
+
+        // The cap leaves room for a few hundred scattered spans.
+        let mut state = self::state(None);
+        for index in 0..300 {
+            state.record_chunk(span(index * 2, index * 2 + 1));
+        }
+        let sealed = state.seal(&key_pair.sk).unwrap();
+        assert_eq!(UploadSessionState::open(&sealed, &key_pair.pk, ISSUED_AT), Ok(state));
+    }
+
+    #[test]
+    fn decoding_errors() {
+        let bytes = state(Some(MIB)).to_bytes();
+        assert_eq!(UploadSessionState::from_bytes(&bytes[..bytes.len() - 1]), Err(SessionStateError::Truncated));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(UploadSessionState::from_bytes(&trailing), Err(SessionStateError::TrailingData));
+        let mut version = bytes.clone();
+        version[4] = 2;
+        assert_eq!(UploadSessionState::from_bytes(&version), Err(SessionStateError::UnsupportedVersion(2)));
+
+        let mut empty_span = state(None);
+        empty_span.record_chunk(span(0, 1));
+        let mut bytes = empty_span.to_bytes();
+        let len = bytes.len();
+        bytes[len - 8..].copy_from_slice(&0u64.to_be_bytes());
+        assert_eq!(
+            UploadSessionState::from_bytes(&bytes),
+            Err(SessionStateError::InvalidSpan(ByteSpanError::Empty { start: 0, end_exclusive: 0 }))
+        );
+    }
+}
@@ -1,3 +1,15 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Sha3_256};
+use time::{Duration, OffsetDateTime};
+
+use crate::bucket_description::BucketName;
+use crate::errors::{hex_span, ParseFailure, SpannedParseError};
+use crate::expiry::{ExpiryTimestamp, ExpiryTimestampError, MAX_EXPIRY_HORIZON};
+use crate::{BucketCompression, DownloadFormat};
+
 pub const DOMAIN_URL: &str = "bucketdrive.co";
 // Special filenames, don't use theses file names if you want to use default implementation of compression and client-side encryption.
 // They are mad of 200 random numbers followed by appropriate extension.
@@ -7,3 +19,1538 @@ pub const V1_COMPRESSION_FILENAME_SIGNATURE: &str = "501175607529509745545355450
 // Both secret-share-link and share-link use the same API endpoint for convenience
 pub const SECRET_SHARE_PATH_URL: &str = "/api/v1/share";
 pub const SHARE_PATH_URL: &str = "/api/v1/share";
+// Public bucket pages, addressed by PublicBucketId.
+pub const PUBLIC_BUCKET_PATH_URL: &str = "/b";
+
+// Custom scheme the mobile apps register for deep links.
+pub const DEEP_LINK_SCHEME: &str = "bucketdrive";
+// Custom schemes links may be issued and parsed under, anything else but https is refused, e.g. javascript: or data:.
+pub const ALLOWED_CUSTOM_SCHEMES: [&str; 1] = [DEEP_LINK_SCHEME];
+// The app routes on the host of a deep link, share links are bucketdrive://share/<segments> without SHARE_PATH_URL.
+pub const DEEP_LINK_SHARE_ROUTE: &str = "share";
+// Longest link URL issued by default, some mail clients, proxies and QR scanners break longer links.
+pub const MAX_LINK_URL_LEN: usize = 2048;
+
+/*
+* Scheme and host share links are parsed against.
+* Both are compared ASCII-case-insensitively since some email clients uppercase them, the rest of the link is case-sensitive.
+* Under a custom scheme the host is not checked, the URL host is the app route instead, see DEEP_LINK_SHARE_ROUTE.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinkOrigin {
+    pub scheme: String,
+    pub host: String,
+    // Longest URL to_url_checked issues under this origin, MAX_LINK_URL_LEN unless set with with_max_url_len.
+    pub max_url_len: usize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LinkScheme {
+    Https,
+    // Lowercase name of a scheme in ALLOWED_CUSTOM_SCHEMES.
+    CustomScheme(String),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum LinkOriginError {
+    #[error("links are not issued under the scheme \"{0}\"")]
+    SchemeNotAllowed(String),
+    #[error(transparent)]
+    UrlParse(#[from] url::ParseError),
+}
+
+// Which part of a URL does not belong to a share link under an origin, mapped to each link type's parsing error.
+#[cfg(feature = "share_link")]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum OriginMismatch {
+    Scheme,
+    Host,
+    Path,
+}
+
+impl LinkOrigin {
+    pub fn new(scheme: impl Into<String>, host: impl Into<String>) -> Self {
+        Self {
+            scheme: scheme.into(),
+            host: host.into(),
+            max_url_len: MAX_LINK_URL_LEN,
+        }
+    }
+
+    pub fn with_max_url_len(mut self, max_url_len: usize) -> Self {
+        self.max_url_len = max_url_len;
+        self
+    }
+
+    // bucketdrive:// deep links, there is no host to compare.
+    pub fn deep_link() -> Self {
+        Self::new(DEEP_LINK_SCHEME, "")
+    }
+
+    // None for a scheme links are never issued under.
+    pub fn link_scheme(&self) -> Option<LinkScheme> {
+        if self.scheme.eq_ignore_ascii_case("https") {
+            return Some(LinkScheme::Https);
+        }
+        ALLOWED_CUSTOM_SCHEMES
+            .iter()
+            .find(|scheme| scheme.eq_ignore_ascii_case(&self.scheme))
+            .map(|scheme| LinkScheme::CustomScheme(scheme.to_string()))
+    }
+
+    pub fn matches_scheme(&self, url: &url::Url) -> bool {
+        self.link_scheme().is_some() && url.scheme().eq_ignore_ascii_case(&self.scheme)
+    }
+
+    // Always true under a custom scheme.
+    pub fn matches_host(&self, url: &url::Url) -> bool {
+        match self.link_scheme() {
+            Some(LinkScheme::CustomScheme(_)) => true,
+            _ => url.host_str().is_some_and(|host| host.eq_ignore_ascii_case(&self.host)),
+        }
+    }
+
+    // Share links start with this, followed by '/' and their first path segment.
+    #[cfg(feature = "share_link")]
+    pub(crate) fn share_link_base(&self) -> Result<String, LinkOriginError> {
+        match self.link_scheme() {
+            Some(LinkScheme::Https) => Ok(format!("https://{}{}", self.host, SHARE_PATH_URL)),
+            Some(LinkScheme::CustomScheme(scheme)) => Ok(format!("{}://{}", scheme, DEEP_LINK_SHARE_ROUTE)),
+            None => Err(LinkOriginError::SchemeNotAllowed(truncate_input(&self.scheme))),
+        }
+    }
+
+    // Path of a share link after its base, starting with '/' and without a trailing slash.
+    #[cfg(feature = "share_link")]
+    pub(crate) fn share_link_path<'a>(&self, url: &'a url::Url) -> Result<&'a str, OriginMismatch> {
+        if !self.matches_scheme(url) {
+            return Err(OriginMismatch::Scheme);
+        }
+        if !self.matches_host(url) {
+            return Err(OriginMismatch::Host);
+        }
+        let path = strip_trailing_slash(url.path());
+        match self.link_scheme() {
+            Some(LinkScheme::CustomScheme(_)) => url
+                .host_str()
+                .filter(|route| route.eq_ignore_ascii_case(DEEP_LINK_SHARE_ROUTE))
+                .map(|_| path)
+                .ok_or(OriginMismatch::Path),
+            _ => path.strip_prefix(SHARE_PATH_URL).ok_or(OriginMismatch::Path),
+        }
+    }
+}
+
+impl Default for LinkOrigin {
+    fn default() -> Self {
+        Self::new("https", DOMAIN_URL)
+    }
+}
+
+// A single trailing slash after the last path segment is accepted, links get one appended when copied from some clients.
+pub(crate) fn strip_trailing_slash(path: &str) -> &str {
+    path.strip_suffix('/').unwrap_or(path)
+}
+
+// Longest input echoed back in error messages, avoids blowing up logs with huge inputs.
+pub const MAX_ERROR_INPUT_LEN: usize = 64;
+
+// Truncate user input to MAX_ERROR_INPUT_LEN characters for use in error messages.
+pub fn truncate_input(input: &str) -> String {
+    match input.char_indices().nth(MAX_ERROR_INPUT_LEN) {
+        Some((index, _)) => format!("{}...", &input[..index]),
+        None => input.to_string(),
+    }
+}
+
+// Compares secret material without returning early on the first differing byte. Lengths are not secret.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0u8, |difference, (a, b)| difference | (a ^ b));
+    std::hint::black_box(difference) == 0
+}
+
+// Short non-reversible identifier of secret material, safe to log and show to users. First 4 bytes of the SHA3-256 digest.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Fingerprint(pub [u8; 4]);
+
+impl Fingerprint {
+    pub fn of(bytes: &[u8]) -> Self {
+        let digest = Sha3_256::digest(bytes);
+        Self([digest[0], digest[1], digest[2], digest[3]])
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum FingerprintParsingError {
+    #[error("invalid fingerprint \"{0}\", expected 8 hex characters")]
+    InvalidFormat(String),
+}
+
+impl SpannedParseError for FingerprintParsingError {
+    fn failure(&self) -> ParseFailure {
+        let FingerprintParsingError::InvalidFormat(input) = self;
+        ParseFailure::spanned(self, input, hex_span(input, 8))
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = FingerprintParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FingerprintParsingError::InvalidFormat(truncate_input(s));
+        if s.len() != 8 || !s.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0u8; 4];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl Serialize for Fingerprint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fingerprint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/*
+* Positive duration of at most 100 years in whole seconds, for link lifetimes given by users.
+* Parses compound forms like "1d12h", "90m" or "2w" with units w, d, h, m and s in descending order,
+* optionally separated by whitespace, as well as ISO-8601 durations without years and months like "PT1H30M".
+* Displays in the largest whole units, "90m" is shown as "1h30m", and uses the string form for serde.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct HumanDuration(Duration);
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum HumanDurationError {
+    #[error("invalid duration \"{0}\"")]
+    InvalidFormat(String),
+    #[error("duration must not be zero")]
+    Zero,
+    #[error("duration {0} is negative")]
+    Negative(String),
+    #[error("duration \"{0}\" is longer than 100 years")]
+    TooLong(String),
+}
+
+// A negative duration points at its sign.
+impl SpannedParseError for HumanDurationError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            HumanDurationError::InvalidFormat(input) | HumanDurationError::TooLong(input) => {
+                ParseFailure::whole(self, input)
+            }
+            HumanDurationError::Negative(input) => ParseFailure::spanned(self, input, 0..1),
+            HumanDurationError::Zero => ParseFailure::unspanned(self),
+        }
+    }
+}
+
+const HUMAN_DURATION_UNITS: [(char, i64); 5] = [('w', 604800), ('d', 86400), ('h', 3600), ('m', 60), ('s', 1)];
+const ISO_DATE_UNITS: [(char, i64); 2] = [('W', 604800), ('D', 86400)];
+const ISO_TIME_UNITS: [(char, i64); 3] = [('H', 3600), ('M', 60), ('S', 1)];
+
+enum DurationComponentError {
+    Invalid,
+    Overflow,
+}
+
+// Sums "<number><unit>" components, units have to follow the order of the table and appear at most once.
+fn sum_duration_components(mut s: &str, units: &[(char, i64)]) -> Result<i64, DurationComponentError> {
+    let mut seconds = 0i64;
+    let mut next_unit = 0;
+    s = s.trim_start();
+    if s.is_empty() {
+        return Err(DurationComponentError::Invalid);
+    }
+    while !s.is_empty() {
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        if digits == 0 {
+            return Err(DurationComponentError::Invalid);
+        }
+        // Only digits, so parsing can only fail on overflow.
+        let value = s[..digits].parse::<i64>().map_err(|_| DurationComponentError::Overflow)?;
+        s = s[digits..].trim_start();
+        let unit = s.chars().next().ok_or(DurationComponentError::Invalid)?;
+        let index = units[next_unit..]
+            .iter()
+            .position(|(name, _)| *name == unit)
+            .ok_or(DurationComponentError::Invalid)?;
+        let (_, unit_seconds) = units[next_unit + index];
+        next_unit += index + 1;
+        seconds = value
+            .checked_mul(unit_seconds)
+            .and_then(|value| seconds.checked_add(value))
+            .ok_or(DurationComponentError::Overflow)?;
+        s = s[unit.len_utf8()..].trim_start();
+    }
+    Ok(seconds)
+}
+
+fn parse_iso_duration(s: &str) -> Result<i64, DurationComponentError> {
+    if s.contains(char::is_whitespace) {
+        return Err(DurationComponentError::Invalid);
+    }
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let date = match (date.is_empty(), time) {
+        (true, Some(_)) => 0,
+        _ => sum_duration_components(date, &ISO_DATE_UNITS)?,
+    };
+    let time = time.map(|time| sum_duration_components(time, &ISO_TIME_UNITS)).transpose()?;
+    date.checked_add(time.unwrap_or(0)).ok_or(DurationComponentError::Overflow)
+}
+
+impl HumanDuration {
+    // Sub-second precision is dropped.
+    pub fn new(duration: Duration) -> Result<Self, HumanDurationError> {
+        let duration = Duration::seconds(duration.whole_seconds());
+        if duration.is_zero() {
+            return Err(HumanDurationError::Zero);
+        }
+        if duration.is_negative() {
+            return Err(HumanDurationError::Negative(duration.to_string()));
+        }
+        if duration > MAX_EXPIRY_HORIZON {
+            return Err(HumanDurationError::TooLong(duration.to_string()));
+        }
+        Ok(Self(duration))
+    }
+
+    pub fn as_duration(&self) -> Duration {
+        self.0
+    }
+
+    pub fn expiry_from_now(&self, now: OffsetDateTime) -> Result<ExpiryTimestamp, ExpiryTimestampError> {
+        ExpiryTimestamp::new_at(now + self.0, now)
+    }
+}
+
+impl From<HumanDuration> for Duration {
+    fn from(value: HumanDuration) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut seconds = self.0.whole_seconds();
+        for (unit, unit_seconds) in HUMAN_DURATION_UNITS {
+            if seconds >= unit_seconds {
+                write!(f, "{}{}", seconds / unit_seconds, unit)?;
+                seconds %= unit_seconds;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = HumanDurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, trimmed),
+        };
+        let seconds = match unsigned.strip_prefix('P') {
+            Some(iso) => parse_iso_duration(iso),
+            None => sum_duration_components(unsigned, &HUMAN_DURATION_UNITS),
+        };
+        let seconds = match seconds {
+            Ok(seconds) => seconds,
+            Err(DurationComponentError::Invalid) => return Err(HumanDurationError::InvalidFormat(truncate_input(s))),
+            Err(DurationComponentError::Overflow) => return Err(HumanDurationError::TooLong(truncate_input(trimmed))),
+        };
+        match (negative, seconds) {
+            (_, 0) => Err(HumanDurationError::Zero),
+            (true, _) => Err(HumanDurationError::Negative(truncate_input(trimmed))),
+            // Positive, so too long is the only way this can fail.
+            (false, seconds) => Self::new(Duration::seconds(seconds))
+                .map_err(|_| HumanDurationError::TooLong(truncate_input(trimmed))),
+        }
+    }
+}
+
+impl Serialize for HumanDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// Units of format_bytes, Binary divides by 1024 (KiB, MiB, ...) and Decimal by 1000 (kB, MB, ...).
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum ByteStyle {
+    #[default]
+    Binary,
+    Decimal,
+}
+
+const BINARY_BYTE_UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+const DECIMAL_BYTE_UNITS: [&str; 7] = ["B", "kB", "MB", "GB", "TB", "PB", "EB"];
+
+impl ByteStyle {
+    fn base(self) -> u128 {
+        match self {
+            ByteStyle::Binary => 1024,
+            ByteStyle::Decimal => 1000,
+        }
+    }
+
+    fn units(self) -> &'static [&'static str; 7] {
+        match self {
+            ByteStyle::Binary => &BINARY_BYTE_UNITS,
+            ByteStyle::Decimal => &DECIMAL_BYTE_UNITS,
+        }
+    }
+}
+
+/*
+* Byte count for people, "512 B", "1.5 KiB", "9.31 GiB". Below one kilo- or kibibyte the count is exact,
+* above it is rounded half up to two decimals with trailing zeros trimmed. A value that rounds up to the next unit
+* is shown in it, 1 MiB - 1 is "1 MiB" and not "1024 KiB".
+*/
+pub fn format_bytes(n: u64, style: ByteStyle) -> String {
+    let base = style.base();
+    let units = style.units();
+    let mut exponent = 0;
+    while exponent + 1 < units.len() && u128::from(n) >= base.pow(exponent as u32 + 1) {
+        exponent += 1;
+    }
+    if exponent == 0 {
+        return format!("{} B", n);
+    }
+    let rounded = |exponent: usize| {
+        let unit = base.pow(exponent as u32);
+        (u128::from(n) * 100 + unit / 2) / unit
+    };
+    let mut hundredths = rounded(exponent);
+    if hundredths >= base * 100 && exponent + 1 < units.len() {
+        exponent += 1;
+        hundredths = rounded(exponent);
+    }
+    let (whole, fraction) = (hundredths / 100, hundredths % 100);
+    match (fraction, fraction % 10) {
+        (0, _) => format!("{} {}", whole, units[exponent]),
+        (fraction, 0) => format!("{}.{} {}", whole, fraction / 10, units[exponent]),
+        (fraction, _) => format!("{}.{:02} {}", whole, fraction, units[exponent]),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum ByteSizeError {
+    #[error("invalid byte size \"{0}\", expected a number and a unit like \"1.5 GiB\"")]
+    InvalidFormat(String),
+    #[error("unknown unit in byte size \"{0}\"")]
+    UnknownUnit(String),
+    #[error("byte size \"{0}\" is not a whole number of bytes")]
+    FractionalBytes(String),
+    #[error("byte size \"{0}\" does not fit in 64 bits")]
+    TooLarge(String),
+}
+
+impl SpannedParseError for ByteSizeError {
+    fn failure(&self) -> ParseFailure {
+        match self {
+            ByteSizeError::InvalidFormat(input)
+            | ByteSizeError::UnknownUnit(input)
+            | ByteSizeError::FractionalBytes(input)
+            | ByteSizeError::TooLarge(input) => ParseFailure::whole(self, input),
+        }
+    }
+}
+
+/*
+* Inverse of format_bytes for either style: a number with up to 9 decimals, optional whitespace and a unit.
+* Units are matched case-insensitively, "KB" is decimal like "kB", no unit means bytes.
+* Fractions are rounded half up to whole bytes, "1.5 B" is rejected since it can not be meant.
+*/
+pub fn parse_bytes(s: &str) -> Result<u64, ByteSizeError> {
+    let trimmed = s.trim();
+    let number_len = trimmed.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(trimmed.len());
+    let (number, unit) = (&trimmed[..number_len], trimmed[number_len..].trim_start());
+    let invalid = || ByteSizeError::InvalidFormat(truncate_input(s));
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() || (number.contains('.') && fraction.is_empty()) || fraction.len() > 9 {
+        return Err(invalid());
+    }
+    // Only digits are left, parsing the whole part can only fail on overflow.
+    let too_large = || ByteSizeError::TooLarge(truncate_input(s));
+    let whole = whole.parse::<u128>().map_err(|_| too_large())?;
+    let scale = 10u128.pow(fraction.len() as u32);
+    let fraction = match fraction.is_empty() {
+        true => 0,
+        false => fraction.parse::<u128>().map_err(|_| invalid())?,
+    };
+    let multiplier = match unit {
+        "" => 1,
+        unit => [ByteStyle::Binary, ByteStyle::Decimal]
+            .into_iter()
+            .find_map(|style| {
+                let exponent = style.units().iter().position(|name| name.eq_ignore_ascii_case(unit))?;
+                Some(style.base().pow(exponent as u32))
+            })
+            .ok_or_else(|| ByteSizeError::UnknownUnit(truncate_input(s)))?,
+    };
+    if multiplier == 1 && fraction != 0 {
+        return Err(ByteSizeError::FractionalBytes(truncate_input(s)));
+    }
+    let bytes = whole
+        .checked_mul(multiplier)
+        .and_then(|bytes| bytes.checked_add((fraction * multiplier + scale / 2) / scale))
+        .ok_or_else(too_large)?;
+    u64::try_from(bytes).map_err(|_| too_large())
+}
+
+/*
+* Loggable representation of values holding secrets.
+* Implementations must never include key, token or signature bytes, only fingerprints of them.
+* Use `%value.redacted_display()` for tracing fields.
+*/
+pub trait Redact {
+    fn redacted(&self) -> String;
+
+    fn redacted_display(&self) -> RedactedDisplay<'_, Self>
+    where
+        Self: Sized,
+    {
+        RedactedDisplay(self)
+    }
+}
+
+pub struct RedactedDisplay<'a, T: Redact>(pub &'a T);
+
+impl<T: Redact> fmt::Display for RedactedDisplay<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.redacted())
+    }
+}
+
+// Longest file name in bytes on the common filesystems (ext4, APFS, NTFS counts UTF-16 units which is never more).
+pub const MAX_FILENAME_BYTES: usize = 255;
+// Characters Windows or macOS do not allow in file names, replaced with '_'.
+const FORBIDDEN_FILENAME_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const RESERVED_WINDOWS_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+const FILENAME_TIMESTAMP_FORMAT: &[time::format_description::FormatItem<'static>] =
+    time::macros::format_description!("[year]-[month]-[day]T[hour][minute][second]Z");
+// Length of a formatted FILENAME_TIMESTAMP_FORMAT.
+const FILENAME_TIMESTAMP_LEN: usize = 18;
+
+fn format_extension(format: &DownloadFormat) -> &'static str {
+    match format {
+        DownloadFormat::Zip => "zip",
+        DownloadFormat::Tar => "tar",
+        DownloadFormat::Raw => "raw",
+    }
+}
+
+fn compression_extension(compression: &BucketCompression) -> Option<&'static str> {
+    match compression {
+        BucketCompression::None => None,
+        BucketCompression::Gzip => Some("gz"),
+        BucketCompression::Brotli => Some("br"),
+        BucketCompression::Zstd => Some("zst"),
+    }
+}
+
+// File name safe on Windows, macOS and Linux. Extensions this module appends are stripped so they are not doubled.
+fn sanitize_file_stem(name: &str) -> String {
+    let mut stem = name
+        .chars()
+        .map(|char| match char.is_control() || FORBIDDEN_FILENAME_CHARS.contains(&char) {
+            true => '_',
+            false => char,
+        })
+        .collect::<String>();
+    let known = |extension: &str| {
+        [DownloadFormat::Zip, DownloadFormat::Tar, DownloadFormat::Raw].iter().any(|format| format_extension(format) == extension)
+            || [BucketCompression::Gzip, BucketCompression::Brotli, BucketCompression::Zstd]
+                .iter()
+                .any(|compression| compression_extension(compression) == Some(extension))
+    };
+    while let Some((rest, extension)) = stem.rsplit_once('.') {
+        if rest.is_empty() || !known(&extension.to_ascii_lowercase()) {
+            break;
+        }
+        stem.truncate(rest.len());
+    }
+    trim_file_stem(&mut stem);
+    // Leading dots hide the file on Unix.
+    if stem.starts_with('.') {
+        stem.replace_range(..1, "_");
+    }
+    let device = stem.split('.').next().unwrap_or_default();
+    if RESERVED_WINDOWS_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(device)) {
+        stem.insert(0, '_');
+    }
+    if stem.is_empty() {
+        stem.push_str("bucket");
+    }
+    stem
+}
+
+// Windows drops trailing dots and spaces.
+fn trim_file_stem(stem: &mut String) {
+    stem.truncate(stem.trim_end_matches(['.', ' ']).len());
+}
+
+/*
+* File name for a bucket download, e.g. "photos-2024-06-01T120000Z.tar.zst". The timestamp is in UTC and left out when None.
+* Deterministic for the same input. The bucket name is sanitized and cut to fit MAX_FILENAME_BYTES,
+* the timestamp and extension are always kept.
+*/
+pub fn suggest_filename(
+    bucket_name: &BucketName,
+    format: DownloadFormat,
+    compression: BucketCompression,
+    timestamp: Option<OffsetDateTime>,
+) -> String {
+    let mut suffix = String::new();
+    if let Some(timestamp) = timestamp {
+        let timestamp = timestamp
+            .to_offset(time::UtcOffset::UTC)
+            .format(FILENAME_TIMESTAMP_FORMAT)
+            .expect("year of an OffsetDateTime always formats");
+        suffix.push('-');
+        suffix.push_str(&timestamp);
+    }
+    suffix.push('.');
+    suffix.push_str(format_extension(&format));
+    if let Some(extension) = compression_extension(&compression) {
+        suffix.push('.');
+        suffix.push_str(extension);
+    }
+    let mut stem = sanitize_file_stem(bucket_name.as_str());
+    let max_stem = MAX_FILENAME_BYTES - suffix.len();
+    if stem.len() > max_stem {
+        let end = (0..=max_stem).rev().find(|index| stem.is_char_boundary(*index)).unwrap_or(0);
+        stem.truncate(end);
+        trim_file_stem(&mut stem);
+    }
+    stem + &suffix
+}
+
+/*
+* Inverse of suggest_filename for resuming downloads: the sanitized bucket name, format and compression.
+* None when the name does not end in a download extension. The timestamp is dropped.
+*/
+pub fn parse_filename(filename: &str) -> Option<(String, DownloadFormat, BucketCompression)> {
+    let (mut rest, mut compression) = (filename, BucketCompression::None);
+    for candidate in [BucketCompression::Gzip, BucketCompression::Brotli, BucketCompression::Zstd] {
+        let extension = compression_extension(&candidate).expect("compressed formats have an extension");
+        if let Some(stripped) = rest.strip_suffix(extension).and_then(|rest| rest.strip_suffix('.')) {
+            (rest, compression) = (stripped, candidate);
+            break;
+        }
+    }
+    let (mut stem, format) = [DownloadFormat::Zip, DownloadFormat::Tar, DownloadFormat::Raw]
+        .into_iter()
+        .find_map(|format| {
+            let stem = rest.strip_suffix(format_extension(&format))?.strip_suffix('.')?;
+            Some((stem, format))
+        })?;
+    if let Some((name, timestamp)) = stem.split_at_checked(stem.len().saturating_sub(FILENAME_TIMESTAMP_LEN)) {
+        let name = name.strip_suffix('-').filter(|name| !name.is_empty());
+        if let Some(name) = name.filter(|_| time::PrimitiveDateTime::parse(timestamp, FILENAME_TIMESTAMP_FORMAT).is_ok()) {
+            stem = name;
+        }
+    }
+    match stem.is_empty() {
+        true => None,
+        false => Some((stem.to_string(), format, compression)),
+    }
+}
+
+// Ed25519 signatures as unpadded base64url strings, for #[serde(with)].
+pub(crate) mod signature_base64 {
+    use base64::{engine::general_purpose, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &ed25519_compact::Signature, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&general_purpose::URL_SAFE_NO_PAD.encode(value.as_slice()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ed25519_compact::Signature, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let bytes = general_purpose::URL_SAFE_NO_PAD.decode(s).map_err(serde::de::Error::custom)?;
+        ed25519_compact::Signature::from_slice(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+/*
+* Unpadded base64url, the alphabet of general_purpose::URL_SAFE_NO_PAD, streamed from a reader to a writer.
+* Input is handled in chunks of whole 3 byte groups (4 characters decoding), so the output is exactly what the base64
+* crate gives for the whole input, errors included, while no buffer is larger than 4 KiB and nothing is allocated.
+*/
+pub mod base64_stream {
+    use std::io::{self, Read, Write};
+
+    use base64::{engine::general_purpose, DecodeError, DecodeSliceError, Engine};
+
+    // Bytes per encoding chunk, a multiple of 3. Encoded it is ENCODED_CHUNK_LEN characters.
+    const DECODED_CHUNK_LEN: usize = 3 * 1024;
+    const ENCODED_CHUNK_LEN: usize = 4 * 1024;
+
+    #[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
+    pub enum Base64StreamError {
+        #[error(transparent)]
+        Io(#[from] io::Error),
+        // Offsets are from the start of the stream, as if the whole input had been decoded at once.
+        #[error(transparent)]
+        Decode(DecodeError),
+    }
+
+    // Fills buffer unless the reader ends first, short reads would otherwise break the group alignment.
+    fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(filled)
+    }
+
+    // Returns the number of characters written.
+    pub fn encode_to_writer(mut reader: impl Read, mut writer: impl Write) -> io::Result<u64> {
+        let mut input = [0; DECODED_CHUNK_LEN];
+        let mut output = [0; ENCODED_CHUNK_LEN];
+        let mut written = 0;
+        loop {
+            let read = read_full(&mut reader, &mut input)?;
+            if read == 0 {
+                return Ok(written);
+            }
+            let encoded = general_purpose::URL_SAFE_NO_PAD
+                .encode_slice(&input[..read], &mut output)
+                .expect("a full chunk fits the output buffer");
+            writer.write_all(&output[..encoded])?;
+            written += encoded as u64;
+            if read < input.len() {
+                return Ok(written);
+            }
+        }
+    }
+
+    /*
+    * Returns the number of bytes written. One byte past every full chunk is read ahead: only the end of the whole input
+    * may hold padding or a partial group, so every chunk before it has to be plain alphabet.
+    */
+    pub fn decode_to_writer(mut reader: impl Read, mut writer: impl Write) -> Result<u64, Base64StreamError> {
+        let mut input = [0; ENCODED_CHUNK_LEN];
+        let mut output = [0; DECODED_CHUNK_LEN];
+        let mut carried = None;
+        let mut consumed = 0;
+        let mut written = 0;
+        loop {
+            let start = match carried.take() {
+                Some(byte) => {
+                    input[0] = byte;
+                    1
+                }
+                None => 0,
+            };
+            let read = start + read_full(&mut reader, &mut input[start..])?;
+            let mut next = [0; 1];
+            let last = read < input.len() || read_full(&mut reader, &mut next)? == 0;
+            let chunk = &input[..read];
+            if !last {
+                if let Some(position) = chunk.iter().position(|byte| !is_alphabet(*byte)) {
+                    let error = DecodeError::InvalidByte(consumed + position, chunk[position]);
+                    let error = drain_for_length(reader, consumed + read + 1, next[0], error)?;
+                    return Err(Base64StreamError::Decode(error));
+                }
+                carried = Some(next[0]);
+            }
+            let decoded = general_purpose::URL_SAFE_NO_PAD
+                .decode_slice(chunk, &mut output)
+                .map_err(|error| match error {
+                    DecodeSliceError::DecodeError(error) => Base64StreamError::Decode(offset_by(error, consumed)),
+                    DecodeSliceError::OutputSliceTooSmall => unreachable!("a full chunk fits the output buffer"),
+                })?;
+            writer.write_all(&output[..decoded])?;
+            consumed += read;
+            written += decoded as u64;
+            if last {
+                return Ok(written);
+            }
+        }
+    }
+
+    fn is_alphabet(byte: u8) -> bool {
+        byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_')
+    }
+
+    /*
+    * The base64 crate checks the input length before any byte, an invalid byte early in the stream is only reported
+    * once the rest has been read and the length turned out fine. Nothing more gets written meanwhile.
+    */
+    fn drain_for_length(
+        mut reader: impl Read,
+        mut len: usize,
+        mut last: u8,
+        error: DecodeError,
+    ) -> io::Result<DecodeError> {
+        let mut buffer = [0; ENCODED_CHUNK_LEN];
+        loop {
+            let read = read_full(&mut reader, &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            len += read;
+            last = buffer[read - 1];
+        }
+        Ok(match len % 4 == 1 {
+            true if last != b'=' && !is_alphabet(last) => DecodeError::InvalidByte(len - 1, last),
+            true => DecodeError::InvalidLength,
+            false => error,
+        })
+    }
+
+    fn offset_by(error: DecodeError, consumed: usize) -> DecodeError {
+        match error {
+            DecodeError::InvalidByte(offset, byte) => DecodeError::InvalidByte(offset + consumed, byte),
+            DecodeError::InvalidLastSymbol(offset, byte) => DecodeError::InvalidLastSymbol(offset + consumed, byte),
+            DecodeError::InvalidLength => DecodeError::InvalidLength,
+            DecodeError::InvalidPadding => DecodeError::InvalidPadding,
+        }
+    }
+}
+
+/*
+* Non-empty half-open byte span [start, end_exclusive) of an object.
+* HTTP Content-Range uses closed spans, "bytes 0-499/1234" is the span [0, 500) of a 1234 byte object.
+* Keep spans half-open everywhere and only convert at the HTTP boundary, mixing both is how off-by-one corruption happens.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ByteSpan {
+    start: u64,
+    end_exclusive: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum ByteSpanError {
+    #[error("byte span {start}..{end_exclusive} is empty")]
+    Empty { start: u64, end_exclusive: u64 },
+    #[error("invalid content range \"{0}\", expected \"bytes <first>-<last>/<total or *>\"")]
+    InvalidContentRange(String),
+    #[error("byte span ending at {end_exclusive} is outside of the {total} byte object")]
+    OutOfBounds { end_exclusive: u64, total: u64 },
+}
+
+// Digits only, u64::from_str would also take a leading '+'.
+fn parse_content_range_number(s: &str) -> Option<u64> {
+    match !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().ok(),
+        false => None,
+    }
+}
+
+// Spans are never empty, so there is no is_empty.
+#[allow(clippy::len_without_is_empty)]
+impl ByteSpan {
+    pub fn new(start: u64, end_exclusive: u64) -> Result<Self, ByteSpanError> {
+        if end_exclusive <= start {
+            return Err(ByteSpanError::Empty { start, end_exclusive });
+        }
+        Ok(Self { start, end_exclusive })
+    }
+
+    // Parses the value of a Content-Range header, returning the span and the total object length if known.
+    // The unsatisfied range form "bytes */1234" has no span and is rejected, as are spans reaching past the total.
+    pub fn from_http_content_range(value: &str) -> Result<(Self, Option<u64>), ByteSpanError> {
+        let invalid = || ByteSpanError::InvalidContentRange(truncate_input(value));
+        let (span, total) = value
+            .strip_prefix("bytes ")
+            .and_then(|rest| rest.split_once('/'))
+            .ok_or_else(invalid)?;
+        let (first, last) = span.split_once('-').ok_or_else(invalid)?;
+        let first = parse_content_range_number(first).ok_or_else(invalid)?;
+        let last = parse_content_range_number(last).ok_or_else(invalid)?;
+        let total = match total {
+            "*" => None,
+            total => Some(parse_content_range_number(total).ok_or_else(invalid)?),
+        };
+        // A closed span ending at u64::MAX has no half-open end.
+        let end_exclusive = last.checked_add(1).ok_or_else(invalid)?;
+        if last < first {
+            return Err(invalid());
+        }
+        let span = Self::new(first, end_exclusive)?;
+        if let Some(total) = total {
+            if end_exclusive > total {
+                return Err(ByteSpanError::OutOfBounds { end_exclusive, total });
+            }
+        }
+        Ok((span, total))
+    }
+
+    // "bytes <first>-<last>/<total>", with "*" for an unknown total.
+    pub fn to_http_content_range(&self, total: Option<u64>) -> String {
+        let last = self.end_exclusive - 1;
+        match total {
+            Some(total) => format!("bytes {}-{}/{}", self.start, last, total),
+            None => format!("bytes {}-{}/*", self.start, last),
+        }
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end_exclusive(&self) -> u64 {
+        self.end_exclusive
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end_exclusive - self.start
+    }
+
+    pub fn contains(&self, offset: u64) -> bool {
+        self.start <= offset && offset < self.end_exclusive
+    }
+
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        Self::new(self.start.max(other.start), self.end_exclusive.min(other.end_exclusive)).ok()
+    }
+
+    // The covering span when the two overlap or touch, [0, 10) and [10, 20) union to [0, 20).
+    pub fn union_if_adjacent(&self, other: &Self) -> Option<Self> {
+        if self.end_exclusive < other.start || other.end_exclusive < self.start {
+            return None;
+        }
+        Some(Self {
+            start: self.start.min(other.start),
+            end_exclusive: self.end_exclusive.max(other.end_exclusive),
+        })
+    }
+
+    // Splits into [start, offset) and [offset, end_exclusive), offset is absolute and has to leave both halves non-empty.
+    pub fn split_at(&self, offset: u64) -> Option<(Self, Self)> {
+        if offset <= self.start || offset >= self.end_exclusive {
+            return None;
+        }
+        Some((
+            Self {
+                start: self.start,
+                end_exclusive: offset,
+            },
+            Self {
+                start: offset,
+                end_exclusive: self.end_exclusive,
+            },
+        ))
+    }
+}
+
+/*
+* Set of byte spans, e.g. the parts of an upload a storage node already received.
+* Always normalized: sorted by start, with overlapping and touching spans merged into one.
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct SpanSet {
+    spans: Vec<ByteSpan>,
+}
+
+impl SpanSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spans(&self) -> &[ByteSpan] {
+        &self.spans
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    // Number of bytes covered, saturating for sets covering nearly all of u64.
+    pub fn covered_len(&self) -> u64 {
+        self.spans.iter().fold(0u64, |sum, span| sum.saturating_add(span.len()))
+    }
+
+    pub fn insert(&mut self, span: ByteSpan) {
+        // Spans ending before the new one starts, and spans starting at or before it ends, stay apart or merge.
+        let first = self.spans.partition_point(|existing| existing.end_exclusive < span.start);
+        let last = self.spans.partition_point(|existing| existing.start <= span.end_exclusive);
+        let merged = self.spans[first..last]
+            .iter()
+            .fold(span, |merged, existing| merged.union_if_adjacent(existing).unwrap_or(merged));
+        self.spans.splice(first..last, [merged]);
+    }
+
+    // The spans of [0, total_len) not in the set, what is still missing of a total_len byte object.
+    pub fn complement(&self, total_len: u64) -> SpanSet {
+        let mut missing = Vec::new();
+        let mut cursor = 0;
+        for span in &self.spans {
+            if span.start >= total_len {
+                break;
+            }
+            if span.start > cursor {
+                missing.push(ByteSpan {
+                    start: cursor,
+                    end_exclusive: span.start,
+                });
+            }
+            cursor = span.end_exclusive;
+        }
+        if cursor < total_len {
+            missing.push(ByteSpan {
+                start: cursor,
+                end_exclusive: total_len,
+            });
+        }
+        SpanSet { spans: missing }
+    }
+
+    pub fn is_complete(&self, total_len: u64) -> bool {
+        self.complement(total_len).is_empty()
+    }
+}
+
+impl FromIterator<ByteSpan> for SpanSet {
+    fn from_iter<I: IntoIterator<Item = ByteSpan>>(iter: I) -> Self {
+        let mut set = SpanSet::new();
+        for span in iter {
+            set.insert(span);
+        }
+        set
+    }
+}
+
+// Whether the two strings have a common substring of at least `length` bytes. Used by tests checking for leaked secrets.
+#[cfg(all(test, feature = "share_link"))]
+pub(crate) fn shares_substring(haystack: &str, needle: &str, length: usize) -> bool {
+    needle
+        .as_bytes()
+        .windows(length)
+        .any(|window| haystack.as_bytes().windows(length).any(|candidate| candidate == window))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_time_eq_compares_bytes() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+
+    #[test]
+    fn byte_formatting() {
+        const KIB: u64 = 1024;
+        const TIB: u64 = 1024 * 1024 * 1024 * 1024;
+        let table = [
+            (0, "0 B", "0 B"),
+            (512, "512 B", "512 B"),
+            (999, "999 B", "999 B"),
+            (1000, "1000 B", "1 kB"),
+            (1023, "1023 B", "1.02 kB"),
+            (KIB, "1 KiB", "1.02 kB"),
+            (1536, "1.5 KiB", "1.54 kB"),
+            (1_500_000, "1.43 MiB", "1.5 MB"),
+            (KIB * KIB - 1, "1 MiB", "1.05 MB"),
+            (999_999, "976.56 KiB", "1 MB"),
+            (10_000_000_000, "9.31 GiB", "10 GB"),
+            (TIB - 1, "1 TiB", "1.1 TB"),
+            (TIB, "1 TiB", "1.1 TB"),
+            (u64::MAX, "16 EiB", "18.45 EB"),
+        ];
+        for (bytes, binary, decimal) in table {
+            assert_eq!(format_bytes(bytes, ByteStyle::Binary), binary, "{}", bytes);
+            assert_eq!(format_bytes(bytes, ByteStyle::Decimal), decimal, "{}", bytes);
+        }
+    }
+
+    #[test]
+    fn byte_parsing() {
+        let table = [
+            ("512", 512),
+            ("512 B", 512),
+            ("1.0 b", 1),
+            ("1 KiB", 1024),
+            ("1.5kib", 1536),
+            ("1 kB", 1000),
+            ("1 KB", 1000),
+            (" 9.31 GiB ", 9_996_536_381),
+            ("10 GB", 10_000_000_000),
+            ("0.001 kB", 1),
+        ];
+        for (input, bytes) in table {
+            assert_eq!(parse_bytes(input), Ok(bytes), "{}", input);
+        }
+        let invalid = |input: &str| ByteSizeError::InvalidFormat(input.to_string());
+        assert_eq!(parse_bytes(""), Err(invalid("")));
+        assert_eq!(parse_bytes("GiB"), Err(invalid("GiB")));
+        assert_eq!(parse_bytes("-1 B"), Err(invalid("-1 B")));
+        assert_eq!(parse_bytes("1. KiB"), Err(invalid("1. KiB")));
+        assert_eq!(parse_bytes(".5 KiB"), Err(invalid(".5 KiB")));
+        assert_eq!(parse_bytes("1.2.3 KiB"), Err(invalid("1.2.3 KiB")));
+        assert_eq!(parse_bytes("0.0000000001 GiB"), Err(invalid("0.0000000001 GiB")));
+        assert_eq!(parse_bytes("1 KiBs"), Err(ByteSizeError::UnknownUnit("1 KiBs".to_string())));
+        assert_eq!(parse_bytes("1 Kb"), Ok(1000));
+        assert_eq!(parse_bytes("1.5"), Err(ByteSizeError::FractionalBytes("1.5".to_string())));
+        assert_eq!(parse_bytes("16 EiB"), Err(ByteSizeError::TooLarge("16 EiB".to_string())));
+        assert_eq!(parse_bytes("15.99 EiB"), Ok(18_435_214_858_663_483_146));
+        assert_eq!(parse_bytes(&u64::MAX.to_string()), Ok(u64::MAX));
+        assert!(matches!(parse_bytes("99999999999999999999999999999999999999999"), Err(ByteSizeError::TooLarge(_))));
+    }
+
+    // Formatting rounds, so parsing the text gives the value back to within the rounding of two decimals.
+    #[test]
+    fn byte_round_trip() {
+        for style in [ByteStyle::Binary, ByteStyle::Decimal] {
+            for bytes in [0, 1, 1023, 1024, 1536, 999_999, 1 << 40, (1 << 40) - 1, 123_456_789_012, u64::MAX / 2] {
+                let text = format_bytes(bytes, style);
+                let parsed = parse_bytes(&text).unwrap();
+                assert_eq!(format_bytes(parsed, style), text);
+                let tolerance = (bytes / 200).max(if bytes < 1000 { 0 } else { 1 });
+                assert!(parsed.abs_diff(bytes) <= tolerance, "{} {} {}", bytes, text, parsed);
+            }
+        }
+    }
+
+    #[test]
+    fn truncate_input_keeps_short_input() {
+        assert_eq!(truncate_input(""), "");
+        assert_eq!(truncate_input("eu-north-1"), "eu-north-1");
+        let exact = "x".repeat(MAX_ERROR_INPUT_LEN);
+        assert_eq!(truncate_input(&exact), exact);
+    }
+
+    #[test]
+    fn truncate_input_truncates_on_char_boundary() {
+        let input = "\u{e5}".repeat(100);
+        let truncated = truncate_input(&input);
+        assert_eq!(truncated, format!("{}...", "\u{e5}".repeat(MAX_ERROR_INPUT_LEN)));
+    }
+
+    #[test]
+    fn fingerprint_round_trip() {
+        let fingerprint = Fingerprint::of(b"bucketdrive");
+        assert_eq!(fingerprint.to_string().len(), 8);
+        assert_eq!(fingerprint.to_string().parse::<Fingerprint>(), Ok(fingerprint));
+        let json = serde_json::to_string(&fingerprint).unwrap();
+        assert_eq!(json, format!("\"{}\"", fingerprint));
+        assert!("abcd123".parse::<Fingerprint>().is_err());
+        assert!("abcd123g".parse::<Fingerprint>().is_err());
+        assert!("abcd12\u{e5}".parse::<Fingerprint>().is_err());
+    }
+
+    #[test]
+    fn human_duration_parsing() {
+        let hours = |hours: i64| Ok(HumanDuration(Duration::hours(hours)));
+        let minutes = |minutes: i64| Ok(HumanDuration(Duration::minutes(minutes)));
+        let invalid = |s: &str| Err(HumanDurationError::InvalidFormat(s.to_string()));
+        let table = [
+            ("7d", hours(7 * 24)),
+            ("12h", hours(12)),
+            ("30m", minutes(30)),
+            ("90m", minutes(90)),
+            ("2w", hours(14 * 24)),
+            ("1d12h", hours(36)),
+            ("1w2d3h4m5s", Ok(HumanDuration(Duration::seconds(788645)))),
+            ("45s", Ok(HumanDuration(Duration::seconds(45)))),
+            (" 1d 12h ", hours(36)),
+            ("1d\t12 h", hours(36)),
+            ("PT1H30M", minutes(90)),
+            ("P2W", hours(14 * 24)),
+            ("P1DT12H", hours(36)),
+            ("PT45S", Ok(HumanDuration(Duration::seconds(45)))),
+            ("7dd", invalid("7dd")),
+            ("d7", invalid("d7")),
+            ("7", invalid("7")),
+            ("", invalid("")),
+            ("7days", invalid("7days")),
+            ("12h1d", invalid("12h1d")),
+            ("1h1h", invalid("1h1h")),
+            ("7D", invalid("7D")),
+            ("1.5h", invalid("1.5h")),
+            ("+7d", invalid("+7d")),
+            ("P", invalid("P")),
+            ("PT", invalid("PT")),
+            ("P1DT", invalid("P1DT")),
+            ("P1Y", invalid("P1Y")),
+            ("P1M", invalid("P1M")),
+            ("PT1H 30M", invalid("PT1H 30M")),
+            ("pt1h", invalid("pt1h")),
+            ("0d", Err(HumanDurationError::Zero)),
+            ("0h0m", Err(HumanDurationError::Zero)),
+            ("PT0S", Err(HumanDurationError::Zero)),
+            ("-7d", Err(HumanDurationError::Negative("-7d".to_string()))),
+            ("5217w", hours(5217 * 7 * 24)),
+            ("36525d", hours(36525 * 24)),
+            ("36526d", Err(HumanDurationError::TooLong("36526d".to_string()))),
+            ("99999999999999999999d", Err(HumanDurationError::TooLong("99999999999999999999d".to_string()))),
+            ("9999999999999999w", Err(HumanDurationError::TooLong("9999999999999999w".to_string()))),
+        ];
+        for (input, expected) in table {
+            assert_eq!(input.parse::<HumanDuration>(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn human_duration_round_trip() {
+        for (input, display) in [("7d", "1w"), ("90m", "1h30m"), ("PT1H30M", "1h30m"), ("1d12h", "1d12h"), ("14d", "2w"), ("61s", "1m1s")] {
+            let duration: HumanDuration = input.parse().unwrap();
+            assert_eq!(duration.to_string(), display);
+            assert_eq!(display.parse::<HumanDuration>(), Ok(duration));
+            let json = serde_json::to_string(&duration).unwrap();
+            assert_eq!(json, format!("\"{}\"", display));
+            assert_eq!(serde_json::from_str::<HumanDuration>(&json).unwrap(), duration);
+        }
+        assert!(serde_json::from_str::<HumanDuration>("\"0s\"").is_err());
+    }
+
+    #[test]
+    fn human_duration_expiry() {
+        let now = time::macros::datetime!(2024-06-01 12:00:00.5 UTC);
+        let expiry = "1d".parse::<HumanDuration>().unwrap().expiry_from_now(now).unwrap();
+        assert_eq!(expiry.as_offset_date_time(), time::macros::datetime!(2024-06-02 12:00 UTC));
+        let longest = HumanDuration::new(MAX_EXPIRY_HORIZON).unwrap();
+        assert!(longest.expiry_from_now(now).is_ok());
+
+        let before_min = time::macros::datetime!(2010-01-01 0:00 UTC);
+        assert!(matches!(
+            "1d".parse::<HumanDuration>().unwrap().expiry_from_now(before_min),
+            Err(ExpiryTimestampError::TooEarly(_))
+        ));
+        assert_eq!(HumanDuration::new(Duration::milliseconds(500)), Err(HumanDurationError::Zero));
+        assert!(matches!(HumanDuration::new(Duration::hours(-1)), Err(HumanDurationError::Negative(_))));
+    }
+
+    fn bucket_name(name: &str) -> BucketName {
+        BucketName::try_from(name.to_string()).unwrap()
+    }
+
+    #[test]
+    fn filename_for_every_format_and_compression() {
+        let photos = bucket_name("photos");
+        let timestamp = Some(time::macros::datetime!(2024-06-01 14:00:00 +02:00));
+        let formats = [(DownloadFormat::Zip, "zip"), (DownloadFormat::Tar, "tar"), (DownloadFormat::Raw, "raw")];
+        let compressions = [
+            (BucketCompression::None, ""),
+            (BucketCompression::Gzip, ".gz"),
+            (BucketCompression::Brotli, ".br"),
+            (BucketCompression::Zstd, ".zst"),
+        ];
+        for (format, format_extension) in &formats {
+            for (compression, compression_extension) in &compressions {
+                let filename = suggest_filename(&photos, format.clone(), compression.clone(), timestamp);
+                assert_eq!(filename, format!("photos-2024-06-01T120000Z.{}{}", format_extension, compression_extension));
+                assert_eq!(
+                    parse_filename(&filename),
+                    Some(("photos".to_string(), format.clone(), compression.clone()))
+                );
+                let filename = suggest_filename(&photos, format.clone(), compression.clone(), None);
+                assert_eq!(filename, format!("photos.{}{}", format_extension, compression_extension));
+                assert_eq!(
+                    parse_filename(&filename),
+                    Some(("photos".to_string(), format.clone(), compression.clone()))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn filename_extensions_are_not_doubled() {
+        let backup = bucket_name("backup.tar.gz");
+        assert_eq!(suggest_filename(&backup, DownloadFormat::Tar, BucketCompression::Zstd, None), "backup.tar.zst");
+        let notes = bucket_name("notes.v2");
+        assert_eq!(suggest_filename(&notes, DownloadFormat::Zip, BucketCompression::None, None), "notes.v2.zip");
+        assert_eq!(parse_filename("notes.v2.zip"), Some(("notes.v2".to_string(), DownloadFormat::Zip, BucketCompression::None)));
+    }
+
+    #[test]
+    fn filename_is_sanitized() {
+        let name = bucket_name("Team: Q1/Q2 <draft>? \u{e5}ngstr\u{f6}m \u{1f4f7}");
+        let filename = suggest_filename(&name, DownloadFormat::Tar, BucketCompression::Gzip, None);
+        assert_eq!(filename, "Team_ Q1_Q2 _draft__ \u{e5}ngstr\u{f6}m \u{1f4f7}.tar.gz");
+        assert_eq!(
+            parse_filename(&filename),
+            Some(("Team_ Q1_Q2 _draft__ \u{e5}ngstr\u{f6}m \u{1f4f7}".to_string(), DownloadFormat::Tar, BucketCompression::Gzip))
+        );
+        assert_eq!(suggest_filename(&bucket_name(".hidden"), DownloadFormat::Zip, BucketCompression::None, None), "_hidden.zip");
+        assert_eq!(suggest_filename(&bucket_name("con"), DownloadFormat::Zip, BucketCompression::None, None), "_con.zip");
+        assert_eq!(suggest_filename(&bucket_name("dots..."), DownloadFormat::Zip, BucketCompression::None, None), "dots.zip");
+        assert_eq!(suggest_filename(&bucket_name(".tar"), DownloadFormat::Raw, BucketCompression::None, None), "_tar.raw");
+    }
+
+    #[test]
+    fn long_filename_keeps_extension() {
+        let timestamp = Some(time::macros::datetime!(2024-06-01 12:00 UTC));
+        let name = bucket_name(&"a".repeat(300));
+        let filename = suggest_filename(&name, DownloadFormat::Tar, BucketCompression::Zstd, timestamp);
+        assert_eq!(filename.len(), MAX_FILENAME_BYTES);
+        assert!(filename.ends_with("a-2024-06-01T120000Z.tar.zst"), "{}", filename);
+        assert_eq!(parse_filename(&filename).map(|(stem, _, _)| stem.len()), Some(MAX_FILENAME_BYTES - 27));
+
+        // Cut on a char boundary, 300 two byte chars.
+        let name = bucket_name(&"\u{f6}".repeat(300));
+        let filename = suggest_filename(&name, DownloadFormat::Zip, BucketCompression::None, None);
+        assert!(filename.len() <= MAX_FILENAME_BYTES);
+        assert!(filename.ends_with("\u{f6}.zip"), "{}", filename);
+    }
+
+    #[test]
+    fn unparseable_filenames() {
+        for filename in ["photos", "photos.gz", "photos.zst", ".zip", "photos.tar.xz", "photos.TAR"] {
+            assert_eq!(parse_filename(filename), None, "{}", filename);
+        }
+        // Not a timestamp, kept as part of the name.
+        assert_eq!(
+            parse_filename("photos-2024-13-01T120000Z.zip"),
+            Some(("photos-2024-13-01T120000Z".to_string(), DownloadFormat::Zip, BucketCompression::None))
+        );
+    }
+
+    #[test]
+    fn content_range_parsing() {
+        let span = |start, end_exclusive| ByteSpan::new(start, end_exclusive).unwrap();
+        assert_eq!(ByteSpan::from_http_content_range("bytes 0-499/1234"), Ok((span(0, 500), Some(1234))));
+        assert_eq!(ByteSpan::from_http_content_range("bytes 500-1233/*"), Ok((span(500, 1234), None)));
+        // A single byte, first and last are the same.
+        assert_eq!(ByteSpan::from_http_content_range("bytes 7-7/8"), Ok((span(7, 8), Some(8))));
+        assert_eq!(span(7, 8).len(), 1);
+        assert_eq!(span(7, 8).to_http_content_range(Some(8)), "bytes 7-7/8");
+        assert_eq!(span(0, 500).to_http_content_range(None), "bytes 0-499/*");
+
+        for invalid in [
+            "bytes */1234",
+            "bytes 5-4/10",
+            "bytes 0-499",
+            "bytes=0-499/1234",
+            "bytes -499/1234",
+            "bytes +0-499/1234",
+            "bytes 0-499/-1",
+            "bytes 0-18446744073709551615/*",
+            " bytes 0-499/1234",
+        ] {
+            assert_eq!(
+                ByteSpan::from_http_content_range(invalid),
+                Err(ByteSpanError::InvalidContentRange(invalid.to_string())),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(ByteSpan::from_http_content_range("bytes 0-499/500"), Ok((span(0, 500), Some(500))));
+        assert_eq!(
+            ByteSpan::from_http_content_range("bytes 0-500/500"),
+            Err(ByteSpanError::OutOfBounds {
+                end_exclusive: 501,
+                total: 500
+            })
+        );
+        assert_eq!(
+            ByteSpan::from_http_content_range("bytes 0-0/0"),
+            Err(ByteSpanError::OutOfBounds {
+                end_exclusive: 1,
+                total: 0
+            })
+        );
+    }
+
+    #[test]
+    fn byte_span_operations() {
+        let span = |start, end_exclusive| ByteSpan::new(start, end_exclusive).unwrap();
+        assert_eq!(ByteSpan::new(5, 5), Err(ByteSpanError::Empty { start: 5, end_exclusive: 5 }));
+        assert!(ByteSpan::new(6, 5).is_err());
+
+        assert_eq!(span(0, 10).intersect(&span(5, 20)), Some(span(5, 10)));
+        assert_eq!(span(0, 10).intersect(&span(10, 20)), None);
+        assert_eq!(span(0, 10).union_if_adjacent(&span(10, 20)), Some(span(0, 20)));
+        assert_eq!(span(0, 10).union_if_adjacent(&span(3, 4)), Some(span(0, 10)));
+        assert_eq!(span(0, 10).union_if_adjacent(&span(11, 20)), None);
+
+        assert_eq!(span(0, 10).split_at(4), Some((span(0, 4), span(4, 10))));
+        assert_eq!(span(0, 10).split_at(0), None);
+        assert_eq!(span(0, 10).split_at(10), None);
+        assert_eq!(span(3, 4).split_at(3), None);
+        assert!(span(3, 4).contains(3) && !span(3, 4).contains(4));
+    }
+
+    #[test]
+    fn span_set_bookkeeping() {
+        let span = |start, end_exclusive| ByteSpan::new(start, end_exclusive).unwrap();
+        let mut received = SpanSet::new();
+        assert!(received.is_complete(0));
+        assert!(!received.is_complete(1));
+        received.insert(span(20, 30));
+        received.insert(span(0, 10));
+        received.insert(span(10, 15));
+        assert_eq!(received.spans(), [span(0, 15), span(20, 30)]);
+        assert_eq!(received.complement(40).spans(), [span(15, 20), span(30, 40)]);
+        assert_eq!(received.complement(18).spans(), [span(15, 18)]);
+        assert_eq!(received.covered_len(), 25);
+
+        received.insert(span(12, 35));
+        assert_eq!(received.spans(), [span(0, 35)]);
+        assert!(received.is_complete(35));
+        assert!(received.is_complete(20));
+        assert!(!received.is_complete(36));
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn truncate_input_never_panics(s in "\\PC*") {
+            let truncated = truncate_input(&s);
+            proptest::prop_assert!(truncated.chars().count() <= MAX_ERROR_INPUT_LEN + 3);
+        }
+
+        #[test]
+        fn content_range_round_trip(
+            start in 0u64..u64::MAX - 1,
+            len in 1u64..1 << 40,
+            extra in proptest::option::of(0u64..1 << 40),
+        ) {
+            let end_exclusive = start.saturating_add(len);
+            let span = ByteSpan::new(start, end_exclusive).unwrap();
+            let total = extra.map(|extra| end_exclusive.saturating_add(extra));
+            let header = span.to_http_content_range(total);
+            proptest::prop_assert_eq!(ByteSpan::from_http_content_range(&header), Ok((span, total)));
+        }
+
+        #[test]
+        fn span_set_stays_normalized(
+            inserted in proptest::collection::vec((0u64..200, 1u64..30), 0..20),
+            total_len in 0u64..256,
+        ) {
+            let spans = inserted
+                .iter()
+                .map(|(start, len)| ByteSpan::new(*start, start + len).unwrap())
+                .collect::<Vec<_>>();
+            let set = spans.iter().copied().collect::<SpanSet>();
+            for pair in set.spans().windows(2) {
+                // Sorted, with a gap between neighbours, touching spans would have been merged.
+                proptest::prop_assert!(pair[0].end_exclusive() < pair[1].start());
+            }
+
+            let covered = |offset: u64| spans.iter().any(|span| span.contains(offset));
+            let missing = set.complement(total_len);
+            for offset in 0..256 {
+                proptest::prop_assert_eq!(set.spans().iter().any(|span| span.contains(offset)), covered(offset));
+                let expected_missing = offset < total_len && !covered(offset);
+                proptest::prop_assert_eq!(missing.spans().iter().any(|span| span.contains(offset)), expected_missing);
+            }
+            proptest::prop_assert_eq!(set.is_complete(total_len), (0..total_len).all(covered));
+            proptest::prop_assert_eq!(set.covered_len(), (0..256).filter(|offset| covered(*offset)).count() as u64);
+
+            // Insertion order does not matter.
+            let reversed = spans.iter().rev().copied().collect::<SpanSet>();
+            proptest::prop_assert_eq!(reversed, set);
+        }
+    }
+
+    // Hands out at most 7 bytes per read, chunks still have to stay aligned to whole groups.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let len = buffer.len().min(self.0.len()).min(7);
+            buffer[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn base64_stream_matches_the_base64_crate() {
+        use base64::{engine::general_purpose, Engine};
+
+        for len in [0, 1, 2, 3, 4, 3071, 3072, 3073, 4095, 4096, 4097, 6144, 10_000] {
+            let bytes = (0..len).map(|index| (index * 7 + index / 256) as u8).collect::<Vec<_>>();
+            let expected = general_purpose::URL_SAFE_NO_PAD.encode(&bytes);
+
+            let mut encoded = Vec::new();
+            let written = base64_stream::encode_to_writer(bytes.as_slice(), &mut encoded).unwrap();
+            assert_eq!(String::from_utf8(encoded.clone()).unwrap(), expected, "{}", len);
+            assert_eq!(written, expected.len() as u64);
+            let mut trickled = Vec::new();
+            base64_stream::encode_to_writer(Trickle(&bytes), &mut trickled).unwrap();
+            assert_eq!(trickled, encoded, "{}", len);
+
+            let mut decoded = Vec::new();
+            let written = base64_stream::decode_to_writer(expected.as_bytes(), &mut decoded).unwrap();
+            assert_eq!(decoded, bytes, "{}", len);
+            assert_eq!(written, len as u64);
+            let mut trickled = Vec::new();
+            base64_stream::decode_to_writer(Trickle(expected.as_bytes()), &mut trickled).unwrap();
+            assert_eq!(trickled, bytes, "{}", len);
+        }
+    }
+
+    #[test]
+    fn base64_stream_errors_match_the_base64_crate() {
+        use base64::{engine::general_purpose, Engine};
+
+        let valid = general_purpose::URL_SAFE_NO_PAD.encode([9; 6000]);
+        let mut invalid = vec![
+            "A".to_string(),
+            "AB==".to_string(),
+            "AB+/".to_string(),
+            "AB".to_string() + "C",
+            valid.clone() + "A",
+            valid.clone() + "AB",
+        ];
+        // Positions around the first chunk boundary, with suffixes making the total length invalid.
+        for position in [0, 10, 4095, 4096, 5000] {
+            for (replacement, suffix) in [("=", ""), ("+", ""), ("=", "A"), ("+", "!"), ("=", "AAAA!"), ("=", "AB")] {
+                let mut replaced = valid.clone() + suffix;
+                replaced.replace_range(position..position + 1, replacement);
+                invalid.push(replaced);
+            }
+        }
+        for input in invalid {
+            let expected = general_purpose::URL_SAFE_NO_PAD.decode(&input);
+            let streamed = base64_stream::decode_to_writer(input.as_bytes(), std::io::sink());
+            match (expected, streamed) {
+                (Ok(_), Ok(_)) => {}
+                (Err(expected), Err(base64_stream::Base64StreamError::Decode(error))) => assert_eq!(error, expected),
+                (expected, streamed) => panic!("{:?} {:?} for {}", expected, streamed, truncate_input(&input)),
+            }
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::framing::{expect_header, write_header, BinaryFormat, FramingError, FRAME_HEADER_LEN};
+use crate::ClusterId;
+
+pub const VERSION_VECTOR_VERSION: u8 = 1;
+
+/*
+* Replication progress of a bucket, the highest sequence number seen from every region cluster.
+* Clusters that have not been seen count as 0 and are not stored, so equal vectors always have the same encoding.
+* Serialized as a plain object, e.g. {"1":42,"7":3}, and in gossip messages as a frame header (see framing) and count
+* (u32 BE) followed by (cluster u32 BE, seq u64 BE) pairs in ascending cluster order.
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<ClusterId, u64>", into = "BTreeMap<ClusterId, u64>")]
+pub struct VersionVector(BTreeMap<ClusterId, u64>);
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum VersionVectorError {
+    #[error("version vector is {actual} bytes, expected {expected}")]
+    InvalidLength { expected: usize, actual: usize },
+    #[error("cluster {0} is out of order or duplicated")]
+    Unsorted(ClusterId),
+    #[error("cluster {0} has sequence number 0")]
+    ZeroSequence(ClusterId),
+    #[error("unsupported version vector version {0}")]
+    UnsupportedVersion(u8),
+    #[error(transparent)]
+    Framing(#[from] FramingError),
+}
+
+impl VersionVector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, cluster: ClusterId) -> u64 {
+        self.0.get(&cluster).copied().unwrap_or(0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ClusterId, u64)> + '_ {
+        self.0.iter().map(|(cluster, seq)| (*cluster, *seq))
+    }
+
+    // Never moves a cluster backwards, older sequence numbers are ignored.
+    pub fn observe(&mut self, cluster: ClusterId, seq: u64) {
+        if seq == 0 {
+            return;
+        }
+        let current = self.0.entry(cluster).or_insert(seq);
+        *current = (*current).max(seq);
+    }
+
+    // Pointwise max.
+    pub fn merge(&self, other: &VersionVector) -> VersionVector {
+        let mut merged = self.clone();
+        for (cluster, seq) in other.iter() {
+            merged.observe(cluster, seq);
+        }
+        merged
+    }
+
+    // Seen everything other has seen, true for equal vectors.
+    pub fn dominates(&self, other: &VersionVector) -> bool {
+        other.iter().all(|(cluster, seq)| self.get(cluster) >= seq)
+    }
+
+    // Neither has seen everything the other has, the updates happened independently.
+    pub fn concurrent_with(&self, other: &VersionVector) -> bool {
+        !self.dominates(other) && !other.dominates(self)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FRAME_HEADER_LEN + 4 + self.0.len() * 12);
+        write_header(BinaryFormat::VersionVector, VERSION_VECTOR_VERSION, &mut bytes);
+        bytes.extend_from_slice(&(self.0.len() as u32).to_be_bytes());
+        for (cluster, seq) in self.iter() {
+            bytes.extend_from_slice(&cluster.to_be_bytes());
+            bytes.extend_from_slice(&seq.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VersionVectorError> {
+        let (version, body) = expect_header(bytes, BinaryFormat::VersionVector)?;
+        if version != VERSION_VECTOR_VERSION {
+            return Err(VersionVectorError::UnsupportedVersion(version));
+        }
+        let count = body
+            .get(..4)
+            .map(|count| u32::from_be_bytes(count.try_into().unwrap()) as usize)
+            .ok_or(VersionVectorError::InvalidLength {
+                expected: FRAME_HEADER_LEN + 4,
+                actual: bytes.len(),
+            })?;
+        let expected = count
+            .checked_mul(12)
+            .and_then(|pairs| pairs.checked_add(FRAME_HEADER_LEN + 4))
+            .unwrap_or(usize::MAX);
+        if bytes.len() != expected {
+            return Err(VersionVectorError::InvalidLength {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        let mut vector = BTreeMap::new();
+        let mut previous = None;
+        for entry in body[4..].chunks_exact(12) {
+            let cluster = ClusterId::from_be_bytes(entry[..4].try_into().unwrap());
+            let seq = u64::from_be_bytes(entry[4..].try_into().unwrap());
+            if previous.is_some_and(|previous| previous >= cluster) {
+                return Err(VersionVectorError::Unsorted(cluster));
+            }
+            if seq == 0 {
+                return Err(VersionVectorError::ZeroSequence(cluster));
+            }
+            previous = Some(cluster);
+            vector.insert(cluster, seq);
+        }
+        Ok(Self(vector))
+    }
+}
+
+// Zero entries are dropped.
+impl From<BTreeMap<ClusterId, u64>> for VersionVector {
+    fn from(value: BTreeMap<ClusterId, u64>) -> Self {
+        let mut vector = Self::new();
+        for (cluster, seq) in value {
+            vector.observe(cluster, seq);
+        }
+        vector
+    }
+}
+
+impl From<VersionVector> for BTreeMap<ClusterId, u64> {
+    fn from(value: VersionVector) -> Self {
+        value.0
+    }
+}
+
+// The version vector partial order, None for concurrent vectors.
+impl PartialOrd for VersionVector {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.dominates(other), other.dominates(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn vector(entries: &[(ClusterId, u64)]) -> VersionVector {
+        entries.iter().copied().collect::<BTreeMap<_, _>>().into()
+    }
+
+    fn any_vector() -> impl Strategy<Value = VersionVector> {
+        proptest::collection::btree_map(0u32..6, 0u64..5, 0..6).prop_map(VersionVector::from)
+    }
+
+    #[test]
+    fn observe_is_monotonic() {
+        let mut clock = VersionVector::new();
+        clock.observe(1, 5);
+        clock.observe(1, 3);
+        clock.observe(2, 0);
+        assert_eq!(clock.get(1), 5);
+        assert_eq!(clock, vector(&[(1, 5)]));
+        assert_eq!(clock.get(2), 0);
+    }
+
+    #[test]
+    fn partial_order() {
+        let a = vector(&[(1, 2), (2, 1)]);
+        let b = vector(&[(1, 2), (2, 3)]);
+        let c = vector(&[(1, 3)]);
+        assert!(b.dominates(&a) && !a.dominates(&b));
+        assert!(a < b);
+        assert!(b.concurrent_with(&c));
+        assert_eq!(b.partial_cmp(&c), None);
+        assert_eq!(b.merge(&c), vector(&[(1, 3), (2, 3)]));
+        assert!(VersionVector::new() <= a);
+        assert_eq!(vector(&[(4, 0)]), VersionVector::new());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let clock = vector(&[(1, 42), (7, 3)]);
+        let json = serde_json::to_string(&clock).unwrap();
+        assert_eq!(json, r#"{"1":42,"7":3}"#);
+        assert_eq!(serde_json::from_str::<VersionVector>(&json).unwrap(), clock);
+        assert_eq!(serde_json::from_str::<VersionVector>(r#"{"1":42,"7":3,"9":0}"#).unwrap(), clock);
+        assert!(serde_json::from_str::<VersionVector>(r#"{"eu":1}"#).is_err());
+    }
+
+    #[test]
+    fn binary_errors() {
+        let bytes = vector(&[(1, 42), (7, 3)]).to_bytes();
+        assert_eq!(bytes.len(), FRAME_HEADER_LEN + 4 + 2 * 12);
+        assert!(matches!(
+            VersionVector::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(VersionVectorError::InvalidLength { .. })
+        ));
+        let pairs = FRAME_HEADER_LEN + 4;
+        let mut swapped = bytes.clone();
+        swapped[pairs..pairs + 12].copy_from_slice(&bytes[pairs + 12..pairs + 24]);
+        swapped[pairs + 12..pairs + 24].copy_from_slice(&bytes[pairs..pairs + 12]);
+        assert_eq!(VersionVector::from_bytes(&swapped), Err(VersionVectorError::Unsorted(1)));
+        let mut zero = bytes.clone();
+        zero[pairs + 4..pairs + 12].copy_from_slice(&0u64.to_be_bytes());
+        assert_eq!(VersionVector::from_bytes(&zero), Err(VersionVectorError::ZeroSequence(1)));
+        let mut huge = bytes[..pairs].to_vec();
+        huge[FRAME_HEADER_LEN..].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(VersionVector::from_bytes(&huge).is_err());
+        let mut version = bytes.clone();
+        version[FRAME_HEADER_LEN - 1] = 2;
+        assert_eq!(VersionVector::from_bytes(&version), Err(VersionVectorError::UnsupportedVersion(2)));
+        assert_eq!(
+            VersionVector::from_bytes(&bytes[FRAME_HEADER_LEN..]),
+            Err(VersionVectorError::Framing(FramingError::UnknownMagic([0, 0, 0, 2])))
+        );
+        assert_eq!(VersionVector::from_bytes(&VersionVector::new().to_bytes()), Ok(VersionVector::new()));
+    }
+
+    proptest! {
+        #[test]
+        fn merge_is_commutative(a in any_vector(), b in any_vector()) {
+            prop_assert_eq!(a.merge(&b), b.merge(&a));
+        }
+
+        #[test]
+        fn merge_is_associative(a in any_vector(), b in any_vector(), c in any_vector()) {
+            prop_assert_eq!(a.merge(&b).merge(&c), a.merge(&b.merge(&c)));
+        }
+
+        #[test]
+        fn merge_is_idempotent(a in any_vector(), b in any_vector()) {
+            prop_assert_eq!(a.merge(&a), a.clone());
+            let merged = a.merge(&b);
+            prop_assert_eq!(merged.merge(&b), merged.clone());
+            prop_assert!(merged.dominates(&a) && merged.dominates(&b));
+        }
+
+        #[test]
+        fn dominance_is_antisymmetric(a in any_vector(), b in any_vector()) {
+            if a.dominates(&b) && b.dominates(&a) {
+                prop_assert_eq!(&a, &b);
+            }
+            prop_assert_eq!(a.concurrent_with(&b), a.partial_cmp(&b).is_none());
+        }
+
+        #[test]
+        fn binary_and_serde_round_trip(a in any_vector()) {
+            prop_assert_eq!(VersionVector::from_bytes(&a.to_bytes()).unwrap(), a.clone());
+            let json = serde_json::to_string(&a).unwrap();
+            prop_assert_eq!(serde_json::from_str::<VersionVector>(&json).unwrap(), a);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::bucket_event::{BucketEventKind, EventEnvelope};
+use crate::bucket_guid::BucketGuid;
+use crate::util::{truncate_input, Fingerprint};
+use crate::UserId;
+
+pub const MAX_WEBHOOK_BUCKET_FILTERS: usize = 100;
+
+/*
+* Which event kinds a webhook gets. The string form is the one grammar of the registration API and the dispatcher:
+* "all", or event kind names separated by commas, e.g. "object_uploaded,quota_exceeded". Serialized as that string.
+* Only is never empty, a filter matching nothing has no string form.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EventFilter {
+    All,
+    Only(BTreeSet<BucketEventKind>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum EventFilterParsingError {
+    #[error("event filter lists no event kinds")]
+    Empty,
+    #[error("unknown event kind \"{0}\"")]
+    UnknownKind(String),
+    #[error("event kind {0} appears more than once")]
+    DuplicateKind(BucketEventKind),
+}
+
+impl EventFilter {
+    pub fn only(kinds: impl IntoIterator<Item = BucketEventKind>) -> Result<Self, EventFilterParsingError> {
+        let kinds = kinds.into_iter().collect::<BTreeSet<_>>();
+        match kinds.is_empty() {
+            true => Err(EventFilterParsingError::Empty),
+            false => Ok(EventFilter::Only(kinds)),
+        }
+    }
+
+    pub fn contains(&self, kind: BucketEventKind) -> bool {
+        match self {
+            EventFilter::All => true,
+            EventFilter::Only(kinds) => kinds.contains(&kind),
+        }
+    }
+}
+
+// Kinds in declaration order, so equal filters always have the same string.
+impl fmt::Display for EventFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventFilter::All => f.write_str("all"),
+            EventFilter::Only(kinds) => {
+                let names = kinds.iter().map(BucketEventKind::to_string).collect::<Vec<_>>();
+                f.write_str(&names.join(","))
+            }
+        }
+    }
+}
+
+// Whitespace around names is ignored, names are case sensitive.
+impl FromStr for EventFilter {
+    type Err = EventFilterParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "all" {
+            return Ok(EventFilter::All);
+        }
+        let mut kinds = BTreeSet::new();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let kind = name
+                .parse::<BucketEventKind>()
+                .map_err(|_| EventFilterParsingError::UnknownKind(truncate_input(name)))?;
+            if !kinds.insert(kind) {
+                return Err(EventFilterParsingError::DuplicateKind(kind));
+            }
+        }
+        Self::only(kinds)
+    }
+}
+
+impl Serialize for EventFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for EventFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/*
+* A registered webhook endpoint. buckets None means every bucket of the owner.
+* secret_fingerprint identifies the signing secret of the deliveries without revealing it.
+* Stored and accepted subscriptions have passed validate.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WebhookSubscription {
+    pub id: uuid::Uuid,
+    pub owner: UserId,
+    pub endpoint: url::Url,
+    pub events: EventFilter,
+    pub buckets: Option<Vec<BucketGuid>>,
+    pub secret_fingerprint: Fingerprint,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error, strum::IntoStaticStr)]
+pub enum WebhookSubscriptionError {
+    #[error("webhook endpoint {0} is not https")]
+    InsecureEndpoint(String),
+    #[error("event filter lists no event kinds")]
+    EmptyEventFilter,
+    #[error("bucket filter lists no buckets, leave it out to get events of every bucket")]
+    EmptyBucketFilter,
+    #[error("{0} bucket filters, at most {MAX_WEBHOOK_BUCKET_FILTERS}")]
+    TooManyBucketFilters(usize),
+}
+
+impl WebhookSubscription {
+    pub fn validate(&self) -> Result<(), WebhookSubscriptionError> {
+        if self.endpoint.scheme() != "https" {
+            return Err(WebhookSubscriptionError::InsecureEndpoint(self.endpoint.to_string()));
+        }
+        // Only reachable by building Only directly, parsing rejects it.
+        if matches!(&self.events, EventFilter::Only(kinds) if kinds.is_empty()) {
+            return Err(WebhookSubscriptionError::EmptyEventFilter);
+        }
+        match self.buckets.as_ref().map(Vec::len) {
+            Some(0) => Err(WebhookSubscriptionError::EmptyBucketFilter),
+            Some(count) if count > MAX_WEBHOOK_BUCKET_FILTERS => {
+                Err(WebhookSubscriptionError::TooManyBucketFilters(count))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Whether the envelope is delivered to this subscription: it is active and both filters let the event through.
+    pub fn matches(&self, envelope: &EventEnvelope) -> bool {
+        self.active
+            && self.events.contains(envelope.event.kind())
+            && self.buckets.as_ref().is_none_or(|buckets| buckets.contains(&envelope.bucket))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::bucket_event::BucketEvent;
+    use crate::storage_quota::StorageQuota;
+    use crate::BucketStatus;
+
+    fn bucket(n: u128) -> BucketGuid {
+        BucketGuid::new(uuid::Uuid::from_u128(1), uuid::Uuid::from_u128(n))
+    }
+
+    // Every kind has to be listed here, a new kind fails to compile until it is.
+    fn event(kind: BucketEventKind) -> BucketEvent {
+        match kind {
+            BucketEventKind::BucketCreated => BucketEvent::BucketCreated,
+            BucketEventKind::BucketDeleted => BucketEvent::BucketDeleted,
+            BucketEventKind::StatusChanged => BucketEvent::StatusChanged { status: BucketStatus::Archiving },
+            BucketEventKind::ObjectUploaded => BucketEvent::ObjectUploaded { key: "a.txt".to_string(), size: 3 },
+            BucketEventKind::ObjectDeleted => BucketEvent::ObjectDeleted { key: "a.txt".to_string() },
+            BucketEventKind::QuotaExceeded => BucketEvent::QuotaExceeded {
+                quota: StorageQuota::from_bytes(100),
+                attempted: StorageQuota::from_bytes(101),
+            },
+        }
+    }
+
+    fn envelope(kind: BucketEventKind, bucket: BucketGuid) -> EventEnvelope {
+        EventEnvelope {
+            id: uuid::Uuid::from_u128(9),
+            bucket,
+            occurred_at: datetime!(2024-06-01 0:00 UTC),
+            event: event(kind),
+        }
+    }
+
+    fn subscription(events: &str, buckets: Option<Vec<BucketGuid>>) -> WebhookSubscription {
+        WebhookSubscription {
+            id: uuid::Uuid::from_u128(7),
+            owner: uuid::Uuid::from_u128(1),
+            endpoint: "https://hooks.example.com/bucketdrive".parse().unwrap(),
+            events: events.parse().unwrap(),
+            buckets,
+            secret_fingerprint: Fingerprint::of(b"webhook secret"),
+            active: true,
+        }
+    }
+
+    #[test]
+    fn every_kind_matches_its_filter() {
+        for kind in BucketEventKind::iter() {
+            assert_eq!(event(kind).kind(), kind);
+            let only = subscription(&kind.to_string(), None);
+            let all = subscription("all", None);
+            for other in BucketEventKind::iter() {
+                assert_eq!(only.matches(&envelope(other, bucket(2))), other == kind, "{} {}", kind, other);
+                assert!(all.matches(&envelope(other, bucket(2))));
+            }
+        }
+    }
+
+    #[test]
+    fn filter_combinations() {
+        let uploads_and_quota = "object_uploaded, quota_exceeded";
+        let subscription = self::subscription(uploads_and_quota, Some(vec![bucket(2), bucket(3)]));
+        let cases = [
+            (BucketEventKind::ObjectUploaded, bucket(2), true),
+            (BucketEventKind::QuotaExceeded, bucket(3), true),
+            (BucketEventKind::ObjectUploaded, bucket(4), false),
+            (BucketEventKind::ObjectDeleted, bucket(2), false),
+            (BucketEventKind::ObjectDeleted, bucket(4), false),
+        ];
+        for (kind, bucket, expected) in cases {
+            assert_eq!(subscription.matches(&envelope(kind, bucket)), expected, "{} {}", kind, bucket);
+        }
+
+        let every_bucket = self::subscription(uploads_and_quota, None);
+        assert!(every_bucket.matches(&envelope(BucketEventKind::ObjectUploaded, bucket(4))));
+        let everything = self::subscription("all", Some(vec![bucket(2)]));
+        assert!(everything.matches(&envelope(BucketEventKind::BucketDeleted, bucket(2))));
+        assert!(!everything.matches(&envelope(BucketEventKind::BucketDeleted, bucket(3))));
+
+        let mut inactive = self::subscription("all", None);
+        inactive.active = false;
+        assert!(BucketEventKind::iter().all(|kind| !inactive.matches(&envelope(kind, bucket(2)))));
+    }
+
+    #[test]
+    fn filter_grammar() {
+        let filter = " quota_exceeded ,object_uploaded".parse::<EventFilter>().unwrap();
+        assert_eq!(filter.to_string(), "object_uploaded,quota_exceeded");
+        assert_eq!(filter.to_string().parse(), Ok(filter.clone()));
+        assert_eq!(serde_json::to_string(&filter).unwrap(), r#""object_uploaded,quota_exceeded""#);
+        assert_eq!(serde_json::from_str::<EventFilter>(r#""all""#).unwrap(), EventFilter::All);
+
+        assert_eq!("".parse::<EventFilter>(), Err(EventFilterParsingError::Empty));
+        assert_eq!(" , ".parse::<EventFilter>(), Err(EventFilterParsingError::Empty));
+        assert_eq!(EventFilter::only([]), Err(EventFilterParsingError::Empty));
+        assert_eq!(
+            "object_uploaded,ObjectDeleted".parse::<EventFilter>(),
+            Err(EventFilterParsingError::UnknownKind("ObjectDeleted".to_string()))
+        );
+        assert_eq!(
+            "object_uploaded,object_uploaded".parse::<EventFilter>(),
+            Err(EventFilterParsingError::DuplicateKind(BucketEventKind::ObjectUploaded))
+        );
+        assert!("all,object_uploaded".parse::<EventFilter>().is_err());
+    }
+
+    #[test]
+    fn validation_rejections() {
+        assert_eq!(subscription("all", None).validate(), Ok(()));
+        let full = (0..MAX_WEBHOOK_BUCKET_FILTERS as u128).map(bucket).collect::<Vec<_>>();
+        assert_eq!(subscription("all", Some(full.clone())).validate(), Ok(()));
+
+        let mut http = subscription("all", None);
+        http.endpoint = "http://hooks.example.com/bucketdrive".parse().unwrap();
+        assert!(matches!(http.validate(), Err(WebhookSubscriptionError::InsecureEndpoint(_))));
+
+        let mut empty_events = subscription("all", None);
+        empty_events.events = EventFilter::Only(BTreeSet::new());
+        assert_eq!(empty_events.validate(), Err(WebhookSubscriptionError::EmptyEventFilter));
+
+        assert_eq!(
+            subscription("all", Some(Vec::new())).validate(),
+            Err(WebhookSubscriptionError::EmptyBucketFilter)
+        );
+        let too_many = full.into_iter().chain([bucket(1000)]).collect::<Vec<_>>();
+        assert_eq!(
+            subscription("all", Some(too_many)).validate(),
+            Err(WebhookSubscriptionError::TooManyBucketFilters(MAX_WEBHOOK_BUCKET_FILTERS + 1))
+        );
+    }
+
+    #[test]
+    fn serde() {
+        let subscription = subscription("object_uploaded", Some(vec![bucket(2)]));
+        let json = serde_json::to_value(&subscription).unwrap();
+        assert_eq!(json["events"], "object_uploaded");
+        assert_eq!(json["endpoint"], "https://hooks.example.com/bucketdrive");
+        assert_eq!(json["secret_fingerprint"], Fingerprint::of(b"webhook secret").to_string());
+        assert_eq!(serde_json::from_value::<WebhookSubscription>(json).unwrap(), subscription);
+    }
+}
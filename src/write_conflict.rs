@@ -0,0 +1,274 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bucket_object::ContentHash;
+use crate::manifest::ManifestEntry;
+
+/*
+* What a service does when an object is written while another write to the same key already landed.
+* Serialized as an object tagged with "strategy", e.g. {"strategy": "reject_if_changed", "expected": "<hex>"}.
+* RejectIfChanged carries the hash the client based its write on, so it only makes sense per write, not as a bucket default.
+*/
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, strum::Display, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum WriteConflictStrategy {
+    #[default]
+    LastWriterWins,
+    RejectIfChanged {
+        expected: ContentHash,
+    },
+    // Keeps the existing object and stores the incoming one next to it under a suffixed key.
+    VersionBoth,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WriteRejection {
+    // The client expected to overwrite an object that does not exist, or only as a tombstone.
+    Missing { expected: ContentHash },
+    Changed { expected: ContentHash, actual: ContentHash },
+}
+
+impl fmt::Display for WriteRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteRejection::Missing { expected } => write!(f, "expected {} but the object does not exist", expected),
+            WriteRejection::Changed { expected, actual } => {
+                write!(f, "expected {} but the object changed to {}", expected, actual)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum WriteDecision {
+    Accept,
+    Reject { reason: WriteRejection },
+    // Store the incoming object under its key with suffix appended, e.g. "/notes.txt~2".
+    AcceptAsNewVersion { suffix: String },
+}
+
+impl WriteConflictStrategy {
+    pub fn is_default(&self) -> bool {
+        *self == WriteConflictStrategy::LastWriterWins
+    }
+
+    // Same as resolve_among with no other entries in the listing.
+    pub fn resolve(&self, existing: Option<&ManifestEntry>, incoming: &ManifestEntry) -> WriteDecision {
+        self.resolve_among(existing, incoming, &[])
+    }
+
+    /*
+    * existing is the current entry under the incoming key, a tombstone counts as no object.
+    * listing holds the other entries of the bucket, a version suffix is never picked if its key is taken there,
+    * tombstones included, e.g. by an earlier version or a user's own "/notes.txt~2".
+    * Writing the same content again is never a conflict.
+    */
+    pub fn resolve_among(
+        &self,
+        existing: Option<&ManifestEntry>,
+        incoming: &ManifestEntry,
+        listing: &[ManifestEntry],
+    ) -> WriteDecision {
+        let existing = existing.filter(|entry| !entry.deleted);
+        match (self, existing) {
+            (WriteConflictStrategy::LastWriterWins, _) => WriteDecision::Accept,
+            (WriteConflictStrategy::RejectIfChanged { expected }, None) => WriteDecision::Reject {
+                reason: WriteRejection::Missing { expected: *expected },
+            },
+            (WriteConflictStrategy::RejectIfChanged { expected }, Some(existing)) if existing.hash != *expected => {
+                WriteDecision::Reject {
+                    reason: WriteRejection::Changed {
+                        expected: *expected,
+                        actual: existing.hash,
+                    },
+                }
+            }
+            (WriteConflictStrategy::RejectIfChanged { .. }, Some(_)) => WriteDecision::Accept,
+            (WriteConflictStrategy::VersionBoth, Some(existing)) if existing.hash != incoming.hash => {
+                WriteDecision::AcceptAsNewVersion {
+                    suffix: version_suffix(incoming, listing),
+                }
+            }
+            (WriteConflictStrategy::VersionBoth, _) => WriteDecision::Accept,
+        }
+    }
+}
+
+// "~2", "~3", ... the first one whose key is not in the listing.
+fn version_suffix(incoming: &ManifestEntry, listing: &[ManifestEntry]) -> String {
+    (2u64..)
+        .map(|version| format!("~{}", version))
+        .find(|suffix| {
+            let candidate = format!("{}{}", incoming.key, suffix);
+            !listing.iter().any(|entry| entry.key.as_str() == candidate)
+        })
+        .expect("a listing can not take every suffix")
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn entry(key: &str, content: &[u8]) -> ManifestEntry {
+        ManifestEntry {
+            key: key.parse().unwrap(),
+            size: content.len() as u64,
+            hash: ContentHash::of(content),
+            modified: datetime!(2024-06-01 12:00 UTC),
+            deleted: false,
+        }
+    }
+
+    fn tombstone(key: &str, content: &[u8]) -> ManifestEntry {
+        ManifestEntry {
+            deleted: true,
+            ..entry(key, content)
+        }
+    }
+
+    #[test]
+    fn decisions() {
+        let old = ContentHash::of(b"old");
+        let incoming = entry("/notes.txt", b"new");
+        let existing = entry("/notes.txt", b"old");
+        let same = entry("/notes.txt", b"new");
+        let deleted = tombstone("/notes.txt", b"old");
+        let version = |suffix: &str| WriteDecision::AcceptAsNewVersion {
+            suffix: suffix.to_string(),
+        };
+        let table = [
+            (WriteConflictStrategy::LastWriterWins, None, WriteDecision::Accept),
+            (
+                WriteConflictStrategy::LastWriterWins,
+                Some(&existing),
+                WriteDecision::Accept,
+            ),
+            (
+                WriteConflictStrategy::RejectIfChanged { expected: old },
+                Some(&existing),
+                WriteDecision::Accept,
+            ),
+            (
+                WriteConflictStrategy::RejectIfChanged { expected: old },
+                Some(&same),
+                WriteDecision::Reject {
+                    reason: WriteRejection::Changed {
+                        expected: old,
+                        actual: same.hash,
+                    },
+                },
+            ),
+            (
+                WriteConflictStrategy::RejectIfChanged { expected: old },
+                None,
+                WriteDecision::Reject {
+                    reason: WriteRejection::Missing { expected: old },
+                },
+            ),
+            (
+                WriteConflictStrategy::RejectIfChanged { expected: old },
+                Some(&deleted),
+                WriteDecision::Reject {
+                    reason: WriteRejection::Missing { expected: old },
+                },
+            ),
+            (WriteConflictStrategy::VersionBoth, None, WriteDecision::Accept),
+            (
+                WriteConflictStrategy::VersionBoth,
+                Some(&deleted),
+                WriteDecision::Accept,
+            ),
+            (WriteConflictStrategy::VersionBoth, Some(&same), WriteDecision::Accept),
+            (WriteConflictStrategy::VersionBoth, Some(&existing), version("~2")),
+        ];
+        for (strategy, existing, expected) in table {
+            assert_eq!(
+                strategy.resolve(existing, &incoming),
+                expected,
+                "{} {:?}",
+                strategy,
+                existing
+            );
+        }
+    }
+
+    #[test]
+    fn version_suffix_skips_taken_keys() {
+        let incoming = entry("/notes.txt", b"new");
+        let existing = entry("/notes.txt", b"old");
+        let suffix = |listing: &[ManifestEntry]| match WriteConflictStrategy::VersionBoth.resolve_among(
+            Some(&existing),
+            &incoming,
+            listing,
+        ) {
+            WriteDecision::AcceptAsNewVersion { suffix } => suffix,
+            decision => panic!("{:?}", decision),
+        };
+        assert_eq!(suffix(&[]), "~2");
+        assert_eq!(suffix(&[entry("/notes.txt~2", b"v2")]), "~3");
+        // A tombstone still holds its key, unrelated keys do not matter.
+        assert_eq!(
+            suffix(&[
+                tombstone("/notes.txt~2", b"v2"),
+                entry("/notes.txt~3", b"user's own file")
+            ]),
+            "~4"
+        );
+        assert_eq!(
+            suffix(&[entry("/notes.txt~3", b"v3"), entry("/other.txt~2", b"")]),
+            "~2"
+        );
+    }
+
+    #[test]
+    fn serde_forms() {
+        let expected = ContentHash::of(b"old");
+        let forms = [
+            (
+                WriteConflictStrategy::LastWriterWins,
+                r#"{"strategy":"last_writer_wins"}"#,
+            ),
+            (
+                WriteConflictStrategy::RejectIfChanged { expected },
+                &*format!(r#"{{"strategy":"reject_if_changed","expected":"{}"}}"#, expected),
+            ),
+            (WriteConflictStrategy::VersionBoth, r#"{"strategy":"version_both"}"#),
+        ];
+        for (strategy, json) in forms {
+            assert_eq!(serde_json::to_string(&strategy).unwrap(), json);
+            assert_eq!(serde_json::from_str::<WriteConflictStrategy>(json).unwrap(), strategy);
+        }
+        assert_eq!(
+            WriteConflictStrategy::RejectIfChanged { expected }.to_string(),
+            "reject_if_changed"
+        );
+        assert_eq!(WriteConflictStrategy::default(), WriteConflictStrategy::LastWriterWins);
+
+        let decisions = [
+            WriteDecision::Accept,
+            WriteDecision::Reject {
+                reason: WriteRejection::Missing { expected },
+            },
+            WriteDecision::AcceptAsNewVersion {
+                suffix: "~2".to_string(),
+            },
+        ];
+        for decision in decisions {
+            let json = serde_json::to_string(&decision).unwrap();
+            assert_eq!(serde_json::from_str::<WriteDecision>(&json).unwrap(), decision);
+        }
+        assert_eq!(
+            serde_json::to_string(&WriteDecision::AcceptAsNewVersion {
+                suffix: "~2".to_string()
+            })
+            .unwrap(),
+            r#"{"decision":"accept_as_new_version","suffix":"~2"}"#
+        );
+    }
+}
@@ -0,0 +1,38 @@
+// Without the libtest harness (see Cargo.toml), the counting allocator sees every allocation of the process and
+// nothing else runs next to the measured calls.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bucket_common_types::util::base64_stream;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let input = vec![0xa5; 1024 * 1024];
+    let encoded = base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, &input);
+
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    let written = base64_stream::encode_to_writer(input.as_slice(), std::io::sink()).unwrap();
+    let decoded = base64_stream::decode_to_writer(encoded.as_bytes(), std::io::sink()).unwrap();
+    let allocated = ALLOCATED.load(Ordering::SeqCst) - before;
+
+    assert_eq!(written, encoded.len() as u64);
+    assert_eq!(decoded, input.len() as u64);
+    assert_eq!(allocated, 0);
+}
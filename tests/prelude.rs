@@ -0,0 +1,94 @@
+// Every item of the prelude, used only through the prelude. A type moving between modules must not break this file.
+use bucket_common_types::prelude::*;
+
+fn user_id() -> UserId {
+    Uuid::parse_str("11111111-0000-4000-8000-000000000001").unwrap()
+}
+
+fn bucket_id() -> BucketId {
+    Uuid::parse_str("22222222-0000-4000-8000-000000000002").unwrap()
+}
+
+#[test]
+fn core_types() {
+    let region = "eu-north".parse::<BucketRegion>().unwrap();
+    let cluster_id: ClusterId = 3;
+    let cluster = RegionCluster { region: region.clone(), cluster_id };
+    assert_eq!(cluster.to_string(), "eu-north-3");
+    assert!(DataResidency::Europe.allows(&region));
+    assert_eq!(Capability::ShareLink.to_string(), "share_link");
+
+    assert_eq!("Zstd".parse(), Ok(BucketCompression::Zstd));
+    assert_eq!("AV1".parse(), Ok(VideoCodec::AV1));
+    assert_eq!("Available".parse(), Ok(BucketStatus::Available));
+    assert!(BucketStorageClass::Archive.is_archive());
+    assert_eq!("Private".parse(), Ok(BucketVisibility::Private));
+    assert_eq!("Zip".parse(), Ok(DownloadFormat::Zip));
+    assert_eq!("Free".parse(), Ok(PaymentPlan::Free));
+    assert_eq!("Metered".parse(), Ok(PaymentModel::Metered));
+    assert_eq!("Card".parse(), Ok(PaymentMethod::Card));
+    assert!(BucketFeaturesFlags::IS_SHARABLE.contains(BucketFeaturesFlags::IS_SHARABLE));
+    assert!((Verification::EMAIL | Verification::TOTP).contains(Verification::TOTP));
+
+    let encryption = BucketEncryption::custom("acme-hsm").unwrap();
+    let name: &CustomEncryptionName = match &encryption {
+        BucketEncryption::Custom(name) => name,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(name.as_str(), "acme-hsm");
+}
+
+#[test]
+fn newtypes() {
+    let guid = BucketGuid::new(user_id(), bucket_id());
+    assert_eq!(guid.to_string().parse::<BucketGuid>().unwrap(), guid);
+    assert!(BucketName::try_from("photos".to_string()).is_ok());
+    assert!(BucketDisplayName::try_from("Photos".to_string()).is_ok());
+    assert!(BucketDescription::try_from("Holiday photos".to_string()).is_ok());
+    assert!("abcdefghijklmnopqrst".parse::<PublicBucketId>().is_ok());
+
+    let now = OffsetDateTime::from_unix_timestamp(1_717_200_000).unwrap();
+    let ttl = "1h".parse::<HumanDuration>().unwrap();
+    let expires: ExpiryTimestamp = ttl.expiry_from_now(now).unwrap();
+    assert_eq!(expires.unix_timestamp(), 1_717_203_600);
+    assert_eq!("1 KiB".parse(), Ok(StorageQuota::from_bytes(1024)));
+    assert_eq!(ByteSpan::new(0, 500).unwrap().len(), 500);
+    assert_eq!(Fingerprint::of(b"bucket").to_string().len(), 8);
+    let origin = LinkOrigin::new("https", "bucketdrive.co");
+    assert!(origin.matches_host(&"https://bucketdrive.co/share".parse::<Url>().unwrap()));
+}
+
+#[test]
+fn errors() {
+    let error = "soon".parse::<HumanDuration>().unwrap_err();
+    let failure: ParseFailure = SpannedParseError::failure(&error);
+    assert_eq!(failure.kind, "InvalidFormat");
+}
+
+#[cfg(feature = "share_link")]
+#[test]
+fn share_links() {
+    let permissions = BucketSharePermissionFlags::VIEW | BucketSharePermissionFlags::READ;
+    assert_eq!(permissions.to_short_string(), "vr------");
+    let token = ShareLinkToken([7; 32]);
+    let link = ShareLink { token: token.0 };
+    assert!(link.to_string().starts_with("https://"));
+}
+
+#[cfg(feature = "secret_share_link")]
+#[test]
+fn secret_share_links() {
+    let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([1; 32]));
+    let link = SecretShareLink::new(
+        user_id(),
+        bucket_id(),
+        [7u8; 32].into(),
+        BucketSharePermissionFlags::READ,
+        None,
+        &key_pair.sk,
+    );
+    assert!(link.verify_signature(key_pair.pk).is_ok());
+    let key_id = "links-2024".parse::<KeyId>().unwrap();
+    let keyring = Keyring::new(Vec::new()).unwrap();
+    assert!(keyring.get(&key_id).is_none());
+}
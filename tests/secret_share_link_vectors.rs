@@ -0,0 +1,69 @@
+use bucket_common_types::secret_share_link::SecretShareLink;
+use bucket_common_types::test_vectors::{secret_share_link_vectors, SecretShareLinkVector};
+
+const VECTORS: &str = include_str!("../testdata/secret_share_link_vectors.json");
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex<const N: usize>(value: &str) -> [u8; N] {
+    assert_eq!(value.len(), N * 2, "{}", value);
+    std::array::from_fn(|index| u8::from_str_radix(&value[index * 2..index * 2 + 2], 16).unwrap())
+}
+
+fn committed_vectors() -> Vec<SecretShareLinkVector> {
+    serde_json::from_str(VECTORS).unwrap()
+}
+
+// Any change to the link encoding, hashing or signing shows up here.
+#[test]
+fn committed_vectors_are_up_to_date() {
+    let generated = serde_json::to_string_pretty(&secret_share_link_vectors()).unwrap() + "\n";
+    assert!(
+        generated == VECTORS,
+        "testdata/secret_share_link_vectors.json is stale, regenerate it with `cargo run --example generate-vectors > testdata/secret_share_link_vectors.json` if the change is intended"
+    );
+}
+
+#[test]
+fn committed_vectors_parse_and_verify() {
+    let vectors = committed_vectors();
+    assert!(!vectors.is_empty());
+    for vector in vectors {
+        let link: SecretShareLink = vector.url.parse().unwrap_or_else(|error| panic!("{}: {}", vector.name, error));
+        assert_eq!(link.to_string(), vector.url, "{}", vector.name);
+        assert_eq!(link.user_id, vector.user_id, "{}", vector.name);
+        assert_eq!(link.bucket_id, vector.bucket_id, "{}", vector.name);
+        assert_eq!(hex(link.bucket_key.as_slice()), vector.bucket_key, "{}", vector.name);
+        assert_eq!(link.permission.bits(), vector.permission, "{}", vector.name);
+        assert_eq!(link.expires.map(|expires| expires.unix_timestamp()), vector.expires, "{}", vector.name);
+        assert_eq!(link.not_before.map(|not_before| not_before.unix_timestamp()), vector.not_before, "{}", vector.name);
+        assert_eq!(link.issued_at.map(|issued_at| issued_at.unix_timestamp()), vector.issued_at, "{}", vector.name);
+        assert_eq!(
+            link.prefixes.iter().map(|prefix| prefix.to_string()).collect::<Vec<_>>(),
+            vector.prefixes,
+            "{}",
+            vector.name
+        );
+        assert_eq!(link.channel, vector.channel, "{}", vector.name);
+        assert_eq!(link.recipient, vector.recipient, "{}", vector.name);
+        assert_eq!(link.max_total_bytes.map(|cap| cap.bytes()), vector.max_total_bytes, "{}", vector.name);
+        assert_eq!(hex(&link.canonical_bytes()), vector.canonical_bytes, "{}", vector.name);
+        assert_eq!(hex(&link.get_token()), vector.token, "{}", vector.name);
+        assert_eq!(hex(link.signature.as_slice()), vector.signature, "{}", vector.name);
+
+        let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(from_hex(&vector.seed)));
+        assert_eq!(hex(key_pair.pk.as_slice()), vector.public_key, "{}", vector.name);
+        let public_key = ed25519_compact::PublicKey::new(from_hex(&vector.public_key));
+        assert!(link.verify_signature(public_key).is_ok(), "{}", vector.name);
+    }
+}
+
+#[test]
+fn vectors_cover_every_permission_flag() {
+    let vectors = committed_vectors();
+    for (_, flag) in bucket_common_types::share_link::BucketSharePermissionFlags::all().iter_names() {
+        assert!(vectors.iter().any(|vector| vector.permission == flag.bits()), "{:?}", flag);
+    }
+}